@@ -4,14 +4,25 @@ use uuid::Uuid;
 
 use super::{
     prose_modes::{ProseModelManager, ProseMode},
-    saliency_engine::{SaliencyEngine, SaliencyContext, StoryBibleElements},
+    saliency_engine::{annotate_from_database, SaliencyEngine, SaliencyContext, StoryBibleElements},
     visualize::{VisualizeEngine, VisualizeRequest, GeneratedImage},
-    brainstorm::{BrainstormEngine, BrainstormRequest, BrainstormSession},
-    AIProvider, AIContext,
+    brainstorm::{BrainstormEngine, BrainstormIdea, BrainstormRequest, BrainstormSession, DuplicateIdea, IdeaTheme, StoryBibleExport},
+    stylometry::{project_fingerprint, StyleDrift},
+    AIProvider, AIContext, AIProviderManager, TextChunkStream, ToolLoopResult, ToolTurn,
 };
+use super::token_counter::{pricing, UsageMeter};
+use super::tokenizer;
+use async_trait::async_trait;
+use futures_util::stream::{self, StreamExt};
 use crate::error::{Result, StoryWeaverError};
-use crate::database::{get_pool, operations::ai_card_ops::AICardOps};
+use crate::database::{get_pool, operations::ai_card_ops::AICardOps, DbPool};
+use crate::database::operations::{
+    AIBudget, AIBudgetOps, BudgetEnforcement, BudgetPeriod, BudgetScope, CreditUsage as CreditUsageRecord, CreditUsageOps,
+};
 use crate::models::ai_card::CreateAICardRequest;
+use chrono::{Datelike, Duration, NaiveDate, Utc};
+use once_cell::sync::OnceCell;
+use std::sync::Arc;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AdvancedGenerationRequest {
@@ -33,6 +44,9 @@ pub struct AdvancedGenerationResult {
     pub prose_mode_used: String,
     pub saliency_context: Option<SaliencyContext>,
     pub cliche_detection: Option<super::prose_modes::ClicheDetectionResult>,
+    /// Drift from the project's style examples; `None` without examples
+    #[serde(default)]
+    pub style_drift: Option<StyleDrift>,
     pub token_count: i32,
     pub credits_used: i32,
     pub generation_id: String,
@@ -132,6 +146,457 @@ impl CreditTracker {
             true // No limit set
         }
     }
+
+    /// Check a request's estimated cost against every budget it counts
+    /// towards, before it is sent
+    pub async fn preflight(pool: &DbPool, estimate: SpendEstimate) -> Result<PreflightCheck> {
+        let now = Utc::now();
+        let budgets = AIBudgetOps::get_applicable(pool, estimate.project_id.as_deref(), &estimate.provider).await?;
+
+        let mut statuses = Vec::with_capacity(budgets.len());
+        for budget in budgets {
+            let period_start = budget.period.start(now);
+            let spent_usd = Self::spent(pool, &budget, &period_start).await?;
+            statuses.push(BudgetStatus {
+                projected_usd: spent_usd + estimate.cost_usd,
+                budget,
+                period_start,
+                spent_usd,
+            });
+        }
+
+        Ok(PreflightCheck {
+            decision: Self::evaluate(&statuses),
+            estimate,
+            budgets: statuses,
+        })
+    }
+
+    /// Whether the budgets allow a request that would bring them to their
+    /// projected spend. Block budgets win over ones that need confirmation.
+    pub fn evaluate(statuses: &[BudgetStatus]) -> BudgetDecision {
+        let mut decision = BudgetDecision::Allowed;
+        for status in statuses.iter().filter(|s| s.refuses()) {
+            let message = format!(
+                "{} would reach ${:.2} of its ${:.2} {} limit",
+                status.budget.describe(),
+                status.projected_usd,
+                status.budget.limit_usd,
+                status.budget.period.as_str()
+            );
+            match status.budget.enforcement {
+                BudgetEnforcement::Block => {
+                    return BudgetDecision::Blocked { budget_id: status.budget.id.clone(), message };
+                }
+                BudgetEnforcement::Confirm if decision == BudgetDecision::Allowed => {
+                    decision = BudgetDecision::NeedsConfirmation { budget_id: status.budget.id.clone(), message };
+                }
+                BudgetEnforcement::Confirm => {}
+            }
+        }
+        decision
+    }
+
+    /// Record the spend of a request that was sent, returning the budget
+    /// thresholds it crossed for the first time this period
+    pub async fn record_spend(pool: &DbPool, estimate: &SpendEstimate, operation_type: &str) -> Result<Vec<BudgetAlert>> {
+        CreditUsageOps::create(pool, &CreditUsageRecord {
+            id: None,
+            project_id: estimate.project_id.clone(),
+            operation_type: operation_type.to_string(),
+            provider: Some(estimate.provider.clone()),
+            model_used: estimate.model.clone(),
+            tokens_used: Some((estimate.input_tokens + estimate.output_tokens) as i32),
+            credits_consumed: 0.0,
+            cost_usd: estimate.cost_usd,
+            operation_details: serde_json::to_string(estimate).ok(),
+            session_id: None,
+            created_at: None,
+        })
+        .await?;
+
+        let now = Utc::now();
+        let mut alerts = Vec::new();
+        for budget in AIBudgetOps::get_applicable(pool, estimate.project_id.as_deref(), &estimate.provider).await? {
+            let period_start = budget.period.start(now);
+            let spent_usd = Self::spent(pool, &budget, &period_start).await?;
+            for threshold in budget.alert_thresholds.iter().copied() {
+                if spent_usd >= budget.limit_usd * threshold
+                    && AIBudgetOps::record_alert(pool, &budget.id, &period_start, threshold).await?
+                {
+                    alerts.push(BudgetAlert { budget: budget.clone(), threshold, spent_usd });
+                }
+            }
+        }
+        Ok(alerts)
+    }
+
+    /// Current spend of every budget
+    pub async fn budget_statuses(pool: &DbPool) -> Result<Vec<BudgetStatus>> {
+        let now = Utc::now();
+        let mut statuses = Vec::new();
+        for budget in AIBudgetOps::list(pool).await? {
+            let period_start = budget.period.start(now);
+            let spent_usd = Self::spent(pool, &budget, &period_start).await?;
+            statuses.push(BudgetStatus { budget, period_start, spent_usd, projected_usd: spent_usd });
+        }
+        Ok(statuses)
+    }
+
+    /// Project a project's spend to the end of the month from its daily
+    /// average over the last 30 days
+    pub async fn forecast(pool: &DbPool, project_id: &str) -> Result<SpendForecast> {
+        let today = Utc::now().date_naive();
+        let month_start = NaiveDate::from_ymd_opt(today.year(), today.month(), 1).unwrap_or(today);
+        let window_start = (today - Duration::days(FORECAST_WINDOW_DAYS - 1)).min(month_start);
+        let usage = CreditUsageOps::get_usage_in_range(
+            pool,
+            project_id,
+            &window_start.format("%Y-%m-%d").to_string(),
+            &today.format("%Y-%m-%d").to_string(),
+        )
+        .await?;
+
+        let day_of = |record: &CreditUsageRecord| {
+            record
+                .created_at
+                .as_deref()
+                .and_then(|at| NaiveDate::parse_from_str(at.get(..10)?, "%Y-%m-%d").ok())
+        };
+        let recent_start = today - Duration::days(FORECAST_WINDOW_DAYS - 1);
+        let recent: Vec<(NaiveDate, f64)> = usage
+            .iter()
+            .filter_map(|record| day_of(record).map(|day| (day, record.cost_usd)))
+            .collect();
+        let spent_this_month_usd: f64 = recent.iter().filter(|(day, _)| *day >= month_start).map(|(_, cost)| cost).sum();
+
+        // Average over the days since spending started, so a new project isn't diluted
+        let first_day = recent.iter().map(|(day, _)| *day).filter(|day| *day >= recent_start).min();
+        let daily_average_usd = match first_day {
+            Some(first_day) => {
+                let days = (today - first_day).num_days() + 1;
+                let spent: f64 = recent.iter().filter(|(day, _)| *day >= recent_start).map(|(_, cost)| cost).sum();
+                spent / days as f64
+            }
+            None => 0.0,
+        };
+
+        let next_month = if today.month() == 12 {
+            NaiveDate::from_ymd_opt(today.year() + 1, 1, 1)
+        } else {
+            NaiveDate::from_ymd_opt(today.year(), today.month() + 1, 1)
+        }
+        .unwrap_or(today);
+        let days_remaining = (next_month - today).num_days() - 1;
+        let projected_month_usd = spent_this_month_usd + daily_average_usd * days_remaining as f64;
+
+        let monthly_limit_usd = AIBudgetOps::get_applicable(pool, Some(project_id), "")
+            .await?
+            .into_iter()
+            .find(|b| b.scope == BudgetScope::Project && b.period == BudgetPeriod::Monthly)
+            .map(|b| b.limit_usd);
+        let limit_reached_on = monthly_limit_usd.and_then(|limit| {
+            if spent_this_month_usd >= limit {
+                Some(today)
+            } else if daily_average_usd > 0.0 && projected_month_usd > limit {
+                let days = ((limit - spent_this_month_usd) / daily_average_usd).ceil() as i64;
+                Some(today + Duration::days(days))
+            } else {
+                None
+            }
+        });
+
+        Ok(SpendForecast {
+            project_id: project_id.to_string(),
+            spent_this_month_usd,
+            daily_average_usd,
+            projected_month_usd,
+            monthly_limit_usd,
+            limit_reached_on: limit_reached_on.map(|day| day.format("%Y-%m-%d").to_string()),
+        })
+    }
+
+    async fn spent(pool: &DbPool, budget: &AIBudget, period_start: &str) -> Result<f64> {
+        let scope_id = budget.scope_id.as_deref();
+        match budget.scope {
+            BudgetScope::Global => CreditUsageOps::get_spend_since(pool, None, None, period_start).await,
+            BudgetScope::Project => CreditUsageOps::get_spend_since(pool, scope_id, None, period_start).await,
+            BudgetScope::Provider => CreditUsageOps::get_spend_since(pool, None, scope_id, period_start).await,
+        }
+    }
+}
+
+/// Output allowance for requests that don't say how long the answer may be
+pub const DEFAULT_EXPECTED_OUTPUT_TOKENS: u32 = 1_000;
+
+/// Days of history the spend forecast averages over
+const FORECAST_WINDOW_DAYS: i64 = 30;
+
+// Set at startup so budget alerts can raise desktop notifications
+static BUDGET_NOTIFIER: OnceCell<tauri::AppHandle> = OnceCell::new();
+
+/// Estimated cost of a request before it is sent
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpendEstimate {
+    pub project_id: Option<String>,
+    pub provider: String,
+    pub model: String,
+    pub input_tokens: u32,
+    pub output_tokens: u32,
+    pub cost_usd: f64,
+    /// Set when the model has no price, so the request counts as free
+    pub warning: Option<String>,
+}
+
+impl SpendEstimate {
+    pub fn new(project_id: Option<String>, provider: &str, model: &str, input_tokens: u32, output_tokens: u32) -> Self {
+        let cost = pricing().estimate(provider, model, input_tokens, output_tokens);
+        Self {
+            project_id,
+            provider: provider.to_string(),
+            model: model.to_string(),
+            input_tokens,
+            output_tokens,
+            cost_usd: cost.total_cost,
+            warning: cost.warning,
+        }
+    }
+
+    /// The same request priced on the tokens it actually used
+    pub fn with_usage(&self, input_tokens: u32, output_tokens: u32) -> Self {
+        Self::new(self.project_id.clone(), &self.provider, &self.model, input_tokens, output_tokens)
+    }
+}
+
+/// Spend of a request that was sent, recorded once its output is known
+pub struct PendingSpend {
+    pool: DbPool,
+    estimate: SpendEstimate,
+    operation_type: String,
+    meter: UsageMeter,
+}
+
+impl PendingSpend {
+    pub fn new(pool: DbPool, estimate: SpendEstimate, operation_type: &str, meter: UsageMeter) -> Self {
+        Self { pool, estimate, operation_type: operation_type.to_string(), meter }
+    }
+
+    /// Record the spend from the usage the provider reported or, when it
+    /// reported none, from the estimated prompt and `generated` counted with
+    /// the model's tokenizer
+    pub async fn record(self, generated: &str) {
+        let spend = match self.meter.reported() {
+            Some(usage) => self.estimate.with_usage(usage.input_tokens, usage.output_tokens),
+            None => {
+                let output_tokens = tokenizer::count_tokens(&self.estimate.model, generated) as u32;
+                self.estimate.with_usage(self.estimate.input_tokens, output_tokens)
+            }
+        };
+        match CreditTracker::record_spend(&self.pool, &spend, &self.operation_type).await {
+            Ok(alerts) => notify_budget_alerts(&alerts),
+            Err(e) => tracing::warn!("Failed to record AI spend: {}", e),
+        }
+    }
+}
+
+/// A routed result whose spend is recorded from what was actually generated
+#[async_trait]
+pub trait Metered: Sized + Send {
+    /// Record `spend` for this result and hand it back. Streams are recorded
+    /// when they end.
+    async fn record_spend(self, spend: PendingSpend) -> Self;
+}
+
+#[async_trait]
+impl Metered for String {
+    async fn record_spend(self, spend: PendingSpend) -> Self {
+        spend.record(&self).await;
+        self
+    }
+}
+
+#[async_trait]
+impl Metered for Vec<String> {
+    async fn record_spend(self, spend: PendingSpend) -> Self {
+        spend.record(&self.join("\n")).await;
+        self
+    }
+}
+
+#[async_trait]
+impl Metered for ToolTurn {
+    async fn record_spend(self, spend: PendingSpend) -> Self {
+        let generated = match &self {
+            ToolTurn::Answer(answer) => answer.clone(),
+            ToolTurn::Calls { content, calls } => {
+                format!("{}{}", content.as_deref().unwrap_or_default(), serde_json::to_string(calls).unwrap_or_default())
+            }
+        };
+        spend.record(&generated).await;
+        self
+    }
+}
+
+#[async_trait]
+impl Metered for ToolLoopResult {
+    async fn record_spend(self, spend: PendingSpend) -> Self {
+        spend.record(&self.text).await;
+        self
+    }
+}
+
+#[async_trait]
+impl Metered for TextChunkStream {
+    async fn record_spend(self, spend: PendingSpend) -> Self {
+        metered_stream(self, spend)
+    }
+}
+
+/// Count a stream's deltas and record its spend when it ends, or with the
+/// text received so far when it is dropped early because it was stopped or
+/// cancelled
+fn metered_stream(chunks: TextChunkStream, spend: PendingSpend) -> TextChunkStream {
+    struct Metering {
+        spend: Option<PendingSpend>,
+        generated: String,
+    }
+
+    impl Drop for Metering {
+        fn drop(&mut self) {
+            if let (Some(spend), Ok(runtime)) = (self.spend.take(), tokio::runtime::Handle::try_current()) {
+                let generated = std::mem::take(&mut self.generated);
+                runtime.spawn(async move { spend.record(&generated).await });
+            }
+        }
+    }
+
+    let metering = Metering { spend: Some(spend), generated: String::new() };
+    stream::unfold((chunks, metering), |(mut chunks, mut metering)| async move {
+        match chunks.next().await {
+            Some(delta) => {
+                if let Ok(text) = &delta {
+                    metering.generated.push_str(text);
+                }
+                Some((delta, (chunks, metering)))
+            }
+            None => {
+                if let Some(spend) = metering.spend.take() {
+                    spend.record(&metering.generated).await;
+                }
+                None
+            }
+        }
+    })
+    .boxed()
+}
+
+/// A budget's spend in its current period
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BudgetStatus {
+    pub budget: AIBudget,
+    pub period_start: String,
+    pub spent_usd: f64,
+    /// Spend once the request being checked is added
+    pub projected_usd: f64,
+}
+
+impl BudgetStatus {
+    /// Whether the budget stands in the way of the request being checked
+    pub fn refuses(&self) -> bool {
+        self.projected_usd > self.budget.limit_usd
+            && (self.budget.enforcement == BudgetEnforcement::Block || !self.budget.is_approved_for(&self.period_start))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "decision", rename_all = "snake_case")]
+pub enum BudgetDecision {
+    Allowed,
+    /// Over a confirm budget; allowed once the user approves the overrun
+    NeedsConfirmation { budget_id: String, message: String },
+    Blocked { budget_id: String, message: String },
+}
+
+/// Outcome of checking a request against the budgets before sending it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PreflightCheck {
+    pub estimate: SpendEstimate,
+    pub budgets: Vec<BudgetStatus>,
+    pub decision: BudgetDecision,
+}
+
+impl PreflightCheck {
+    /// Only provider budgets refuse the request, so another provider could serve it
+    pub fn provider_limited(&self) -> bool {
+        let mut refusing = self.budgets.iter().filter(|s| s.refuses()).peekable();
+        refusing.peek().is_some() && refusing.all(|s| s.budget.scope == BudgetScope::Provider)
+    }
+
+    /// The estimate if the request may be sent, otherwise the budget error
+    pub fn into_result(self) -> Result<SpendEstimate> {
+        match self.decision {
+            BudgetDecision::Allowed => Ok(self.estimate),
+            BudgetDecision::NeedsConfirmation { budget_id, message } => {
+                Err(StoryWeaverError::budget_exceeded(budget_id, message, true))
+            }
+            BudgetDecision::Blocked { budget_id, message } => {
+                Err(StoryWeaverError::budget_exceeded(budget_id, message, false))
+            }
+        }
+    }
+}
+
+/// A budget threshold crossed for the first time in its period
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BudgetAlert {
+    pub budget: AIBudget,
+    pub threshold: f64,
+    pub spent_usd: f64,
+}
+
+impl BudgetAlert {
+    pub fn message(&self) -> String {
+        format!(
+            "{} has used {:.0}% of its ${:.2} {} limit (${:.2})",
+            self.budget.describe(),
+            self.threshold * 100.0,
+            self.budget.limit_usd,
+            self.budget.period.as_str(),
+            self.spent_usd
+        )
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpendForecast {
+    pub project_id: String,
+    pub spent_this_month_usd: f64,
+    pub daily_average_usd: f64,
+    /// Spend by the end of the month at the current daily average
+    pub projected_month_usd: f64,
+    pub monthly_limit_usd: Option<f64>,
+    /// Day the project's monthly budget runs out at the current pace, if it does this month
+    pub limit_reached_on: Option<String>,
+}
+
+/// Raise budget alerts as desktop notifications from now on
+pub fn set_budget_notifier(app_handle: tauri::AppHandle) {
+    let _ = BUDGET_NOTIFIER.set(app_handle);
+}
+
+/// Log budget alerts and show them as desktop notifications
+pub fn notify_budget_alerts(alerts: &[BudgetAlert]) {
+    use tauri_plugin_notification::NotificationExt;
+
+    for alert in alerts {
+        let message = alert.message();
+        tracing::warn!("{}", message);
+        if let Some(app_handle) = BUDGET_NOTIFIER.get() {
+            if let Err(e) = app_handle.notification().builder().title("AI budget alert").body(&message).show() {
+                tracing::warn!("Failed to show budget notification: {}", e);
+            }
+        }
+    }
 }
 
 impl AdvancedAIManager {
@@ -151,29 +616,35 @@ impl AdvancedAIManager {
         self.ai_providers.insert(name, provider);
     }
 
-    pub async fn generate_with_advanced_features(
+    /// Embed with the app's routed providers instead of the offline embedder
+    pub fn set_embedder(&mut self, embedder: Arc<AIProviderManager>) {
+        self.saliency_engine.set_embedder(embedder);
+    }
+
+    /// Prompt and context for a prose-mode generation, shared by the blocking
+    /// and streaming paths
+    pub async fn prepare_generation(
         &mut self,
-        request: AdvancedGenerationRequest,
-        story_bible: Option<StoryBibleElements>,
-    ) -> Result<AdvancedGenerationResult> {
+        request: &AdvancedGenerationRequest,
+        story_bible: Option<&StoryBibleElements>,
+    ) -> Result<(String, AIContext, Option<SaliencyContext>)> {
         // Get prose mode settings
         let generation_settings = self.prose_manager
             .create_generation_settings(&request.prose_mode, request.ultra_creative)
             .ok_or_else(|| StoryWeaverError::invalid_input("Invalid prose mode".to_string()))?;
 
         // Build saliency context if requested
-        let saliency_context = if request.use_saliency_engine {
-            if let Some(sb) = story_bible.as_ref() {
-                Some(self.saliency_engine.build_context(
+        let saliency_context = match story_bible {
+            Some(sb) if request.use_saliency_engine => Some(
+                self.build_saliency_context(
                     &request.project_id,
+                    request.document_id.as_deref(),
                     &request.text_context,
                     sb,
-                ).map_err(|e| StoryWeaverError::SaliencyEngineError { message: e.to_string() })?)
-            } else {
-                None
-            }
-        } else {
-            None
+                )
+                .await?,
+            ),
+            _ => None,
         };
 
         // Build enhanced context
@@ -199,17 +670,29 @@ impl AdvancedAIManager {
             project_id: Some(request.project_id.clone()),
             document_id: request.document_id.clone(),
             preceding_text: Some(enhanced_context),
+            prose_mode: Some(request.prose_mode.clone()),
             ..Default::default()
         };
 
+        let prompt = generation_settings.special_instructions.unwrap_or_default();
+        Ok((prompt, ai_context, saliency_context))
+    }
+
+    pub async fn generate_with_advanced_features(
+        &mut self,
+        request: AdvancedGenerationRequest,
+        story_bible: Option<StoryBibleElements>,
+    ) -> Result<AdvancedGenerationResult> {
+        let (prompt, ai_context, saliency_context) = self.prepare_generation(&request, story_bible.as_ref()).await?;
+
         // Get appropriate AI provider based on prose mode
         let provider_name = self.get_provider_for_prose_mode(&request.prose_mode)?;
         let provider = self.ai_providers.get_mut(&provider_name)
             .ok_or_else(|| StoryWeaverError::ResourceUnavailable { resource: "AI provider".to_string() })?;
 
         // Generate text
-        let prompt = generation_settings.special_instructions.unwrap_or_default();
         let generated_text = provider.generate_text(&prompt, &ai_context).await?;
+        let model = provider.get_model_name().to_string();
 
         // Perform cliché detection if ultra-creative mode
         let cliche_detection = if request.ultra_creative {
@@ -218,8 +701,20 @@ impl AdvancedAIManager {
             None
         };
 
+        // Flag output that doesn't sound like the author before it's accepted
+        let style_drift = match get_pool() {
+            Ok(pool) => match project_fingerprint(&pool, &request.project_id).await {
+                Ok(fingerprint) => fingerprint.map(|fingerprint| fingerprint.score(&generated_text)),
+                Err(e) => {
+                    tracing::warn!("Failed to build style fingerprint: {}", e);
+                    None
+                }
+            },
+            Err(_) => None,
+        };
+
         // Calculate token count and credits
-        let token_count = self.estimate_tokens(&generated_text);
+        let token_count = self.estimate_tokens(&model, &generated_text);
         let credits_used = self.calculate_credits(&request.prose_mode, token_count);
 
         // Track credit usage
@@ -232,6 +727,7 @@ impl AdvancedAIManager {
             prose_mode_used: request.prose_mode,
             saliency_context,
             cliche_detection,
+            style_drift,
             token_count,
             credits_used,
             generation_id,
@@ -267,46 +763,35 @@ impl AdvancedAIManager {
         Ok(generated_image)
     }
 
+    /// Provider used for brainstorming (prefer openai -> claude -> gemini -> any)
+    fn brainstorm_provider(&self) -> Result<&dyn AIProvider> {
+        ["openai", "claude", "gemini"]
+            .iter()
+            .find_map(|name| self.ai_providers.get(*name))
+            .or_else(|| self.ai_providers.values().next())
+            .map(|provider| provider.as_ref())
+            .ok_or_else(|| StoryWeaverError::ResourceUnavailable { resource: "AI provider".to_string() })
+    }
+
     pub async fn create_brainstorm_session(
         &mut self,
         request: BrainstormRequest,
     ) -> Result<String> {
-        let session_id = self.brainstorm_engine.create_session(request.clone())?;
-        
+        let pool = get_pool()?;
+
         // Build the base prompt for context
-        let base_prompt = self.brainstorm_engine
-            .build_generation_prompt(&request)
-            .map_err(|e| StoryWeaverError::internal(format!("Failed to build brainstorm prompt: {}", e)))?;
-        
-        // Select an AI provider (prefer openai -> claude -> gemini -> any)
-        let provider_name = if self.ai_providers.contains_key("openai") {
-            "openai".to_string()
-        } else if self.ai_providers.contains_key("claude") {
-            "claude".to_string()
-        } else if self.ai_providers.contains_key("gemini") {
-            "gemini".to_string()
-        } else {
-            self.ai_providers
-                .keys()
-                .next()
-                .cloned()
-                .ok_or_else(|| StoryWeaverError::ResourceUnavailable { resource: "AI provider".to_string() })?
-        };
-        let provider = self
-            .ai_providers
-            .get_mut(&provider_name)
-            .ok_or_else(|| StoryWeaverError::ResourceUnavailable { resource: "AI provider".to_string() })?;
-        
+        let base_prompt = self.brainstorm_engine.build_generation_prompt(&request)?.text;
+        let provider = self.brainstorm_provider()?;
+        let session_id = self.brainstorm_engine.create_session(&pool, request.clone()).await?;
+
         // Generate initial ideas using the AI provider
         let ideas = self
             .brainstorm_engine
-            .generate_ideas(&session_id, &request, Some(provider.as_ref()))
-            .await
-            .map_err(|e| StoryWeaverError::AIGenerationError { message: e.to_string() })?;
+            .generate_ideas(&pool, &session_id, &request, Some(provider))
+            .await?;
         
         // Persist generated ideas as AI response cards
         if !ideas.is_empty() {
-            let pool = get_pool()?;
             let model_name = provider.get_model_name().to_string();
             for idea in &ideas {
                 let tags_str = if idea.tags.is_empty() {
@@ -596,9 +1081,8 @@ Focus on extracting concrete, usable story elements that would be valuable for a
         }
     }
 
-    fn estimate_tokens(&self, text: &str) -> i32 {
-        // Rough estimation: 1 token ≈ 0.75 words
-        (text.split_whitespace().count() as f32 * 1.33) as i32
+    fn estimate_tokens(&self, model: &str, text: &str) -> i32 {
+        super::tokenizer::count_tokens(model, text) as i32
     }
 
     fn calculate_credits(&self, prose_mode: &str, token_count: i32) -> i32 {
@@ -628,8 +1112,14 @@ Focus on extracting concrete, usable story elements that would be valuable for a
         self.prose_manager.list_prose_modes()
     }
 
-    pub fn get_brainstorm_session(&self, session_id: &str) -> Option<&BrainstormSession> {
-        self.brainstorm_engine.get_session(session_id)
+    pub async fn get_brainstorm_session(&self, session_id: &str) -> Result<Option<BrainstormSession>> {
+        let pool = get_pool()?;
+        self.brainstorm_engine.get_session(&pool, session_id).await
+    }
+
+    pub async fn list_brainstorm_sessions(&self, project_id: &str) -> Result<Vec<BrainstormSession>> {
+        let pool = get_pool()?;
+        self.brainstorm_engine.list_project_sessions(&pool, project_id).await
     }
 
     pub async fn get_generated_images(&self, project_id: &str) -> Result<Vec<GeneratedImage>> {
@@ -641,14 +1131,52 @@ Focus on extracting concrete, usable story elements that would be valuable for a
         Ok(())
     }
 
-    pub fn rate_brainstorm_idea(&mut self, session_id: &str, idea_id: &str, rating: u32) -> Result<()> {
-        self.brainstorm_engine.rate_idea(session_id, idea_id, rating as i32)?;
-        Ok(())
+    pub async fn rate_brainstorm_idea(&self, session_id: &str, idea_id: &str, rating: u32) -> Result<()> {
+        let pool = get_pool()?;
+        self.brainstorm_engine.rate_idea(&pool, session_id, idea_id, rating as i32).await
     }
 
-    pub fn mark_idea_as_keeper(&mut self, session_id: &str, idea_id: &str, _is_keeper: bool) -> Result<()> {
-        self.brainstorm_engine.mark_as_keeper(session_id, idea_id)?;
-        Ok(())
+    pub async fn mark_idea_as_keeper(&self, session_id: &str, idea_id: &str, is_keeper: bool) -> Result<()> {
+        let pool = get_pool()?;
+        if is_keeper {
+            self.brainstorm_engine.mark_as_keeper(&pool, session_id, idea_id).await
+        } else {
+            self.brainstorm_engine.remove_keeper(&pool, session_id, idea_id).await
+        }
+    }
+
+    pub async fn brainstorm_more_like_this(&mut self, session_id: &str, num_ideas: usize) -> Result<Vec<BrainstormIdea>> {
+        let pool = get_pool()?;
+        let provider = self.brainstorm_provider()?;
+        let ideas = self.brainstorm_engine.more_like_this(&pool, session_id, num_ideas, provider).await?;
+
+        if let Some(session) = self.brainstorm_engine.get_session(&pool, session_id).await? {
+            let credits_used = 100 + ideas.len() as i32 * 10;
+            self.credit_tracker.track_usage(&session.project_id, credits_used);
+        }
+        Ok(ideas)
+    }
+
+    /// Merge two ideas, written together by the AI when a provider is configured
+    pub async fn merge_brainstorm_ideas(&self, session_id: &str, first_id: &str, second_id: &str) -> Result<BrainstormIdea> {
+        let pool = get_pool()?;
+        let provider = self.brainstorm_provider().ok();
+        self.brainstorm_engine.merge_ideas(&pool, session_id, first_id, second_id, provider).await
+    }
+
+    pub async fn deduplicate_brainstorm_ideas(&self, session_id: &str) -> Result<Vec<DuplicateIdea>> {
+        let pool = get_pool()?;
+        self.brainstorm_engine.deduplicate(&pool, session_id).await
+    }
+
+    pub async fn cluster_brainstorm_ideas(&self, session_id: &str) -> Result<Vec<IdeaTheme>> {
+        let pool = get_pool()?;
+        self.brainstorm_engine.cluster_themes(&pool, session_id).await
+    }
+
+    pub async fn export_brainstorm_keepers(&self, session_id: &str) -> Result<StoryBibleExport> {
+        let pool = get_pool()?;
+        self.brainstorm_engine.export_keepers_to_story_bible(&pool, session_id).await
     }
 
     pub async fn get_daily_usage(&self) -> Result<i32> {
@@ -659,36 +1187,25 @@ Focus on extracting concrete, usable story elements that would be valuable for a
         Ok(self.credit_tracker.get_credit_status().await)
     }
 
+    /// Saliency context for the text before the cursor. Visibility and
+    /// chapter recency missing from `story_bible` are read from the database.
     pub async fn build_saliency_context(
         &mut self,
         project_id: &str,
+        document_id: Option<&str>,
         text_context: &str,
         story_bible: &StoryBibleElements,
     ) -> Result<SaliencyContext> {
-        self.saliency_engine.build_context(project_id, text_context, story_bible)
-            .map_err(|e| StoryWeaverError::SaliencyEngineError { message: e.to_string() })
-    }
-
-    pub async fn start_streaming_generation(
-        &mut self,
-        _request: AdvancedGenerationRequest,
-        _story_bible: Option<StoryBibleElements>,
-    ) -> Result<String> {
-        // For now, return a placeholder stream ID
-        // This would be implemented with actual streaming in the future
-        let stream_id = uuid::Uuid::new_v4().to_string();
-        Ok(stream_id)
-    }
+        let mut story_bible = story_bible.clone();
+        if let Ok(pool) = get_pool() {
+            if let Err(e) = annotate_from_database(&pool, project_id, document_id, &mut story_bible).await {
+                tracing::warn!("Failed to load story bible visibility and recency: {}", e);
+            }
+        }
 
-    pub async fn get_stream_status(
-        &self,
-        _stream_id: &str,
-    ) -> Result<std::collections::HashMap<String, serde_json::Value>> {
-        // Placeholder implementation for streaming status
-        let mut status = std::collections::HashMap::new();
-        status.insert("status".to_string(), serde_json::Value::String("completed".to_string()));
-        status.insert("progress".to_string(), serde_json::Value::Number(serde_json::Number::from(100)));
-        Ok(status)
+        self.saliency_engine.build_context(project_id, text_context, &story_bible)
+            .await
+            .map_err(|e| StoryWeaverError::SaliencyEngineError { message: e.to_string() })
     }
 
     pub async fn analyze_content_for_import(
@@ -2,7 +2,13 @@ use serde::{Deserialize, Serialize};
 use std::str::FromStr;
 use uuid::Uuid;
 use std::collections::HashMap;
-use super::{AIProvider, AIContext};
+use super::{AIProvider, AIContext, JsonSchema, StructuredGeneration};
+use super::local_embedding::{cosine_similarity, LocalEmbeddingProvider};
+use super::prompt_templates::{render_prompt, PromptScope, RenderedPrompt};
+use crate::database::models::{AIGenerationType, Character, CharacterRole, PlotThread, PlotThreadStatus, ThreadPriority, VisibilityLevel, WorldElement};
+use crate::database::operations::{AIHistoryOps, BrainstormSessionOps, CharacterOps, PlotThreadOps, WorldElementOps};
+use crate::database::DbPool;
+use crate::error::{Result, StoryWeaverError};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BrainstormSession {
@@ -17,6 +23,21 @@ pub struct BrainstormSession {
     pub updated_at: chrono::DateTime<chrono::Utc>,
 }
 
+impl BrainstormSession {
+    /// Ideas grouped by the theme `BrainstormEngine::cluster_themes` gave them
+    pub fn themes(&self) -> Vec<IdeaTheme> {
+        let mut themes: Vec<IdeaTheme> = Vec::new();
+        for idea in &self.ideas {
+            let Some(name) = &idea.theme else { continue };
+            match themes.iter_mut().find(|theme| &theme.name == name) {
+                Some(theme) => theme.idea_ids.push(idea.id.clone()),
+                None => themes.push(IdeaTheme { name: name.clone(), idea_ids: vec![idea.id.clone()] }),
+            }
+        }
+        themes
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub enum BrainstormCategory {
     #[default]
@@ -55,7 +76,7 @@ impl BrainstormCategory {
 impl FromStr for BrainstormCategory {
     type Err = String;
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
         match s {
             "Characters" => Ok(BrainstormCategory::Characters),
             "Plot" => Ok(BrainstormCategory::Plot),
@@ -73,41 +94,38 @@ impl FromStr for BrainstormCategory {
 }
 
 impl BrainstormCategory {
-    pub fn get_prompt_template(&self) -> String {
+    /// Value of the `category` variable in the `brainstorm` prompt template
+    pub fn template_key(&self) -> &'static str {
         match self {
-            BrainstormCategory::Characters => {
-                "Generate creative character ideas including their personality traits, backgrounds, motivations, and unique characteristics. Consider diverse perspectives and compelling flaws.".to_string()
-            },
-            BrainstormCategory::Plot => {
-                "Brainstorm plot ideas, story arcs, plot twists, and narrative structures. Focus on compelling conflicts and satisfying resolutions.".to_string()
-            },
-            BrainstormCategory::Worldbuilding => {
-                "Create worldbuilding elements including cultures, societies, magic systems, technology, geography, and unique world features.".to_string()
-            },
-            BrainstormCategory::Dialogue => {
-                "Generate dialogue ideas, character voice patterns, memorable quotes, and conversation scenarios that reveal character and advance plot.".to_string()
-            },
-            BrainstormCategory::Scenes => {
-                "Brainstorm scene ideas, dramatic moments, action sequences, and emotional beats that would be compelling to read.".to_string()
-            },
-            BrainstormCategory::Themes => {
-                "Explore thematic elements, deeper meanings, philosophical questions, and universal truths that could be woven throughout the story.".to_string()
-            },
-            BrainstormCategory::Conflicts => {
-                "Generate conflict ideas including internal struggles, interpersonal tensions, societal issues, and external obstacles.".to_string()
-            },
-            BrainstormCategory::Settings => {
-                "Create setting ideas including locations, environments, atmospheres, and places that would enhance the story's mood and themes.".to_string()
-            },
-            BrainstormCategory::Relationships => {
-                "Brainstorm relationship dynamics, character interactions, romantic elements, friendships, rivalries, and family connections.".to_string()
-            },
-            BrainstormCategory::Backstory => {
-                "Generate backstory elements, character histories, past events, formative experiences, and hidden secrets that shape the present narrative.".to_string()
-            },
-            BrainstormCategory::Custom(_) => {
-                "Generate creative ideas related to the specified topic. Think outside the box and explore unique angles and perspectives.".to_string()
-            },
+            BrainstormCategory::Characters => "characters",
+            BrainstormCategory::Plot => "plot",
+            BrainstormCategory::Worldbuilding => "worldbuilding",
+            BrainstormCategory::Dialogue => "dialogue",
+            BrainstormCategory::Scenes => "scenes",
+            BrainstormCategory::Themes => "themes",
+            BrainstormCategory::Conflicts => "conflicts",
+            BrainstormCategory::Settings => "settings",
+            BrainstormCategory::Relationships => "relationships",
+            BrainstormCategory::Backstory => "backstory",
+            BrainstormCategory::Custom(_) => "custom",
+        }
+    }
+
+    /// Story bible entry a keeper of this category becomes, with the
+    /// element type for world elements
+    pub fn story_bible_kind(&self) -> (StoryBibleKind, &'static str) {
+        match self {
+            BrainstormCategory::Characters | BrainstormCategory::Relationships | BrainstormCategory::Backstory => {
+                (StoryBibleKind::Character, "")
+            }
+            BrainstormCategory::Worldbuilding => (StoryBibleKind::WorldElement, "concept"),
+            BrainstormCategory::Settings => (StoryBibleKind::WorldElement, "location"),
+            BrainstormCategory::Themes => (StoryBibleKind::WorldElement, "theme"),
+            BrainstormCategory::Plot
+            | BrainstormCategory::Conflicts
+            | BrainstormCategory::Scenes
+            | BrainstormCategory::Dialogue
+            | BrainstormCategory::Custom(_) => (StoryBibleKind::PlotThread, ""),
         }
     }
 }
@@ -121,9 +139,116 @@ pub struct BrainstormIdea {
     pub rating: Option<i32>, // 1-5 stars
     pub notes: String,
     pub is_keeper: bool,
+    #[serde(default)]
+    pub origin: IdeaOrigin,
+    /// Ideas this one was seeded by or merged from
+    #[serde(default)]
+    pub derived_from: Vec<String>,
+    #[serde(default)]
+    pub theme: Option<String>,
+    /// Story bible entry the idea was exported as
+    #[serde(default)]
+    pub exported_to: Option<StoryBibleLink>,
     pub created_at: chrono::DateTime<chrono::Utc>,
 }
 
+/// How an idea came about
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum IdeaOrigin {
+    #[default]
+    Generated,
+    /// Filled in from a built-in template when no AI provider is available
+    Template,
+    /// Generated from the session's keepers
+    MoreLikeThis,
+    /// Combined from two other ideas
+    Merged,
+}
+
+impl IdeaOrigin {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Generated => "generated",
+            Self::Template => "template",
+            Self::MoreLikeThis => "more_like_this",
+            Self::Merged => "merged",
+        }
+    }
+
+    pub fn parse(value: &str) -> Self {
+        match value {
+            "template" => Self::Template,
+            "more_like_this" => Self::MoreLikeThis,
+            "merged" => Self::Merged,
+            _ => Self::Generated,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StoryBibleKind {
+    Character,
+    WorldElement,
+    PlotThread,
+}
+
+impl StoryBibleKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Character => "character",
+            Self::WorldElement => "world_element",
+            Self::PlotThread => "plot_thread",
+        }
+    }
+}
+
+/// A story bible row created from an idea
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StoryBibleLink {
+    pub kind: StoryBibleKind,
+    pub id: String,
+}
+
+impl StoryBibleLink {
+    pub fn parse(kind: &str, id: String) -> Option<Self> {
+        let kind = match kind {
+            "character" => StoryBibleKind::Character,
+            "world_element" => StoryBibleKind::WorldElement,
+            "plot_thread" => StoryBibleKind::PlotThread,
+            _ => return None,
+        };
+        Some(Self { kind, id })
+    }
+}
+
+/// Ideas that share a theme
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IdeaTheme {
+    pub name: String,
+    pub idea_ids: Vec<String>,
+}
+
+/// An idea removed as a near-duplicate of another
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DuplicateIdea {
+    pub id: String,
+    pub content: String,
+    pub duplicate_of: String,
+    pub similarity: f32,
+}
+
+/// Story bible rows created from a session's keepers
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StoryBibleExport {
+    pub characters: Vec<Character>,
+    pub world_elements: Vec<WorldElement>,
+    pub plot_threads: Vec<PlotThread>,
+    /// Keepers exported before, which are not exported again
+    pub already_exported: Vec<StoryBibleLink>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BrainstormRequest {
     pub project_id: String,
@@ -142,6 +267,10 @@ pub struct BrainstormConfig {
     pub enable_auto_tagging: bool,
     pub enable_idea_scoring: bool,
     pub creativity_boost_threshold: i32,
+    /// Embedding similarity at which two ideas count as the same idea
+    pub duplicate_threshold: f32,
+    /// Embedding similarity an idea needs to join a theme
+    pub theme_threshold: f32,
 }
 
 impl Default for BrainstormConfig {
@@ -152,22 +281,138 @@ impl Default for BrainstormConfig {
             enable_auto_tagging: true,
             enable_idea_scoring: true,
             creativity_boost_threshold: 7,
+            duplicate_threshold: 0.9,
+            theme_threshold: 0.3,
         }
     }
 }
 
+/// Structured reply for `BrainstormEngine::generate_ideas`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct GeneratedIdeas {
+    ideas: Vec<GeneratedIdea>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct GeneratedIdea {
+    content: String,
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+fn ideas_schema() -> JsonSchema {
+    JsonSchema::new(
+        "brainstorm_ideas",
+        serde_json::json!({
+            "type": "object",
+            "properties": {
+                "ideas": {
+                    "type": "array",
+                    "minItems": 1,
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "content": { "type": "string", "minLength": 1 },
+                            "tags": { "type": "array", "items": { "type": "string" } }
+                        },
+                        "required": ["content"],
+                        "additionalProperties": false
+                    }
+                }
+            },
+            "required": ["ideas"],
+            "additionalProperties": false
+        }),
+    )
+}
+
+/// Words too common to name a theme after
+const THEME_STOPWORDS: &[&str] = &[
+    "about", "after", "also", "because", "been", "before", "being", "could", "every", "from", "have", "into",
+    "just", "like", "more", "must", "only", "other", "over", "should", "some", "someone", "something", "than",
+    "that", "their", "them", "then", "there", "these", "they", "this", "those", "through", "what", "when",
+    "where", "which", "while", "whose", "with", "would", "your",
+];
+
+/// Longest story bible name made from an idea
+const MAX_ENTRY_NAME_CHARS: usize = 60;
+
+/// Name for a story bible entry: the idea's first sentence, shortened at a word
+fn entry_name(content: &str) -> String {
+    let first = content
+        .split(['.', '!', '?', ';', ':', '\n'])
+        .map(str::trim)
+        .find(|part| !part.is_empty())
+        .unwrap_or(content.trim());
+    if first.chars().count() <= MAX_ENTRY_NAME_CHARS {
+        return first.to_string();
+    }
+    let cut: String = first.chars().take(MAX_ENTRY_NAME_CHARS).collect();
+    let cut = cut.rsplit_once(' ').map(|(head, _)| head).unwrap_or(&cut);
+    format!("{}…", cut.trim_end_matches([',', ' ']))
+}
+
+/// Label for a group of ideas: the tag most of them share, otherwise their
+/// most widespread significant word
+fn theme_name(ideas: &[&BrainstormIdea]) -> Option<String> {
+    fn most_shared(words: impl Iterator<Item = Vec<String>>) -> Option<(String, usize)> {
+        let mut counts: Vec<(String, usize)> = Vec::new();
+        for per_idea in words {
+            let mut seen = Vec::new();
+            for word in per_idea {
+                if seen.contains(&word) {
+                    continue;
+                }
+                match counts.iter_mut().find(|(w, _)| *w == word) {
+                    Some((_, count)) => *count += 1,
+                    None => counts.push((word.clone(), 1)),
+                }
+                seen.push(word);
+            }
+        }
+        // First seen wins ties, so names are stable
+        counts.into_iter().fold(None, |best, (word, count)| match best {
+            Some((_, best_count)) if best_count >= count => best,
+            _ => Some((word, count)),
+        })
+    }
+
+    let min_share = if ideas.len() > 1 { 2 } else { 1 };
+    if let Some((tag, _)) = most_shared(ideas.iter().map(|idea| idea.tags.clone())).filter(|(_, n)| *n >= min_share) {
+        return Some(capitalize(&tag));
+    }
+
+    let words = ideas.iter().map(|idea| {
+        idea.content
+            .to_lowercase()
+            .split(|c: char| !c.is_alphabetic())
+            .filter(|word| word.chars().count() >= 4 && !THEME_STOPWORDS.contains(word))
+            .map(str::to_string)
+            .collect::<Vec<_>>()
+    });
+    most_shared(words).map(|(word, _)| capitalize(&word))
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
 pub struct BrainstormEngine {
     config: BrainstormConfig,
-    sessions: HashMap<String, BrainstormSession>,
     idea_templates: HashMap<String, Vec<String>>,
+    embeddings: LocalEmbeddingProvider,
 }
 
 impl BrainstormEngine {
     pub fn new(config: BrainstormConfig) -> Self {
         let mut engine = Self {
             config,
-            sessions: HashMap::new(),
             idea_templates: HashMap::new(),
+            embeddings: LocalEmbeddingProvider::new(),
         };
         engine.initialize_templates();
         engine
@@ -202,9 +447,9 @@ impl BrainstormEngine {
         ]);
     }
 
-    pub fn create_session(&mut self, request: BrainstormRequest) -> Result<String, Box<dyn std::error::Error>> {
+
+    pub async fn create_session(&self, pool: &DbPool, request: BrainstormRequest) -> Result<String> {
         let session_id = Uuid::new_v4().to_string();
-        
         let session = BrainstormSession {
             id: session_id.clone(),
             project_id: request.project_id,
@@ -217,22 +462,26 @@ impl BrainstormEngine {
             updated_at: chrono::Utc::now(),
         };
 
-        self.sessions.insert(session_id.clone(), session);
+        BrainstormSessionOps::create(pool, &session).await?;
         Ok(session_id)
     }
 
     pub async fn generate_ideas(
-        &mut self,
+        &self,
+        pool: &DbPool,
         session_id: &str,
         request: &BrainstormRequest,
         ai_provider: Option<&dyn AIProvider>,
-    ) -> Result<Vec<BrainstormIdea>, Box<dyn std::error::Error>> {
+    ) -> Result<Vec<BrainstormIdea>> {
         let mut ideas = Vec::new();
         let num_ideas = request.num_ideas.min(self.config.max_ideas_per_session);
 
+        // Fail before spending a request on a session that doesn't exist
+        BrainstormSessionOps::get(pool, session_id).await?;
+
         // Generate base prompt
         let base_prompt = self.build_generation_prompt(request)?;
-        
+
         // If an AI provider is available, use it to generate ideas
         if let Some(provider) = ai_provider {
             // Build AI context
@@ -242,131 +491,454 @@ impl BrainstormEngine {
             ai_ctx.key_details = Some(request.focus_areas.clone());
             ai_ctx.creativity_level = Some(request.creativity_level as u8);
             ai_ctx.feature_type = Some(super::WritingFeature::Brainstorm);
-            
-            // Call provider brainstorm with base prompt; truncate to requested count
-            let mut generated = provider.brainstorm(&base_prompt, &ai_ctx).await?;
-            if generated.len() > num_ideas {
-                generated.truncate(num_ideas);
-            }
-            
-            for idea_text in generated {
-                // Pre-compute values that require immutable borrows
-                let tags = if self.config.enable_auto_tagging {
-                    self.auto_generate_tags(&idea_text)
-                } else {
-                    Vec::new()
-                };
-                
-                let rating = if self.config.enable_idea_scoring {
-                    Some(self.score_idea(&idea_text))
-                } else {
-                    None
-                };
-                
-                let idea = BrainstormIdea {
-                    id: Uuid::new_v4().to_string(),
-                    content: idea_text,
-                    category: request.category.to_string(),
-                    tags,
-                    rating,
-                    notes: String::new(),
-                    is_keeper: false,
-                    created_at: chrono::Utc::now(),
-                };
-                ideas.push(idea.clone());
+
+            // Ask for typed ideas; truncate to requested count
+            let prompt = RenderedPrompt {
+                text: format!("{}\n\nGive {} distinct ideas.", base_prompt.text, num_ideas),
+                template: base_prompt.template,
+            };
+            let mut generated: GeneratedIdeas = provider
+                .generate_structured(&prompt.text, &ideas_schema(), &ai_ctx)
+                .await?;
+            generated.ideas.retain(|idea| !idea.content.trim().is_empty());
+            generated.ideas.truncate(num_ideas);
+            let response = generated.ideas.iter().map(|idea| idea.content.as_str()).collect::<Vec<_>>().join("\n");
+            AIHistoryOps::record_rendered(pool, &request.project_id, None, AIGenerationType::Brainstorm, provider, prompt, &response).await;
+
+            for generated_idea in generated.ideas {
+                ideas.push(self.build_idea(&generated_idea.content, &request.category, generated_idea.tags));
             }
-            
-            // Save to session and return
-            let session = self.sessions.get_mut(session_id)
-                .ok_or("Session not found")?;
-            
-            for idea in &ideas {
-                session.ideas.push(idea.clone());
+        } else {
+            // Fallback: template-based ideas
+            let templates = self.idea_templates.get(&request.category.to_string())
+                .cloned()
+                .unwrap_or_else(|| vec!["Generate a creative idea for {topic}".to_string()]);
+
+            for i in 0..num_ideas {
+                let template = &templates[i % templates.len()];
+                let idea_content = self.generate_idea_from_template(template, request)?;
+                let mut idea = self.build_idea(&idea_content, &request.category, Vec::new());
+                idea.origin = IdeaOrigin::Template;
+                ideas.push(idea);
             }
-            session.updated_at = chrono::Utc::now();
-            
-            return Ok(ideas);
         }
-        
-        // Fallback: template-based ideas
-        // In a real implementation, this would call the AI provider
-        let templates = self.idea_templates.get(&request.category.to_string())
-            .cloned()
-            .unwrap_or_else(|| vec!["Generate a creative idea for {topic}".to_string()]);
-
-        for i in 0..num_ideas {
-            let template = &templates[i % templates.len()];
-            let idea_content = self.generate_idea_from_template(template, request)?;
-            
-            // Pre-compute values that require immutable borrows
-            let tags = if self.config.enable_auto_tagging {
-                self.auto_generate_tags(&idea_content)
-            } else {
-                Vec::new()
-            };
-            
-            let rating = if self.config.enable_idea_scoring {
-                Some(self.score_idea(&idea_content))
-            } else {
-                None
-            };
-            
-            let idea = BrainstormIdea {
-                id: Uuid::new_v4().to_string(),
-                content: idea_content,
-                category: request.category.to_string(),
-                tags,
-                rating,
-                notes: String::new(),
-                is_keeper: false,
-                created_at: chrono::Utc::now(),
-            };
 
-            ideas.push(idea.clone());
+        BrainstormSessionOps::add_ideas(pool, session_id, &ideas).await?;
+        Ok(ideas)
+    }
+
+    /// New ideas in the direction of the session's keepers
+    pub async fn more_like_this(
+        &self,
+        pool: &DbPool,
+        session_id: &str,
+        num_ideas: usize,
+        ai_provider: &dyn AIProvider,
+    ) -> Result<Vec<BrainstormIdea>> {
+        let session = BrainstormSessionOps::get(pool, session_id).await?;
+        let (keepers, others): (Vec<&BrainstormIdea>, Vec<&BrainstormIdea>) =
+            session.ideas.iter().partition(|idea| idea.is_keeper);
+        if keepers.is_empty() {
+            return Err(StoryWeaverError::validation("Mark at least one idea as a keeper to get more like it"));
         }
+        let num_ideas = num_ideas.clamp(1, self.config.max_ideas_per_session);
 
-        // Now get mutable reference to session and add all ideas
-        let session = self.sessions.get_mut(session_id)
-            .ok_or("Session not found")?;
-        
-        for idea in &ideas {
-            session.ideas.push(idea.clone());
+        let values = serde_json::json!({
+            "category": session.category.to_string().to_lowercase(),
+            "keepers": keepers.iter().map(|idea| idea.content.as_str()).collect::<Vec<_>>(),
+            "others": others.iter().map(|idea| idea.content.as_str()).collect::<Vec<_>>(),
+            "num_ideas": num_ideas,
+        });
+        let prompt = render_prompt("brainstorm_more", &PromptScope::for_project(&session.project_id), &values)?;
+
+        let mut ai_ctx = AIContext::default();
+        ai_ctx.project_id = Some(session.project_id.clone());
+        ai_ctx.feature_type = Some(super::WritingFeature::Brainstorm);
+
+        let generated: GeneratedIdeas = ai_provider
+            .generate_structured(&prompt.text, &ideas_schema(), &ai_ctx)
+            .await?;
+        let response = generated.ideas.iter().map(|idea| idea.content.as_str()).collect::<Vec<_>>().join("\n");
+        AIHistoryOps::record_rendered(pool, &session.project_id, None, AIGenerationType::Brainstorm, ai_provider, prompt, &response).await;
+
+        // Skip ideas the session already has, and repeats within the reply
+        let mut known: Vec<Vec<f32>> = session.ideas.iter().map(|idea| self.embeddings.embed(&idea.content)).collect();
+        let keeper_ids: Vec<String> = keepers.iter().map(|idea| idea.id.clone()).collect();
+        let mut ideas = Vec::new();
+        for generated_idea in generated.ideas {
+            if ideas.len() >= num_ideas {
+                break;
+            }
+            if generated_idea.content.trim().is_empty() {
+                continue;
+            }
+            let vector = self.embeddings.embed(&generated_idea.content);
+            if known.iter().any(|other| cosine_similarity(&vector, other) >= self.config.duplicate_threshold) {
+                continue;
+            }
+            known.push(vector);
+
+            let mut idea = self.build_idea(&generated_idea.content, &session.category, generated_idea.tags);
+            idea.origin = IdeaOrigin::MoreLikeThis;
+            idea.derived_from = keeper_ids.clone();
+            ideas.push(idea);
         }
-        session.updated_at = chrono::Utc::now();
-        
+
+        BrainstormSessionOps::add_ideas(pool, session_id, &ideas).await?;
         Ok(ideas)
     }
 
-    pub(crate) fn build_generation_prompt(&self, request: &BrainstormRequest) -> Result<String, Box<dyn std::error::Error>> {
-        let mut prompt = request.category.get_prompt_template();
-        
-        if let Some(seed) = &request.seed_prompt {
-            prompt.push_str(&format!("\n\nSeed idea: {}", seed));
+    /// Combine two ideas into one that replaces both. Without a provider the
+    /// two ideas are joined as written.
+    pub async fn merge_ideas(
+        &self,
+        pool: &DbPool,
+        session_id: &str,
+        first_id: &str,
+        second_id: &str,
+        ai_provider: Option<&dyn AIProvider>,
+    ) -> Result<BrainstormIdea> {
+        if first_id == second_id {
+            return Err(StoryWeaverError::validation("Pick two different ideas to merge"));
         }
-        
-        if let Some(context) = &request.context {
-            prompt.push_str(&format!("\n\nContext: {}", context));
+        let session = BrainstormSessionOps::get(pool, session_id).await?;
+        let first = BrainstormSessionOps::get_idea(pool, session_id, first_id).await?;
+        let second = BrainstormSessionOps::get_idea(pool, session_id, second_id).await?;
+
+        let content = match ai_provider {
+            Some(provider) => {
+                let values = serde_json::json!({
+                    "category": session.category.to_string().to_lowercase(),
+                    "first": first.content,
+                    "second": second.content,
+                });
+                let prompt = render_prompt("brainstorm_merge", &PromptScope::for_project(&session.project_id), &values)?;
+
+                let mut ai_ctx = AIContext::default();
+                ai_ctx.project_id = Some(session.project_id.clone());
+                ai_ctx.feature_type = Some(super::WritingFeature::Brainstorm);
+                let merged = provider.generate_text(&prompt.text, &ai_ctx).await?.trim().to_string();
+                AIHistoryOps::record_rendered(pool, &session.project_id, None, AIGenerationType::Brainstorm, provider, prompt, &merged).await;
+                merged
+            }
+            None => String::new(),
+        };
+        let content = if content.is_empty() {
+            format!("{} {}", first.content.trim(), second.content.trim())
+        } else {
+            content
+        };
+
+        let mut tags = first.tags.clone();
+        for tag in &second.tags {
+            if !tags.contains(tag) {
+                tags.push(tag.clone());
+            }
         }
-        
-        if !request.focus_areas.is_empty() {
-            prompt.push_str(&format!("\n\nFocus on: {}", request.focus_areas.join(", ")));
+        let notes: Vec<&str> = [first.notes.trim(), second.notes.trim()].into_iter().filter(|n| !n.is_empty()).collect();
+
+        let merged = BrainstormIdea {
+            id: Uuid::new_v4().to_string(),
+            content,
+            category: first.category.clone(),
+            tags,
+            rating: first.rating.max(second.rating),
+            notes: notes.join("\n\n"),
+            is_keeper: first.is_keeper || second.is_keeper,
+            origin: IdeaOrigin::Merged,
+            derived_from: vec![first.id.clone(), second.id.clone()],
+            theme: first.theme.clone().or_else(|| second.theme.clone()),
+            exported_to: None,
+            created_at: chrono::Utc::now(),
+        };
+
+        BrainstormSessionOps::merge_ideas(pool, session_id, &merged, first_id, second_id).await?;
+        Ok(merged)
+    }
+
+    /// Remove ideas that say the same thing as another idea in the session.
+    /// Keepers, then higher rated and then earlier ideas survive, and take
+    /// over the tags of the ideas removed in their favour.
+    pub async fn deduplicate(&self, pool: &DbPool, session_id: &str) -> Result<Vec<DuplicateIdea>> {
+        let session = BrainstormSessionOps::get(pool, session_id).await?;
+
+        let mut ranked: Vec<(usize, &BrainstormIdea)> = session.ideas.iter().enumerate().collect();
+        ranked.sort_by_key(|(position, idea)| (!idea.is_keeper, std::cmp::Reverse(idea.rating.unwrap_or(0)), *position));
+
+        let mut survivors: Vec<(BrainstormIdea, Vec<f32>)> = Vec::new();
+        let mut duplicates = Vec::new();
+        for (_, idea) in ranked {
+            let vector = self.embeddings.embed(&idea.content);
+            let closest = survivors
+                .iter()
+                .enumerate()
+                .map(|(index, (_, other))| (index, cosine_similarity(&vector, other)))
+                .filter(|(_, similarity)| *similarity >= self.config.duplicate_threshold)
+                .max_by(|a, b| a.1.total_cmp(&b.1));
+
+            match closest {
+                Some((index, similarity)) if !idea.is_keeper => {
+                    let survivor = &mut survivors[index].0;
+                    for tag in &idea.tags {
+                        if !survivor.tags.contains(tag) {
+                            survivor.tags.push(tag.clone());
+                        }
+                    }
+                    duplicates.push(DuplicateIdea {
+                        id: idea.id.clone(),
+                        content: idea.content.clone(),
+                        duplicate_of: survivor.id.clone(),
+                        similarity,
+                    });
+                }
+                _ => survivors.push((idea.clone(), vector)),
+            }
         }
-        
-        // Adjust creativity based on level
-        if request.creativity_level >= self.config.creativity_boost_threshold {
-            prompt.push_str("\n\nBe especially creative and think outside the box. Explore unconventional ideas and unique perspectives.");
+
+        if duplicates.is_empty() {
+            return Ok(duplicates);
         }
-        
-        prompt.push_str(&format!("\n\nGenerate {} distinct ideas.", request.num_ideas));
-        
-        Ok(prompt)
+        for (survivor, _) in &survivors {
+            let original = session.ideas.iter().find(|idea| idea.id == survivor.id);
+            if original.map_or(false, |original| original.tags != survivor.tags) {
+                BrainstormSessionOps::update_idea(pool, session_id, survivor).await?;
+            }
+        }
+        let removed: Vec<String> = duplicates.iter().map(|duplicate| duplicate.id.clone()).collect();
+        BrainstormSessionOps::delete_ideas(pool, session_id, &removed).await?;
+
+        Ok(duplicates)
+    }
+
+    /// Group the session's ideas into themes of similar ideas. Ideas like no
+    /// other idea get no theme.
+    pub async fn cluster_themes(&self, pool: &DbPool, session_id: &str) -> Result<Vec<IdeaTheme>> {
+        let session = BrainstormSessionOps::get(pool, session_id).await?;
+
+        // Greedy clustering: each idea joins the closest group whose centroid
+        // is similar enough, or starts its own
+        let mut clusters: Vec<(Vec<f32>, Vec<usize>)> = Vec::new();
+        for (index, idea) in session.ideas.iter().enumerate() {
+            let vector = self.embeddings.embed(&idea.content);
+            let closest = clusters
+                .iter()
+                .enumerate()
+                .map(|(cluster, (centroid, _))| (cluster, cosine_similarity(&vector, centroid)))
+                .filter(|(_, similarity)| *similarity >= self.config.theme_threshold)
+                .max_by(|a, b| a.1.total_cmp(&b.1));
+
+            match closest {
+                Some((cluster, _)) => {
+                    let (centroid, members) = &mut clusters[cluster];
+                    let count = members.len() as f32;
+                    for (value, added) in centroid.iter_mut().zip(&vector) {
+                        *value = (*value * count + added) / (count + 1.0);
+                    }
+                    members.push(index);
+                }
+                None => clusters.push((vector, vec![index])),
+            }
+        }
+
+        let mut themes: Vec<IdeaTheme> = Vec::new();
+        for (_, members) in clusters.into_iter().filter(|(_, members)| members.len() > 1) {
+            let ideas: Vec<&BrainstormIdea> = members.iter().map(|&index| &session.ideas[index]).collect();
+            let base = theme_name(&ideas).unwrap_or_else(|| "Theme".to_string());
+            let mut name = base.clone();
+            let mut suffix = 2;
+            while themes.iter().any(|theme| theme.name == name) {
+                name = format!("{} {}", base, suffix);
+                suffix += 1;
+            }
+            themes.push(IdeaTheme {
+                name,
+                idea_ids: ideas.iter().map(|idea| idea.id.clone()).collect(),
+            });
+        }
+
+        let assignments: Vec<(String, String)> = themes
+            .iter()
+            .flat_map(|theme| theme.idea_ids.iter().map(|id| (id.clone(), theme.name.clone())))
+            .collect();
+        BrainstormSessionOps::set_themes(pool, session_id, &assignments).await?;
+
+        Ok(themes)
+    }
+
+    pub async fn mark_as_keeper(&self, pool: &DbPool, session_id: &str, idea_id: &str) -> Result<()> {
+        let mut idea = BrainstormSessionOps::get_idea(pool, session_id, idea_id).await?;
+        idea.is_keeper = true;
+        BrainstormSessionOps::update_idea(pool, session_id, &idea).await
+    }
+
+    pub async fn remove_keeper(&self, pool: &DbPool, session_id: &str, idea_id: &str) -> Result<()> {
+        let mut idea = BrainstormSessionOps::get_idea(pool, session_id, idea_id).await?;
+        idea.is_keeper = false;
+        BrainstormSessionOps::update_idea(pool, session_id, &idea).await
+    }
+
+    pub async fn rate_idea(&self, pool: &DbPool, session_id: &str, idea_id: &str, rating: i32) -> Result<()> {
+        let mut idea = BrainstormSessionOps::get_idea(pool, session_id, idea_id).await?;
+        idea.rating = Some(rating.min(5).max(1));
+        BrainstormSessionOps::update_idea(pool, session_id, &idea).await
+    }
+
+    pub async fn add_idea_notes(&self, pool: &DbPool, session_id: &str, idea_id: &str, notes: String) -> Result<()> {
+        let mut idea = BrainstormSessionOps::get_idea(pool, session_id, idea_id).await?;
+        idea.notes = notes;
+        BrainstormSessionOps::update_idea(pool, session_id, &idea).await
+    }
+
+    pub async fn get_session(&self, pool: &DbPool, session_id: &str) -> Result<Option<BrainstormSession>> {
+        BrainstormSessionOps::get_by_id(pool, session_id).await
+    }
+
+    pub async fn list_project_sessions(&self, pool: &DbPool, project_id: &str) -> Result<Vec<BrainstormSession>> {
+        BrainstormSessionOps::get_by_project(pool, project_id).await
+    }
+
+    pub async fn get_keepers(&self, pool: &DbPool, session_id: &str) -> Result<Vec<BrainstormIdea>> {
+        let ideas = BrainstormSessionOps::get_ideas(pool, session_id).await?;
+        Ok(ideas.into_iter().filter(|idea| idea.is_keeper).collect())
+    }
+
+    /// Turn the session's keepers into characters, world elements or plot
+    /// threads, depending on the session's category. Keepers exported
+    /// before are left alone.
+    pub async fn export_keepers_to_story_bible(&self, pool: &DbPool, session_id: &str) -> Result<StoryBibleExport> {
+        let session = BrainstormSessionOps::get(pool, session_id).await?;
+        let (kind, element_type) = session.category.story_bible_kind();
+        let mut export = StoryBibleExport::default();
+
+        for idea in session.ideas.iter().filter(|idea| idea.is_keeper) {
+            if let Some(link) = &idea.exported_to {
+                export.already_exported.push(link.clone());
+                continue;
+            }
+
+            let name = entry_name(&idea.content);
+            let description = if idea.notes.trim().is_empty() {
+                idea.content.clone()
+            } else {
+                format!("{}\n\n{}", idea.content, idea.notes.trim())
+            };
+            let source = serde_json::json!({
+                "brainstorm": {
+                    "session_id": session.id,
+                    "idea_id": idea.id,
+                    "tags": idea.tags,
+                    "rating": idea.rating,
+                }
+            })
+            .to_string();
+
+            let id = match kind {
+                StoryBibleKind::Character => {
+                    let mut character = Character::new(session.project_id.clone(), name, CharacterRole::Supporting);
+                    character.description = Some(description);
+                    character.metadata = source;
+                    let character = CharacterOps::create(pool, character).await?;
+                    let id = character.id.clone();
+                    export.characters.push(character);
+                    id
+                }
+                StoryBibleKind::WorldElement => {
+                    let mut element = WorldElement::new(Some(session.project_id.clone()), name, element_type.to_string());
+                    element.description = Some(description);
+                    element.properties = source;
+                    let element = WorldElementOps::create(pool, element).await?;
+                    let id = element.id.clone();
+                    export.world_elements.push(element);
+                    id
+                }
+                StoryBibleKind::PlotThread => {
+                    let now = chrono::Utc::now();
+                    let thread = PlotThread {
+                        id: String::new(),
+                        project_id: session.project_id.clone(),
+                        name,
+                        description: Some(description),
+                        status: PlotThreadStatus::Planned,
+                        priority: ThreadPriority::Subplot,
+                        characters_involved: "[]".to_string(),
+                        documents_involved: "[]".to_string(),
+                        visibility: VisibilityLevel::Relevant,
+                        created_at: now,
+                        updated_at: now,
+                    };
+                    let thread = PlotThreadOps::create(pool, thread).await?;
+                    let id = thread.id.clone();
+                    export.plot_threads.push(thread);
+                    id
+                }
+            };
+
+            BrainstormSessionOps::mark_exported(pool, &idea.id, &StoryBibleLink { kind, id }).await?;
+        }
+
+        Ok(export)
+    }
+
+    /// A new idea with the configured auto tags and score, plus `extra_tags`
+    fn build_idea(&self, content: &str, category: &BrainstormCategory, extra_tags: Vec<String>) -> BrainstormIdea {
+        let content = content.trim().to_string();
+        let mut tags = if self.config.enable_auto_tagging {
+            self.auto_generate_tags(&content)
+        } else {
+            Vec::new()
+        };
+        for tag in extra_tags {
+            let tag = tag.trim().to_lowercase();
+            if !tag.is_empty() && !tags.contains(&tag) {
+                tags.push(tag);
+            }
+        }
+        let rating = if self.config.enable_idea_scoring {
+            Some(self.score_idea(&content))
+        } else {
+            None
+        };
+
+        BrainstormIdea {
+            id: Uuid::new_v4().to_string(),
+            content,
+            category: category.to_string(),
+            tags,
+            rating,
+            notes: String::new(),
+            is_keeper: false,
+            origin: IdeaOrigin::Generated,
+            derived_from: Vec::new(),
+            theme: None,
+            exported_to: None,
+            created_at: chrono::Utc::now(),
+        }
+    }
+
+    pub(crate) fn build_generation_prompt(&self, request: &BrainstormRequest) -> Result<RenderedPrompt> {
+        let topic = match &request.category {
+            BrainstormCategory::Custom(topic) => Some(topic.as_str()),
+            _ => None,
+        };
+        let values = serde_json::json!({
+            "category": request.category.template_key(),
+            "topic": topic,
+            "seed": request.seed_prompt,
+            "context": request.context,
+            "focus_areas": request.focus_areas,
+            // Adjust creativity based on level
+            "extra_creative": request.creativity_level >= self.config.creativity_boost_threshold,
+            "num_ideas": request.num_ideas,
+        });
+
+        render_prompt("brainstorm", &PromptScope::for_project(&request.project_id), &values)
     }
 
     fn generate_idea_from_template(
         &self,
         template: &str,
         request: &BrainstormRequest,
-    ) -> Result<String, Box<dyn std::error::Error>> {
+    ) -> Result<String> {
         // Simple template filling for demonstration
         // In a real implementation, this would use AI generation
         let mut idea = template.to_string();
@@ -465,104 +1037,6 @@ impl BrainstormEngine {
         score.min(5).max(1)
     }
 
-    pub fn mark_as_keeper(&mut self, session_id: &str, idea_id: &str) -> Result<(), Box<dyn std::error::Error>> {
-        let session = self.sessions.get_mut(session_id)
-            .ok_or("Session not found")?;
-        
-        // Find and mark the idea
-        if let Some(idea) = session.ideas.iter_mut().find(|i| i.id == idea_id) {
-            idea.is_keeper = true;
-            if !session.keepers.contains(&idea_id.to_string()) {
-                session.keepers.push(idea_id.to_string());
-            }
-            session.updated_at = chrono::Utc::now();
-            Ok(())
-        } else {
-            Err("Idea not found".into())
-        }
-    }
-
-    pub fn remove_keeper(&mut self, session_id: &str, idea_id: &str) -> Result<(), Box<dyn std::error::Error>> {
-        let session = self.sessions.get_mut(session_id)
-            .ok_or("Session not found")?;
-        
-        // Find and unmark the idea
-        if let Some(idea) = session.ideas.iter_mut().find(|i| i.id == idea_id) {
-            idea.is_keeper = false;
-            session.keepers.retain(|id| id != idea_id);
-            session.updated_at = chrono::Utc::now();
-            Ok(())
-        } else {
-            Err("Idea not found".into())
-        }
-    }
-
-    pub fn rate_idea(&mut self, session_id: &str, idea_id: &str, rating: i32) -> Result<(), Box<dyn std::error::Error>> {
-        let session = self.sessions.get_mut(session_id)
-            .ok_or("Session not found")?;
-        
-        if let Some(idea) = session.ideas.iter_mut().find(|i| i.id == idea_id) {
-            idea.rating = Some(rating.min(5).max(1));
-            session.updated_at = chrono::Utc::now();
-            Ok(())
-        } else {
-            Err("Idea not found".into())
-        }
-    }
-
-    pub fn add_idea_notes(&mut self, session_id: &str, idea_id: &str, notes: String) -> Result<(), Box<dyn std::error::Error>> {
-        let session = self.sessions.get_mut(session_id)
-            .ok_or("Session not found")?;
-        
-        if let Some(idea) = session.ideas.iter_mut().find(|i| i.id == idea_id) {
-            idea.notes = notes;
-            session.updated_at = chrono::Utc::now();
-            Ok(())
-        } else {
-            Err("Idea not found".into())
-        }
-    }
-
-    pub fn get_session(&self, session_id: &str) -> Option<&BrainstormSession> {
-        self.sessions.get(session_id)
-    }
-
-    pub fn list_project_sessions(&self, project_id: &str) -> Vec<&BrainstormSession> {
-        self.sessions
-            .values()
-            .filter(|session| session.project_id == project_id)
-            .collect()
-    }
-
-    pub fn get_keepers(&self, session_id: &str) -> Result<Vec<&BrainstormIdea>, Box<dyn std::error::Error>> {
-        let session = self.sessions.get(session_id)
-            .ok_or("Session not found")?;
-        
-        Ok(session.ideas.iter().filter(|idea| idea.is_keeper).collect())
-    }
-
-    pub fn export_keepers_to_story_bible(&self, session_id: &str) -> Result<String, Box<dyn std::error::Error>> {
-        let keepers = self.get_keepers(session_id)?;
-        let session = self.sessions.get(session_id).ok_or("Session not found")?;
-        
-        let mut export = format!("# {} Ideas - Keepers\n\n", session.category.to_string());
-        
-        for idea in keepers {
-            export.push_str(&format!("## {}\n", idea.content));
-            if !idea.notes.is_empty() {
-                export.push_str(&format!("**Notes:** {}\n", idea.notes));
-            }
-            if !idea.tags.is_empty() {
-                export.push_str(&format!("**Tags:** {}\n", idea.tags.join(", ")));
-            }
-            if let Some(rating) = idea.rating {
-                export.push_str(&format!("**Rating:** {}/5\n", rating));
-            }
-            export.push_str("\n");
-        }
-        
-        Ok(export)
-    }
 }
 
 impl Default for BrainstormEngine {
@@ -0,0 +1,128 @@
+//! Cancellation handles for in-flight AI requests
+//!
+//! A `CancellationToken` is shared between whoever starts an AI request and
+//! whoever may stop it. Racing a request future against the token drops the
+//! future on cancellation, and dropping a provider future or chunk stream
+//! closes its HTTP connection, so a cancelled request stops generating (and
+//! billing) tokens instead of running to completion in the background.
+
+use super::streaming::TextChunkStream;
+use crate::error::{Result, StoryWeaverError};
+use futures_util::StreamExt;
+use std::fmt;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio::sync::Notify;
+
+#[derive(Default)]
+struct TokenState {
+    cancelled: AtomicBool,
+    notify: Notify,
+}
+
+/// A cheaply cloneable cancellation flag; clones share state
+#[derive(Clone, Default)]
+pub struct CancellationToken {
+    state: Arc<TokenState>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.state.cancelled.store(true, Ordering::SeqCst);
+        self.state.notify.notify_waiters();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.state.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Resolves once the token is cancelled
+    pub async fn cancelled(&self) {
+        loop {
+            let notified = self.state.notify.notified();
+            tokio::pin!(notified);
+            // Register before checking the flag so a concurrent cancel isn't missed
+            notified.as_mut().enable();
+            if self.is_cancelled() {
+                return;
+            }
+            notified.await;
+        }
+    }
+
+    /// Run `future` unless the token is cancelled first, in which case the
+    /// future is dropped and an `AIRequestCancelled` error is returned
+    pub async fn run<T, F>(&self, operation: &str, future: F) -> Result<T>
+    where
+        F: Future<Output = Result<T>>,
+    {
+        if self.is_cancelled() {
+            return Err(StoryWeaverError::ai_cancelled(operation));
+        }
+        tokio::select! {
+            biased;
+            _ = self.cancelled() => Err(StoryWeaverError::ai_cancelled(operation)),
+            result = future => result,
+        }
+    }
+
+    /// End `chunks` as soon as the token is cancelled, dropping the provider
+    /// stream. Deltas received before cancellation are still yielded.
+    pub fn guard_stream(&self, chunks: TextChunkStream) -> TextChunkStream {
+        let token = self.clone();
+        chunks.take_until(async move { token.cancelled().await }).boxed()
+    }
+}
+
+impl fmt::Debug for CancellationToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CancellationToken")
+            .field("cancelled", &self.is_cancelled())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::stream;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_run_drops_future_on_cancel() {
+        let token = CancellationToken::new();
+        let canceller = token.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            canceller.cancel();
+        });
+
+        let result: Result<()> = token
+            .run("test", async {
+                tokio::time::sleep(Duration::from_secs(30)).await;
+                Ok(())
+            })
+            .await;
+
+        assert!(matches!(result, Err(StoryWeaverError::AIRequestCancelled { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_guard_stream_keeps_deltas_before_cancel() {
+        let token = CancellationToken::new();
+        let canceller = token.clone();
+        let chunks = stream::iter(vec![Ok("first ".to_string())])
+            .chain(stream::pending())
+            .boxed();
+
+        let mut guarded = token.guard_stream(chunks);
+        assert_eq!(guarded.next().await.unwrap().unwrap(), "first ");
+        canceller.cancel();
+        assert!(guarded.next().await.is_none());
+    }
+}
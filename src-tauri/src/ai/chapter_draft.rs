@@ -0,0 +1,238 @@
+//! Whole-chapter drafting from an outline's scenes
+//!
+//! Scenes are drafted in order, each with its own POV, tense and extra
+//! instructions, and a summary of the previous scene fed forward. Every scene
+//! is checkpointed as soon as it is written, so a draft that fails resumes at
+//! the next scene. The finished chapter is saved as a new document version.
+
+use super::prompt_templates::{render_prompt, PromptScope, RenderedPrompt};
+use super::{AIContext, AIProvider, CancellationToken, WritingFeature};
+use crate::database::models::{AIGenerationType, Document, DocumentType, DocumentVersion, Outline, Scene};
+use crate::database::operations::{
+    AIHistoryOps, ChapterDraft, ChapterDraftOps, ChapterDraftStatus, CharacterOps, DocumentOps, DocumentVersionOps, DraftedScene,
+    OutlineOps, SceneOps, StoryBibleOps,
+};
+use crate::database::DbPool;
+use crate::error::{Result, StoryWeaverError};
+use chrono::Utc;
+use uuid::Uuid;
+
+/// Placed between scenes in the assembled chapter
+pub const SCENE_BREAK: &str = "\n\n* * *\n\n";
+
+/// Recorded as `created_by` on the versions chapter drafts create
+const VERSION_AUTHOR: &str = "chapter_draft";
+
+/// Create a draft of the outline's chapter, to be written by `run_chapter_draft`
+pub async fn create_chapter_draft(pool: &DbPool, outline_id: &str, document_id: Option<String>) -> Result<ChapterDraft> {
+    let outline = OutlineOps::get_by_id(pool, outline_id).await?;
+    let scene_count = SceneOps::get_scene_count(pool, outline_id).await?;
+    if scene_count == 0 {
+        return Err(StoryWeaverError::validation("The chapter's outline has no scenes to draft"));
+    }
+    if let Some(document_id) = &document_id {
+        let document = DocumentOps::get_by_id(pool, document_id)
+            .await?
+            .ok_or_else(|| StoryWeaverError::DocumentNotFound { id: document_id.clone() })?;
+        if document.project_id != outline.project_id {
+            return Err(StoryWeaverError::validation("The document belongs to another project"));
+        }
+    }
+
+    let mut draft = ChapterDraft::new(&outline.project_id, outline_id, document_id);
+    draft.scene_count = scene_count;
+    draft.credit_estimate = Some(SceneOps::get_total_credit_estimate(pool, outline_id).await?);
+    ChapterDraftOps::create(pool, &draft).await?;
+    Ok(draft)
+}
+
+/// Draft the chapter's remaining scenes and save the chapter as a document
+/// version. `on_progress` is called with the scenes done and the total after
+/// each checkpoint. A completed draft is returned as it is.
+pub async fn run_chapter_draft(
+    pool: &DbPool,
+    provider: &dyn AIProvider,
+    draft_id: &str,
+    cancellation: Option<CancellationToken>,
+    on_progress: &mut (dyn FnMut(usize, usize) + Send),
+) -> Result<ChapterDraft> {
+    let draft = ChapterDraftOps::get(pool, draft_id).await?;
+    if draft.status == ChapterDraftStatus::Completed {
+        return Ok(draft);
+    }
+
+    match draft_scenes(pool, provider, &draft, cancellation, on_progress).await {
+        Ok(()) => ChapterDraftOps::get(pool, draft_id).await,
+        Err(error) => {
+            // Cancellation leaves the draft to be resumed like a failure
+            ChapterDraftOps::mark_failed(pool, draft_id, &error.to_string()).await?;
+            Err(error)
+        }
+    }
+}
+
+async fn draft_scenes(
+    pool: &DbPool,
+    provider: &dyn AIProvider,
+    draft: &ChapterDraft,
+    cancellation: Option<CancellationToken>,
+    on_progress: &mut (dyn FnMut(usize, usize) + Send),
+) -> Result<()> {
+    let outline = OutlineOps::get_by_id(pool, &draft.outline_id).await?;
+    let scenes = SceneOps::get_by_outline(pool, &draft.outline_id).await?;
+    if scenes.is_empty() {
+        return Err(StoryWeaverError::validation("The chapter's outline has no scenes to draft"));
+    }
+    let credit_estimate = SceneOps::get_total_credit_estimate(pool, &draft.outline_id).await?;
+    ChapterDraftOps::mark_running(pool, &draft.id, scenes.len() as i32, Some(credit_estimate)).await?;
+
+    let story_bible = StoryBibleOps::get_by_project(pool, &draft.project_id).await.ok();
+    let characters = CharacterOps::get_by_project(pool, &draft.project_id).await?;
+    let pov_names = |ids: &str| -> Vec<String> {
+        let ids: Vec<String> = serde_json::from_str(ids).unwrap_or_default();
+        characters.iter().filter(|c| ids.contains(&c.id)).map(|c| c.name.clone()).collect()
+    };
+
+    let mut checkpoints = ChapterDraftOps::get_scenes(pool, &draft.id).await?;
+    let mut previous_summary: Option<String> = None;
+    let total = scenes.len();
+    for (index, scene) in scenes.iter().enumerate() {
+        if let Some(done) = checkpoints.iter().find(|done| done.scene_id == scene.id) {
+            previous_summary = Some(done.summary.clone());
+            continue;
+        }
+
+        let mut context = AIContext::default();
+        context.project_id = Some(draft.project_id.clone());
+        context.document_id = draft.document_id.clone();
+        context.feature_type = Some(WritingFeature::Write);
+        context.story_context = story_bible.as_ref().and_then(|bible| bible.synopsis.clone());
+        context.genre = story_bible.as_ref().and_then(|bible| bible.genre.clone());
+        context.writing_style = story_bible.as_ref().and_then(|bible| bible.style.clone());
+        context.word_count_target = scene.word_count_estimate.map(|words| words.max(0) as usize);
+        context.cancellation = cancellation.clone();
+
+        let pov = scene
+            .pov
+            .clone()
+            .or_else(|| outline.pov.clone())
+            .or_else(|| story_bible.as_ref().and_then(|bible| bible.global_pov.clone()));
+        let tense = scene
+            .tense
+            .clone()
+            .or_else(|| outline.tense.clone())
+            .or_else(|| story_bible.as_ref().and_then(|bible| bible.global_tense.clone()));
+        let mut pov_characters = pov_names(&scene.character_pov_ids);
+        if pov_characters.is_empty() {
+            pov_characters = pov_names(&outline.character_pov_ids);
+        }
+
+        let prompt = scene_prompt(&outline, scene, index, total, previous_summary.as_deref(), pov, tense, pov_characters)?;
+        let content = provider.generate_text(&prompt.text, &context).await?.trim().to_string();
+        if content.is_empty() {
+            return Err(StoryWeaverError::internal(format!("The AI returned no prose for scene {}", scene.scene_number)));
+        }
+        let document_id = draft.document_id.as_deref();
+        AIHistoryOps::record_rendered(pool, &draft.project_id, document_id, AIGenerationType::AutoWrite, provider, prompt, &content).await;
+
+        let recap_prompt = render_prompt("scene_recap", &PromptScope::for_project(&draft.project_id), &serde_json::json!({ "scene": content }))?;
+        let summary = provider.generate_text(&recap_prompt.text, &context).await?.trim().to_string();
+        AIHistoryOps::record_rendered(pool, &draft.project_id, document_id, AIGenerationType::Summary, provider, recap_prompt, &summary).await;
+
+        let drafted = DraftedScene {
+            draft_id: draft.id.clone(),
+            scene_id: scene.id.clone(),
+            position: index as i32,
+            word_count: content.split_whitespace().count() as i32,
+            content,
+            summary: summary.clone(),
+            created_at: Utc::now(),
+        };
+        ChapterDraftOps::save_scene(pool, &drafted).await?;
+        checkpoints.push(drafted);
+        previous_summary = Some(summary);
+
+        let done = scenes.iter().filter(|scene| checkpoints.iter().any(|c| c.scene_id == scene.id)).count();
+        on_progress(done, total);
+    }
+
+    // Assemble in the outline's current scene order
+    let chapter = scenes
+        .iter()
+        .filter_map(|scene| checkpoints.iter().find(|done| done.scene_id == scene.id))
+        .map(|done| done.content.as_str())
+        .collect::<Vec<_>>()
+        .join(SCENE_BREAK);
+    let (document_id, version_id) = save_chapter(pool, draft, &outline, chapter).await?;
+    ChapterDraftOps::mark_completed(pool, &draft.id, &document_id, &version_id).await
+}
+
+#[allow(clippy::too_many_arguments)]
+fn scene_prompt(
+    outline: &Outline,
+    scene: &Scene,
+    index: usize,
+    total: usize,
+    previous_summary: Option<&str>,
+    pov: Option<String>,
+    tense: Option<String>,
+    pov_characters: Vec<String>,
+) -> Result<RenderedPrompt> {
+    let values = serde_json::json!({
+        "chapter_title": outline.title,
+        "chapter_summary": outline.summary,
+        "scene_number": index + 1,
+        "scene_count": total,
+        "scene_title": scene.title,
+        "scene_summary": scene.summary,
+        "previous_summary": previous_summary,
+        "pov": pov,
+        "pov_characters": pov_characters,
+        "tense": tense,
+        "word_target": scene.word_count_estimate,
+        "extra_instructions": scene.extra_instructions,
+    });
+    render_prompt("chapter_scene", &PromptScope::for_project(&outline.project_id), &values)
+}
+
+/// Save the chapter as a new version of the draft's document, leaving the
+/// document's current text alone, or as a new chapter document
+async fn save_chapter(pool: &DbPool, draft: &ChapterDraft, outline: &Outline, chapter: String) -> Result<(String, String)> {
+    let existing = match &draft.document_id {
+        Some(id) => DocumentOps::get_by_id(pool, id).await?,
+        None => None,
+    };
+    let document = match existing {
+        Some(document) => document,
+        None => {
+            let title = outline
+                .title
+                .clone()
+                .filter(|title| !title.trim().is_empty())
+                .or_else(|| outline.chapter_number.map(|number| format!("Chapter {}", number)))
+                .unwrap_or_else(|| "Chapter draft".to_string());
+            let mut document = Document::new(draft.project_id.clone(), title, DocumentType::Chapter);
+            document.content = chapter.clone();
+            document.order_index = outline.chapter_number.unwrap_or(0);
+            DocumentOps::create(pool, document).await?
+        }
+    };
+
+    let comment = match outline.chapter_number {
+        Some(number) => format!("Draft of chapter {} from its outline", number),
+        None => "Chapter draft from its outline".to_string(),
+    };
+    let version = DocumentVersion {
+        id: Uuid::new_v4().to_string(),
+        document_id: document.id.clone(),
+        word_count: chapter.split_whitespace().count() as i32,
+        content: chapter,
+        version_number: DocumentVersionOps::get_next_version_number(pool, &document.id).await?,
+        created_at: Utc::now(),
+        created_by: Some(VERSION_AUTHOR.to_string()),
+        comment: Some(comment),
+    };
+    let version = DocumentVersionOps::create(pool, version).await?;
+
+    Ok((document.id, version.id))
+}
@@ -0,0 +1,238 @@
+//! Persistent Quick Chat threads
+//!
+//! Each turn sends the thread's pinned story bible references, a rolling
+//! summary of older messages and the recent messages verbatim. When the
+//! history outgrows its share of the provider's `get_context_window`, the
+//! oldest messages are folded into the summary, keeping the last few intact.
+
+use super::{tokenizer, AIContext, AIProvider, WritingFeature};
+use crate::database::models::{Document, DocumentType};
+use crate::database::operations::{
+    ChatRole, ChatThread, ChatThreadMessage, ChatThreadOps, CharacterOps, DocumentOps, LocationOps, PinnedElementType,
+    PlotThreadOps, WorldElementOps,
+};
+use crate::database::DbPool;
+use crate::error::Result;
+use serde::{Deserialize, Serialize};
+
+/// Share of the context window a chat turn may fill with history
+const HISTORY_WINDOW_FRACTION: f64 = 0.5;
+/// Most recent messages that are always sent verbatim
+const MIN_RECENT_MESSAGES: usize = 4;
+
+/// A completed chat turn
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatTurn {
+    pub user_message: ChatThreadMessage,
+    pub reply: ChatThreadMessage,
+    /// Whether older messages were folded into the summary this turn
+    pub summarized: bool,
+}
+
+/// Send `content` to a thread and store both it and the reply. Nothing is
+/// stored if the provider fails, so the turn can simply be retried.
+pub async fn send_message(pool: &DbPool, provider: &dyn AIProvider, thread_id: &str, content: &str) -> Result<ChatTurn> {
+    let mut thread = ChatThreadOps::get_by_id(pool, thread_id).await?;
+    let model = provider.get_model_name().to_string();
+    let messages = ChatThreadOps::get_messages(pool, thread_id).await?;
+    let context = chat_context(&thread);
+
+    let pins = render_pins(pool, &thread).await?;
+    let pending = (ChatRole::User, content.to_string(), tokenizer::count_tokens(&model, content) as i64);
+    let mut history: Vec<(ChatRole, String, i64)> = messages
+        .iter()
+        .map(|m| (m.role, m.content.clone(), m.token_count))
+        .collect();
+    history.push(pending);
+
+    let budget = (provider.get_context_window() as f64 * HISTORY_WINDOW_FRACTION) as i64;
+    let summarized = fold_history(pool, provider, &mut thread, &history, &pins, budget, &context).await?;
+
+    let recent = &history[(thread.summarized_count.max(0) as usize).min(history.len() - 1)..];
+    let prompt = chat_prompt(&pins, &thread.summary, recent);
+    let reply = provider.quick_chat(&prompt, &context).await?;
+
+    let user_message = ChatThreadOps::add_message(pool, thread_id, ChatRole::User, content, history.last().map_or(0, |m| m.2)).await?;
+    let reply_tokens = tokenizer::count_tokens(&model, &reply) as i64;
+    let reply = ChatThreadOps::add_message(pool, thread_id, ChatRole::Assistant, &reply, reply_tokens).await?;
+
+    Ok(ChatTurn { user_message, reply, summarized })
+}
+
+/// Fold the oldest unsummarized messages into the thread's summary until the
+/// rest fits `budget`, never touching the last `MIN_RECENT_MESSAGES`
+async fn fold_history(
+    pool: &DbPool,
+    provider: &dyn AIProvider,
+    thread: &mut ChatThread,
+    history: &[(ChatRole, String, i64)],
+    pins: &str,
+    budget: i64,
+    context: &AIContext,
+) -> Result<bool> {
+    let model = provider.get_model_name();
+    let start = (thread.summarized_count.max(0) as usize).min(history.len());
+    let fixed = tokenizer::count_tokens(model, pins) as i64 + tokenizer::count_tokens(model, &thread.summary) as i64;
+    let mut total: i64 = fixed + history[start..].iter().map(|m| m.2).sum::<i64>();
+
+    let foldable = history.len().saturating_sub(MIN_RECENT_MESSAGES);
+    let mut end = start;
+    while total > budget && end < foldable {
+        total -= history[end].2;
+        end += 1;
+    }
+    if end == start {
+        return Ok(false);
+    }
+
+    let prompt = summary_prompt(&thread.summary, &history[start..end]);
+    let summary = provider.generate_text(&prompt, context).await?;
+    thread.summary = summary.trim().to_string();
+    thread.summarized_count = end as i64;
+    ChatThreadOps::set_summary(pool, &thread.id, &thread.summary, thread.summarized_count).await?;
+    Ok(true)
+}
+
+fn chat_context(thread: &ChatThread) -> AIContext {
+    AIContext {
+        project_id: Some(thread.project_id.clone()),
+        document_id: thread.document_id.clone(),
+        feature_type: Some(WritingFeature::QuickChat),
+        ..AIContext::default()
+    }
+}
+
+fn speaker(role: ChatRole) -> &'static str {
+    match role {
+        ChatRole::User => "User",
+        ChatRole::Assistant => "Assistant",
+    }
+}
+
+fn summary_prompt(summary: &str, messages: &[(ChatRole, String, i64)]) -> String {
+    let mut prompt = String::from(
+        "Update the running summary of this conversation about the user's story. Keep every decision, \
+         fact about the story and open question; drop pleasantries. Reply with the summary only.\n\n",
+    );
+    if !summary.is_empty() {
+        prompt.push_str(&format!("Summary so far:\n{}\n\n", summary));
+    }
+    prompt.push_str("New messages:\n");
+    for (role, content, _) in messages {
+        prompt.push_str(&format!("{}: {}\n", speaker(*role), content));
+    }
+    prompt
+}
+
+/// The message sent to `quick_chat`: pins, summary, then the recent messages
+/// ending with the user's new one
+fn chat_prompt(pins: &str, summary: &str, recent: &[(ChatRole, String, i64)]) -> String {
+    let mut prompt = String::new();
+    if !pins.is_empty() {
+        prompt.push_str(&format!("Pinned story bible references:\n{}\n", pins));
+    }
+    if !summary.is_empty() {
+        prompt.push_str(&format!("Summary of earlier conversation:\n{}\n\n", summary));
+    }
+    if let Some(((_, latest, _), earlier)) = recent.split_last() {
+        if !earlier.is_empty() {
+            prompt.push_str("Recent messages:\n");
+            for (role, content, _) in earlier {
+                prompt.push_str(&format!("{}: {}\n", speaker(*role), content));
+            }
+            prompt.push('\n');
+        }
+        if prompt.is_empty() {
+            return latest.clone();
+        }
+        prompt.push_str(&format!("User: {}", latest));
+    }
+    prompt
+}
+
+/// One line per pinned element that still exists
+async fn render_pins(pool: &DbPool, thread: &ChatThread) -> Result<String> {
+    if thread.pinned_references.is_empty() {
+        return Ok(String::new());
+    }
+    let pinned = |kind: PinnedElementType, id: &str| {
+        thread
+            .pinned_references
+            .iter()
+            .any(|pin| pin.element_type == kind && pin.element_id == id)
+    };
+    let detail = |text: &Option<String>| text.as_deref().filter(|t| !t.is_empty()).map(|t| format!(": {}", t)).unwrap_or_default();
+
+    let mut lines = Vec::new();
+    for c in CharacterOps::get_by_project(pool, &thread.project_id).await? {
+        if pinned(PinnedElementType::Character, &c.id) {
+            let about = c.description.clone().or_else(|| c.personality.clone());
+            lines.push(format!("- {} (character){}", c.name, detail(&about)));
+        }
+    }
+    for l in LocationOps::get_by_project(pool, &thread.project_id).await? {
+        if pinned(PinnedElementType::Location, &l.id) {
+            lines.push(format!("- {} (location){}", l.name, detail(&l.description)));
+        }
+    }
+    for w in WorldElementOps::get_by_project(pool, &thread.project_id).await? {
+        if pinned(PinnedElementType::WorldElement, &w.id) {
+            lines.push(format!("- {} ({}){}", w.name, w.element_type, detail(&w.description)));
+        }
+    }
+    for p in PlotThreadOps::get_by_project(pool, &thread.project_id).await? {
+        if pinned(PinnedElementType::PlotThread, &p.id) {
+            lines.push(format!("- {} (plot thread){}", p.name, detail(&p.description)));
+        }
+    }
+    Ok(lines.join("\n"))
+}
+
+/// Save a thread's full transcript as a notes document in its project
+pub async fn export_to_document(pool: &DbPool, thread_id: &str, title: Option<&str>) -> Result<Document> {
+    let thread = ChatThreadOps::get_by_id(pool, thread_id).await?;
+    let messages = ChatThreadOps::get_messages(pool, thread_id).await?;
+    let title = title.map(|t| t.to_string()).unwrap_or_else(|| thread.title.clone());
+
+    let mut content = format!("# {}\n", title);
+    for message in &messages {
+        let who = match message.role {
+            ChatRole::User => "You",
+            ChatRole::Assistant => "Assistant",
+        };
+        content.push_str(&format!("\n**{}:** {}\n", who, message.content));
+    }
+
+    let mut document = Document::new(thread.project_id.clone(), title, DocumentType::Notes);
+    document.content = content;
+    DocumentOps::create(pool, document).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn turn(role: ChatRole, content: &str) -> (ChatRole, String, i64) {
+        (role, content.to_string(), 1)
+    }
+
+    #[test]
+    fn test_first_message_is_sent_as_is() {
+        assert_eq!(chat_prompt("", "", &[turn(ChatRole::User, "Who is Mara?")]), "Who is Mara?");
+    }
+
+    #[test]
+    fn test_prompt_orders_pins_summary_and_history() {
+        let recent = [
+            turn(ChatRole::User, "Name the ship"),
+            turn(ChatRole::Assistant, "The Gull"),
+            turn(ChatRole::User, "Why that name?"),
+        ];
+        let prompt = chat_prompt("- Mara (character)", "They chose a coastal setting.", &recent);
+        let pins = prompt.find("Pinned").unwrap();
+        let summary = prompt.find("Summary of earlier").unwrap();
+        let history = prompt.find("Assistant: The Gull").unwrap();
+        assert!(pins < summary && summary < history);
+        assert!(prompt.ends_with("User: Why that name?"));
+    }
+}
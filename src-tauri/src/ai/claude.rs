@@ -1,13 +1,16 @@
 //! Claude (Anthropic) Provider implementation for StoryWeaver
 
-use super::{AIProvider, AIContext, TextStream, RewriteStyle};
+use super::{AIProvider, AIContext, TextChunkStream, RewriteStyle};
+use super::resilience::{ProviderHealth, Resilience, ResiliencePolicy, ResilientSend};
+use super::streaming::sse_text_stream;
+use super::prompt_templates::{render_prompt, rewrite_values, system_values, PromptScope};
+use super::tools::{ToolCall, ToolDefinition, ToolMessage, ToolTurn};
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
+use serde_json::json;
 use crate::error::{Result, StoryWeaverError};
 use std::sync::Arc;
 use std::time::Duration;
-use tokio::sync::Mutex;
-use tokio::time::sleep;
 
 // Rate limiting constants
 const REQUESTS_PER_MINUTE: u32 = 50; // Anthropic's rate limits may differ
@@ -33,6 +36,8 @@ struct ClaudeCompletionRequest {
     max_tokens: u32,
     temperature: f32,
     system: Option<String>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    stream: bool,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -53,98 +58,151 @@ pub struct ClaudeProvider {
     pub api_key: String,
     pub model: String,
     pub client: reqwest::Client,
-    pub rate_limiter: Arc<Mutex<RateLimiter>>,
+    pub resilience: Arc<Resilience>,
 }
 
-pub struct RateLimiter {
-    request_count: u32,
-    token_count: u32,
-    last_reset: std::time::Instant,
-}
+impl ClaudeProvider {
+    /// Token count for rate limiting, using this model's tokenizer
+    fn count_tokens(&self, text: &str) -> u32 {
+        super::tokenizer::count_tokens(&self.model, text) as u32
+    }
 
-impl RateLimiter {
-    fn new() -> Self {
+    pub fn new(api_key: String, model: String) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(60))
+            .build()
+            .unwrap_or_default();
+            
         Self {
-            request_count: 0,
-            token_count: 0,
-            last_reset: std::time::Instant::now(),
+            api_key,
+            model,
+            client,
+            resilience: Arc::new(Resilience::new(
+                "claude",
+                ResiliencePolicy::with_rate_limits(REQUESTS_PER_MINUTE, TOKENS_PER_MINUTE),
+            )),
         }
     }
 
-    async fn wait_if_needed(&mut self, estimated_tokens: u32) -> Result<()> {
-        // Reset counters if a minute has passed
-        let now = std::time::Instant::now();
-        if now.duration_since(self.last_reset).as_secs() >= 60 {
-            self.request_count = 0;
-            self.token_count = 0;
-            self.last_reset = now;
+    /// Override the retry, pacing and circuit breaker settings
+    pub fn with_resilience_policy(mut self, policy: ResiliencePolicy) -> Self {
+        self.resilience = Arc::new(Resilience::new("claude", policy));
+        self
+    }
+
+    /// POST a Messages API body and return the raw response text
+    async fn post_messages<T: Serialize + ?Sized>(&self, body: &T, estimated_tokens: u32) -> Result<String> {
+        let response = self.client.post("https://api.anthropic.com/v1/messages")
+            .header("x-api-key", &self.api_key)
+            .header("anthropic-version", "2023-06-01")
+            .header("Content-Type", "application/json")
+            .json(body)
+            .send_with(&self.resilience, estimated_tokens)
+            .await?;
+        
+        // Check for errors first
+        let status_code = response.status().as_u16();
+        let is_success = response.status().is_success();
+        
+        // Get response text
+        let response_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
+        
+        if !is_success {
+            return Err(StoryWeaverError::AIRequest {
+                provider: "claude".to_string(),
+                status_code,
+                message: format!("Claude API error: {}", response_text),
+            });
         }
 
-        // Check if we're about to exceed limits
-        if self.request_count >= REQUESTS_PER_MINUTE || 
-           self.token_count + estimated_tokens >= TOKENS_PER_MINUTE {
-            
-            // Calculate time to wait until next minute
-            let elapsed = now.duration_since(self.last_reset).as_millis() as u64;
-            let wait_time = 60000_u64.saturating_sub(elapsed);
-            
-            // Wait until rate limit resets
-            if wait_time > 0 {
-                sleep(Duration::from_millis(wait_time)).await;
-                self.request_count = 0;
-                self.token_count = 0;
-                self.last_reset = std::time::Instant::now();
-            }
+        Ok(response_text)
+    }
+
+    /// Send a streaming Messages API request and yield the text deltas as they arrive
+    async fn send_stream_request(&self, mut request: ClaudeCompletionRequest, estimated_tokens: u32) -> Result<TextChunkStream> {
+        request.stream = true;
+
+        let response = self.client.post("https://api.anthropic.com/v1/messages")
+            .header("x-api-key", &self.api_key)
+            .header("anthropic-version", "2023-06-01")
+            .header("Content-Type", "application/json")
+            .json(&request)
+            .send_with(&self.resilience, estimated_tokens)
+            .await?;
+
+        let status_code = response.status().as_u16();
+        if !response.status().is_success() {
+            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
+            return Err(StoryWeaverError::AIRequest {
+                provider: "claude".to_string(),
+                status_code,
+                message: format!("Claude API error: {}", error_text),
+            });
         }
 
-        // Update counters
-        self.request_count += 1;
-        self.token_count += estimated_tokens;
-        
-        Ok(())
+        Ok(sse_text_stream(response, "claude", content_block_delta))
     }
 
-    fn update_token_usage(&mut self, usage: &TokenUsage) {
-        self.token_count = self.token_count.saturating_add(usage.input_tokens + usage.output_tokens);
+    fn build_system_message(&self, context: &AIContext) -> Result<String> {
+        Ok(render_prompt("system", &PromptScope::from_context(context), &system_values(context))?.text)
     }
 }
 
-impl ClaudeProvider {
-    pub fn new(api_key: String, model: String) -> Self {
-        let client = reqwest::Client::builder()
-            .timeout(Duration::from_secs(60))
-            .build()
-            .unwrap_or_default();
-            
-        Self {
-            api_key,
-            model,
-            client,
-            rate_limiter: Arc::new(Mutex::new(RateLimiter::new())),
-        }
+/// Text of a `content_block_delta` event; other event types carry no text
+fn content_block_delta(event: &serde_json::Value) -> Option<String> {
+    if event.get("type").and_then(|t| t.as_str()) != Some("content_block_delta") {
+        return None;
     }
+    event
+        .get("delta")
+        .and_then(|delta| delta.get("text"))
+        .and_then(|text| text.as_str())
+        .map(|text| text.to_string())
+}
 
-    fn build_system_message(&self, _context: &AIContext) -> String {
-        // Build a system message based on the context
-        // This could include information about the document, user preferences, etc.
-        "You are StoryWeaver, an AI writing assistant. Help the user write their story.".to_string()
+/// Messages API turns for a tool conversation. Tool results go back as
+/// `tool_result` blocks in a user turn, and consecutive user blocks share one
+/// turn because the API expects roles to alternate.
+fn claude_tool_messages(messages: &[ToolMessage]) -> Vec<serde_json::Value> {
+    let mut turns: Vec<serde_json::Value> = Vec::new();
+    for message in messages {
+        let (role, blocks) = match message {
+            ToolMessage::User { content } => ("user", vec![json!({ "type": "text", "text": content })]),
+            ToolMessage::Assistant { content, tool_calls } => {
+                let mut blocks = Vec::new();
+                if let Some(text) = content.as_ref().filter(|text| !text.is_empty()) {
+                    blocks.push(json!({ "type": "text", "text": text }));
+                }
+                for call in tool_calls {
+                    blocks.push(json!({ "type": "tool_use", "id": call.id, "name": call.name, "input": call.arguments }));
+                }
+                ("assistant", blocks)
+            }
+            ToolMessage::Tool { call_id, content, .. } => {
+                ("user", vec![json!({ "type": "tool_result", "tool_use_id": call_id, "content": content })])
+            }
+        };
+
+        match turns.last_mut() {
+            Some(last) if last["role"] == role => {
+                if let Some(existing) = last["content"].as_array_mut() {
+                    existing.extend(blocks);
+                }
+            }
+            _ => turns.push(json!({ "role": role, "content": blocks })),
+        }
     }
+    turns
 }
 
 #[async_trait]
 impl AIProvider for ClaudeProvider {
     async fn generate_text(&self, prompt: &str, context: &AIContext) -> Result<String> {
         // Estimate token usage for rate limiting
-        let estimated_tokens = (prompt.len() / 4) as u32 + 500; // Rough estimate
-        
-        // Wait if we need to respect rate limits
-        {
-            let mut rate_limiter = self.rate_limiter.lock().await;
-            rate_limiter.wait_if_needed(estimated_tokens).await?;
-        }
+        let estimated_tokens = self.count_tokens(prompt) + 500;
         
         // Build request
-        let system = self.build_system_message(context);
+        let system = self.build_system_message(context)?;
         let user_message = ClaudeMessage {
             role: "user".to_string(),
             content: vec![ClaudeContent {
@@ -159,36 +217,11 @@ impl AIProvider for ClaudeProvider {
             max_tokens: 1000,
             temperature: 0.7,
             system: Some(system),
+            stream: false,
         };
         
         // Make API call
-        let response = self.client.post("https://api.anthropic.com/v1/messages")
-            .header("x-api-key", &self.api_key)
-            .header("anthropic-version", "2023-06-01")
-            .header("Content-Type", "application/json")
-            .json(&request)
-            .send()
-            .await
-            .map_err(|e| StoryWeaverError::AIRequest {
-                provider: "claude".to_string(),
-                status_code: 0,
-                message: format!("Failed to send request to Claude API: {}", e),
-            })?;
-        
-        // Check for errors first
-        let status_code = response.status().as_u16();
-        let is_success = response.status().is_success();
-        
-        // Get response text
-        let response_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-        
-        if !is_success {
-            return Err(StoryWeaverError::AIRequest {
-                provider: "claude".to_string(),
-                status_code,
-                message: format!("Claude API error: {}", response_text),
-            });
-        }
+        let response_text = self.post_messages(&request, estimated_tokens).await?;
         
         // Parse response
         let completion: ClaudeCompletionResponse = serde_json::from_str(&response_text)
@@ -197,10 +230,9 @@ impl AIProvider for ClaudeProvider {
                 message: format!("Failed to parse Claude API response: {}", e),
             })?;
         
-        // Update rate limiter with actual token usage
+        // Record actual token usage for pacing
         if let Some(usage) = &completion.usage {
-            let mut rate_limiter = self.rate_limiter.lock().await;
-            rate_limiter.update_token_usage(usage);
+            self.resilience.record_usage(estimated_tokens, usage.input_tokens, usage.output_tokens).await;
         }
         
         // Extract generated text
@@ -221,18 +253,12 @@ impl AIProvider for ClaudeProvider {
         }
     }
 
-    async fn generate_text_stream(&self, prompt: &str, context: &AIContext) -> Result<TextStream> {
+    async fn generate_text_stream(&self, prompt: &str, context: &AIContext) -> Result<TextChunkStream> {
         // Estimate token usage for rate limiting
-        let estimated_tokens = (prompt.len() / 4) as u32 + 500; // Rough estimate
-        
-        // Wait if we need to respect rate limits
-        {
-            let mut rate_limiter = self.rate_limiter.lock().await;
-            rate_limiter.wait_if_needed(estimated_tokens).await?;
-        }
+        let estimated_tokens = self.count_tokens(prompt) + 500;
         
         // Build request
-        let system = self.build_system_message(context);
+        let system = self.build_system_message(context)?;
         let user_message = ClaudeMessage {
             role: "user".to_string(),
             content: vec![ClaudeContent {
@@ -247,105 +273,16 @@ impl AIProvider for ClaudeProvider {
             max_tokens: 1000,
             temperature: 0.7,
             system: Some(system),
+            stream: false,
         };
         
-        // Make API call with streaming
-        let response = self.client.post("https://api.anthropic.com/v1/messages")
-            .header("x-api-key", &self.api_key)
-            .header("anthropic-version", "2023-06-01")
-            .header("Content-Type", "application/json")
-            .header("anthropic-beta", "messages-2023-12-15-streaming") // Enable streaming
-            .query(&[("stream", "true")]) // Add stream parameter
-            .json(&request)
-            .send()
-            .await
-            .map_err(|e| StoryWeaverError::AIRequest {
-                provider: "claude".to_string(),
-                status_code: 0,
-                message: format!("Failed to send request to Claude API: {}", e),
-            })?;
-        
-        // Check for errors first
-        let status_code = response.status().as_u16();
-        let is_success = response.status().is_success();
-        
-        if !is_success {
-            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(StoryWeaverError::AIRequest {
-                provider: "claude".to_string(),
-                status_code,
-                message: format!("Claude API error: {}", error_text),
-            });
-        }
-        
-        // Create a new TextStream
-        let mut text_stream = TextStream::new();
-        
-        // Process the streaming response
-        let mut stream = response.bytes_stream();
-        use futures_util::StreamExt;
-        
-        while let Some(chunk_result) = stream.next().await {
-            let chunk = chunk_result.map_err(|e| StoryWeaverError::AIProvider {
-                provider: "claude".to_string(),
-                message: format!("Error reading stream chunk: {}", e),
-            })?;
-            let chunk_str = String::from_utf8_lossy(&chunk);
-            
-            // Claude sends "data: " prefixed SSE events
-            for line in chunk_str.lines() {
-                if line.starts_with("data: ") {
-                    let data = &line[6..]; // Skip "data: " prefix
-                    
-                    // Skip empty lines and [DONE] marker
-                    if data.is_empty() || data == "[DONE]" {
-                        continue;
-                    }
-                    
-                    // Parse the JSON data
-                    match serde_json::from_str::<serde_json::Value>(data) {
-                        Ok(json) => {
-                            // Extract the content from the JSON
-                            if let Some(delta) = json.get("delta") {
-                                if let Some(text) = delta.get("text").and_then(|t| t.as_str()) {
-                                    text_stream.append(text);
-                                }
-                            }
-                        },
-                        Err(e) => {
-                            eprintln!("Error parsing JSON from stream: {}", e);
-                            continue;
-                        }
-                    }
-                }
-            }
-        }
-        
-        // Mark the stream as complete
-        text_stream.complete();
-        
-        Ok(text_stream)
+        self.send_stream_request(request, estimated_tokens).await
     }
 
     async fn rewrite_text(&self, text: &str, style: &RewriteStyle) -> Result<String> {
-        let estimated_tokens = (text.len() / 4) as u32 + 500;
-        {
-            let mut rate_limiter = self.rate_limiter.lock().await;
-            rate_limiter.wait_if_needed(estimated_tokens).await?;
-        }
+        let estimated_tokens = self.count_tokens(text) + 500;
 
-        let style_instruction = match style {
-            RewriteStyle::Rephrase => "Rephrase this text while keeping the same meaning:",
-            RewriteStyle::Shorter => "Rewrite this text to be more concise:",
-            RewriteStyle::MoreDescriptive => "Rewrite this text to be more descriptive and vivid:",
-            RewriteStyle::Longer => "Expand this text with more details and elaboration:",
-            RewriteStyle::MoreFormal => "Rewrite this text in a more formal, professional tone:",
-            RewriteStyle::MoreCasual => "Rewrite this text in a more casual, conversational tone:",
-            RewriteStyle::MoreVivid => "Rewrite this text with more vivid imagery and sensory details:",
-            RewriteStyle::MoreDirect => "Rewrite this text to be more direct and straightforward:",
-            RewriteStyle::MorePoetic => "Rewrite this text in a more poetic, lyrical style:",
-            RewriteStyle::ToneShift(tone) => &format!("Rewrite this text in a {} tone:", tone),
-        };
+        let style_instruction = render_prompt("rewrite", &PromptScope::global(), &rewrite_values(style))?.text;
         
         let system = format!("You are a helpful writing assistant. {}", style_instruction);
         
@@ -363,6 +300,7 @@ impl AIProvider for ClaudeProvider {
             max_tokens: (text.len() as u32 / 2), // Limit token usage based on input
             temperature: 0.7,
             system: Some(system),
+            stream: false,
         };
         
         // Make API call
@@ -371,13 +309,8 @@ impl AIProvider for ClaudeProvider {
             .header("anthropic-version", "2023-06-01")
             .header("Content-Type", "application/json")
             .json(&request)
-            .send()
-            .await
-            .map_err(|e| StoryWeaverError::AIRequest {
-                provider: "claude".to_string(),
-                status_code: 0,
-                message: format!("Failed to send request to Claude API: {}", e),
-            })?;
+            .send_with(&self.resilience, estimated_tokens)
+            .await?;
         
         // Check for errors
         let status = response.status();
@@ -399,10 +332,9 @@ impl AIProvider for ClaudeProvider {
                 message: format!("Failed to parse Claude API response: {}", e),
             })?;
         
-        // Update rate limiter with actual token usage
+        // Record actual token usage for pacing
         if let Some(usage) = &completion.usage {
-            let mut rate_limiter = self.rate_limiter.lock().await;
-            rate_limiter.update_token_usage(usage);
+            self.resilience.record_usage(estimated_tokens, usage.input_tokens, usage.output_tokens).await;
         }
         
         // Extract generated text
@@ -424,13 +356,71 @@ impl AIProvider for ClaudeProvider {
     }
 
     async fn generate_embedding(&self, _text: &str) -> Result<Vec<f32>> {
-        // Claude doesn't have a native embedding API, so we'll return an error
+        // Claude doesn't have a native embedding API; AIProviderManager::embed
+        // falls back to local embeddings
         Err(StoryWeaverError::AIProvider {
             provider: "claude".to_string(),
             message: "Claude does not support embeddings".to_string(),
         })
     }
 
+    async fn generate_with_tools(&self, messages: &[ToolMessage], tools: &[ToolDefinition], context: &AIContext) -> Result<ToolTurn> {
+        let estimated_tokens = messages
+            .iter()
+            .map(|message| match message {
+                ToolMessage::User { content } | ToolMessage::Tool { content, .. } => self.count_tokens(content),
+                ToolMessage::Assistant { .. } => 100,
+            })
+            .sum::<u32>()
+            + 1000;
+
+        let mut body = json!({
+            "model": self.model,
+            "max_tokens": 2000,
+            "temperature": 0.7,
+            "system": self.build_system_message(context)?,
+            "messages": claude_tool_messages(messages),
+        });
+        if !tools.is_empty() {
+            body["tools"] = tools
+                .iter()
+                .map(|tool| json!({ "name": tool.name, "description": tool.description, "input_schema": tool.parameters }))
+                .collect();
+        }
+
+        let response_text = self.post_messages(&body, estimated_tokens).await?;
+        let completion: serde_json::Value = serde_json::from_str(&response_text)
+            .map_err(|e| StoryWeaverError::AIProvider {
+                provider: "claude".to_string(),
+                message: format!("Failed to parse Claude API response: {}", e),
+            })?;
+
+        if let Some(usage) = completion.get("usage") {
+            let tokens = |key: &str| usage.get(key).and_then(|t| t.as_u64()).unwrap_or(0) as u32;
+            self.resilience.record_usage(estimated_tokens, tokens("input_tokens"), tokens("output_tokens")).await;
+        }
+
+        let mut text = String::new();
+        let mut calls = Vec::new();
+        for block in completion.get("content").and_then(|c| c.as_array()).into_iter().flatten() {
+            match block.get("type").and_then(|t| t.as_str()) {
+                Some("text") => text.push_str(block.get("text").and_then(|t| t.as_str()).unwrap_or_default()),
+                Some("tool_use") => calls.push(ToolCall {
+                    id: block.get("id").and_then(|id| id.as_str()).unwrap_or_default().to_string(),
+                    name: block.get("name").and_then(|n| n.as_str()).unwrap_or_default().to_string(),
+                    arguments: block.get("input").cloned().unwrap_or_else(|| json!({})),
+                }),
+                _ => {}
+            }
+        }
+
+        if calls.is_empty() {
+            Ok(ToolTurn::Answer(text))
+        } else {
+            Ok(ToolTurn::Calls { content: Some(text).filter(|t| !t.is_empty()), calls })
+        }
+    }
+
     fn supports_streaming(&self) -> bool {
         true
     }
@@ -452,29 +442,18 @@ impl AIProvider for ClaudeProvider {
         "Claude"
     }
 
+    fn health(&self) -> ProviderHealth {
+        self.resilience.health()
+    }
+
     fn supports_image_generation(&self) -> bool {
         false
     }
 
-    async fn rewrite_text_stream(&self, text: &str, style: &RewriteStyle) -> Result<TextStream> {
-        let estimated_tokens = (text.len() / 4) as u32 + 500;
-        {
-            let mut rate_limiter = self.rate_limiter.lock().await;
-            rate_limiter.wait_if_needed(estimated_tokens).await?;
-        }
+    async fn rewrite_text_stream(&self, text: &str, style: &RewriteStyle) -> Result<TextChunkStream> {
+        let estimated_tokens = self.count_tokens(text) + 500;
 
-        let style_instruction = match style {
-            RewriteStyle::Rephrase => "Rephrase this text while keeping the same meaning:",
-            RewriteStyle::Shorter => "Rewrite this text to be more concise:",
-            RewriteStyle::MoreDescriptive => "Rewrite this text to be more descriptive and vivid:",
-            RewriteStyle::Longer => "Expand this text with more details and elaboration:",
-            RewriteStyle::MoreFormal => "Rewrite this text in a more formal, professional tone:",
-            RewriteStyle::MoreCasual => "Rewrite this text in a more casual, conversational tone:",
-            RewriteStyle::MoreVivid => "Rewrite this text with more vivid imagery and sensory details:",
-            RewriteStyle::MoreDirect => "Rewrite this text to be more direct and straightforward:",
-            RewriteStyle::MorePoetic => "Rewrite this text in a more poetic, lyrical style:",
-            RewriteStyle::ToneShift(tone) => &format!("Rewrite this text in a {} tone:", tone),
-        };
+        let style_instruction = render_prompt("rewrite", &PromptScope::global(), &rewrite_values(style))?.text;
         
         let system = format!("You are a helpful writing assistant. {}", style_instruction);
         
@@ -492,83 +471,10 @@ impl AIProvider for ClaudeProvider {
             max_tokens: (text.len() as u32 * 2), // Allow for expansion
             temperature: 0.7,
             system: Some(system),
+            stream: false,
         };
         
-        // Make API call with streaming
-        let response = self.client.post("https://api.anthropic.com/v1/messages")
-            .header("x-api-key", &self.api_key)
-            .header("anthropic-version", "2023-06-01")
-            .header("Content-Type", "application/json")
-            .header("anthropic-beta", "messages-2023-12-15-streaming") // Enable streaming
-            .query(&[("stream", "true")]) // Add stream parameter
-            .json(&request)
-            .send()
-            .await
-            .map_err(|e| StoryWeaverError::AIRequest {
-                provider: "claude".to_string(),
-                status_code: 0,
-                message: format!("Failed to send request to Claude API: {}", e),
-            })?;
-        
-        // Check for errors
-        let status = response.status();
-        let is_success = status.is_success();
-        
-        if !is_success {
-            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(StoryWeaverError::AIRequest {
-                provider: "claude".to_string(),
-                status_code: status.as_u16(),
-                message: format!("Claude API error: {}", error_text),
-            });
-        }
-        
-        // Create a new TextStream
-        let mut text_stream = TextStream::new();
-        
-        // Process the streaming response
-        let mut stream = response.bytes_stream();
-        use futures_util::StreamExt;
-        
-        while let Some(chunk_result) = stream.next().await {
-            let chunk = chunk_result.map_err(|e| StoryWeaverError::Network {
-                message: format!("Error reading stream chunk: {}", e),
-            })?;
-            let chunk_str = String::from_utf8_lossy(&chunk);
-            
-            // Claude sends "data: " prefixed SSE events
-            for line in chunk_str.lines() {
-                if line.starts_with("data: ") {
-                    let data = &line[6..]; // Skip "data: " prefix
-                    
-                    // Skip empty lines and [DONE] marker
-                    if data.is_empty() || data == "[DONE]" {
-                        continue;
-                    }
-                    
-                    // Parse the JSON data
-                    match serde_json::from_str::<serde_json::Value>(data) {
-                        Ok(json) => {
-                            // Extract the content from the JSON
-                            if let Some(delta) = json.get("delta") {
-                                if let Some(text) = delta.get("text").and_then(|t| t.as_str()) {
-                                    text_stream.append(text);
-                                }
-                            }
-                        },
-                        Err(e) => {
-                            eprintln!("Error parsing JSON from stream: {}", e);
-                            continue;
-                        }
-                    }
-                }
-            }
-        }
-        
-        // Mark the stream as complete
-        text_stream.complete();
-        
-        Ok(text_stream)
+        self.send_stream_request(request, estimated_tokens).await
     }
 
     async fn expand_text(&self, text: &str, context: &AIContext) -> Result<String> {
@@ -576,13 +482,9 @@ impl AIProvider for ClaudeProvider {
         self.generate_text(&prompt, context).await
     }
 
-    async fn expand_text_stream(&self, text: &str, context: &AIContext) -> Result<TextStream> {
-        let estimated_tokens = (text.len() / 4) as u32 + 500;
-        {
-            let mut rate_limiter = self.rate_limiter.lock().await;
-            rate_limiter.wait_if_needed(estimated_tokens).await?;
-        }
-        
+    async fn expand_text_stream(&self, text: &str, context: &AIContext) -> Result<TextChunkStream> {
+        let estimated_tokens = self.count_tokens(text) + 500;
+
         let mut prompt = String::new();
         
         // Add genre context if available
@@ -623,83 +525,10 @@ impl AIProvider for ClaudeProvider {
             max_tokens: 2000, // Allow for significant expansion
             temperature: 0.7,
             system: Some(system),
+            stream: false,
         };
         
-        // Make API call with streaming
-        let response = self.client.post("https://api.anthropic.com/v1/messages")
-            .header("x-api-key", &self.api_key)
-            .header("anthropic-version", "2023-06-01")
-            .header("Content-Type", "application/json")
-            .header("anthropic-beta", "messages-2023-12-15-streaming") // Enable streaming
-            .query(&[("stream", "true")]) // Add stream parameter
-            .json(&request)
-            .send()
-            .await
-            .map_err(|e| StoryWeaverError::AIRequest {
-                provider: "claude".to_string(),
-                status_code: 0,
-                message: format!("Failed to send request to Claude API: {}", e),
-            })?;
-        
-        // Check for errors
-        let status = response.status();
-        let is_success = status.is_success();
-        
-        if !is_success {
-            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(StoryWeaverError::AIRequest {
-                provider: "claude".to_string(),
-                status_code: status.as_u16(),
-                message: format!("Claude API error: {}", error_text),
-            });
-        }
-        
-        // Create a new TextStream
-        let mut text_stream = TextStream::new();
-        
-        // Process the streaming response
-        let mut stream = response.bytes_stream();
-        use futures_util::StreamExt;
-        
-        while let Some(chunk_result) = stream.next().await {
-            let chunk = chunk_result.map_err(|e| StoryWeaverError::Network {
-                message: format!("Error reading stream chunk: {}", e),
-            })?;
-            let chunk_str = String::from_utf8_lossy(&chunk);
-            
-            // Claude sends "data: " prefixed SSE events
-            for line in chunk_str.lines() {
-                if line.starts_with("data: ") {
-                    let data = &line[6..]; // Skip "data: " prefix
-                    
-                    // Skip empty lines and [DONE] marker
-                    if data.is_empty() || data == "[DONE]" {
-                        continue;
-                    }
-                    
-                    // Parse the JSON data
-                    match serde_json::from_str::<serde_json::Value>(data) {
-                        Ok(json) => {
-                            // Extract the content from the JSON
-                            if let Some(delta) = json.get("delta") {
-                                if let Some(text) = delta.get("text").and_then(|t| t.as_str()) {
-                                    text_stream.append(text);
-                                }
-                            }
-                        },
-                        Err(e) => {
-                            eprintln!("Error parsing JSON from stream: {}", e);
-                            continue;
-                        }
-                    }
-                }
-            }
-        }
-        
-        // Mark the stream as complete
-        text_stream.complete();
-        
-        Ok(text_stream)
+        self.send_stream_request(request, estimated_tokens).await
     }
 
     async fn describe_scene(&self, description: &str, context: &AIContext) -> Result<String> {
@@ -707,13 +536,9 @@ impl AIProvider for ClaudeProvider {
         self.generate_text(&prompt, context).await
     }
 
-    async fn describe_scene_stream(&self, description: &str, context: &AIContext) -> Result<TextStream> {
-        let estimated_tokens = (description.len() / 4) as u32 + 500;
-        {
-            let mut rate_limiter = self.rate_limiter.lock().await;
-            rate_limiter.wait_if_needed(estimated_tokens).await?;
-        }
-        
+    async fn describe_scene_stream(&self, description: &str, context: &AIContext) -> Result<TextChunkStream> {
+        let estimated_tokens = self.count_tokens(description) + 500;
+
         let mut prompt = String::new();
         
         prompt.push_str("Create a detailed, vivid scene description based on the following information:\n\n");
@@ -776,83 +601,10 @@ impl AIProvider for ClaudeProvider {
             max_tokens: 2000,
             temperature: 0.7,
             system: Some(system),
+            stream: false,
         };
         
-        // Make API call with streaming
-        let response = self.client.post("https://api.anthropic.com/v1/messages")
-            .header("x-api-key", &self.api_key)
-            .header("anthropic-version", "2023-06-01")
-            .header("Content-Type", "application/json")
-            .header("anthropic-beta", "messages-2023-12-15-streaming") // Enable streaming
-            .query(&[("stream", "true")]) // Add stream parameter
-            .json(&request)
-            .send()
-            .await
-            .map_err(|e| StoryWeaverError::AIRequest {
-                provider: "claude".to_string(),
-                status_code: 0,
-                message: format!("Failed to send request to Claude API: {}", e),
-            })?;
-        
-        // Check for errors
-        let status = response.status();
-        let is_success = status.is_success();
-        
-        if !is_success {
-            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(StoryWeaverError::AIRequest {
-                provider: "claude".to_string(),
-                status_code: status.as_u16(),
-                message: format!("Claude API error: {}", error_text),
-            });
-        }
-        
-        // Create a new TextStream
-        let mut text_stream = TextStream::new();
-        
-        // Process the streaming response
-        let mut stream = response.bytes_stream();
-        use futures_util::StreamExt;
-        
-        while let Some(chunk_result) = stream.next().await {
-            let chunk = chunk_result.map_err(|e| StoryWeaverError::Network {
-                message: format!("Error reading stream chunk: {}", e),
-            })?;
-            let chunk_str = String::from_utf8_lossy(&chunk);
-            
-            // Claude sends "data: " prefixed SSE events
-            for line in chunk_str.lines() {
-                if line.starts_with("data: ") {
-                    let data = &line[6..]; // Skip "data: " prefix
-                    
-                    // Skip empty lines and [DONE] marker
-                    if data.is_empty() || data == "[DONE]" {
-                        continue;
-                    }
-                    
-                    // Parse the JSON data
-                    match serde_json::from_str::<serde_json::Value>(data) {
-                        Ok(json) => {
-                            // Extract the content from the JSON
-                            if let Some(delta) = json.get("delta") {
-                                if let Some(text) = delta.get("text").and_then(|t| t.as_str()) {
-                                    text_stream.append(text);
-                                }
-                            }
-                        },
-                        Err(e) => {
-                            eprintln!("Error parsing JSON from stream: {}", e);
-                            continue;
-                        }
-                    }
-                }
-            }
-        }
-        
-        // Mark the stream as complete
-        text_stream.complete();
-        
-        Ok(text_stream)
+        self.send_stream_request(request, estimated_tokens).await
     }
 
     async fn brainstorm(&self, topic: &str, context: &AIContext) -> Result<Vec<String>> {
@@ -876,13 +628,9 @@ impl AIProvider for ClaudeProvider {
         self.generate_text(message, context).await
     }
 
-    async fn quick_chat_stream(&self, message: &str, context: &AIContext) -> Result<TextStream> {
-        let estimated_tokens = (message.len() / 4) as u32 + 300;
-        {
-            let mut rate_limiter = self.rate_limiter.lock().await;
-            rate_limiter.wait_if_needed(estimated_tokens).await?;
-        }
-        
+    async fn quick_chat_stream(&self, message: &str, context: &AIContext) -> Result<TextChunkStream> {
+        let estimated_tokens = self.count_tokens(message) + 300;
+
         let mut system = "You are StoryWeaver, an AI writing assistant. You help the user with their writing project by answering questions and providing guidance.".to_string();
         
         // Add story context if available
@@ -909,83 +657,10 @@ impl AIProvider for ClaudeProvider {
             max_tokens: 1000,
             temperature: 0.7,
             system: Some(system),
+            stream: false,
         };
         
-        // Make API call with streaming
-        let response = self.client.post("https://api.anthropic.com/v1/messages")
-            .header("x-api-key", &self.api_key)
-            .header("anthropic-version", "2023-06-01")
-            .header("Content-Type", "application/json")
-            .header("anthropic-beta", "messages-2023-12-15-streaming") // Enable streaming
-            .query(&[("stream", "true")]) // Add stream parameter
-            .json(&request)
-            .send()
-            .await
-            .map_err(|e| StoryWeaverError::AIRequest {
-                provider: "claude".to_string(),
-                status_code: 0,
-                message: format!("Failed to send request to Claude API: {}", e),
-            })?;
-        
-        // Check for errors
-        let status = response.status();
-        let is_success = status.is_success();
-        
-        if !is_success {
-            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(StoryWeaverError::AIRequest {
-                provider: "claude".to_string(),
-                status_code: status.as_u16(),
-                message: format!("Claude API error: {}", error_text),
-            });
-        }
-        
-        // Create a new TextStream
-        let mut text_stream = TextStream::new();
-        
-        // Process the streaming response
-        let mut stream = response.bytes_stream();
-        use futures_util::StreamExt;
-        
-        while let Some(chunk_result) = stream.next().await {
-            let chunk = chunk_result.map_err(|e| StoryWeaverError::Network {
-                message: format!("Error reading stream chunk: {}", e),
-            })?;
-            let chunk_str = String::from_utf8_lossy(&chunk);
-            
-            // Claude sends "data: " prefixed SSE events
-            for line in chunk_str.lines() {
-                if line.starts_with("data: ") {
-                    let data = &line[6..]; // Skip "data: " prefix
-                    
-                    // Skip empty lines and [DONE] marker
-                    if data.is_empty() || data == "[DONE]" {
-                        continue;
-                    }
-                    
-                    // Parse the JSON data
-                    match serde_json::from_str::<serde_json::Value>(data) {
-                        Ok(json) => {
-                            // Extract the content from the JSON
-                            if let Some(delta) = json.get("delta") {
-                                if let Some(text) = delta.get("text").and_then(|t| t.as_str()) {
-                                    text_stream.append(text);
-                                }
-                            }
-                        },
-                        Err(e) => {
-                            eprintln!("Error parsing JSON from stream: {}", e);
-                            continue;
-                        }
-                    }
-                }
-            }
-        }
-        
-        // Mark the stream as complete
-        text_stream.complete();
-        
-        Ok(text_stream)
+        self.send_stream_request(request, estimated_tokens).await
     }
 
     async fn generate_image(&self, _prompt: &str) -> Result<String> {
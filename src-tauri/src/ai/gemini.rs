@@ -1,13 +1,15 @@
 //! Google Gemini Provider implementation for StoryWeaver
 
-use super::{AIProvider, AIContext, TextStream, RewriteStyle};
+use super::{AIProvider, AIContext, TextChunkStream, RewriteStyle};
+use super::resilience::{ProviderHealth, Resilience, ResiliencePolicy, ResilientSend};
+use super::streaming::sse_text_stream;
+use super::prompt_templates::{render_prompt, rewrite_values, system_values, PromptScope};
+use super::structured::JsonSchema;
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
 use crate::error::{Result, StoryWeaverError};
 use std::sync::Arc;
 use std::time::Duration;
-use tokio::sync::Mutex;
-use tokio::time::sleep;
 
 // Rate limiting constants for Gemini
 const REQUESTS_PER_MINUTE: u32 = 60;
@@ -42,6 +44,10 @@ struct GenerationConfig {
     top_p: f32,
     #[serde(rename = "topK")]
     top_k: u32,
+    #[serde(rename = "responseMimeType", skip_serializing_if = "Option::is_none")]
+    response_mime_type: Option<String>,
+    #[serde(rename = "responseSchema", skip_serializing_if = "Option::is_none")]
+    response_schema: Option<serde_json::Value>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -68,12 +74,12 @@ struct GeminiResponse {
 #[derive(Debug, Clone, Deserialize)]
 struct UsageMetadata {
     #[serde(rename = "promptTokenCount")]
-    #[allow(dead_code)]
     prompt_token_count: u32,
-    #[serde(rename = "candidatesTokenCount")]
-    #[allow(dead_code)]
+    // Left out when the response has no candidates
+    #[serde(rename = "candidatesTokenCount", default)]
     candidates_token_count: u32,
     #[serde(rename = "totalTokenCount")]
+    #[allow(dead_code)]
     total_token_count: u32,
 }
 
@@ -81,63 +87,15 @@ pub struct GeminiProvider {
     pub api_key: String,
     pub model: String,
     pub client: reqwest::Client,
-    pub rate_limiter: Arc<Mutex<RateLimiter>>,
+    pub resilience: Arc<Resilience>,
 }
 
-pub struct RateLimiter {
-    request_count: u32,
-    token_count: u32,
-    last_reset: std::time::Instant,
-}
-
-impl RateLimiter {
-    fn new() -> Self {
-        Self {
-            request_count: 0,
-            token_count: 0,
-            last_reset: std::time::Instant::now(),
-        }
-    }
-
-    async fn wait_if_needed(&mut self, estimated_tokens: u32) -> Result<()> {
-        // Reset counters if a minute has passed
-        let now = std::time::Instant::now();
-        if now.duration_since(self.last_reset).as_secs() >= 60 {
-            self.request_count = 0;
-            self.token_count = 0;
-            self.last_reset = now;
-        }
-
-        // Check if we're about to exceed limits
-        if self.request_count >= REQUESTS_PER_MINUTE || 
-           self.token_count + estimated_tokens >= TOKENS_PER_MINUTE {
-            
-            // Calculate time to wait until next minute
-            let elapsed = now.duration_since(self.last_reset).as_millis() as u64;
-            let wait_time = 60000_u64.saturating_sub(elapsed);
-            
-            // Wait until rate limit resets
-            if wait_time > 0 {
-                sleep(Duration::from_millis(wait_time)).await;
-                self.request_count = 0;
-                self.token_count = 0;
-                self.last_reset = std::time::Instant::now();
-            }
-        }
-
-        // Update counters
-        self.request_count += 1;
-        self.token_count += estimated_tokens;
-        
-        Ok(())
-    }
-
-    fn update_token_usage(&mut self, usage: &UsageMetadata) {
-        self.token_count = self.token_count.saturating_add(usage.total_token_count);
+impl GeminiProvider {
+    /// Token count for rate limiting, using this model's tokenizer
+    fn count_tokens(&self, text: &str) -> u32 {
+        super::tokenizer::count_tokens(&self.model, text) as u32
     }
-}
 
-impl GeminiProvider {
     pub fn new(api_key: String, model: String) -> Self {
         let client = reqwest::Client::builder()
             .timeout(Duration::from_secs(60))
@@ -148,10 +106,19 @@ impl GeminiProvider {
             api_key,
             model,
             client,
-            rate_limiter: Arc::new(Mutex::new(RateLimiter::new())),
+            resilience: Arc::new(Resilience::new(
+                "gemini",
+                ResiliencePolicy::with_rate_limits(REQUESTS_PER_MINUTE, TOKENS_PER_MINUTE),
+            )),
         }
     }
 
+    /// Override the retry, pacing and circuit breaker settings
+    pub fn with_resilience_policy(mut self, policy: ResiliencePolicy) -> Self {
+        self.resilience = Arc::new(Resilience::new("gemini", policy));
+        self
+    }
+
     fn get_api_url(&self) -> String {
         format!(
             "https://generativelanguage.googleapis.com/v1beta/models/{}:generateContent?key={}",
@@ -161,28 +128,98 @@ impl GeminiProvider {
 
     fn get_streaming_api_url(&self) -> String {
         format!(
-            "https://generativelanguage.googleapis.com/v1beta/models/{}:streamGenerateContent?key={}",
+            "https://generativelanguage.googleapis.com/v1beta/models/{}:streamGenerateContent?alt=sse&key={}",
             self.model, self.api_key
         )
     }
 
-    fn build_system_content(&self, context: &AIContext) -> GeminiContent {
-        let mut system_text = "You are StoryWeaver, an AI writing assistant. Help the user write their story.".to_string();
+    /// Send a `generateContent` request and return the first candidate's text
+    async fn send_request(&self, request: &GeminiRequest, estimated_tokens: u32) -> Result<String> {
+        // Make API call
+        let response = self.client.post(&self.get_api_url())
+            .header("Content-Type", "application/json")
+            .json(request)
+            .send_with(&self.resilience, estimated_tokens)
+            .await?;
         
-        // Add genre context if available
-        if let Some(genre) = &context.genre {
-            system_text.push_str(&format!(" The story is in the {} genre.", genre));
+        // Check for errors first
+        let status_code = response.status().as_u16();
+        let is_success = response.status().is_success();
+        
+        // Get response text
+        let response_text = response.text().await
+            .map_err(|e| StoryWeaverError::AIRequest {
+                provider: "gemini".to_string(),
+                status_code: 0,
+                message: format!("Failed to read response: {}", e),
+            })?;
+        
+        if !is_success {
+            return Err(StoryWeaverError::AIRequest {
+                provider: "gemini".to_string(),
+                status_code,
+                message: format!("Gemini API error: {}", response_text),
+            });
         }
         
-        // Add writing style if available
-        if let Some(style) = &context.writing_style {
-            system_text.push_str(&format!(" Use a {} writing style.", style));
+        // Parse response
+        let gemini_response: GeminiResponse = serde_json::from_str(&response_text)
+            .map_err(|e| StoryWeaverError::AIProvider {
+                provider: "gemini".to_string(),
+                message: format!("Failed to parse Gemini API response: {}", e),
+            })?;
+        
+        // Record actual token usage for pacing
+        if let Some(usage) = &gemini_response.usage_metadata {
+            self.resilience.record_usage(estimated_tokens, usage.prompt_token_count, usage.candidates_token_count).await;
         }
         
-        GeminiContent {
+        // Extract generated text
+        if let Some(candidate) = gemini_response.candidates.first() {
+            if let Some(part) = candidate.content.parts.first() {
+                Ok(part.text.clone())
+            } else {
+                Err(StoryWeaverError::AIProvider {
+                    provider: "gemini".to_string(),
+                    message: "No text parts in response".to_string(),
+                })
+            }
+        } else {
+            Err(StoryWeaverError::AIProvider {
+                provider: "gemini".to_string(),
+                message: "No candidates returned".to_string(),
+            })
+        }
+    }
+
+    /// Send a `streamGenerateContent` request and yield the text deltas as they arrive
+    async fn send_stream_request(&self, request: &GeminiRequest, estimated_tokens: u32) -> Result<TextChunkStream> {
+        let response = self.client.post(&self.get_streaming_api_url())
+            .header("Content-Type", "application/json")
+            .json(request)
+            .send_with(&self.resilience, estimated_tokens)
+            .await?;
+
+        let status_code = response.status().as_u16();
+        if !response.status().is_success() {
+            let response_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
+            return Err(StoryWeaverError::AIRequest {
+                provider: "gemini".to_string(),
+                status_code,
+                message: format!("Gemini API error: {}", response_text),
+            });
+        }
+
+        Ok(sse_text_stream(response, "gemini", candidate_text_delta))
+    }
+
+    fn build_system_content(&self, context: &AIContext) -> Result<GeminiContent> {
+        let system_text = render_prompt("system", &PromptScope::from_context(context), &system_values(context))?.text;
+
+        Ok(GeminiContent {
             role: "model".to_string(),
             parts: vec![GeminiPart { text: system_text }],
-        }
+        })
     }
 
     fn create_generation_config(&self, max_tokens: u32, temperature: f32) -> GenerationConfig {
@@ -191,6 +228,8 @@ impl GeminiProvider {
             max_output_tokens: max_tokens,
             top_p: 0.95,
             top_k: 40,
+            response_mime_type: None,
+            response_schema: None,
         }
     }
 
@@ -214,28 +253,72 @@ impl GeminiProvider {
             },
         ]
     }
-    
-    async fn rewrite_text_with_tone(&self, text: &str, tone: &str) -> Result<String> {
-        let prompt = format!("Rewrite this text in a {} tone:\n\n{}", tone, text);
-        let context = AIContext::default();
-        self.generate_text(&prompt, &context).await
+}
+
+/// Translate a JSON Schema into the OpenAPI subset `responseSchema` accepts:
+/// upper-case types, `nullable` instead of a `null` type, and no keywords
+/// Gemini rejects. Dropped constraints are still checked when the reply is
+/// validated.
+fn gemini_schema(schema: &serde_json::Value) -> serde_json::Value {
+    let Some(object) = schema.as_object() else {
+        return schema.clone();
+    };
+
+    let mut converted = serde_json::Map::new();
+    for (key, value) in object {
+        match key.as_str() {
+            "type" => {
+                let types: Vec<&str> = match value {
+                    serde_json::Value::String(name) => vec![name.as_str()],
+                    serde_json::Value::Array(names) => names.iter().filter_map(|n| n.as_str()).collect(),
+                    _ => Vec::new(),
+                };
+                if types.contains(&"null") {
+                    converted.insert("nullable".to_string(), serde_json::Value::Bool(true));
+                }
+                if let Some(name) = types.iter().find(|name| **name != "null") {
+                    converted.insert("type".to_string(), serde_json::Value::String(name.to_uppercase()));
+                }
+            }
+            "properties" => {
+                let properties = value
+                    .as_object()
+                    .map(|properties| properties.iter().map(|(name, property)| (name.clone(), gemini_schema(property))).collect())
+                    .unwrap_or_default();
+                converted.insert(key.clone(), serde_json::Value::Object(properties));
+            }
+            "items" => {
+                converted.insert(key.clone(), gemini_schema(value));
+            }
+            "description" | "enum" | "required" | "format" | "nullable" | "minItems" | "maxItems" | "minimum" | "maximum" => {
+                converted.insert(key.clone(), value.clone());
+            }
+            _ => {}
+        }
     }
+    serde_json::Value::Object(converted)
+}
+
+/// Concatenated text parts of the first candidate in a streamed response chunk
+fn candidate_text_delta(event: &serde_json::Value) -> Option<String> {
+    let parts = event
+        .get("candidates")
+        .and_then(|candidates| candidates.get(0))
+        .and_then(|candidate| candidate.get("content"))
+        .and_then(|content| content.get("parts"))
+        .and_then(|parts| parts.as_array())?;
+
+    Some(parts.iter().filter_map(|part| part.get("text").and_then(|t| t.as_str())).collect())
 }
 
 #[async_trait]
 impl AIProvider for GeminiProvider {
     async fn generate_text(&self, prompt: &str, context: &AIContext) -> Result<String> {
         // Estimate token usage for rate limiting
-        let estimated_tokens = (prompt.len() / 4) as u32 + 500;
-        
-        // Wait if we need to respect rate limits
-        {
-            let mut rate_limiter = self.rate_limiter.lock().await;
-            rate_limiter.wait_if_needed(estimated_tokens).await?;
-        }
+        let estimated_tokens = self.count_tokens(prompt) + 500;
         
         // Build request
-        let system_content = self.build_system_content(context);
+        let system_content = self.build_system_content(context)?;
         let user_content = GeminiContent {
             role: "user".to_string(),
             parts: vec![GeminiPart { text: prompt.to_string() }],
@@ -247,81 +330,15 @@ impl AIProvider for GeminiProvider {
             safety_settings: Some(self.create_safety_settings()),
         };
         
-        // Make API call
-        let response = self.client.post(&self.get_api_url())
-            .header("Content-Type", "application/json")
-            .json(&request)
-            .send()
-            .await
-            .map_err(|e| StoryWeaverError::AIRequest {
-                provider: "gemini".to_string(),
-                status_code: 0,
-                message: format!("Failed to send request to Gemini API: {}", e),
-            })?;
-        
-        // Check for errors first
-        let status_code = response.status().as_u16();
-        let is_success = response.status().is_success();
-        
-        // Get response text
-        let response_text = response.text().await
-            .map_err(|e| StoryWeaverError::AIRequest {
-                provider: "gemini".to_string(),
-                status_code: 0,
-                message: format!("Failed to read response: {}", e),
-            })?;
-        
-        if !is_success {
-            return Err(StoryWeaverError::AIRequest {
-                provider: "gemini".to_string(),
-                status_code,
-                message: format!("Gemini API error: {}", response_text),
-            });
-        }
-        
-        // Parse response
-        let gemini_response: GeminiResponse = serde_json::from_str(&response_text)
-            .map_err(|e| StoryWeaverError::AIProvider {
-                provider: "gemini".to_string(),
-                message: format!("Failed to parse Gemini API response: {}", e),
-            })?;
-        
-        // Update rate limiter with actual token usage
-        if let Some(usage) = &gemini_response.usage_metadata {
-            let mut rate_limiter = self.rate_limiter.lock().await;
-            rate_limiter.update_token_usage(usage);
-        }
-        
-        // Extract generated text
-        if let Some(candidate) = gemini_response.candidates.first() {
-            if let Some(part) = candidate.content.parts.first() {
-                Ok(part.text.clone())
-            } else {
-                Err(StoryWeaverError::AIProvider {
-                    provider: "gemini".to_string(),
-                    message: "No text parts in response".to_string(),
-                })
-            }
-        } else {
-            Err(StoryWeaverError::AIProvider {
-                provider: "gemini".to_string(),
-                message: "No candidates returned".to_string(),
-            })
-        }
+        self.send_request(&request, estimated_tokens).await
     }
 
-    async fn generate_text_stream(&self, prompt: &str, context: &AIContext) -> Result<TextStream> {
+    async fn generate_text_stream(&self, prompt: &str, context: &AIContext) -> Result<TextChunkStream> {
         // Estimate token usage for rate limiting
-        let estimated_tokens = (prompt.len() / 4) as u32 + 500;
-        
-        // Wait if we need to respect rate limits
-        {
-            let mut rate_limiter = self.rate_limiter.lock().await;
-            rate_limiter.wait_if_needed(estimated_tokens).await?;
-        }
+        let estimated_tokens = self.count_tokens(prompt) + 500;
         
         // Build request
-        let system_content = self.build_system_content(context);
+        let system_content = self.build_system_content(context)?;
         let user_content = GeminiContent {
             role: "user".to_string(),
             parts: vec![GeminiPart { text: prompt.to_string() }],
@@ -333,106 +350,12 @@ impl AIProvider for GeminiProvider {
             safety_settings: Some(self.create_safety_settings()),
         };
         
-        // Make API call with streaming
-        let response = self.client.post(&self.get_streaming_api_url())
-            .header("Content-Type", "application/json")
-            .json(&request)
-            .send()
-            .await
-            .map_err(|e| StoryWeaverError::AIRequest {
-                provider: "gemini".to_string(),
-                status_code: 0,
-                message: format!("Failed to send request to Gemini API: {}", e),
-            })?;
-        
-        // Check for errors first
-        let status_code = response.status().as_u16();
-        let is_success = response.status().is_success();
-        
-        if !is_success {
-            // Only get response text if there's an error
-            let response_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(StoryWeaverError::AIRequest {
-                provider: "gemini".to_string(),
-                status_code,
-                message: format!("Gemini API error: {}", response_text),
-            });
-        }
-        
-        // Create a new TextStream
-        let mut text_stream = TextStream::new();
-        
-        // Process the streaming response
-        let mut stream = response.bytes_stream();
-        use futures_util::StreamExt;
-        
-        while let Some(chunk_result) = stream.next().await {
-            let chunk = chunk_result.map_err(|e| StoryWeaverError::Network {
-                message: format!("Error reading stream chunk: {}", e),
-            })?;
-            let chunk_str = String::from_utf8_lossy(&chunk);
-            
-            // Gemini streaming format is a series of JSON objects, each on its own line
-            for line in chunk_str.lines() {
-                if line.trim().is_empty() {
-                    continue;
-                }
-                
-                // Parse the JSON data
-                match serde_json::from_str::<serde_json::Value>(line) {
-                    Ok(json) => {
-                        // Check if this is a content chunk
-                        if let Some(candidates) = json.get("candidates") {
-                            if let Some(candidate) = candidates.get(0) {
-                                if let Some(content) = candidate.get("content") {
-                                    if let Some(parts) = content.get("parts") {
-                                        if let Some(part) = parts.get(0) {
-                                            if let Some(text) = part.get("text").and_then(|t| t.as_str()) {
-                                                text_stream.append(text);
-                                            }
-                                        }
-                                    }
-                                }
-                                
-                                // Check if this is the final chunk
-                                if let Some(finish_reason) = candidate.get("finishReason") {
-                                    if finish_reason.is_string() {
-                                        text_stream.complete();
-                                    }
-                                }
-                            }
-                        }
-                    },
-                    Err(e) => {
-                        eprintln!("Error parsing JSON from stream: {}", e);
-                        continue;
-                    }
-                }
-            }
-        }
-        
-        // Ensure the stream is marked as complete
-        if !text_stream.is_complete {
-            text_stream.complete();
-        }
-        
-        Ok(text_stream)
+        self.send_stream_request(&request, estimated_tokens).await
     }
 
     async fn rewrite_text(&self, text: &str, style: &RewriteStyle) -> Result<String> {
         // Build prompt based on rewrite style
-        let style_instruction = match style {
-            RewriteStyle::Rephrase => "Rephrase this text while keeping the same meaning:",
-            RewriteStyle::Shorter => "Rewrite this text to be more concise:",
-            RewriteStyle::MoreDescriptive => "Rewrite this text to be more descriptive and vivid:",
-            RewriteStyle::Longer => "Expand this text with more details and elaboration:",
-            RewriteStyle::MoreFormal => "Rewrite this text in a more formal, professional tone:",
-            RewriteStyle::MoreCasual => "Rewrite this text in a more casual, conversational tone:",
-            RewriteStyle::MoreVivid => "Rewrite this text with more vivid imagery and sensory details:",
-            RewriteStyle::MoreDirect => "Rewrite this text to be more direct and straightforward:",
-            RewriteStyle::MorePoetic => "Rewrite this text in a more poetic, lyrical style:",
-            RewriteStyle::ToneShift(tone) => return self.rewrite_text_with_tone(text, tone).await,
-        };
+        let style_instruction = render_prompt("rewrite", &PromptScope::global(), &rewrite_values(style))?.text;
         
         let prompt = format!("{}\n\n{}", style_instruction, text);
         let context = AIContext::default();
@@ -440,13 +363,33 @@ impl AIProvider for GeminiProvider {
     }
 
     async fn generate_embedding(&self, _text: &str) -> Result<Vec<f32>> {
-        // Gemini doesn't have a direct embedding API like OpenAI
-        // We'll need to use a different approach or return a placeholder
-        // In production, you might use Google's Universal Sentence Encoder or similar
-        
-        // For now, return a placeholder embedding
-        let embedding_size = 768; // Standard size for many models
-        Ok(vec![0.0; embedding_size])
+        // Not wired to Gemini's embedding endpoint; AIProviderManager::embed
+        // falls back to local embeddings rather than using a placeholder vector
+        Err(StoryWeaverError::AIProvider {
+            provider: "gemini".to_string(),
+            message: "Gemini embeddings are not supported".to_string(),
+        })
+    }
+
+    async fn generate_json(&self, prompt: &str, schema: &JsonSchema, context: &AIContext) -> Result<String> {
+        let estimated_tokens = self.count_tokens(prompt) + 1000;
+        let mut generation_config = self.create_generation_config(2000, 0.7);
+        generation_config.response_mime_type = Some("application/json".to_string());
+        generation_config.response_schema = Some(gemini_schema(&schema.schema));
+
+        let request = GeminiRequest {
+            contents: vec![
+                self.build_system_content(context)?,
+                GeminiContent {
+                    role: "user".to_string(),
+                    parts: vec![GeminiPart { text: prompt.to_string() }],
+                },
+            ],
+            generation_config,
+            safety_settings: Some(self.create_safety_settings()),
+        };
+
+        self.send_request(&request, estimated_tokens).await
     }
 
     fn supports_streaming(&self) -> bool {
@@ -469,29 +412,18 @@ impl AIProvider for GeminiProvider {
     fn get_provider_name(&self) -> &str {
         "Gemini"
     }
+
+    fn health(&self) -> ProviderHealth {
+        self.resilience.health()
+    }
     
     fn supports_image_generation(&self) -> bool {
         false // Gemini doesn't directly support image generation like DALL-E
     }
     
-    async fn rewrite_text_stream(&self, text: &str, style: &RewriteStyle) -> Result<TextStream> {
+    async fn rewrite_text_stream(&self, text: &str, style: &RewriteStyle) -> Result<TextChunkStream> {
         // Build prompt based on rewrite style
-        let style_instruction = match style {
-            RewriteStyle::Rephrase => "Rephrase this text while keeping the same meaning:",
-            RewriteStyle::Shorter => "Rewrite this text to be more concise:",
-            RewriteStyle::MoreDescriptive => "Rewrite this text to be more descriptive and vivid:",
-            RewriteStyle::Longer => "Expand this text with more details and elaboration:",
-            RewriteStyle::MoreFormal => "Rewrite this text in a more formal, professional tone:",
-            RewriteStyle::MoreCasual => "Rewrite this text in a more casual, conversational tone:",
-            RewriteStyle::MoreVivid => "Rewrite this text with more vivid imagery and sensory details:",
-            RewriteStyle::MoreDirect => "Rewrite this text to be more direct and straightforward:",
-            RewriteStyle::MorePoetic => "Rewrite this text in a more poetic, lyrical style:",
-            RewriteStyle::ToneShift(tone) => {
-                let prompt = format!("Rewrite this text in a {} tone:\n\n{}", tone, text);
-                let context = AIContext::default();
-                return self.generate_text_stream(&prompt, &context).await;
-            },
-        };
+        let style_instruction = render_prompt("rewrite", &PromptScope::global(), &rewrite_values(style))?.text;
         
         let prompt = format!("{}\n\n{}", style_instruction, text);
         let context = AIContext::default();
@@ -526,7 +458,7 @@ impl AIProvider for GeminiProvider {
         self.generate_text(&prompt, context).await
     }
     
-    async fn expand_text_stream(&self, text: &str, context: &AIContext) -> Result<TextStream> {
+    async fn expand_text_stream(&self, text: &str, context: &AIContext) -> Result<TextChunkStream> {
         let mut prompt = String::new();
         
         // Add genre context if available
@@ -604,7 +536,7 @@ impl AIProvider for GeminiProvider {
         self.generate_text(&prompt, context).await
     }
     
-    async fn describe_scene_stream(&self, description: &str, context: &AIContext) -> Result<TextStream> {
+    async fn describe_scene_stream(&self, description: &str, context: &AIContext) -> Result<TextChunkStream> {
         let mut prompt = String::new();
         
         prompt.push_str("Create a detailed, vivid scene description based on the following information:\n\n");
@@ -765,7 +697,7 @@ impl AIProvider for GeminiProvider {
         self.generate_text(&prompt, context).await
     }
     
-    async fn quick_chat_stream(&self, message: &str, context: &AIContext) -> Result<TextStream> {
+    async fn quick_chat_stream(&self, message: &str, context: &AIContext) -> Result<TextChunkStream> {
         let mut prompt = String::new();
         
         // Add story context if available
@@ -0,0 +1,292 @@
+//! Offline embeddings for semantic features
+//!
+//! `LocalEmbeddingProvider` turns text into a fixed-size vector without a
+//! model file or network access: word unigrams, word bigrams and character
+//! trigrams are counted, weighted sublinearly, and hashed into a sparse random
+//! projection. Texts that share vocabulary (or word stems, through the
+//! trigrams) land close together under cosine similarity. It is the fallback
+//! for `AIProviderManager::embed` when no cloud embedding route is configured,
+//! so saliency and similarity work for writers without an API key.
+//!
+//! Hashing is spelled out here rather than taken from `std` so vectors stay
+//! comparable across builds once they are stored.
+
+use super::streaming::TextChunkStream;
+use super::{AIContext, AIProvider, RewriteStyle};
+use crate::error::{Result, StoryWeaverError};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Model name recorded for vectors produced by `LocalEmbeddingProvider`
+pub const LOCAL_EMBEDDING_MODEL: &str = "local-hashed-ngram-384";
+
+/// Dimension of local embeddings
+pub const LOCAL_EMBEDDING_DIMENSION: usize = 384;
+
+/// Output dimensions each feature is projected onto
+const PROJECTIONS_PER_FEATURE: u64 = 4;
+
+const WORD_WEIGHT: f32 = 1.0;
+const BIGRAM_WEIGHT: f32 = 0.5;
+const TRIGRAM_WEIGHT: f32 = 0.2;
+/// Function words carry little meaning but dominate counts
+const STOPWORD_WEIGHT: f32 = 0.1;
+
+const STOPWORDS: &[&str] = &[
+    "a", "an", "and", "are", "as", "at", "be", "but", "by", "for", "from", "had", "has", "have", "he", "her",
+    "his", "i", "in", "is", "it", "its", "me", "my", "of", "on", "or", "she", "so", "that", "the", "their",
+    "them", "then", "there", "they", "this", "to", "was", "we", "were", "with", "you", "your",
+];
+
+/// An embedding vector and the model that produced it. Vectors from
+/// different models are not comparable.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Embedding {
+    pub model: String,
+    pub vector: Vec<f32>,
+}
+
+/// Cosine similarity of two vectors; 0.0 when either is empty, zero or the
+/// lengths differ
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() || a.is_empty() {
+        return 0.0;
+    }
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 0.0;
+    }
+    dot / (norm_a * norm_b)
+}
+
+/// 64-bit FNV-1a
+fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in bytes {
+        hash ^= u64::from(*byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
+
+/// SplitMix64 finalizer, used to derive independent projections from one hash
+fn mix(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+/// Offline hashed n-gram embedder
+#[derive(Debug, Clone)]
+pub struct LocalEmbeddingProvider {
+    dimension: usize,
+}
+
+impl Default for LocalEmbeddingProvider {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LocalEmbeddingProvider {
+    pub fn new() -> Self {
+        Self { dimension: LOCAL_EMBEDDING_DIMENSION }
+    }
+
+    pub fn dimension(&self) -> usize {
+        self.dimension
+    }
+
+    /// Embed `text` into a unit vector; text with no words embeds to zeros
+    pub fn embed(&self, text: &str) -> Vec<f32> {
+        let mut vector = vec![0.0f32; self.dimension];
+        for (feature, weight) in Self::features(text) {
+            let hash = fnv1a(feature.as_bytes());
+            for projection in 0..PROJECTIONS_PER_FEATURE {
+                let bits = mix(hash ^ projection.wrapping_mul(0x9e37_79b9_7f4a_7c15));
+                let index = (bits % self.dimension as u64) as usize;
+                let sign = if bits >> 63 == 0 { 1.0 } else { -1.0 };
+                vector[index] += sign * weight;
+            }
+        }
+
+        let norm = vector.iter().map(|v| v * v).sum::<f32>().sqrt();
+        if norm > 0.0 {
+            vector.iter_mut().for_each(|v| *v /= norm);
+        }
+        vector
+    }
+
+    /// Weighted features of `text`, with term counts damped to `1 + ln(count)`
+    fn features(text: &str) -> HashMap<String, f32> {
+        let lowered = text.to_lowercase();
+        let words: Vec<&str> = lowered
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|w| !w.is_empty())
+            .collect();
+
+        let mut counts: HashMap<String, (f32, f32)> = HashMap::new();
+        let mut add = |feature: String, weight: f32| {
+            let entry = counts.entry(feature).or_insert((0.0, weight));
+            entry.0 += 1.0;
+        };
+
+        for word in &words {
+            let stopword = STOPWORDS.contains(word);
+            add(format!("w:{}", word), if stopword { STOPWORD_WEIGHT } else { WORD_WEIGHT });
+            if !stopword && word.chars().count() > 3 {
+                let chars: Vec<char> = format!("<{}>", word).chars().collect();
+                for trigram in chars.windows(3) {
+                    add(format!("c:{}", trigram.iter().collect::<String>()), TRIGRAM_WEIGHT);
+                }
+            }
+        }
+        for pair in words.windows(2) {
+            if !STOPWORDS.contains(&pair[0]) || !STOPWORDS.contains(&pair[1]) {
+                add(format!("b:{} {}", pair[0], pair[1]), BIGRAM_WEIGHT);
+            }
+        }
+
+        counts
+            .into_iter()
+            .map(|(feature, (count, weight))| (feature, weight * (1.0 + count.ln())))
+            .collect()
+    }
+}
+
+fn unsupported(operation: &str) -> StoryWeaverError {
+    StoryWeaverError::NotSupported {
+        operation: format!("{} (the local embedding provider only generates embeddings)", operation),
+    }
+}
+
+#[async_trait]
+impl AIProvider for LocalEmbeddingProvider {
+    async fn generate_text(&self, _prompt: &str, _context: &AIContext) -> Result<String> {
+        Err(unsupported("generate_text"))
+    }
+
+    async fn generate_text_stream(&self, _prompt: &str, _context: &AIContext) -> Result<TextChunkStream> {
+        Err(unsupported("generate_text_stream"))
+    }
+
+    async fn rewrite_text(&self, _text: &str, _style: &RewriteStyle) -> Result<String> {
+        Err(unsupported("rewrite_text"))
+    }
+
+    async fn rewrite_text_stream(&self, _text: &str, _style: &RewriteStyle) -> Result<TextChunkStream> {
+        Err(unsupported("rewrite_text_stream"))
+    }
+
+    async fn expand_text(&self, _text: &str, _context: &AIContext) -> Result<String> {
+        Err(unsupported("expand_text"))
+    }
+
+    async fn expand_text_stream(&self, _text: &str, _context: &AIContext) -> Result<TextChunkStream> {
+        Err(unsupported("expand_text_stream"))
+    }
+
+    async fn describe_scene(&self, _description: &str, _context: &AIContext) -> Result<String> {
+        Err(unsupported("describe_scene"))
+    }
+
+    async fn describe_scene_stream(&self, _description: &str, _context: &AIContext) -> Result<TextChunkStream> {
+        Err(unsupported("describe_scene_stream"))
+    }
+
+    async fn brainstorm(&self, _topic: &str, _context: &AIContext) -> Result<Vec<String>> {
+        Err(unsupported("brainstorm"))
+    }
+
+    async fn related_words(&self, _word: &str, _context: &AIContext) -> Result<Vec<String>> {
+        Err(unsupported("related_words"))
+    }
+
+    async fn quick_edit(&self, _text: &str, _instruction: &str) -> Result<String> {
+        Err(unsupported("quick_edit"))
+    }
+
+    async fn quick_chat(&self, _message: &str, _context: &AIContext) -> Result<String> {
+        Err(unsupported("quick_chat"))
+    }
+
+    async fn quick_chat_stream(&self, _message: &str, _context: &AIContext) -> Result<TextChunkStream> {
+        Err(unsupported("quick_chat_stream"))
+    }
+
+    async fn generate_image(&self, _prompt: &str) -> Result<String> {
+        Err(unsupported("generate_image"))
+    }
+
+    async fn generate_embedding(&self, text: &str) -> Result<Vec<f32>> {
+        Ok(self.embed(text))
+    }
+
+    fn supports_streaming(&self) -> bool {
+        false
+    }
+
+    fn supports_image_generation(&self) -> bool {
+        false
+    }
+
+    fn get_context_window(&self) -> usize {
+        0
+    }
+
+    fn get_model_name(&self) -> &str {
+        LOCAL_EMBEDDING_MODEL
+    }
+
+    fn get_provider_name(&self) -> &str {
+        "local"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_embedding_is_deterministic_and_normalized() {
+        let provider = LocalEmbeddingProvider::new();
+        let first = provider.embed("The lighthouse keeper climbed the stairs.");
+        let second = provider.embed("The lighthouse keeper climbed the stairs.");
+
+        assert_eq!(first, second);
+        assert_eq!(first.len(), LOCAL_EMBEDDING_DIMENSION);
+        let norm = first.iter().map(|v| v * v).sum::<f32>().sqrt();
+        assert!((norm - 1.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_related_text_scores_higher_than_unrelated() {
+        let provider = LocalEmbeddingProvider::new();
+        let query = provider.embed("Mara sails the storm-battered ship toward the harbor");
+        let related = provider.embed("The ship limped into harbor after the storm, Mara at the helm");
+        let unrelated = provider.embed("A quiet dinner party in the city ended with a toast");
+
+        assert!(cosine_similarity(&query, &related) > cosine_similarity(&query, &unrelated) + 0.1);
+    }
+
+    #[test]
+    fn test_trigrams_match_inflected_forms() {
+        let provider = LocalEmbeddingProvider::new();
+        let base = provider.embed("sailing");
+        let inflected = provider.embed("sailed");
+        let other = provider.embed("thunder");
+
+        assert!(cosine_similarity(&base, &inflected) > cosine_similarity(&base, &other));
+    }
+
+    #[test]
+    fn test_text_without_words_embeds_to_zeros() {
+        let vector = LocalEmbeddingProvider::new().embed("  ...  ");
+        assert!(vector.iter().all(|v| *v == 0.0));
+        assert_eq!(cosine_similarity(&vector, &vector), 0.0);
+    }
+}
@@ -0,0 +1,734 @@
+//! Deterministic mock and record/replay providers for offline testing
+//!
+//! `MockProvider` answers from scripted responses keyed by feature and,
+//! optionally, a hash of the request input, with simulated streaming, latency
+//! and error injection. `RecordingProvider` wraps a real provider and captures
+//! its responses into a `Fixture` that a `ReplayProvider` serves back without
+//! network access.
+
+use super::streaming::TextChunkStream;
+use super::structured::{json_prompt, JsonSchema};
+use super::{AIContext, AIProvider, RewriteStyle, WritingFeature};
+use crate::error::{Result, StoryWeaverError};
+use async_trait::async_trait;
+use futures_util::stream::{self, StreamExt};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, VecDeque};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::time::sleep;
+
+/// Feature key for `generate_embedding`, alongside `WritingFeature::route_key`
+pub const EMBEDDING_FEATURE: &str = super::routing::EMBEDDING_ROUTE_KEY;
+
+/// Dimension of the embeddings `MockProvider` derives from input text
+pub const MOCK_EMBEDDING_DIMENSION: usize = 64;
+
+/// Stable hash of a request input, used to key scripted and recorded responses
+pub fn input_hash(input: &str) -> String {
+    let digest = Sha256::digest(input.as_bytes());
+    digest[..16].iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// A bag-of-words embedding: texts sharing words get similar vectors, which
+/// is enough to exercise semantic ranking without a model
+pub fn mock_embedding(text: &str) -> Vec<f32> {
+    let mut vector = vec![0.0f32; MOCK_EMBEDDING_DIMENSION];
+    for word in text.split(|c: char| !c.is_alphanumeric()).filter(|w| !w.is_empty()) {
+        let digest = Sha256::digest(word.to_lowercase().as_bytes());
+        vector[digest[0] as usize % MOCK_EMBEDDING_DIMENSION] += 1.0;
+    }
+    let norm = vector.iter().map(|v| v * v).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        vector.iter_mut().for_each(|v| *v /= norm);
+    }
+    vector
+}
+
+/// An injected provider failure
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MockError {
+    /// HTTP status to report; 0 stands for a connection failure
+    pub status_code: u16,
+    pub message: String,
+    /// Seconds to report for a 429
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry_after: Option<u64>,
+}
+
+impl MockError {
+    /// Capture a real provider error for a fixture
+    pub fn from_error(error: &StoryWeaverError) -> Self {
+        let (status_code, message, retry_after) = match error {
+            StoryWeaverError::AIRequest { status_code, message, .. } => (*status_code, message.clone(), None),
+            StoryWeaverError::AIRateLimit { retry_after, .. } => (429, error.to_string(), Some(*retry_after)),
+            StoryWeaverError::InvalidAPIKey { .. } => (401, error.to_string(), None),
+            StoryWeaverError::Network { .. }
+            | StoryWeaverError::RequestTimeout { .. }
+            | StoryWeaverError::ConnectionFailed { .. } => (0, error.to_string(), None),
+            _ => (500, error.to_string(), None),
+        };
+        Self { status_code, message, retry_after }
+    }
+
+    pub fn to_error(&self, provider: &str) -> StoryWeaverError {
+        match (self.status_code, self.retry_after) {
+            (429, Some(retry_after)) => StoryWeaverError::ai_rate_limit(provider, retry_after),
+            (0, _) => StoryWeaverError::network(format!("{}: {}", provider, self.message)),
+            (status, _) => StoryWeaverError::ai_request(provider, status, self.message.as_str()),
+        }
+    }
+}
+
+/// What a mock returns for one request
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ScriptedResponse {
+    pub text: String,
+    /// Deltas for streaming calls; empty means `text` split at word boundaries
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub chunks: Vec<String>,
+    /// Delay before the response, or before the first chunk when streaming
+    #[serde(default)]
+    pub latency_ms: u64,
+    /// Delay between streamed chunks
+    #[serde(default)]
+    pub chunk_delay_ms: u64,
+    /// Fail the request; when streaming, after any `chunks` have been sent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<MockError>,
+}
+
+impl ScriptedResponse {
+    pub fn text(text: &str) -> Self {
+        Self {
+            text: text.to_string(),
+            ..Self::default()
+        }
+    }
+
+    pub fn chunks(chunks: &[&str]) -> Self {
+        Self {
+            text: chunks.concat(),
+            chunks: chunks.iter().map(|c| c.to_string()).collect(),
+            ..Self::default()
+        }
+    }
+
+    pub fn error(status_code: u16, message: &str) -> Self {
+        Self {
+            error: Some(MockError {
+                status_code,
+                message: message.to_string(),
+                retry_after: None,
+            }),
+            ..Self::default()
+        }
+    }
+
+    pub fn rate_limited(retry_after: u64) -> Self {
+        Self {
+            error: Some(MockError {
+                status_code: 429,
+                message: "rate limited".to_string(),
+                retry_after: Some(retry_after),
+            }),
+            ..Self::default()
+        }
+    }
+
+    pub fn with_latency(mut self, latency: Duration) -> Self {
+        self.latency_ms = latency.as_millis() as u64;
+        self
+    }
+
+    pub fn with_chunk_delay(mut self, delay: Duration) -> Self {
+        self.chunk_delay_ms = delay.as_millis() as u64;
+        self
+    }
+
+    /// Deltas a streaming call yields
+    pub fn stream_chunks(&self) -> Vec<String> {
+        if !self.chunks.is_empty() {
+            return self.chunks.clone();
+        }
+        self.text.split_inclusive(' ').map(str::to_string).collect()
+    }
+}
+
+/// One request a `MockProvider` received
+#[derive(Debug, Clone, PartialEq)]
+pub struct MockCall {
+    pub feature: String,
+    pub input: String,
+    pub input_hash: String,
+    pub streamed: bool,
+}
+
+/// A recorded response and the request it answered
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedInteraction {
+    pub feature: String,
+    pub input_hash: String,
+    pub response: ScriptedResponse,
+}
+
+/// Responses recorded from a provider session, stored as JSON
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Fixture {
+    pub provider: String,
+    pub model: String,
+    #[serde(default)]
+    pub interactions: Vec<RecordedInteraction>,
+}
+
+impl Fixture {
+    pub fn load(path: &Path) -> Result<Self> {
+        let json = std::fs::read_to_string(path).map_err(|e| {
+            StoryWeaverError::file_operation("read", path.display().to_string().as_str(), e.to_string().as_str())
+        })?;
+        serde_json::from_str(&json)
+            .map_err(|e| StoryWeaverError::deserialization(format!("Invalid AI fixture {}: {}", path.display(), e)))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let json = serde_json::to_string_pretty(self)
+            .map_err(|e| StoryWeaverError::serialization(format!("Failed to serialize AI fixture: {}", e)))?;
+        std::fs::write(path, json).map_err(|e| {
+            StoryWeaverError::file_operation("write", path.display().to_string().as_str(), e.to_string().as_str())
+        })
+    }
+}
+
+type ScriptKey = (String, Option<String>);
+
+/// An `AIProvider` that answers from scripted responses.
+///
+/// A response scripted for a feature and input wins over one scripted for the
+/// feature alone. Several responses for the same key are served in order and
+/// the last one repeats. Unscripted requests get a deterministic echo, or an
+/// error when the provider is strict.
+pub struct MockProvider {
+    name: String,
+    model: String,
+    context_window: usize,
+    strict: bool,
+    scripts: Mutex<HashMap<ScriptKey, VecDeque<ScriptedResponse>>>,
+    calls: Mutex<Vec<MockCall>>,
+}
+
+/// A strict `MockProvider` serving a recorded `Fixture`
+pub type ReplayProvider = MockProvider;
+
+impl MockProvider {
+    pub fn new() -> Self {
+        Self {
+            name: "mock".to_string(),
+            model: "mock-model".to_string(),
+            context_window: 8192,
+            strict: false,
+            scripts: Mutex::new(HashMap::new()),
+            calls: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn with_name(mut self, name: &str) -> Self {
+        self.name = name.to_string();
+        self
+    }
+
+    pub fn with_model(mut self, model: &str) -> Self {
+        self.model = model.to_string();
+        self
+    }
+
+    pub fn with_context_window(mut self, context_window: usize) -> Self {
+        self.context_window = context_window;
+        self
+    }
+
+    /// Fail unscripted requests instead of echoing them
+    pub fn strict(mut self) -> Self {
+        self.strict = true;
+        self
+    }
+
+    /// Script a response for every request of a feature
+    pub fn on(self, feature: &WritingFeature, response: ScriptedResponse) -> Self {
+        self.push_script(feature.route_key(), None, response);
+        self
+    }
+
+    /// Script a response for one specific input of a feature
+    pub fn on_input(self, feature: &WritingFeature, input: &str, response: ScriptedResponse) -> Self {
+        self.push_script(feature.route_key(), Some(input_hash(input)), response);
+        self
+    }
+
+    /// Script the vector returned by `generate_embedding`
+    pub fn on_embedding(self, embedding: Vec<f32>) -> Self {
+        let text = serde_json::to_string(&embedding).unwrap_or_default();
+        self.push_script(EMBEDDING_FEATURE, None, ScriptedResponse::text(&text));
+        self
+    }
+
+    /// A strict provider answering with the fixture's recorded responses
+    pub fn from_fixture(fixture: Fixture) -> Self {
+        let provider = Self::new().with_name(&fixture.provider).with_model(&fixture.model).strict();
+        for interaction in fixture.interactions {
+            provider.push_script(&interaction.feature, Some(interaction.input_hash), interaction.response);
+        }
+        provider
+    }
+
+    pub fn load_fixture(path: &Path) -> Result<Self> {
+        Ok(Self::from_fixture(Fixture::load(path)?))
+    }
+
+    /// Requests received so far, oldest first
+    pub fn calls(&self) -> Vec<MockCall> {
+        self.calls.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    fn push_script(&self, feature: &str, hash: Option<String>, response: ScriptedResponse) {
+        self.scripts
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .entry((feature.to_string(), hash))
+            .or_default()
+            .push_back(response);
+    }
+
+    /// The scripted response for a request; `None` when nothing is scripted
+    /// and the provider is not strict
+    fn next_response(&self, feature: &str, input: &str, streamed: bool) -> Result<Option<ScriptedResponse>> {
+        let hash = input_hash(input);
+        self.calls.lock().unwrap_or_else(|e| e.into_inner()).push(MockCall {
+            feature: feature.to_string(),
+            input: input.to_string(),
+            input_hash: hash.clone(),
+            streamed,
+        });
+
+        let mut scripts = self.scripts.lock().unwrap_or_else(|e| e.into_inner());
+        let key = [Some(hash.clone()), None]
+            .into_iter()
+            .map(|h| (feature.to_string(), h))
+            .find(|key| scripts.get(key).is_some_and(|queue| !queue.is_empty()));
+
+        let Some(queue) = key.and_then(|key| scripts.get_mut(&key)) else {
+            if self.strict {
+                return Err(StoryWeaverError::ai_provider(
+                    self.name.clone(),
+                    format!("no recorded response for {} request {}", feature, hash),
+                ));
+            }
+            return Ok(None);
+        };
+
+        Ok(if queue.len() > 1 { queue.pop_front() } else { queue.front().cloned() })
+    }
+
+    fn echo(&self, feature: &str, input: &str) -> ScriptedResponse {
+        let preview: String = input.chars().take(60).collect();
+        ScriptedResponse::text(&format!("[{} {}] {}", self.name, feature, preview))
+    }
+
+    async fn respond(&self, feature: &str, input: &str) -> Result<String> {
+        let response = match self.next_response(feature, input, false)? {
+            Some(response) => response,
+            None => self.echo(feature, input),
+        };
+        sleep(Duration::from_millis(response.latency_ms)).await;
+        match &response.error {
+            Some(error) => Err(error.to_error(&self.name)),
+            None => Ok(response.text),
+        }
+    }
+
+    async fn respond_stream(&self, feature: &str, input: &str) -> Result<TextChunkStream> {
+        let response = match self.next_response(feature, input, true)? {
+            Some(response) => response,
+            None => self.echo(feature, input),
+        };
+        sleep(Duration::from_millis(response.latency_ms)).await;
+
+        // An error with no chunks fails the request itself rather than the stream
+        let error = match response.error.as_ref().map(|e| e.to_error(&self.name)) {
+            Some(error) if response.chunks.is_empty() => return Err(error),
+            error => error,
+        };
+
+        let chunk_delay = Duration::from_millis(response.chunk_delay_ms);
+        let chunks = stream::iter(response.stream_chunks()).then(move |chunk| async move {
+            if !chunk_delay.is_zero() {
+                sleep(chunk_delay).await;
+            }
+            Ok(chunk)
+        });
+        Ok(chunks.chain(stream::iter(error.map(Err))).boxed())
+    }
+
+    async fn respond_list(&self, feature: &str, input: &str) -> Result<Vec<String>> {
+        Ok(parse_list(&self.respond(feature, input).await?))
+    }
+}
+
+impl Default for MockProvider {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Split a numbered, bulleted or comma-separated list into items
+fn parse_list(text: &str) -> Vec<String> {
+    let lines: Vec<&str> = text.lines().filter(|l| !l.trim().is_empty()).collect();
+    let items: Vec<&str> = if lines.len() == 1 { lines[0].split(',').collect() } else { lines };
+    items
+        .into_iter()
+        .map(|item| item.trim().trim_start_matches(|c: char| c.is_ascii_digit() || matches!(c, '.' | ')' | '-' | '*')).trim())
+        .filter(|item| !item.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Request inputs are hashed per feature; these build the input for the
+/// methods that take more than one string
+fn quick_edit_input(text: &str, instruction: &str) -> String {
+    format!("{}\n{}", instruction, text)
+}
+
+fn rewrite_input(text: &str, style: &RewriteStyle) -> String {
+    format!("{:?}\n{}", style, text)
+}
+
+#[async_trait]
+impl AIProvider for MockProvider {
+    async fn generate_text(&self, prompt: &str, _context: &AIContext) -> Result<String> {
+        self.respond(WritingFeature::Write.route_key(), prompt).await
+    }
+
+    async fn generate_text_stream(&self, prompt: &str, _context: &AIContext) -> Result<TextChunkStream> {
+        self.respond_stream(WritingFeature::Write.route_key(), prompt).await
+    }
+
+    async fn rewrite_text(&self, text: &str, style: &RewriteStyle) -> Result<String> {
+        self.respond(WritingFeature::Rewrite(style.clone()).route_key(), &rewrite_input(text, style)).await
+    }
+
+    async fn rewrite_text_stream(&self, text: &str, style: &RewriteStyle) -> Result<TextChunkStream> {
+        self.respond_stream(WritingFeature::Rewrite(style.clone()).route_key(), &rewrite_input(text, style)).await
+    }
+
+    async fn expand_text(&self, text: &str, _context: &AIContext) -> Result<String> {
+        self.respond(WritingFeature::Expand.route_key(), text).await
+    }
+
+    async fn expand_text_stream(&self, text: &str, _context: &AIContext) -> Result<TextChunkStream> {
+        self.respond_stream(WritingFeature::Expand.route_key(), text).await
+    }
+
+    async fn describe_scene(&self, description: &str, _context: &AIContext) -> Result<String> {
+        self.respond(WritingFeature::Describe.route_key(), description).await
+    }
+
+    async fn describe_scene_stream(&self, description: &str, _context: &AIContext) -> Result<TextChunkStream> {
+        self.respond_stream(WritingFeature::Describe.route_key(), description).await
+    }
+
+    async fn brainstorm(&self, topic: &str, _context: &AIContext) -> Result<Vec<String>> {
+        self.respond_list(WritingFeature::Brainstorm.route_key(), topic).await
+    }
+
+    async fn related_words(&self, word: &str, _context: &AIContext) -> Result<Vec<String>> {
+        self.respond_list(WritingFeature::RelatedWords.route_key(), word).await
+    }
+
+    async fn quick_edit(&self, text: &str, instruction: &str) -> Result<String> {
+        self.respond(WritingFeature::QuickEdit.route_key(), &quick_edit_input(text, instruction)).await
+    }
+
+    async fn quick_chat(&self, message: &str, _context: &AIContext) -> Result<String> {
+        self.respond(WritingFeature::QuickChat.route_key(), message).await
+    }
+
+    async fn quick_chat_stream(&self, message: &str, _context: &AIContext) -> Result<TextChunkStream> {
+        self.respond_stream(WritingFeature::QuickChat.route_key(), message).await
+    }
+
+    async fn generate_image(&self, prompt: &str) -> Result<String> {
+        self.respond(WritingFeature::Visualize.route_key(), prompt).await
+    }
+
+    async fn generate_embedding(&self, text: &str) -> Result<Vec<f32>> {
+        // Unscripted embeddings are derived from the text rather than echoed
+        let Some(response) = self.next_response(EMBEDDING_FEATURE, text, false)? else {
+            return Ok(mock_embedding(text));
+        };
+        sleep(Duration::from_millis(response.latency_ms)).await;
+        if let Some(error) = &response.error {
+            return Err(error.to_error(&self.name));
+        }
+        serde_json::from_str(&response.text)
+            .map_err(|e| StoryWeaverError::deserialization(format!("Invalid scripted embedding: {}", e)))
+    }
+
+    fn supports_streaming(&self) -> bool {
+        true
+    }
+
+    fn supports_image_generation(&self) -> bool {
+        true
+    }
+
+    fn get_context_window(&self) -> usize {
+        self.context_window
+    }
+
+    fn get_model_name(&self) -> &str {
+        &self.model
+    }
+
+    fn get_provider_name(&self) -> &str {
+        &self.name
+    }
+}
+
+/// Wraps a real provider and records every response into a `Fixture`
+pub struct RecordingProvider {
+    inner: Arc<dyn AIProvider>,
+    fixture: Arc<Mutex<Fixture>>,
+}
+
+impl RecordingProvider {
+    pub fn new(inner: Arc<dyn AIProvider>) -> Self {
+        let fixture = Fixture {
+            provider: inner.get_provider_name().to_string(),
+            model: inner.get_model_name().to_string(),
+            interactions: Vec::new(),
+        };
+        Self {
+            inner,
+            fixture: Arc::new(Mutex::new(fixture)),
+        }
+    }
+
+    /// Everything recorded so far
+    pub fn fixture(&self) -> Fixture {
+        self.fixture.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        self.fixture().save(path)
+    }
+
+    async fn record<T, F>(&self, feature: &str, input: &str, call: F, to_text: impl Fn(&T) -> String) -> Result<T>
+    where
+        F: std::future::Future<Output = Result<T>>,
+    {
+        let started = Instant::now();
+        let result = call.await;
+        let response = ScriptedResponse {
+            text: result.as_ref().map(&to_text).unwrap_or_default(),
+            latency_ms: started.elapsed().as_millis() as u64,
+            error: result.as_ref().err().map(MockError::from_error),
+            ..ScriptedResponse::default()
+        };
+        push_interaction(&self.fixture, feature, input, response);
+        result
+    }
+
+    async fn record_stream<F>(&self, feature: &str, input: &str, call: F) -> Result<TextChunkStream>
+    where
+        F: std::future::Future<Output = Result<TextChunkStream>>,
+    {
+        let started = Instant::now();
+        let chunks = match call.await {
+            Ok(chunks) => chunks,
+            Err(e) => {
+                let response = ScriptedResponse {
+                    latency_ms: started.elapsed().as_millis() as u64,
+                    error: Some(MockError::from_error(&e)),
+                    ..ScriptedResponse::default()
+                };
+                push_interaction(&self.fixture, feature, input, response);
+                return Err(e);
+            }
+        };
+
+        let latency_ms = started.elapsed().as_millis() as u64;
+        let key = (feature.to_string(), input.to_string());
+        let fixture = Some(self.fixture.clone());
+
+        // The interaction is recorded once the stream ends or fails
+        Ok(stream::unfold((chunks, Vec::new(), fixture), move |(mut chunks, mut seen, fixture)| {
+            let key = key.clone();
+            async move {
+                let fixture = fixture?;
+                match chunks.next().await {
+                    Some(Ok(delta)) => {
+                        seen.push(delta.clone());
+                        Some((Ok(delta), (chunks, seen, Some(fixture))))
+                    }
+                    Some(Err(e)) => {
+                        let response = ScriptedResponse {
+                            text: seen.concat(),
+                            chunks: seen.clone(),
+                            latency_ms,
+                            error: Some(MockError::from_error(&e)),
+                            ..ScriptedResponse::default()
+                        };
+                        push_interaction(&fixture, &key.0, &key.1, response);
+                        Some((Err(e), (chunks, seen, None)))
+                    }
+                    None => {
+                        let response = ScriptedResponse {
+                            text: seen.concat(),
+                            chunks: seen,
+                            latency_ms,
+                            ..ScriptedResponse::default()
+                        };
+                        push_interaction(&fixture, &key.0, &key.1, response);
+                        None
+                    }
+                }
+            }
+        })
+        .boxed())
+    }
+}
+
+fn push_interaction(fixture: &Mutex<Fixture>, feature: &str, input: &str, response: ScriptedResponse) {
+    fixture.lock().unwrap_or_else(|e| e.into_inner()).interactions.push(RecordedInteraction {
+        feature: feature.to_string(),
+        input_hash: input_hash(input),
+        response,
+    });
+}
+
+fn list_text(items: &[String]) -> String {
+    items.join("\n")
+}
+
+#[async_trait]
+impl AIProvider for RecordingProvider {
+    async fn generate_text(&self, prompt: &str, context: &AIContext) -> Result<String> {
+        self.record(WritingFeature::Write.route_key(), prompt, self.inner.generate_text(prompt, context), String::clone).await
+    }
+
+    async fn generate_text_stream(&self, prompt: &str, context: &AIContext) -> Result<TextChunkStream> {
+        self.record_stream(WritingFeature::Write.route_key(), prompt, self.inner.generate_text_stream(prompt, context)).await
+    }
+
+    async fn rewrite_text(&self, text: &str, style: &RewriteStyle) -> Result<String> {
+        let feature = WritingFeature::Rewrite(style.clone());
+        self.record(feature.route_key(), &rewrite_input(text, style), self.inner.rewrite_text(text, style), String::clone).await
+    }
+
+    async fn rewrite_text_stream(&self, text: &str, style: &RewriteStyle) -> Result<TextChunkStream> {
+        let feature = WritingFeature::Rewrite(style.clone());
+        self.record_stream(feature.route_key(), &rewrite_input(text, style), self.inner.rewrite_text_stream(text, style)).await
+    }
+
+    async fn expand_text(&self, text: &str, context: &AIContext) -> Result<String> {
+        self.record(WritingFeature::Expand.route_key(), text, self.inner.expand_text(text, context), String::clone).await
+    }
+
+    async fn expand_text_stream(&self, text: &str, context: &AIContext) -> Result<TextChunkStream> {
+        self.record_stream(WritingFeature::Expand.route_key(), text, self.inner.expand_text_stream(text, context)).await
+    }
+
+    async fn describe_scene(&self, description: &str, context: &AIContext) -> Result<String> {
+        self.record(WritingFeature::Describe.route_key(), description, self.inner.describe_scene(description, context), String::clone).await
+    }
+
+    async fn describe_scene_stream(&self, description: &str, context: &AIContext) -> Result<TextChunkStream> {
+        self.record_stream(WritingFeature::Describe.route_key(), description, self.inner.describe_scene_stream(description, context)).await
+    }
+
+    async fn brainstorm(&self, topic: &str, context: &AIContext) -> Result<Vec<String>> {
+        self.record(WritingFeature::Brainstorm.route_key(), topic, self.inner.brainstorm(topic, context), |items: &Vec<String>| list_text(items)).await
+    }
+
+    async fn related_words(&self, word: &str, context: &AIContext) -> Result<Vec<String>> {
+        self.record(WritingFeature::RelatedWords.route_key(), word, self.inner.related_words(word, context), |items: &Vec<String>| list_text(items)).await
+    }
+
+    async fn quick_edit(&self, text: &str, instruction: &str) -> Result<String> {
+        let input = quick_edit_input(text, instruction);
+        self.record(WritingFeature::QuickEdit.route_key(), &input, self.inner.quick_edit(text, instruction), String::clone).await
+    }
+
+    async fn quick_chat(&self, message: &str, context: &AIContext) -> Result<String> {
+        self.record(WritingFeature::QuickChat.route_key(), message, self.inner.quick_chat(message, context), String::clone).await
+    }
+
+    async fn quick_chat_stream(&self, message: &str, context: &AIContext) -> Result<TextChunkStream> {
+        self.record_stream(WritingFeature::QuickChat.route_key(), message, self.inner.quick_chat_stream(message, context)).await
+    }
+
+    async fn generate_image(&self, prompt: &str) -> Result<String> {
+        self.record(WritingFeature::Visualize.route_key(), prompt, self.inner.generate_image(prompt), String::clone).await
+    }
+
+    async fn generate_embedding(&self, text: &str) -> Result<Vec<f32>> {
+        self.record(EMBEDDING_FEATURE, text, self.inner.generate_embedding(text), |embedding: &Vec<f32>| serde_json::to_string(embedding).unwrap_or_default()).await
+    }
+
+    async fn generate_json(&self, prompt: &str, schema: &JsonSchema, context: &AIContext) -> Result<String> {
+        // Keyed like the prompted fallback, which is what a replaying mock answers
+        let input = json_prompt(prompt, schema);
+        self.record(WritingFeature::Write.route_key(), &input, self.inner.generate_json(prompt, schema, context), String::clone).await
+    }
+
+    fn supports_streaming(&self) -> bool {
+        self.inner.supports_streaming()
+    }
+
+    fn supports_image_generation(&self) -> bool {
+        self.inner.supports_image_generation()
+    }
+
+    fn get_context_window(&self) -> usize {
+        self.inner.get_context_window()
+    }
+
+    fn get_model_name(&self) -> &str {
+        self.inner.get_model_name()
+    }
+
+    fn get_provider_name(&self) -> &str {
+        self.inner.get_provider_name()
+    }
+
+    fn health(&self) -> super::ProviderHealth {
+        self.inner.health()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_list_handles_numbered_and_comma_lists() {
+        assert_eq!(parse_list("1. A storm\n2) A wreck\n- A rescue"), vec!["A storm", "A wreck", "A rescue"]);
+        assert_eq!(parse_list("gale, squall, tempest"), vec!["gale", "squall", "tempest"]);
+    }
+
+    #[test]
+    fn test_mock_embedding_is_deterministic_and_normalized() {
+        let a = mock_embedding("The harbor at dawn");
+        assert_eq!(a, mock_embedding("the HARBOR at dawn"));
+        let norm: f32 = a.iter().map(|v| v * v).sum::<f32>().sqrt();
+        assert!((norm - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_input_hash_is_stable() {
+        assert_eq!(input_hash("prompt"), input_hash("prompt"));
+        assert_ne!(input_hash("prompt"), input_hash("prompt "));
+        assert_eq!(input_hash("prompt").len(), 32);
+    }
+}
@@ -14,13 +14,45 @@ pub mod advanced_ai_manager;
 pub mod token_counter;
 pub mod cache;
 pub mod streaming_optimizer;
+pub mod routing;
+pub mod tokenizer;
+pub mod resilience;
+pub mod mock_provider;
+pub mod cancellation;
+pub mod local_embedding;
+pub mod semantic_index;
+pub mod structured;
+pub mod tools;
+pub mod story_bible_tools;
+pub mod chat_memory;
+pub mod prompt_templates;
+pub mod chapter_draft;
+pub mod story_summary;
+pub mod stylometry;
+pub mod story_bible_extraction;
 
 // Re-export commonly used types
 pub use ai_history::{AIInteraction, AIHistoryManager, AIInteractionBuilder};
+pub use streaming::{TextChunkStream, StreamingEnvelope};
+pub use routing::{RoutingTable, RouteTarget, RouteRequest, RouteTaken, RouteAttempt, BudgetRequest, EMBEDDING_ROUTE_KEY};
+pub use resilience::{CircuitState, ProviderHealth, ResiliencePolicy};
+pub use cancellation::CancellationToken;
+pub use local_embedding::{cosine_similarity, Embedding, LocalEmbeddingProvider, LOCAL_EMBEDDING_MODEL};
+pub use mock_provider::{MockProvider, ReplayProvider, RecordingProvider, ScriptedResponse, Fixture};
+pub use structured::{JsonSchema, StructuredGeneration};
+pub use tools::{ToolCall, ToolCallRecord, ToolDefinition, ToolExecutor, ToolLoopResult, ToolMessage, ToolTurn, MAX_TOOL_ROUNDS};
+pub use story_bible_tools::StoryBibleTools;
+pub use prompt_templates::{render_prompt, PromptScope, RenderedPrompt, TemplateTrace, TemplateUsage};
+pub use chapter_draft::{create_chapter_draft, run_chapter_draft, SCENE_BREAK};
+pub use story_summary::{refresh_story_summaries, summary_context, SummaryRefresh};
+pub use stylometry::{project_fingerprint, StyleDrift, StyleFingerprint, StyleProfile};
+pub use story_bible_extraction::{create_story_bible_extraction, name_similarity, run_story_bible_extraction};
 
 use async_trait::async_trait;
+use futures_util::StreamExt;
 use std::collections::HashMap;
-use std::sync::Arc;
+use std::future::Future;
+use std::sync::{Arc, RwLock};
 use crate::error::{Result, StoryWeaverError};
 use crate::database::models::{Character, Location, PlotThread};
 
@@ -51,19 +83,25 @@ pub struct AIContext {
     // Feature-specific context
     pub feature_type: Option<WritingFeature>,
     pub feature_options: Option<HashMap<String, String>>,
+    pub prose_mode: Option<String>,      // Prose mode name, for prompt template overrides
     
     // Additional metadata
     pub word_count_target: Option<usize>,
     pub genre: Option<String>,
     pub key_details: Option<Vec<String>>, // Important details to include
+
+    // Lets the caller abort the request while it is in flight
+    pub cancellation: Option<CancellationToken>,
 }
 
-/// Streaming text response from AI providers
+/// Accumulated text of a streamed response
 #[derive(Debug, Default, Clone)]
 pub struct TextStream {
     pub content: String,
     pub is_complete: bool,
     pub token_count: usize,
+    /// Model whose tokenizer counts `token_count`
+    pub model: Option<String>,
 }
 
 impl TextStream {
@@ -71,14 +109,32 @@ impl TextStream {
         Self::default()
     }
 
+    pub fn for_model(model: &str) -> Self {
+        Self {
+            model: Some(model.to_string()),
+            ..Self::default()
+        }
+    }
+
     pub fn append(&mut self, text: &str) {
         self.content.push_str(text);
-        // Rough estimate of tokens (characters / 4)
-        self.token_count = self.content.len() / 4;
+        // Counting each delta keeps appends cheap; `complete` recounts the whole text
+        self.token_count += tokenizer::count_tokens(self.model.as_deref().unwrap_or_default(), text);
     }
 
     pub fn complete(&mut self) {
         self.is_complete = true;
+        self.token_count = tokenizer::count_tokens(self.model.as_deref().unwrap_or_default(), &self.content);
+    }
+
+    /// Drain a chunk stream into a completed `TextStream`
+    pub async fn from_chunks(mut chunks: TextChunkStream) -> Result<Self> {
+        let mut text = Self::new();
+        while let Some(delta) = chunks.next().await {
+            text.append(&delta?);
+        }
+        text.complete();
+        Ok(text)
     }
 }
 
@@ -114,19 +170,19 @@ pub enum WritingFeature {
 pub trait AIProvider: Send + Sync {
     // Basic text generation
     async fn generate_text(&self, prompt: &str, context: &AIContext) -> Result<String>;
-    async fn generate_text_stream(&self, prompt: &str, context: &AIContext) -> Result<TextStream>;
+    async fn generate_text_stream(&self, prompt: &str, context: &AIContext) -> Result<TextChunkStream>;
     
     // Rewrite functionality
     async fn rewrite_text(&self, text: &str, style: &RewriteStyle) -> Result<String>;
-    async fn rewrite_text_stream(&self, text: &str, style: &RewriteStyle) -> Result<TextStream>;
+    async fn rewrite_text_stream(&self, text: &str, style: &RewriteStyle) -> Result<TextChunkStream>;
     
     // Expand functionality - add more detail to text
     async fn expand_text(&self, text: &str, context: &AIContext) -> Result<String>;
-    async fn expand_text_stream(&self, text: &str, context: &AIContext) -> Result<TextStream>;
+    async fn expand_text_stream(&self, text: &str, context: &AIContext) -> Result<TextChunkStream>;
     
     // Describe functionality - generate vivid descriptions
     async fn describe_scene(&self, description: &str, context: &AIContext) -> Result<String>;
-    async fn describe_scene_stream(&self, description: &str, context: &AIContext) -> Result<TextStream>;
+    async fn describe_scene_stream(&self, description: &str, context: &AIContext) -> Result<TextChunkStream>;
     
     // Brainstorm functionality - generate ideas
     async fn brainstorm(&self, topic: &str, context: &AIContext) -> Result<Vec<String>>;
@@ -137,13 +193,28 @@ pub trait AIProvider: Send + Sync {
     // Quick tools
     async fn quick_edit(&self, text: &str, instruction: &str) -> Result<String>;
     async fn quick_chat(&self, message: &str, context: &AIContext) -> Result<String>;
-    async fn quick_chat_stream(&self, message: &str, context: &AIContext) -> Result<TextStream>;
+    async fn quick_chat_stream(&self, message: &str, context: &AIContext) -> Result<TextChunkStream>;
     
     // Image generation for Visualize feature
     async fn generate_image(&self, prompt: &str) -> Result<String>; // Returns URL or base64 image
     
     // Embeddings for semantic search and context relevance
     async fn generate_embedding(&self, text: &str) -> Result<Vec<f32>>;
+
+    /// JSON text meant to match `schema`. Providers with a native structured
+    /// output mode override this; the default asks for JSON in the prompt.
+    /// `StructuredGeneration::generate_structured` validates the reply.
+    async fn generate_json(&self, prompt: &str, schema: &JsonSchema, context: &AIContext) -> Result<String> {
+        self.generate_text(&structured::json_prompt(prompt, schema), context).await
+    }
+
+    /// One model turn of a tool-calling conversation; `tools::run_tool_loop`
+    /// drives the whole exchange. Providers with native function calling
+    /// override this; the default uses the prompted protocol in `tools`.
+    async fn generate_with_tools(&self, messages: &[ToolMessage], tools: &[ToolDefinition], context: &AIContext) -> Result<ToolTurn> {
+        let reply = self.generate_text(&tools::prompted_tool_request(messages, tools), context).await?;
+        Ok(tools::parse_prompted_turn(&reply, !tools.is_empty()))
+    }
     
     // Provider information
     fn supports_streaming(&self) -> bool;
@@ -151,9 +222,14 @@ pub trait AIProvider: Send + Sync {
     fn get_context_window(&self) -> usize;
     fn get_model_name(&self) -> &str;
     fn get_provider_name(&self) -> &str;
+
+    /// Circuit breaker view of the provider; providers without one are always healthy
+    fn health(&self) -> ProviderHealth {
+        ProviderHealth::healthy()
+    }
 }
 
-pub use openai::OpenAIProvider;
+pub use openai::{OpenAIProvider, OpenAICompatibleConfig};
 pub use claude::ClaudeProvider;
 pub use gemini::GeminiProvider;
 
@@ -161,193 +237,556 @@ pub use gemini::GeminiProvider;
 pub use prose_modes::{ProseMode, ProseModelManager, GenerationSettings};
 pub use saliency_engine::{SaliencyEngine, SaliencyContext, SelectedElements};
 pub use visualize::{VisualizeEngine, VisualizeRequest, GeneratedImage, ImageResolution};
-pub use brainstorm::{BrainstormEngine, BrainstormSession, BrainstormRequest, BrainstormIdea, BrainstormCategory};
+pub use brainstorm::{
+    BrainstormCategory, BrainstormEngine, BrainstormIdea, BrainstormRequest, BrainstormSession, DuplicateIdea, IdeaOrigin,
+    IdeaTheme, StoryBibleExport, StoryBibleKind, StoryBibleLink,
+};
 pub use advanced_ai_manager::{AdvancedAIManager, AdvancedGenerationRequest, AdvancedGenerationResult, StyleExample, CreditUsage};
-pub use token_counter::{TokenCounter, TokenUsage, CostEstimate, TokenCountResult};
+pub use advanced_ai_manager::{
+    BudgetAlert, BudgetDecision, BudgetStatus, CreditTracker, Metered, PendingSpend, PreflightCheck, SpendEstimate,
+    SpendForecast,
+};
+pub use token_counter::{
+    pricing, recompute_history_costs, reload_pricing, set_pricing, BillableUsage, CostEstimate, HistoryCostRecompute,
+    TokenCountResult, TokenCounter, TokenUsage, UsageMeter,
+};
+pub use tokenizer::{Tokenizer, TokenizerFamily};
 
 pub struct AIProviderManager {
-    providers: HashMap<String, Arc<dyn AIProvider>>,
+    // Self-hosted providers are saved and deleted from settings while the manager is shared
+    providers: RwLock<HashMap<String, Arc<dyn AIProvider>>>,
     default_provider: Option<String>,
-    // Add rate limiter and other fields as needed
+    // The default provider as set at startup, which the `AIProvider` accessors describe
+    default: Option<Arc<dyn AIProvider>>,
+    // Routing can be changed at runtime from settings, while the manager is shared
+    routing: RwLock<RoutingTable>,
+    // Serves embeddings when no cloud embedding route is configured or it fails
+    local_embeddings: LocalEmbeddingProvider,
+    // Where spend is recorded and checked against the AI budgets; unset, nothing is enforced
+    budget_pool: RwLock<Option<crate::database::DbPool>>,
 }
 
 impl AIProviderManager {
     pub fn new() -> Self {
         Self {
-            providers: HashMap::new(),
+            providers: RwLock::new(HashMap::new()),
             default_provider: None,
+            default: None,
+            routing: RwLock::new(RoutingTable::default()),
+            local_embeddings: LocalEmbeddingProvider::new(),
+            budget_pool: RwLock::new(None),
         }
     }
 
     pub fn register_provider(&mut self, name: String, provider: Arc<dyn AIProvider>) {
-        self.providers.insert(name, provider);
+        if self.default_provider.as_deref() == Some(name.as_str()) {
+            self.default = Some(provider.clone());
+        }
+        self.providers.get_mut().unwrap_or_else(|e| e.into_inner()).insert(name, provider);
     }
 
     pub fn set_default_provider(&mut self, name: String) {
+        self.default = self.get_provider(&name);
         self.default_provider = Some(name);
     }
 
-    pub fn get_provider(&self, name: &str) -> Option<&Arc<dyn AIProvider>> {
-        self.providers.get(name)
+    /// Register `provider` under `name` while the manager is in use, replacing
+    /// any provider already registered there
+    pub fn replace_provider(&self, name: String, provider: Arc<dyn AIProvider>) {
+        self.providers.write().unwrap_or_else(|e| e.into_inner()).insert(name, provider);
+    }
+
+    /// Stop serving requests from the provider registered under `name`.
+    /// Returns whether one was registered.
+    pub fn unregister_provider(&self, name: &str) -> bool {
+        self.providers.write().unwrap_or_else(|e| e.into_inner()).remove(name).is_some()
+    }
+
+    fn registered(&self) -> std::sync::RwLockReadGuard<'_, HashMap<String, Arc<dyn AIProvider>>> {
+        self.providers.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn get_provider(&self, name: &str) -> Option<Arc<dyn AIProvider>> {
+        self.registered().get(name).cloned()
     }
 
-    pub fn get_default_provider(&self) -> Option<&Arc<dyn AIProvider>> {
+    pub fn get_default_provider(&self) -> Option<Arc<dyn AIProvider>> {
         match &self.default_provider {
-            Some(name) => self.providers.get(name),
+            Some(name) => self.get_provider(name),
             None => None,
         }
     }
     
     pub fn list_providers(&self) -> Vec<String> {
-        self.providers.keys().cloned().collect()
+        self.registered().keys().cloned().collect()
     }
     
     pub fn get_default_provider_name(&self) -> Option<String> {
         self.default_provider.clone()
     }
+
+    /// Register a self-hosted OpenAI-compatible endpoint under its configured name
+    pub fn register_openai_compatible(&mut self, config: OpenAICompatibleConfig) {
+        let name = config.name.clone();
+        self.register_provider(name, Arc::new(config.into_provider()));
+    }
+
+    /// Register a self-hosted OpenAI-compatible endpoint while the manager is
+    /// in use. A provider saved again under the same name is replaced, so new
+    /// settings and API keys apply to the next request.
+    pub fn replace_openai_compatible(&self, config: OpenAICompatibleConfig) {
+        let name = config.name.clone();
+        self.replace_provider(name, Arc::new(config.into_provider()));
+    }
+
+    /// Register every active OpenAI-compatible provider persisted through
+    /// `AIProviderOps`, pulling optional API keys from the OS keychain.
+    /// Returns the number of providers registered.
+    pub async fn load_openai_compatible_providers(&mut self, pool: &crate::database::DbPool) -> Result<usize> {
+        let configs = crate::database::operations::AIProviderOps::list_openai_compatible(pool).await?;
+        let count = configs.len();
+
+        for mut config in configs {
+            let key_ref = crate::security::api_keys::ApiProvider::Custom(config.name.clone());
+            config.api_key = crate::security::api_keys::read_stored_api_key(&key_ref).unwrap_or(None);
+            self.register_openai_compatible(config);
+        }
+
+        Ok(count)
+    }
 }
 
-#[async_trait]
-impl AIProvider for AIProviderManager {
-    async fn generate_text(&self, prompt: &str, context: &AIContext) -> Result<String> {
-        match self.get_default_provider() {
-            Some(provider) => provider.generate_text(prompt, context).await,
-            None => Err(StoryWeaverError::NotSupported { operation: "No default AI provider configured".to_string() }),
+impl AIProviderManager {
+    pub fn routing_table(&self) -> RoutingTable {
+        self.routing.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    pub fn set_routing_table(&self, table: RoutingTable) {
+        *self.routing.write().unwrap_or_else(|e| e.into_inner()) = table;
+    }
+
+    /// Record the spend of routed requests in `pool` and refuse the ones
+    /// that would go over an AI budget
+    pub fn enable_budgets(&self, pool: crate::database::DbPool) {
+        *self.budget_pool.write().unwrap_or_else(|e| e.into_inner()) = Some(pool);
+    }
+
+    fn budget_pool(&self) -> Option<crate::database::DbPool> {
+        self.budget_pool.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Load the routing table saved in app settings, if any
+    pub async fn load_routing_table(&self, pool: &crate::database::DbPool) -> Result<()> {
+        let setting = crate::database::operations::AppSettingsOps::get_setting(pool, routing::ROUTING_TABLE_SETTING_KEY).await?;
+        if let Some(setting) = setting {
+            let table: RoutingTable = serde_json::from_str(&setting.value)
+                .map_err(|e| StoryWeaverError::deserialization(format!("Invalid AI routing table: {}", e)))?;
+            self.set_routing_table(table);
         }
+        Ok(())
     }
 
-    async fn generate_text_stream(&self, prompt: &str, context: &AIContext) -> Result<TextStream> {
-        match self.get_default_provider() {
-            Some(provider) => provider.generate_text_stream(prompt, context).await,
-            None => Err(StoryWeaverError::NotSupported { operation: "No default AI provider configured".to_string() }),
+    /// Providers to try for a request, in order: plugin route (or the plugin's
+    /// own `ai_model`), feature route, fallback chain, then the default provider.
+    /// Targets that aren't registered are skipped; each provider appears once.
+    pub fn resolve_route(&self, request: &RouteRequest) -> Vec<(String, Arc<dyn AIProvider>)> {
+        let table = self.routing_table();
+        let providers = self.registered();
+        let mut targets: Vec<RouteTarget> = Vec::new();
+        let mut model_matches: Vec<String> = Vec::new();
+
+        if let Some(plugin_id) = request.plugin_id {
+            if let Some(route) = table.plugins.get(&plugin_id) {
+                targets.extend(route.iter().cloned());
+            } else if let Some(ai_model) = &request.plugin_model {
+                match RouteTarget::parse(ai_model) {
+                    Some(target) => targets.push(target),
+                    None => {
+                        // A bare model name: use whichever providers serve it
+                        let mut keys: Vec<String> = providers.iter()
+                            .filter(|(key, provider)| *key == ai_model || provider.get_model_name() == ai_model)
+                            .map(|(key, _)| key.clone())
+                            .collect();
+                        keys.sort();
+                        model_matches = keys;
+                    }
+                }
+            }
         }
+
+        if let Some(feature) = &request.feature {
+            if let Some(route) = table.features.get(feature.route_key()) {
+                targets.extend(route.iter().cloned());
+            }
+        }
+        targets.extend(table.fallback_chain.iter().cloned());
+
+        let mut keys = model_matches;
+        for target in &targets {
+            if let Some(key) = target.registry_keys().into_iter().find(|k| providers.contains_key(k)) {
+                keys.push(key);
+            }
+        }
+        if let Some(default) = &self.default_provider {
+            keys.push(default.clone());
+        }
+
+        let mut chain: Vec<(String, Arc<dyn AIProvider>)> = Vec::new();
+        for key in keys {
+            if chain.iter().any(|(existing, _)| *existing == key) {
+                continue;
+            }
+            if let Some(provider) = providers.get(&key) {
+                chain.push((key, provider.clone()));
+            }
+        }
+
+        // Providers with an open circuit would fail fast; keep them only as a last resort
+        chain.sort_by_key(|(_, provider)| !provider.health().is_healthy());
+        chain
     }
 
-    async fn rewrite_text(&self, text: &str, style: &RewriteStyle) -> Result<String> {
-        match self.get_default_provider() {
-            Some(provider) => provider.rewrite_text(text, style).await,
-            None => Err(StoryWeaverError::NotSupported { operation: "No default AI provider configured".to_string() }),
+    /// Circuit breaker health of every registered provider
+    pub fn provider_health(&self) -> HashMap<String, ProviderHealth> {
+        self.registered()
+            .iter()
+            .map(|(key, provider)| (key.clone(), provider.health()))
+            .collect()
+    }
+
+    /// Run `operation` against the resolved route, failing over to the next
+    /// provider on rate-limit, auth, server and connection errors. Returns the
+    /// result together with the route actually taken.
+    ///
+    /// Requests carrying a `BudgetRequest` are checked against the AI budgets
+    /// before each provider is tried; a provider whose own budget is spent is
+    /// skipped, any other budget refuses the request. The spend is recorded
+    /// once the result is complete, from the usage the provider reported or
+    /// the tokens actually generated; a stream that is stopped early records
+    /// what it generated so far.
+    pub async fn execute_routed<T, F, Fut>(&self, request: &RouteRequest, operation: F) -> Result<(T, RouteTaken)>
+    where
+        T: Metered,
+        F: Fn(Arc<dyn AIProvider>) -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let chain = self.resolve_route(request);
+        if chain.is_empty() {
+            return Err(StoryWeaverError::NotSupported { operation: "No default AI provider configured".to_string() });
+        }
+
+        let mut route = RouteTaken {
+            feature: request.feature.as_ref().map(|f| f.route_key().to_string()),
+            plugin_id: request.plugin_id,
+            attempts: Vec::new(),
+            prompt_template: None,
+        };
+        let last = chain.len() - 1;
+        let budget_pool = request.budget.as_ref().and_then(|_| self.budget_pool());
+
+        for (index, (key, provider)) in chain.into_iter().enumerate() {
+            let model = provider.get_model_name().to_string();
+            let estimate = match (&budget_pool, &request.budget) {
+                (Some(pool), Some(budget)) => {
+                    let estimate = SpendEstimate::new(budget.project_id.clone(), &key, &model, budget.input_tokens, budget.output_tokens);
+                    match CreditTracker::preflight(pool, estimate).await {
+                        Ok(check) if index < last && check.provider_limited() => {
+                            let error = check.into_result().err().map(|e| e.to_string());
+                            tracing::warn!("AI provider {} is over budget, trying next in chain", key);
+                            route.attempts.push(RouteAttempt { provider: key, model, error });
+                            continue;
+                        }
+                        Ok(check) => Some(check.into_result()?),
+                        // Budgets are a safeguard; a failing check shouldn't stop writing
+                        Err(e) => {
+                            tracing::warn!("Failed to check AI budgets: {}", e);
+                            None
+                        }
+                    }
+                }
+                _ => None,
+            };
+            let meter = UsageMeter::new();
+            let templates = TemplateTrace::new();
+            let attempt = meter.measure(templates.trace(operation(provider)));
+            let result = match &request.cancellation {
+                Some(token) => token.run(&key, attempt).await,
+                None => attempt.await,
+            };
+            match result {
+                Ok(value) => {
+                    let value = match (&budget_pool, estimate) {
+                        (Some(pool), Some(estimate)) => {
+                            let operation_type = route.feature.as_deref().unwrap_or("plugin");
+                            value.record_spend(PendingSpend::new(pool.clone(), estimate, operation_type, meter)).await
+                        }
+                        _ => value,
+                    };
+                    route.attempts.push(RouteAttempt { provider: key, model, error: None });
+                    route.prompt_template = templates.used();
+                    if route.failed_over() {
+                        tracing::info!("AI request served after failover: {:?}", route.attempts);
+                    }
+                    return Ok((value, route));
+                }
+                Err(e) if index < last && routing::should_fail_over(&e) => {
+                    tracing::warn!("AI provider {} failed, trying next in chain: {}", key, e);
+                    route.attempts.push(RouteAttempt { provider: key, model, error: Some(e.to_string()) });
+                }
+                Err(e) => return Err(e),
+            }
         }
+
+        Err(StoryWeaverError::NotSupported { operation: "No AI provider available".to_string() })
     }
 
-    async fn rewrite_text_stream(&self, text: &str, style: &RewriteStyle) -> Result<TextStream> {
-        match self.get_default_provider() {
-            Some(provider) => provider.rewrite_text_stream(text, style).await,
-            None => Err(StoryWeaverError::NotSupported { operation: "No default AI provider configured".to_string() }),
+    /// Embed `text` with the providers routed under `EMBEDDING_ROUTE_KEY`,
+    /// falling back to the offline `LocalEmbeddingProvider`. Cloud embeddings
+    /// are opt-in through the routing table, so an unconfigured install never
+    /// sends manuscript text out just to rank context.
+    pub async fn embed(&self, text: &str) -> Result<Embedding> {
+        let targets = self.routing_table().features.get(EMBEDDING_ROUTE_KEY).cloned().unwrap_or_default();
+        for target in &targets {
+            let Some(provider) = target.registry_keys().iter().find_map(|key| self.get_provider(key)) else {
+                continue;
+            };
+            match provider.generate_embedding(text).await {
+                // An all-zero vector is a placeholder, not an embedding
+                Ok(vector) if vector.iter().any(|v| *v != 0.0) => {
+                    return Ok(Embedding { model: provider.get_model_name().to_string(), vector });
+                }
+                Ok(_) => tracing::warn!("Embedding provider {} returned an empty vector", target.provider),
+                Err(e) => tracing::warn!("Embedding provider {} failed, using local embeddings: {}", target.provider, e),
+            }
         }
+
+        Ok(Embedding {
+            model: LOCAL_EMBEDDING_MODEL.to_string(),
+            vector: self.local_embeddings.embed(text),
+        })
+    }
+
+    /// Model `embed` will use when its first routed provider answers. Stored
+    /// vectors from this model can be reused without re-embedding.
+    pub fn embedding_model(&self) -> String {
+        self.routing_table()
+            .features
+            .get(EMBEDDING_ROUTE_KEY)
+            .into_iter()
+            .flatten()
+            .find_map(|target| target.registry_keys().iter().find_map(|key| self.get_provider(key)))
+            .map(|provider| provider.get_model_name().to_string())
+            .unwrap_or_else(|| LOCAL_EMBEDDING_MODEL.to_string())
+    }
+
+    async fn routed<T, F, Fut>(&self, request: RouteRequest, operation: F) -> Result<T>
+    where
+        T: Metered,
+        F: Fn(Arc<dyn AIProvider>) -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        self.execute_routed(&request, operation).await.map(|(value, _)| value)
+    }
+}
+
+/// Route by the context's feature, falling back to the method's own feature
+fn context_route(context: &AIContext, fallback: WritingFeature, input: &str) -> RouteRequest {
+    RouteRequest::feature(context.feature_type.clone().unwrap_or(fallback))
+        .with_cancellation(context.cancellation.clone())
+        .with_budget(context_budget(context, input))
+}
+
+/// Route by the method's feature, honouring the context's cancellation
+fn feature_route(context: &AIContext, feature: WritingFeature, input: &str) -> RouteRequest {
+    RouteRequest::feature(feature)
+        .with_cancellation(context.cancellation.clone())
+        .with_budget(context_budget(context, input))
+}
+
+/// Route for a method without a context, budgeted on its input alone
+fn input_route(feature: WritingFeature, input: &str) -> RouteRequest {
+    RouteRequest::feature(feature).with_budget(BudgetRequest::new(None, input, advanced_ai_manager::DEFAULT_EXPECTED_OUTPUT_TOKENS))
+}
+
+/// Budget size of `input` sent with the context's text, on behalf of its project
+pub(crate) fn context_budget(context: &AIContext, input: &str) -> BudgetRequest {
+    let mut text = input.to_string();
+    let parts = [&context.preceding_text, &context.following_text, &context.selected_text, &context.story_context];
+    for part in parts.into_iter().flatten() {
+        text.push('\n');
+        text.push_str(part);
+    }
+    let output_tokens = context
+        .word_count_target
+        .map(|words| (words * 4 / 3) as u32)
+        .unwrap_or(advanced_ai_manager::DEFAULT_EXPECTED_OUTPUT_TOKENS);
+    BudgetRequest::new(context.project_id.clone(), &text, output_tokens)
+}
+
+/// Stop yielding deltas once the context's request is cancelled
+fn guard_stream(context: &AIContext, chunks: TextChunkStream) -> TextChunkStream {
+    match &context.cancellation {
+        Some(token) => token.guard_stream(chunks),
+        None => chunks,
+    }
+}
+
+/// A generated image's URL or base64 data, which is billed on its prompt alone
+struct ImageOutput(String);
+
+#[async_trait]
+impl Metered for ImageOutput {
+    async fn record_spend(self, spend: PendingSpend) -> Self {
+        spend.record("").await;
+        self
+    }
+}
+
+#[async_trait]
+impl AIProvider for AIProviderManager {
+    async fn generate_text(&self, prompt: &str, context: &AIContext) -> Result<String> {
+        self.routed(context_route(context, WritingFeature::Write, prompt), |provider| async move {
+            provider.generate_text(prompt, context).await
+        }).await
+    }
+
+    async fn generate_text_stream(&self, prompt: &str, context: &AIContext) -> Result<TextChunkStream> {
+        self.routed(context_route(context, WritingFeature::Write, prompt), |provider| async move {
+            provider.generate_text_stream(prompt, context).await
+        }).await.map(|chunks| guard_stream(context, chunks))
+    }
+
+    async fn rewrite_text(&self, text: &str, style: &RewriteStyle) -> Result<String> {
+        self.routed(input_route(WritingFeature::Rewrite(style.clone()), text), |provider| async move {
+            provider.rewrite_text(text, style).await
+        }).await
+    }
+
+    async fn rewrite_text_stream(&self, text: &str, style: &RewriteStyle) -> Result<TextChunkStream> {
+        self.routed(input_route(WritingFeature::Rewrite(style.clone()), text), |provider| async move {
+            provider.rewrite_text_stream(text, style).await
+        }).await
     }
 
     async fn expand_text(&self, text: &str, context: &AIContext) -> Result<String> {
-        match self.get_default_provider() {
-            Some(provider) => provider.expand_text(text, context).await,
-            None => Err(StoryWeaverError::NotSupported { operation: "No default AI provider configured".to_string() }),
-        }
+        self.routed(feature_route(context, WritingFeature::Expand, text), |provider| async move {
+            provider.expand_text(text, context).await
+        }).await
     }
 
-    async fn expand_text_stream(&self, text: &str, context: &AIContext) -> Result<TextStream> {
-        match self.get_default_provider() {
-            Some(provider) => provider.expand_text_stream(text, context).await,
-            None => Err(StoryWeaverError::NotSupported { operation: "No default AI provider configured".to_string() }),
-        }
+    async fn expand_text_stream(&self, text: &str, context: &AIContext) -> Result<TextChunkStream> {
+        self.routed(feature_route(context, WritingFeature::Expand, text), |provider| async move {
+            provider.expand_text_stream(text, context).await
+        }).await.map(|chunks| guard_stream(context, chunks))
     }
 
     async fn describe_scene(&self, description: &str, context: &AIContext) -> Result<String> {
-        match self.get_default_provider() {
-            Some(provider) => provider.describe_scene(description, context).await,
-            None => Err(StoryWeaverError::NotSupported { operation: "No default AI provider configured".to_string() }),
-        }
+        self.routed(feature_route(context, WritingFeature::Describe, description), |provider| async move {
+            provider.describe_scene(description, context).await
+        }).await
     }
 
-    async fn describe_scene_stream(&self, description: &str, context: &AIContext) -> Result<TextStream> {
-        match self.get_default_provider() {
-            Some(provider) => provider.describe_scene_stream(description, context).await,
-            None => Err(StoryWeaverError::NotSupported { operation: "No default AI provider configured".to_string() }),
-        }
+    async fn describe_scene_stream(&self, description: &str, context: &AIContext) -> Result<TextChunkStream> {
+        self.routed(feature_route(context, WritingFeature::Describe, description), |provider| async move {
+            provider.describe_scene_stream(description, context).await
+        }).await.map(|chunks| guard_stream(context, chunks))
     }
 
     async fn brainstorm(&self, topic: &str, context: &AIContext) -> Result<Vec<String>> {
-        match self.get_default_provider() {
-            Some(provider) => provider.brainstorm(topic, context).await,
-            None => Err(StoryWeaverError::NotSupported { operation: "No default AI provider configured".to_string() }),
-        }
+        self.routed(feature_route(context, WritingFeature::Brainstorm, topic), |provider| async move {
+            provider.brainstorm(topic, context).await
+        }).await
     }
 
     async fn related_words(&self, word: &str, context: &AIContext) -> Result<Vec<String>> {
-        match self.get_default_provider() {
-            Some(provider) => provider.related_words(word, context).await,
-            None => Err(StoryWeaverError::NotSupported { operation: "No default AI provider configured".to_string() }),
-        }
+        self.routed(feature_route(context, WritingFeature::RelatedWords, word), |provider| async move {
+            provider.related_words(word, context).await
+        }).await
     }
 
     async fn quick_edit(&self, text: &str, instruction: &str) -> Result<String> {
-        match self.get_default_provider() {
-            Some(provider) => provider.quick_edit(text, instruction).await,
-            None => Err(StoryWeaverError::NotSupported { operation: "No default AI provider configured".to_string() }),
-        }
+        self.routed(input_route(WritingFeature::QuickEdit, &format!("{}\n{}", instruction, text)), |provider| async move {
+            provider.quick_edit(text, instruction).await
+        }).await
     }
 
     async fn quick_chat(&self, message: &str, context: &AIContext) -> Result<String> {
-        match self.get_default_provider() {
-            Some(provider) => provider.quick_chat(message, context).await,
-            None => Err(StoryWeaverError::NotSupported { operation: "No default AI provider configured".to_string() }),
-        }
+        self.routed(feature_route(context, WritingFeature::QuickChat, message), |provider| async move {
+            provider.quick_chat(message, context).await
+        }).await
     }
 
-    async fn quick_chat_stream(&self, message: &str, context: &AIContext) -> Result<TextStream> {
-        match self.get_default_provider() {
-            Some(provider) => provider.quick_chat_stream(message, context).await,
-            None => Err(StoryWeaverError::NotSupported { operation: "No default AI provider configured".to_string() }),
-        }
+    async fn quick_chat_stream(&self, message: &str, context: &AIContext) -> Result<TextChunkStream> {
+        self.routed(feature_route(context, WritingFeature::QuickChat, message), |provider| async move {
+            provider.quick_chat_stream(message, context).await
+        }).await.map(|chunks| guard_stream(context, chunks))
     }
 
     async fn generate_image(&self, prompt: &str) -> Result<String> {
-        match self.get_default_provider() {
-            Some(provider) => provider.generate_image(prompt).await,
-            None => Err(StoryWeaverError::NotSupported { operation: "No default AI provider configured".to_string() }),
-        }
+        self.routed(input_route(WritingFeature::Visualize, prompt), |provider| async move {
+            provider.generate_image(prompt).await.map(ImageOutput)
+        }).await.map(|image| image.0)
     }
 
     async fn generate_embedding(&self, text: &str) -> Result<Vec<f32>> {
-        match self.get_default_provider() {
-            Some(provider) => provider.generate_embedding(text).await,
-            None => Err(StoryWeaverError::NotSupported { operation: "No default AI provider configured".to_string() }),
-        }
+        self.embed(text).await.map(|embedding| embedding.vector)
+    }
+
+    async fn generate_json(&self, prompt: &str, schema: &JsonSchema, context: &AIContext) -> Result<String> {
+        let input = format!("{}\n{}", prompt, schema.schema);
+        self.routed(context_route(context, WritingFeature::Write, &input), |provider| async move {
+            provider.generate_json(prompt, schema, context).await
+        }).await
+    }
+
+    async fn generate_with_tools(&self, messages: &[ToolMessage], tools: &[ToolDefinition], context: &AIContext) -> Result<ToolTurn> {
+        let input = serde_json::to_string(messages).unwrap_or_default();
+        self.routed(context_route(context, WritingFeature::Write, &input), |provider| async move {
+            provider.generate_with_tools(messages, tools, context).await
+        }).await
     }
 
     fn supports_streaming(&self) -> bool {
-        match self.get_default_provider() {
+        match &self.default {
             Some(provider) => provider.supports_streaming(),
             None => false,
         }
     }
 
     fn supports_image_generation(&self) -> bool {
-        match self.get_default_provider() {
+        match &self.default {
             Some(provider) => provider.supports_image_generation(),
             None => false,
         }
     }
 
     fn get_context_window(&self) -> usize {
-        match self.get_default_provider() {
+        match &self.default {
             Some(provider) => provider.get_context_window(),
             None => 0,
         }
     }
 
     fn get_model_name(&self) -> &str {
-        match self.get_default_provider() {
+        match &self.default {
             Some(provider) => provider.get_model_name(),
             None => "No Provider",
         }
     }
 
     fn get_provider_name(&self) -> &str {
-        match self.get_default_provider() {
+        match &self.default {
             Some(provider) => provider.get_provider_name(),
             None => "No Provider",
         }
     }
+
+    fn health(&self) -> ProviderHealth {
+        match &self.default {
+            Some(provider) => provider.health(),
+            None => ProviderHealth::healthy(),
+        }
+    }
 }
 
 pub mod streaming;
@@ -1,13 +1,20 @@
 //! OpenAI Provider implementation for StoryWeaver
 
-use super::{AIProvider, AIContext, TextStream, RewriteStyle};
+use super::{AIProvider, AIContext, TextChunkStream, RewriteStyle};
+use super::resilience::{ProviderHealth, Resilience, ResiliencePolicy, ResilientSend};
+use super::streaming::sse_text_stream;
+use super::structured::{json_prompt, JsonSchema};
+use super::prompt_templates::{render_prompt, rewrite_values, system_values, PromptScope};
+use super::tools::{ToolCall, ToolDefinition, ToolMessage, ToolTurn};
 use crate::error::{Result, StoryWeaverError};
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
+use serde_json::json;
 use std::sync::Arc;
 use std::time::Duration;
-use tokio::sync::Mutex;
-use tokio::time::sleep;
+
+/// Base URL of the hosted OpenAI API
+pub const DEFAULT_BASE_URL: &str = "https://api.openai.com/v1";
 
 // Rate limiting constants
 const REQUESTS_PER_MINUTE: u32 = 60;
@@ -26,6 +33,9 @@ struct ChatCompletionRequest {
     temperature: f32,
     max_tokens: Option<u32>,
     stream: bool,
+    /// Native structured output, e.g. `{"type": "json_schema", ...}`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_format: Option<serde_json::Value>,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -38,6 +48,7 @@ struct ChatCompletionChoice {
 #[derive(Debug, Clone, Deserialize)]
 struct ChatCompletionResponse {
     #[allow(dead_code)]
+    #[serde(default)]
     id: String,
     choices: Vec<ChatCompletionChoice>,
     usage: Option<TokenUsage>,
@@ -45,10 +56,11 @@ struct ChatCompletionResponse {
 
 #[derive(Debug, Clone, Deserialize)]
 struct TokenUsage {
-    #[allow(dead_code)]
     prompt_tokens: u32,
-    #[allow(dead_code)]
+    // Embedding responses only report prompt tokens
+    #[serde(default)]
     completion_tokens: u32,
+    #[allow(dead_code)]
     total_tokens: u32,
 }
 
@@ -69,68 +81,61 @@ struct EmbeddingResponse {
     usage: Option<TokenUsage>,
 }
 
-pub struct OpenAIProvider {
-    pub api_key: String,
-    pub model: String,
-    pub embedding_model: String,
-    pub client: reqwest::Client,
-    pub rate_limiter: Arc<Mutex<RateLimiter>>,
+#[derive(Debug, Clone, Deserialize)]
+struct ModelListEntry {
+    id: String,
 }
 
-pub struct RateLimiter {
-    request_count: u32,
-    token_count: u32,
-    last_reset: std::time::Instant,
+#[derive(Debug, Clone, Deserialize)]
+struct ModelListResponse {
+    data: Vec<ModelListEntry>,
 }
 
-impl RateLimiter {
-    fn new() -> Self {
-        Self {
-            request_count: 0,
-            token_count: 0,
-            last_reset: std::time::Instant::now(),
-        }
-    }
-
-    async fn wait_if_needed(&mut self, estimated_tokens: u32) -> Result<()> {
-        // Reset counters if a minute has passed
-        let now = std::time::Instant::now();
-        if now.duration_since(self.last_reset).as_secs() >= 60 {
-            self.request_count = 0;
-            self.token_count = 0;
-            self.last_reset = now;
-        }
-
-        // Check if we're about to exceed limits
-        if self.request_count >= REQUESTS_PER_MINUTE || 
-           self.token_count + estimated_tokens >= TOKENS_PER_MINUTE {
-            
-            // Calculate time to wait until next minute
-            let elapsed = now.duration_since(self.last_reset).as_millis() as u64;
-            let wait_time = 60000_u64.saturating_sub(elapsed);
-            
-            // Wait until rate limit resets
-            if wait_time > 0 {
-                sleep(Duration::from_millis(wait_time)).await;
-                self.request_count = 0;
-                self.token_count = 0;
-                self.last_reset = std::time::Instant::now();
-            }
-        }
+/// Connection settings for a self-hosted server that speaks the OpenAI
+/// chat completions protocol (Ollama, llama.cpp server, LM Studio, vLLM...)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OpenAICompatibleConfig {
+    pub name: String,
+    pub display_name: String,
+    pub base_url: String,
+    pub api_key: Option<String>,
+    pub model: String,
+    pub embedding_model: Option<String>,
+    pub context_window: Option<usize>,
+}
 
-        // Update counters
-        self.request_count += 1;
-        self.token_count += estimated_tokens;
-        
-        Ok(())
+impl OpenAICompatibleConfig {
+    /// Build a provider from this configuration
+    pub fn into_provider(self) -> OpenAIProvider {
+        let embedding_model = self.embedding_model.unwrap_or_else(|| self.model.clone());
+        let mut provider = OpenAIProvider::new(self.api_key.unwrap_or_default(), self.model)
+            .with_base_url(&self.base_url)
+            .with_provider_name(&self.name)
+            .with_embedding_model(embedding_model);
+        provider.context_window = self.context_window;
+        provider.image_generation = false;
+        provider
     }
+}
 
-    fn update_token_usage(&mut self, usage: &TokenUsage) {
-        self.token_count = self.token_count.saturating_add(usage.total_tokens);
-    }
+pub struct OpenAIProvider {
+    pub api_key: String,
+    pub model: String,
+    pub embedding_model: String,
+    pub base_url: String,
+    pub provider_name: String,
+    pub context_window: Option<usize>,
+    pub image_generation: bool,
+    pub client: reqwest::Client,
+    pub resilience: Arc<Resilience>,
 }
 
 impl OpenAIProvider {
+    /// Token count for rate limiting, using this model's tokenizer
+    fn count_tokens(&self, text: &str) -> u32 {
+        super::tokenizer::count_tokens(&self.model, text) as u32
+    }
+
     pub fn new(api_key: String, model: String) -> Self {
         let client = reqwest::Client::builder()
             .timeout(Duration::from_secs(60))
@@ -141,78 +146,112 @@ impl OpenAIProvider {
             api_key,
             model,
             embedding_model: "text-embedding-ada-002".to_string(),
+            base_url: DEFAULT_BASE_URL.to_string(),
+            provider_name: "OpenAI".to_string(),
+            context_window: None,
+            image_generation: true,
             client,
-            rate_limiter: Arc::new(Mutex::new(RateLimiter::new())),
+            resilience: Arc::new(Resilience::new(
+                "OpenAI",
+                ResiliencePolicy::with_rate_limits(REQUESTS_PER_MINUTE, TOKENS_PER_MINUTE),
+            )),
         }
     }
 
-    fn build_system_message(&self, _context: &AIContext) -> ChatMessage {
-        // Build a system message based on the context
-        // This could include information about the document, user preferences, etc.
-        ChatMessage {
-            role: "system".to_string(),
-            content: "You are StoryWeaver, an AI writing assistant. Help the user write their story.".to_string(),
+    /// Point the provider at a different OpenAI-compatible server
+    pub fn with_base_url(mut self, base_url: &str) -> Self {
+        self.base_url = base_url.trim_end_matches('/').to_string();
+        self
+    }
+
+    /// Override the name reported by `get_provider_name` and used in errors
+    pub fn with_provider_name(mut self, provider_name: &str) -> Self {
+        self.provider_name = provider_name.to_string();
+        self.resilience = Arc::new(Resilience::new(provider_name, self.resilience.policy().clone()));
+        self
+    }
+
+    /// Override the retry, pacing and circuit breaker settings
+    pub fn with_resilience_policy(mut self, policy: ResiliencePolicy) -> Self {
+        self.resilience = Arc::new(Resilience::new(&self.provider_name, policy));
+        self
+    }
+
+    /// Override the model used for `generate_embedding`
+    pub fn with_embedding_model(mut self, embedding_model: String) -> Self {
+        self.embedding_model = embedding_model;
+        self
+    }
+
+    fn endpoint(&self, path: &str) -> String {
+        format!("{}/{}", self.base_url, path)
+    }
+
+    /// Attach the bearer token, if any. Local servers usually run without a key.
+    fn authorize(&self, request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        if self.api_key.is_empty() {
+            request
+        } else {
+            request.header("Authorization", format!("Bearer {}", self.api_key))
         }
     }
-}
 
-#[async_trait]
-impl AIProvider for OpenAIProvider {
-    async fn generate_text(&self, prompt: &str, context: &AIContext) -> Result<String> {
-        // Estimate token usage for rate limiting
-        let estimated_tokens = (prompt.len() / 4) as u32 + 500; // Rough estimate
-        
-        // Wait if we need to respect rate limits
-        {
-            let mut rate_limiter = self.rate_limiter.lock().await;
-            rate_limiter.wait_if_needed(estimated_tokens).await?;
+    /// Discover the models served by the endpoint through `GET /models`
+    pub async fn list_models(&self) -> Result<Vec<String>> {
+        let response = self.authorize(self.client.get(self.endpoint("models")))
+            .send_with(&self.resilience, 0)
+            .await?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
+            return Err(StoryWeaverError::ai_request(self.provider_name.as_str(), status.as_u16(), &error_text));
         }
-        
-        // Build request
-        let system_message = self.build_system_message(context);
-        let user_message = ChatMessage {
-            role: "user".to_string(),
-            content: prompt.to_string(),
-        };
-        
-        let request = ChatCompletionRequest {
-            model: self.model.clone(),
-            messages: vec![system_message, user_message],
-            temperature: 0.7,
-            max_tokens: Some(1000),
-            stream: false,
-        };
-        
+
+        let models: ModelListResponse = response.json().await
+            .map_err(|e| StoryWeaverError::deserialization(format!("Failed to parse model list: {}", e)))?;
+
+        let mut names: Vec<String> = models.data.into_iter().map(|m| m.id).collect();
+        names.sort();
+        Ok(names)
+    }
+
+    /// POST a chat completion body and return the raw response text
+    async fn post_chat<T: Serialize + ?Sized>(&self, body: &T, estimated_tokens: u32) -> Result<String> {
         // Make API call
-        let response = self.client.post("https://api.openai.com/v1/chat/completions")
-            .header("Authorization", format!("Bearer {}", self.api_key))
+        let response = self.authorize(self.client.post(self.endpoint("chat/completions")))
             .header("Content-Type", "application/json")
-            .json(&request)
-            .send()
-            .await
-            .map_err(|e| StoryWeaverError::network(format!("Failed to send request to OpenAI API: {}", e)))?;
-        
+            .json(body)
+            .send_with(&self.resilience, estimated_tokens)
+            .await?;
+
         // Check for errors first
         let status_code = response.status().as_u16();
         let is_success = response.status().is_success();
-        
+
         // Get response text
         let response_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-        
+
         if !is_success {
-            return Err(StoryWeaverError::ai_request("OpenAI", status_code, &response_text));
+            return Err(StoryWeaverError::ai_request(self.provider_name.as_str(), status_code, &response_text));
         }
-        
+
+        Ok(response_text)
+    }
+
+    /// Send a chat completion and return the first choice's content
+    async fn send_chat(&self, request: &ChatCompletionRequest, estimated_tokens: u32) -> Result<String> {
+        let response_text = self.post_chat(request, estimated_tokens).await?;
+
         // Parse response
         let completion: ChatCompletionResponse = serde_json::from_str(&response_text)
             .map_err(|e| StoryWeaverError::deserialization(format!("Failed to parse OpenAI API response: {}", e)))?;
-        
-        // Update rate limiter with actual token usage
+
+        // Record actual token usage for pacing
         if let Some(usage) = &completion.usage {
-            let mut rate_limiter = self.rate_limiter.lock().await;
-            rate_limiter.update_token_usage(usage);
+            self.resilience.record_usage(estimated_tokens, usage.prompt_tokens, usage.completion_tokens).await;
         }
-        
+
         // Extract generated text
         if let Some(choice) = completion.choices.first() {
             Ok(choice.message.content.clone())
@@ -223,18 +262,61 @@ impl AIProvider for OpenAIProvider {
         }
     }
 
-    async fn generate_text_stream(&self, prompt: &str, context: &AIContext) -> Result<TextStream> {
-        // Estimate token usage for rate limiting
-        let estimated_tokens = (prompt.len() / 4) as u32 + 500; // Rough estimate
-        
-        // Wait if we need to respect rate limits
-        {
-            let mut rate_limiter = self.rate_limiter.lock().await;
-            rate_limiter.wait_if_needed(estimated_tokens).await?;
+    /// Send a streaming chat completion and yield the content deltas as they arrive
+    async fn send_chat_stream(&self, request: &ChatCompletionRequest, estimated_tokens: u32) -> Result<TextChunkStream> {
+        let response = self.authorize(self.client.post(self.endpoint("chat/completions")))
+            .header("Content-Type", "application/json")
+            .json(request)
+            .send_with(&self.resilience, estimated_tokens)
+            .await?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
+            return Err(StoryWeaverError::ai_request(self.provider_name.as_str(), status.as_u16(), &error_text));
         }
+
+        Ok(sse_text_stream(response, &self.provider_name, chat_completion_delta))
+    }
+
+    fn build_system_message(&self, context: &AIContext) -> Result<ChatMessage> {
+        Ok(ChatMessage {
+            role: "system".to_string(),
+            content: render_prompt("system", &PromptScope::from_context(context), &system_values(context))?.text,
+        })
+    }
+}
+
+/// `choices[0].delta.content` of a streamed chat completion chunk
+fn chat_completion_delta(event: &serde_json::Value) -> Option<String> {
+    event
+        .get("choices")
+        .and_then(|choices| choices.get(0))
+        .and_then(|choice| choice.get("delta"))
+        .and_then(|delta| delta.get("content"))
+        .and_then(|content| content.as_str())
+        .map(|content| content.to_string())
+}
+
+/// A `tool_calls` entry of a chat completion message; `arguments` arrives as a JSON string
+fn openai_tool_call(call: &serde_json::Value) -> Option<ToolCall> {
+    let function = call.get("function")?;
+    let arguments = function.get("arguments").and_then(|a| a.as_str()).unwrap_or("{}");
+    Some(ToolCall {
+        id: call.get("id")?.as_str()?.to_string(),
+        name: function.get("name")?.as_str()?.to_string(),
+        arguments: serde_json::from_str(arguments).unwrap_or_else(|_| json!({})),
+    })
+}
+
+#[async_trait]
+impl AIProvider for OpenAIProvider {
+    async fn generate_text(&self, prompt: &str, context: &AIContext) -> Result<String> {
+        // Estimate token usage for rate limiting
+        let estimated_tokens = self.count_tokens(prompt) + 500;
         
         // Build request
-        let system_message = self.build_system_message(context);
+        let system_message = self.build_system_message(context)?;
         let user_message = ChatMessage {
             role: "user".to_string(),
             content: prompt.to_string(),
@@ -245,101 +327,42 @@ impl AIProvider for OpenAIProvider {
             messages: vec![system_message, user_message],
             temperature: 0.7,
             max_tokens: Some(1000),
-            stream: true, // Enable streaming
+            stream: false,
+            response_format: None,
         };
         
-        // Make API call with streaming
-        let response = self.client.post("https://api.openai.com/v1/chat/completions")
-            .header("Authorization", format!("Bearer {}", self.api_key))
-            .header("Content-Type", "application/json")
-            .json(&request)
-            .send()
-            .await
-            .map_err(|e| StoryWeaverError::network(format!("Failed to send request to OpenAI API: {}", e)))?;
-        
-        // Check for errors first
-        let status_code = response.status().as_u16();
-        let is_success = response.status().is_success();
-        
-        if !is_success {
-            // Get response text for error handling
-            let response_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(StoryWeaverError::ai_request("OpenAI", status_code, &response_text));
-        }
-        
-        // Create a new TextStream
-        let mut text_stream = TextStream::new();
-        
-        // Process the streaming response
-        let mut stream = response.bytes_stream();
-        use futures_util::StreamExt;
+        self.send_chat(&request, estimated_tokens).await
+    }
+
+    async fn generate_text_stream(&self, prompt: &str, context: &AIContext) -> Result<TextChunkStream> {
+        // Estimate token usage for rate limiting
+        let estimated_tokens = self.count_tokens(prompt) + 500;
         
-        while let Some(chunk_result) = stream.next().await {
-            let chunk = chunk_result.map_err(|e| StoryWeaverError::network(format!("Error reading stream chunk: {}", e)))?;
-            let chunk_str = String::from_utf8_lossy(&chunk);
-            
-            // OpenAI sends "data: " prefixed SSE events
-            for line in chunk_str.lines() {
-                if line.starts_with("data: ") {
-                    let data = &line[6..]; // Skip "data: " prefix
-                    
-                    // Skip empty lines and [DONE] marker
-                    if data.is_empty() || data == "[DONE]" {
-                        continue;
-                    }
-                    
-                    // Parse the JSON data
-                    match serde_json::from_str::<serde_json::Value>(data) {
-                        Ok(json) => {
-                            // Extract the content from the JSON
-                            if let Some(content) = json
-                                .get("choices")
-                                .and_then(|choices| choices.get(0))
-                                .and_then(|choice| choice.get("delta"))
-                                .and_then(|delta| delta.get("content"))
-                                .and_then(|content| content.as_str())
-                            {
-                                text_stream.append(content);
-                            }
-                        },
-                        Err(e) => {
-                            eprintln!("Error parsing JSON from stream: {}", e);
-                            continue;
-                        }
-                    }
-                }
-            }
-        }
+        // Build request
+        let system_message = self.build_system_message(context)?;
+        let user_message = ChatMessage {
+            role: "user".to_string(),
+            content: prompt.to_string(),
+        };
         
-        // Mark the stream as complete
-        text_stream.complete();
+        let request = ChatCompletionRequest {
+            model: self.model.clone(),
+            messages: vec![system_message, user_message],
+            temperature: 0.7,
+            max_tokens: Some(1000),
+            stream: true, // Enable streaming
+            response_format: None,
+        };
         
-        Ok(text_stream)
+        self.send_chat_stream(&request, estimated_tokens).await
     }
 
     async fn rewrite_text(&self, text: &str, style: &RewriteStyle) -> Result<String> {
         // Estimate token usage for rate limiting
-        let estimated_tokens = (text.len() / 4) as u32 + 500; // Rough estimate
-        
-        // Wait if we need to respect rate limits
-        {
-            let mut rate_limiter = self.rate_limiter.lock().await;
-            rate_limiter.wait_if_needed(estimated_tokens).await?;
-        }
+        let estimated_tokens = self.count_tokens(text) + 500;
         
         // Build prompt based on rewrite style
-        let style_instruction = match style {
-            RewriteStyle::Rephrase => "Rephrase this text while keeping the same meaning:".to_string(),
-            RewriteStyle::Shorter => "Rewrite this text to be more concise:".to_string(),
-            RewriteStyle::MoreDescriptive => "Rewrite this text to be more descriptive and vivid:".to_string(),
-            RewriteStyle::Longer => "Expand this text with more details and elaboration:".to_string(),
-            RewriteStyle::MoreFormal => "Rewrite this text in a more formal, professional tone:".to_string(),
-            RewriteStyle::MoreCasual => "Rewrite this text in a more casual, conversational tone:".to_string(),
-            RewriteStyle::MoreVivid => "Rewrite this text with more vivid imagery and sensory details:".to_string(),
-            RewriteStyle::MoreDirect => "Rewrite this text to be more direct and straightforward:".to_string(),
-            RewriteStyle::MorePoetic => "Rewrite this text in a more poetic, lyrical style:".to_string(),
-            RewriteStyle::ToneShift(tone) => format!("Rewrite this text in a {} tone:", tone),
-        };
+        let style_instruction = render_prompt("rewrite", &PromptScope::global(), &rewrite_values(style))?.text;
         
         let system_message = ChatMessage {
             role: "system".to_string(),
@@ -357,16 +380,15 @@ impl AIProvider for OpenAIProvider {
             temperature: 0.7,
             max_tokens: Some(2000), // Limit token usage based on input
             stream: false,
+            response_format: None,
         };
         
         // Make API call
-        let response = self.client.post("https://api.openai.com/v1/chat/completions")
-            .header("Authorization", format!("Bearer {}", self.api_key))
+        let response = self.authorize(self.client.post(self.endpoint("chat/completions")))
             .header("Content-Type", "application/json")
             .json(&request)
-            .send()
-            .await
-            .map_err(|e| StoryWeaverError::network(format!("Failed to send request to OpenAI API: {}", e)))?;
+            .send_with(&self.resilience, estimated_tokens)
+            .await?;
         
         // Check for errors first
         let status_code = response.status().as_u16();
@@ -374,7 +396,7 @@ impl AIProvider for OpenAIProvider {
         
         if !is_success {
             let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(StoryWeaverError::ai_request("OpenAI", status_code, &error_text));
+            return Err(StoryWeaverError::ai_request(self.provider_name.as_str(), status_code, &error_text));
         }
         
         // Get response text
@@ -385,10 +407,9 @@ impl AIProvider for OpenAIProvider {
         let completion: ChatCompletionResponse = serde_json::from_str(&response_text)
             .map_err(|e| StoryWeaverError::deserialization(format!("Failed to parse OpenAI API response: {}", e)))?;
         
-        // Update rate limiter with actual token usage
+        // Record actual token usage for pacing
         if let Some(usage) = &completion.usage {
-            let mut rate_limiter = self.rate_limiter.lock().await;
-            rate_limiter.update_token_usage(usage);
+            self.resilience.record_usage(estimated_tokens, usage.prompt_tokens, usage.completion_tokens).await;
         }
         
         // Extract generated text
@@ -403,13 +424,7 @@ impl AIProvider for OpenAIProvider {
 
     async fn generate_embedding(&self, text: &str) -> Result<Vec<f32>> {
         // Estimate token usage for rate limiting
-        let estimated_tokens = (text.len() / 4) as u32; // Rough estimate
-        
-        // Wait if we need to respect rate limits
-        {
-            let mut rate_limiter = self.rate_limiter.lock().await;
-            rate_limiter.wait_if_needed(estimated_tokens).await?;
-        }
+        let estimated_tokens = self.count_tokens(text);
         
         // Build request
         let request = EmbeddingRequest {
@@ -418,13 +433,11 @@ impl AIProvider for OpenAIProvider {
         };
         
         // Make API call
-        let response = self.client.post("https://api.openai.com/v1/embeddings")
-            .header("Authorization", format!("Bearer {}", self.api_key))
+        let response = self.authorize(self.client.post(self.endpoint("embeddings")))
             .header("Content-Type", "application/json")
             .json(&request)
-            .send()
-            .await
-            .map_err(|e| StoryWeaverError::network(format!("Failed to send request to OpenAI API: {}", e)))?;
+            .send_with(&self.resilience, estimated_tokens)
+            .await?;
         
         // Check for errors
         let status_code = response.status();
@@ -432,17 +445,16 @@ impl AIProvider for OpenAIProvider {
         
         if !is_success {
             let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(StoryWeaverError::ai_request("OpenAI", status_code.as_u16(), &error_text));
+            return Err(StoryWeaverError::ai_request(self.provider_name.as_str(), status_code.as_u16(), &error_text));
         }
         
         // Parse response
         let embedding_response: EmbeddingResponse = response.json().await
             .map_err(|e| StoryWeaverError::deserialization(format!("Failed to parse OpenAI API response: {}", e)))?;
         
-        // Update rate limiter with actual token usage
+        // Record actual token usage for pacing
         if let Some(usage) = &embedding_response.usage {
-            let mut rate_limiter = self.rate_limiter.lock().await;
-            rate_limiter.update_token_usage(usage);
+            self.resilience.record_usage(estimated_tokens, usage.prompt_tokens, usage.completion_tokens).await;
         }
         
         // Extract embedding
@@ -455,11 +467,114 @@ impl AIProvider for OpenAIProvider {
         }
     }
 
+    async fn generate_json(&self, prompt: &str, schema: &JsonSchema, context: &AIContext) -> Result<String> {
+        let estimated_tokens = self.count_tokens(prompt) + 1000;
+        let request = ChatCompletionRequest {
+            model: self.model.clone(),
+            messages: vec![
+                self.build_system_message(context)?,
+                ChatMessage { role: "user".to_string(), content: prompt.to_string() },
+            ],
+            temperature: 0.7,
+            max_tokens: Some(2000),
+            stream: false,
+            response_format: Some(serde_json::json!({
+                "type": "json_schema",
+                "json_schema": { "name": schema.name, "schema": schema.schema },
+            })),
+        };
+
+        match self.send_chat(&request, estimated_tokens).await {
+            // Older models and some compatible servers reject `response_format`
+            Err(StoryWeaverError::AIRequest { status_code: 400 | 422, .. }) => {
+                self.generate_text(&json_prompt(prompt, schema), context).await
+            }
+            result => result,
+        }
+    }
+
+    async fn generate_with_tools(&self, messages: &[ToolMessage], tools: &[ToolDefinition], context: &AIContext) -> Result<ToolTurn> {
+        let system_message = self.build_system_message(context)?;
+        let mut chat = vec![json!({ "role": system_message.role, "content": system_message.content })];
+        let mut estimated_tokens = 1000;
+        for message in messages {
+            chat.push(match message {
+                ToolMessage::User { content } => {
+                    estimated_tokens += self.count_tokens(content);
+                    json!({ "role": "user", "content": content })
+                }
+                ToolMessage::Assistant { content, tool_calls } => {
+                    let mut assistant = json!({ "role": "assistant", "content": content });
+                    if !tool_calls.is_empty() {
+                        assistant["tool_calls"] = tool_calls
+                            .iter()
+                            .map(|call| json!({
+                                "id": call.id,
+                                "type": "function",
+                                "function": { "name": call.name, "arguments": call.arguments.to_string() },
+                            }))
+                            .collect();
+                    }
+                    assistant
+                }
+                ToolMessage::Tool { call_id, content, .. } => {
+                    estimated_tokens += self.count_tokens(content);
+                    json!({ "role": "tool", "tool_call_id": call_id, "content": content })
+                }
+            });
+        }
+
+        let mut body = json!({
+            "model": self.model,
+            "messages": chat,
+            "temperature": 0.7,
+            "max_tokens": 2000,
+        });
+        if !tools.is_empty() {
+            body["tools"] = tools
+                .iter()
+                .map(|tool| json!({
+                    "type": "function",
+                    "function": { "name": tool.name, "description": tool.description, "parameters": tool.parameters },
+                }))
+                .collect();
+        }
+
+        let response_text = self.post_chat(&body, estimated_tokens).await?;
+        let completion: serde_json::Value = serde_json::from_str(&response_text)
+            .map_err(|e| StoryWeaverError::deserialization(format!("Failed to parse OpenAI API response: {}", e)))?;
+
+        if let Some(usage) = completion.get("usage") {
+            let tokens = |key: &str| usage.get(key).and_then(|t| t.as_u64()).unwrap_or(0) as u32;
+            self.resilience.record_usage(estimated_tokens, tokens("prompt_tokens"), tokens("completion_tokens")).await;
+        }
+
+        let message = completion.pointer("/choices/0/message").ok_or_else(|| StoryWeaverError::AIGenerationError {
+            message: "No completion choices returned".to_string(),
+        })?;
+        let content = message.get("content").and_then(|c| c.as_str()).map(|c| c.to_string());
+        let calls: Vec<ToolCall> = message
+            .get("tool_calls")
+            .and_then(|calls| calls.as_array())
+            .map(|calls| calls.iter().filter_map(openai_tool_call).collect())
+            .unwrap_or_default();
+
+        if calls.is_empty() {
+            Ok(ToolTurn::Answer(content.unwrap_or_default()))
+        } else {
+            Ok(ToolTurn::Calls { content, calls })
+        }
+    }
+
     fn supports_streaming(&self) -> bool {
         true
     }
 
     fn get_context_window(&self) -> usize {
+        if let Some(context_window) = self.context_window {
+            return context_window;
+        }
+
         match self.model.as_str() {
             "gpt-4-turbo" => 128000,
             "gpt-4" => 8192,
@@ -473,37 +588,25 @@ impl AIProvider for OpenAIProvider {
     }
     
     fn get_provider_name(&self) -> &str {
-        "OpenAI"
+        &self.provider_name
+    }
+
+    fn health(&self) -> ProviderHealth {
+        self.resilience.health()
     }
     
     fn supports_image_generation(&self) -> bool {
-        true // OpenAI supports DALL-E for image generation
+        self.image_generation // OpenAI supports DALL-E; compatible servers usually don't
     }
     
     // Implement the new methods required by the AIProvider trait
     
-    async fn rewrite_text_stream(&self, text: &str, style: &RewriteStyle) -> Result<TextStream> {
+    async fn rewrite_text_stream(&self, text: &str, style: &RewriteStyle) -> Result<TextChunkStream> {
         // Estimate token usage for rate limiting
-        let estimated_tokens = (text.len() / 4) as u32 + 500;
-        
-        {
-            let mut rate_limiter = self.rate_limiter.lock().await;
-            rate_limiter.wait_if_needed(estimated_tokens).await?;
-        }
+        let estimated_tokens = self.count_tokens(text) + 500;
         
         // Build prompt based on rewrite style
-        let style_instruction = match style {
-            RewriteStyle::Rephrase => "Rephrase this text while keeping the same meaning:".to_string(),
-            RewriteStyle::Shorter => "Rewrite this text to be more concise:".to_string(),
-            RewriteStyle::MoreDescriptive => "Rewrite this text to be more descriptive and vivid:".to_string(),
-            RewriteStyle::Longer => "Expand this text with more details and elaboration:".to_string(),
-            RewriteStyle::MoreFormal => "Rewrite this text in a more formal, professional tone:".to_string(),
-            RewriteStyle::MoreCasual => "Rewrite this text in a more casual, conversational tone:".to_string(),
-            RewriteStyle::MoreVivid => "Rewrite this text with more vivid imagery and sensory details:".to_string(),
-            RewriteStyle::MoreDirect => "Rewrite this text to be more direct and straightforward:".to_string(),
-            RewriteStyle::MorePoetic => "Rewrite this text in a more poetic, lyrical style:".to_string(),
-            RewriteStyle::ToneShift(tone) => format!("Rewrite this text in a {} tone:", tone),
-        };
+        let style_instruction = render_prompt("rewrite", &PromptScope::global(), &rewrite_values(style))?.text;
         
         let system_message = ChatMessage {
             role: "system".to_string(),
@@ -521,84 +624,15 @@ impl AIProvider for OpenAIProvider {
             temperature: 0.7,
             max_tokens: Some(text.len() as u32 * 2), // Allow for expansion
             stream: true, // Enable streaming
+            response_format: None,
         };
         
-        // Make API call with streaming
-        let response = self.client.post("https://api.openai.com/v1/chat/completions")
-            .header("Authorization", format!("Bearer {}", self.api_key))
-            .header("Content-Type", "application/json")
-            .json(&request)
-            .send()
-            .await
-            .map_err(|e| StoryWeaverError::network(format!("Failed to send request to OpenAI API: {}", e)))?;
-        
-        // Check for errors
-        let status = response.status();
-        let is_success = status.is_success();
-        
-        if !is_success {
-            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(StoryWeaverError::ai_request("OpenAI", status.as_u16(), &error_text));
-        }
-        
-        // Create a new TextStream
-        let mut text_stream = TextStream::new();
-        
-        // Process the streaming response
-        let mut stream = response.bytes_stream();
-        use futures_util::StreamExt;
-        
-        while let Some(chunk_result) = stream.next().await {
-            let chunk = chunk_result.map_err(|e| StoryWeaverError::network(format!("Error reading stream chunk: {}", e)))?;
-            let chunk_str = String::from_utf8_lossy(&chunk);
-            
-            // OpenAI sends "data: " prefixed SSE events
-            for line in chunk_str.lines() {
-                if line.starts_with("data: ") {
-                    let data = &line[6..]; // Skip "data: " prefix
-                    
-                    // Skip empty lines and [DONE] marker
-                    if data.is_empty() || data == "[DONE]" {
-                        continue;
-                    }
-                    
-                    // Parse the JSON data
-                    match serde_json::from_str::<serde_json::Value>(data) {
-                        Ok(json) => {
-                            // Extract the content from the JSON
-                            if let Some(content) = json
-                                .get("choices")
-                                .and_then(|choices| choices.get(0))
-                                .and_then(|choice| choice.get("delta"))
-                                .and_then(|delta| delta.get("content"))
-                                .and_then(|content| content.as_str())
-                            {
-                                text_stream.append(content);
-                            }
-                        },
-                        Err(e) => {
-                            eprintln!("Error parsing JSON from stream: {}", e);
-                            continue;
-                        }
-                    }
-                }
-            }
-        }
-        
-        // Mark the stream as complete
-        text_stream.complete();
-        
-        Ok(text_stream)
+        self.send_chat_stream(&request, estimated_tokens).await
     }
     
     async fn expand_text(&self, text: &str, context: &AIContext) -> Result<String> {
         // Estimate token usage for rate limiting
-        let estimated_tokens = (text.len() / 4) as u32 + 500;
-        
-        {
-            let mut rate_limiter = self.rate_limiter.lock().await;
-            rate_limiter.wait_if_needed(estimated_tokens).await?;
-        }
+        let estimated_tokens = self.count_tokens(text) + 500;
         
         // Build system message
         let system_message = ChatMessage {
@@ -644,16 +678,15 @@ impl AIProvider for OpenAIProvider {
             temperature: 0.7,
             max_tokens: Some(2000), // Allow for significant expansion
             stream: false,
+            response_format: None,
         };
         
         // Make API call
-        let response = self.client.post("https://api.openai.com/v1/chat/completions")
-            .header("Authorization", format!("Bearer {}", self.api_key))
+        let response = self.authorize(self.client.post(self.endpoint("chat/completions")))
             .header("Content-Type", "application/json")
             .json(&request)
-            .send()
-            .await
-            .map_err(|e| StoryWeaverError::network(format!("Failed to send request to OpenAI API: {}", e)))?;
+            .send_with(&self.resilience, estimated_tokens)
+            .await?;
         
         // Check for errors
         let status_code = response.status();
@@ -661,17 +694,16 @@ impl AIProvider for OpenAIProvider {
         let response_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
         
         if !is_success {
-            return Err(StoryWeaverError::ai_request("OpenAI", status_code.as_u16(), &response_text));
+            return Err(StoryWeaverError::ai_request(self.provider_name.as_str(), status_code.as_u16(), &response_text));
         }
         
         // Parse response
         let completion: ChatCompletionResponse = serde_json::from_str(&response_text)
             .map_err(|e| StoryWeaverError::deserialization(format!("Failed to parse OpenAI API response: {}", e)))?;
         
-        // Update rate limiter with actual token usage
+        // Record actual token usage for pacing
         if let Some(usage) = &completion.usage {
-            let mut rate_limiter = self.rate_limiter.lock().await;
-            rate_limiter.update_token_usage(usage);
+            self.resilience.record_usage(estimated_tokens, usage.prompt_tokens, usage.completion_tokens).await;
         }
         
         // Extract generated text
@@ -684,14 +716,9 @@ impl AIProvider for OpenAIProvider {
         }
     }
     
-    async fn expand_text_stream(&self, text: &str, context: &AIContext) -> Result<TextStream> {
+    async fn expand_text_stream(&self, text: &str, context: &AIContext) -> Result<TextChunkStream> {
         // Estimate token usage for rate limiting
-        let estimated_tokens = (text.len() / 4) as u32 + 500;
-        
-        {
-            let mut rate_limiter = self.rate_limiter.lock().await;
-            rate_limiter.wait_if_needed(estimated_tokens).await?;
-        }
+        let estimated_tokens = self.count_tokens(text) + 500;
         
         // Build system message
         let system_message = ChatMessage {
@@ -737,84 +764,15 @@ impl AIProvider for OpenAIProvider {
             temperature: 0.7,
             max_tokens: Some(2000), // Allow for significant expansion
             stream: true, // Enable streaming
+            response_format: None,
         };
         
-        // Make API call with streaming
-        let response = self.client.post("https://api.openai.com/v1/chat/completions")
-            .header("Authorization", format!("Bearer {}", self.api_key))
-            .header("Content-Type", "application/json")
-            .json(&request)
-            .send()
-            .await
-            .map_err(|e| StoryWeaverError::network(format!("Failed to send request to OpenAI API: {}", e)))?;
-        
-        // Check for errors
-        let status = response.status();
-        let is_success = status.is_success();
-        
-        if !is_success {
-            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(StoryWeaverError::ai_request("OpenAI", status.as_u16(), &error_text));
-        }
-        
-        // Create a new TextStream
-        let mut text_stream = TextStream::new();
-        
-        // Process the streaming response
-        let mut stream = response.bytes_stream();
-        use futures_util::StreamExt;
-        
-        while let Some(chunk_result) = stream.next().await {
-            let chunk = chunk_result.map_err(|e| StoryWeaverError::network(format!("Error reading stream chunk: {}", e)))?;
-            let chunk_str = String::from_utf8_lossy(&chunk);
-            
-            // OpenAI sends "data: " prefixed SSE events
-            for line in chunk_str.lines() {
-                if line.starts_with("data: ") {
-                    let data = &line[6..]; // Skip "data: " prefix
-                    
-                    // Skip empty lines and [DONE] marker
-                    if data.is_empty() || data == "[DONE]" {
-                        continue;
-                    }
-                    
-                    // Parse the JSON data
-                    match serde_json::from_str::<serde_json::Value>(data) {
-                        Ok(json) => {
-                            // Extract the content from the JSON
-                            if let Some(content) = json
-                                .get("choices")
-                                .and_then(|choices| choices.get(0))
-                                .and_then(|choice| choice.get("delta"))
-                                .and_then(|delta| delta.get("content"))
-                                .and_then(|content| content.as_str())
-                            {
-                                text_stream.append(content);
-                            }
-                        },
-                        Err(e) => {
-                            eprintln!("Error parsing JSON from stream: {}", e);
-                            continue;
-                        }
-                    }
-                }
-            }
-        }
-        
-        // Mark the stream as complete
-        text_stream.complete();
-        
-        Ok(text_stream)
+        self.send_chat_stream(&request, estimated_tokens).await
     }
     
     async fn describe_scene(&self, description: &str, context: &AIContext) -> Result<String> {
         // Estimate token usage for rate limiting
-        let estimated_tokens = (description.len() / 4) as u32 + 500;
-        
-        {
-            let mut rate_limiter = self.rate_limiter.lock().await;
-            rate_limiter.wait_if_needed(estimated_tokens).await?;
-        }
+        let estimated_tokens = self.count_tokens(description) + 500;
         
         // Build system message
         let system_message = ChatMessage {
@@ -890,16 +848,15 @@ impl AIProvider for OpenAIProvider {
             temperature: 0.7,
             max_tokens: Some(2000),
             stream: false,
+            response_format: None,
         };
         
         // Make API call
-        let response = self.client.post("https://api.openai.com/v1/chat/completions")
-            .header("Authorization", format!("Bearer {}", self.api_key))
+        let response = self.authorize(self.client.post(self.endpoint("chat/completions")))
             .header("Content-Type", "application/json")
             .json(&request)
-            .send()
-            .await
-            .map_err(|e| StoryWeaverError::network(format!("Failed to send request to OpenAI API: {}", e)))?;
+            .send_with(&self.resilience, estimated_tokens)
+            .await?;
         
         // Check for errors
         let status_code = response.status();
@@ -907,17 +864,16 @@ impl AIProvider for OpenAIProvider {
         let response_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
         
         if !is_success {
-            return Err(StoryWeaverError::ai_request("OpenAI", status_code.as_u16(), &response_text));
+            return Err(StoryWeaverError::ai_request(self.provider_name.as_str(), status_code.as_u16(), &response_text));
         }
         
         // Parse response
         let completion: ChatCompletionResponse = serde_json::from_str(&response_text)
             .map_err(|e| StoryWeaverError::deserialization(format!("Failed to parse OpenAI API response: {}", e)))?;
         
-        // Update rate limiter with actual token usage
+        // Record actual token usage for pacing
         if let Some(usage) = &completion.usage {
-            let mut rate_limiter = self.rate_limiter.lock().await;
-            rate_limiter.update_token_usage(usage);
+            self.resilience.record_usage(estimated_tokens, usage.prompt_tokens, usage.completion_tokens).await;
         }
         
         // Extract generated text
@@ -930,14 +886,9 @@ impl AIProvider for OpenAIProvider {
         }
     }
     
-    async fn describe_scene_stream(&self, description: &str, context: &AIContext) -> Result<TextStream> {
+    async fn describe_scene_stream(&self, description: &str, context: &AIContext) -> Result<TextChunkStream> {
         // Estimate token usage for rate limiting
-        let estimated_tokens = (description.len() / 4) as u32 + 500;
-        
-        {
-            let mut rate_limiter = self.rate_limiter.lock().await;
-            rate_limiter.wait_if_needed(estimated_tokens).await?;
-        }
+        let estimated_tokens = self.count_tokens(description) + 500;
         
         // Build system message
         let system_message = ChatMessage {
@@ -1013,84 +964,15 @@ impl AIProvider for OpenAIProvider {
             temperature: 0.7,
             max_tokens: Some(2000),
             stream: true, // Enable streaming
+            response_format: None,
         };
         
-        // Make API call with streaming
-        let response = self.client.post("https://api.openai.com/v1/chat/completions")
-            .header("Authorization", format!("Bearer {}", self.api_key))
-            .header("Content-Type", "application/json")
-            .json(&request)
-            .send()
-            .await
-            .map_err(|e| StoryWeaverError::network(format!("Failed to send request to OpenAI API: {}", e)))?;
-        
-        // Check for errors
-        let status = response.status();
-        let is_success = status.is_success();
-        
-        if !is_success {
-            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(StoryWeaverError::ai_request("OpenAI", status.as_u16(), &error_text));
-        }
-        
-        // Create a new TextStream
-        let mut text_stream = TextStream::new();
-        
-        // Process the streaming response
-        let mut stream = response.bytes_stream();
-        use futures_util::StreamExt;
-        
-        while let Some(chunk_result) = stream.next().await {
-            let chunk = chunk_result.map_err(|e| StoryWeaverError::network(format!("Error reading stream chunk: {}", e)))?;
-            let chunk_str = String::from_utf8_lossy(&chunk);
-            
-            // OpenAI sends "data: " prefixed SSE events
-            for line in chunk_str.lines() {
-                if line.starts_with("data: ") {
-                    let data = &line[6..]; // Skip "data: " prefix
-                    
-                    // Skip empty lines and [DONE] marker
-                    if data.is_empty() || data == "[DONE]" {
-                        continue;
-                    }
-                    
-                    // Parse the JSON data
-                    match serde_json::from_str::<serde_json::Value>(data) {
-                        Ok(json) => {
-                            // Extract the content from the JSON
-                            if let Some(content) = json
-                                .get("choices")
-                                .and_then(|choices| choices.get(0))
-                                .and_then(|choice| choice.get("delta"))
-                                .and_then(|delta| delta.get("content"))
-                                .and_then(|content| content.as_str())
-                            {
-                                text_stream.append(content);
-                            }
-                        },
-                        Err(e) => {
-                            eprintln!("Error parsing JSON from stream: {}", e);
-                            continue;
-                        }
-                    }
-                }
-            }
-        }
-        
-        // Mark the stream as complete
-        text_stream.complete();
-        
-        Ok(text_stream)
+        self.send_chat_stream(&request, estimated_tokens).await
     }
     
     async fn brainstorm(&self, topic: &str, context: &AIContext) -> Result<Vec<String>> {
         // Estimate token usage for rate limiting
-        let estimated_tokens = (topic.len() / 4) as u32 + 500;
-        
-        {
-            let mut rate_limiter = self.rate_limiter.lock().await;
-            rate_limiter.wait_if_needed(estimated_tokens).await?;
-        }
+        let estimated_tokens = self.count_tokens(topic) + 500;
         
         // Build system message
         let system_message = ChatMessage {
@@ -1133,16 +1015,15 @@ impl AIProvider for OpenAIProvider {
             temperature: 0.8, // Higher temperature for more creative ideas
             max_tokens: Some(2000),
             stream: false,
+            response_format: None,
         };
         
         // Make API call
-        let response = self.client.post("https://api.openai.com/v1/chat/completions")
-            .header("Authorization", format!("Bearer {}", self.api_key))
+        let response = self.authorize(self.client.post(self.endpoint("chat/completions")))
             .header("Content-Type", "application/json")
             .json(&request)
-            .send()
-            .await
-            .map_err(|e| StoryWeaverError::network(format!("Failed to send request to OpenAI API: {}", e)))?;
+            .send_with(&self.resilience, estimated_tokens)
+            .await?;
         
         // Check for errors
         let status = response.status();
@@ -1150,17 +1031,16 @@ impl AIProvider for OpenAIProvider {
         
         if !is_success {
             let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(StoryWeaverError::ai_request("OpenAI", status.as_u16(), &error_text));
+            return Err(StoryWeaverError::ai_request(self.provider_name.as_str(), status.as_u16(), &error_text));
         }
         
         // Parse response
         let completion: ChatCompletionResponse = response.json().await
             .map_err(|e| StoryWeaverError::deserialization(format!("Failed to parse OpenAI API response: {}", e)))?;
         
-        // Update rate limiter with actual token usage
+        // Record actual token usage for pacing
         if let Some(usage) = &completion.usage {
-            let mut rate_limiter = self.rate_limiter.lock().await;
-            rate_limiter.update_token_usage(usage);
+            self.resilience.record_usage(estimated_tokens, usage.prompt_tokens, usage.completion_tokens).await;
         }
         
         // Extract generated text and parse into a list of ideas
@@ -1204,12 +1084,7 @@ impl AIProvider for OpenAIProvider {
     
     async fn related_words(&self, word: &str, context: &AIContext) -> Result<Vec<String>> {
         // Estimate token usage for rate limiting
-        let estimated_tokens = (word.len() / 4) as u32 + 200;
-        
-        {
-            let mut rate_limiter = self.rate_limiter.lock().await;
-            rate_limiter.wait_if_needed(estimated_tokens).await?;
-        }
+        let estimated_tokens = self.count_tokens(word) + 200;
         
         // Build system message
         let system_message = ChatMessage {
@@ -1249,16 +1124,15 @@ impl AIProvider for OpenAIProvider {
             temperature: 0.5,
             max_tokens: Some(500),
             stream: false,
+            response_format: None,
         };
         
         // Make API call
-        let response = self.client.post("https://api.openai.com/v1/chat/completions")
-            .header("Authorization", format!("Bearer {}", self.api_key))
+        let response = self.authorize(self.client.post(self.endpoint("chat/completions")))
             .header("Content-Type", "application/json")
             .json(&request)
-            .send()
-            .await
-            .map_err(|e| StoryWeaverError::network(format!("Failed to send request to OpenAI API: {}", e)))?;
+            .send_with(&self.resilience, estimated_tokens)
+            .await?;
         
         // Check for errors
         let status = response.status();
@@ -1266,17 +1140,16 @@ impl AIProvider for OpenAIProvider {
         
         if !is_success {
             let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(StoryWeaverError::ai_request("OpenAI", status.as_u16(), &error_text));
+            return Err(StoryWeaverError::ai_request(self.provider_name.as_str(), status.as_u16(), &error_text));
         }
         
         // Parse response
         let completion: ChatCompletionResponse = response.json().await
             .map_err(|e| StoryWeaverError::deserialization(format!("Failed to parse OpenAI API response: {}", e)))?;
         
-        // Update rate limiter with actual token usage
+        // Record actual token usage for pacing
         if let Some(usage) = &completion.usage {
-            let mut rate_limiter = self.rate_limiter.lock().await;
-            rate_limiter.update_token_usage(usage);
+            self.resilience.record_usage(estimated_tokens, usage.prompt_tokens, usage.completion_tokens).await;
         }
         
         // Extract generated text and parse into a list of words
@@ -1300,12 +1173,7 @@ impl AIProvider for OpenAIProvider {
     
     async fn quick_edit(&self, text: &str, instruction: &str) -> Result<String> {
         // Estimate token usage for rate limiting
-        let estimated_tokens = (text.len() / 4) as u32 + (instruction.len() / 4) as u32 + 300;
-        
-        {
-            let mut rate_limiter = self.rate_limiter.lock().await;
-            rate_limiter.wait_if_needed(estimated_tokens).await?;
-        }
+        let estimated_tokens = self.count_tokens(text) + self.count_tokens(instruction) + 300;
         
         // Build system message
         let system_message = ChatMessage {
@@ -1327,16 +1195,15 @@ impl AIProvider for OpenAIProvider {
             temperature: 0.5,
             max_tokens: Some((text.len() as u32 * 2).max(500)), // Allow for expansion
             stream: false,
+            response_format: None,
         };
         
         // Make API call
-        let response = self.client.post("https://api.openai.com/v1/chat/completions")
-            .header("Authorization", format!("Bearer {}", self.api_key))
+        let response = self.authorize(self.client.post(self.endpoint("chat/completions")))
             .header("Content-Type", "application/json")
             .json(&request)
-            .send()
-            .await
-            .map_err(|e| StoryWeaverError::network(format!("Failed to send request to OpenAI API: {}", e)))?;
+            .send_with(&self.resilience, estimated_tokens)
+            .await?;
         
         // Check for errors
         let status = response.status();
@@ -1344,17 +1211,16 @@ impl AIProvider for OpenAIProvider {
         
         if !is_success {
             let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(StoryWeaverError::ai_request("OpenAI", status.as_u16(), &error_text));
+            return Err(StoryWeaverError::ai_request(self.provider_name.as_str(), status.as_u16(), &error_text));
         }
         
         // Parse response
         let completion: ChatCompletionResponse = response.json().await
             .map_err(|e| StoryWeaverError::deserialization(format!("Failed to parse OpenAI API response: {}", e)))?;
         
-        // Update rate limiter with actual token usage
+        // Record actual token usage for pacing
         if let Some(usage) = &completion.usage {
-            let mut rate_limiter = self.rate_limiter.lock().await;
-            rate_limiter.update_token_usage(usage);
+            self.resilience.record_usage(estimated_tokens, usage.prompt_tokens, usage.completion_tokens).await;
         }
         
         // Extract generated text
@@ -1369,12 +1235,7 @@ impl AIProvider for OpenAIProvider {
     
     async fn quick_chat(&self, message: &str, context: &AIContext) -> Result<String> {
         // Estimate token usage for rate limiting
-        let estimated_tokens = (message.len() / 4) as u32 + 300;
-        
-        {
-            let mut rate_limiter = self.rate_limiter.lock().await;
-            rate_limiter.wait_if_needed(estimated_tokens).await?;
-        }
+        let estimated_tokens = self.count_tokens(message) + 300;
         
         // Build system message
         let mut system_content = "You are StoryWeaver, an AI writing assistant. You help the user with their writing project by answering questions and providing guidance.".to_string();
@@ -1406,16 +1267,15 @@ impl AIProvider for OpenAIProvider {
             temperature: 0.7,
             max_tokens: Some(1000),
             stream: false,
+            response_format: None,
         };
         
         // Make API call
-        let response = self.client.post("https://api.openai.com/v1/chat/completions")
-            .header("Authorization", format!("Bearer {}", self.api_key))
+        let response = self.authorize(self.client.post(self.endpoint("chat/completions")))
             .header("Content-Type", "application/json")
             .json(&request)
-            .send()
-            .await
-            .map_err(|e| StoryWeaverError::network(format!("Failed to send request to OpenAI API: {}", e)))?;
+            .send_with(&self.resilience, estimated_tokens)
+            .await?;
         
         // Check for errors
         let status = response.status();
@@ -1423,17 +1283,16 @@ impl AIProvider for OpenAIProvider {
         
         if !is_success {
             let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(StoryWeaverError::ai_request("OpenAI", status.as_u16(), &error_text));
+            return Err(StoryWeaverError::ai_request(self.provider_name.as_str(), status.as_u16(), &error_text));
         }
         
         // Parse response
         let completion: ChatCompletionResponse = response.json().await
             .map_err(|e| StoryWeaverError::deserialization(format!("Failed to parse OpenAI API response: {}", e)))?;
         
-        // Update rate limiter with actual token usage
+        // Record actual token usage for pacing
         if let Some(usage) = &completion.usage {
-            let mut rate_limiter = self.rate_limiter.lock().await;
-            rate_limiter.update_token_usage(usage);
+            self.resilience.record_usage(estimated_tokens, usage.prompt_tokens, usage.completion_tokens).await;
         }
         
         // Extract generated text
@@ -1446,14 +1305,9 @@ impl AIProvider for OpenAIProvider {
         }
     }
     
-    async fn quick_chat_stream(&self, message: &str, context: &AIContext) -> Result<TextStream> {
+    async fn quick_chat_stream(&self, message: &str, context: &AIContext) -> Result<TextChunkStream> {
         // Estimate token usage for rate limiting
-        let estimated_tokens = (message.len() / 4) as u32 + 300;
-        
-        {
-            let mut rate_limiter = self.rate_limiter.lock().await;
-            rate_limiter.wait_if_needed(estimated_tokens).await?;
-        }
+        let estimated_tokens = self.count_tokens(message) + 300;
         
         // Build system message
         let mut system_content = "You are StoryWeaver, an AI writing assistant. You help the user with their writing project by answering questions and providing guidance.".to_string();
@@ -1485,84 +1339,21 @@ impl AIProvider for OpenAIProvider {
             temperature: 0.7,
             max_tokens: Some(1000),
             stream: true, // Enable streaming
+            response_format: None,
         };
         
-        // Make API call with streaming
-        let response = self.client.post("https://api.openai.com/v1/chat/completions")
-            .header("Authorization", format!("Bearer {}", self.api_key))
-            .header("Content-Type", "application/json")
-            .json(&request)
-            .send()
-            .await
-            .map_err(|e| StoryWeaverError::network(format!("Failed to send request to OpenAI API: {}", e)))?;
-        
-        // Check for errors
-        let status = response.status();
-        let is_success = status.is_success();
-        
-        if !is_success {
-            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(StoryWeaverError::ai_request("OpenAI", status.as_u16(), &error_text));
-        }
-        
-        // Create a new TextStream
-        let mut text_stream = TextStream::new();
-        
-        // Process the streaming response
-        let mut stream = response.bytes_stream();
-        use futures_util::StreamExt;
-        
-        while let Some(chunk_result) = stream.next().await {
-            let chunk = chunk_result.map_err(|e| StoryWeaverError::network(format!("Error reading stream chunk: {}", e)))?;
-            let chunk_str = String::from_utf8_lossy(&chunk);
-            
-            // OpenAI sends "data: " prefixed SSE events
-            for line in chunk_str.lines() {
-                if line.starts_with("data: ") {
-                    let data = &line[6..]; // Skip "data: " prefix
-                    
-                    // Skip empty lines and [DONE] marker
-                    if data.is_empty() || data == "[DONE]" {
-                        continue;
-                    }
-                    
-                    // Parse the JSON data
-                    match serde_json::from_str::<serde_json::Value>(data) {
-                        Ok(json) => {
-                            // Extract the content from the JSON
-                            if let Some(content) = json
-                                .get("choices")
-                                .and_then(|choices| choices.get(0))
-                                .and_then(|choice| choice.get("delta"))
-                                .and_then(|delta| delta.get("content"))
-                                .and_then(|content| content.as_str())
-                            {
-                                text_stream.append(content);
-                            }
-                        },
-                        Err(e) => {
-                            eprintln!("Error parsing JSON from stream: {}", e);
-                            continue;
-                        }
-                    }
-                }
-            }
-        }
-        
-        // Mark the stream as complete
-        text_stream.complete();
-        
-        Ok(text_stream)
+        self.send_chat_stream(&request, estimated_tokens).await
     }
     
     async fn generate_image(&self, prompt: &str) -> Result<String> {
-        // Estimate token usage for rate limiting
-        let estimated_tokens = (prompt.len() / 4) as u32 + 100;
-        
-        {
-            let mut rate_limiter = self.rate_limiter.lock().await;
-            rate_limiter.wait_if_needed(estimated_tokens).await?;
+        if !self.image_generation {
+            return Err(StoryWeaverError::NotSupported {
+                operation: format!("Image generation is not available for {}", self.provider_name),
+            });
         }
+
+        // Estimate token usage for rate limiting
+        let estimated_tokens = self.count_tokens(prompt) + 100;
         
         // Build DALL-E 3 request
         let request = serde_json::json!({
@@ -1575,14 +1366,11 @@ impl AIProvider for OpenAIProvider {
         });
         
         // Make API call to DALL-E
-        let response = self.client
-            .post("https://api.openai.com/v1/images/generations")
-            .header("Authorization", format!("Bearer {}", self.api_key))
+        let response = self.authorize(self.client.post(self.endpoint("images/generations")))
             .header("Content-Type", "application/json")
             .json(&request)
-            .send()
-            .await
-            .map_err(|e| StoryWeaverError::network(format!("Failed to send request to DALL-E API: {}", e)))?;
+            .send_with(&self.resilience, estimated_tokens)
+            .await?;
         
         // Check for errors
         let status = response.status();
@@ -1590,23 +1378,13 @@ impl AIProvider for OpenAIProvider {
         
         if !is_success {
             let error_text = response.text().await.unwrap_or_default();
-            return Err(StoryWeaverError::ai_request("OpenAI", status.as_u16(), &error_text));
+            return Err(StoryWeaverError::ai_request(self.provider_name.as_str(), status.as_u16(), &error_text));
         }
         
         // Parse response
         let response_json: serde_json::Value = response.json().await
             .map_err(|e| StoryWeaverError::deserialization(format!("Failed to parse DALL-E response: {}", e)))?;
         
-        // Update rate limiter with actual usage
-        {
-            let mut rate_limiter = self.rate_limiter.lock().await;
-            rate_limiter.update_token_usage(&TokenUsage {
-                prompt_tokens: estimated_tokens,
-                completion_tokens: 0,
-                total_tokens: estimated_tokens,
-            });
-        }
-        
         // Extract image URL from response
         if let Some(data) = response_json["data"].as_array() {
             if let Some(first_image) = data.first() {
@@ -0,0 +1,805 @@
+//! Prompt template registry
+//!
+//! Prompts are named, versioned templates stored in `prompt_templates`, seeded
+//! from the built-in versions below. A version can apply everywhere or
+//! override the default for one project, one prose mode, or both; the most
+//! specific active scope wins. Several versions active in one scope split its
+//! traffic by weight, and each project is bucketed deterministically so it
+//! keeps seeing the same version. Every render reports the template name and
+//! version so the AI history can record which prompt produced a generation.
+//!
+//! Templates use a small handlebars-style syntax:
+//! `{{name}}`, `{{#if name}}...{{else}}...{{/if}}`, `{{#if name == "value"}}`,
+//! `{{#unless name}}...{{/unless}}` and `{{#each list}}{{this}}{{@index}}{{/each}}`.
+//! Lists render comma-separated when substituted directly.
+
+use super::{AIContext, RewriteStyle};
+use crate::database::operations::{PromptTemplate, PromptTemplateOps, TemplateVariable, VariableKind};
+use crate::database::DbPool;
+use crate::error::{Result, StoryWeaverError};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::future::Future;
+use std::sync::{Arc, Mutex, RwLock};
+
+/// Where a prompt is being rendered, for picking overrides
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PromptScope {
+    pub project_id: Option<String>,
+    pub prose_mode: Option<String>,
+}
+
+impl PromptScope {
+    pub fn global() -> Self {
+        Self::default()
+    }
+
+    pub fn for_project(project_id: &str) -> Self {
+        Self { project_id: Some(project_id.to_string()), prose_mode: None }
+    }
+
+    pub fn from_context(context: &AIContext) -> Self {
+        Self { project_id: context.project_id.clone(), prose_mode: context.prose_mode.clone() }
+    }
+}
+
+/// The template version behind a prompt, as recorded in the AI history
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TemplateUsage {
+    pub name: String,
+    pub version: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RenderedPrompt {
+    pub text: String,
+    pub template: TemplateUsage,
+}
+
+// ---------------------------------------------------------------------------
+// Template syntax
+// ---------------------------------------------------------------------------
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Text(String),
+    Var(String),
+    If { path: String, equals: Option<String>, negate: bool, then: Vec<Node>, otherwise: Vec<Node> },
+    Each { path: String, body: Vec<Node> },
+}
+
+enum Token {
+    Text(String),
+    Tag(String),
+}
+
+/// A parsed template body
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    nodes: Vec<Node>,
+}
+
+impl Template {
+    pub fn parse(body: &str) -> Result<Self> {
+        let mut tokens = tokenize(body)?.into_iter();
+        let (nodes, _) = parse_block(&mut tokens, None)?;
+        Ok(Self { nodes })
+    }
+
+    pub fn render(&self, values: &Value) -> String {
+        let mut out = String::new();
+        render_nodes(&self.nodes, values, &mut Vec::new(), &mut out);
+        out
+    }
+
+    /// Variables the template reads outside `{{#each}}` bodies, where item
+    /// fields can't be told apart from template variables
+    fn root_variables(&self) -> Vec<&str> {
+        fn collect<'a>(nodes: &'a [Node], names: &mut Vec<&'a str>) {
+            for node in nodes {
+                match node {
+                    Node::Text(_) => {}
+                    Node::Var(path) => names.push(root_of(path)),
+                    Node::If { path, then, otherwise, .. } => {
+                        names.push(root_of(path));
+                        collect(then, names);
+                        collect(otherwise, names);
+                    }
+                    Node::Each { path, .. } => names.push(root_of(path)),
+                }
+            }
+        }
+        let mut names = Vec::new();
+        collect(&self.nodes, &mut names);
+        names.retain(|name| *name != "this" && !name.starts_with('@'));
+        names
+    }
+}
+
+fn root_of(path: &str) -> &str {
+    path.split('.').next().unwrap_or(path)
+}
+
+fn tokenize(body: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut rest = body;
+    while let Some(start) = rest.find("{{") {
+        if start > 0 {
+            tokens.push(Token::Text(rest[..start].to_string()));
+        }
+        let end = rest[start..]
+            .find("}}")
+            .ok_or_else(|| StoryWeaverError::invalid_input("Prompt template has a \"{{\" without a closing \"}}\""))?;
+        tokens.push(Token::Tag(rest[start + 2..start + end].trim().to_string()));
+        rest = &rest[start + end + 2..];
+    }
+    if !rest.is_empty() {
+        tokens.push(Token::Text(rest.to_string()));
+    }
+    Ok(tokens)
+}
+
+fn valid_path(path: &str) -> bool {
+    !path.is_empty()
+        && path
+            .split('.')
+            .all(|part| !part.is_empty() && part.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '@'))
+}
+
+fn checked_path(path: &str, tag: &str) -> Result<String> {
+    let path = path.trim();
+    if valid_path(path) {
+        Ok(path.to_string())
+    } else {
+        Err(StoryWeaverError::invalid_input(format!("Invalid variable in prompt template tag {{{{{}}}}}", tag)))
+    }
+}
+
+/// Parse nodes until the block `open` closes. Returns the nodes and whether
+/// they ended at an `{{else}}`.
+fn parse_block(tokens: &mut std::vec::IntoIter<Token>, open: Option<&str>) -> Result<(Vec<Node>, bool)> {
+    let mut nodes = Vec::new();
+    while let Some(token) = tokens.next() {
+        let tag = match token {
+            Token::Text(text) => {
+                nodes.push(Node::Text(text));
+                continue;
+            }
+            Token::Tag(tag) => tag,
+        };
+
+        if tag == "else" {
+            return match open {
+                Some("if") | Some("unless") => Ok((nodes, true)),
+                _ => Err(StoryWeaverError::invalid_input("Prompt template has {{else}} outside an {{#if}} block")),
+            };
+        }
+        if let Some(closing) = tag.strip_prefix('/') {
+            return if Some(closing.trim()) == open {
+                Ok((nodes, false))
+            } else {
+                Err(StoryWeaverError::invalid_input(format!("Prompt template has an unexpected {{{{{}}}}}", tag)))
+            };
+        }
+
+        if let Some(condition) = tag.strip_prefix("#if ").or_else(|| tag.strip_prefix("#unless ")) {
+            let block = if tag.starts_with("#if") { "if" } else { "unless" };
+            let (path, equals, negate) = match condition.split_once("==").map(|parts| (parts, false)).or_else(|| {
+                condition.split_once("!=").map(|parts| (parts, true))
+            }) {
+                Some(((path, literal), negate)) => {
+                    let literal = literal.trim();
+                    let literal = literal
+                        .strip_prefix('"')
+                        .and_then(|l| l.strip_suffix('"'))
+                        .ok_or_else(|| {
+                            StoryWeaverError::invalid_input(format!(
+                                "Prompt template tag {{{{{}}}}} must compare with a \"quoted\" value",
+                                tag
+                            ))
+                        })?;
+                    (checked_path(path, &tag)?, Some(literal.to_string()), negate)
+                }
+                None => (checked_path(condition, &tag)?, None, false),
+            };
+            let (then, has_else) = parse_block(tokens, Some(block))?;
+            let otherwise = if has_else {
+                match parse_block(tokens, Some(block))? {
+                    (_, true) => {
+                        return Err(StoryWeaverError::invalid_input("Prompt template has two {{else}} in one block"));
+                    }
+                    (nodes, false) => nodes,
+                }
+            } else {
+                Vec::new()
+            };
+            nodes.push(Node::If { path, equals, negate: negate ^ (block == "unless"), then, otherwise });
+        } else if let Some(list) = tag.strip_prefix("#each ") {
+            let path = checked_path(list, &tag)?;
+            let (body, _) = parse_block(tokens, Some("each"))?;
+            nodes.push(Node::Each { path, body });
+        } else if tag.starts_with('#') {
+            return Err(StoryWeaverError::invalid_input(format!("Unknown prompt template block {{{{{}}}}}", tag)));
+        } else {
+            nodes.push(Node::Var(checked_path(&tag, &tag)?));
+        }
+    }
+
+    match open {
+        Some(block) => Err(StoryWeaverError::invalid_input(format!("Prompt template never closes {{{{#{}}}}}", block))),
+        None => Ok((nodes, false)),
+    }
+}
+
+/// `(item, index)` for each enclosing `{{#each}}`, innermost last
+type Frames = Vec<(Value, usize)>;
+
+fn lookup(path: &str, values: &Value, frames: &Frames) -> Value {
+    let mut parts = path.split('.');
+    let root = parts.next().unwrap_or_default();
+    let mut value = match root {
+        "this" => frames.last().map(|(item, _)| item.clone()).unwrap_or(Value::Null),
+        "@index" => frames.last().map(|(_, index)| json!(index)).unwrap_or(Value::Null),
+        _ => frames
+            .iter()
+            .rev()
+            .find_map(|(item, _)| item.get(root).cloned())
+            .or_else(|| values.get(root).cloned())
+            .unwrap_or(Value::Null),
+    };
+    for part in parts {
+        value = value.get(part).cloned().unwrap_or(Value::Null);
+    }
+    value
+}
+
+fn truthy(value: &Value) -> bool {
+    match value {
+        Value::Null => false,
+        Value::Bool(b) => *b,
+        Value::Number(n) => n.as_f64().is_some_and(|n| n != 0.0),
+        Value::String(s) => !s.trim().is_empty(),
+        Value::Array(items) => !items.is_empty(),
+        Value::Object(fields) => !fields.is_empty(),
+    }
+}
+
+fn display(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        Value::Array(items) => items.iter().map(display).collect::<Vec<_>>().join(", "),
+        other => other.to_string(),
+    }
+}
+
+fn render_nodes(nodes: &[Node], values: &Value, frames: &mut Frames, out: &mut String) {
+    for node in nodes {
+        match node {
+            Node::Text(text) => out.push_str(text),
+            Node::Var(path) => out.push_str(&display(&lookup(path, values, frames))),
+            Node::If { path, equals, negate, then, otherwise } => {
+                let value = lookup(path, values, frames);
+                let holds = match equals {
+                    Some(literal) => display(&value) == *literal,
+                    None => truthy(&value),
+                };
+                render_nodes(if holds != *negate { then } else { otherwise }, values, frames, out);
+            }
+            Node::Each { path, body } => {
+                if let Value::Array(items) = lookup(path, values, frames) {
+                    for (index, item) in items.into_iter().enumerate() {
+                        frames.push((item, index));
+                        render_nodes(body, values, frames, out);
+                        frames.pop();
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Parse `body` and check it only reads declared variables
+pub fn check_template(body: &str, variables: &[TemplateVariable]) -> Result<Template> {
+    let template = Template::parse(body)?;
+    if let Some(unknown) = template
+        .root_variables()
+        .into_iter()
+        .find(|name| !variables.iter().any(|v| v.name == *name))
+    {
+        return Err(StoryWeaverError::invalid_input(format!(
+            "Prompt template uses undeclared variable \"{}\"",
+            unknown
+        )));
+    }
+    Ok(template)
+}
+
+/// Check `values` supplies every required variable with the declared type
+pub fn check_values(template_name: &str, variables: &[TemplateVariable], values: &Value) -> Result<()> {
+    for variable in variables {
+        let value = values.get(&variable.name).unwrap_or(&Value::Null);
+        let matches = match (variable.kind, value) {
+            (_, Value::Null) => {
+                if variable.required {
+                    return Err(StoryWeaverError::invalid_input(format!(
+                        "Prompt template {} requires variable \"{}\"",
+                        template_name, variable.name
+                    )));
+                }
+                true
+            }
+            (VariableKind::Text, v) => v.is_string(),
+            (VariableKind::Number, v) => v.is_number(),
+            (VariableKind::Boolean, v) => v.is_boolean(),
+            (VariableKind::List, v) => v.is_array(),
+        };
+        if !matches {
+            return Err(StoryWeaverError::invalid_input(format!(
+                "Prompt template {} expects variable \"{}\" to be {:?}",
+                template_name, variable.name, variable.kind
+            )));
+        }
+    }
+    Ok(())
+}
+
+// ---------------------------------------------------------------------------
+// Built-in templates
+// ---------------------------------------------------------------------------
+
+fn builtin(name: &str, description: &str, body: &str, variables: Vec<TemplateVariable>) -> PromptTemplate {
+    PromptTemplate {
+        id: format!("builtin:{}", name),
+        version: 1,
+        description: Some(description.to_string()),
+        ..PromptTemplate::new(name, body, variables)
+    }
+}
+
+/// Version 1 of every template the app renders
+pub fn builtin_templates() -> Vec<PromptTemplate> {
+    use TemplateVariable as V;
+    use VariableKind::*;
+
+    vec![
+        builtin(
+            "system",
+            "System prompt for writing requests",
+            "You are StoryWeaver, an AI writing assistant. Help the user write their story.\
+             {{#if genre}} The story is in the {{genre}} genre.{{/if}}\
+             {{#if writing_style}} Use a {{writing_style}} writing style.{{/if}}",
+            vec![V::optional("genre", Text), V::optional("writing_style", Text), V::optional("tone", Text)],
+        ),
+        builtin(
+            "rewrite",
+            "Instruction for rewriting selected text in a style",
+            "{{#if style == \"rephrase\"}}Rephrase this text while keeping the same meaning:{{/if}}\
+             {{#if style == \"shorter\"}}Rewrite this text to be more concise:{{/if}}\
+             {{#if style == \"more_descriptive\"}}Rewrite this text to be more descriptive and vivid:{{/if}}\
+             {{#if style == \"longer\"}}Expand this text with more details and elaboration:{{/if}}\
+             {{#if style == \"more_formal\"}}Rewrite this text in a more formal, professional tone:{{/if}}\
+             {{#if style == \"more_casual\"}}Rewrite this text in a more casual, conversational tone:{{/if}}\
+             {{#if style == \"more_vivid\"}}Rewrite this text with more vivid imagery and sensory details:{{/if}}\
+             {{#if style == \"more_direct\"}}Rewrite this text to be more direct and straightforward:{{/if}}\
+             {{#if style == \"more_poetic\"}}Rewrite this text in a more poetic, lyrical style:{{/if}}\
+             {{#if style == \"tone_shift\"}}Rewrite this text in a {{tone}} tone:{{/if}}",
+            vec![V::required("style", Text), V::optional("tone", Text)],
+        ),
+        builtin(
+            "brainstorm",
+            "Brainstorming prompt for a category of ideas",
+            "{{#if category == \"characters\"}}Generate creative character ideas including their personality traits, backgrounds, motivations, and unique characteristics. Consider diverse perspectives and compelling flaws.{{/if}}\
+             {{#if category == \"plot\"}}Brainstorm plot ideas, story arcs, plot twists, and narrative structures. Focus on compelling conflicts and satisfying resolutions.{{/if}}\
+             {{#if category == \"worldbuilding\"}}Create worldbuilding elements including cultures, societies, magic systems, technology, geography, and unique world features.{{/if}}\
+             {{#if category == \"dialogue\"}}Generate dialogue ideas, character voice patterns, memorable quotes, and conversation scenarios that reveal character and advance plot.{{/if}}\
+             {{#if category == \"scenes\"}}Brainstorm scene ideas, dramatic moments, action sequences, and emotional beats that would be compelling to read.{{/if}}\
+             {{#if category == \"themes\"}}Explore thematic elements, deeper meanings, philosophical questions, and universal truths that could be woven throughout the story.{{/if}}\
+             {{#if category == \"conflicts\"}}Generate conflict ideas including internal struggles, interpersonal tensions, societal issues, and external obstacles.{{/if}}\
+             {{#if category == \"settings\"}}Create setting ideas including locations, environments, atmospheres, and places that would enhance the story's mood and themes.{{/if}}\
+             {{#if category == \"relationships\"}}Brainstorm relationship dynamics, character interactions, romantic elements, friendships, rivalries, and family connections.{{/if}}\
+             {{#if category == \"backstory\"}}Generate backstory elements, character histories, past events, formative experiences, and hidden secrets that shape the present narrative.{{/if}}\
+             {{#if category == \"custom\"}}Generate creative ideas related to the specified topic. Think outside the box and explore unique angles and perspectives.{{/if}}\
+             {{#if seed}}\n\nSeed idea: {{seed}}{{/if}}\
+             {{#if context}}\n\nContext: {{context}}{{/if}}\
+             {{#if focus_areas}}\n\nFocus on: {{focus_areas}}{{/if}}\
+             {{#if extra_creative}}\n\nBe especially creative and think outside the box. Explore unconventional ideas and unique perspectives.{{/if}}\
+             \n\nGenerate {{num_ideas}} distinct ideas.",
+            vec![
+                V::required("category", Text),
+                V::optional("topic", Text),
+                V::optional("seed", Text),
+                V::optional("context", Text),
+                V::optional("focus_areas", List),
+                V::optional("extra_creative", Boolean),
+                V::required("num_ideas", Number),
+            ],
+        ),
+        builtin(
+            "brainstorm_more",
+            "Brainstorming prompt for more ideas like the ones kept",
+            "The writer is brainstorming {{category}} ideas and kept these:\
+             {{#each keepers}}\n- {{this}}{{/each}}\
+             \n\nGenerate {{num_ideas}} new ideas in the same spirit. Build on what makes the kept ideas work, \
+             but don't repeat or lightly reword them{{#if others}} or any of these earlier ideas:{{#each others}}\n- {{this}}{{/each}}{{else}}.{{/if}}",
+            vec![
+                V::required("category", Text),
+                V::required("keepers", List),
+                V::optional("others", List),
+                V::required("num_ideas", Number),
+            ],
+        ),
+        builtin(
+            "brainstorm_merge",
+            "Brainstorming prompt combining two ideas into one",
+            "Combine these two {{category}} ideas into a single idea that keeps the strongest parts of both.\
+             \n\nFirst idea: {{first}}\n\nSecond idea: {{second}}\
+             \n\nReply with the combined idea only, in one or two sentences.",
+            vec![V::required("category", Text), V::required("first", Text), V::required("second", Text)],
+        ),
+        builtin(
+            "character_traits",
+            "Story bible: traits for a character",
+            "Generate {{trait_count}} character traits for {{character_name}}.\
+             {{#if existing_traits}} Don't repeat these existing traits: {{existing_traits}}.{{/if}}\
+             {{#if custom_prompt}}\n\n{{custom_prompt}}{{/if}}",
+            vec![
+                V::required("trait_count", Number),
+                V::required("character_name", Text),
+                V::optional("existing_traits", List),
+                V::optional("custom_prompt", Text),
+            ],
+        ),
+        builtin(
+            "world_element",
+            "Story bible: a new world element",
+            "Create the {{element_type}} \"{{name}}\" for this story.\
+             {{#if existing_elements}} Stay consistent with these existing elements: {{existing_elements}}.{{/if}}\
+             {{#if custom_prompt}}\n\n{{custom_prompt}}{{/if}}",
+            vec![
+                V::required("element_type", Text),
+                V::required("name", Text),
+                V::optional("existing_elements", List),
+                V::optional("custom_prompt", Text),
+            ],
+        ),
+        builtin(
+            "outline",
+            "Story bible: a chapter-by-chapter outline",
+            "{{#if custom_prompt}}{{custom_prompt}}{{else}}Generate a detailed story outline, chapter by chapter{{/if}}",
+            vec![V::optional("custom_prompt", Text)],
+        ),
+        builtin(
+            "scene",
+            "Story bible: content for an outlined scene",
+            "{{#if custom_prompt}}{{custom_prompt}}{{else}}Generate scene content for: {{scene_title}}{{/if}}",
+            vec![V::required("scene_title", Text), V::optional("scene_summary", Text), V::optional("custom_prompt", Text)],
+        ),
+        builtin(
+            "chapter_scene",
+            "Chapter draft: prose for one scene of an outlined chapter",
+            "Write scene {{scene_number}} of {{scene_count}}{{#if chapter_title}} of the chapter \"{{chapter_title}}\"{{/if}} as finished prose.\
+             {{#if chapter_summary}}\n\nChapter summary: {{chapter_summary}}{{/if}}\
+             {{#if previous_summary}}\n\nThe previous scene: {{previous_summary}}{{else}}\n\nThis is the chapter's opening scene.{{/if}}\
+             {{#if scene_title}}\n\nScene: {{scene_title}}{{/if}}\
+             {{#if scene_summary}}\nWhat happens: {{scene_summary}}{{/if}}\
+             {{#if pov}}\n\nPoint of view: {{pov}}{{/if}}\
+             {{#if pov_characters}}\nPOV characters: {{pov_characters}}{{/if}}\
+             {{#if tense}}\nTense: {{tense}}{{/if}}\
+             {{#if word_target}}\nLength: about {{word_target}} words{{/if}}\
+             {{#if extra_instructions}}\n\nAdditional instructions: {{extra_instructions}}{{/if}}\
+             \n\nPick up where the previous scene left off. Reply with the scene's prose only, without a heading.",
+            vec![
+                V::optional("chapter_title", Text),
+                V::optional("chapter_summary", Text),
+                V::required("scene_number", Number),
+                V::required("scene_count", Number),
+                V::optional("scene_title", Text),
+                V::optional("scene_summary", Text),
+                V::optional("previous_summary", Text),
+                V::optional("pov", Text),
+                V::optional("pov_characters", List),
+                V::optional("tense", Text),
+                V::optional("word_target", Number),
+                V::optional("extra_instructions", Text),
+            ],
+        ),
+        builtin(
+            "scene_recap",
+            "Chapter draft: summary of a drafted scene for the next one",
+            "Summarize this scene in two or three sentences for the writer of the next scene. \
+             Say where and how it ends, who is present, and what is left unresolved.\n\n{{scene}}",
+            vec![V::required("scene", Text)],
+        ),
+        builtin(
+            "summary_scene",
+            "Story summaries: one scene of the manuscript",
+            "Summarize this scene in three to five sentences for a writer continuing the story. \
+             Say what happens, who is present and where, and what the characters learn or decide.\
+             {{#if title}}\n\nScene: {{title}}{{/if}}\n\n{{text}}",
+            vec![V::optional("title", Text), V::required("text", Text)],
+        ),
+        builtin(
+            "summary_chapter",
+            "Story summaries: one chapter, from its scene summaries or its text",
+            "Summarize this chapter in one paragraph of at most eight sentences for a writer continuing the story. \
+             Keep the events in order and keep every name, place and unresolved question.\
+             {{#if title}}\n\nChapter: {{title}}{{/if}}\
+             {{#if scene_summaries}}\n\nIts scenes, in order:\n{{scene_summaries}}{{/if}}\
+             {{#if text}}\n\n{{text}}{{/if}}",
+            vec![
+                V::optional("title", Text),
+                V::optional("scene_summaries", Text),
+                V::optional("text", Text),
+            ],
+        ),
+        builtin(
+            "summary_book",
+            "Story summaries: the whole manuscript, from its chapter summaries",
+            "Summarize the story so far in at most three paragraphs for a writer continuing it. \
+             Cover the main characters, the central conflict and where each plot thread stands.\
+             \n\nThe chapters, in order:\n{{chapter_summaries}}",
+            vec![V::required("chapter_summaries", Text)],
+        ),
+        builtin(
+            "continuity_claims",
+            "Continuity check: facts a passage states about characters and places",
+            "List the facts this passage states outright about the characters and places below. \
+             For each fact give the subject's name as listed, the attribute, the value, \
+             and a short quote copied exactly from the passage.\
+             \n\nAttributes: eye_color, hair_color, age (in years), title (such as Captain or Lady), \
+             home and birthplace (the name of a place), location_kind (what kind of place a location is, such as city or castle), \
+             knows and learns (a fact the character knows or finds out), unaware (a fact the character doesn't know).\
+             {{#if characters}}\n\nCharacters: {{characters}}{{/if}}\
+             {{#if places}}\nPlaces: {{places}}{{/if}}\
+             \n\n{{text}}",
+            vec![V::optional("characters", List), V::optional("places", List), V::required("text", Text)],
+        ),
+        builtin(
+            "story_bible_extraction",
+            "Story bible extraction: entries a manuscript passage introduces",
+            "Read this passage from a novel and list the story bible entries it gives evidence for: \
+             characters, locations, world elements (organizations, objects, magic, customs, creatures) \
+             and timeline events. Use each entry's fullest name in the passage. For each entry give the kind, \
+             the name, a category (a character's role, a location's type, a world element's type), \
+             a one-sentence description of what the passage says about it, the story date of an event if the passage gives one, \
+             and a short quote copied exactly from the passage.\
+             {{#if known}}\n\nLeave out these, which are already in the story bible: {{known}}{{/if}}\
+             \n\n{{text}}",
+            vec![V::optional("known", List), V::required("text", Text)],
+        ),
+        builtin(
+            "style_analysis",
+            "Story bible: style prompt from an example",
+            "{{#if custom_prompt}}{{custom_prompt}}{{else}}Analyze the writing style and generate a style prompt{{/if}}",
+            vec![V::optional("custom_prompt", Text)],
+        ),
+        builtin(
+            "outline_from_text",
+            "Story bible: outline from pasted text",
+            "{{#if custom_prompt}}{{custom_prompt}}{{else}}Generate an outline from the provided text{{/if}}",
+            vec![V::optional("custom_prompt", Text)],
+        ),
+    ]
+}
+
+/// Variables for the `rewrite` template
+pub fn rewrite_values(style: &RewriteStyle) -> Value {
+    let (key, tone) = match style {
+        RewriteStyle::Rephrase => ("rephrase", None),
+        RewriteStyle::Shorter => ("shorter", None),
+        RewriteStyle::MoreDescriptive => ("more_descriptive", None),
+        RewriteStyle::Longer => ("longer", None),
+        RewriteStyle::MoreFormal => ("more_formal", None),
+        RewriteStyle::MoreCasual => ("more_casual", None),
+        RewriteStyle::MoreVivid => ("more_vivid", None),
+        RewriteStyle::MoreDirect => ("more_direct", None),
+        RewriteStyle::MorePoetic => ("more_poetic", None),
+        RewriteStyle::ToneShift(tone) => ("tone_shift", Some(tone.as_str())),
+    };
+    json!({ "style": key, "tone": tone })
+}
+
+/// Variables for the `system` template
+pub fn system_values(context: &AIContext) -> Value {
+    json!({ "genre": context.genre, "writing_style": context.writing_style, "tone": context.tone })
+}
+
+// ---------------------------------------------------------------------------
+// Registry
+// ---------------------------------------------------------------------------
+
+struct Entry {
+    template: PromptTemplate,
+    parsed: Template,
+}
+
+/// The active template versions, parsed and ready to render
+#[derive(Default)]
+pub struct PromptRegistry {
+    entries: Vec<Entry>,
+}
+
+static BUILTIN_REGISTRY: Lazy<PromptRegistry> = Lazy::new(|| PromptRegistry::from_templates(builtin_templates()));
+
+static PROMPT_REGISTRY: Lazy<RwLock<Arc<PromptRegistry>>> = Lazy::new(|| RwLock::new(Arc::new(PromptRegistry::default())));
+
+impl PromptRegistry {
+    /// Registry of the given active versions; ones that no longer parse are
+    /// skipped so the wider scope (or the built-in) applies instead
+    pub fn from_templates(templates: Vec<PromptTemplate>) -> Self {
+        let entries = templates
+            .into_iter()
+            .filter(|t| t.traffic_weight > 0)
+            .filter_map(|template| match Template::parse(&template.body) {
+                Ok(parsed) => Some(Entry { template, parsed }),
+                Err(e) => {
+                    tracing::warn!("Skipping prompt template {} v{}: {}", template.name, template.version, e);
+                    None
+                }
+            })
+            .collect();
+        Self { entries }
+    }
+
+    /// Seed the built-ins into `pool` and load every active version
+    pub async fn load(pool: &DbPool) -> Result<Self> {
+        for template in builtin_templates() {
+            PromptTemplateOps::seed_builtin(pool, &template).await?;
+        }
+        Ok(Self::from_templates(PromptTemplateOps::get_active(pool).await?))
+    }
+
+    /// The version of `name` to use in `scope`: project and prose mode
+    /// overrides first, then project, then prose mode, then the default
+    pub fn resolve(&self, name: &str, scope: &PromptScope) -> Option<&PromptTemplate> {
+        let project = scope.project_id.as_deref();
+        let mode = scope.prose_mode.as_deref();
+        let levels = [(project, mode), (project, None), (None, mode), (None, None)];
+
+        levels.iter().find_map(|(project, mode)| {
+            let candidates: Vec<&PromptTemplate> = self
+                .entries
+                .iter()
+                .map(|e| &e.template)
+                .filter(|t| t.name == name && t.project_id.as_deref() == *project && t.prose_mode.as_deref() == *mode)
+                .collect();
+            pick_weighted(&candidates, &format!("{}:{}", name, scope.project_id.as_deref().unwrap_or_default()))
+        })
+    }
+
+    /// Render `name` for `scope`, falling back to the built-in version
+    pub fn render(&self, name: &str, scope: &PromptScope, values: &Value) -> Result<RenderedPrompt> {
+        let (template, parsed) = match self.resolve(name, scope) {
+            Some(template) => (template, self.parsed(template)),
+            None => {
+                let template = BUILTIN_REGISTRY
+                    .resolve(name, &PromptScope::global())
+                    .ok_or_else(|| StoryWeaverError::not_found("prompt template", name))?;
+                (template, BUILTIN_REGISTRY.parsed(template))
+            }
+        };
+        check_values(name, &template.variables, values)?;
+
+        Ok(RenderedPrompt {
+            text: parsed.render(values),
+            template: TemplateUsage { name: template.name.clone(), version: template.version },
+        })
+    }
+
+    fn parsed(&self, template: &PromptTemplate) -> &Template {
+        &self
+            .entries
+            .iter()
+            .find(|e| e.template.id == template.id)
+            .expect("resolved templates come from this registry")
+            .parsed
+    }
+}
+
+/// Stable bucket for `key` among the candidates' traffic weights
+fn pick_weighted<'a>(candidates: &[&'a PromptTemplate], key: &str) -> Option<&'a PromptTemplate> {
+    let total: i64 = candidates.iter().map(|t| t.traffic_weight).sum();
+    if total <= 0 {
+        return None;
+    }
+    // FNV-1a, so buckets stay put across restarts
+    let hash = key.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    });
+    let mut bucket = (hash % total as u64) as i64;
+    candidates.iter().copied().find(|t| {
+        bucket -= t.traffic_weight;
+        bucket < 0
+    })
+}
+
+/// The registry used when rendering prompts
+pub fn prompt_registry() -> Arc<PromptRegistry> {
+    PROMPT_REGISTRY.read().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+/// Reload the active versions from the database, e.g. after a template changes
+pub async fn reload_prompt_registry(pool: &DbPool) -> Result<()> {
+    let registry = PromptRegistry::load(pool).await?;
+    *PROMPT_REGISTRY.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(registry);
+    Ok(())
+}
+
+/// Render `name` with the current registry
+pub fn render_prompt(name: &str, scope: &PromptScope, values: &Value) -> Result<RenderedPrompt> {
+    let rendered = prompt_registry().render(name, scope, values)?;
+    TemplateTrace::note(&rendered.template);
+    Ok(rendered)
+}
+
+tokio::task_local! {
+    static TEMPLATE_TRACE: TemplateTrace;
+}
+
+/// Notes the template behind the prompts a provider renders while serving one
+/// request, so the result can be credited to the template version that
+/// produced it
+#[derive(Debug, Clone, Default)]
+pub struct TemplateTrace(Arc<Mutex<Option<TemplateUsage>>>);
+
+impl TemplateTrace {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run `future` with the templates it renders going to this trace
+    pub async fn trace<F: Future>(&self, future: F) -> F::Output {
+        TEMPLATE_TRACE.scope(self.clone(), future).await
+    }
+
+    /// Note a rendered template. The first one is kept, except that the
+    /// generic system prompt gives way to the template for the task itself.
+    /// Does nothing outside `trace`.
+    fn note(template: &TemplateUsage) {
+        let _ = TEMPLATE_TRACE.try_with(|trace| {
+            let mut used = trace.0.lock().unwrap_or_else(|e| e.into_inner());
+            if !used.as_ref().is_some_and(|current| current.name != "system") {
+                *used = Some(template.clone());
+            }
+        });
+    }
+
+    /// The template credited with the request, if any was rendered
+    pub fn used(&self) -> Option<TemplateUsage> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_conditionals_loops_and_comparisons() {
+        let template = Template::parse(
+            "{{#if name}}Hi {{name}}.{{else}}Hi.{{/if}}{{#each items}} {{@index}}={{this}}{{/each}}\
+             {{#if mode == \"dark\"}} dark{{/if}}{{#unless missing}} none{{/unless}}",
+        )
+        .unwrap();
+        let text = template.render(&json!({ "name": "Mara", "items": ["a", "b"], "mode": "dark" }));
+        assert_eq!(text, "Hi Mara. 0=a 1=b dark none");
+        assert_eq!(template.render(&json!({})), "Hi. none");
+    }
+
+    #[test]
+    fn test_parse_errors_are_reported() {
+        assert!(Template::parse("{{#if name}}open").is_err());
+        assert!(Template::parse("{{/each}}").is_err());
+        assert!(Template::parse("{{name").is_err());
+        assert!(Template::parse("{{#if a == b}}x{{/if}}").is_err());
+    }
+
+    #[test]
+    fn test_builtins_only_use_declared_variables() {
+        for template in builtin_templates() {
+            check_template(&template.body, &template.variables).unwrap();
+        }
+    }
+}
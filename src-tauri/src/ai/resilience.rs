@@ -0,0 +1,666 @@
+//! Shared resilience layer for AI provider HTTP calls
+//!
+//! Every provider sends its requests through a `Resilience`. It paces requests
+//! against per-minute limits, retries retryable failures with capped
+//! exponential backoff and jitter, and honors `Retry-After` and the OpenAI,
+//! Anthropic and Gemini rate-limit hints. Repeated failures trip a circuit
+//! breaker; `AIProviderManager` reads the resulting `ProviderHealth` to route
+//! around the provider until it recovers.
+
+use crate::ai::token_counter::UsageMeter;
+use crate::error::{Result, StoryWeaverError};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use reqwest::header::HeaderMap;
+use serde::Serialize;
+use std::sync::Mutex as StdMutex;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use tokio::time::sleep;
+
+/// Retry, pacing and circuit breaker settings for one provider
+#[derive(Debug, Clone)]
+pub struct ResiliencePolicy {
+    pub requests_per_minute: u32,
+    pub tokens_per_minute: u32,
+    /// Retries after the first attempt
+    pub max_retries: u32,
+    /// Backoff before the first retry; doubles on each further retry
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    /// Longest server-requested wait to sit through. Longer waits fail at once
+    /// so the request can fail over to another provider.
+    pub max_retry_after: Duration,
+    /// Consecutive failed requests that open the circuit
+    pub failure_threshold: u32,
+    /// How long an open circuit rejects requests before letting a probe through
+    pub open_duration: Duration,
+}
+
+impl Default for ResiliencePolicy {
+    fn default() -> Self {
+        Self {
+            requests_per_minute: 60,
+            tokens_per_minute: 90_000,
+            max_retries: 3,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(20),
+            max_retry_after: Duration::from_secs(60),
+            failure_threshold: 5,
+            open_duration: Duration::from_secs(30),
+        }
+    }
+}
+
+impl ResiliencePolicy {
+    pub fn with_rate_limits(requests_per_minute: u32, tokens_per_minute: u32) -> Self {
+        Self {
+            requests_per_minute,
+            tokens_per_minute,
+            ..Self::default()
+        }
+    }
+
+    /// Backoff before retry number `attempt + 1`: capped exponential with equal
+    /// jitter, so concurrent requests don't retry in lockstep
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let exponential = self.base_delay.saturating_mul(2u32.saturating_pow(attempt)).min(self.max_delay);
+        exponential / 2 + exponential.mul_f64(jitter() / 2.0)
+    }
+}
+
+/// Whether a failure is worth retrying against the same provider
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailureClass {
+    Retryable,
+    Fatal,
+}
+
+/// Rate limits, timeouts, conflicts and server errors are transient; other
+/// client errors would fail the same way again
+pub fn classify_status(status: u16) -> FailureClass {
+    match status {
+        408 | 409 | 425 | 429 | 500 | 502 | 503 | 504 | 529 => FailureClass::Retryable,
+        _ => FailureClass::Fatal,
+    }
+}
+
+pub fn classify_transport(error: &reqwest::Error) -> FailureClass {
+    if error.is_timeout() || error.is_connect() || error.is_request() {
+        FailureClass::Retryable
+    } else {
+        FailureClass::Fatal
+    }
+}
+
+/// How long the server asked us to wait, from `Retry-After` or the
+/// provider-specific rate-limit headers
+pub fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok()).map(str::trim);
+
+    if let Some(ms) = header("retry-after-ms").and_then(|v| v.parse::<f64>().ok()) {
+        return Some(Duration::from_secs_f64(ms.max(0.0) / 1000.0));
+    }
+    if let Some(value) = header("retry-after") {
+        if let Ok(seconds) = value.parse::<f64>() {
+            return Some(Duration::from_secs_f64(seconds.max(0.0)));
+        }
+        if let Ok(date) = DateTime::parse_from_rfc2822(value) {
+            return Some(until(date.with_timezone(&Utc)));
+        }
+    }
+
+    // Exhausted limits: OpenAI reports resets as durations ("6m0s"),
+    // Anthropic as RFC 3339 timestamps
+    let mut wait: Option<Duration> = None;
+    for kind in ["requests", "tokens"] {
+        if header(&format!("x-ratelimit-remaining-{}", kind)) == Some("0") {
+            if let Some(reset) = header(&format!("x-ratelimit-reset-{}", kind)).and_then(parse_duration_spec) {
+                wait = wait.max(Some(reset));
+            }
+        }
+    }
+    for kind in ["requests", "tokens", "input-tokens", "output-tokens"] {
+        if header(&format!("anthropic-ratelimit-{}-remaining", kind)) == Some("0") {
+            let reset = header(&format!("anthropic-ratelimit-{}-reset", kind))
+                .and_then(|v| DateTime::parse_from_rfc3339(v).ok());
+            if let Some(reset) = reset {
+                wait = wait.max(Some(until(reset.with_timezone(&Utc))));
+            }
+        }
+    }
+    wait
+}
+
+/// Gemini puts its retry hint in the error body as a `RetryInfo` detail
+pub fn retry_after_from_body(body: &str) -> Option<Duration> {
+    let json: serde_json::Value = serde_json::from_str(body).ok()?;
+    json.get("error")?
+        .get("details")?
+        .as_array()?
+        .iter()
+        .filter(|detail| {
+            detail.get("@type").and_then(|t| t.as_str()).is_some_and(|t| t.ends_with("RetryInfo"))
+        })
+        .find_map(|detail| detail.get("retryDelay").and_then(|d| d.as_str()).and_then(parse_duration_spec))
+}
+
+/// Parse durations like `"20ms"`, `"1.5s"`, `"6m0s"` or a bare number of seconds
+pub fn parse_duration_spec(value: &str) -> Option<Duration> {
+    let mut rest = value.trim();
+    if let Ok(seconds) = rest.parse::<f64>() {
+        return Some(Duration::from_secs_f64(seconds.max(0.0)));
+    }
+    if rest.is_empty() {
+        return None;
+    }
+
+    let mut seconds = 0.0;
+    while !rest.is_empty() {
+        let number_end = rest.find(|c: char| !c.is_ascii_digit() && c != '.')?;
+        let number: f64 = rest[..number_end].parse().ok()?;
+        rest = &rest[number_end..];
+        let unit_end = rest.find(|c: char| c.is_ascii_digit() || c == '.').unwrap_or(rest.len());
+        let scale = match &rest[..unit_end] {
+            "h" => 3600.0,
+            "m" => 60.0,
+            "s" => 1.0,
+            "ms" => 1e-3,
+            "us" | "µs" => 1e-6,
+            "ns" => 1e-9,
+            _ => return None,
+        };
+        seconds += number * scale;
+        rest = &rest[unit_end..];
+    }
+    Some(Duration::from_secs_f64(seconds))
+}
+
+fn until(moment: DateTime<Utc>) -> Duration {
+    (moment - Utc::now()).to_std().unwrap_or(Duration::ZERO)
+}
+
+/// A number in `[0, 1)` that differs between calls. `RandomState` is seeded
+/// per instance, which is plenty for spreading retries apart.
+fn jitter() -> f64 {
+    use std::hash::{BuildHasher, Hasher};
+    let mut hasher = std::collections::hash_map::RandomState::new().build_hasher();
+    hasher.write_u128(
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or_default(),
+    );
+    (hasher.finish() >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CircuitState {
+    /// Requests flow normally
+    Closed,
+    /// Requests are rejected without contacting the provider
+    Open,
+    /// The cool-down has passed; one probe request decides whether to close
+    HalfOpen,
+}
+
+/// Health of a provider as seen by its circuit breaker
+#[derive(Debug, Clone, Serialize)]
+pub struct ProviderHealth {
+    pub state: CircuitState,
+    pub consecutive_failures: u32,
+    pub last_error: Option<String>,
+    /// Seconds until an open circuit lets a probe request through
+    pub retry_in_secs: Option<u64>,
+}
+
+impl ProviderHealth {
+    pub fn healthy() -> Self {
+        Self {
+            state: CircuitState::Closed,
+            consecutive_failures: 0,
+            last_error: None,
+            retry_in_secs: None,
+        }
+    }
+
+    pub fn is_healthy(&self) -> bool {
+        self.state != CircuitState::Open
+    }
+}
+
+#[derive(Debug)]
+struct CircuitBreaker {
+    state: CircuitState,
+    consecutive_failures: u32,
+    opened_at: Option<Instant>,
+    last_error: Option<String>,
+    probe_in_flight: bool,
+}
+
+impl CircuitBreaker {
+    fn new() -> Self {
+        Self {
+            state: CircuitState::Closed,
+            consecutive_failures: 0,
+            opened_at: None,
+            last_error: None,
+            probe_in_flight: false,
+        }
+    }
+
+    fn remaining_open(&self, policy: &ResiliencePolicy) -> Duration {
+        self.opened_at
+            .map(|opened| policy.open_duration.saturating_sub(opened.elapsed()))
+            .unwrap_or(Duration::ZERO)
+    }
+
+    /// Admit a request, or return how long until the circuit may admit one.
+    /// `Ok(true)` means the request is the half-open probe.
+    fn try_acquire(&mut self, policy: &ResiliencePolicy) -> std::result::Result<bool, Duration> {
+        match self.state {
+            CircuitState::Closed => Ok(false),
+            CircuitState::Open => {
+                let remaining = self.remaining_open(policy);
+                if remaining.is_zero() {
+                    self.state = CircuitState::HalfOpen;
+                    self.probe_in_flight = true;
+                    Ok(true)
+                } else {
+                    Err(remaining)
+                }
+            }
+            CircuitState::HalfOpen if !self.probe_in_flight => {
+                self.probe_in_flight = true;
+                Ok(true)
+            }
+            CircuitState::HalfOpen => Err(Duration::ZERO),
+        }
+    }
+
+    /// Let another request probe after one ended without an outcome
+    fn release_probe(&mut self) {
+        self.probe_in_flight = false;
+    }
+
+    fn record_success(&mut self) {
+        *self = Self::new();
+    }
+
+    fn record_failure(&mut self, error: &StoryWeaverError, policy: &ResiliencePolicy) {
+        self.consecutive_failures += 1;
+        self.last_error = Some(error.to_string());
+        self.probe_in_flight = false;
+        if self.state == CircuitState::HalfOpen || self.consecutive_failures >= policy.failure_threshold {
+            self.state = CircuitState::Open;
+            self.opened_at = Some(Instant::now());
+        }
+    }
+
+    fn health(&self, policy: &ResiliencePolicy) -> ProviderHealth {
+        ProviderHealth {
+            state: self.state,
+            consecutive_failures: self.consecutive_failures,
+            last_error: self.last_error.clone(),
+            retry_in_secs: (self.state == CircuitState::Open).then(|| self.remaining_open(policy).as_secs()),
+        }
+    }
+}
+
+const PACING_WINDOW: Duration = Duration::from_secs(60);
+
+/// Client-side pacing against the provider's per-minute limits
+#[derive(Debug)]
+struct RequestPacer {
+    request_count: u32,
+    token_count: u32,
+    /// Start of the minute being counted; in the future once requests have
+    /// been pushed into a later minute
+    window_start: Instant,
+}
+
+impl RequestPacer {
+    fn new() -> Self {
+        Self {
+            request_count: 0,
+            token_count: 0,
+            window_start: Instant::now(),
+        }
+    }
+
+    /// Count a request against the limits and return how long it has to wait
+    /// before being sent. The wait is slept after the pacer is unlocked, so
+    /// other requests are paced meanwhile.
+    fn reserve(&mut self, estimated_tokens: u32, policy: &ResiliencePolicy) -> Duration {
+        let now = Instant::now();
+        // Start a new window if a minute has passed
+        if now >= self.window_start + PACING_WINDOW {
+            self.reset(now);
+        }
+
+        // A request that would exceed a limit goes into the next minute
+        if self.request_count > 0
+            && (self.request_count >= policy.requests_per_minute
+                || self.token_count.saturating_add(estimated_tokens) >= policy.tokens_per_minute)
+        {
+            self.reset(self.window_start + PACING_WINDOW);
+        }
+
+        self.request_count += 1;
+        self.token_count = self.token_count.saturating_add(estimated_tokens);
+        self.window_start.saturating_duration_since(now)
+    }
+
+    fn reset(&mut self, window_start: Instant) {
+        self.request_count = 0;
+        self.token_count = 0;
+        self.window_start = window_start;
+    }
+}
+
+pub struct Resilience {
+    provider: String,
+    policy: ResiliencePolicy,
+    pacer: Mutex<RequestPacer>,
+    breaker: StdMutex<CircuitBreaker>,
+}
+
+impl Resilience {
+    pub fn new(provider: &str, policy: ResiliencePolicy) -> Self {
+        Self {
+            provider: provider.to_string(),
+            policy,
+            pacer: Mutex::new(RequestPacer::new()),
+            breaker: StdMutex::new(CircuitBreaker::new()),
+        }
+    }
+
+    pub fn policy(&self) -> &ResiliencePolicy {
+        &self.policy
+    }
+
+    pub fn health(&self) -> ProviderHealth {
+        self.breaker.lock().unwrap_or_else(|e| e.into_inner()).health(&self.policy)
+    }
+
+    /// Replace the estimate made when pacing a request with the usage the
+    /// provider actually reported, and report it for the request's spend
+    pub async fn record_usage(&self, estimated_tokens: u32, input_tokens: u32, output_tokens: u32) {
+        UsageMeter::report(input_tokens, output_tokens);
+        let actual_tokens = input_tokens.saturating_add(output_tokens);
+        let mut pacer = self.pacer.lock().await;
+        pacer.token_count = pacer.token_count.saturating_sub(estimated_tokens).saturating_add(actual_tokens);
+    }
+
+    /// Send `request`, retrying retryable failures. Returns only successful
+    /// responses; a final failure becomes an `AIRequest` error carrying the
+    /// status code and response body, or `AIRateLimit` when the server asks
+    /// for a longer wait than the policy allows.
+    pub async fn send(&self, request: reqwest::RequestBuilder, estimated_tokens: u32) -> Result<reqwest::Response> {
+        let mut admission = self.admit()?;
+        let wait = self.pacer.lock().await.reserve(estimated_tokens, &self.policy);
+        if !wait.is_zero() {
+            sleep(wait).await;
+        }
+
+        let mut attempt = 0;
+        loop {
+            let builder = request.try_clone().ok_or_else(|| {
+                StoryWeaverError::system(format!("{} request body cannot be retried", self.provider))
+            })?;
+
+            let delay = match builder.send().await {
+                Ok(response) if response.status().is_success() => {
+                    admission.record_success();
+                    return Ok(response);
+                }
+                Ok(response) => {
+                    let status = response.status().as_u16();
+                    let server_delay = retry_after(response.headers());
+                    let body = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
+                    let server_delay = server_delay.or_else(|| retry_after_from_body(&body));
+                    let error = StoryWeaverError::ai_request(self.provider.as_str(), status, &body);
+
+                    if classify_status(status) == FailureClass::Fatal {
+                        // The provider answered; only bad credentials make it unusable
+                        if matches!(status, 401 | 403) {
+                            admission.record_failure(&error);
+                        } else {
+                            admission.record_success();
+                        }
+                        return Err(error);
+                    }
+                    if let Some(wait) = server_delay.filter(|wait| *wait > self.policy.max_retry_after) {
+                        let error = if status == 429 {
+                            StoryWeaverError::ai_rate_limit(self.provider.as_str(), wait.as_secs())
+                        } else {
+                            error
+                        };
+                        admission.record_failure(&error);
+                        return Err(error);
+                    }
+                    if attempt >= self.policy.max_retries {
+                        admission.record_failure(&error);
+                        return Err(error);
+                    }
+                    server_delay.unwrap_or_else(|| self.policy.backoff(attempt))
+                }
+                Err(e) => {
+                    let class = classify_transport(&e);
+                    let error = StoryWeaverError::network(format!("Failed to send request to {}: {}", self.provider, e));
+                    if class == FailureClass::Fatal || attempt >= self.policy.max_retries {
+                        admission.record_failure(&error);
+                        return Err(error);
+                    }
+                    self.policy.backoff(attempt)
+                }
+            };
+
+            attempt += 1;
+            tracing::warn!(
+                "{} request failed, retry {}/{} in {:?}",
+                self.provider, attempt, self.policy.max_retries, delay
+            );
+            sleep(delay).await;
+        }
+    }
+
+    fn admit(&self) -> Result<Admission<'_>> {
+        let mut breaker = self.breaker.lock().unwrap_or_else(|e| e.into_inner());
+        match breaker.try_acquire(&self.policy) {
+            Ok(probe) => Ok(Admission { resilience: self, probe }),
+            Err(remaining) => Err(StoryWeaverError::ai_request(
+                self.provider.as_str(),
+                503,
+                &format!("circuit open after repeated failures, retry in {}s", remaining.as_secs()),
+            )),
+        }
+    }
+
+    fn record_success(&self) {
+        self.breaker.lock().unwrap_or_else(|e| e.into_inner()).record_success();
+    }
+
+    fn record_failure(&self, error: &StoryWeaverError) {
+        let mut breaker = self.breaker.lock().unwrap_or_else(|e| e.into_inner());
+        breaker.record_failure(error, &self.policy);
+        if breaker.state == CircuitState::Open {
+            tracing::warn!("{} marked unhealthy: {}", self.provider, error);
+        }
+    }
+}
+
+/// A request let through the circuit breaker. Its outcome is recorded through
+/// it; if the request is dropped first (cancelled, or its future abandoned),
+/// a half-open probe slot is given back so the circuit can't stay stuck
+/// rejecting every request.
+struct Admission<'a> {
+    resilience: &'a Resilience,
+    probe: bool,
+}
+
+impl Admission<'_> {
+    fn record_success(&mut self) {
+        self.probe = false;
+        self.resilience.record_success();
+    }
+
+    fn record_failure(&mut self, error: &StoryWeaverError) {
+        self.probe = false;
+        self.resilience.record_failure(error);
+    }
+}
+
+impl Drop for Admission<'_> {
+    fn drop(&mut self) {
+        if self.probe {
+            self.resilience.breaker.lock().unwrap_or_else(|e| e.into_inner()).release_probe();
+        }
+    }
+}
+
+/// `send_with` on request builders, so provider code reads like a plain `send`
+#[async_trait]
+pub trait ResilientSend {
+    async fn send_with(self, resilience: &Resilience, estimated_tokens: u32) -> Result<reqwest::Response>;
+}
+
+#[async_trait]
+impl ResilientSend for reqwest::RequestBuilder {
+    async fn send_with(self, resilience: &Resilience, estimated_tokens: u32) -> Result<reqwest::Response> {
+        resilience.send(self, estimated_tokens).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    #[test]
+    fn test_status_classification() {
+        assert_eq!(classify_status(429), FailureClass::Retryable);
+        assert_eq!(classify_status(503), FailureClass::Retryable);
+        assert_eq!(classify_status(529), FailureClass::Retryable);
+        assert_eq!(classify_status(400), FailureClass::Fatal);
+        assert_eq!(classify_status(401), FailureClass::Fatal);
+    }
+
+    #[test]
+    fn test_retry_after_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert("retry-after", HeaderValue::from_static("7"));
+        assert_eq!(retry_after(&headers), Some(Duration::from_secs(7)));
+
+        let mut headers = HeaderMap::new();
+        headers.insert("retry-after-ms", HeaderValue::from_static("250"));
+        assert_eq!(retry_after(&headers), Some(Duration::from_millis(250)));
+
+        let mut headers = HeaderMap::new();
+        headers.insert("x-ratelimit-remaining-requests", HeaderValue::from_static("0"));
+        headers.insert("x-ratelimit-reset-requests", HeaderValue::from_static("1m30s"));
+        headers.insert("x-ratelimit-remaining-tokens", HeaderValue::from_static("1200"));
+        headers.insert("x-ratelimit-reset-tokens", HeaderValue::from_static("5m"));
+        assert_eq!(retry_after(&headers), Some(Duration::from_secs(90)));
+
+        assert_eq!(retry_after(&HeaderMap::new()), None);
+    }
+
+    #[test]
+    fn test_retry_delay_from_gemini_body() {
+        let body = r#"{"error":{"code":429,"details":[{"@type":"type.googleapis.com/google.rpc.RetryInfo","retryDelay":"13s"}]}}"#;
+        assert_eq!(retry_after_from_body(body), Some(Duration::from_secs(13)));
+        assert_eq!(retry_after_from_body("not json"), None);
+    }
+
+    #[test]
+    fn test_duration_specs() {
+        assert_eq!(parse_duration_spec("20ms"), Some(Duration::from_millis(20)));
+        assert_eq!(parse_duration_spec("1.5s"), Some(Duration::from_millis(1500)));
+        assert_eq!(parse_duration_spec("6m0s"), Some(Duration::from_secs(360)));
+        assert_eq!(parse_duration_spec("soon"), None);
+    }
+
+    #[test]
+    fn test_backoff_is_capped_and_jittered() {
+        let policy = ResiliencePolicy::default();
+        for attempt in 0..10 {
+            let delay = policy.backoff(attempt);
+            let ceiling = policy.base_delay.saturating_mul(2u32.saturating_pow(attempt)).min(policy.max_delay);
+            assert!(delay >= ceiling / 2 && delay <= ceiling);
+        }
+    }
+
+    #[test]
+    fn test_circuit_opens_and_half_opens() {
+        let policy = ResiliencePolicy {
+            failure_threshold: 2,
+            open_duration: Duration::ZERO,
+            ..ResiliencePolicy::default()
+        };
+        let error = StoryWeaverError::ai_request("openai", 503, "overloaded");
+        let mut breaker = CircuitBreaker::new();
+
+        breaker.record_failure(&error, &policy);
+        assert_eq!(breaker.state, CircuitState::Closed);
+        breaker.record_failure(&error, &policy);
+        assert_eq!(breaker.state, CircuitState::Open);
+
+        // Cool-down elapsed: one probe goes through, concurrent requests wait
+        assert!(breaker.try_acquire(&policy).is_ok());
+        assert_eq!(breaker.state, CircuitState::HalfOpen);
+        assert!(breaker.try_acquire(&policy).is_err());
+
+        breaker.record_success();
+        assert!(breaker.health(&policy).is_healthy());
+        assert_eq!(breaker.consecutive_failures, 0);
+    }
+
+    #[tokio::test]
+    async fn test_dropped_probe_lets_the_next_request_probe() {
+        // A server that accepts connections and never answers
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/v1/chat/completions", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let mut held = Vec::new();
+            while let Ok((socket, _)) = listener.accept().await {
+                held.push(socket);
+            }
+        });
+
+        let resilience = Resilience::new(
+            "openai",
+            ResiliencePolicy {
+                failure_threshold: 1,
+                open_duration: Duration::ZERO,
+                ..ResiliencePolicy::default()
+            },
+        );
+        resilience.record_failure(&StoryWeaverError::ai_request("openai", 503, "overloaded"));
+        assert_eq!(resilience.health().state, CircuitState::Open);
+
+        // The probe is abandoned mid-flight, as when a request is cancelled
+        let client = reqwest::Client::new();
+        let probe = resilience.send(client.post(&url), 0);
+        assert!(tokio::time::timeout(Duration::from_millis(100), probe).await.is_err());
+        assert_eq!(resilience.health().state, CircuitState::HalfOpen);
+
+        let next = resilience.admit().expect("the probe slot was not released");
+        assert!(next.probe);
+        assert!(resilience.admit().is_err());
+    }
+
+    #[test]
+    fn test_pacer_pushes_requests_over_the_limit_into_later_minutes() {
+        let policy = ResiliencePolicy::with_rate_limits(2, 90_000);
+        let mut pacer = RequestPacer::new();
+
+        assert!(pacer.reserve(10, &policy).is_zero());
+        assert!(pacer.reserve(10, &policy).is_zero());
+        let next_minute = pacer.reserve(10, &policy);
+        assert!(next_minute > Duration::from_secs(59) && next_minute <= PACING_WINDOW);
+        assert!(pacer.reserve(10, &policy) <= PACING_WINDOW);
+        assert!(pacer.reserve(10, &policy) > PACING_WINDOW);
+    }
+}
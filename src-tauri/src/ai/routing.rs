@@ -0,0 +1,248 @@
+//! Per-feature and per-plugin model routing with provider failover
+//!
+//! A `RoutingTable` maps writing features and plugins to preferred providers,
+//! plus a global fallback chain. `AIProviderManager` walks the resolved chain
+//! and moves on to the next provider when one fails with a rate-limit, auth or
+//! server error. The `RouteTaken` of each request can be stored in
+//! `AIGenerationHistory.route_taken`.
+
+use super::{CancellationToken, TemplateUsage, WritingFeature};
+use crate::error::StoryWeaverError;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Settings key the routing table is persisted under
+pub const ROUTING_TABLE_SETTING_KEY: &str = "ai_routing_table";
+
+/// Feature key under which `RoutingTable::features` lists embedding providers
+pub const EMBEDDING_ROUTE_KEY: &str = "embedding";
+
+/// A provider, optionally pinned to a model.
+///
+/// A target with a model resolves to the provider registered as
+/// `"{provider}/{model}"` when one exists, otherwise to `provider` itself.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RouteTarget {
+    pub provider: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+}
+
+impl RouteTarget {
+    pub fn new(provider: &str) -> Self {
+        Self {
+            provider: provider.to_string(),
+            model: None,
+        }
+    }
+
+    pub fn with_model(provider: &str, model: &str) -> Self {
+        Self {
+            provider: provider.to_string(),
+            model: Some(model.to_string()),
+        }
+    }
+
+    /// Parse a plugin's `ai_model` value: `"provider/model"`, `"provider"`, or a
+    /// bare model name (returned as `None`, to be matched against registered
+    /// providers by model name)
+    pub fn parse(value: &str) -> Option<Self> {
+        let value = value.trim();
+        if value.is_empty() {
+            return None;
+        }
+        match value.split_once('/') {
+            Some((provider, model)) if !provider.is_empty() && !model.is_empty() => {
+                Some(Self::with_model(provider, model))
+            }
+            _ => None,
+        }
+    }
+
+    /// Registry keys to try for this target, most specific first
+    pub fn registry_keys(&self) -> Vec<String> {
+        match &self.model {
+            Some(model) => vec![format!("{}/{}", self.provider, model), self.provider.clone()],
+            None => vec![self.provider.clone()],
+        }
+    }
+}
+
+/// Routing preferences for the AI provider manager
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RoutingTable {
+    /// Preferred providers per feature, keyed by `WritingFeature::route_key`
+    #[serde(default)]
+    pub features: HashMap<String, Vec<RouteTarget>>,
+    /// Preferred providers per plugin id
+    #[serde(default)]
+    pub plugins: HashMap<i64, Vec<RouteTarget>>,
+    /// Tried after the feature or plugin routes, before the default provider
+    #[serde(default)]
+    pub fallback_chain: Vec<RouteTarget>,
+}
+
+/// What a routed request is for
+#[derive(Debug, Clone, Default)]
+pub struct RouteRequest {
+    pub feature: Option<WritingFeature>,
+    pub plugin_id: Option<i64>,
+    /// The plugin's own `ai_model`, used when the table has no plugin route
+    pub plugin_model: Option<String>,
+    /// Aborts the attempt in flight and stops any further failover
+    pub cancellation: Option<CancellationToken>,
+    /// Checked against the AI budgets before each provider is tried
+    pub budget: Option<BudgetRequest>,
+}
+
+/// Size of a request, for estimating its cost against the AI budgets
+#[derive(Debug, Clone, Default)]
+pub struct BudgetRequest {
+    pub project_id: Option<String>,
+    pub input_tokens: u32,
+    /// Expected length of the answer
+    pub output_tokens: u32,
+}
+
+impl BudgetRequest {
+    pub fn new(project_id: Option<String>, input: &str, output_tokens: u32) -> Self {
+        Self {
+            project_id,
+            input_tokens: super::tokenizer::count_tokens("", input) as u32,
+            output_tokens,
+        }
+    }
+}
+
+impl RouteRequest {
+    pub fn feature(feature: WritingFeature) -> Self {
+        Self {
+            feature: Some(feature),
+            ..Self::default()
+        }
+    }
+
+    pub fn plugin(plugin_id: i64, ai_model: &str) -> Self {
+        Self {
+            feature: None,
+            plugin_id: Some(plugin_id),
+            plugin_model: Some(ai_model.to_string()).filter(|m| !m.trim().is_empty()),
+            cancellation: None,
+            budget: None,
+        }
+    }
+
+    pub fn with_cancellation(mut self, cancellation: Option<CancellationToken>) -> Self {
+        self.cancellation = cancellation;
+        self
+    }
+
+    pub fn with_budget(mut self, budget: BudgetRequest) -> Self {
+        self.budget = Some(budget);
+        self
+    }
+}
+
+/// One provider tried while serving a request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RouteAttempt {
+    pub provider: String,
+    pub model: String,
+    /// Why the request moved on; `None` for the provider that served it
+    pub error: Option<String>,
+}
+
+/// The route a request actually took
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RouteTaken {
+    pub feature: Option<String>,
+    pub plugin_id: Option<i64>,
+    pub attempts: Vec<RouteAttempt>,
+    /// Template version behind the prompt the serving provider sent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt_template: Option<TemplateUsage>,
+}
+
+impl RouteTaken {
+    /// The attempt that produced the response, if any
+    pub fn served_by(&self) -> Option<&RouteAttempt> {
+        self.attempts.last().filter(|a| a.error.is_none())
+    }
+
+    pub fn failed_over(&self) -> bool {
+        self.attempts.len() > 1
+    }
+
+    /// JSON for `AIGenerationHistory.route_taken`
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap_or_else(|_| "{}".to_string())
+    }
+}
+
+impl WritingFeature {
+    /// Stable key used in the routing table
+    pub fn route_key(&self) -> &'static str {
+        match self {
+            WritingFeature::Write => "write",
+            WritingFeature::Rewrite(_) => "rewrite",
+            WritingFeature::Expand => "expand",
+            WritingFeature::Describe => "describe",
+            WritingFeature::Brainstorm => "brainstorm",
+            WritingFeature::Visualize => "visualize",
+            WritingFeature::RelatedWords => "related_words",
+            WritingFeature::QuickEdit => "quick_edit",
+            WritingFeature::QuickChat => "quick_chat",
+        }
+    }
+}
+
+/// Whether an error should send the request to the next provider in the chain.
+/// Rate limits, bad credentials, server errors and connection problems are
+/// provider-specific; invalid input or filtered content would fail everywhere.
+pub fn should_fail_over(error: &StoryWeaverError) -> bool {
+    match error {
+        StoryWeaverError::AIRateLimit { .. }
+        | StoryWeaverError::InvalidAPIKey { .. }
+        | StoryWeaverError::Network { .. }
+        | StoryWeaverError::RequestTimeout { .. }
+        | StoryWeaverError::ConnectionFailed { .. }
+        | StoryWeaverError::AIGenerationTimeout { .. } => true,
+        StoryWeaverError::AIRequest { status_code, .. } => {
+            matches!(*status_code, 0 | 401 | 403 | 408 | 429) || *status_code >= 500
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_plugin_model() {
+        assert_eq!(RouteTarget::parse("claude/claude-3-haiku"), Some(RouteTarget::with_model("claude", "claude-3-haiku")));
+        assert_eq!(RouteTarget::parse("gpt-4"), None);
+        assert_eq!(RouteTarget::parse("  "), None);
+    }
+
+    #[test]
+    fn test_failover_classification() {
+        assert!(should_fail_over(&StoryWeaverError::ai_request("openai", 429, "slow down")));
+        assert!(should_fail_over(&StoryWeaverError::ai_request("openai", 503, "overloaded")));
+        assert!(should_fail_over(&StoryWeaverError::InvalidAPIKey { provider: "openai".to_string() }));
+        assert!(!should_fail_over(&StoryWeaverError::ai_request("openai", 400, "bad request")));
+        assert!(!should_fail_over(&StoryWeaverError::AIContentFiltered { reason: "policy".to_string() }));
+    }
+
+    #[test]
+    fn test_routing_table_round_trips_as_json() {
+        let mut table = RoutingTable::default();
+        table.features.insert("brainstorm".to_string(), vec![RouteTarget::with_model("claude", "claude-3-opus")]);
+        table.plugins.insert(7, vec![RouteTarget::new("ollama")]);
+        table.fallback_chain.push(RouteTarget::new("gemini"));
+
+        let json = serde_json::to_string(&table).unwrap();
+        let parsed: RoutingTable = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, table);
+    }
+}
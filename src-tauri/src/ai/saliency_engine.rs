@@ -1,7 +1,16 @@
 use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Sqlite};
 use std::collections::HashMap;
+use std::sync::Arc;
 use uuid::Uuid;
 
+use super::local_embedding::{cosine_similarity, Embedding, LocalEmbeddingProvider, LOCAL_EMBEDDING_MODEL};
+use super::story_summary::summary_context;
+use super::AIProviderManager;
+use crate::database::models::{DocumentType, VisibilityLevel};
+use crate::database::operations::{CharacterOps, DocumentOps, LocationOps, PlotThreadOps, WorldElementOps};
+use crate::error::Result;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SaliencyContext {
     pub id: String,
@@ -11,6 +20,9 @@ pub struct SaliencyContext {
     pub relevance_scores: HashMap<String, f32>,
     pub total_tokens: i32,
     pub expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// Rolling summary of the manuscript, when it fit the token budget
+    #[serde(default)]
+    pub story_summary: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -28,6 +40,9 @@ pub struct CharacterElement {
     pub description: String,
     pub relevance_score: f32,
     pub token_count: i32,
+    /// Why the element was included
+    #[serde(default)]
+    pub explanation: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -37,6 +52,8 @@ pub struct LocationElement {
     pub description: String,
     pub relevance_score: f32,
     pub token_count: i32,
+    #[serde(default)]
+    pub explanation: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -46,6 +63,8 @@ pub struct PlotThreadElement {
     pub description: String,
     pub relevance_score: f32,
     pub token_count: i32,
+    #[serde(default)]
+    pub explanation: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -56,6 +75,19 @@ pub struct WorldbuildingElement {
     pub content: String,
     pub relevance_score: f32,
     pub token_count: i32,
+    #[serde(default)]
+    pub explanation: String,
+}
+
+/// How story bible elements are compared with the text being written
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScoringMode {
+    /// Word overlap between the text and each element
+    Keyword,
+    /// Cosine similarity of embeddings
+    #[default]
+    Embedding,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -68,6 +100,18 @@ pub struct SaliencyConfig {
     pub recency_weight: f32,
     pub similarity_threshold: f32,
     pub cache_duration_hours: i32,
+    /// Model whose tokenizer budgets `max_total_tokens`
+    #[serde(default)]
+    pub tokenizer_model: Option<String>,
+    #[serde(default)]
+    pub scoring_mode: ScoringMode,
+    /// Characters before the cursor that elements are compared with
+    #[serde(default = "default_cursor_window_chars")]
+    pub cursor_window_chars: usize,
+}
+
+fn default_cursor_window_chars() -> usize {
+    2000
 }
 
 impl Default for SaliencyConfig {
@@ -81,13 +125,52 @@ impl Default for SaliencyConfig {
             recency_weight: 0.1,
             similarity_threshold: 0.3,
             cache_duration_hours: 24,
+            tokenizer_model: None,
+            scoring_mode: ScoringMode::Embedding,
+            cursor_window_chars: default_cursor_window_chars(),
         }
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ElementKind {
+    Character,
+    Location,
+    PlotThread,
+    Worldbuilding,
+}
+
+impl ElementKind {
+    fn section_header(&self) -> &'static str {
+        match self {
+            ElementKind::Character => "\n## Relevant Characters:\n",
+            ElementKind::Location => "\n## Relevant Locations:\n",
+            ElementKind::PlotThread => "\n## Relevant Plot Threads:\n",
+            ElementKind::Worldbuilding => "\n## Relevant Worldbuilding:\n",
+        }
+    }
+}
+
+/// A story bible entry under consideration, with the line it would add to
+/// the prompt
+struct Candidate {
+    kind: ElementKind,
+    index: usize,
+    line: String,
+    visibility: VisibilityLevel,
+    last_seen_chapter: Option<i32>,
+    similarity: f32,
+    score: f32,
+}
+
 pub struct SaliencyEngine {
     config: SaliencyConfig,
     context_cache: HashMap<String, SaliencyContext>,
+    /// Routed embeddings; the offline embedder is used when unset
+    embedder: Option<Arc<AIProviderManager>>,
+    local_embeddings: LocalEmbeddingProvider,
+    /// Element embeddings by text, so unchanged entries aren't re-embedded
+    embedding_cache: HashMap<String, Embedding>,
 }
 
 impl SaliencyEngine {
@@ -95,18 +178,26 @@ impl SaliencyEngine {
         Self {
             config,
             context_cache: HashMap::new(),
+            embedder: None,
+            local_embeddings: LocalEmbeddingProvider::new(),
+            embedding_cache: HashMap::new(),
         }
     }
 
-    pub fn build_context(
+    pub fn set_embedder(&mut self, embedder: Arc<AIProviderManager>) {
+        self.embedder = Some(embedder);
+        self.embedding_cache.clear();
+    }
+
+    pub async fn build_context(
         &mut self,
         project_id: &str,
         current_text: &str,
         story_bible_elements: &StoryBibleElements,
-    ) -> Result<SaliencyContext, Box<dyn std::error::Error>> {
+    ) -> Result<SaliencyContext> {
         // Generate context hash for caching
         let context_hash = self.generate_context_hash(current_text, story_bible_elements);
-        
+
         // Check cache first
         if let Some(cached_context) = self.get_cached_context(&context_hash) {
             if !self.is_cache_expired(&cached_context) {
@@ -114,10 +205,20 @@ impl SaliencyEngine {
             }
         }
 
+        // The story summary comes first; elements share what's left of the budget
+        let story_summary = story_bible_elements
+            .story_summary
+            .clone()
+            .filter(|summary| !summary.trim().is_empty())
+            .filter(|summary| self.estimate_tokens(&summary_section(summary)) <= self.config.max_total_tokens);
+        let summary_tokens = story_summary.as_deref().map_or(0, |summary| self.estimate_tokens(&summary_section(summary)));
+
         // Build new context
-        let selected_elements = self.select_relevant_elements(current_text, story_bible_elements)?;
+        let budget = self.config.max_total_tokens - summary_tokens;
+        let (selected_elements, element_tokens) =
+            self.select_relevant_elements(current_text, story_bible_elements, budget).await?;
+        let total_tokens = summary_tokens + element_tokens;
         let relevance_scores = self.calculate_relevance_scores(&selected_elements);
-        let total_tokens = self.calculate_total_tokens(&selected_elements);
 
         let context = SaliencyContext {
             id: Uuid::new_v4().to_string(),
@@ -129,6 +230,7 @@ impl SaliencyEngine {
             expires_at: Some(
                 chrono::Utc::now() + chrono::Duration::hours(self.config.cache_duration_hours as i64)
             ),
+            story_summary,
         };
 
         // Cache the context
@@ -137,131 +239,239 @@ impl SaliencyEngine {
         Ok(context)
     }
 
-    fn select_relevant_elements(
-        &self,
+    /// Scores every element, then fills `budget` tokens greedily: elements
+    /// marked `Always` first, then `Relevant` ones above the threshold, best
+    /// first. `Manual` and `Hidden` elements are never picked automatically.
+    async fn select_relevant_elements(
+        &mut self,
         current_text: &str,
         story_bible: &StoryBibleElements,
-    ) -> Result<SelectedElements, Box<dyn std::error::Error>> {
+        budget: i32,
+    ) -> Result<(SelectedElements, i32)> {
+        let mut candidates = Self::candidates(story_bible);
+        let window = cursor_window(current_text, self.config.cursor_window_chars);
+        self.score_similarity(window, story_bible, &mut candidates).await?;
+
+        let current_chapter = story_bible
+            .current_chapter
+            .or_else(|| candidates.iter().filter_map(|c| c.last_seen_chapter).max());
+        for candidate in &mut candidates {
+            let recency = recency_factor(candidate.last_seen_chapter, current_chapter);
+            candidate.score = self.kind_weight(candidate.kind) * candidate.similarity + self.config.recency_weight * recency;
+        }
+
+        candidates.retain(|c| match c.visibility {
+            VisibilityLevel::Always => true,
+            VisibilityLevel::Relevant => c.score >= self.config.similarity_threshold,
+            VisibilityLevel::Manual | VisibilityLevel::Hidden => false,
+        });
+        candidates.sort_by(|a, b| {
+            let always = |c: &Candidate| matches!(c.visibility, VisibilityLevel::Always);
+            always(b)
+                .cmp(&always(a))
+                .then(b.score.partial_cmp(&a.score).unwrap_or(std::cmp::Ordering::Equal))
+        });
+
         let mut selected = SelectedElements {
             characters: Vec::new(),
             locations: Vec::new(),
             plot_threads: Vec::new(),
             worldbuilding: Vec::new(),
         };
+        let mut used_tokens = 0;
+        let mut opened_sections: Vec<ElementKind> = Vec::new();
+
+        for candidate in candidates {
+            let line_tokens = self.estimate_tokens(&candidate.line);
+            let header_tokens = if opened_sections.contains(&candidate.kind) {
+                0
+            } else {
+                self.estimate_tokens(candidate.kind.section_header())
+            };
+            if used_tokens + line_tokens + header_tokens > budget {
+                continue;
+            }
+            used_tokens += line_tokens + header_tokens;
+            if header_tokens > 0 {
+                opened_sections.push(candidate.kind);
+            }
 
-        let mut remaining_tokens = self.config.max_total_tokens;
-
-        // Score and select characters
-        let mut character_scores: Vec<(CharacterElement, f32)> = story_bible.characters
-            .iter()
-            .map(|char| {
-                let element = CharacterElement {
-                    id: char.id.clone(),
-                    name: char.name.clone(),
-                    description: char.description.clone(),
-                    relevance_score: 0.0,
-                    token_count: self.estimate_tokens(&format!("{}: {}", char.name, char.description)),
-                };
-                let score = self.calculate_similarity_score(current_text, &char.description) * self.config.character_weight;
-                (element, score)
-            })
-            .collect();
-
-        character_scores.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
-
-        for (mut element, score) in character_scores {
-            if score >= self.config.similarity_threshold && remaining_tokens >= element.token_count {
-                element.relevance_score = score;
-                remaining_tokens -= element.token_count;
-                selected.characters.push(element);
+            let explanation = self.explain(&candidate, current_chapter);
+            let score = candidate.score;
+            match candidate.kind {
+                ElementKind::Character => {
+                    let char = &story_bible.characters[candidate.index];
+                    selected.characters.push(CharacterElement {
+                        id: char.id.clone(),
+                        name: char.name.clone(),
+                        description: char.description.clone(),
+                        relevance_score: score,
+                        token_count: line_tokens,
+                        explanation,
+                    });
+                }
+                ElementKind::Location => {
+                    let loc = &story_bible.locations[candidate.index];
+                    selected.locations.push(LocationElement {
+                        id: loc.id.clone(),
+                        name: loc.name.clone(),
+                        description: loc.description.clone(),
+                        relevance_score: score,
+                        token_count: line_tokens,
+                        explanation,
+                    });
+                }
+                ElementKind::PlotThread => {
+                    let plot = &story_bible.plot_threads[candidate.index];
+                    selected.plot_threads.push(PlotThreadElement {
+                        id: plot.id.clone(),
+                        title: plot.title.clone(),
+                        description: plot.description.clone(),
+                        relevance_score: score,
+                        token_count: line_tokens,
+                        explanation,
+                    });
+                }
+                ElementKind::Worldbuilding => {
+                    let wb = &story_bible.worldbuilding[candidate.index];
+                    selected.worldbuilding.push(WorldbuildingElement {
+                        id: wb.id.clone(),
+                        category: wb.category.clone(),
+                        title: wb.title.clone(),
+                        content: wb.content.clone(),
+                        relevance_score: score,
+                        token_count: line_tokens,
+                        explanation,
+                    });
+                }
             }
         }
 
-        // Score and select locations
-        let mut location_scores: Vec<(LocationElement, f32)> = story_bible.locations
-            .iter()
-            .map(|loc| {
-                let element = LocationElement {
-                    id: loc.id.clone(),
-                    name: loc.name.clone(),
-                    description: loc.description.clone(),
-                    relevance_score: 0.0,
-                    token_count: self.estimate_tokens(&format!("{}: {}", loc.name, loc.description)),
-                };
-                let score = self.calculate_similarity_score(current_text, &loc.description) * self.config.location_weight;
-                (element, score)
-            })
-            .collect();
+        Ok((selected, used_tokens))
+    }
 
-        location_scores.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+    /// One candidate per element, each carrying the line `format_context_for_ai` prints for it
+    fn candidates(story_bible: &StoryBibleElements) -> Vec<Candidate> {
+        let candidate = |kind, index, line: String, visibility: &Option<VisibilityLevel>, last_seen_chapter| Candidate {
+            kind,
+            index,
+            line,
+            visibility: visibility.clone().unwrap_or(VisibilityLevel::Relevant),
+            last_seen_chapter,
+            similarity: 0.0,
+            score: 0.0,
+        };
+
+        let characters = story_bible.characters.iter().enumerate().map(|(i, char)| {
+            candidate(ElementKind::Character, i, format!("**{}**: {}\n", char.name, char.description), &char.visibility, char.last_seen_chapter)
+        });
+        let locations = story_bible.locations.iter().enumerate().map(|(i, loc)| {
+            candidate(ElementKind::Location, i, format!("**{}**: {}\n", loc.name, loc.description), &loc.visibility, loc.last_seen_chapter)
+        });
+        let plot_threads = story_bible.plot_threads.iter().enumerate().map(|(i, plot)| {
+            candidate(ElementKind::PlotThread, i, format!("**{}**: {}\n", plot.title, plot.description), &plot.visibility, plot.last_seen_chapter)
+        });
+        let worldbuilding = story_bible.worldbuilding.iter().enumerate().map(|(i, wb)| {
+            candidate(ElementKind::Worldbuilding, i, format!("**{} ({})**: {}\n", wb.title, wb.category, wb.content), &wb.visibility, wb.last_seen_chapter)
+        });
+
+        characters.chain(locations).chain(plot_threads).chain(worldbuilding).collect()
+    }
 
-        for (mut element, score) in location_scores {
-            if score >= self.config.similarity_threshold && remaining_tokens >= element.token_count {
-                element.relevance_score = score;
-                remaining_tokens -= element.token_count;
-                selected.locations.push(element);
+    async fn score_similarity(
+        &mut self,
+        window: &str,
+        story_bible: &StoryBibleElements,
+        candidates: &mut [Candidate],
+    ) -> Result<()> {
+        match self.config.scoring_mode {
+            ScoringMode::Keyword => {
+                for candidate in candidates.iter_mut() {
+                    let description = match candidate.kind {
+                        ElementKind::Character => &story_bible.characters[candidate.index].description,
+                        ElementKind::Location => &story_bible.locations[candidate.index].description,
+                        ElementKind::PlotThread => &story_bible.plot_threads[candidate.index].description,
+                        ElementKind::Worldbuilding => &story_bible.worldbuilding[candidate.index].content,
+                    };
+                    candidate.similarity = self.calculate_similarity_score(window, description);
+                }
+            }
+            ScoringMode::Embedding => {
+                let query = self.embed(window).await?;
+                for candidate in candidates.iter_mut() {
+                    let text = candidate.line.replace("**", "");
+                    let mut element = self.embed_cached(&text).await?;
+                    // A provider failing over mid-request leaves vectors from
+                    // different models, which can't be compared
+                    let mut query_vector = query.vector.clone();
+                    if element.model != query.model {
+                        query_vector = self.local_embeddings.embed(window);
+                        element = Embedding {
+                            model: LOCAL_EMBEDDING_MODEL.to_string(),
+                            vector: self.local_embeddings.embed(&text),
+                        };
+                    }
+                    candidate.similarity = cosine_similarity(&query_vector, &element.vector).max(0.0);
+                }
             }
         }
+        Ok(())
+    }
 
-        // Score and select plot threads
-        let mut plot_scores: Vec<(PlotThreadElement, f32)> = story_bible.plot_threads
-            .iter()
-            .map(|plot| {
-                let element = PlotThreadElement {
-                    id: plot.id.clone(),
-                    title: plot.title.clone(),
-                    description: plot.description.clone(),
-                    relevance_score: 0.0,
-                    token_count: self.estimate_tokens(&format!("{}: {}", plot.title, plot.description)),
-                };
-                let score = self.calculate_similarity_score(current_text, &plot.description) * self.config.plot_weight;
-                (element, score)
-            })
-            .collect();
-
-        plot_scores.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
-
-        for (mut element, score) in plot_scores {
-            if score >= self.config.similarity_threshold && remaining_tokens >= element.token_count {
-                element.relevance_score = score;
-                remaining_tokens -= element.token_count;
-                selected.plot_threads.push(element);
-            }
+    async fn embed(&self, text: &str) -> Result<Embedding> {
+        match &self.embedder {
+            Some(embedder) => embedder.embed(text).await,
+            None => Ok(Embedding {
+                model: LOCAL_EMBEDDING_MODEL.to_string(),
+                vector: self.local_embeddings.embed(text),
+            }),
         }
+    }
 
-        // Score and select worldbuilding elements
-        let mut worldbuilding_scores: Vec<(WorldbuildingElement, f32)> = story_bible.worldbuilding
-            .iter()
-            .map(|wb| {
-                let element = WorldbuildingElement {
-                    id: wb.id.clone(),
-                    category: wb.category.clone(),
-                    title: wb.title.clone(),
-                    content: wb.content.clone(),
-                    relevance_score: 0.0,
-                    token_count: self.estimate_tokens(&format!("{}: {}", wb.title, wb.content)),
-                };
-                let score = self.calculate_similarity_score(current_text, &wb.content) * self.config.worldbuilding_weight;
-                (element, score)
-            })
-            .collect();
+    async fn embed_cached(&mut self, text: &str) -> Result<Embedding> {
+        if let Some(embedding) = self.embedding_cache.get(text) {
+            return Ok(embedding.clone());
+        }
+        let embedding = self.embed(text).await?;
+        self.embedding_cache.insert(text.to_string(), embedding.clone());
+        Ok(embedding)
+    }
 
-        worldbuilding_scores.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+    fn kind_weight(&self, kind: ElementKind) -> f32 {
+        match kind {
+            ElementKind::Character => self.config.character_weight,
+            ElementKind::Location => self.config.location_weight,
+            ElementKind::PlotThread => self.config.plot_weight,
+            ElementKind::Worldbuilding => self.config.worldbuilding_weight,
+        }
+    }
 
-        for (mut element, score) in worldbuilding_scores {
-            if score >= self.config.similarity_threshold && remaining_tokens >= element.token_count {
-                element.relevance_score = score;
-                remaining_tokens -= element.token_count;
-                selected.worldbuilding.push(element);
+    fn explain(&self, candidate: &Candidate, current_chapter: Option<i32>) -> String {
+        let mut reasons = Vec::new();
+        if matches!(candidate.visibility, VisibilityLevel::Always) {
+            reasons.push("always included (visibility: always)".to_string());
+        }
+        let measure = match self.config.scoring_mode {
+            ScoringMode::Keyword => "keyword overlap",
+            ScoringMode::Embedding => "embedding similarity",
+        };
+        reasons.push(format!("{} {:.2} with the text around the cursor", measure, candidate.similarity));
+        match (candidate.last_seen_chapter, current_chapter) {
+            (Some(seen), Some(current)) if seen >= current => reasons.push("appears in the current chapter".to_string()),
+            (Some(seen), Some(current)) => {
+                reasons.push(format!("last appeared in chapter {} ({} chapter(s) ago)", seen, current - seen))
             }
+            _ => {}
         }
-
-        Ok(selected)
+        let mut explanation = reasons.join("; ");
+        if let Some(first) = explanation.get_mut(0..1) {
+            first.make_ascii_uppercase();
+        }
+        explanation
     }
 
     fn calculate_similarity_score(&self, text1: &str, text2: &str) -> f32 {
-        // Simple keyword-based similarity for now
-        // In a real implementation, this would use embeddings
+        // Jaccard overlap of the longer words
         let words1: std::collections::HashSet<String> = text1
             .to_lowercase()
             .split_whitespace()
@@ -287,15 +497,8 @@ impl SaliencyEngine {
     }
 
     fn estimate_tokens(&self, text: &str) -> i32 {
-        // Rough estimation: 1 token ≈ 0.75 words
-        (text.split_whitespace().count() as f32 * 1.33) as i32
-    }
-
-    fn calculate_total_tokens(&self, elements: &SelectedElements) -> i32 {
-        elements.characters.iter().map(|c| c.token_count).sum::<i32>()
-            + elements.locations.iter().map(|l| l.token_count).sum::<i32>()
-            + elements.plot_threads.iter().map(|p| p.token_count).sum::<i32>()
-            + elements.worldbuilding.iter().map(|w| w.token_count).sum::<i32>()
+        let model = self.config.tokenizer_model.as_deref().unwrap_or_default();
+        super::tokenizer::count_tokens(model, text) as i32
     }
 
     fn calculate_relevance_scores(&self, elements: &SelectedElements) -> HashMap<String, f32> {
@@ -323,17 +526,27 @@ impl SaliencyEngine {
 
         let mut hasher = DefaultHasher::new();
         text.hash(&mut hasher);
-        
-        // Hash story bible elements for cache invalidation
+        story_bible.current_chapter.hash(&mut hasher);
+        story_bible.story_summary.hash(&mut hasher);
+
+        // Hash everything that affects selection, for cache invalidation
         for char in &story_bible.characters {
-            char.id.hash(&mut hasher);
-            char.name.hash(&mut hasher);
+            (&char.id, &char.name, &char.description).hash(&mut hasher);
+            (visibility_key(&char.visibility), char.last_seen_chapter).hash(&mut hasher);
         }
         for loc in &story_bible.locations {
-            loc.id.hash(&mut hasher);
-            loc.name.hash(&mut hasher);
+            (&loc.id, &loc.name, &loc.description).hash(&mut hasher);
+            (visibility_key(&loc.visibility), loc.last_seen_chapter).hash(&mut hasher);
+        }
+        for plot in &story_bible.plot_threads {
+            (&plot.id, &plot.title, &plot.description).hash(&mut hasher);
+            (visibility_key(&plot.visibility), plot.last_seen_chapter).hash(&mut hasher);
+        }
+        for wb in &story_bible.worldbuilding {
+            (&wb.id, &wb.title, &wb.content).hash(&mut hasher);
+            (visibility_key(&wb.visibility), wb.last_seen_chapter).hash(&mut hasher);
         }
-        
+
         format!("{:x}", hasher.finish())
     }
 
@@ -352,29 +565,33 @@ impl SaliencyEngine {
     pub fn format_context_for_ai(&self, context: &SaliencyContext) -> String {
         let mut formatted = String::new();
 
+        if let Some(summary) = &context.story_summary {
+            formatted.push_str(&summary_section(summary));
+        }
+
         if !context.selected_elements.characters.is_empty() {
-            formatted.push_str("\n## Relevant Characters:\n");
+            formatted.push_str(ElementKind::Character.section_header());
             for char in &context.selected_elements.characters {
                 formatted.push_str(&format!("**{}**: {}\n", char.name, char.description));
             }
         }
 
         if !context.selected_elements.locations.is_empty() {
-            formatted.push_str("\n## Relevant Locations:\n");
+            formatted.push_str(ElementKind::Location.section_header());
             for loc in &context.selected_elements.locations {
                 formatted.push_str(&format!("**{}**: {}\n", loc.name, loc.description));
             }
         }
 
         if !context.selected_elements.plot_threads.is_empty() {
-            formatted.push_str("\n## Relevant Plot Threads:\n");
+            formatted.push_str(ElementKind::PlotThread.section_header());
             for plot in &context.selected_elements.plot_threads {
                 formatted.push_str(&format!("**{}**: {}\n", plot.title, plot.description));
             }
         }
 
         if !context.selected_elements.worldbuilding.is_empty() {
-            formatted.push_str("\n## Relevant Worldbuilding:\n");
+            formatted.push_str(ElementKind::Worldbuilding.section_header());
             for wb in &context.selected_elements.worldbuilding {
                 formatted.push_str(&format!("**{} ({})**: {}\n", wb.title, wb.category, wb.content));
             }
@@ -384,6 +601,146 @@ impl SaliencyEngine {
     }
 }
 
+/// How `format_context_for_ai` prints the story summary
+fn summary_section(summary: &str) -> String {
+    format!("\n## Story So Far:\n{}\n", summary.trim())
+}
+
+/// The last `max_chars` characters of `text`
+fn cursor_window(text: &str, max_chars: usize) -> &str {
+    match text.char_indices().rev().nth(max_chars.saturating_sub(1)) {
+        Some((start, _)) if max_chars > 0 => &text[start..],
+        _ => text,
+    }
+}
+
+/// 1.0 for an element seen in the current chapter, halving with each
+/// chapter since; 0.0 when it hasn't appeared yet
+fn recency_factor(last_seen_chapter: Option<i32>, current_chapter: Option<i32>) -> f32 {
+    match (last_seen_chapter, current_chapter) {
+        (Some(seen), Some(current)) => 0.5f32.powi((current - seen).max(0)),
+        _ => 0.0,
+    }
+}
+
+fn visibility_key(visibility: &Option<VisibilityLevel>) -> u8 {
+    match visibility {
+        None => 0,
+        Some(VisibilityLevel::Always) => 1,
+        Some(VisibilityLevel::Relevant) => 2,
+        Some(VisibilityLevel::Manual) => 3,
+        Some(VisibilityLevel::Hidden) => 4,
+    }
+}
+
+/// Whether `name` occurs in `haystack` as whole words; both already lowercased
+fn mentions(haystack: &str, name: &str) -> bool {
+    if name.is_empty() {
+        return false;
+    }
+    haystack.match_indices(name).any(|(start, _)| {
+        let before = haystack[..start].chars().next_back();
+        let after = haystack[start + name.len()..].chars().next();
+        !before.is_some_and(char::is_alphanumeric) && !after.is_some_and(char::is_alphanumeric)
+    })
+}
+
+/// Fills in what the frontend doesn't send: each element's visibility from
+/// the story bible tables, the chapter it was last named in, and the current
+/// chapter. Chapters are the project's chapter documents in order, numbered
+/// from 1; only chapters up to the current one count. The story summary is
+/// taken from the project's rolling summaries.
+pub async fn annotate_from_database(
+    pool: &Pool<Sqlite>,
+    project_id: &str,
+    current_document_id: Option<&str>,
+    story_bible: &mut StoryBibleElements,
+) -> Result<()> {
+    let chapters: Vec<_> = DocumentOps::get_by_project(pool, project_id)
+        .await?
+        .into_iter()
+        .filter(|doc| matches!(doc.document_type, DocumentType::Chapter))
+        .collect();
+    let current_position = current_document_id.and_then(|id| chapters.iter().position(|doc| doc.id == id));
+    if story_bible.current_chapter.is_none() {
+        story_bible.current_chapter = current_position.map(|i| i as i32 + 1);
+    }
+    if story_bible.story_summary.is_none() {
+        story_bible.story_summary = summary_context(pool, project_id, current_document_id).await?;
+    }
+    let visible_chapters = current_position.map_or(chapters.len(), |i| i + 1);
+    let chapter_texts: Vec<(String, String)> = chapters[..visible_chapters]
+        .iter()
+        .map(|doc| (doc.id.clone(), doc.content.to_lowercase()))
+        .collect();
+    let last_named = |name: &str| {
+        let name = name.trim().to_lowercase();
+        chapter_texts
+            .iter()
+            .rposition(|(_, text)| mentions(text, &name))
+            .map(|i| i as i32 + 1)
+    };
+
+    let characters: HashMap<String, VisibilityLevel> = CharacterOps::get_by_project(pool, project_id)
+        .await?
+        .into_iter()
+        .map(|c| (c.id, c.visibility))
+        .collect();
+    for char in &mut story_bible.characters {
+        char.visibility = char.visibility.take().or_else(|| characters.get(&char.id).cloned());
+        char.last_seen_chapter = char.last_seen_chapter.or_else(|| last_named(&char.name));
+    }
+
+    let locations: HashMap<String, VisibilityLevel> = LocationOps::get_by_project(pool, project_id)
+        .await?
+        .into_iter()
+        .map(|l| (l.id, l.visibility))
+        .collect();
+    for loc in &mut story_bible.locations {
+        loc.visibility = loc.visibility.take().or_else(|| locations.get(&loc.id).cloned());
+        loc.last_seen_chapter = loc.last_seen_chapter.or_else(|| last_named(&loc.name));
+    }
+
+    // Plot threads list the documents they run through
+    let plot_threads: HashMap<String, (VisibilityLevel, Vec<String>)> = PlotThreadOps::get_by_project(pool, project_id)
+        .await?
+        .into_iter()
+        .map(|p| {
+            let documents: Vec<String> = serde_json::from_str(&p.documents_involved).unwrap_or_default();
+            (p.id, (p.visibility, documents))
+        })
+        .collect();
+    for plot in &mut story_bible.plot_threads {
+        let Some((visibility, documents)) = plot_threads.get(&plot.id) else {
+            plot.last_seen_chapter = plot.last_seen_chapter.or_else(|| last_named(&plot.title));
+            continue;
+        };
+        plot.visibility = plot.visibility.take().or_else(|| Some(visibility.clone()));
+        plot.last_seen_chapter = plot.last_seen_chapter.or_else(|| {
+            chapter_texts
+                .iter()
+                .rposition(|(id, _)| documents.contains(id))
+                .map(|i| i as i32 + 1)
+        });
+    }
+
+    let world_elements: HashMap<String, bool> = WorldElementOps::get_by_project(pool, project_id)
+        .await?
+        .into_iter()
+        .map(|w| (w.id, w.is_visible))
+        .collect();
+    for wb in &mut story_bible.worldbuilding {
+        if wb.visibility.is_none() {
+            wb.visibility = world_elements.get(&wb.id).map(|visible| {
+                if *visible { VisibilityLevel::Relevant } else { VisibilityLevel::Hidden }
+            });
+        }
+        wb.last_seen_chapter = wb.last_seen_chapter.or_else(|| last_named(&wb.title));
+    }
+
+    Ok(())
+}
+
 // Placeholder structs for Story Bible elements
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct StoryBibleElements {
@@ -391,6 +748,12 @@ pub struct StoryBibleElements {
     pub locations: Vec<StoryBibleLocation>,
     pub plot_threads: Vec<StoryBiblePlotThread>,
     pub worldbuilding: Vec<StoryBibleWorldbuilding>,
+    /// Number of the chapter being written, from 1
+    #[serde(default)]
+    pub current_chapter: Option<i32>,
+    /// Summary of the manuscript around the text being written
+    #[serde(default)]
+    pub story_summary: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -398,6 +761,12 @@ pub struct StoryBibleCharacter {
     pub id: String,
     pub name: String,
     pub description: String,
+    /// Defaults to `Relevant`
+    #[serde(default)]
+    pub visibility: Option<VisibilityLevel>,
+    /// Last chapter the character appeared in
+    #[serde(default)]
+    pub last_seen_chapter: Option<i32>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -405,6 +774,10 @@ pub struct StoryBibleLocation {
     pub id: String,
     pub name: String,
     pub description: String,
+    #[serde(default)]
+    pub visibility: Option<VisibilityLevel>,
+    #[serde(default)]
+    pub last_seen_chapter: Option<i32>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -412,6 +785,10 @@ pub struct StoryBiblePlotThread {
     pub id: String,
     pub title: String,
     pub description: String,
+    #[serde(default)]
+    pub visibility: Option<VisibilityLevel>,
+    #[serde(default)]
+    pub last_seen_chapter: Option<i32>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -420,10 +797,31 @@ pub struct StoryBibleWorldbuilding {
     pub category: String,
     pub title: String,
     pub content: String,
+    #[serde(default)]
+    pub visibility: Option<VisibilityLevel>,
+    #[serde(default)]
+    pub last_seen_chapter: Option<i32>,
 }
 
 impl Default for SaliencyEngine {
     fn default() -> Self {
         Self::new(SaliencyConfig::default())
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cursor_window_keeps_trailing_characters() {
+        assert_eq!(cursor_window("héllo wörld", 5), "wörld");
+        assert_eq!(cursor_window("short", 50), "short");
+    }
+
+    #[test]
+    fn test_mentions_requires_word_boundaries() {
+        assert!(mentions("then mara left.", "mara"));
+        assert!(!mentions("the marathon began", "mara"));
+    }
+}
@@ -0,0 +1,442 @@
+//! Semantic index over documents and story bible entries
+//!
+//! Documents are split into chunks with `documents::split_into_chunks`;
+//! characters, locations, world elements and scenes are embedded whole. Each
+//! unit is stored in `semantic_entries` with its embedding and mirrored into
+//! an FTS5 table, so `search` can rank by embedding similarity alone or blend
+//! it with BM25 keyword scores. Re-indexing a source only re-embeds the chunks
+//! whose text (or the embedding model) changed.
+
+use super::{cosine_similarity, AIProviderManager};
+use crate::database::get_pool;
+use crate::database::models::{Character, Document, Location, Scene, WorldElement};
+use crate::database::operations::{
+    CharacterOps, DocumentOps, LocationOps, OutlineOps, SceneOps, SemanticEntry, SemanticIndexOps, WorldElementOps,
+};
+use crate::documents::split_into_chunks;
+use crate::error::{Result, StoryWeaverError};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::{Pool, Sqlite};
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::{Arc, OnceLock};
+
+/// Bytes per embedded document chunk; small enough that one chunk is about
+/// one scene beat, so a hit points somewhere useful
+pub const SEMANTIC_CHUNK_SIZE: usize = 1500;
+
+/// Share of the hybrid score taken by embedding similarity; BM25 gets the rest
+const HYBRID_SEMANTIC_WEIGHT: f32 = 0.7;
+
+/// How many keyword candidates to fetch per requested result
+const KEYWORD_CANDIDATES_PER_RESULT: i64 = 5;
+
+/// Kind of record an index entry was built from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SemanticSourceType {
+    Document,
+    Character,
+    Location,
+    WorldElement,
+    Scene,
+}
+
+impl SemanticSourceType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SemanticSourceType::Document => "document",
+            SemanticSourceType::Character => "character",
+            SemanticSourceType::Location => "location",
+            SemanticSourceType::WorldElement => "world_element",
+            SemanticSourceType::Scene => "scene",
+        }
+    }
+}
+
+impl FromStr for SemanticSourceType {
+    type Err = StoryWeaverError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "document" => Ok(SemanticSourceType::Document),
+            "character" => Ok(SemanticSourceType::Character),
+            "location" => Ok(SemanticSourceType::Location),
+            "world_element" => Ok(SemanticSourceType::WorldElement),
+            "scene" => Ok(SemanticSourceType::Scene),
+            _ => Err(StoryWeaverError::validation(format!("Unknown semantic source type: {}", s))),
+        }
+    }
+}
+
+/// A record to (re-)index
+#[derive(Debug, Clone)]
+pub enum SemanticSource {
+    Document(Document),
+    Character(Character),
+    Location(Location),
+    WorldElement(WorldElement),
+    Scene(Scene),
+}
+
+/// How `search` ranks results
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SearchMode {
+    /// Embedding similarity only
+    Semantic,
+    /// Embedding similarity blended with FTS5 BM25
+    #[default]
+    Hybrid,
+}
+
+/// One ranked search result
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SemanticSearchHit {
+    pub source_type: SemanticSourceType,
+    pub source_id: String,
+    pub title: String,
+    pub chunk_index: i32,
+    /// Byte range of the chunk within the document; 0..0 for story bible entries
+    pub start_position: i64,
+    pub end_position: i64,
+    pub content: String,
+    pub score: f32,
+    pub semantic_score: f32,
+    /// BM25 score scaled to 0..1 against the best keyword match; hybrid mode only
+    pub keyword_score: Option<f32>,
+}
+
+/// A unit of text waiting to be embedded
+struct Piece {
+    title: String,
+    content: String,
+    start_position: i64,
+    end_position: i64,
+}
+
+impl Piece {
+    fn whole(title: &str, content: String) -> Self {
+        Self {
+            title: title.to_string(),
+            content,
+            start_position: 0,
+            end_position: 0,
+        }
+    }
+}
+
+fn content_hash(text: &str) -> String {
+    let digest = Sha256::digest(text.as_bytes());
+    digest.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Join labelled fields, skipping empty ones
+fn describe(fields: &[(&str, Option<&str>)]) -> String {
+    fields
+        .iter()
+        .filter_map(|(label, value)| {
+            value
+                .map(str::trim)
+                .filter(|v| !v.is_empty())
+                .map(|v| if label.is_empty() { v.to_string() } else { format!("{}: {}", label, v) })
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Turn free text into an FTS5 query that matches any of its words, so user
+/// punctuation can't produce a syntax error
+pub fn fts_match_query(query: &str) -> Option<String> {
+    let terms: Vec<String> = query
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(|w| format!("\"{}\"", w))
+        .collect();
+    if terms.is_empty() {
+        None
+    } else {
+        Some(terms.join(" OR "))
+    }
+}
+
+/// Builds and queries the semantic index
+pub struct SemanticIndexer {
+    providers: Arc<AIProviderManager>,
+}
+
+impl SemanticIndexer {
+    pub fn new(providers: Arc<AIProviderManager>) -> Self {
+        Self { providers }
+    }
+
+    /// Index or re-index one record; returns the number of entries stored
+    pub async fn index(&self, pool: &Pool<Sqlite>, source: &SemanticSource) -> Result<usize> {
+        match source {
+            SemanticSource::Document(document) => {
+                let pieces = split_into_chunks(&document.id, &document.content, SEMANTIC_CHUNK_SIZE)
+                    .into_iter()
+                    .filter(|chunk| !chunk.content.trim().is_empty())
+                    .map(|chunk| Piece {
+                        title: document.title.clone(),
+                        content: chunk.content,
+                        start_position: chunk.start_position as i64,
+                        end_position: chunk.end_position as i64,
+                    })
+                    .collect();
+                self.index_pieces(pool, &document.project_id, SemanticSourceType::Document, &document.id, pieces).await
+            }
+            SemanticSource::Character(character) => {
+                let role = format!("{:?}", character.role);
+                let content = describe(&[
+                    ("", Some(character.name.as_str())),
+                    ("Role", Some(role.as_str())),
+                    ("", character.description.as_deref()),
+                    ("Appearance", character.appearance.as_deref()),
+                    ("Personality", character.personality.as_deref()),
+                    ("Background", character.background.as_deref()),
+                    ("Goals", character.goals.as_deref()),
+                ]);
+                let pieces = vec![Piece::whole(&character.name, content)];
+                self.index_pieces(pool, &character.project_id, SemanticSourceType::Character, &character.id, pieces).await
+            }
+            SemanticSource::Location(location) => {
+                let location_type = format!("{:?}", location.location_type);
+                let content = describe(&[
+                    ("", Some(location.name.as_str())),
+                    ("Type", Some(location_type.as_str())),
+                    ("", location.description.as_deref()),
+                    ("Geography", location.geography.as_deref()),
+                    ("Climate", location.climate.as_deref()),
+                    ("Culture", location.culture.as_deref()),
+                    ("History", location.history.as_deref()),
+                ]);
+                let pieces = vec![Piece::whole(&location.name, content)];
+                self.index_pieces(pool, &location.project_id, SemanticSourceType::Location, &location.id, pieces).await
+            }
+            SemanticSource::WorldElement(element) => {
+                // Series-level elements without a project aren't searchable per project
+                let Some(project_id) = element.project_id.as_deref() else {
+                    return Ok(0);
+                };
+                let content = describe(&[
+                    ("", Some(element.name.as_str())),
+                    ("Type", Some(element.element_type.as_str())),
+                    ("", element.description.as_deref()),
+                ]);
+                let pieces = vec![Piece::whole(&element.name, content)];
+                self.index_pieces(pool, project_id, SemanticSourceType::WorldElement, &element.id, pieces).await
+            }
+            SemanticSource::Scene(scene) => {
+                let outline = OutlineOps::get_by_id(pool, &scene.outline_id).await?;
+                let title = scene.title.clone().unwrap_or_else(|| format!("Scene {}", scene.scene_number));
+                let content = describe(&[
+                    ("", Some(title.as_str())),
+                    ("", scene.summary.as_deref()),
+                    ("POV", scene.pov.as_deref()),
+                    ("Notes", scene.extra_instructions.as_deref()),
+                ]);
+                let pieces = vec![Piece::whole(&title, content)];
+                self.index_pieces(pool, &outline.project_id, SemanticSourceType::Scene, &scene.id, pieces).await
+            }
+        }
+    }
+
+    /// Drop a deleted record from the index
+    pub async fn remove(&self, pool: &Pool<Sqlite>, source_type: SemanticSourceType, source_id: &str) -> Result<()> {
+        SemanticIndexOps::delete_source(pool, source_type.as_str(), source_id).await
+    }
+
+    /// Index every document and story bible entry of a project, e.g. after an
+    /// upgrade or an embedding model change. Unchanged entries are reused.
+    pub async fn reindex_project(&self, pool: &Pool<Sqlite>, project_id: &str) -> Result<usize> {
+        let mut sources: Vec<SemanticSource> = Vec::new();
+        sources.extend(DocumentOps::get_by_project(pool, project_id).await?.into_iter().map(SemanticSource::Document));
+        sources.extend(CharacterOps::get_by_project(pool, project_id).await?.into_iter().map(SemanticSource::Character));
+        sources.extend(LocationOps::get_by_project(pool, project_id).await?.into_iter().map(SemanticSource::Location));
+        sources.extend(WorldElementOps::get_by_project(pool, project_id).await?.into_iter().map(SemanticSource::WorldElement));
+        for outline in OutlineOps::get_by_project(pool, project_id).await? {
+            sources.extend(SceneOps::get_by_outline(pool, &outline.id).await?.into_iter().map(SemanticSource::Scene));
+        }
+
+        let mut indexed = 0;
+        for source in &sources {
+            indexed += self.index(pool, source).await?;
+        }
+        Ok(indexed)
+    }
+
+    async fn index_pieces(
+        &self,
+        pool: &Pool<Sqlite>,
+        project_id: &str,
+        source_type: SemanticSourceType,
+        source_id: &str,
+        pieces: Vec<Piece>,
+    ) -> Result<usize> {
+        // Reuse vectors for text the current model has already embedded
+        let model = self.providers.embedding_model();
+        let mut existing: HashMap<String, Vec<f32>> = SemanticIndexOps::get_by_source(pool, source_type.as_str(), source_id)
+            .await?
+            .into_iter()
+            .filter(|entry| entry.model == model)
+            .map(|entry| (entry.content_hash, entry.embedding))
+            .collect();
+
+        let mut entries = Vec::with_capacity(pieces.len());
+        for (index, piece) in pieces.into_iter().enumerate() {
+            if piece.content.trim().is_empty() {
+                continue;
+            }
+            let hash = content_hash(&piece.content);
+            let (model, embedding) = match existing.remove(&hash) {
+                Some(embedding) => (model.clone(), embedding),
+                None => {
+                    let embedding = self.providers.embed(&piece.content).await?;
+                    (embedding.model, embedding.vector)
+                }
+            };
+            entries.push(SemanticEntry {
+                id: None,
+                project_id: project_id.to_string(),
+                source_type: source_type.as_str().to_string(),
+                source_id: source_id.to_string(),
+                chunk_index: index as i32,
+                start_position: piece.start_position,
+                end_position: piece.end_position,
+                title: piece.title,
+                content: piece.content,
+                content_hash: hash,
+                model,
+                embedding,
+            });
+        }
+
+        SemanticIndexOps::replace_source(pool, source_type.as_str(), source_id, &entries).await?;
+        Ok(entries.len())
+    }
+
+    /// The `k` entries of a project most relevant to `query`
+    pub async fn search(
+        &self,
+        pool: &Pool<Sqlite>,
+        project_id: &str,
+        query: &str,
+        k: usize,
+        mode: SearchMode,
+    ) -> Result<Vec<SemanticSearchHit>> {
+        if query.trim().is_empty() || k == 0 {
+            return Ok(Vec::new());
+        }
+
+        let query_embedding = self.providers.embed(query).await?;
+        let entries = SemanticIndexOps::get_by_project_and_model(pool, project_id, &query_embedding.model).await?;
+
+        let keyword_scores: HashMap<i64, f32> = match (mode, fts_match_query(query)) {
+            (SearchMode::Hybrid, Some(match_query)) => {
+                let limit = k as i64 * KEYWORD_CANDIDATES_PER_RESULT;
+                let matches = SemanticIndexOps::keyword_search(pool, project_id, &match_query, limit).await?;
+                let best = matches.iter().map(|(_, score)| *score).fold(0.0f64, f64::max);
+                if best > 0.0 {
+                    matches.into_iter().map(|(id, score)| (id, (score / best).max(0.0) as f32)).collect()
+                } else {
+                    HashMap::new()
+                }
+            }
+            _ => HashMap::new(),
+        };
+
+        let mut hits: Vec<SemanticSearchHit> = entries
+            .into_iter()
+            .filter_map(|entry| {
+                let source_type = SemanticSourceType::from_str(&entry.source_type).ok()?;
+                let semantic_score = cosine_similarity(&query_embedding.vector, &entry.embedding).max(0.0);
+                let (score, keyword_score) = match mode {
+                    SearchMode::Semantic => (semantic_score, None),
+                    SearchMode::Hybrid => {
+                        let keyword = entry.id.and_then(|id| keyword_scores.get(&id)).copied().unwrap_or(0.0);
+                        (
+                            HYBRID_SEMANTIC_WEIGHT * semantic_score + (1.0 - HYBRID_SEMANTIC_WEIGHT) * keyword,
+                            Some(keyword),
+                        )
+                    }
+                };
+                (score > 0.0).then(|| SemanticSearchHit {
+                    source_type,
+                    source_id: entry.source_id,
+                    title: entry.title,
+                    chunk_index: entry.chunk_index,
+                    start_position: entry.start_position,
+                    end_position: entry.end_position,
+                    content: entry.content,
+                    score,
+                    semantic_score,
+                    keyword_score,
+                })
+            })
+            .collect();
+
+        hits.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(std::cmp::Ordering::Equal));
+        hits.truncate(k);
+        Ok(hits)
+    }
+}
+
+static SEMANTIC_INDEXER: OnceLock<Arc<SemanticIndexer>> = OnceLock::new();
+
+/// Set up the shared indexer used to keep the index current on save
+pub fn init_semantic_indexer(providers: Arc<AIProviderManager>) -> Result<()> {
+    SEMANTIC_INDEXER
+        .set(Arc::new(SemanticIndexer::new(providers)))
+        .map_err(|_| StoryWeaverError::internal("Semantic indexer already initialized"))
+}
+
+pub fn get_semantic_indexer() -> Result<Arc<SemanticIndexer>> {
+    SEMANTIC_INDEXER
+        .get()
+        .cloned()
+        .ok_or_else(|| StoryWeaverError::internal("Semantic indexer not initialized"))
+}
+
+/// Re-index a saved record in the background so saving never waits on
+/// embedding. A no-op until the indexer is initialized.
+pub fn schedule_index(source: SemanticSource) {
+    let (Ok(indexer), Ok(pool)) = (get_semantic_indexer(), get_pool()) else {
+        return;
+    };
+    tokio::spawn(async move {
+        if let Err(e) = indexer.index(&pool, &source).await {
+            tracing::warn!("Failed to update semantic index: {}", e);
+        }
+    });
+}
+
+/// Drop a deleted record from the index in the background
+pub fn schedule_removal(source_type: SemanticSourceType, source_id: String) {
+    let (Ok(indexer), Ok(pool)) = (get_semantic_indexer(), get_pool()) else {
+        return;
+    };
+    tokio::spawn(async move {
+        if let Err(e) = indexer.remove(&pool, source_type, &source_id).await {
+            tracing::warn!("Failed to remove {} {} from semantic index: {}", source_type.as_str(), source_id, e);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fts_match_query_quotes_words() {
+        assert_eq!(fts_match_query("Mara's ship, at dawn?").unwrap(), "\"Mara\" OR \"s\" OR \"ship\" OR \"at\" OR \"dawn\"");
+        assert!(fts_match_query("  -- ").is_none());
+    }
+
+    #[test]
+    fn test_describe_skips_empty_fields() {
+        let text = describe(&[("", Some("Mara")), ("Goals", Some("  ")), ("Background", Some("Sailor"))]);
+        assert_eq!(text, "Mara\nBackground: Sailor");
+    }
+}
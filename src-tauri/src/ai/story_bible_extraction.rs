@@ -0,0 +1,399 @@
+//! Story bible extraction from an existing manuscript
+//!
+//! Every document of a project is read in manuscript order, and the model
+//! lists the characters, locations, world elements and timeline events each
+//! passage gives evidence for. Evidence for the same entry is merged across
+//! documents by name similarity, with a citation for every passage it came
+//! from. Entries whose names match one already in the story bible (or one of
+//! its aliases) are left out; close matches are flagged for the writer. Each
+//! scanned document is checkpointed, so a failed extraction resumes at the
+//! next one. Nothing reaches the story bible until the writer accepts it.
+
+use super::prompt_templates::{render_prompt, PromptScope};
+use super::{AIContext, AIProvider, CancellationToken, JsonSchema, StructuredGeneration};
+use crate::continuity::assisted::chunks;
+use crate::continuity::manuscript_order;
+use crate::database::models::Document;
+use crate::database::operations::{
+    CharacterOps, DocumentOps, ExtractionStatus, LocationOps, ProposalCitation, ProposalType, StoryBibleAliasOps,
+    StoryBibleEntityType, StoryBibleExtraction, StoryBibleExtractionOps, StoryBibleProposal, TimelineOps,
+    WorldElementOps,
+};
+use crate::database::DbPool;
+use crate::error::{Result, StoryWeaverError};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::{BTreeSet, HashSet};
+
+/// Names at least this similar to an existing entry's are the same entry,
+/// and aren't proposed
+pub const DUPLICATE_SIMILARITY: f64 = 0.9;
+
+/// Proposals at least this similar to an existing entry's name are flagged
+/// as possible duplicates
+pub const POSSIBLE_DUPLICATE_SIMILARITY: f64 = 0.75;
+
+/// Evidence at least this similar to a proposal's name is merged into it
+pub const MERGE_SIMILARITY: f64 = 0.8;
+
+/// Structured reply for `story_bible_extraction`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GeneratedEntries {
+    pub entries: Vec<GeneratedEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GeneratedEntry {
+    pub kind: String,
+    pub name: String,
+    #[serde(default)]
+    pub category: Option<String>,
+    pub description: String,
+    #[serde(default)]
+    pub date: Option<String>,
+    pub quote: String,
+}
+
+pub fn story_bible_entries_schema() -> JsonSchema {
+    JsonSchema::new(
+        "story_bible_entries",
+        json!({
+            "type": "object",
+            "properties": {
+                "entries": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "kind": { "type": "string", "enum": ["character", "location", "world_element", "timeline_event"] },
+                            "name": { "type": "string", "minLength": 1, "maxLength": 200 },
+                            "category": { "type": "string", "maxLength": 100 },
+                            "description": { "type": "string", "maxLength": 1000 },
+                            "date": { "type": "string", "maxLength": 100 },
+                            "quote": { "type": "string", "minLength": 1, "maxLength": 1000 }
+                        },
+                        "required": ["kind", "name", "description", "quote"],
+                        "additionalProperties": false
+                    }
+                }
+            },
+            "required": ["entries"],
+            "additionalProperties": false
+        }),
+    )
+}
+
+/// A name as compared: lowercase words of letters and digits, without a
+/// leading "the"
+fn comparable_name(name: &str) -> Vec<String> {
+    let lowered = name.to_lowercase();
+    let mut words: Vec<String> = lowered
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_string)
+        .collect();
+    if words.len() > 1 && words[0] == "the" {
+        words.remove(0);
+    }
+    words
+}
+
+fn edit_distance(a: &[char], b: &[char]) -> usize {
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.iter().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// How alike two names are, from 0 to 1. Spelling is compared by edit
+/// distance, ignoring case, punctuation and a leading "the"; a name whose
+/// words all appear in the other ("Mara" and "Mara Voss") scores 0.8.
+pub fn name_similarity(a: &str, b: &str) -> f64 {
+    let (a, b) = (comparable_name(a), comparable_name(b));
+    if a.is_empty() || b.is_empty() {
+        return 0.0;
+    }
+    let (joined_a, joined_b): (Vec<char>, Vec<char>) = (a.join(" ").chars().collect(), b.join(" ").chars().collect());
+    let longest = joined_a.len().max(joined_b.len());
+    let spelling = 1.0 - edit_distance(&joined_a, &joined_b) as f64 / longest as f64;
+
+    let (shorter, longer) = if a.len() <= b.len() { (&a, &b) } else { (&b, &a) };
+    let contained = if shorter.iter().all(|word| longer.contains(word)) { 0.8 } else { 0.0 };
+    spelling.max(contained)
+}
+
+/// A story bible entry that already exists, with its aliases
+#[derive(Debug, Clone)]
+pub struct ExistingEntry {
+    pub entity_type: ProposalType,
+    pub id: String,
+    pub name: String,
+    pub aliases: Vec<String>,
+}
+
+impl ExistingEntry {
+    fn similarity(&self, name: &str) -> f64 {
+        std::iter::once(&self.name)
+            .chain(&self.aliases)
+            .map(|known| name_similarity(known, name))
+            .fold(0.0, f64::max)
+    }
+}
+
+/// The project's story bible entries, for deduplication
+pub async fn existing_entries(pool: &DbPool, project_id: &str) -> Result<Vec<ExistingEntry>> {
+    let aliases = StoryBibleAliasOps::get_by_project(pool, project_id).await?;
+    let entry = |entity_type: ProposalType, alias_type: Option<StoryBibleEntityType>, id: String, name: String| {
+        let aliases = aliases
+            .iter()
+            .filter(|alias| Some(alias.entity_type) == alias_type && alias.entity_id == id)
+            .map(|alias| alias.alias.clone())
+            .collect();
+        ExistingEntry { entity_type, id, name, aliases }
+    };
+
+    let mut entries = Vec::new();
+    for character in CharacterOps::get_by_project(pool, project_id).await? {
+        entries.push(entry(ProposalType::Character, Some(StoryBibleEntityType::Character), character.id, character.name));
+    }
+    for location in LocationOps::get_by_project(pool, project_id).await? {
+        entries.push(entry(ProposalType::Location, Some(StoryBibleEntityType::Location), location.id, location.name));
+    }
+    for element in WorldElementOps::get_by_project(pool, project_id).await? {
+        entries.push(entry(ProposalType::WorldElement, Some(StoryBibleEntityType::WorldElement), element.id, element.name));
+    }
+    for event in TimelineOps::get_by_project(pool, project_id).await? {
+        entries.push(entry(ProposalType::TimelineEvent, None, event.id, event.title));
+    }
+    Ok(entries)
+}
+
+/// One entry's evidence from one passage
+#[derive(Debug, Clone)]
+pub struct Evidence {
+    pub entity_type: ProposalType,
+    pub name: String,
+    pub category: Option<String>,
+    pub description: Option<String>,
+    pub date: Option<String>,
+    pub citation: ProposalCitation,
+}
+
+fn non_empty(value: &Option<String>) -> Option<String> {
+    value.as_deref().map(str::trim).filter(|value| !value.is_empty()).map(str::to_string)
+}
+
+/// Turn one of the model's entries into evidence, or `None` when its kind is
+/// unknown or its quote isn't in the text
+fn to_evidence(generated: &GeneratedEntry, document_id: &str, offset: usize, text: &str) -> Option<Evidence> {
+    let entity_type = ProposalType::parse(&generated.kind)?;
+    let name = generated.name.trim();
+    let quote = generated.quote.trim();
+    if name.is_empty() || quote.is_empty() {
+        return None;
+    }
+    let at = text.find(quote)?;
+    let start = offset + text[..at].chars().count();
+    Some(Evidence {
+        entity_type,
+        name: name.to_string(),
+        category: non_empty(&generated.category).map(|category| category.to_lowercase()),
+        description: Some(generated.description.trim().to_string()).filter(|description| !description.is_empty()),
+        date: non_empty(&generated.date).filter(|_| entity_type == ProposalType::TimelineEvent),
+        citation: ProposalCitation {
+            document_id: document_id.to_string(),
+            start,
+            end: start + quote.chars().count(),
+            excerpt: quote.to_string(),
+        },
+    })
+}
+
+/// The most similar entry of `entity_type` among `candidates`, with its
+/// similarity
+fn most_similar<T>(
+    candidates: &[T],
+    entity_type: ProposalType,
+    type_of: impl Fn(&T) -> ProposalType,
+    similarity: impl Fn(&T) -> f64,
+) -> Option<(usize, &T, f64)> {
+    candidates
+        .iter()
+        .enumerate()
+        .filter(|(_, candidate)| type_of(candidate) == entity_type)
+        .map(|(index, candidate)| (index, candidate, similarity(candidate)))
+        .max_by(|a, b| a.2.total_cmp(&b.2))
+}
+
+/// Flag the existing entry closest to the proposal's name, if close enough
+fn flag_possible_duplicate(proposal: &mut StoryBibleProposal, existing: &[ExistingEntry]) {
+    let closest = most_similar(existing, proposal.entity_type, |e| e.entity_type, |e| e.similarity(&proposal.name))
+        .filter(|(_, _, similarity)| *similarity >= POSSIBLE_DUPLICATE_SIMILARITY);
+    proposal.possible_duplicate_id = closest.map(|(_, entry, _)| entry.id.clone());
+    proposal.possible_duplicate_name = closest.map(|(_, entry, _)| entry.name.clone());
+}
+
+/// Add `evidence` to the proposals: merged into the most similar proposal of
+/// its kind, or as a new one. Returns the index of the proposal it went to,
+/// or `None` when the story bible already has the entry.
+pub fn merge_evidence(
+    proposals: &mut Vec<StoryBibleProposal>,
+    evidence: Evidence,
+    existing: &[ExistingEntry],
+    extraction: &StoryBibleExtraction,
+) -> Option<usize> {
+    let known = most_similar(existing, evidence.entity_type, |e| e.entity_type, |e| e.similarity(&evidence.name));
+    if known.is_some_and(|(_, _, similarity)| similarity >= DUPLICATE_SIMILARITY) {
+        return None;
+    }
+
+    let same = most_similar(proposals, evidence.entity_type, |p| p.entity_type, |p| name_similarity(&p.name, &evidence.name))
+        .filter(|(_, _, similarity)| *similarity >= MERGE_SIMILARITY)
+        .map(|(index, _, _)| index);
+    let index = match same {
+        Some(index) => index,
+        None => {
+            let proposal =
+                StoryBibleProposal::new(&extraction.id, &extraction.project_id, evidence.entity_type, &evidence.name);
+            proposals.push(proposal);
+            proposals.len() - 1
+        }
+    };
+
+    let proposal = &mut proposals[index];
+    // The fullest name wins: "Mara" becomes "Mara Voss"
+    if evidence.name.chars().count() > proposal.name.chars().count() {
+        proposal.name = evidence.name;
+    }
+    if proposal.category.is_none() {
+        proposal.category = evidence.category;
+    }
+    if proposal.event_date.is_none() {
+        proposal.event_date = evidence.date;
+    }
+    if let Some(description) = evidence.description {
+        let known = proposal.description.as_deref().unwrap_or("");
+        if !known.to_lowercase().contains(&description.to_lowercase()) {
+            proposal.description = Some(if known.is_empty() { description } else { format!("{} {}", known, description) });
+        }
+    }
+    let cited = proposal
+        .citations
+        .iter()
+        .any(|c| c.document_id == evidence.citation.document_id && c.start == evidence.citation.start);
+    if !cited {
+        proposal.citations.push(evidence.citation);
+    }
+    flag_possible_duplicate(proposal, existing);
+    Some(index)
+}
+
+/// The project's documents in the order they are read: the manuscript's
+/// chapters and scenes first, then everything else
+pub fn reading_order(documents: &[Document]) -> Vec<&Document> {
+    let mut ordered = manuscript_order(documents);
+    let placed: HashSet<&str> = ordered.iter().map(|doc| doc.id.as_str()).collect();
+    let mut rest: Vec<&Document> = documents.iter().filter(|doc| !placed.contains(doc.id.as_str())).collect();
+    rest.sort_by_key(|doc| doc.order_index);
+    ordered.extend(rest);
+    ordered.retain(|doc| !doc.content.trim().is_empty());
+    ordered
+}
+
+/// Create an extraction of the project's story bible, to be run by
+/// `run_story_bible_extraction`
+pub async fn create_story_bible_extraction(pool: &DbPool, project_id: &str) -> Result<StoryBibleExtraction> {
+    let documents = DocumentOps::get_by_project(pool, project_id).await?;
+    let document_count = reading_order(&documents).len();
+    if document_count == 0 {
+        return Err(StoryWeaverError::validation("The project has no documents with text to read"));
+    }
+
+    let mut extraction = StoryBibleExtraction::new(project_id);
+    extraction.document_count = document_count as i32;
+    StoryBibleExtractionOps::create(pool, &extraction).await?;
+    Ok(extraction)
+}
+
+/// Read the project's remaining documents and save what they propose.
+/// `on_progress` is called with the documents done and the total after each
+/// checkpoint. A completed extraction is returned as it is.
+pub async fn run_story_bible_extraction(
+    pool: &DbPool,
+    provider: &dyn AIProvider,
+    extraction_id: &str,
+    cancellation: Option<CancellationToken>,
+    on_progress: &mut (dyn FnMut(usize, usize) + Send),
+) -> Result<StoryBibleExtraction> {
+    let extraction = StoryBibleExtractionOps::get(pool, extraction_id).await?;
+    if extraction.status == ExtractionStatus::Completed {
+        return Ok(extraction);
+    }
+
+    match scan_documents(pool, provider, &extraction, cancellation, on_progress).await {
+        Ok(()) => StoryBibleExtractionOps::get(pool, extraction_id).await,
+        Err(error) => {
+            // Cancellation leaves the extraction to be resumed like a failure
+            StoryBibleExtractionOps::mark_failed(pool, extraction_id, &error.to_string()).await?;
+            Err(error)
+        }
+    }
+}
+
+async fn scan_documents(
+    pool: &DbPool,
+    provider: &dyn AIProvider,
+    extraction: &StoryBibleExtraction,
+    cancellation: Option<CancellationToken>,
+    on_progress: &mut (dyn FnMut(usize, usize) + Send),
+) -> Result<()> {
+    let documents = DocumentOps::get_by_project(pool, &extraction.project_id).await?;
+    let documents = reading_order(&documents);
+    let total = documents.len();
+    StoryBibleExtractionOps::mark_running(pool, &extraction.id, total as i32).await?;
+
+    let existing = existing_entries(pool, &extraction.project_id).await?;
+    let known: Vec<&str> = existing.iter().map(|entry| entry.name.as_str()).collect();
+    let mut proposals = StoryBibleExtractionOps::get_proposals(pool, &extraction.id).await?;
+    let scanned = StoryBibleExtractionOps::get_scanned_documents(pool, &extraction.id).await?;
+
+    let schema = story_bible_entries_schema();
+    let scope = PromptScope::for_project(&extraction.project_id);
+    let mut done = documents.iter().filter(|doc| scanned.contains(&doc.id)).count();
+    for (position, document) in documents.iter().enumerate() {
+        if scanned.contains(&document.id) {
+            continue;
+        }
+
+        let context = AIContext {
+            project_id: Some(extraction.project_id.clone()),
+            document_id: Some(document.id.clone()),
+            cancellation: cancellation.clone(),
+            ..Default::default()
+        };
+        let mut touched = BTreeSet::new();
+        for (offset, chunk) in chunks(&document.content) {
+            if chunk.trim().is_empty() {
+                continue;
+            }
+            let prompt = render_prompt("story_bible_extraction", &scope, &json!({ "known": known, "text": chunk }))?.text;
+            let generated: GeneratedEntries = provider.generate_structured(&prompt, &schema, &context).await?;
+            for evidence in generated.entries.iter().filter_map(|entry| to_evidence(entry, &document.id, offset, chunk)) {
+                touched.extend(merge_evidence(&mut proposals, evidence, &existing, extraction));
+            }
+        }
+
+        let changed: Vec<&StoryBibleProposal> = touched.iter().map(|&index| &proposals[index]).collect();
+        StoryBibleExtractionOps::save_document(pool, &extraction.id, &document.id, position as i32, &changed).await?;
+        done += 1;
+        on_progress(done, total);
+    }
+
+    StoryBibleExtractionOps::mark_completed(pool, &extraction.id).await
+}
@@ -0,0 +1,383 @@
+//! Read-only story bible tools for tool-calling generation
+//!
+//! `StoryBibleTools` answers `lookup_character`, `lookup_location`,
+//! `search_manuscript`, `get_timeline_events` and `get_plot_thread` from the
+//! project's database, so the model can fetch canon on demand during long
+//! chapters. Elements marked hidden are never returned. Every call is written
+//! to the AI history.
+
+use super::semantic_index::fts_match_query;
+use super::tools::{ToolCall, ToolDefinition, ToolExecutor};
+use crate::database::models::{AIGenerationHistory, AIGenerationType, Character, Location, PlotThread, TimelineEvent, VisibilityLevel};
+use crate::database::operations::{AIHistoryOps, CharacterOps, DocumentOps, LocationOps, PlotThreadOps, TimelineOps};
+use crate::database::DbPool;
+use crate::error::{Result, StoryWeaverError};
+use async_trait::async_trait;
+use serde_json::{json, Value};
+
+/// Most matches returned for a name lookup
+const MAX_LOOKUP_MATCHES: usize = 3;
+/// Default and largest number of manuscript passages returned
+const DEFAULT_SEARCH_RESULTS: usize = 5;
+const MAX_SEARCH_RESULTS: usize = 10;
+/// Characters of manuscript text on either side of a search hit
+const EXCERPT_RADIUS: usize = 200;
+const MAX_TIMELINE_EVENTS: usize = 20;
+
+/// Story bible tools scoped to one project
+pub struct StoryBibleTools {
+    pool: DbPool,
+    project_id: String,
+    document_id: Option<String>,
+    provider: String,
+    model: String,
+}
+
+impl StoryBibleTools {
+    pub fn new(pool: DbPool, project_id: &str) -> Self {
+        Self {
+            pool,
+            project_id: project_id.to_string(),
+            document_id: None,
+            provider: "unknown".to_string(),
+            model: "unknown".to_string(),
+        }
+    }
+
+    /// Document being written, recorded with each call in the AI history
+    pub fn for_document(mut self, document_id: Option<&str>) -> Self {
+        self.document_id = document_id.map(|id| id.to_string());
+        self
+    }
+
+    /// Provider and model credited with the calls in the AI history
+    pub fn logged_as(mut self, provider: &str, model: &str) -> Self {
+        self.provider = provider.to_string();
+        self.model = model.to_string();
+        self
+    }
+
+    async fn run(&self, call: &ToolCall) -> Result<Value> {
+        match call.name.as_str() {
+            "lookup_character" => self.lookup_character(required_arg(call, "name")?).await,
+            "lookup_location" => self.lookup_location(required_arg(call, "name")?).await,
+            "search_manuscript" => {
+                let limit = call
+                    .arguments
+                    .get("limit")
+                    .and_then(Value::as_u64)
+                    .map_or(DEFAULT_SEARCH_RESULTS, |limit| (limit as usize).clamp(1, MAX_SEARCH_RESULTS));
+                self.search_manuscript(required_arg(call, "query")?, limit).await
+            }
+            "get_timeline_events" => {
+                self.get_timeline_events(call.str_arg("character"), call.str_arg("location")).await
+            }
+            "get_plot_thread" => self.get_plot_thread(required_arg(call, "name")?).await,
+            other => Err(StoryWeaverError::not_found("tool", other)),
+        }
+    }
+
+    async fn lookup_character(&self, name: &str) -> Result<Value> {
+        let characters = visible_characters(&self.pool, &self.project_id).await?;
+        let matches: Vec<Value> = best_matches(&characters, name, |c| c.name.as_str())
+            .into_iter()
+            .map(|c| {
+                compact(json!({
+                    "name": c.name,
+                    "role": c.role,
+                    "age": c.age,
+                    "description": c.description,
+                    "appearance": c.appearance,
+                    "personality": c.personality,
+                    "background": c.background,
+                    "goals": c.goals,
+                }))
+            })
+            .collect();
+        Ok(found_or_note(matches, "character", name))
+    }
+
+    async fn lookup_location(&self, name: &str) -> Result<Value> {
+        let locations: Vec<Location> = LocationOps::get_by_project(&self.pool, &self.project_id)
+            .await?
+            .into_iter()
+            .filter(|l| !matches!(l.visibility, VisibilityLevel::Hidden))
+            .collect();
+        let matches: Vec<Value> = best_matches(&locations, name, |l| l.name.as_str())
+            .into_iter()
+            .map(|l| {
+                compact(json!({
+                    "name": l.name,
+                    "type": l.location_type,
+                    "description": l.description,
+                    "geography": l.geography,
+                    "climate": l.climate,
+                    "culture": l.culture,
+                    "history": l.history,
+                    "significance": l.significance,
+                }))
+            })
+            .collect();
+        Ok(found_or_note(matches, "location", name))
+    }
+
+    async fn search_manuscript(&self, query: &str, limit: usize) -> Result<Value> {
+        let Some(match_query) = fts_match_query(query) else {
+            return Ok(json!({ "results": [] }));
+        };
+        let documents = DocumentOps::search(&self.pool, &self.project_id, &match_query).await?;
+        let results: Vec<Value> = documents
+            .iter()
+            .take(limit)
+            .map(|d| json!({ "document": d.title, "excerpt": excerpt(&d.content, query) }))
+            .collect();
+        Ok(json!({ "results": results }))
+    }
+
+    async fn get_timeline_events(&self, character: Option<&str>, location: Option<&str>) -> Result<Value> {
+        let mut events: Vec<TimelineEvent> = TimelineOps::get_by_project(&self.pool, &self.project_id)
+            .await?
+            .into_iter()
+            .filter(|e| !matches!(e.visibility, VisibilityLevel::Hidden))
+            .collect();
+
+        if let Some(name) = character {
+            let characters = visible_characters(&self.pool, &self.project_id).await?;
+            let ids: Vec<&str> = best_matches(&characters, name, |c| c.name.as_str()).iter().map(|c| c.id.as_str()).collect();
+            events.retain(|e| ids.iter().any(|id| e.characters_involved.contains(id)));
+        }
+        if let Some(name) = location {
+            let locations = LocationOps::get_by_project(&self.pool, &self.project_id).await?;
+            let ids: Vec<&str> = best_matches(&locations, name, |l| l.name.as_str()).iter().map(|l| l.id.as_str()).collect();
+            events.retain(|e| ids.iter().any(|id| e.locations_involved.contains(id)));
+        }
+
+        let events: Vec<Value> = events
+            .iter()
+            .take(MAX_TIMELINE_EVENTS)
+            .map(|e| {
+                compact(json!({
+                    "title": e.title,
+                    "date": e.event_date,
+                    "importance": e.importance,
+                    "description": e.description,
+                }))
+            })
+            .collect();
+        Ok(json!({ "events": events }))
+    }
+
+    async fn get_plot_thread(&self, name: &str) -> Result<Value> {
+        let threads: Vec<PlotThread> = PlotThreadOps::get_by_project(&self.pool, &self.project_id)
+            .await?
+            .into_iter()
+            .filter(|t| !matches!(t.visibility, VisibilityLevel::Hidden))
+            .collect();
+        let characters = CharacterOps::get_by_project(&self.pool, &self.project_id).await?;
+
+        let matches: Vec<Value> = best_matches(&threads, name, |t| t.name.as_str())
+            .into_iter()
+            .map(|t| {
+                let involved: Vec<&str> = characters
+                    .iter()
+                    .filter(|c| t.characters_involved.contains(&c.id))
+                    .map(|c| c.name.as_str())
+                    .collect();
+                compact(json!({
+                    "name": t.name,
+                    "status": t.status,
+                    "priority": t.priority,
+                    "description": t.description,
+                    "characters": involved,
+                }))
+            })
+            .collect();
+        Ok(found_or_note(matches, "plot thread", name))
+    }
+
+    async fn log(&self, call: &ToolCall, output: &str) {
+        let record = AIGenerationHistory {
+            id: String::new(),
+            project_id: self.project_id.clone(),
+            document_id: self.document_id.clone(),
+            generation_type: AIGenerationType::ToolCall,
+            provider: self.provider.clone(),
+            model: self.model.clone(),
+            prompt: format!("{}({})", call.name, call.arguments),
+            response: output.to_string(),
+            token_count: super::tokenizer::count_tokens(&self.model, output) as i32,
+            cost_estimate: None,
+            context_used: call.arguments.to_string(),
+            route_taken: None,
+            prompt_template: None,
+            prompt_template_version: None,
+            created_at: chrono::Utc::now(),
+        };
+        if let Err(e) = AIHistoryOps::create(&self.pool, record).await {
+            tracing::warn!("Failed to record {} tool call in AI history: {}", call.name, e);
+        }
+    }
+}
+
+#[async_trait]
+impl ToolExecutor for StoryBibleTools {
+    fn definitions(&self) -> Vec<ToolDefinition> {
+        let by_name = |what: &str| {
+            json!({
+                "type": "object",
+                "properties": { "name": { "type": "string", "description": format!("Name of the {}, or part of it", what) } },
+                "required": ["name"],
+            })
+        };
+        vec![
+            ToolDefinition::new(
+                "lookup_character",
+                "Look up a character's story bible entry: role, appearance, personality, background and goals.",
+                by_name("character"),
+            ),
+            ToolDefinition::new(
+                "lookup_location",
+                "Look up a location's story bible entry: description, geography, climate, culture and history.",
+                by_name("location"),
+            ),
+            ToolDefinition::new(
+                "search_manuscript",
+                "Full-text search of the manuscript. Returns matching documents with an excerpt around each hit.",
+                json!({
+                    "type": "object",
+                    "properties": {
+                        "query": { "type": "string", "description": "Words to search for" },
+                        "limit": { "type": "integer", "minimum": 1, "maximum": MAX_SEARCH_RESULTS }
+                    },
+                    "required": ["query"],
+                }),
+            ),
+            ToolDefinition::new(
+                "get_timeline_events",
+                "List timeline events in story order, optionally only those involving a character and/or location.",
+                json!({
+                    "type": "object",
+                    "properties": {
+                        "character": { "type": "string", "description": "Character name" },
+                        "location": { "type": "string", "description": "Location name" }
+                    },
+                }),
+            ),
+            ToolDefinition::new(
+                "get_plot_thread",
+                "Look up a plot thread: status, priority, description and the characters involved.",
+                by_name("plot thread"),
+            ),
+        ]
+    }
+
+    async fn execute(&self, call: &ToolCall) -> Result<String> {
+        let result = self.run(call).await.map(|value| value.to_string());
+        match &result {
+            Ok(output) => self.log(call, output).await,
+            Err(e) => self.log(call, &format!("Error: {}", e)).await,
+        }
+        result
+    }
+}
+
+fn required_arg<'a>(call: &'a ToolCall, key: &str) -> Result<&'a str> {
+    call.str_arg(key)
+        .ok_or_else(|| StoryWeaverError::invalid_input(format!("{} requires a \"{}\" argument", call.name, key)))
+}
+
+async fn visible_characters(pool: &DbPool, project_id: &str) -> Result<Vec<Character>> {
+    Ok(CharacterOps::get_by_project(pool, project_id)
+        .await?
+        .into_iter()
+        .filter(|c| !matches!(c.visibility, VisibilityLevel::Hidden))
+        .collect())
+}
+
+/// Exact case-insensitive name matches, or else names containing the query
+/// (or contained in it)
+fn best_matches<'a, T>(items: &'a [T], query: &str, name: impl Fn(&T) -> &str) -> Vec<&'a T> {
+    let query = query.trim().to_lowercase();
+    let exact: Vec<&T> = items.iter().filter(|item| name(item).to_lowercase() == query).collect();
+    if !exact.is_empty() {
+        return exact;
+    }
+    items
+        .iter()
+        .filter(|item| {
+            let candidate = name(item).to_lowercase();
+            !candidate.is_empty() && (candidate.contains(&query) || query.contains(&candidate))
+        })
+        .take(MAX_LOOKUP_MATCHES)
+        .collect()
+}
+
+fn found_or_note(matches: Vec<Value>, what: &str, name: &str) -> Value {
+    if matches.is_empty() {
+        json!({ "matches": [], "note": format!("No {} named \"{}\" in the story bible", what, name) })
+    } else {
+        json!({ "matches": matches })
+    }
+}
+
+/// Drop null and empty-string fields so the model isn't sent blanks
+fn compact(mut value: Value) -> Value {
+    if let Value::Object(object) = &mut value {
+        object.retain(|_, field| !field.is_null() && field.as_str() != Some(""));
+    }
+    value
+}
+
+/// The text around the first query word found in `content`
+fn excerpt(content: &str, query: &str) -> String {
+    let lower = content.to_lowercase();
+    let hit = query
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .filter_map(|word| lower.find(&word.to_lowercase()))
+        .min()
+        // Lowercasing can shift byte offsets for some scripts
+        .filter(|&hit| lower.len() == content.len() && content.is_char_boundary(hit))
+        .unwrap_or(0);
+
+    let mut start = hit.saturating_sub(EXCERPT_RADIUS);
+    while !content.is_char_boundary(start) {
+        start -= 1;
+    }
+    let mut end = (hit + EXCERPT_RADIUS).min(content.len());
+    while !content.is_char_boundary(end) {
+        end += 1;
+    }
+
+    let mut text = content[start..end].trim().to_string();
+    if start > 0 {
+        text.insert_str(0, "...");
+    }
+    if end < content.len() {
+        text.push_str("...");
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_best_matches_prefers_exact_names() {
+        let names = vec!["Mara".to_string(), "Mara's Mother".to_string(), "Tam".to_string()];
+        let found = best_matches(&names, "mara", |n| n.as_str());
+        assert_eq!(found, vec![&names[0]]);
+        let found = best_matches(&names, "Mother", |n| n.as_str());
+        assert_eq!(found, vec![&names[1]]);
+    }
+
+    #[test]
+    fn test_excerpt_centres_on_the_hit() {
+        let content = format!("{}The lighthouse went dark.{}", "a ".repeat(300), " b".repeat(300));
+        let text = excerpt(&content, "lighthouse");
+        assert!(text.starts_with("...") && text.ends_with("..."));
+        assert!(text.contains("The lighthouse went dark."));
+        assert_eq!(excerpt("Short chapter.", "missing"), "Short chapter.");
+    }
+}
@@ -0,0 +1,283 @@
+//! Rolling hierarchical summaries of a project's manuscript
+//!
+//! Scene documents are summarized from their text, chapters from their
+//! scenes' summaries (or their own text when they have no scenes), and the
+//! book from its chapters' summaries. Each summary keeps the hash of what it
+//! was made from, so a refresh only calls the AI for documents that changed
+//! and for the levels above them. `summary_context` turns the stored
+//! summaries into the story context writing features send with their prompts.
+
+use super::prompt_templates::{render_prompt, PromptScope};
+use super::{AIContext, AIProvider, AIProviderManager};
+use crate::database::get_pool;
+use crate::database::models::{AIGenerationType, Document, DocumentType};
+use crate::database::operations::{AIHistoryOps, DocumentOps, StorySummary, StorySummaryOps, SummaryLevel};
+use crate::database::DbPool;
+use crate::error::{Result, StoryWeaverError};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+
+/// How long a save waits before its project is summarized again, so a burst
+/// of autosaves costs one refresh
+const REFRESH_DELAY: Duration = Duration::from_secs(120);
+
+/// What a refresh did
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SummaryRefresh {
+    /// Summaries written by the AI
+    pub summarized: usize,
+    /// Summaries kept because what they were made from hadn't changed
+    pub unchanged: usize,
+    /// Summaries of documents no longer in the manuscript
+    pub removed: usize,
+}
+
+fn content_hash(text: &str) -> String {
+    let digest = Sha256::digest(text.as_bytes());
+    digest.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Where a summary sits in the manuscript
+struct Placement<'a> {
+    level: SummaryLevel,
+    source_id: &'a str,
+    parent_id: Option<&'a str>,
+    position: i32,
+    title: &'a str,
+}
+
+/// One pass over a project's summaries
+struct Refresh<'a> {
+    pool: &'a DbPool,
+    provider: &'a dyn AIProvider,
+    project_id: &'a str,
+    existing: HashMap<(SummaryLevel, String), StorySummary>,
+    kept: Vec<String>,
+    stats: SummaryRefresh,
+}
+
+impl Refresh<'_> {
+    /// The summary for `placement`, asking the AI only when `source_text` has
+    /// changed since it was last summarized
+    async fn summarize(
+        &mut self,
+        placement: Placement<'_>,
+        template: &str,
+        values: serde_json::Value,
+        source_text: &str,
+    ) -> Result<String> {
+        let hash = content_hash(source_text);
+        let mut summary = match self.existing.remove(&(placement.level, placement.source_id.to_string())) {
+            Some(existing) if existing.content_hash == hash => {
+                let moved = existing.parent_id.as_deref() != placement.parent_id
+                    || existing.position != placement.position
+                    || existing.title != placement.title;
+                self.kept.push(existing.id.clone());
+                self.stats.unchanged += 1;
+                if !moved {
+                    return Ok(existing.summary);
+                }
+                let mut existing = existing;
+                existing.parent_id = placement.parent_id.map(str::to_string);
+                existing.position = placement.position;
+                existing.title = placement.title.to_string();
+                StorySummaryOps::upsert(self.pool, &existing).await?;
+                return Ok(existing.summary);
+            }
+            Some(existing) => existing,
+            None => StorySummary::new(self.project_id, placement.level, placement.source_id),
+        };
+
+        let prompt = render_prompt(template, &PromptScope::for_project(self.project_id), &values)?;
+        let context = AIContext {
+            project_id: Some(self.project_id.to_string()),
+            ..Default::default()
+        };
+        let text = self.provider.generate_text(&prompt.text, &context).await?.trim().to_string();
+        if text.is_empty() {
+            return Err(StoryWeaverError::internal(format!(
+                "The AI returned an empty {} summary",
+                placement.level.as_str()
+            )));
+        }
+        let document_id = (placement.level != SummaryLevel::Book).then_some(placement.source_id);
+        AIHistoryOps::record_rendered(self.pool, self.project_id, document_id, AIGenerationType::Summary, self.provider, prompt, &text).await;
+
+        summary.parent_id = placement.parent_id.map(str::to_string);
+        summary.position = placement.position;
+        summary.title = placement.title.to_string();
+        summary.summary = text;
+        summary.content_hash = hash;
+        summary.updated_at = Utc::now();
+        StorySummaryOps::upsert(self.pool, &summary).await?;
+
+        self.kept.push(summary.id);
+        self.stats.summarized += 1;
+        Ok(summary.summary)
+    }
+}
+
+/// Bring a project's scene, chapter and book summaries up to date. Chapters
+/// are the project's chapter documents in order; scenes are scene documents
+/// whose parent is a chapter. Summaries are saved as they are written, so a
+/// refresh that fails part way keeps what it finished.
+pub async fn refresh_story_summaries(pool: &DbPool, provider: &dyn AIProvider, project_id: &str) -> Result<SummaryRefresh> {
+    let documents = DocumentOps::get_by_project(pool, project_id).await?;
+    let existing = StorySummaryOps::get_by_project(pool, project_id)
+        .await?
+        .into_iter()
+        .map(|summary| ((summary.level, summary.source_id.clone()), summary))
+        .collect();
+    let mut refresh = Refresh {
+        pool,
+        provider,
+        project_id,
+        existing,
+        kept: Vec::new(),
+        stats: SummaryRefresh::default(),
+    };
+
+    let chapters = documents.iter().filter(|doc| matches!(doc.document_type, DocumentType::Chapter));
+    let mut chapter_summaries: Vec<(&Document, String)> = Vec::new();
+    for chapter in chapters {
+        let scenes = documents.iter().filter(|doc| {
+            matches!(doc.document_type, DocumentType::Scene)
+                && doc.parent_id.as_deref() == Some(chapter.id.as_str())
+                && !doc.content.trim().is_empty()
+        });
+        let mut scene_summaries = Vec::new();
+        for (position, scene) in scenes.enumerate() {
+            let placement = Placement {
+                level: SummaryLevel::Scene,
+                source_id: &scene.id,
+                parent_id: Some(&chapter.id),
+                position: position as i32,
+                title: &scene.title,
+            };
+            let values = serde_json::json!({ "title": scene.title, "text": scene.content });
+            scene_summaries.push(refresh.summarize(placement, "summary_scene", values, &scene.content).await?);
+        }
+        if scene_summaries.is_empty() && chapter.content.trim().is_empty() {
+            continue;
+        }
+
+        let scene_summaries = scene_summaries.join("\n\n");
+        let placement = Placement {
+            level: SummaryLevel::Chapter,
+            source_id: &chapter.id,
+            parent_id: None,
+            position: chapter_summaries.len() as i32,
+            title: &chapter.title,
+        };
+        let values = serde_json::json!({
+            "title": chapter.title,
+            "scene_summaries": scene_summaries,
+            "text": chapter.content,
+        });
+        let source_text = format!("{}\n\n{}", scene_summaries, chapter.content);
+        let summary = refresh.summarize(placement, "summary_chapter", values, &source_text).await?;
+        chapter_summaries.push((chapter, summary));
+    }
+
+    if !chapter_summaries.is_empty() {
+        let source_text = chapter_summaries
+            .iter()
+            .map(|(_, summary)| summary.as_str())
+            .collect::<Vec<_>>()
+            .join("\n\n");
+        let listed = chapter_summaries
+            .iter()
+            .map(|(chapter, summary)| format!("{}: {}", chapter.title, summary))
+            .collect::<Vec<_>>()
+            .join("\n\n");
+        let placement = Placement {
+            level: SummaryLevel::Book,
+            source_id: project_id,
+            parent_id: None,
+            position: 0,
+            title: "",
+        };
+        let values = serde_json::json!({ "chapter_summaries": listed });
+        refresh.summarize(placement, "summary_book", values, &source_text).await?;
+    }
+
+    refresh.stats.removed = StorySummaryOps::delete_stale(pool, project_id, &refresh.kept).await? as usize;
+    Ok(refresh.stats)
+}
+
+/// Story context for writing in `document_id`: the book summary, then the
+/// previous and current chapters' summaries. A scene document counts as its
+/// chapter. `None` until the project has been summarized.
+pub async fn summary_context(pool: &DbPool, project_id: &str, document_id: Option<&str>) -> Result<Option<String>> {
+    let summaries = StorySummaryOps::get_by_project(pool, project_id).await?;
+    if summaries.is_empty() {
+        return Ok(None);
+    }
+
+    let chapter_id = match document_id {
+        Some(id) => DocumentOps::get_by_id(pool, id).await?.map(|doc| match doc.document_type {
+            DocumentType::Scene => doc.parent_id.unwrap_or(doc.id),
+            _ => doc.id,
+        }),
+        None => None,
+    };
+    let chapters: Vec<&StorySummary> = summaries.iter().filter(|s| s.level == SummaryLevel::Chapter).collect();
+    let current = chapter_id.and_then(|id| chapters.iter().position(|chapter| chapter.source_id == id));
+
+    let mut parts = Vec::new();
+    if let Some(book) = summaries.iter().find(|s| s.level == SummaryLevel::Book) {
+        parts.push(format!("Story summary: {}", book.summary));
+    }
+    if let Some(index) = current {
+        if let Some(previous) = index.checked_sub(1).map(|i| chapters[i]) {
+            parts.push(format!("Previous chapter ({}): {}", previous.title, previous.summary));
+        }
+        parts.push(format!("This chapter ({}): {}", chapters[index].title, chapters[index].summary));
+    }
+
+    Ok((!parts.is_empty()).then(|| parts.join("\n\n")))
+}
+
+static SUMMARY_PROVIDERS: OnceLock<Arc<AIProviderManager>> = OnceLock::new();
+
+/// Projects with a refresh waiting to run
+static PENDING_REFRESHES: OnceLock<Mutex<HashSet<String>>> = OnceLock::new();
+
+/// Set up the providers the background summarizer writes with
+pub fn init_story_summarizer(providers: Arc<AIProviderManager>) -> Result<()> {
+    SUMMARY_PROVIDERS
+        .set(providers)
+        .map_err(|_| StoryWeaverError::internal("Story summarizer already initialized"))
+}
+
+/// Refresh a project's summaries now with the app's providers
+pub async fn refresh_project_summaries(pool: &DbPool, project_id: &str) -> Result<SummaryRefresh> {
+    let providers = SUMMARY_PROVIDERS
+        .get()
+        .ok_or_else(|| StoryWeaverError::internal("Story summarizer not initialized"))?;
+    refresh_story_summaries(pool, providers.as_ref(), project_id).await
+}
+
+/// Refresh a project's summaries in the background after `REFRESH_DELAY`.
+/// Saves made while a refresh is waiting share it. A no-op until the
+/// summarizer is initialized.
+pub fn schedule_summary_refresh(project_id: String) {
+    let (Some(providers), Ok(pool)) = (SUMMARY_PROVIDERS.get().cloned(), get_pool()) else {
+        return;
+    };
+    let pending = PENDING_REFRESHES.get_or_init(Default::default);
+    if !pending.lock().unwrap_or_else(|e| e.into_inner()).insert(project_id.clone()) {
+        return;
+    }
+    tokio::spawn(async move {
+        tokio::time::sleep(REFRESH_DELAY).await;
+        pending.lock().unwrap_or_else(|e| e.into_inner()).remove(&project_id);
+        if let Err(e) = refresh_story_summaries(&pool, providers.as_ref(), &project_id).await {
+            tracing::warn!("Failed to refresh story summaries: {}", e);
+        }
+    });
+}
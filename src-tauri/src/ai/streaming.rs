@@ -1,11 +1,330 @@
+//! Incremental streaming primitives shared by the AI providers
+//!
+//! Providers turn their server-sent-event responses into a `TextChunkStream`
+//! of text deltas. Commands relay those deltas through the `StreamingOptimizer`
+//! to the frontend as `ai_stream_chunk` events.
+
+use crate::ai::streaming_optimizer::StreamingOptimizer;
+use crate::ai::TextStream;
+use crate::error::{Result, StoryWeaverError};
+use futures_util::stream::{self, BoxStream, StreamExt};
 use serde::Serialize;
+use std::collections::VecDeque;
+use tauri::{Emitter, Runtime};
 use ts_rs::TS;
 
+/// Event carrying streamed text to the frontend
+pub const STREAM_CHUNK_EVENT: &str = "ai_stream_chunk";
+
 #[derive(Serialize, Clone, Debug, TS)]
 #[ts(export)]
 pub struct StreamingEnvelope {
+    /// Everything generated so far
     pub content: String,
+    /// Text added since the previous envelope
+    pub delta: String,
     pub is_complete: bool,
+    /// True when the writer stopped the stream before the provider finished
+    pub stopped: bool,
     pub token_count: u32,
     pub stream_id: String,
-}
\ No newline at end of file
+}
+
+/// Text deltas as they arrive from a provider
+pub type TextChunkStream = BoxStream<'static, Result<String>>;
+
+/// Pulls the text delta out of one decoded SSE event payload
+pub type DeltaExtractor = fn(&serde_json::Value) -> Option<String>;
+
+/// A stream that yields the whole text at once, for providers or code paths
+/// that cannot stream
+pub fn single_chunk_stream(text: String) -> TextChunkStream {
+    stream::once(async move { Ok(text) }).boxed()
+}
+
+/// Incremental decoder for `text/event-stream` bodies.
+///
+/// Bytes can be split anywhere by the transport, including inside a UTF-8
+/// sequence, so only complete lines are decoded.
+#[derive(Debug, Default)]
+pub struct SseDecoder {
+    buffer: Vec<u8>,
+    data: Vec<String>,
+}
+
+impl SseDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feed raw bytes and return the `data` payload of every event they complete
+    pub fn push(&mut self, bytes: &[u8]) -> Vec<String> {
+        self.buffer.extend_from_slice(bytes);
+
+        let mut events = Vec::new();
+        while let Some(pos) = self.buffer.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=pos).collect();
+            let line = String::from_utf8_lossy(&line);
+            self.process_line(line.trim_end_matches(['\n', '\r']), &mut events);
+        }
+        events
+    }
+
+    /// Flush an event left unterminated when the connection closed
+    pub fn finish(&mut self) -> Option<String> {
+        let mut events = Vec::new();
+        if !self.buffer.is_empty() {
+            let rest = std::mem::take(&mut self.buffer);
+            let line = String::from_utf8_lossy(&rest).to_string();
+            self.process_line(line.trim_end_matches(['\n', '\r']), &mut events);
+        }
+        self.process_line("", &mut events);
+        events.pop()
+    }
+
+    fn process_line(&mut self, line: &str, events: &mut Vec<String>) {
+        if line.is_empty() {
+            if !self.data.is_empty() {
+                events.push(self.data.join("\n"));
+                self.data.clear();
+            }
+        } else if let Some(value) = line.strip_prefix("data:") {
+            self.data.push(value.strip_prefix(' ').unwrap_or(value).to_string());
+        }
+        // `event:`, `id:`, `retry:` and `:` comment lines carry nothing we need
+    }
+}
+
+struct SseState {
+    bytes: BoxStream<'static, reqwest::Result<Vec<u8>>>,
+    decoder: SseDecoder,
+    pending: VecDeque<Result<String>>,
+    provider: String,
+    extract: DeltaExtractor,
+    done: bool,
+}
+
+impl SseState {
+    fn queue_event(&mut self, payload: String) {
+        if payload == "[DONE]" {
+            self.done = true;
+            return;
+        }
+
+        match serde_json::from_str::<serde_json::Value>(&payload) {
+            Ok(json) => {
+                if let Some(error) = json.get("error") {
+                    let message = error
+                        .get("message")
+                        .and_then(|m| m.as_str())
+                        .map(|m| m.to_string())
+                        .unwrap_or_else(|| error.to_string());
+                    self.pending.push_back(Err(StoryWeaverError::ai_provider(self.provider.clone(), message)));
+                    self.done = true;
+                } else if let Some(delta) = (self.extract)(&json).filter(|d| !d.is_empty()) {
+                    self.pending.push_back(Ok(delta));
+                }
+            }
+            Err(e) => {
+                tracing::warn!("Skipping malformed {} stream event: {}", self.provider, e);
+            }
+        }
+    }
+}
+
+/// Turn a successful SSE response into a stream of text deltas.
+///
+/// Dropping the returned stream closes the connection, which is how a
+/// stopped generation stops costing tokens.
+pub fn sse_text_stream(response: reqwest::Response, provider: &str, extract: DeltaExtractor) -> TextChunkStream {
+    let state = SseState {
+        bytes: response.bytes_stream().map(|chunk| chunk.map(|b| b.to_vec())).boxed(),
+        decoder: SseDecoder::new(),
+        pending: VecDeque::new(),
+        provider: provider.to_string(),
+        extract,
+        done: false,
+    };
+
+    stream::unfold(state, |mut state| async move {
+        loop {
+            if let Some(item) = state.pending.pop_front() {
+                return Some((item, state));
+            }
+            if state.done {
+                return None;
+            }
+
+            match state.bytes.next().await {
+                Some(Ok(bytes)) => {
+                    for payload in state.decoder.push(&bytes) {
+                        if state.done {
+                            break;
+                        }
+                        state.queue_event(payload);
+                    }
+                }
+                Some(Err(e)) => {
+                    state.done = true;
+                    state.pending.push_back(Err(StoryWeaverError::network(format!(
+                        "Error reading {} stream: {}",
+                        state.provider, e
+                    ))));
+                }
+                None => {
+                    state.done = true;
+                    if let Some(payload) = state.decoder.finish() {
+                        state.queue_event(payload);
+                    }
+                }
+            }
+        }
+    })
+    .boxed()
+}
+
+/// Relay provider deltas through the streaming optimizer to the frontend.
+///
+/// `stream_id` must already be registered with `StreamingOptimizer::create_stream`,
+/// so a stop request can arrive before the first chunk does. Each delta is
+/// buffered in the optimizer under `stream_id` and emitted as a
+/// `StreamingEnvelope` as soon as it is consumed. A stop request cancels the
+/// stream's token, which interrupts the wait for the next chunk, drops the
+/// provider stream and emits a final envelope marked `stopped`. Token counts
+/// use `model`'s tokenizer. Returns the text generated so far.
+pub async fn relay_to_frontend<R: Runtime, E: Emitter<R>>(
+    emitter: &E,
+    optimizer: &StreamingOptimizer,
+    stream_id: &str,
+    model: &str,
+    chunks: TextChunkStream,
+) -> Result<TextStream> {
+    relay(optimizer, stream_id, model, chunks, |envelope| {
+        emitter
+            .emit(STREAM_CHUNK_EVENT, &envelope)
+            .map_err(|e| StoryWeaverError::system(format!("Failed to emit stream chunk: {}", e)))
+    })
+    .await
+}
+
+/// The loop behind `relay_to_frontend`, handing each envelope to `emit`
+async fn relay(
+    optimizer: &StreamingOptimizer,
+    stream_id: &str,
+    model: &str,
+    mut chunks: TextChunkStream,
+    mut emit: impl FnMut(StreamingEnvelope) -> Result<()>,
+) -> Result<TextStream> {
+    let cancellation = optimizer.cancellation_token(stream_id).await?;
+    let mut text = TextStream::for_model(model);
+    let mut stopped = false;
+
+    loop {
+        // Waiting on the provider is raced against a stop, so a stalled
+        // stream can't hold the writer's Stop until its next chunk
+        let next = tokio::select! {
+            biased;
+            _ = cancellation.cancelled() => {
+                stopped = true;
+                break;
+            }
+            next = chunks.next() => next,
+        };
+
+        match next {
+            Some(Ok(delta)) => {
+                optimizer.push_to_stream(stream_id, delta).await?;
+                while let Some(delta) = optimizer.consume_from_stream(stream_id).await? {
+                    text.append(&delta);
+                    emit(envelope(stream_id, &text, delta, false))?;
+                }
+            }
+            Some(Err(e)) => {
+                optimizer.complete_stream(stream_id).await?;
+                return Err(e);
+            }
+            None => break,
+        }
+    }
+    drop(chunks);
+
+    text.complete();
+    optimizer.complete_stream(stream_id).await?;
+    emit(envelope(stream_id, &text, String::new(), stopped))?;
+
+    Ok(text)
+}
+
+fn envelope(stream_id: &str, text: &TextStream, delta: String, stopped: bool) -> StreamingEnvelope {
+    StreamingEnvelope {
+        content: text.content.clone(),
+        delta,
+        is_complete: text.is_complete,
+        stopped,
+        token_count: text.token_count as u32,
+        stream_id: stream_id.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decoder_joins_events_split_across_chunks() {
+        let mut decoder = SseDecoder::new();
+
+        assert!(decoder.push(b"data: {\"a\":").is_empty());
+        let events = decoder.push(b"1}\n\ndata: [DONE]\n\n");
+
+        assert_eq!(events, vec!["{\"a\":1}".to_string(), "[DONE]".to_string()]);
+    }
+
+    #[test]
+    fn test_decoder_handles_split_utf8_and_event_lines() {
+        let mut decoder = SseDecoder::new();
+        let frame = "event: content_block_delta\r\ndata: caf\u{e9}\r\n\r\n".as_bytes();
+        let split = frame.len() - 5; // inside the two-byte 'é'
+
+        assert!(decoder.push(&frame[..split]).is_empty());
+        assert_eq!(decoder.push(&frame[split..]), vec!["caf\u{e9}".to_string()]);
+    }
+
+    #[test]
+    fn test_decoder_flushes_unterminated_event() {
+        let mut decoder = SseDecoder::new();
+        assert!(decoder.push(b"data: tail").is_empty());
+        assert_eq!(decoder.finish(), Some("tail".to_string()));
+        assert_eq!(decoder.finish(), None);
+    }
+
+    #[tokio::test]
+    async fn test_stop_interrupts_a_stalled_stream() {
+        use crate::ai::streaming_optimizer::StreamingConfig;
+        use std::sync::Arc;
+        use std::time::Duration;
+
+        let optimizer = Arc::new(StreamingOptimizer::new(StreamingConfig::default()));
+        optimizer.create_stream("stalled".to_string()).await.unwrap();
+        // One chunk, then a provider that never sends another
+        let chunks = stream::once(async { Ok("Once upon".to_string()) }).chain(stream::pending()).boxed();
+
+        let stopper = optimizer.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            stopper.request_stop("stalled").await.unwrap();
+        });
+
+        let mut envelopes = Vec::new();
+        let relayed = relay(&optimizer, "stalled", "", chunks, |envelope| {
+            envelopes.push(envelope);
+            Ok(())
+        });
+        let text = tokio::time::timeout(Duration::from_secs(5), relayed).await.expect("stop was not noticed").unwrap();
+
+        assert_eq!(text.content, "Once upon");
+        let last = envelopes.last().unwrap();
+        assert!(last.stopped && last.is_complete);
+        assert_eq!(last.content, "Once upon");
+    }
+}
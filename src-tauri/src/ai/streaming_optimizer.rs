@@ -1,6 +1,7 @@
 //! Streaming memory optimization for AI operations in StoryWeaver
 //! Implements memory-efficient streaming with backpressure and resource management
 
+use crate::ai::CancellationToken;
 use crate::error::{Result, StoryWeaverError};
 use serde::{Deserialize, Serialize};
 use std::collections::VecDeque;
@@ -43,6 +44,9 @@ pub struct StreamBuffer {
     pub created_at: Instant,
     pub last_activity: Instant,
     pub is_complete: bool,
+    pub stop_requested: bool,
+    /// Cancelled by `request_stop`; aborts the provider request feeding the stream
+    pub cancellation: CancellationToken,
     pub consumer_position: usize,
 }
 
@@ -56,6 +60,8 @@ impl StreamBuffer {
             created_at: now,
             last_activity: now,
             is_complete: false,
+            stop_requested: false,
+            cancellation: CancellationToken::new(),
             consumer_position: 0,
         }
     }
@@ -234,6 +240,34 @@ impl StreamingOptimizer {
         }
     }
 
+    /// Ask the producer of a stream to stop early (e.g. the writer pressed Stop)
+    pub async fn request_stop(&self, stream_id: &str) -> Result<()> {
+        let mut streams = self.streams.write().await;
+
+        if let Some(stream) = streams.get_mut(stream_id) {
+            stream.stop_requested = true;
+            stream.cancellation.cancel();
+            Ok(())
+        } else {
+            Err(StoryWeaverError::not_found("Stream", stream_id))
+        }
+    }
+
+    /// Token cancelled when a stop is requested for the stream
+    pub async fn cancellation_token(&self, stream_id: &str) -> Result<CancellationToken> {
+        let streams = self.streams.read().await;
+        streams
+            .get(stream_id)
+            .map(|s| s.cancellation.clone())
+            .ok_or_else(|| StoryWeaverError::not_found("Stream", stream_id))
+    }
+
+    /// Whether a stop has been requested for a stream
+    pub async fn is_stop_requested(&self, stream_id: &str) -> bool {
+        let streams = self.streams.read().await;
+        streams.get(stream_id).map(|s| s.stop_requested).unwrap_or(false)
+    }
+
     /// Check if a stream is complete and empty
     pub async fn is_stream_finished(&self, stream_id: &str) -> Result<bool> {
         let streams = self.streams.read().await;
@@ -461,6 +495,20 @@ mod tests {
         assert!(optimizer.is_stream_finished(&stream_id).await.unwrap());
     }
 
+    #[tokio::test]
+    async fn test_stop_request() {
+        let optimizer = StreamingOptimizer::new(StreamingConfig::default());
+
+        let stream_id = "test_stream".to_string();
+        optimizer.create_stream(stream_id.clone()).await.unwrap();
+        assert!(!optimizer.is_stop_requested(&stream_id).await);
+
+        optimizer.request_stop(&stream_id).await.unwrap();
+        assert!(optimizer.is_stop_requested(&stream_id).await);
+
+        assert!(optimizer.request_stop("missing").await.is_err());
+    }
+
     #[tokio::test]
     async fn test_memory_tracking() {
         let config = StreamingConfig::default();
@@ -0,0 +1,319 @@
+//! Structured JSON generation
+//!
+//! Callers describe the output they want with a JSON Schema and get back a
+//! validated, deserialized value from `StructuredGeneration::generate_structured`.
+//! Providers with a native structured output mode (OpenAI `response_format`,
+//! Gemini `responseSchema`) override `AIProvider::generate_json`; the rest are
+//! asked for JSON in the prompt. Either way the reply is repaired where that is
+//! safe (code fences, surrounding prose, trailing commas), checked against the
+//! schema, and the model is re-prompted with the validation errors when it
+//! doesn't conform.
+//!
+//! The validator covers the subset of JSON Schema the app's schemas use:
+//! `type`, `enum`, `properties`, `required`, `additionalProperties`, `items`,
+//! `minItems`/`maxItems`, `minLength`/`maxLength` and `minimum`/`maximum`.
+
+use super::{AIContext, AIProvider};
+use crate::error::{Result, StoryWeaverError};
+use async_trait::async_trait;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Requests made for one structured value before giving up
+pub const MAX_STRUCTURED_ATTEMPTS: usize = 3;
+
+/// Longest previous reply quoted back to the model when re-prompting
+const MAX_QUOTED_REPLY_CHARS: usize = 2000;
+
+static TRAILING_COMMA: Lazy<Regex> = Lazy::new(|| Regex::new(r",(\s*[}\]])").unwrap());
+
+/// A named JSON Schema describing a structured response
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JsonSchema {
+    /// Identifier sent to providers that name their schemas, e.g. `character_traits`
+    pub name: String,
+    pub schema: Value,
+}
+
+impl JsonSchema {
+    pub fn new(name: &str, schema: Value) -> Self {
+        Self { name: name.to_string(), schema }
+    }
+
+    /// Check `value` against the schema, listing every violation with its path
+    pub fn validate(&self, value: &Value) -> std::result::Result<(), Vec<String>> {
+        let mut errors = Vec::new();
+        validate_at(&self.schema, value, "$", &mut errors);
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+fn type_matches(expected: &str, value: &Value) -> bool {
+    match expected {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "boolean" => value.is_boolean(),
+        "null" => value.is_null(),
+        "number" => value.is_number(),
+        "integer" => value.is_i64() || value.is_u64() || value.as_f64().is_some_and(|n| n.fract() == 0.0),
+        _ => true,
+    }
+}
+
+fn validate_at(schema: &Value, value: &Value, path: &str, errors: &mut Vec<String>) {
+    let Some(schema) = schema.as_object() else {
+        return;
+    };
+
+    if let Some(expected) = schema.get("type") {
+        let allowed: Vec<&str> = match expected {
+            Value::String(name) => vec![name.as_str()],
+            Value::Array(names) => names.iter().filter_map(Value::as_str).collect(),
+            _ => Vec::new(),
+        };
+        if !allowed.is_empty() && !allowed.iter().any(|name| type_matches(name, value)) {
+            errors.push(format!("{}: expected {}, got {}", path, allowed.join(" or "), json_type(value)));
+            return;
+        }
+    }
+
+    if let Some(options) = schema.get("enum").and_then(Value::as_array) {
+        if !options.contains(value) {
+            errors.push(format!("{}: must be one of {}", path, Value::Array(options.clone())));
+        }
+    }
+
+    match value {
+        Value::Object(object) => {
+            if let Some(required) = schema.get("required").and_then(Value::as_array) {
+                for key in required.iter().filter_map(Value::as_str) {
+                    if !object.contains_key(key) {
+                        errors.push(format!("{}: missing required property \"{}\"", path, key));
+                    }
+                }
+            }
+            let properties = schema.get("properties").and_then(Value::as_object);
+            for (key, item) in object {
+                let item_path = format!("{}.{}", path, key);
+                match (properties.and_then(|p| p.get(key)), schema.get("additionalProperties")) {
+                    (Some(property), _) => validate_at(property, item, &item_path, errors),
+                    (None, Some(Value::Bool(false))) => {
+                        errors.push(format!("{}: unexpected property", item_path));
+                    }
+                    (None, Some(additional)) => validate_at(additional, item, &item_path, errors),
+                    (None, None) => {}
+                }
+            }
+        }
+        Value::Array(items) => {
+            if let Some(min) = schema.get("minItems").and_then(Value::as_u64) {
+                if (items.len() as u64) < min {
+                    errors.push(format!("{}: expected at least {} item(s), got {}", path, min, items.len()));
+                }
+            }
+            if let Some(max) = schema.get("maxItems").and_then(Value::as_u64) {
+                if items.len() as u64 > max {
+                    errors.push(format!("{}: expected at most {} item(s), got {}", path, max, items.len()));
+                }
+            }
+            if let Some(item_schema) = schema.get("items") {
+                for (index, item) in items.iter().enumerate() {
+                    validate_at(item_schema, item, &format!("{}[{}]", path, index), errors);
+                }
+            }
+        }
+        Value::String(text) => {
+            let length = text.chars().count() as u64;
+            if let Some(min) = schema.get("minLength").and_then(Value::as_u64) {
+                if length < min {
+                    errors.push(format!("{}: expected at least {} character(s)", path, min));
+                }
+            }
+            if let Some(max) = schema.get("maxLength").and_then(Value::as_u64) {
+                if length > max {
+                    errors.push(format!("{}: expected at most {} character(s)", path, max));
+                }
+            }
+        }
+        Value::Number(number) => {
+            let number = number.as_f64().unwrap_or_default();
+            if let Some(min) = schema.get("minimum").and_then(Value::as_f64) {
+                if number < min {
+                    errors.push(format!("{}: must be at least {}", path, min));
+                }
+            }
+            if let Some(max) = schema.get("maximum").and_then(Value::as_f64) {
+                if number > max {
+                    errors.push(format!("{}: must be at most {}", path, max));
+                }
+            }
+        }
+        Value::Bool(_) | Value::Null => {}
+    }
+}
+
+fn json_type(value: &Value) -> &'static str {
+    match value {
+        Value::Object(_) => "object",
+        Value::Array(_) => "array",
+        Value::String(_) => "string",
+        Value::Number(_) => "number",
+        Value::Bool(_) => "boolean",
+        Value::Null => "null",
+    }
+}
+
+/// Pull a JSON value out of a model reply: the reply itself, the body of a
+/// code fence, or the outermost `{...}`/`[...]` span, retried without
+/// trailing commas
+pub fn extract_json(reply: &str) -> Option<Value> {
+    let trimmed = reply.trim();
+    let mut candidates = vec![trimmed.to_string()];
+
+    if let Some(start) = trimmed.find("```") {
+        let body = &trimmed[start + 3..];
+        // Skip the fence's language tag
+        let body = body.split_once('\n').map_or(body, |(_, rest)| rest);
+        if let Some(end) = body.find("```") {
+            candidates.push(body[..end].trim().to_string());
+        }
+    }
+
+    let start = trimmed.find(['{', '[']);
+    let end = trimmed.rfind(['}', ']']);
+    if let (Some(start), Some(end)) = (start, end) {
+        if start < end {
+            candidates.push(trimmed[start..=end].to_string());
+        }
+    }
+
+    candidates.iter().find_map(|candidate| {
+        serde_json::from_str(candidate)
+            .ok()
+            .or_else(|| serde_json::from_str(&TRAILING_COMMA.replace_all(candidate, "$1")).ok())
+    })
+}
+
+/// Prompt asking for JSON matching `schema`, for providers without a native mode
+pub fn json_prompt(prompt: &str, schema: &JsonSchema) -> String {
+    format!(
+        "{}\n\nRespond with a single JSON value that matches this JSON Schema. \
+         Output only the JSON, with no commentary and no code fences.\n{}",
+        prompt,
+        serde_json::to_string_pretty(&schema.schema).unwrap_or_default()
+    )
+}
+
+/// The original prompt followed by the problems with the previous reply
+fn retry_prompt(prompt: &str, reply: &str, problems: &[String]) -> String {
+    let quoted: String = reply.chars().take(MAX_QUOTED_REPLY_CHARS).collect();
+    format!(
+        "{}\n\nYour previous reply did not match the required JSON Schema:\n- {}\n\nPrevious reply:\n{}\n\n\
+         Reply again with corrected JSON only.",
+        prompt,
+        problems.join("\n- "),
+        quoted
+    )
+}
+
+/// Parse, validate and deserialize a reply, or list what is wrong with it
+pub fn parse_structured<T: DeserializeOwned>(reply: &str, schema: &JsonSchema) -> std::result::Result<T, Vec<String>> {
+    let value = extract_json(reply).ok_or_else(|| vec!["the reply is not valid JSON".to_string()])?;
+    schema.validate(&value)?;
+    serde_json::from_value(value).map_err(|e| vec![format!("$: {}", e)])
+}
+
+/// Typed structured generation for every `AIProvider`
+#[async_trait]
+pub trait StructuredGeneration: AIProvider {
+    /// Generate a value of type `T` described by `schema`, re-prompting with
+    /// the validation errors up to `MAX_STRUCTURED_ATTEMPTS` times
+    async fn generate_structured<T>(&self, prompt: &str, schema: &JsonSchema, context: &AIContext) -> Result<T>
+    where
+        T: DeserializeOwned + Send,
+    {
+        let mut request = prompt.to_string();
+        let mut problems = Vec::new();
+
+        for attempt in 1..=MAX_STRUCTURED_ATTEMPTS {
+            let reply = self.generate_json(&request, schema, context).await?;
+            match parse_structured(&reply, schema) {
+                Ok(value) => return Ok(value),
+                Err(errors) => {
+                    tracing::warn!(
+                        "Structured reply {}/{} from {} did not match {}: {}",
+                        attempt,
+                        MAX_STRUCTURED_ATTEMPTS,
+                        self.get_provider_name(),
+                        schema.name,
+                        errors.join("; ")
+                    );
+                    request = retry_prompt(prompt, &reply, &errors);
+                    problems = errors;
+                }
+            }
+        }
+
+        Err(StoryWeaverError::AIGenerationError {
+            message: format!(
+                "{} did not return valid {} after {} attempts: {}",
+                self.get_provider_name(),
+                schema.name,
+                MAX_STRUCTURED_ATTEMPTS,
+                problems.join("; ")
+            ),
+        })
+    }
+}
+
+impl<P: AIProvider + ?Sized> StructuredGeneration for P {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn traits_schema() -> JsonSchema {
+        JsonSchema::new(
+            "traits",
+            json!({
+                "type": "object",
+                "properties": {
+                    "traits": { "type": "array", "items": { "type": "string", "minLength": 1 }, "minItems": 1 }
+                },
+                "required": ["traits"],
+                "additionalProperties": false
+            }),
+        )
+    }
+
+    #[test]
+    fn test_validation_reports_paths() {
+        let schema = traits_schema();
+        assert!(schema.validate(&json!({ "traits": ["brave"] })).is_ok());
+
+        let errors = schema.validate(&json!({ "traits": ["brave", 3], "mood": "dark" })).unwrap_err();
+        assert!(errors.contains(&"$.traits[1]: expected string, got number".to_string()));
+        assert!(errors.contains(&"$.mood: unexpected property".to_string()));
+
+        let errors = schema.validate(&json!({})).unwrap_err();
+        assert_eq!(errors, vec!["$: missing required property \"traits\"".to_string()]);
+    }
+
+    #[test]
+    fn test_extract_json_repairs_common_reply_shapes() {
+        let expected = json!({ "traits": ["wry", "loyal"] });
+        assert_eq!(extract_json("{\"traits\": [\"wry\", \"loyal\"]}"), Some(expected.clone()));
+        assert_eq!(extract_json("```json\n{\"traits\": [\"wry\", \"loyal\"]}\n```"), Some(expected.clone()));
+        assert_eq!(extract_json("Sure! Here you go: {\"traits\": [\"wry\", \"loyal\",],} Enjoy."), Some(expected));
+        assert_eq!(extract_json("no json here"), None);
+    }
+}
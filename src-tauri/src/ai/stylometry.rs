@@ -0,0 +1,486 @@
+//! Deterministic local stylometry
+//!
+//! Measures the habits that make prose sound like its author: how long the
+//! sentences run, how much is dialogue, how dense the vocabulary is, which
+//! function words recur, whose point of view the pronouns take and how the
+//! punctuation falls. A project's fingerprint is the profile of its style
+//! examples taken together; any passage can be scored for drift from it
+//! without calling a provider.
+
+use crate::database::operations::StyleExampleOps;
+use crate::database::DbPool;
+use crate::error::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Words whose rates mark an author regardless of subject
+pub const FUNCTION_WORDS: &[&str] = &[
+    "the", "a", "an", "and", "but", "or", "nor", "so", "yet", "of", "to", "in", "on", "at", "by", "for", "with",
+    "from", "into", "over", "under", "as", "that", "which", "who", "this", "these", "those", "it", "its", "is",
+    "was", "were", "be", "been", "had", "have", "has", "not", "no", "then", "than", "if", "when", "while",
+    "there", "here", "what", "all", "some", "just", "very", "only", "still", "even", "up", "out", "down", "back",
+];
+
+const FIRST_PERSON: &[&str] = &["i", "me", "my", "mine", "myself", "we", "us", "our", "ours", "ourselves"];
+const SECOND_PERSON: &[&str] = &["you", "your", "yours", "yourself", "yourselves"];
+const THIRD_PERSON: &[&str] = &[
+    "he", "him", "his", "himself", "she", "her", "hers", "herself", "they", "them", "their", "theirs", "themselves",
+];
+
+/// Upper bounds, in words, of the sentence length ranges; the last range is open
+pub const SENTENCE_LENGTH_BUCKETS: &[usize] = &[5, 10, 15, 20, 30];
+
+/// Marks counted per 1000 words, by name
+const PUNCTUATION_MARKS: &[&str] = &["comma", "semicolon", "colon", "dash", "ellipsis", "exclamation", "question", "parenthesis"];
+
+/// Abbreviations whose period doesn't end a sentence
+const ABBREVIATIONS: &[&str] = &["mr", "mrs", "ms", "dr", "st", "jr", "sr", "prof", "capt", "lt", "sgt"];
+
+/// Passages shorter than this are scored but never flagged
+pub const MIN_RELIABLE_WORDS: usize = 100;
+
+/// Drift at or above which a reliable passage is flagged
+pub const DRIFT_THRESHOLD: f32 = 0.35;
+
+/// Share of pronoun uses in each person
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PronounProfile {
+    pub first_person: f32,
+    pub second_person: f32,
+    pub third_person: f32,
+}
+
+impl PronounProfile {
+    fn shares(&self) -> [f32; 3] {
+        [self.first_person, self.second_person, self.third_person]
+    }
+
+    /// "first person", "second person" or "third person", whichever leads
+    fn dominant(&self) -> Option<&'static str> {
+        let names = ["first person", "second person", "third person"];
+        let shares = self.shares();
+        let (index, share) = shares
+            .iter()
+            .enumerate()
+            .max_by(|a, b| a.1.partial_cmp(b.1).unwrap_or(std::cmp::Ordering::Equal))?;
+        (*share > 0.0).then(|| names[index])
+    }
+}
+
+/// What a passage measures
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StyleProfile {
+    pub word_count: usize,
+    pub sentence_count: usize,
+    pub sentence_length_mean: f32,
+    pub sentence_length_stddev: f32,
+    /// Share of sentences in each `SENTENCE_LENGTH_BUCKETS` range
+    pub sentence_length_distribution: Vec<f32>,
+    /// Share of words inside quotation marks
+    pub dialogue_ratio: f32,
+    /// Share of words that aren't function words or pronouns
+    pub lexical_density: f32,
+    /// Uses per 1000 words of each of `FUNCTION_WORDS`
+    pub function_words: BTreeMap<String, f32>,
+    pub pov_pronouns: PronounProfile,
+    /// Uses per 1000 words of each mark
+    pub punctuation: BTreeMap<String, f32>,
+}
+
+/// A project's style: the profile of its examples together
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StyleFingerprint {
+    pub project_id: String,
+    pub example_count: usize,
+    pub profile: StyleProfile,
+}
+
+/// A measure drift is scored on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StyleDimension {
+    SentenceLength,
+    Dialogue,
+    LexicalDensity,
+    FunctionWords,
+    Pronouns,
+    Punctuation,
+}
+
+impl StyleDimension {
+    /// Share of the overall drift score
+    fn weight(&self) -> f32 {
+        match self {
+            StyleDimension::SentenceLength => 0.2,
+            StyleDimension::Dialogue => 0.1,
+            StyleDimension::LexicalDensity => 0.15,
+            StyleDimension::FunctionWords => 0.25,
+            StyleDimension::Pronouns => 0.15,
+            StyleDimension::Punctuation => 0.15,
+        }
+    }
+}
+
+/// How far one measure sits from the fingerprint, from 0 (same) to 1
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DimensionDrift {
+    pub dimension: StyleDimension,
+    pub score: f32,
+    /// What differs, for the writer
+    pub note: String,
+}
+
+/// How far a passage sits from a fingerprint
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StyleDrift {
+    /// Weighted drift over all dimensions, from 0 (same style) to 1
+    pub score: f32,
+    /// Reliable and at or above `DRIFT_THRESHOLD`
+    pub flagged: bool,
+    /// Long enough, at `MIN_RELIABLE_WORDS`, for the score to mean much
+    pub reliable: bool,
+    pub word_count: usize,
+    /// Most drifted first
+    pub dimensions: Vec<DimensionDrift>,
+    pub profile: StyleProfile,
+}
+
+/// Lowercased words, each with whether it sits inside quotation marks
+fn words(text: &str) -> Vec<(String, bool)> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_quote = false;
+    let mut word_in_quote = false;
+    let flush = |word: &mut String, in_quote: bool, words: &mut Vec<(String, bool)>| {
+        let trimmed = word.trim_end_matches('\'');
+        if !trimmed.is_empty() {
+            words.push((trimmed.to_string(), in_quote));
+        }
+        word.clear();
+    };
+
+    for c in text.chars() {
+        let apostrophe = c == '\'' || c == '’';
+        if c.is_alphanumeric() || (apostrophe && !word.is_empty()) {
+            if word.is_empty() {
+                word_in_quote = in_quote;
+            }
+            if apostrophe {
+                word.push('\'');
+            } else {
+                word.extend(c.to_lowercase());
+            }
+            continue;
+        }
+        flush(&mut word, word_in_quote, &mut words);
+        match c {
+            '"' => in_quote = !in_quote,
+            '“' => in_quote = true,
+            '”' => in_quote = false,
+            _ => {}
+        }
+    }
+    flush(&mut word, word_in_quote, &mut words);
+    words
+}
+
+/// Whether a sentence candidate ends on an abbreviation like "Dr."
+fn ends_with_abbreviation(sentence: &str) -> bool {
+    let last = sentence.split_whitespace().last().unwrap_or_default();
+    let last: String = last.chars().filter(|c| c.is_alphabetic()).collect::<String>().to_lowercase();
+    ABBREVIATIONS.contains(&last.as_str())
+}
+
+/// Word count of each sentence. Sentences end at terminal punctuation
+/// followed by a space, and at blank lines.
+fn sentence_lengths(text: &str) -> Vec<usize> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut lengths = Vec::new();
+    let mut sentence = String::new();
+    let end_sentence = |sentence: &mut String, lengths: &mut Vec<usize>| {
+        let count = words(sentence).len();
+        if count > 0 {
+            lengths.push(count);
+        }
+        sentence.clear();
+    };
+
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        sentence.push(c);
+        i += 1;
+        if matches!(c, '.' | '!' | '?' | '…') {
+            // Runs like "?!" and closing quotes belong to the sentence
+            while i < chars.len() && matches!(chars[i], '.' | '!' | '?' | '…' | '"' | '”' | '’' | '\'' | ')') {
+                sentence.push(chars[i]);
+                i += 1;
+            }
+            let at_break = i >= chars.len() || chars[i].is_whitespace();
+            if at_break && !(c == '.' && ends_with_abbreviation(&sentence)) {
+                end_sentence(&mut sentence, &mut lengths);
+            }
+        } else if c == '\n' && chars.get(i) == Some(&'\n') {
+            end_sentence(&mut sentence, &mut lengths);
+        }
+    }
+    end_sentence(&mut sentence, &mut lengths);
+    lengths
+}
+
+fn punctuation_counts(text: &str) -> BTreeMap<&'static str, usize> {
+    let mut counts: BTreeMap<&'static str, usize> = PUNCTUATION_MARKS.iter().map(|mark| (*mark, 0)).collect();
+    for c in text.chars() {
+        let mark = match c {
+            ',' => "comma",
+            ';' => "semicolon",
+            ':' => "colon",
+            '—' | '–' => "dash",
+            '…' => "ellipsis",
+            '!' => "exclamation",
+            '?' => "question",
+            '(' => "parenthesis",
+            _ => continue,
+        };
+        *counts.entry(mark).or_default() += 1;
+    }
+    *counts.entry("dash").or_default() += text.matches("--").count();
+    *counts.entry("ellipsis").or_default() += text.matches("...").count();
+    counts
+}
+
+impl StyleProfile {
+    /// Measure a passage
+    pub fn measure(text: &str) -> Self {
+        let words = words(text);
+        let word_count = words.len();
+        let per_thousand = |count: usize| if word_count == 0 { 0.0 } else { count as f32 * 1000.0 / word_count as f32 };
+        let share = |count: usize, total: usize| if total == 0 { 0.0 } else { count as f32 / total as f32 };
+
+        let lengths = sentence_lengths(text);
+        let sentence_count = lengths.len();
+        let sentence_length_mean = share(lengths.iter().sum(), sentence_count);
+        let sentence_length_stddev = if sentence_count == 0 {
+            0.0
+        } else {
+            let variance = lengths
+                .iter()
+                .map(|&len| (len as f32 - sentence_length_mean).powi(2))
+                .sum::<f32>()
+                / sentence_count as f32;
+            variance.sqrt()
+        };
+        let mut buckets = vec![0usize; SENTENCE_LENGTH_BUCKETS.len() + 1];
+        for &len in &lengths {
+            let bucket = SENTENCE_LENGTH_BUCKETS
+                .iter()
+                .position(|&upper| len <= upper)
+                .unwrap_or(SENTENCE_LENGTH_BUCKETS.len());
+            buckets[bucket] += 1;
+        }
+        let sentence_length_distribution = buckets.into_iter().map(|count| share(count, sentence_count)).collect();
+
+        let dialogue_words = words.iter().filter(|(_, in_quote)| *in_quote).count();
+        let is_function_word = |word: &str| {
+            FUNCTION_WORDS.contains(&word)
+                || FIRST_PERSON.contains(&word)
+                || SECOND_PERSON.contains(&word)
+                || THIRD_PERSON.contains(&word)
+        };
+        let content_words = words.iter().filter(|(word, _)| !is_function_word(word)).count();
+
+        let function_words = FUNCTION_WORDS
+            .iter()
+            .map(|function_word| {
+                let uses = words.iter().filter(|(word, _)| word == function_word).count();
+                (function_word.to_string(), per_thousand(uses))
+            })
+            .collect();
+
+        let count_in = |list: &[&str]| words.iter().filter(|(word, _)| list.contains(&word.as_str())).count();
+        let (first, second, third) = (count_in(FIRST_PERSON), count_in(SECOND_PERSON), count_in(THIRD_PERSON));
+        let pronouns = first + second + third;
+        let pov_pronouns = PronounProfile {
+            first_person: share(first, pronouns),
+            second_person: share(second, pronouns),
+            third_person: share(third, pronouns),
+        };
+
+        let punctuation = punctuation_counts(text)
+            .into_iter()
+            .map(|(mark, count)| (mark.to_string(), per_thousand(count)))
+            .collect();
+
+        Self {
+            word_count,
+            sentence_count,
+            sentence_length_mean,
+            sentence_length_stddev,
+            sentence_length_distribution,
+            dialogue_ratio: share(dialogue_words, word_count),
+            lexical_density: share(content_words, word_count),
+            function_words,
+            pov_pronouns,
+            punctuation,
+        }
+    }
+}
+
+/// Half the summed absolute differences of two distributions, from 0 to 1
+fn total_variation(a: &[f32], b: &[f32]) -> f32 {
+    (a.iter().zip(b).map(|(x, y)| (x - y).abs()).sum::<f32>() / 2.0).min(1.0)
+}
+
+fn cosine(a: &[f32], b: &[f32]) -> Option<f32> {
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm = |v: &[f32]| v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let (na, nb) = (norm(a), norm(b));
+    (na > 0.0 && nb > 0.0).then(|| dot / (na * nb))
+}
+
+fn percent(share: f32) -> String {
+    format!("{:.0}%", share * 100.0)
+}
+
+impl StyleFingerprint {
+    /// The fingerprint of a set of example passages
+    pub fn from_examples(project_id: &str, examples: &[&str]) -> Self {
+        Self {
+            project_id: project_id.to_string(),
+            example_count: examples.len(),
+            profile: StyleProfile::measure(&examples.join("\n\n")),
+        }
+    }
+
+    /// How far `text` sits from this fingerprint
+    pub fn score(&self, text: &str) -> StyleDrift {
+        let profile = StyleProfile::measure(text);
+        let expected = &self.profile;
+        let mut dimensions = vec![
+            sentence_length_drift(&profile, expected),
+            DimensionDrift {
+                dimension: StyleDimension::Dialogue,
+                score: ((profile.dialogue_ratio - expected.dialogue_ratio).abs() / 0.5).min(1.0),
+                note: format!(
+                    "{} of the words are dialogue; the examples have {}",
+                    percent(profile.dialogue_ratio),
+                    percent(expected.dialogue_ratio)
+                ),
+            },
+            DimensionDrift {
+                dimension: StyleDimension::LexicalDensity,
+                score: ((profile.lexical_density - expected.lexical_density).abs() / 0.2).min(1.0),
+                note: format!(
+                    "{} of the words carry content; the examples have {}",
+                    percent(profile.lexical_density),
+                    percent(expected.lexical_density)
+                ),
+            },
+            function_word_drift(&profile, expected),
+            pronoun_drift(&profile, expected),
+            punctuation_drift(&profile, expected),
+        ];
+
+        let score = dimensions.iter().map(|d| d.dimension.weight() * d.score).sum::<f32>().min(1.0);
+        dimensions.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(std::cmp::Ordering::Equal));
+        let reliable = profile.word_count >= MIN_RELIABLE_WORDS;
+
+        StyleDrift {
+            score,
+            flagged: reliable && score >= DRIFT_THRESHOLD,
+            reliable,
+            word_count: profile.word_count,
+            dimensions,
+            profile,
+        }
+    }
+}
+
+fn sentence_length_drift(profile: &StyleProfile, expected: &StyleProfile) -> DimensionDrift {
+    let mean_drift = if expected.sentence_length_mean > 0.0 {
+        ((profile.sentence_length_mean - expected.sentence_length_mean).abs() / expected.sentence_length_mean).min(1.0)
+    } else {
+        0.0
+    };
+    let shape_drift = total_variation(&profile.sentence_length_distribution, &expected.sentence_length_distribution);
+    DimensionDrift {
+        dimension: StyleDimension::SentenceLength,
+        score: 0.5 * mean_drift + 0.5 * shape_drift,
+        note: format!(
+            "Sentences average {:.1} words; the examples average {:.1}",
+            profile.sentence_length_mean, expected.sentence_length_mean
+        ),
+    }
+}
+
+fn function_word_drift(profile: &StyleProfile, expected: &StyleProfile) -> DimensionDrift {
+    let rates = |p: &StyleProfile| -> Vec<f32> { p.function_words.values().copied().collect() };
+    let score = cosine(&rates(profile), &rates(expected)).map_or(0.0, |similarity| ((1.0 - similarity) / 0.25).clamp(0.0, 1.0));
+
+    // The word whose rate differs most, to point at
+    let gap = profile
+        .function_words
+        .iter()
+        .map(|(word, rate)| (word, rate, expected.function_words.get(word).copied().unwrap_or(0.0)))
+        .max_by(|a, b| (a.1 - a.2).abs().partial_cmp(&(b.1 - b.2).abs()).unwrap_or(std::cmp::Ordering::Equal));
+    let note = match gap {
+        Some((word, rate, expected_rate)) => format!(
+            "\"{}\" appears {:.0} times per 1000 words; the examples use it {:.0} times",
+            word, rate, expected_rate
+        ),
+        None => "No function words to compare".to_string(),
+    };
+    DimensionDrift { dimension: StyleDimension::FunctionWords, score, note }
+}
+
+fn pronoun_drift(profile: &StyleProfile, expected: &StyleProfile) -> DimensionDrift {
+    let (Some(actual), Some(usual)) = (profile.pov_pronouns.dominant(), expected.pov_pronouns.dominant()) else {
+        return DimensionDrift {
+            dimension: StyleDimension::Pronouns,
+            score: 0.0,
+            note: "Too few pronouns to compare point of view".to_string(),
+        };
+    };
+    DimensionDrift {
+        dimension: StyleDimension::Pronouns,
+        score: total_variation(&profile.pov_pronouns.shares(), &expected.pov_pronouns.shares()),
+        note: format!("Pronouns are mostly {}; the examples are mostly {}", actual, usual),
+    }
+}
+
+/// Rates below this many per 1000 words count as this many, so a rare mark
+/// appearing once doesn't dominate
+const PUNCTUATION_RATE_FLOOR: f32 = 5.0;
+
+fn punctuation_drift(profile: &StyleProfile, expected: &StyleProfile) -> DimensionDrift {
+    let gaps: Vec<(&String, f32, f32, f32)> = profile
+        .punctuation
+        .iter()
+        .map(|(mark, &rate)| {
+            let usual = expected.punctuation.get(mark).copied().unwrap_or(0.0);
+            let gap = (rate - usual).abs() / rate.max(usual).max(PUNCTUATION_RATE_FLOOR);
+            (mark, rate, usual, gap)
+        })
+        .collect();
+    let score = if gaps.is_empty() { 0.0 } else { gaps.iter().map(|g| g.3).sum::<f32>() / gaps.len() as f32 };
+    let note = gaps
+        .iter()
+        .max_by(|a, b| a.3.partial_cmp(&b.3).unwrap_or(std::cmp::Ordering::Equal))
+        .map(|(mark, rate, usual, _)| format!("The {} rate is {:.1} per 1000 words; the examples have {:.1}", mark, rate, usual))
+        .unwrap_or_else(|| "No punctuation to compare".to_string());
+    DimensionDrift { dimension: StyleDimension::Punctuation, score, note }
+}
+
+/// The fingerprint of a project's style examples; `None` without examples
+pub async fn project_fingerprint(pool: &DbPool, project_id: &str) -> Result<Option<StyleFingerprint>> {
+    let examples = StyleExampleOps::get_by_project(pool, project_id).await?;
+    let texts: Vec<&str> = examples
+        .iter()
+        .map(|example| example.example_text.as_str())
+        .filter(|text| !text.trim().is_empty())
+        .collect();
+    if texts.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(StyleFingerprint::from_examples(project_id, &texts)))
+}
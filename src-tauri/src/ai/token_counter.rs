@@ -1,11 +1,20 @@
 //! Token counting and cost estimation for AI providers
 //!
 //! This module provides functionality to count tokens and estimate costs
-//! for different AI providers and models.
+//! for different AI providers and models. Counting is delegated to the
+//! per-model tokenizers in `super::tokenizer`; prices come from the
+//! user-editable `model_pricing` catalog, seeded with the built-in prices.
 
+use super::tokenizer;
+use crate::database::operations::{AIHistoryOps, ModelPrice, ModelPricingOps, ANY_MODEL};
+use crate::database::DbPool;
 use crate::error::Result;
-use std::collections::HashMap;
+use chrono::{NaiveDate, Utc};
+use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::future::Future;
+use std::sync::{Arc, Mutex, RwLock};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TokenUsage {
@@ -20,6 +29,12 @@ pub struct CostEstimate {
     pub output_cost: f64,
     pub total_cost: f64,
     pub currency: String,
+    /// Date the price used took effect
+    #[serde(default)]
+    pub priced_from: Option<NaiveDate>,
+    /// Set when the catalog has no price for the model, so the cost is 0
+    #[serde(default)]
+    pub warning: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -30,79 +45,197 @@ pub struct TokenCountResult {
     pub model: String,
 }
 
-/// Pricing information for different AI models
-#[derive(Debug, Clone)]
-pub struct ModelPricing {
-    pub input_cost_per_1k: f64,  // Cost per 1000 input tokens
-    pub output_cost_per_1k: f64, // Cost per 1000 output tokens
+/// What a request was billed for
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct BillableUsage {
+    /// Input tokens, including the cached ones
+    pub input_tokens: u32,
+    /// Input tokens served from the provider's prompt cache
+    #[serde(default)]
+    pub cached_input_tokens: u32,
+    pub output_tokens: u32,
+    #[serde(default)]
+    pub images: u32,
 }
 
-pub struct TokenCounter {
-    pricing: HashMap<String, ModelPricing>,
+impl BillableUsage {
+    pub fn tokens(input_tokens: u32, output_tokens: u32) -> Self {
+        Self { input_tokens, output_tokens, ..Default::default() }
+    }
 }
 
-impl TokenCounter {
+tokio::task_local! {
+    static USAGE_METER: UsageMeter;
+}
+
+/// Collects the token usage providers report while serving one request, so
+/// its spend is recorded from the provider's numbers rather than an estimate
+#[derive(Debug, Clone, Default)]
+pub struct UsageMeter(Arc<Mutex<Option<TokenUsage>>>);
+
+impl UsageMeter {
     pub fn new() -> Self {
-        let mut pricing = HashMap::new();
-        
-        // OpenAI pricing (as of 2024)
-        pricing.insert("gpt-4".to_string(), ModelPricing {
-            input_cost_per_1k: 0.03,
-            output_cost_per_1k: 0.06,
-        });
-        pricing.insert("gpt-4-turbo".to_string(), ModelPricing {
-            input_cost_per_1k: 0.01,
-            output_cost_per_1k: 0.03,
-        });
-        pricing.insert("gpt-3.5-turbo".to_string(), ModelPricing {
-            input_cost_per_1k: 0.0015,
-            output_cost_per_1k: 0.002,
+        Self::default()
+    }
+
+    /// Run `future` with the usage reported by providers going to this meter
+    pub async fn measure<F: Future>(&self, future: F) -> F::Output {
+        USAGE_METER.scope(self.clone(), future).await
+    }
+
+    /// Report usage from a provider response to the request being measured.
+    /// Does nothing outside `measure`.
+    pub fn report(input_tokens: u32, output_tokens: u32) {
+        let _ = USAGE_METER.try_with(|meter| {
+            let mut reported = meter.0.lock().unwrap_or_else(|e| e.into_inner());
+            let usage = reported.get_or_insert(TokenUsage { input_tokens: 0, output_tokens: 0, total_tokens: 0 });
+            usage.input_tokens = usage.input_tokens.saturating_add(input_tokens);
+            usage.output_tokens = usage.output_tokens.saturating_add(output_tokens);
+            usage.total_tokens = usage.input_tokens.saturating_add(usage.output_tokens);
         });
-        
+    }
+
+    /// Everything reported so far, or `None` if the provider reported nothing
+    pub fn reported(&self) -> Option<TokenUsage> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+}
+
+/// Date from which the built-in prices apply
+fn builtin_effective_from() -> NaiveDate {
+    NaiveDate::from_ymd_opt(2024, 1, 1).expect("valid date")
+}
+
+/// Prices seeded into the catalog the first time it is loaded
+pub fn builtin_prices() -> Vec<ModelPrice> {
+    let from = builtin_effective_from();
+    let mut prices = vec![
+        // OpenAI pricing (as of 2024)
+        ModelPrice::new("gpt-4", 0.03, 0.06, from),
+        ModelPrice::new("gpt-4-turbo", 0.01, 0.03, from),
+        ModelPrice::new("gpt-3.5-turbo", 0.0015, 0.002, from),
         // Claude pricing (as of 2024)
-        pricing.insert("claude-3-opus".to_string(), ModelPricing {
-            input_cost_per_1k: 0.015,
-            output_cost_per_1k: 0.075,
-        });
-        pricing.insert("claude-3-sonnet".to_string(), ModelPricing {
-            input_cost_per_1k: 0.003,
-            output_cost_per_1k: 0.015,
-        });
-        pricing.insert("claude-3-haiku".to_string(), ModelPricing {
-            input_cost_per_1k: 0.00025,
-            output_cost_per_1k: 0.00125,
-        });
-        
-        Self { pricing }
+        ModelPrice::new("claude-3-opus", 0.015, 0.075, from),
+        ModelPrice::new("claude-3-sonnet", 0.003, 0.015, from),
+        ModelPrice::new("claude-3-haiku", 0.00025, 0.00125, from),
+        // Gemini pricing (as of 2024)
+        ModelPrice::new("gemini-pro", 0.0005, 0.0015, from),
+        ModelPrice::new("gemini-pro-vision", 0.0005, 0.0015, from),
+        // Local models have no API cost
+        ModelPrice::new(ANY_MODEL, 0.0, 0.0, from).for_provider("ollama"),
+        ModelPrice::new(ANY_MODEL, 0.0, 0.0, from).for_provider("local"),
+    ];
+    for (model, per_image) in [("dall-e-3", 0.04), ("dall-e-2", 0.02)] {
+        let mut price = ModelPrice::new(model, 0.0, 0.0, from);
+        price.image_cost = Some(per_image);
+        prices.push(price);
+    }
+    for price in &mut prices {
+        price.source = "builtin".to_string();
+    }
+    prices
+}
+
+/// How closely a catalog entry's model matches a requested model: exact,
+/// a versioned name of it (`gpt-4` for `gpt-4-0613`), or any model
+fn model_match(entry: &str, model: &str) -> Option<usize> {
+    if entry == model {
+        Some(usize::MAX)
+    } else if entry == ANY_MODEL {
+        Some(0)
+    } else if model.starts_with(entry) && model[entry.len()..].starts_with(['-', ':', '@']) {
+        Some(entry.len())
+    } else {
+        None
+    }
+}
+
+pub struct TokenCounter {
+    prices: Vec<ModelPrice>,
+}
+
+impl TokenCounter {
+    /// Counter with the built-in prices
+    pub fn new() -> Self {
+        Self::from_prices(builtin_prices())
+    }
+
+    pub fn from_prices(prices: Vec<ModelPrice>) -> Self {
+        Self { prices }
     }
     
-    /// Count tokens in text using a simple approximation
-    /// This is a basic implementation - in production, you might want to use
-    /// provider-specific tokenizers like tiktoken for OpenAI
+    /// Count tokens in text with the generic tokenizer. Prefer
+    /// `count_tokens_for_model` when the model is known.
     pub fn count_tokens(&self, text: &str) -> u32 {
-        // Simple approximation: ~4 characters per token for English text
-        // This is a rough estimate and should be replaced with proper tokenization
-        let char_count = text.chars().count() as f64;
-        (char_count / 4.0).ceil() as u32
+        self.count_tokens_for_model("", text)
+    }
+
+    /// Count tokens in text with the tokenizer of the given model
+    pub fn count_tokens_for_model(&self, model: &str, text: &str) -> u32 {
+        tokenizer::count_tokens(model, text) as u32
+    }
+
+    /// The price of `model` served by `provider` on `date`. The closest model
+    /// match wins, then a price for the provider over one for any provider,
+    /// then the latest price in effect on the date.
+    pub fn price_for(&self, provider: &str, model: &str, date: NaiveDate) -> Option<&ModelPrice> {
+        self.prices
+            .iter()
+            .filter(|p| p.effective_from <= date)
+            .filter(|p| p.provider.as_deref().map_or(true, |pp| pp.eq_ignore_ascii_case(provider)))
+            .filter_map(|p| model_match(&p.model, model).map(|rank| (rank, p.provider.is_some(), p.effective_from, p)))
+            .max_by_key(|(rank, for_provider, from, _)| (*rank, *for_provider, *from))
+            .map(|(_, _, _, price)| price)
     }
     
-    /// Estimate cost for a given token usage and model
+    /// Estimate cost for a given token usage and model at today's prices
     pub fn estimate_cost(
         &self,
-        _provider: &str,
+        provider: &str,
         model: &str,
         input_tokens: u32,
         output_tokens: u32,
     ) -> f64 {
-        let pricing = self.pricing.get(model)
-            .unwrap_or(&ModelPricing {
-                input_cost_per_1k: 0.01,  // Default fallback pricing
-                output_cost_per_1k: 0.03,
-            });
-        
-        let input_cost = (input_tokens as f64 / 1000.0) * pricing.input_cost_per_1k;
-        let output_cost = (output_tokens as f64 / 1000.0) * pricing.output_cost_per_1k;
-        input_cost + output_cost
+        self.estimate(provider, model, input_tokens, output_tokens).total_cost
+    }
+
+    /// Cost breakdown for a given token usage and model at today's prices
+    pub fn estimate(&self, provider: &str, model: &str, input_tokens: u32, output_tokens: u32) -> CostEstimate {
+        self.estimate_usage(provider, model, BillableUsage::tokens(input_tokens, output_tokens), Utc::now().date_naive())
+    }
+
+    /// Cost breakdown for `usage` at the prices in effect on `date`. Models
+    /// without a price cost 0 and carry a warning.
+    pub fn estimate_usage(&self, provider: &str, model: &str, usage: BillableUsage, date: NaiveDate) -> CostEstimate {
+        let Some(price) = self.price_for(provider, model, date) else {
+            return CostEstimate {
+                input_cost: 0.0,
+                output_cost: 0.0,
+                total_cost: 0.0,
+                currency: "USD".to_string(),
+                priced_from: None,
+                warning: Some(format!(
+                    "No price for {} ({}) on {}; add it to the model pricing catalog to track its cost",
+                    model, provider, date
+                )),
+            };
+        };
+
+        let cached = usage.cached_input_tokens.min(usage.input_tokens);
+        let cached_rate = price.cached_input_cost_per_1k.unwrap_or(price.input_cost_per_1k);
+        let input_cost = ((usage.input_tokens - cached) as f64 / 1000.0) * price.input_cost_per_1k
+            + (cached as f64 / 1000.0) * cached_rate;
+        let output_cost = (usage.output_tokens as f64 / 1000.0) * price.output_cost_per_1k
+            + usage.images as f64 * price.image_cost.unwrap_or_default();
+
+        CostEstimate {
+            input_cost,
+            output_cost,
+            total_cost: input_cost + output_cost,
+            currency: price.currency.clone(),
+            priced_from: Some(price.effective_from),
+            warning: None,
+        }
     }
     
     /// Count tokens and estimate cost for a request/response pair
@@ -113,8 +246,8 @@ impl TokenCounter {
         provider: &str,
         model: &str,
     ) -> Result<TokenCountResult> {
-        let input_tokens = self.count_tokens(input_text);
-        let output_tokens = self.count_tokens(output_text);
+        let input_tokens = self.count_tokens_for_model(model, input_text);
+        let output_tokens = self.count_tokens_for_model(model, output_text);
         let total_tokens = input_tokens + output_tokens;
         
         let usage = TokenUsage {
@@ -123,16 +256,7 @@ impl TokenCounter {
             total_tokens,
         };
         
-        let total_cost = self.estimate_cost(provider, model, input_tokens, output_tokens);
-        let input_cost = (input_tokens as f64 / 1000.0) * 0.0015; // Approximate input cost
-        let output_cost = total_cost - input_cost;
-        
-        let cost = CostEstimate {
-            input_cost,
-            output_cost,
-            total_cost,
-            currency: "USD".to_string(),
-        };
+        let cost = self.estimate(provider, model, input_tokens, output_tokens);
         
         Ok(TokenCountResult {
             usage,
@@ -142,22 +266,9 @@ impl TokenCounter {
         })
     }
     
-    /// Get available models and their pricing
-    pub fn get_model_pricing(&self) -> &HashMap<String, ModelPricing> {
-        &self.pricing
-    }
-    
-    /// Add or update pricing for a model
-    pub fn update_model_pricing(
-        &mut self,
-        model: String,
-        input_cost_per_1k: f64,
-        output_cost_per_1k: f64,
-    ) {
-        self.pricing.insert(model, ModelPricing {
-            input_cost_per_1k,
-            output_cost_per_1k,
-        });
+    /// Every price in the catalog
+    pub fn prices(&self) -> &[ModelPrice] {
+        &self.prices
     }
 }
 
@@ -167,6 +278,60 @@ impl Default for TokenCounter {
     }
 }
 
+static PRICING: Lazy<RwLock<Arc<TokenCounter>>> = Lazy::new(|| RwLock::new(Arc::new(TokenCounter::new())));
+
+/// The counter with the current pricing catalog
+pub fn pricing() -> Arc<TokenCounter> {
+    PRICING.read().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+/// Replace the pricing catalog in use
+pub fn set_pricing(prices: Vec<ModelPrice>) {
+    *PRICING.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(TokenCounter::from_prices(prices));
+}
+
+/// Seed the built-in prices into `pool` and reload the catalog, e.g. after a price changes
+pub async fn reload_pricing(pool: &DbPool) -> Result<()> {
+    ModelPricingOps::seed_builtin(pool, &builtin_prices()).await?;
+    set_pricing(ModelPricingOps::list(pool).await?);
+    Ok(())
+}
+
+/// Outcome of recomputing the cost of a project's past generations
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HistoryCostRecompute {
+    pub updated: usize,
+    /// Records left as they were because their model has no price
+    pub unpriced: usize,
+    pub unpriced_models: Vec<String>,
+}
+
+/// Recompute `cost_estimate` of a project's generation history at the
+/// prices in effect when each generation was made
+pub async fn recompute_history_costs(pool: &DbPool, project_id: &str, counter: &TokenCounter) -> Result<HistoryCostRecompute> {
+    let records = AIHistoryOps::get_by_project(pool, project_id, Some(i32::MAX)).await?;
+    let mut outcome = HistoryCostRecompute::default();
+    let mut unpriced_models = BTreeSet::new();
+
+    for record in records {
+        let usage = BillableUsage::tokens(
+            counter.count_tokens_for_model(&record.model, &record.prompt),
+            counter.count_tokens_for_model(&record.model, &record.response),
+        );
+        let cost = counter.estimate_usage(&record.provider, &record.model, usage, record.created_at.date_naive());
+        if cost.warning.is_some() {
+            outcome.unpriced += 1;
+            unpriced_models.insert(record.model);
+            continue;
+        }
+        AIHistoryOps::update_cost_estimate(pool, &record.id, cost.total_cost).await?;
+        outcome.updated += 1;
+    }
+
+    outcome.unpriced_models = unpriced_models.into_iter().collect();
+    Ok(outcome)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -177,8 +342,18 @@ mod tests {
         let text = "Hello, world! This is a test.";
         let token_count = counter.count_tokens(text);
         
-        // Should be approximately 7 tokens (28 chars / 4)
-        assert!(token_count >= 6 && token_count <= 8);
+        // "Hello" "," " world" "!" " This" " is" " a" " test" "."
+        assert!(token_count >= 8 && token_count <= 10);
+    }
+    
+    #[test]
+    fn test_model_specific_counting() {
+        let counter = TokenCounter::new();
+        let text = "「行かないで」と彼女は言った。";
+
+        // Non-English text costs far more than characters / 4 suggests
+        assert!(counter.count_tokens_for_model("gpt-4", text) > (text.chars().count() / 4) as u32);
+        assert!(counter.count_tokens_for_model("claude-3-haiku", text) > 0);
     }
     
     #[test]
@@ -0,0 +1,471 @@
+//! Per-model tokenizers for token counting
+//!
+//! OpenAI-family models are counted with a byte-level BPE loaded offline from
+//! tiktoken vocab files (`cl100k_base.tiktoken`, `o200k_base.tiktoken`) in the
+//! app's `tokenizers` directory. Claude and Gemini don't publish their
+//! vocabularies, so they use approximations calibrated per script and
+//! character class. An OpenAI model whose vocab file isn't installed falls
+//! back to an approximation of its encoding.
+
+use crate::error::{Result, StoryWeaverError};
+use base64::Engine;
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+
+/// Counts tokens the way a particular model family does
+pub trait Tokenizer: Send + Sync {
+    fn name(&self) -> &str;
+    fn count_tokens(&self, text: &str) -> usize;
+}
+
+/// Tokenizer families, selected from a model name
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TokenizerFamily {
+    /// GPT-4, GPT-3.5 and the v3 embedding models
+    Cl100k,
+    /// GPT-4o, GPT-4.1 and the o-series reasoning models
+    O200k,
+    Claude,
+    Gemini,
+    /// Anything else, e.g. local models behind an OpenAI-compatible server
+    Generic,
+}
+
+impl TokenizerFamily {
+    pub fn for_model(model: &str) -> Self {
+        let model = model.to_lowercase();
+        // Routing keys and some gateways prefix the model with its provider
+        let model = model.rsplit('/').next().unwrap_or_default();
+
+        let o200k = ["gpt-4o", "chatgpt-4o", "gpt-4.1", "gpt-4.5", "gpt-5", "o1", "o3", "o4"];
+        let cl100k = ["gpt-4", "gpt-3.5", "text-embedding-3", "text-embedding-ada"];
+
+        if o200k.iter().any(|prefix| model.starts_with(prefix)) {
+            TokenizerFamily::O200k
+        } else if cl100k.iter().any(|prefix| model.starts_with(prefix)) {
+            TokenizerFamily::Cl100k
+        } else if model.contains("claude") {
+            TokenizerFamily::Claude
+        } else if model.contains("gemini") || model.contains("gemma") {
+            TokenizerFamily::Gemini
+        } else {
+            TokenizerFamily::Generic
+        }
+    }
+
+    /// The tiktoken vocab file for BPE families
+    pub fn vocab_file(&self) -> Option<&'static str> {
+        match self {
+            TokenizerFamily::Cl100k => Some("cl100k_base.tiktoken"),
+            TokenizerFamily::O200k => Some("o200k_base.tiktoken"),
+            _ => None,
+        }
+    }
+
+    /// Approximation used when no exact tokenizer is available
+    pub fn approximation(&self) -> ApproximationProfile {
+        match self {
+            TokenizerFamily::Cl100k | TokenizerFamily::Generic => ApproximationProfile {
+                single_token_word_chars: 8,
+                extra_chars_per_token: 4.0,
+                tokens_per_cjk_char: 1.2,
+                bytes_per_token_other: 2.5,
+            },
+            TokenizerFamily::O200k => ApproximationProfile {
+                single_token_word_chars: 9,
+                extra_chars_per_token: 4.5,
+                tokens_per_cjk_char: 0.8,
+                bytes_per_token_other: 3.5,
+            },
+            TokenizerFamily::Claude => ApproximationProfile {
+                single_token_word_chars: 7,
+                extra_chars_per_token: 3.5,
+                tokens_per_cjk_char: 1.3,
+                bytes_per_token_other: 2.2,
+            },
+            TokenizerFamily::Gemini => ApproximationProfile {
+                single_token_word_chars: 9,
+                extra_chars_per_token: 4.5,
+                tokens_per_cjk_char: 0.7,
+                bytes_per_token_other: 3.5,
+            },
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            TokenizerFamily::Cl100k => "cl100k_base",
+            TokenizerFamily::O200k => "o200k_base",
+            TokenizerFamily::Claude => "claude",
+            TokenizerFamily::Gemini => "gemini",
+            TokenizerFamily::Generic => "generic",
+        }
+    }
+}
+
+/// Byte-level BPE over a tiktoken rank table
+pub struct BpeTokenizer {
+    name: String,
+    ranks: HashMap<Vec<u8>, u32>,
+}
+
+impl BpeTokenizer {
+    pub fn new(name: &str, ranks: HashMap<Vec<u8>, u32>) -> Self {
+        Self {
+            name: name.to_string(),
+            ranks,
+        }
+    }
+
+    /// Parse the tiktoken format: one `<base64 token> <rank>` pair per line
+    pub fn from_tiktoken(name: &str, contents: &str) -> Result<Self> {
+        let mut ranks = HashMap::new();
+        for (line_number, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let invalid = || StoryWeaverError::deserialization(format!(
+                "Invalid {} vocab entry on line {}", name, line_number + 1
+            ));
+            let (token, rank) = line.split_once(' ').ok_or_else(invalid)?;
+            let token = base64::engine::general_purpose::STANDARD.decode(token).map_err(|_| invalid())?;
+            let rank = rank.trim().parse::<u32>().map_err(|_| invalid())?;
+            ranks.insert(token, rank);
+        }
+        Ok(Self::new(name, ranks))
+    }
+
+    pub fn from_file(name: &str, path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| StoryWeaverError::system(format!("Failed to read vocab file {}: {}", path.display(), e)))?;
+        Self::from_tiktoken(name, &contents)
+    }
+
+    /// Token ids for `text`. Byte sequences missing from the vocabulary are
+    /// emitted as `u32::MAX`, one per unmergeable part.
+    pub fn encode(&self, text: &str) -> Vec<u32> {
+        let mut ids = Vec::new();
+        for piece in pre_tokenize(text) {
+            let bytes = piece.as_bytes();
+            match self.ranks.get(bytes) {
+                Some(&rank) => ids.push(rank),
+                None => self.byte_pair_encode(bytes, &mut ids),
+            }
+        }
+        ids
+    }
+
+    fn byte_pair_encode(&self, piece: &[u8], ids: &mut Vec<u32>) {
+        // Part boundaries; repeatedly merge the adjacent pair with the lowest rank
+        let mut parts: Vec<usize> = (0..=piece.len()).collect();
+        loop {
+            let mut best: Option<(u32, usize)> = None;
+            for i in 0..parts.len().saturating_sub(2) {
+                if let Some(&rank) = self.ranks.get(&piece[parts[i]..parts[i + 2]]) {
+                    if best.map(|(best_rank, _)| rank < best_rank).unwrap_or(true) {
+                        best = Some((rank, i));
+                    }
+                }
+            }
+            match best {
+                Some((_, i)) => {
+                    parts.remove(i + 1);
+                }
+                None => break,
+            }
+        }
+
+        for bounds in parts.windows(2) {
+            ids.push(self.ranks.get(&piece[bounds[0]..bounds[1]]).copied().unwrap_or(u32::MAX));
+        }
+    }
+}
+
+impl Tokenizer for BpeTokenizer {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn count_tokens(&self, text: &str) -> usize {
+        self.encode(text).len()
+    }
+}
+
+/// Calibration constants for an approximate tokenizer
+#[derive(Debug, Clone, Copy)]
+pub struct ApproximationProfile {
+    /// Words up to this many characters usually encode as a single token
+    pub single_token_word_chars: usize,
+    /// Characters per additional token in longer words
+    pub extra_chars_per_token: f64,
+    /// Tokens per Chinese, Japanese or Korean character
+    pub tokens_per_cjk_char: f64,
+    /// UTF-8 bytes per token for other non-Latin scripts
+    pub bytes_per_token_other: f64,
+}
+
+/// Estimates token counts from the same word/number/punctuation pieces a BPE
+/// would see, costing each piece by script
+pub struct ApproximateTokenizer {
+    name: String,
+    profile: ApproximationProfile,
+}
+
+impl ApproximateTokenizer {
+    pub fn new(name: &str, profile: ApproximationProfile) -> Self {
+        Self {
+            name: name.to_string(),
+            profile,
+        }
+    }
+
+    fn word_tokens(&self, word: &str) -> f64 {
+        let mut latin_chars = 0usize;
+        let mut cjk_chars = 0usize;
+        let mut other_bytes = 0usize;
+        for c in word.chars() {
+            if is_cjk(c) {
+                cjk_chars += 1;
+            } else if is_latin(c) {
+                latin_chars += 1;
+            } else {
+                other_bytes += c.len_utf8();
+            }
+        }
+
+        let mut tokens = 0.0;
+        if latin_chars > 0 {
+            let extra = latin_chars.saturating_sub(self.profile.single_token_word_chars) as f64;
+            tokens += 1.0 + (extra / self.profile.extra_chars_per_token).ceil();
+        }
+        tokens += cjk_chars as f64 * self.profile.tokens_per_cjk_char;
+        tokens += other_bytes as f64 / self.profile.bytes_per_token_other;
+        tokens
+    }
+}
+
+impl Tokenizer for ApproximateTokenizer {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn count_tokens(&self, text: &str) -> usize {
+        let mut total = 0.0;
+        for piece in pre_tokenize(text) {
+            let Some(first) = piece.chars().next() else { continue };
+            let tokens = if piece.chars().any(|c| c.is_alphabetic()) {
+                // A leading space folds into the word; other leading characters don't
+                let word = piece.trim_start_matches(|c: char| !c.is_alphabetic());
+                let leading = if first == ' ' || first.is_alphabetic() { 0.0 } else { 1.0 };
+                leading + self.word_tokens(word)
+            } else if first.is_numeric() || piece.trim().is_empty() {
+                1.0
+            } else {
+                // Punctuation runs such as `."` or `...` often share a token
+                (piece.trim().chars().count() as f64 / 2.0).ceil().max(1.0)
+            };
+            total += tokens.max(1.0);
+        }
+        total.round() as usize
+    }
+}
+
+fn is_latin(c: char) -> bool {
+    c.is_ascii_alphabetic() || ('\u{00C0}'..='\u{024F}').contains(&c)
+}
+
+fn is_cjk(c: char) -> bool {
+    matches!(c,
+        '\u{3040}'..='\u{30FF}'   // Hiragana, Katakana
+        | '\u{3400}'..='\u{4DBF}' // CJK Extension A
+        | '\u{4E00}'..='\u{9FFF}' // CJK Unified Ideographs
+        | '\u{AC00}'..='\u{D7AF}' // Hangul syllables
+        | '\u{F900}'..='\u{FAFF}' // CJK Compatibility Ideographs
+    )
+}
+
+/// Split text into the pieces BPE merges within, following the cl100k
+/// pre-tokenization rules: contractions, letter runs with one leading
+/// non-letter, numbers of up to three digits, punctuation runs, and whitespace.
+pub fn pre_tokenize(text: &str) -> Vec<&str> {
+    let chars: Vec<(usize, char)> = text.char_indices().collect();
+    let byte_at = |i: usize| chars.get(i).map(|(b, _)| *b).unwrap_or(text.len());
+
+    let mut pieces = Vec::new();
+    let mut start = 0;
+    while start < chars.len() {
+        let end = piece_end(&chars, start);
+        pieces.push(&text[byte_at(start)..byte_at(end)]);
+        start = end;
+    }
+    pieces
+}
+
+fn piece_end(chars: &[(usize, char)], start: usize) -> usize {
+    let at = |i: usize| chars.get(i).map(|(_, c)| *c);
+    let run = |mut i: usize, pred: &dyn Fn(char) -> bool| {
+        while at(i).is_some_and(pred) {
+            i += 1;
+        }
+        i
+    };
+    let is_letter = |c: char| c.is_alphabetic();
+    let is_number = |c: char| c.is_numeric();
+    let is_newline = |c: char| c == '\r' || c == '\n';
+    let is_punct = |c: char| !c.is_whitespace() && !c.is_alphabetic() && !c.is_numeric();
+    let c = chars[start].1;
+
+    // Contractions
+    if c == '\'' {
+        let next: String = chars[start + 1..].iter().take(2).map(|(_, c)| c.to_ascii_lowercase()).collect();
+        if let Some(suffix) = ["s", "t", "re", "ve", "m", "ll", "d"].iter().find(|s| next.starts_with(*s)) {
+            return start + 1 + suffix.len();
+        }
+    }
+
+    // Letters, with at most one leading non-letter
+    if is_letter(c) {
+        return run(start, &is_letter);
+    }
+    if !is_newline(c) && !is_number(c) && at(start + 1).is_some_and(is_letter) {
+        return run(start + 1, &is_letter);
+    }
+
+    // Numbers split into groups of three digits
+    if is_number(c) {
+        return run(start, &is_number).min(start + 3);
+    }
+
+    // Punctuation, with an optional leading space and trailing newlines
+    let punct_start = if c == ' ' { start + 1 } else { start };
+    if at(punct_start).is_some_and(is_punct) {
+        let end = run(punct_start, &is_punct);
+        return run(end, &is_newline);
+    }
+
+    // Whitespace up to the last newline; otherwise leave the final space to
+    // lead the next word
+    let end = run(start, &|c: char| c.is_whitespace());
+    if let Some(last_newline) = (start..end).rev().find(|&i| is_newline(chars[i].1)) {
+        return last_newline + 1;
+    }
+    if end < chars.len() && end - start > 1 {
+        return end - 1;
+    }
+    end
+}
+
+static TOKENIZERS: Lazy<RwLock<HashMap<TokenizerFamily, Arc<dyn Tokenizer>>>> = Lazy::new(|| {
+    let families = [
+        TokenizerFamily::Cl100k,
+        TokenizerFamily::O200k,
+        TokenizerFamily::Claude,
+        TokenizerFamily::Gemini,
+        TokenizerFamily::Generic,
+    ];
+    let tokenizers: HashMap<TokenizerFamily, Arc<dyn Tokenizer>> = families
+        .into_iter()
+        .map(|family| {
+            let tokenizer: Arc<dyn Tokenizer> = Arc::new(ApproximateTokenizer::new(family.name(), family.approximation()));
+            (family, tokenizer)
+        })
+        .collect();
+    RwLock::new(tokenizers)
+});
+
+/// Use `tokenizer` for every model in `family`
+pub fn register_tokenizer(family: TokenizerFamily, tokenizer: Arc<dyn Tokenizer>) {
+    TOKENIZERS.write().unwrap_or_else(|e| e.into_inner()).insert(family, tokenizer);
+}
+
+/// Load whichever tiktoken vocab files are present in `dir`, returning how many
+/// were loaded. Missing files are not an error: those families keep their
+/// approximation.
+pub fn load_vocab_dir(dir: &Path) -> usize {
+    let mut loaded = 0;
+    for family in [TokenizerFamily::Cl100k, TokenizerFamily::O200k] {
+        let Some(file) = family.vocab_file() else { continue };
+        let path = dir.join(file);
+        if !path.exists() {
+            continue;
+        }
+        match BpeTokenizer::from_file(family.name(), &path) {
+            Ok(tokenizer) => {
+                register_tokenizer(family, Arc::new(tokenizer));
+                loaded += 1;
+            }
+            Err(e) => tracing::warn!("Ignoring {} vocab: {}", family.name(), e),
+        }
+    }
+    loaded
+}
+
+/// The tokenizer for a model name
+pub fn tokenizer_for_model(model: &str) -> Arc<dyn Tokenizer> {
+    let family = TokenizerFamily::for_model(model);
+    let tokenizers = TOKENIZERS.read().unwrap_or_else(|e| e.into_inner());
+    match tokenizers.get(&family) {
+        Some(tokenizer) => tokenizer.clone(),
+        None => Arc::new(ApproximateTokenizer::new(family.name(), family.approximation())),
+    }
+}
+
+/// Count the tokens `model` would see in `text`
+pub fn count_tokens(model: &str, text: &str) -> usize {
+    tokenizer_for_model(model).count_tokens(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pre_tokenize_follows_cl100k_rules() {
+        assert_eq!(pre_tokenize("Hello, world!"), vec!["Hello", ",", " world", "!"]);
+        assert_eq!(pre_tokenize("She'll pay 12345"), vec!["She", "'ll", " pay", " ", "123", "45"]);
+        assert_eq!(pre_tokenize("\"Run!\"\n\n  he said"), vec!["\"Run", "!\"\n\n", " ", " he", " said"]);
+    }
+
+    #[test]
+    fn test_bpe_merges_by_rank() {
+        let mut ranks: HashMap<Vec<u8>, u32> = (0u8..=255).map(|b| (vec![b], b as u32)).collect();
+        ranks.insert(b"he".to_vec(), 256);
+        ranks.insert(b"ll".to_vec(), 257);
+        ranks.insert(b"hell".to_vec(), 258);
+        let tokenizer = BpeTokenizer::new("test", ranks);
+
+        assert_eq!(tokenizer.encode("hello"), vec![258, b'o' as u32]);
+        assert_eq!(tokenizer.count_tokens("hello hello"), 5);
+    }
+
+    #[test]
+    fn test_tiktoken_format_parses() {
+        let tokenizer = BpeTokenizer::from_tiktoken("test", "aA== 0\naGk= 1\n").unwrap();
+        assert_eq!(tokenizer.encode("hi"), vec![1]);
+        assert!(BpeTokenizer::from_tiktoken("test", "not-base64!! x").is_err());
+    }
+
+    #[test]
+    fn test_family_selection() {
+        assert_eq!(TokenizerFamily::for_model("gpt-4o-mini"), TokenizerFamily::O200k);
+        assert_eq!(TokenizerFamily::for_model("gpt-4-turbo"), TokenizerFamily::Cl100k);
+        assert_eq!(TokenizerFamily::for_model("claude-3-haiku-20240307"), TokenizerFamily::Claude);
+        assert_eq!(TokenizerFamily::for_model("gemini-1.5-pro"), TokenizerFamily::Gemini);
+        assert_eq!(TokenizerFamily::for_model("openrouter/gpt-4o"), TokenizerFamily::O200k);
+        assert_eq!(TokenizerFamily::for_model("llama3"), TokenizerFamily::Generic);
+    }
+
+    #[test]
+    fn test_approximation_weights_scripts_and_dialogue() {
+        let tokenizer = ApproximateTokenizer::new("test", TokenizerFamily::Cl100k.approximation());
+
+        assert_eq!(tokenizer.count_tokens("This is a longer test sentence."), 7);
+        // Dialogue is punctuation-heavy: far more tokens than characters / 4 suggests
+        assert!(tokenizer.count_tokens("\"No.\" \"Yes!\" \"Why?\" \"Because.\"") >= 10);
+        // Each CJK character costs about a token, not a quarter of one
+        assert!(tokenizer.count_tokens("我们明天去海边") >= 7);
+    }
+}
@@ -0,0 +1,276 @@
+//! Tool calling
+//!
+//! Lets the model pull in the canon it needs during generation instead of
+//! receiving a fixed slice of the story bible up front. `run_tool_loop`
+//! alternates between the provider and a `ToolExecutor` until the model
+//! answers in prose. Providers with native function calling (OpenAI `tools`,
+//! Claude `tool_use`) override `AIProvider::generate_with_tools`; the rest use
+//! a prompted protocol where the model asks for tools with a
+//! `{"tool_calls": [...]}` JSON reply.
+
+use super::structured::extract_json;
+use super::{AIContext, AIProvider};
+use crate::error::{Result, StoryWeaverError};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::time::Instant;
+
+/// Rounds of tool calls allowed before the model must answer
+pub const MAX_TOOL_ROUNDS: usize = 5;
+
+/// A tool the model may call, with a JSON Schema for its arguments
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolDefinition {
+    pub name: String,
+    pub description: String,
+    pub parameters: Value,
+}
+
+impl ToolDefinition {
+    pub fn new(name: &str, description: &str, parameters: Value) -> Self {
+        Self {
+            name: name.to_string(),
+            description: description.to_string(),
+            parameters,
+        }
+    }
+}
+
+/// A tool invocation requested by the model
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolCall {
+    /// Provider-assigned id that the matching result must echo
+    pub id: String,
+    pub name: String,
+    pub arguments: Value,
+}
+
+impl ToolCall {
+    /// String argument `key`, if present and non-empty
+    pub fn str_arg(&self, key: &str) -> Option<&str> {
+        self.arguments
+            .get(key)
+            .and_then(Value::as_str)
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+    }
+}
+
+/// One entry in a tool-calling conversation
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "role", rename_all = "snake_case")]
+pub enum ToolMessage {
+    User { content: String },
+    Assistant { content: Option<String>, tool_calls: Vec<ToolCall> },
+    Tool { call_id: String, name: String, content: String },
+}
+
+/// What the model did with its turn
+#[derive(Debug, Clone, PartialEq)]
+pub enum ToolTurn {
+    /// The final answer
+    Answer(String),
+    /// Tools to run before the model continues, with any text it wrote alongside
+    Calls { content: Option<String>, calls: Vec<ToolCall> },
+}
+
+/// A tool call that ran during a loop, as shown in the AI history
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolCallRecord {
+    pub call: ToolCall,
+    pub output: String,
+    pub is_error: bool,
+    pub duration_ms: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolLoopResult {
+    pub text: String,
+    pub tool_calls: Vec<ToolCallRecord>,
+    /// Provider requests made, including the one that produced the answer
+    pub rounds: usize,
+}
+
+/// Runs the tools offered to the model
+#[async_trait]
+pub trait ToolExecutor: Send + Sync {
+    fn definitions(&self) -> Vec<ToolDefinition>;
+
+    /// Run `call` and return the text handed back to the model. Errors are
+    /// reported to the model rather than ending the loop.
+    async fn execute(&self, call: &ToolCall) -> Result<String>;
+}
+
+/// Ask `provider` to answer `prompt`, running the tools it requests along the
+/// way. After `max_rounds` rounds of calls the tools are withdrawn and the
+/// model is told to answer with what it has.
+pub async fn run_tool_loop<P: AIProvider + ?Sized>(
+    provider: &P,
+    prompt: &str,
+    context: &AIContext,
+    executor: &dyn ToolExecutor,
+    max_rounds: usize,
+) -> Result<ToolLoopResult> {
+    let tools = executor.definitions();
+    let mut messages = vec![ToolMessage::User { content: prompt.to_string() }];
+    let mut records = Vec::new();
+
+    for round in 1..=max_rounds + 1 {
+        if context.cancellation.as_ref().is_some_and(|token| token.is_cancelled()) {
+            return Err(StoryWeaverError::ai_cancelled("tool calling"));
+        }
+
+        let offered: &[ToolDefinition] = if round <= max_rounds { &tools } else { &[] };
+        if offered.is_empty() && round > 1 {
+            messages.push(ToolMessage::User {
+                content: "No more tool calls are available. Write the final answer with what you have.".to_string(),
+            });
+        }
+
+        let (content, calls) = match provider.generate_with_tools(&messages, offered, context).await? {
+            ToolTurn::Answer(text) => {
+                return Ok(ToolLoopResult { text, tool_calls: records, rounds: round });
+            }
+            ToolTurn::Calls { content, calls } => (content, calls),
+        };
+        if offered.is_empty() {
+            break;
+        }
+
+        messages.push(ToolMessage::Assistant { content, tool_calls: calls.clone() });
+        for call in calls {
+            let started = Instant::now();
+            let (output, is_error) = match executor.execute(&call).await {
+                Ok(output) => (output, false),
+                Err(e) => (format!("Error: {}", e), true),
+            };
+            tracing::debug!("Tool {} ran in {:?} (error: {})", call.name, started.elapsed(), is_error);
+            messages.push(ToolMessage::Tool {
+                call_id: call.id.clone(),
+                name: call.name.clone(),
+                content: output.clone(),
+            });
+            records.push(ToolCallRecord {
+                call,
+                output,
+                is_error,
+                duration_ms: started.elapsed().as_millis() as u64,
+            });
+        }
+    }
+
+    Err(StoryWeaverError::AIGenerationError {
+        message: format!(
+            "{} kept requesting tools after {} rounds without answering",
+            provider.get_provider_name(),
+            max_rounds
+        ),
+    })
+}
+
+/// Single prompt carrying the tool list and the conversation so far, for
+/// providers without native function calling
+pub fn prompted_tool_request(messages: &[ToolMessage], tools: &[ToolDefinition]) -> String {
+    let mut prompt = String::new();
+
+    if !tools.is_empty() {
+        prompt.push_str("You can call these tools to look things up before answering:\n");
+        for tool in tools {
+            prompt.push_str(&format!("- {}: {} Arguments: {}\n", tool.name, tool.description, tool.parameters));
+        }
+        prompt.push_str(
+            "\nTo call tools, reply with only a JSON object of the form \
+             {\"tool_calls\": [{\"name\": \"<tool>\", \"arguments\": {...}}]}. \
+             When you have what you need, reply with the final answer as plain text.\n\n",
+        );
+    }
+
+    for message in messages {
+        match message {
+            ToolMessage::User { content } => prompt.push_str(&format!("User:\n{}\n\n", content)),
+            ToolMessage::Assistant { content, tool_calls } => {
+                if let Some(content) = content {
+                    prompt.push_str(&format!("Assistant:\n{}\n\n", content));
+                }
+                for call in tool_calls {
+                    prompt.push_str(&format!("Assistant called {} with {}\n\n", call.name, call.arguments));
+                }
+            }
+            ToolMessage::Tool { name, content, .. } => {
+                prompt.push_str(&format!("Result of {}:\n{}\n\n", name, content));
+            }
+        }
+    }
+
+    prompt.push_str("Assistant:");
+    prompt
+}
+
+/// Read a prompted-protocol reply: a `tool_calls` object when tools were
+/// offered, otherwise the answer
+pub fn parse_prompted_turn(reply: &str, tools_offered: bool) -> ToolTurn {
+    if tools_offered {
+        let calls: Vec<ToolCall> = extract_json(reply)
+            .as_ref()
+            .and_then(|value| value.get("tool_calls"))
+            .and_then(Value::as_array)
+            .map(|calls| {
+                calls
+                    .iter()
+                    .filter_map(|call| {
+                        let name = call.get("name")?.as_str()?;
+                        Some(ToolCall {
+                            id: uuid::Uuid::new_v4().to_string(),
+                            name: name.to_string(),
+                            arguments: call.get("arguments").cloned().unwrap_or_else(|| Value::Object(Default::default())),
+                        })
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        if !calls.is_empty() {
+            return ToolTurn::Calls { content: None, calls };
+        }
+    }
+    ToolTurn::Answer(reply.trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_prompted_reply_with_tool_calls() {
+        let reply = "```json\n{\"tool_calls\": [{\"name\": \"lookup_character\", \"arguments\": {\"name\": \"Mara\"}}]}\n```";
+        match parse_prompted_turn(reply, true) {
+            ToolTurn::Calls { calls, .. } => {
+                assert_eq!(calls.len(), 1);
+                assert_eq!(calls[0].name, "lookup_character");
+                assert_eq!(calls[0].str_arg("name"), Some("Mara"));
+            }
+            other => panic!("expected tool calls, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_prompted_reply_is_an_answer_without_tools() {
+        let reply = "{\"tool_calls\": [{\"name\": \"lookup_character\"}]}";
+        assert_eq!(parse_prompted_turn(reply, false), ToolTurn::Answer(reply.to_string()));
+        assert_eq!(parse_prompted_turn("  The tide turned.  ", true), ToolTurn::Answer("The tide turned.".to_string()));
+    }
+
+    #[test]
+    fn test_prompted_request_lists_tools_and_transcript() {
+        let tools = vec![ToolDefinition::new("lookup_location", "Find a location.", json!({ "type": "object" }))];
+        let messages = vec![
+            ToolMessage::User { content: "Continue the chapter".to_string() },
+            ToolMessage::Tool { call_id: "1".to_string(), name: "lookup_location".to_string(), content: "Greyhaven".to_string() },
+        ];
+        let prompt = prompted_tool_request(&messages, &tools);
+        assert!(prompt.contains("- lookup_location: Find a location."));
+        assert!(prompt.contains("Result of lookup_location:\nGreyhaven"));
+        assert!(!prompted_tool_request(&messages, &[]).contains("tool_calls"));
+    }
+}
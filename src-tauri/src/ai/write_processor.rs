@@ -1,6 +1,9 @@
 //! Write Processor for handling AI-powered writing features
 
 use super::{AIProvider, AIContext};
+use super::story_bible_tools::StoryBibleTools;
+use super::tools::{run_tool_loop, ToolCallRecord, ToolLoopResult, MAX_TOOL_ROUNDS};
+use super::story_summary::summary_context;
 use crate::database::DbPool;
 use crate::database::operations::{DocumentOps, CharacterOps, LocationOps, WorldElementOps};
 use crate::database::models::{Character, Location, WorldElement, CharacterRole};
@@ -18,6 +21,15 @@ pub struct WriteSettings {
     pub include_story_bible: Option<bool>,
     pub max_story_bible_tokens: Option<usize>,
     pub story_bible_priority: Option<StoryBiblePriority>,
+    /// Let the model look up the Story Bible through tools instead of
+    /// receiving a fixed slice of it in the prompt
+    pub use_story_bible_tools: Option<bool>,
+}
+
+impl WriteSettings {
+    pub fn uses_story_bible_tools(&self) -> bool {
+        self.include_story_bible.unwrap_or(true) && self.use_story_bible_tools.unwrap_or(false)
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -41,6 +53,9 @@ pub struct WriteResult {
     pub credits_used: f32,
     pub word_count: usize,
     pub tokens_used: usize,
+    /// Story Bible lookups the model made while writing
+    #[serde(default)]
+    pub tool_calls: Vec<ToolCallRecord>,
 }
 
 pub struct WriteProcessor {
@@ -51,8 +66,8 @@ pub struct WriteProcessor {
 impl WriteProcessor {
     pub fn new(ai_provider: Arc<dyn AIProvider>) -> Self {
         Self {
+            context_builder: ContextBuilder::with_model(ai_provider.get_model_name()),
             ai_provider,
-            context_builder: ContextBuilder::new(),
         }
     }
 
@@ -73,13 +88,11 @@ impl WriteProcessor {
         let prompt = self.build_auto_write_prompt(&context);
         
         // Generate text
-        let generated_text = self.ai_provider
-            .generate_text(&prompt, &context)
-            .await?;
+        let (generated_text, tool_calls) = self.generate(&prompt, &context, settings, db_pool).await?;
         
         // Calculate metrics
         let word_count = count_words(&generated_text);
-        let tokens_used = estimate_tokens(&generated_text);
+        let tokens_used = estimate_tokens(self.ai_provider.get_model_name(), &generated_text);
         let credits_used = calculate_credits(tokens_used);
         
         Ok(WriteResult {
@@ -87,6 +100,7 @@ impl WriteProcessor {
             credits_used,
             word_count,
             tokens_used,
+            tool_calls,
         })
     }
 
@@ -115,13 +129,11 @@ impl WriteProcessor {
         );
         
         // Generate text
-        let generated_text = self.ai_provider
-            .generate_text(&prompt, &context)
-            .await?;
+        let (generated_text, tool_calls) = self.generate(&prompt, &context, settings, db_pool).await?;
         
         // Calculate metrics
         let word_count = count_words(&generated_text);
-        let tokens_used = estimate_tokens(&generated_text);
+        let tokens_used = estimate_tokens(self.ai_provider.get_model_name(), &generated_text);
         let credits_used = calculate_credits(tokens_used);
         
         Ok(WriteResult {
@@ -129,6 +141,7 @@ impl WriteProcessor {
             credits_used,
             word_count,
             tokens_used,
+            tool_calls,
         })
     }
 
@@ -158,13 +171,11 @@ impl WriteProcessor {
         );
         
         // Generate text
-        let generated_text = self.ai_provider
-            .generate_text(&prompt, &context)
-            .await?;
+        let (generated_text, tool_calls) = self.generate(&prompt, &context, settings, db_pool).await?;
         
         // Calculate metrics
         let word_count = count_words(&generated_text);
-        let tokens_used = estimate_tokens(&generated_text);
+        let tokens_used = estimate_tokens(self.ai_provider.get_model_name(), &generated_text);
         let credits_used = calculate_credits(tokens_used);
         
         Ok(WriteResult {
@@ -172,9 +183,27 @@ impl WriteProcessor {
             credits_used,
             word_count,
             tokens_used,
+            tool_calls,
         })
     }
 
+    /// Generate from `prompt`, running the Story Bible tool loop when enabled
+    async fn generate(
+        &self,
+        prompt: &str,
+        context: &AIContext,
+        settings: &WriteSettings,
+        db_pool: &DbPool,
+    ) -> Result<(String, Vec<ToolCallRecord>)> {
+        let project_id = match context.project_id.as_deref() {
+            Some(project_id) if settings.uses_story_bible_tools() => project_id,
+            _ => return Ok((self.ai_provider.generate_text(prompt, context).await?, Vec::new())),
+        };
+
+        let result = write_with_story_bible_tools(self.ai_provider.as_ref(), prompt, context, project_id, db_pool).await?;
+        Ok((result.text, result.tool_calls))
+    }
+
     fn build_auto_write_prompt(&self, context: &AIContext) -> String {
         let mut prompt = String::new();
         
@@ -191,9 +220,27 @@ impl WriteProcessor {
     }
 }
 
+/// Generate from `prompt` on `provider`, letting the model look up the
+/// project's Story Bible through tools. Each lookup is logged to the AI
+/// history under the provider's name and model.
+pub async fn write_with_story_bible_tools(
+    provider: &dyn AIProvider,
+    prompt: &str,
+    context: &AIContext,
+    project_id: &str,
+    db_pool: &DbPool,
+) -> Result<ToolLoopResult> {
+    let tools = StoryBibleTools::new(db_pool.clone(), project_id)
+        .for_document(context.document_id.as_deref())
+        .logged_as(provider.get_provider_name(), provider.get_model_name());
+    run_tool_loop(provider, prompt, context, &tools, MAX_TOOL_ROUNDS).await
+}
+
 /// Context builder for assembling relevant context for AI generation
 pub struct ContextBuilder {
     // Could add caching and other optimizations here
+    /// Model whose tokenizer budgets Story Bible context
+    tokenizer_model: Option<String>,
 }
 
 /// Story Bible budget allocation for token management
@@ -239,7 +286,11 @@ impl StoryBibleBudget {
 
 impl ContextBuilder {
     pub fn new() -> Self {
-        Self {}
+        Self { tokenizer_model: None }
+    }
+
+    pub fn with_model(model: &str) -> Self {
+        Self { tokenizer_model: Some(model.to_string()) }
     }
 
     /// Build context for writing features
@@ -296,21 +347,15 @@ impl ContextBuilder {
             context.following_text = Some(following);
         }
         
-        // Create a story summary (simplified for now)
-        let summary = if content.len() > 500 {
-            format!("{}...", &content[..500])
-        } else {
-            content.clone()
-        };
-        context.story_context = Some(summary);
+        context.story_context = Some(self.story_context(&document.project_id, &document.id, &content, db_pool).await);
         
         // Set document and project IDs
         context.document_id = Some(document_id.to_string());
         context.project_id = Some(document.project_id.to_string());
         
-        // Add Story Bible elements if enabled
+        // Add Story Bible elements if enabled; with tools the model fetches them itself
         if let Some(settings) = settings {
-            if settings.include_story_bible.unwrap_or(true) {
+            if settings.include_story_bible.unwrap_or(true) && !settings.uses_story_bible_tools() {
                 self.enrich_with_story_bible(&mut context, &document.project_id, settings, db_pool).await?;
             }
         } else {
@@ -324,6 +369,7 @@ impl ContextBuilder {
                 include_story_bible: Some(true),
                 max_story_bible_tokens: Some(1000),
                 story_bible_priority: Some(StoryBiblePriority::Balanced),
+                use_story_bible_tools: None,
             };
             self.enrich_with_story_bible(&mut context, &document.project_id, &default_settings, db_pool).await?;
         }
@@ -331,6 +377,22 @@ impl ContextBuilder {
         Ok(context)
     }
 
+    /// The project's rolling summaries around the document, or the
+    /// document's opening until the project has been summarized
+    async fn story_context(&self, project_id: &str, document_id: &str, content: &str, db_pool: &DbPool) -> String {
+        match summary_context(db_pool, project_id, Some(document_id)).await {
+            Ok(Some(summary)) => return summary,
+            Ok(None) => {}
+            Err(e) => tracing::warn!("Failed to load story summaries: {}", e),
+        }
+
+        if content.chars().count() > 500 {
+            format!("{}...", content.chars().take(500).collect::<String>())
+        } else {
+            content.to_string()
+        }
+    }
+
     /// Enrich context with Story Bible elements
     async fn enrich_with_story_bible(
         &self,
@@ -554,7 +616,7 @@ impl ContextBuilder {
             content.push_str(&background.chars().take(200).collect::<String>());
         }
         
-        estimate_tokens(&content)
+        estimate_tokens(self.tokenizer_model.as_deref().unwrap_or_default(), &content)
     }
 
     /// Estimate token count for a location
@@ -576,7 +638,7 @@ impl ContextBuilder {
             content.push_str(&culture.chars().take(100).collect::<String>());
         }
         
-        estimate_tokens(&content)
+        estimate_tokens(self.tokenizer_model.as_deref().unwrap_or_default(), &content)
     }
 
     /// Estimate token count for a world element
@@ -588,7 +650,7 @@ impl ContextBuilder {
             content.push_str(&desc.chars().take(200).collect::<String>());
         }
         
-        estimate_tokens(&content)
+        estimate_tokens(self.tokenizer_model.as_deref().unwrap_or_default(), &content)
     }
 
     /// Build context for rewrite features
@@ -623,13 +685,7 @@ impl ContextBuilder {
             context.project_id = Some(document.project_id.to_string());
             
             // Add story context if available
-            let content = document.content;
-            let summary = if content.len() > 500 {
-                format!("{}...", &content[..500])
-            } else {
-                content
-            };
-            context.story_context = Some(summary);
+            context.story_context = Some(self.story_context(&document.project_id, &document.id, &document.content, db_pool).await);
 
             // Add Story Bible elements if enabled
             if let Some(settings) = settings {
@@ -647,6 +703,7 @@ impl ContextBuilder {
                     include_story_bible: Some(true),
                     max_story_bible_tokens: Some(800), // Slightly less for rewrite context
                     story_bible_priority: Some(StoryBiblePriority::Balanced),
+                    use_story_bible_tools: None,
                 };
                 self.enrich_with_story_bible(&mut context, &document.project_id, &default_settings, db_pool).await?;
             }
@@ -661,10 +718,9 @@ fn count_words(text: &str) -> usize {
     text.split_whitespace().count()
 }
 
-/// Estimate token count (rough approximation)
-fn estimate_tokens(text: &str) -> usize {
-    // Rough estimate: 1 token ≈ 4 characters
-    text.len() / 4
+/// Count tokens with the model's tokenizer
+fn estimate_tokens(model: &str, text: &str) -> usize {
+    super::tokenizer::count_tokens(model, text)
 }
 
 /// Calculate credits based on token usage
@@ -689,8 +745,9 @@ mod tests {
 
     #[test]
     fn test_estimate_tokens() {
-        assert_eq!(estimate_tokens("Hello world"), 2); // 11 chars / 4 ≈ 2
-        assert_eq!(estimate_tokens("This is a longer test sentence."), 7); // 31 chars / 4 ≈ 7
+        assert_eq!(estimate_tokens("gpt-4", "Hello world"), 2); // "Hello", " world"
+        assert_eq!(estimate_tokens("gpt-4", "This is a longer test sentence."), 7); // six words and "."
+        assert!(estimate_tokens("gpt-4", "\"Wait!\" \"Why?\"") > "\"Wait!\" \"Why?\"".len() / 4);
     }
 
     #[test]
@@ -1,7 +1,7 @@
 //! AI Task Processor for StoryWeaver
 //! Handles background processing of AI generation tasks
 
-use crate::ai::{AIContext, AIProviderManager};
+use crate::ai::{AIContext, AIProvider, AIProviderManager};
 use crate::background::{Task, TaskProcessor, TaskStatus, TaskType};
 use crate::error::{Result, StoryWeaverError};
 use std::sync::Arc;
@@ -30,12 +30,12 @@ impl AITaskProcessor {
             .unwrap_or("default");
         
         // Get AI provider manager from app state
-        let ai_manager = self.app_handle.state::<AIProviderManager>();
+        let ai_manager = self.app_handle.state::<Arc<AIProviderManager>>();
         
-        // Get the specified provider or default
-        let provider = if provider_name == "default" {
-            ai_manager.get_default_provider()
-                .ok_or_else(|| StoryWeaverError::internal("No default AI provider configured"))?
+        // A named provider is used as is; otherwise the manager routes the
+        // request and checks it against the AI budgets
+        let provider: Arc<dyn AIProvider> = if provider_name == "default" {
+            ai_manager.inner().clone()
         } else {
             ai_manager.get_provider(provider_name)
                 .ok_or_else(|| StoryWeaverError::internal(format!("AI provider '{}' not found", provider_name)))?
@@ -60,9 +60,11 @@ impl AITaskProcessor {
             creativity_level: None,
             feature_type: None,
             feature_options: None,
+            prose_mode: None,
             word_count_target: None,
             genre: None,
             key_details: None,
+            cancellation: Some(task.cancellation.clone()),
         };
         
         // Generate text
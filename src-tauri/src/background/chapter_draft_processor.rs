@@ -0,0 +1,75 @@
+//! Chapter Draft Task Processor for StoryWeaver
+//! Drafts a whole chapter scene by scene in the background
+
+use crate::ai::{run_chapter_draft, AIProviderManager};
+use crate::background::{Task, TaskProcessor, TaskStatus, TaskType};
+use crate::database::get_pool;
+use crate::error::{Result, StoryWeaverError};
+use std::sync::Arc;
+use tauri::{Emitter, Manager};
+use tokio::sync::Mutex;
+
+/// Chapter Draft Task Processor
+pub struct ChapterDraftProcessor {
+    app_handle: tauri::AppHandle,
+}
+
+impl ChapterDraftProcessor {
+    pub fn new(app_handle: tauri::AppHandle) -> Self {
+        Self { app_handle }
+    }
+}
+
+#[async_trait::async_trait]
+impl TaskProcessor for ChapterDraftProcessor {
+    async fn process_task(&self, task: Arc<Mutex<Task>>) -> Result<()> {
+        let mut task_lock = task.lock().await;
+
+        // Check if task is still valid
+        if task_lock.status != TaskStatus::Running {
+            return Err(StoryWeaverError::internal(format!("Task {} is not in running state", task_lock.id)));
+        }
+
+        let draft_id = task_lock
+            .metadata
+            .get("draft_id")
+            .and_then(|v| v.as_str())
+            .map(str::to_string)
+            .ok_or_else(|| StoryWeaverError::internal("Missing draft_id in chapter draft task"))?;
+        let task_id = task_lock.id.clone();
+        let cancellation = task_lock.cancellation.clone();
+
+        let pool = get_pool()?;
+        let ai_manager = self.app_handle.state::<Arc<AIProviderManager>>().inner().clone();
+        let app_handle = self.app_handle.clone();
+        let mut on_progress = |done: usize, total: usize| {
+            task_lock.update_progress(done as f32 / total.max(1) as f32);
+            if let Err(e) = app_handle.emit(
+                "chapter-draft-progress",
+                serde_json::json!({ "taskId": task_id, "draftId": draft_id, "scenesDone": done, "sceneCount": total }),
+            ) {
+                eprintln!("Failed to emit chapter-draft-progress event: {}", e);
+            }
+        };
+
+        let draft = run_chapter_draft(&pool, ai_manager.as_ref(), &draft_id, Some(cancellation), &mut on_progress).await?;
+
+        if let Err(e) = self.app_handle.emit(
+            "chapter-draft-completed",
+            serde_json::json!({
+                "taskId": task_id,
+                "draftId": draft.id,
+                "documentId": draft.document_id,
+                "versionId": draft.version_id,
+            }),
+        ) {
+            eprintln!("Failed to emit chapter-draft-completed event: {}", e);
+        }
+
+        Ok(())
+    }
+
+    fn can_process(&self, task_type: &TaskType) -> bool {
+        matches!(task_type, TaskType::ChapterDraft)
+    }
+}
@@ -2,10 +2,13 @@
 //! Provides a task queue system for managing long-running operations
 
 pub mod ai_processor;
+pub mod chapter_draft_processor;
+pub mod story_bible_extraction_processor;
 
+use crate::ai::CancellationToken;
 use crate::error::{Result, StoryWeaverError};
 use serde::{Deserialize, Serialize};
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
 use std::fmt;
 use std::str::FromStr;
 use std::sync::Arc;
@@ -68,6 +71,10 @@ pub enum TaskType {
     Export,
     Import,
     Backup,
+    /// Drafting a whole chapter from its outline's scenes
+    ChapterDraft,
+    /// Scanning a project's documents for story bible entries
+    StoryBibleExtraction,
     Other(String),
 }
 
@@ -80,6 +87,8 @@ impl fmt::Display for TaskType {
             TaskType::Export => write!(f, "export"),
             TaskType::Import => write!(f, "import"),
             TaskType::Backup => write!(f, "backup"),
+            TaskType::ChapterDraft => write!(f, "chapter_draft"),
+            TaskType::StoryBibleExtraction => write!(f, "story_bible_extraction"),
             TaskType::Other(s) => write!(f, "{}", s),
         }
     }
@@ -102,6 +111,9 @@ pub struct Task {
     pub project_id: Option<String>,
     pub document_id: Option<String>,
     pub metadata: serde_json::Value,
+    /// Cancelled by `cancel_task` to abort the task while it is running
+    #[serde(skip)]
+    pub cancellation: CancellationToken,
 }
 
 impl Task {
@@ -129,6 +141,7 @@ impl Task {
             project_id,
             document_id,
             metadata: metadata.unwrap_or_else(|| serde_json::json!({})),
+            cancellation: CancellationToken::new(),
         }
     }
 
@@ -166,6 +179,9 @@ pub struct TaskQueue {
     max_history_size: usize,
     max_concurrent_tasks: usize,
     running_tasks: RwLock<Vec<Arc<Mutex<Task>>>>,
+    // Keyed by task id so running tasks can be cancelled without their lock,
+    // which the processor holds for the duration of the task
+    cancellations: RwLock<HashMap<String, CancellationToken>>,
     last_cleanup: Mutex<Instant>,
 }
 
@@ -177,17 +193,16 @@ impl TaskQueue {
             max_history_size,
             max_concurrent_tasks,
             running_tasks: RwLock::new(Vec::new()),
+            cancellations: RwLock::new(HashMap::new()),
             last_cleanup: Mutex::new(Instant::now()),
         }
     }
 
     /// Add a task to the queue
     pub async fn enqueue_task(&self, task: Task) -> Result<String> {
+        let task_id = task.id.clone();
+        self.cancellations.write().await.insert(task_id.clone(), task.cancellation.clone());
         let task = Arc::new(Mutex::new(task));
-        let task_id = {
-            let locked_task = task.lock().await;
-            locked_task.id.clone()
-        };
 
         let mut tasks = self.tasks.write().await;
         tasks.push_back(task);
@@ -257,6 +272,7 @@ impl TaskQueue {
                 }
             }
             
+            self.cancellations.write().await.remove(task_id);
             let mut history = self.history.write().await;
             history.push(task);
             
@@ -271,7 +287,40 @@ impl TaskQueue {
         }
     }
 
-    /// Cancel a queued task
+    /// The cancellation token of a queued or running task
+    pub async fn cancellation_token(&self, task_id: &str) -> Option<CancellationToken> {
+        self.cancellations.read().await.get(task_id).cloned()
+    }
+
+    /// Move a running task whose processing was aborted by `cancel_task` into history
+    pub async fn finish_cancelled(&self, task_id: &str) -> Result<()> {
+        let mut running_tasks = self.running_tasks.write().await;
+        let task_index = running_tasks.iter().position(|t| {
+            t.try_lock()
+                .map(|task| task.id == task_id)
+                .map_err(|e| tracing::warn!("Failed to lock task for cancellation check: {}", e))
+                .unwrap_or(false)
+        });
+
+        let task = match task_index {
+            Some(index) => running_tasks.remove(index),
+            None => return Err(StoryWeaverError::internal(format!("Task {} not found in running tasks", task_id))),
+        };
+        task.lock().await.mark_cancelled();
+
+        self.cancellations.write().await.remove(task_id);
+        let mut history = self.history.write().await;
+        history.push(task);
+        if history.len() > self.max_history_size {
+            history.remove(0);
+        }
+
+        Ok(())
+    }
+
+    /// Cancel a queued or running task. A queued task moves straight to
+    /// history; a running task has its cancellation token tripped, which aborts
+    /// its in-flight AI request, and is moved to history once processing stops.
     pub async fn cancel_task(&self, task_id: &str) -> Result<()> {
         // Check queued tasks
         let mut tasks = self.tasks.write().await;
@@ -292,32 +341,17 @@ impl TaskQueue {
                 task_lock.mark_cancelled();
             }
             
+            self.cancellations.write().await.remove(task_id);
             let mut history = self.history.write().await;
             history.push(task);
             
             return Ok(());
         }
+        drop(tasks);
         
-        // Check running tasks
-        let mut running_tasks = self.running_tasks.write().await;
-        let task_index = running_tasks.iter().position(|t| {
-            t.try_lock()
-                .map(|task| task.id == task_id)
-                .map_err(|e| tracing::warn!("Failed to lock task for cancellation check: {}", e))
-                .unwrap_or(false)
-        });
-        
-        if let Some(index) = task_index {
-            let task = running_tasks.remove(index);
-            
-            {
-                let mut task_lock = task.lock().await;
-                task_lock.mark_cancelled();
-            }
-            
-            let mut history = self.history.write().await;
-            history.push(task);
-            
+        // Running tasks are cancelled through their token
+        if let Some(token) = self.cancellations.read().await.get(task_id) {
+            token.cancel();
             return Ok(());
         }
         
@@ -542,12 +576,17 @@ impl BackgroundTaskManager {
                             let processor_clone = Arc::clone(processor);
                             let task_queue_clone = Arc::clone(&task_queue);
                             let task_id_clone = task_id.clone();
+                            let cancellation = task_queue.cancellation_token(&task_id).await.unwrap_or_default();
                             
                             // Process task in a separate task
                             tokio::spawn(async move {
-                                let result = processor_clone.process_task(Arc::clone(&task)).await;
+                                let result = cancellation.run("background task", processor_clone.process_task(Arc::clone(&task))).await;
                                 
-                                if let Err(e) = result {
+                                if cancellation.is_cancelled() {
+                                    if let Err(e) = task_queue_clone.finish_cancelled(&task_id_clone).await {
+                                        eprintln!("Error cancelling task: {}", e);
+                                    }
+                                } else if let Err(e) = result {
                                     if let Err(e2) = task_queue_clone.complete_task(&task_id_clone, false, Some(e.to_string())).await {
                                         eprintln!("Error completing task: {}", e2);
                                     }
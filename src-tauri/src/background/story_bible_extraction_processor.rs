@@ -0,0 +1,81 @@
+//! Story Bible Extraction Task Processor for StoryWeaver
+//! Scans a project's documents for story bible entries in the background
+
+use crate::ai::{run_story_bible_extraction, AIProviderManager};
+use crate::background::{Task, TaskProcessor, TaskStatus, TaskType};
+use crate::database::get_pool;
+use crate::error::{Result, StoryWeaverError};
+use std::sync::Arc;
+use tauri::{Emitter, Manager};
+use tokio::sync::Mutex;
+
+/// Story Bible Extraction Task Processor
+pub struct StoryBibleExtractionProcessor {
+    app_handle: tauri::AppHandle,
+}
+
+impl StoryBibleExtractionProcessor {
+    pub fn new(app_handle: tauri::AppHandle) -> Self {
+        Self { app_handle }
+    }
+}
+
+#[async_trait::async_trait]
+impl TaskProcessor for StoryBibleExtractionProcessor {
+    async fn process_task(&self, task: Arc<Mutex<Task>>) -> Result<()> {
+        let mut task_lock = task.lock().await;
+
+        // Check if task is still valid
+        if task_lock.status != TaskStatus::Running {
+            return Err(StoryWeaverError::internal(format!("Task {} is not in running state", task_lock.id)));
+        }
+
+        let extraction_id = task_lock
+            .metadata
+            .get("extraction_id")
+            .and_then(|v| v.as_str())
+            .map(str::to_string)
+            .ok_or_else(|| StoryWeaverError::internal("Missing extraction_id in story bible extraction task"))?;
+        let task_id = task_lock.id.clone();
+        let cancellation = task_lock.cancellation.clone();
+
+        let pool = get_pool()?;
+        let ai_manager = self.app_handle.state::<Arc<AIProviderManager>>().inner().clone();
+        let app_handle = self.app_handle.clone();
+        let mut on_progress = |done: usize, total: usize| {
+            task_lock.update_progress(done as f32 / total.max(1) as f32);
+            if let Err(e) = app_handle.emit(
+                "story-bible-extraction-progress",
+                serde_json::json!({
+                    "taskId": task_id,
+                    "extractionId": extraction_id,
+                    "documentsDone": done,
+                    "documentCount": total,
+                }),
+            ) {
+                eprintln!("Failed to emit story-bible-extraction-progress event: {}", e);
+            }
+        };
+
+        let extraction =
+            run_story_bible_extraction(&pool, ai_manager.as_ref(), &extraction_id, Some(cancellation), &mut on_progress)
+                .await?;
+
+        if let Err(e) = self.app_handle.emit(
+            "story-bible-extraction-completed",
+            serde_json::json!({
+                "taskId": task_id,
+                "extractionId": extraction.id,
+                "projectId": extraction.project_id,
+            }),
+        ) {
+            eprintln!("Failed to emit story-bible-extraction-completed event: {}", e);
+        }
+
+        Ok(())
+    }
+
+    fn can_process(&self, task_type: &TaskType) -> bool {
+        matches!(task_type, TaskType::StoryBibleExtraction)
+    }
+}
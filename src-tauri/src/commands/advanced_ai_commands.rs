@@ -1,19 +1,24 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use tauri::State;
+use std::sync::Arc;
+use futures_util::stream::{self, StreamExt};
+use tauri::{Emitter, State, Window};
 use tokio::sync::Mutex;
 use std::str::FromStr;
-use crate::database::{get_pool, models::{Document, DocumentType}, operations::DocumentOps};
+use crate::database::{get_pool, models::{Document, DocumentType}, operations::{DocumentOps, StreamingSession, StreamingSessionOps}};
 
 use crate::ai::{
     advanced_ai_manager::StyleAnalysis, AdvancedAIManager, AdvancedGenerationRequest,
     AdvancedGenerationResult, BrainstormRequest, BrainstormSession, GeneratedImage,
     ProseMode, SaliencyContext, StyleExample, VisualizeRequest,
 };
+use crate::ai::{context_budget, AIProvider, AIProviderManager, RouteRequest, WritingFeature};
+use crate::ai::streaming::relay_to_frontend;
+use crate::ai::streaming_optimizer::get_streaming_optimizer;
 use crate::error::{Result, StoryWeaverError};
 use crate::ai::saliency_engine::StoryBibleElements as SaliencyStoryBible;
 use crate::ai::visualize::ImageResolution;
-use crate::ai::brainstorm::BrainstormCategory;
+use crate::ai::brainstorm::{BrainstormCategory, BrainstormIdea, DuplicateIdea, IdeaTheme, StoryBibleExport};
 
 // State wrapper for the Advanced AI Manager
 pub type AdvancedAIState = Mutex<AdvancedAIManager>;
@@ -257,7 +262,19 @@ pub async fn get_brainstorm_session(
     crate::security::validation::validate_security_input(&session_id)?;
     
     let manager = ai_state.lock().await;
-    Ok(manager.get_brainstorm_session(&session_id).cloned())
+    manager.get_brainstorm_session(&session_id).await
+}
+
+#[tauri::command]
+pub async fn list_brainstorm_sessions(
+    project_id: String,
+    ai_state: State<'_, AdvancedAIState>,
+) -> Result<Vec<BrainstormSession>> {
+    // Input validation
+    crate::security::validation::validate_security_input(&project_id)?;
+
+    let manager = ai_state.lock().await;
+    manager.list_brainstorm_sessions(&project_id).await
 }
 
 #[tauri::command]
@@ -275,8 +292,8 @@ pub async fn rate_brainstorm_idea(
         return Err(StoryWeaverError::InvalidInput { message: "rating must be between 0 and 10".to_string() });
     }
     
-    let mut manager = ai_state.lock().await;
-    manager.rate_brainstorm_idea(&session_id, &idea_id, rating)
+    let manager = ai_state.lock().await;
+    manager.rate_brainstorm_idea(&session_id, &idea_id, rating).await
 }
 
 #[tauri::command]
@@ -290,8 +307,77 @@ pub async fn mark_idea_as_keeper(
     crate::security::validation::validate_security_input(&session_id)?;
     crate::security::validation::validate_security_input(&idea_id)?;
     
+    let manager = ai_state.lock().await;
+    manager.mark_idea_as_keeper(&session_id, &idea_id, is_keeper).await
+}
+
+#[tauri::command]
+pub async fn brainstorm_more_like_this(
+    session_id: String,
+    num_ideas: u32,
+    ai_state: State<'_, AdvancedAIState>,
+) -> Result<Vec<BrainstormIdea>> {
+    // Input validation
+    crate::security::validation::validate_security_input(&session_id)?;
+
+    if num_ideas == 0 || num_ideas > 100 {
+        return Err(StoryWeaverError::InvalidInput { message: "num_ideas must be between 1 and 100".to_string() });
+    }
+
     let mut manager = ai_state.lock().await;
-    manager.mark_idea_as_keeper(&session_id, &idea_id, is_keeper)
+    manager.brainstorm_more_like_this(&session_id, num_ideas as usize).await
+}
+
+#[tauri::command]
+pub async fn merge_brainstorm_ideas(
+    session_id: String,
+    first_idea_id: String,
+    second_idea_id: String,
+    ai_state: State<'_, AdvancedAIState>,
+) -> Result<BrainstormIdea> {
+    // Input validation
+    crate::security::validation::validate_security_input(&session_id)?;
+    crate::security::validation::validate_security_input(&first_idea_id)?;
+    crate::security::validation::validate_security_input(&second_idea_id)?;
+
+    let manager = ai_state.lock().await;
+    manager.merge_brainstorm_ideas(&session_id, &first_idea_id, &second_idea_id).await
+}
+
+#[tauri::command]
+pub async fn deduplicate_brainstorm_ideas(
+    session_id: String,
+    ai_state: State<'_, AdvancedAIState>,
+) -> Result<Vec<DuplicateIdea>> {
+    // Input validation
+    crate::security::validation::validate_security_input(&session_id)?;
+
+    let manager = ai_state.lock().await;
+    manager.deduplicate_brainstorm_ideas(&session_id).await
+}
+
+#[tauri::command]
+pub async fn cluster_brainstorm_ideas(
+    session_id: String,
+    ai_state: State<'_, AdvancedAIState>,
+) -> Result<Vec<IdeaTheme>> {
+    // Input validation
+    crate::security::validation::validate_security_input(&session_id)?;
+
+    let manager = ai_state.lock().await;
+    manager.cluster_brainstorm_ideas(&session_id).await
+}
+
+#[tauri::command]
+pub async fn export_brainstorm_keepers(
+    session_id: String,
+    ai_state: State<'_, AdvancedAIState>,
+) -> Result<StoryBibleExport> {
+    // Input validation
+    crate::security::validation::validate_security_input(&session_id)?;
+
+    let manager = ai_state.lock().await;
+    manager.export_brainstorm_keepers(&session_id).await
 }
 
 // Style Examples Management
@@ -430,10 +516,14 @@ pub async fn build_saliency_context(
     project_id: String,
     text_context: String,
     story_bible: SaliencyStoryBible,
+    document_id: Option<String>,
     ai_state: State<'_, AdvancedAIState>,
 ) -> Result<SaliencyContext> {
     // Input validation
     crate::security::validation::validate_security_input(&project_id)?;
+    if let Some(ref doc_id) = document_id {
+        crate::security::validation::validate_security_input(doc_id)?;
+    }
     crate::security::validation::validate_content_length(&text_context, 50000)?;
     crate::security::validation::validate_security_input(&text_context)?;
     
@@ -441,7 +531,7 @@ pub async fn build_saliency_context(
     let story_bible_saliency = SaliencyStoryBible::from(story_bible);
 
     ai_manager
-        .build_saliency_context(&project_id, &text_context, &story_bible_saliency)
+        .build_saliency_context(&project_id, document_id.as_deref(), &text_context, &story_bible_saliency)
         .await
 }
 
@@ -465,11 +555,17 @@ pub async fn smart_import_content(
         .await
 }
 
-// Streaming Generation (placeholder for future implementation)
+// Streaming Generation
+/// Start a prose-mode generation that streams to the frontend as
+/// `ai_stream_chunk` events and is tracked in `streaming_sessions`.
+/// Returns the stream id used by `get_stream_status` and
+/// `cancel_streaming_generation`.
 #[tauri::command]
 pub async fn start_streaming_generation(
     request: ProseGenerationRequest,
     ai_state: State<'_, AdvancedAIState>,
+    providers: State<'_, Arc<AIProviderManager>>,
+    window: Window,
 ) -> Result<String> {
     // Input validation (reuse the same validation as generate_with_prose_mode)
     crate::security::validation::validate_security_input(&request.project_id)?;
@@ -498,7 +594,6 @@ pub async fn start_streaming_generation(
         crate::security::validation::validate_security_input(instructions)?;
     }
     
-    let mut ai_manager = ai_state.lock().await;
     let advanced_request = AdvancedGenerationRequest {
         project_id: request.project_id,
         document_id: request.document_id,
@@ -512,21 +607,116 @@ pub async fn start_streaming_generation(
         special_instructions: request.special_instructions,
     };
     let story_bible_saliency = request.story_bible.map(SaliencyStoryBible::from);
-    ai_manager
-        .start_streaming_generation(advanced_request, story_bible_saliency)
+    let (prompt, mut context, _) = ai_state
+        .lock()
         .await
+        .prepare_generation(&advanced_request, story_bible_saliency.as_ref())
+        .await?;
+
+    let optimizer = get_streaming_optimizer()?;
+    let stream_id = uuid::Uuid::new_v4().to_string();
+    optimizer.create_stream(stream_id.clone()).await?;
+    let cancellation = optimizer.cancellation_token(&stream_id).await?;
+    context.cancellation = Some(cancellation.clone());
+
+    let providers = providers.inner().clone();
+    let pool = get_pool()?;
+    StreamingSessionOps::create(&pool, &StreamingSession {
+        id: None,
+        session_id: stream_id.clone(),
+        project_id: advanced_request.project_id.clone(),
+        model_used: providers.get_default_provider().map(|provider| provider.get_model_name().to_string()).unwrap_or_default(),
+        prompt: prompt.clone(),
+        status: "active".to_string(),
+        generated_content: None,
+        tokens_generated: None,
+        credits_consumed: None,
+        error_message: None,
+        metadata: None,
+        started_at: Some(chrono::Utc::now().to_rfc3339()),
+        completed_at: None,
+        created_at: None,
+        updated_at: None,
+    }).await?;
+
+    let session_id = stream_id.clone();
+    tokio::spawn(async move {
+        let (prompt, context) = (prompt.as_str(), &context);
+        let route_request = RouteRequest::feature(WritingFeature::Write)
+            .with_cancellation(Some(cancellation.clone()))
+            .with_budget(context_budget(context, prompt));
+        let outcome = match providers
+            .execute_routed(&route_request, |provider| async move {
+                provider.generate_text_stream(prompt, context).await
+            })
+            .await
+        {
+            Ok((chunks, route)) => {
+                if let Err(e) = StreamingSessionOps::update_metadata(&pool, &session_id, &route.to_json()).await {
+                    tracing::warn!("Failed to record route for stream {}: {}", session_id, e);
+                }
+                let model = route.served_by().map(|attempt| attempt.model.clone()).unwrap_or_default();
+                relay_to_frontend(&window, &optimizer, &session_id, &model, chunks).await
+            }
+            // Stopped before the provider answered: still send the final `stopped` chunk
+            Err(_) if cancellation.is_cancelled() => {
+                relay_to_frontend(&window, &optimizer, &session_id, "", stream::empty().boxed()).await
+            }
+            Err(e) => {
+                let _ = optimizer.complete_stream(&session_id).await;
+                Err(e)
+            }
+        };
+
+        // A cancelled stream keeps whatever text arrived before the stop
+        let recorded = match outcome {
+            Ok(text) if cancellation.is_cancelled() => {
+                StreamingSessionOps::mark_cancelled(&pool, &session_id, &text.content, text.token_count as i32).await
+            }
+            Ok(text) => {
+                match StreamingSessionOps::update_content(&pool, &session_id, &text.content, text.token_count as i32).await {
+                    Ok(()) => StreamingSessionOps::update_status(&pool, &session_id, "completed").await,
+                    Err(e) => Err(e),
+                }
+            }
+            Err(e) => {
+                if let Err(emit_err) = window.emit("ai_stream_error", format!("Streaming generation failed: {}", e)) {
+                    eprintln!("Failed to emit stream error: {}", emit_err);
+                }
+                StreamingSessionOps::update_error(&pool, &session_id, &e.to_string()).await
+            }
+        };
+        if let Err(e) = recorded {
+            tracing::warn!("Failed to update streaming session {}: {}", session_id, e);
+        }
+    });
+
+    Ok(stream_id)
 }
 
 #[tauri::command]
-pub async fn get_stream_status(
-    stream_id: String,
-    ai_state: State<'_, AdvancedAIState>,
-) -> Result<HashMap<String, serde_json::Value>> {
+pub async fn get_stream_status(stream_id: String) -> Result<HashMap<String, serde_json::Value>> {
     // Input validation
     crate::security::validation::validate_security_input(&stream_id)?;
     
-    let ai_manager = ai_state.lock().await;
-    ai_manager.get_stream_status(&stream_id).await
+    let pool = get_pool()?;
+    let session = StreamingSessionOps::get_by_session_id(&pool, &stream_id)
+        .await?
+        .ok_or_else(|| StoryWeaverError::not_found("Stream", stream_id.as_str()))?;
+
+    let (status, progress) = match session.status.as_str() {
+        "active" => ("generating".to_string(), 0),
+        other => (other.to_string(), 100),
+    };
+    let mut result = HashMap::new();
+    result.insert("status".to_string(), serde_json::Value::String(status));
+    result.insert("progress".to_string(), serde_json::Value::from(progress));
+    result.insert("current_text".to_string(), serde_json::Value::String(session.generated_content.unwrap_or_default()));
+    result.insert("tokens_generated".to_string(), serde_json::Value::from(session.tokens_generated.unwrap_or(0)));
+    if let Some(error_message) = session.error_message {
+        result.insert("error_message".to_string(), serde_json::Value::String(error_message));
+    }
+    Ok(result)
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -610,10 +800,16 @@ pub async fn save_generated_content(data: SaveGeneratedContentRequest) -> Result
     }
 }
 
+/// Cancel a streaming generation. The provider request is dropped and the
+/// session is marked cancelled with the text generated so far.
 #[tauri::command]
-pub async fn cancel_streaming_generation(stream_id: String, _ai_state: State<'_, AdvancedAIState>) -> Result<()> {
-    // Validate input and accept cancel in current placeholder implementation
+pub async fn cancel_streaming_generation(stream_id: String) -> Result<()> {
     crate::security::validation::validate_security_input(&stream_id)?;
-    // In a future implementation, signal the running task to cancel.
-    Ok(())
+
+    let optimizer = get_streaming_optimizer()?;
+    match optimizer.request_stop(&stream_id).await {
+        // The stream already finished; there is nothing left to cancel
+        Err(StoryWeaverError::NotFound { .. }) => Ok(()),
+        result => result,
+    }
 }
@@ -0,0 +1,167 @@
+//! AI budget command handlers
+
+use crate::ai::advanced_ai_manager::DEFAULT_EXPECTED_OUTPUT_TOKENS;
+use crate::ai::{AIProviderManager, BudgetStatus, CreditTracker, PreflightCheck, SpendEstimate, SpendForecast};
+use crate::commands::CommandResponse;
+use crate::database::get_pool;
+use crate::database::operations::{AIBudget, AIBudgetOps, BudgetEnforcement, BudgetPeriod, BudgetScope};
+use crate::error::{Result, StoryWeaverError};
+use crate::security::rate_limit::{rl_create, rl_delete, rl_list, rl_update};
+use crate::security::validators::{validate_body_limits, validate_id, validate_optional_id, validate_optional_str};
+use chrono::Utc;
+use serde::Deserialize;
+use std::sync::Arc;
+use tauri::State;
+
+/// Longest text accepted for a cost estimate
+const MAX_PREFLIGHT_BYTES: usize = 2_000_000;
+
+#[derive(Debug, Deserialize)]
+pub struct SetAIBudgetRequest {
+    pub scope: BudgetScope,
+    /// Project id or provider key; ignored for global budgets
+    pub scope_id: Option<String>,
+    pub period: BudgetPeriod,
+    pub limit_usd: f64,
+    pub enforcement: Option<BudgetEnforcement>,
+    /// Fractions of the limit to notify at (default 50%, 80% and 100%)
+    pub alert_thresholds: Option<Vec<f64>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PreflightRequest {
+    pub project_id: Option<String>,
+    /// Registered provider key; defaults to the default provider
+    pub provider: Option<String>,
+    /// Prompt and context that would be sent
+    pub text: String,
+    pub expected_output_tokens: Option<u32>,
+}
+
+/// Every budget with its spend in the current period
+#[tauri::command]
+pub async fn list_ai_budgets() -> CommandResponse<Vec<BudgetStatus>> {
+    async fn list() -> Result<Vec<BudgetStatus>> {
+        rl_list("ai_budgets", None)?;
+        let pool = get_pool()?;
+        CreditTracker::budget_statuses(&pool).await
+    }
+
+    list().await.into()
+}
+
+/// Create a budget, or change the one for the same scope and period
+#[tauri::command]
+pub async fn set_ai_budget(request: SetAIBudgetRequest) -> CommandResponse<AIBudget> {
+    async fn set(request: SetAIBudgetRequest) -> Result<AIBudget> {
+        rl_create("ai_budget", None)?;
+        if request.scope == BudgetScope::Global {
+            validate_optional_id("scope_id", &request.scope_id, 64)?;
+        } else {
+            validate_id("scope_id", request.scope_id.as_deref().unwrap_or_default(), 64)?;
+        }
+        if !request.limit_usd.is_finite() || request.limit_usd <= 0.0 {
+            return Err(StoryWeaverError::validation("Budget limit must be a positive amount"));
+        }
+        if let Some(thresholds) = &request.alert_thresholds {
+            if thresholds.len() > 10 || thresholds.iter().any(|t| !t.is_finite() || *t <= 0.0 || *t > 2.0) {
+                return Err(StoryWeaverError::validation(
+                    "Alert thresholds must be up to 10 fractions of the limit between 0 and 2",
+                ));
+            }
+        }
+
+        let mut budget = AIBudget::new(request.scope, request.scope_id, request.period, request.limit_usd);
+        if let Some(enforcement) = request.enforcement {
+            budget.enforcement = enforcement;
+        }
+        if let Some(thresholds) = request.alert_thresholds {
+            budget.alert_thresholds = thresholds;
+        }
+
+        let pool = get_pool()?;
+        AIBudgetOps::upsert(&pool, budget).await
+    }
+
+    set(request).await.into()
+}
+
+#[tauri::command]
+pub async fn delete_ai_budget(id: String) -> CommandResponse<()> {
+    async fn delete(id: String) -> Result<()> {
+        rl_delete("ai_budget", None)?;
+        validate_id("id", &id, 64)?;
+        let pool = get_pool()?;
+        AIBudgetOps::delete(&pool, &id).await
+    }
+
+    delete(id).await.into()
+}
+
+/// Let requests go over a budget that asks for confirmation, until its
+/// current period ends
+#[tauri::command]
+pub async fn approve_budget_overrun(id: String) -> CommandResponse<AIBudget> {
+    async fn approve(id: String) -> Result<AIBudget> {
+        rl_update("ai_budget", None)?;
+        validate_id("id", &id, 64)?;
+
+        let pool = get_pool()?;
+        let budget = AIBudgetOps::get_by_id(&pool, &id).await?;
+        if budget.enforcement != BudgetEnforcement::Confirm {
+            return Err(StoryWeaverError::validation("Only budgets that ask for confirmation can be overrun"));
+        }
+        AIBudgetOps::approve_overrun(&pool, &id, &budget.period.start(Utc::now())).await
+    }
+
+    approve(id).await.into()
+}
+
+/// Estimate what sending `text` would cost and whether the budgets allow it
+#[tauri::command]
+pub async fn preflight_ai_cost(
+    request: PreflightRequest,
+    ai_manager: State<'_, Arc<AIProviderManager>>,
+) -> CommandResponse<PreflightCheck> {
+    async fn preflight(request: PreflightRequest, ai_manager: Arc<AIProviderManager>) -> Result<PreflightCheck> {
+        rl_list("ai_budgets", request.project_id.as_deref())?;
+        validate_optional_id("project_id", &request.project_id, 64)?;
+        validate_optional_str("provider", &request.provider, 100, false)?;
+        validate_body_limits("text", &request.text, MAX_PREFLIGHT_BYTES, MAX_PREFLIGHT_BYTES)?;
+
+        let provider_key = request
+            .provider
+            .or_else(|| ai_manager.get_default_provider_name())
+            .ok_or_else(|| StoryWeaverError::resource_unavailable("AI provider"))?;
+        let provider = ai_manager
+            .get_provider(&provider_key)
+            .ok_or_else(|| StoryWeaverError::not_found("AI provider", provider_key.as_str()))?;
+
+        let input_tokens = crate::ai::tokenizer::count_tokens(provider.get_model_name(), &request.text) as u32;
+        let estimate = SpendEstimate::new(
+            request.project_id,
+            &provider_key,
+            provider.get_model_name(),
+            input_tokens,
+            request.expected_output_tokens.unwrap_or(DEFAULT_EXPECTED_OUTPUT_TOKENS),
+        );
+
+        let pool = get_pool()?;
+        CreditTracker::preflight(&pool, estimate).await
+    }
+
+    preflight(request, ai_manager.inner().clone()).await.into()
+}
+
+/// A project's spend this month projected to the end of the month
+#[tauri::command]
+pub async fn get_spend_forecast(project_id: String) -> CommandResponse<SpendForecast> {
+    async fn forecast(project_id: String) -> Result<SpendForecast> {
+        rl_list("ai_budgets", Some(&project_id))?;
+        validate_id("project_id", &project_id, 64)?;
+        let pool = get_pool()?;
+        CreditTracker::forecast(&pool, &project_id).await
+    }
+
+    forecast(project_id).await.into()
+}
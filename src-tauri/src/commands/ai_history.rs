@@ -1,5 +1,6 @@
 //! AI generation history command handlers
 
+use crate::ai::RouteTaken;
 use crate::database::{get_pool, models::*, operations::AIHistoryOps};
 use crate::error::Result;
 use serde::{Deserialize, Serialize};
@@ -18,6 +19,11 @@ pub struct CreateAIHistoryRequest {
     pub token_count: Option<i32>,
     pub cost_estimate: Option<f64>,
     pub context_used: Option<String>,
+    pub route_taken: Option<String>,
+    /// Template name and version from the generation response's `prompt_template`;
+    /// when left out, the template recorded in `route_taken` is used
+    pub prompt_template: Option<String>,
+    pub prompt_template_version: Option<i64>,
 }
 
 /// AI usage statistics
@@ -97,6 +103,16 @@ pub async fn create_ai_history(request: CreateAIHistoryRequest) -> Result<AIGene
             crate::security::validation::validate_security_input(context)?;
         }
         
+        if let Some(ref route) = request.route_taken {
+            crate::security::validation::validate_content_length(route, 10000)?;
+            crate::security::validation::validate_security_input(route)?;
+        }
+        
+        if let Some(ref template) = request.prompt_template {
+            crate::security::validation::validate_content_length(template, 100)?;
+            crate::security::validation::validate_security_input(template)?;
+        }
+        
         if let Some(token_count) = request.token_count {
             if token_count < 0 {
                 return Err(crate::error::StoryWeaverError::InvalidInput { message: "token_count cannot be negative".to_string() });
@@ -109,6 +125,17 @@ pub async fn create_ai_history(request: CreateAIHistoryRequest) -> Result<AIGene
             }
         }
         
+        // Generations routed on the backend carry their template in the route
+        let (prompt_template, prompt_template_version) = match request.prompt_template {
+            Some(template) => (Some(template), request.prompt_template_version),
+            None => request
+                .route_taken
+                .as_deref()
+                .and_then(|route| serde_json::from_str::<RouteTaken>(route).ok())
+                .and_then(|route| route.prompt_template)
+                .map_or((None, None), |template| (Some(template.name), Some(template.version))),
+        };
+        
         let pool = get_pool()?;
         
         let record = AIGenerationHistory {
@@ -124,6 +151,9 @@ pub async fn create_ai_history(request: CreateAIHistoryRequest) -> Result<AIGene
                 "outline" => AIGenerationType::Outline,
                 "character_development" => AIGenerationType::CharacterDevelopment,
                 "world_building" => AIGenerationType::WorldBuilding,
+                "plugin" => AIGenerationType::Plugin,
+                "tool_call" => AIGenerationType::ToolCall,
+                "summary" => AIGenerationType::Summary,
                 _ => return Err(crate::error::StoryWeaverError::InvalidInput { message: format!("Invalid generation type: {}", request.generation_type) }),
             },
             provider: request.provider,
@@ -133,6 +163,9 @@ pub async fn create_ai_history(request: CreateAIHistoryRequest) -> Result<AIGene
             token_count: request.token_count.unwrap_or(0),
             cost_estimate: request.cost_estimate,
             context_used: request.context_used.unwrap_or_else(|| "{}".to_string()),
+            route_taken: request.route_taken,
+            prompt_template,
+            prompt_template_version,
             created_at: chrono::Utc::now(),
         };
         
@@ -377,6 +410,8 @@ pub struct CostEstimate {
     pub input_tokens: i32,
     pub output_tokens: i32,
     pub estimated_cost: f64,
+    /// Set when the model has no price in the catalog
+    pub warning: Option<String>,
 }
 
 /// Calculate cost estimate for AI generation
@@ -402,73 +437,16 @@ pub async fn calculate_cost_estimate(
         if output_tokens < 0 {
             return Err(crate::error::StoryWeaverError::InvalidInput { message: "output_tokens cannot be negative".to_string() });
         }
-        // Cost calculation based on provider and model
-        // These are approximate rates as of 2024 - should be updated regularly
-        let estimated_cost = match provider.to_lowercase().as_str() {
-            "openai" => {
-                match model.as_str() {
-                    "gpt-4" => {
-                        (input_tokens as f64 * 0.00003) + (output_tokens as f64 * 0.00006)
-                    }
-                    "gpt-4-turbo" => {
-                        (input_tokens as f64 * 0.00001) + (output_tokens as f64 * 0.00003)
-                    }
-                    "gpt-3.5-turbo" => {
-                        (input_tokens as f64 * 0.0000015) + (output_tokens as f64 * 0.000002)
-                    }
-                    _ => {
-                        // Default to GPT-3.5-turbo pricing
-                        (input_tokens as f64 * 0.0000015) + (output_tokens as f64 * 0.000002)
-                    }
-                }
-            }
-            "anthropic" => {
-                match model.as_str() {
-                    "claude-3-opus" => {
-                        (input_tokens as f64 * 0.000015) + (output_tokens as f64 * 0.000075)
-                    }
-                    "claude-3-sonnet" => {
-                        (input_tokens as f64 * 0.000003) + (output_tokens as f64 * 0.000015)
-                    }
-                    "claude-3-haiku" => {
-                        (input_tokens as f64 * 0.00000025) + (output_tokens as f64 * 0.00000125)
-                    }
-                    _ => {
-                        // Default to Claude-3-haiku pricing
-                        (input_tokens as f64 * 0.00000025) + (output_tokens as f64 * 0.00000125)
-                    }
-                }
-            }
-            "google" => {
-                match model.as_str() {
-                    "gemini-pro" => {
-                        (input_tokens as f64 * 0.0000005) + (output_tokens as f64 * 0.0000015)
-                    }
-                    "gemini-pro-vision" => {
-                        (input_tokens as f64 * 0.0000005) + (output_tokens as f64 * 0.0000015)
-                    }
-                    _ => {
-                        // Default to Gemini Pro pricing
-                        (input_tokens as f64 * 0.0000005) + (output_tokens as f64 * 0.0000015)
-                    }
-                }
-            }
-            "ollama" | "local" => {
-                // Local models have no API cost
-                0.0
-            }
-            _ => {
-                // Unknown provider, use conservative estimate
-                (input_tokens as f64 * 0.000001) + (output_tokens as f64 * 0.000002)
-            }
-        };
+        // Cost from the model pricing catalog
+        let cost = crate::ai::pricing().estimate(&provider, &model, input_tokens as u32, output_tokens as u32);
         
         Ok(CostEstimate {
             provider,
             model,
             input_tokens,
             output_tokens,
-            estimated_cost,
+            estimated_cost: cost.total_cost,
+            warning: cost.warning,
         })
     }
     
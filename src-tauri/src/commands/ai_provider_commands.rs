@@ -0,0 +1,200 @@
+//! AI provider configuration commands
+//!
+//! Lets writers register self-hosted, OpenAI-compatible endpoints (Ollama,
+//! llama.cpp server, LM Studio) alongside the built-in cloud providers,
+//! choose which provider serves each feature or plugin, and see which
+//! providers are currently unhealthy.
+
+use crate::ai::routing::ROUTING_TABLE_SETTING_KEY;
+use crate::ai::{AIProviderManager, OpenAICompatibleConfig, ProviderHealth, RouteTarget, RoutingTable};
+use crate::commands::CommandResponse;
+use crate::database::{get_pool, operations::{AIProviderOps, AppSettingsOps}};
+use crate::error::{Result, StoryWeaverError};
+use crate::security::api_keys::{get_api_key_manager, read_stored_api_key, ApiProvider};
+use crate::security::validation::validate_content_length;
+use crate::security::validators::{validate_id, validate_non_empty_str, validate_optional_str};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tauri::State;
+
+/// Provider names owned by the built-in integrations and the providers seeded
+/// into ai_providers
+const RESERVED_PROVIDER_NAMES: [&str; 6] = ["openai", "claude", "gemini", "anthropic", "google", "deepseek"];
+
+fn validate_base_url(base_url: &str) -> Result<()> {
+    validate_content_length(base_url, 2048)?;
+    if !(base_url.starts_with("http://") || base_url.starts_with("https://")) {
+        return Err(StoryWeaverError::input_validation(
+            "base_url",
+            "must start with http:// or https://",
+        ));
+    }
+    Ok(())
+}
+
+fn validate_config(config: &OpenAICompatibleConfig) -> Result<()> {
+    validate_id("name", &config.name, 64)?;
+    if RESERVED_PROVIDER_NAMES.contains(&config.name.to_lowercase().as_str()) {
+        return Err(StoryWeaverError::input_validation(
+            "name",
+            "is reserved for a built-in provider",
+        ));
+    }
+    validate_non_empty_str("display_name", &config.display_name, 128)?;
+    validate_base_url(&config.base_url)?;
+    validate_non_empty_str("model", &config.model, 256)?;
+    validate_optional_str("embedding_model", &config.embedding_model, 256, false)?;
+    if let Some(context_window) = config.context_window {
+        if context_window == 0 || context_window > 10_000_000 {
+            return Err(StoryWeaverError::input_validation(
+                "context_window",
+                "must be between 1 and 10,000,000 tokens",
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Save an OpenAI-compatible provider. The API key, if any, goes to the OS keychain.
+/// The provider is registered with the AI provider manager right away, replacing
+/// the one saved before under the same name.
+#[tauri::command]
+pub async fn save_openai_compatible_provider(
+    state: State<'_, Arc<AIProviderManager>>,
+    config: OpenAICompatibleConfig,
+) -> CommandResponse<i64> {
+    async fn save(providers: &AIProviderManager, mut config: OpenAICompatibleConfig) -> Result<i64> {
+        validate_config(&config)?;
+        config.base_url = config.base_url.trim_end_matches('/').to_string();
+
+        let pool = get_pool()?;
+        let provider_id = AIProviderOps::save_openai_compatible(&pool, &config).await?;
+
+        let key_ref = ApiProvider::Custom(config.name.clone());
+        match config.api_key.as_deref().filter(|k| !k.trim().is_empty()) {
+            Some(api_key) => {
+                let manager = get_api_key_manager()?;
+                manager.save_api_key(key_ref, api_key).await?;
+            }
+            // Saving without a key keeps the one already in the keychain
+            None => config.api_key = read_stored_api_key(&key_ref).unwrap_or(None),
+        }
+
+        providers.replace_openai_compatible(config);
+        Ok(provider_id)
+    }
+
+    save(state.inner(), config).await.into()
+}
+
+/// List the saved OpenAI-compatible providers (API keys are never returned)
+#[tauri::command]
+pub async fn get_openai_compatible_providers() -> CommandResponse<Vec<OpenAICompatibleConfig>> {
+    async fn list() -> Result<Vec<OpenAICompatibleConfig>> {
+        let pool = get_pool()?;
+        AIProviderOps::list_openai_compatible(&pool).await
+    }
+
+    list().await.into()
+}
+
+/// Delete an OpenAI-compatible provider and its stored API key. The provider
+/// stops serving requests right away.
+#[tauri::command]
+pub async fn delete_openai_compatible_provider(
+    state: State<'_, Arc<AIProviderManager>>,
+    name: String,
+) -> CommandResponse<()> {
+    async fn delete(providers: &AIProviderManager, name: String) -> Result<()> {
+        validate_id("name", &name, 64)?;
+        if RESERVED_PROVIDER_NAMES.contains(&name.to_lowercase().as_str()) {
+            return Err(StoryWeaverError::input_validation("name", "is reserved for a built-in provider"));
+        }
+
+        let pool = get_pool()?;
+        AIProviderOps::delete_openai_compatible(&pool, &name).await?;
+        providers.unregister_provider(&name);
+
+        let manager = get_api_key_manager()?;
+        manager.delete_api_key(ApiProvider::Custom(name)).await
+    }
+
+    delete(state.inner(), name).await.into()
+}
+
+/// Query `GET {base_url}/models` so the settings screen can offer a model picker
+#[tauri::command]
+pub async fn discover_openai_compatible_models(
+    base_url: String,
+    api_key: Option<String>,
+) -> CommandResponse<Vec<String>> {
+    async fn discover(base_url: String, api_key: Option<String>) -> Result<Vec<String>> {
+        validate_base_url(&base_url)?;
+
+        let config = OpenAICompatibleConfig {
+            name: "discovery".to_string(),
+            display_name: "Discovery".to_string(),
+            base_url,
+            api_key,
+            model: String::new(),
+            embedding_model: None,
+            context_window: None,
+        };
+        config.into_provider().list_models().await
+    }
+
+    discover(base_url, api_key).await.into()
+}
+
+fn validate_route(field: &str, targets: &[RouteTarget]) -> Result<()> {
+    if targets.len() > 10 {
+        return Err(StoryWeaverError::input_validation(field, "at most 10 providers per route"));
+    }
+    for target in targets {
+        validate_non_empty_str(field, &target.provider, 128)?;
+        validate_optional_str(field, &target.model, 256, false)?;
+    }
+    Ok(())
+}
+
+/// Get the current feature/plugin routing table and fallback chain
+#[tauri::command]
+pub async fn get_ai_routing_table(state: State<'_, Arc<AIProviderManager>>) -> CommandResponse<RoutingTable> {
+    CommandResponse::success(state.routing_table())
+}
+
+/// Replace the routing table. It takes effect immediately and is saved to app settings.
+#[tauri::command]
+pub async fn save_ai_routing_table(
+    state: State<'_, Arc<AIProviderManager>>,
+    table: RoutingTable,
+) -> CommandResponse<()> {
+    async fn save(manager: &AIProviderManager, table: RoutingTable) -> Result<()> {
+        for (feature, targets) in &table.features {
+            validate_id("feature", feature, 64)?;
+            validate_route("features", targets)?;
+        }
+        for targets in table.plugins.values() {
+            validate_route("plugins", targets)?;
+        }
+        validate_route("fallback_chain", &table.fallback_chain)?;
+
+        let value = serde_json::to_string(&table)
+            .map_err(|e| StoryWeaverError::serialization(format!("Failed to serialize routing table: {}", e)))?;
+        let pool = get_pool()?;
+        AppSettingsOps::set_setting(&pool, ROUTING_TABLE_SETTING_KEY, &value).await?;
+
+        manager.set_routing_table(table);
+        Ok(())
+    }
+
+    save(state.inner(), table).await.into()
+}
+
+/// Get the circuit breaker health of every registered provider
+#[tauri::command]
+pub async fn get_ai_provider_health(
+    state: State<'_, Arc<AIProviderManager>>,
+) -> CommandResponse<HashMap<String, ProviderHealth>> {
+    CommandResponse::success(state.provider_health())
+}
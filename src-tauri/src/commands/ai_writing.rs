@@ -1,14 +1,19 @@
 //! AI Writing Commands for StoryWeaver
 
 use crate::error::{StoryWeaverError, Result};
-use crate::ai::{AIProviderManager, AIContext, TextStream, streaming::StreamingEnvelope};
+use crate::ai::{context_budget, AIProvider, AIProviderManager, AIContext, CancellationToken, TextChunkStream, RouteRequest, RouteTaken, ToolCallRecord, WritingFeature};
+use crate::ai::write_processor::{self, write_with_story_bible_tools, ContextBuilder, StoryBiblePriority, WriteMode};
+use crate::ai::streaming::relay_to_frontend;
+use crate::ai::tokenizer;
+use crate::ai::streaming_optimizer::{get_streaming_optimizer, StreamingOptimizer};
+use crate::database::{get_pool, DbPool};
+use crate::database::operations::{StreamingSession, StreamingSessionOps};
 use crate::security::rate_limit::{rl_create, rl_update, rl_list};
 use crate::security::validators::{validate_non_empty_str, validate_body_limits, validate_optional_str};
+use futures_util::stream::{self, StreamExt};
 use serde::{Deserialize, Serialize};
 use tauri::{Emitter, State, Window};
 use std::sync::Arc;
-use std::collections::HashMap;
-use tokio::time::{sleep, Duration};
 use chrono;
 
 /// Validate WriteSettings input
@@ -103,149 +108,239 @@ fn validate_brainstorm_settings(settings: &BrainstormSettings) -> Result<()> {
     Ok(())
 }
 
-// Placeholder structs for WriteProcessor and its dependencies
-pub struct ContextBuilder;
-impl ContextBuilder {
-    pub async fn build_write_context(&self, _document_id: i32, _cursor_position: usize, _word_count: usize) -> crate::error::Result<WriteContext> {
-        // Placeholder implementation
-        Ok(WriteContext {
-            preceding_text: "This is some preceding text.".to_string(),
-            story_summary: "A brief story summary.".to_string(),
-            ai_context: AIContext {
-                project_id: Some("placeholder_project_id".to_string()),
-                document_id: Some(_document_id.to_string()),
-                story_context: Some("A brief story context.".to_string()),
-                user_preferences: Some(HashMap::new()),
-                preceding_text: None,
-                following_text: None,
-                selected_text: None,
-                characters: None,
-                locations: None,
-                plot_threads: None,
-                writing_style: None,
-                tone: None,
-                creativity_level: None,
-                feature_type: None,
-                feature_options: None,
-                word_count_target: None,
-                genre: None,
-                key_details: None,
-            },
-        })
-    }
-}
-
-pub struct WriteContext {
-    pub preceding_text: String,
-    pub story_summary: String,
-    pub ai_context: AIContext,
-}
-
 pub struct WriteProcessor {
     ai_provider_manager: Arc<AIProviderManager>,
     context_builder: ContextBuilder,
+    db_pool: Arc<DbPool>,
 }
 
 impl WriteProcessor {
-    pub fn new(ai_provider_manager: Arc<AIProviderManager>, context_builder: ContextBuilder) -> Self {
+    pub fn new(ai_provider_manager: Arc<AIProviderManager>, db_pool: Arc<DbPool>) -> Self {
         Self {
+            context_builder: ContextBuilder::with_model(ai_provider_manager.get_model_name()),
             ai_provider_manager,
-            context_builder,
+            db_pool,
         }
     }
-    
-    pub async fn auto_write_stream(&self, document_id: i32, cursor_position: usize, _settings: WriteSettings) -> crate::error::Result<TextStream> {
-        let context = self.context_builder.build_write_context(document_id, cursor_position, 1000).await?;
-        
-        let prompt = format!(
-            "Continue this story naturally. Context: {}\n\nContinue from here:",
-            context.preceding_text
-        );
-        
-        let provider = self.ai_provider_manager.get_default_provider()
-            .ok_or_else(|| crate::error::StoryWeaverError::ai_provider("default".to_string(), "No default AI provider found".to_string()))?;
 
-        let stream = provider.generate_text_stream(&prompt, &context.ai_context).await
-            .map_err(|e| crate::error::StoryWeaverError::ai_provider("default".to_string(), e.to_string()))?;
-        
-        Ok(stream)
+    /// The text around the cursor along with the project's story summaries,
+    /// and its Story Bible unless the model looks that up through tools
+    async fn build_context(&self, document_id: i32, cursor_position: usize, settings: &WriteSettings, mode: WriteMode) -> Result<AIContext> {
+        self.context_builder
+            .build_write_context_with_settings(document_id, cursor_position, 1000, &self.db_pool, Some(&settings.context_settings(mode)))
+            .await
     }
-    
-    pub async fn guided_write_stream(&self, document_id: i32, user_prompt: &str, _settings: WriteSettings) -> crate::error::Result<TextStream> {
-        let context = self.context_builder.build_write_context(document_id, 0, 1000).await?;
-        
-        let prompt = format!(
-            "Write the next part of this story based on this direction: '{}'\n\nStory context: {}",
-            user_prompt, context.story_summary
-        );
-        
-        let provider = self.ai_provider_manager.get_default_provider()
-            .ok_or_else(|| crate::error::StoryWeaverError::ai_provider("default".to_string(), "No default AI provider found".to_string()))?;
 
-        let stream = provider.generate_text_stream(&prompt, &context.ai_context).await
-            .map_err(|e| crate::error::StoryWeaverError::ai_provider("default".to_string(), e.to_string()))?;
-        
-        Ok(stream)
+    /// Generate from `prompt` on the routed provider, running the Story Bible
+    /// tool loop when the settings turn it on
+    async fn generate(&self, request: &RouteRequest, prompt: &str, context: &AIContext, settings: &WriteSettings) -> Result<(String, Vec<ToolCallRecord>, RouteTaken)> {
+        let project_id = match context.project_id.as_deref() {
+            Some(project_id) if settings.uses_story_bible_tools() => project_id,
+            _ => {
+                let (generated_text, route) = self.ai_provider_manager
+                    .execute_routed(request, |provider| async move {
+                        provider.generate_text(prompt, context).await
+                    })
+                    .await?;
+                return Ok((generated_text, Vec::new(), route));
+            }
+        };
+
+        let db_pool = self.db_pool.as_ref();
+        let (result, route) = self.ai_provider_manager
+            .execute_routed(request, |provider| async move {
+                write_with_story_bible_tools(provider.as_ref(), prompt, context, project_id, db_pool).await
+            })
+            .await?;
+        Ok((result.text, result.tool_calls, route))
     }
 
-    pub async fn auto_write(&self, document_id: i32, cursor_position: usize, _settings: WriteSettings) -> crate::error::Result<WriteResult> { // Changed return type to Result
-        let context = self.context_builder.build_write_context(document_id, cursor_position, 1000).await?;
+    /// Stream from `prompt`. With Story Bible tools the text is only known
+    /// once the model stops looking things up, so it arrives as one chunk.
+    async fn generate_stream(&self, request: &RouteRequest, prompt: &str, context: &AIContext, settings: &WriteSettings) -> Result<(TextChunkStream, RouteTaken)> {
+        if settings.uses_story_bible_tools() {
+            let (generated_text, _, route) = self.generate(request, prompt, context, settings).await?;
+            return Ok((stream::once(async move { Ok(generated_text) }).boxed(), route));
+        }
+
+        self.ai_provider_manager
+            .execute_routed(request, |provider| async move {
+                provider.generate_text_stream(prompt, context).await
+            })
+            .await
+    }
+    
+    /// Prompt and context for continuing the story from the cursor
+    pub async fn prepare_auto_write(&self, document_id: i32, cursor_position: usize, settings: &WriteSettings) -> Result<PreparedWrite> {
+        let context = self.build_context(document_id, cursor_position, settings, WriteMode::Auto).await?;
         
         let prompt = format!(
             "Continue this story naturally. Context: {}\n\nContinue from here:",
-            context.preceding_text
+            context.preceding_text.as_deref().unwrap_or_default()
         );
         
-        let provider = self.ai_provider_manager.get_default_provider()
-            .ok_or_else(|| crate::error::StoryWeaverError::ai_provider("default".to_string(), "No default AI provider found".to_string()))?;
-
-        let generated_text = provider.generate_text(&prompt, &context.ai_context).await
-            .map_err(|e| crate::error::StoryWeaverError::ai_provider("default".to_string(), e.to_string()))?; // Map error to StoryWeaverError
-        
-        // Calculate actual credits and word count
-        let word_count = generated_text.split_whitespace().count();
-        let token_count = generated_text.len() / 4; // Rough estimate: 4 chars per token
-        let credits_used = (token_count as f32 * 0.002) as u32; // Rough cost estimate
-        
-        Ok(WriteResult {
-            generated_text,
-            credits_used,
-            word_count,
-        })
+        Ok(PreparedWrite { prompt, context })
     }
     
-    pub async fn guided_write(&self, document_id: i32, user_prompt: &str, _settings: WriteSettings) -> crate::error::Result<WriteResult> { // Changed return type to Result
-        let context = self.context_builder.build_write_context(document_id, 0, 1000).await?;
+    /// Prompt and context for writing the next part in the user's direction
+    pub async fn prepare_guided_write(&self, document_id: i32, user_prompt: &str, settings: &WriteSettings) -> Result<PreparedWrite> {
+        let context = self.build_context(document_id, 0, settings, WriteMode::Guided).await?;
         
         let prompt = format!(
             "Write the next part of this story based on this direction: '{}'\n\nStory context: {}",
-            user_prompt, context.story_summary
+            user_prompt, context.story_context.as_deref().unwrap_or_default()
         );
         
-        let provider = self.ai_provider_manager.get_default_provider()
-            .ok_or_else(|| crate::error::StoryWeaverError::ai_provider("default".to_string(), "No default AI provider found".to_string()))?;
+        Ok(PreparedWrite { prompt, context })
+    }
+    
+    /// Start streaming a prepared write from the routed provider
+    pub async fn stream_write(&self, prepared: &PreparedWrite, settings: &WriteSettings, cancellation: &CancellationToken) -> Result<(TextChunkStream, RouteTaken)> {
+        let request = RouteRequest::feature(WritingFeature::Write)
+            .with_cancellation(Some(cancellation.clone()))
+            .with_budget(context_budget(&prepared.context, &prepared.prompt));
+        self.generate_stream(&request, &prepared.prompt, &prepared.context, settings).await
+    }
+    
+    pub async fn auto_write(&self, document_id: i32, cursor_position: usize, settings: WriteSettings) -> crate::error::Result<WriteResult> { // Changed return type to Result
+        let prepared = self.prepare_auto_write(document_id, cursor_position, &settings).await?;
+        self.write(&prepared, &settings).await
+    }
+    
+    pub async fn guided_write(&self, document_id: i32, user_prompt: &str, settings: WriteSettings) -> crate::error::Result<WriteResult> { // Changed return type to Result
+        let prepared = self.prepare_guided_write(document_id, user_prompt, &settings).await?;
+        self.write(&prepared, &settings).await
+    }
 
-        let generated_text = provider.generate_text(&prompt, &context.ai_context).await
-            .map_err(|e| crate::error::StoryWeaverError::ai_provider("default".to_string(), e.to_string()))?; // Map error to StoryWeaverError
+    async fn write(&self, prepared: &PreparedWrite, settings: &WriteSettings) -> Result<WriteResult> {
+        let request = RouteRequest::feature(WritingFeature::Write).with_budget(context_budget(&prepared.context, &prepared.prompt));
+        let (generated_text, tool_calls, route) = self.generate(&request, &prepared.prompt, &prepared.context, settings).await?;
         
         // Calculate actual credits and word count
         let word_count = generated_text.split_whitespace().count();
-        let token_count = generated_text.len() / 4; // Rough estimate: 4 chars per token
+        let model = route.served_by().map(|attempt| attempt.model.as_str()).unwrap_or_default();
+        let token_count = tokenizer::count_tokens(model, &generated_text);
         let credits_used = (token_count as f32 * 0.002) as u32; // Rough cost estimate
         
         Ok(WriteResult {
             generated_text,
             credits_used,
             word_count,
+            route,
+            tool_calls,
         })
     }
 }
 
+/// A write request ready to send
+pub struct PreparedWrite {
+    pub prompt: String,
+    pub context: AIContext,
+}
+
+/// Relay a started write stream to the frontend and record how it ended in
+/// its streaming session. A stopped stream keeps the text generated so far.
+async fn finish_write_stream(
+    window: &Window,
+    optimizer: &StreamingOptimizer,
+    pool: &DbPool,
+    stream_id: &str,
+    cancellation: &CancellationToken,
+    started: Result<(TextChunkStream, RouteTaken)>,
+    failure: &str,
+) {
+    let outcome = match started {
+        Ok((chunks, route)) => {
+            if let Err(e) = StreamingSessionOps::update_metadata(pool, stream_id, &route.to_json()).await {
+                tracing::warn!("Failed to record route for stream {}: {}", stream_id, e);
+            }
+            let model = route.served_by().map(|attempt| attempt.model.clone()).unwrap_or_default();
+            relay_to_frontend(window, optimizer, stream_id, &model, chunks).await
+        }
+        // Stopped before the provider answered: still send the final `stopped` chunk
+        Err(_) if cancellation.is_cancelled() => {
+            relay_to_frontend(window, optimizer, stream_id, "", stream::empty().boxed()).await
+        }
+        Err(e) => {
+            let _ = optimizer.complete_stream(stream_id).await;
+            Err(e)
+        }
+    };
+
+    let recorded = match outcome {
+        Ok(text) if cancellation.is_cancelled() => {
+            StreamingSessionOps::mark_cancelled(pool, stream_id, &text.content, text.token_count as i32).await
+        }
+        Ok(text) => {
+            match StreamingSessionOps::update_content(pool, stream_id, &text.content, text.token_count as i32).await {
+                Ok(()) => StreamingSessionOps::update_status(pool, stream_id, "completed").await,
+                Err(e) => Err(e),
+            }
+        }
+        Err(e) => {
+            if let Err(emit_err) = window.emit("ai_stream_error", format!("{}: {}", failure, e)) {
+                eprintln!("Failed to emit stream error: {}", emit_err);
+            }
+            StreamingSessionOps::update_error(pool, stream_id, &e.to_string()).await
+        }
+    };
+    if let Err(e) = recorded {
+        tracing::warn!("Failed to update streaming session {}: {}", stream_id, e);
+    }
+}
+
+/// Track a write stream in `streaming_sessions` from the moment it starts
+async fn start_write_session(pool: &DbPool, stream_id: &str, model: &str, prepared: &PreparedWrite) -> Result<()> {
+    StreamingSessionOps::create(pool, &StreamingSession {
+        id: None,
+        session_id: stream_id.to_string(),
+        project_id: prepared.context.project_id.clone().unwrap_or_default(),
+        model_used: model.to_string(),
+        prompt: prepared.prompt.clone(),
+        status: "active".to_string(),
+        generated_content: None,
+        tokens_generated: None,
+        credits_consumed: None,
+        error_message: None,
+        metadata: None,
+        started_at: Some(chrono::Utc::now().to_rfc3339()),
+        completed_at: None,
+        created_at: None,
+        updated_at: None,
+    }).await?;
+    Ok(())
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct WriteSettings {
     pub creativity_level: u8,
     pub tone: String,
     pub key_details: String,
+    /// Let the model look up the Story Bible through tools instead of
+    /// receiving a fixed slice of it in the prompt
+    pub use_story_bible_tools: Option<bool>,
+}
+
+impl WriteSettings {
+    fn uses_story_bible_tools(&self) -> bool {
+        self.use_story_bible_tools.unwrap_or(false)
+    }
+
+    /// Settings the context builder reads: the Story Bible at its usual
+    /// size, or none of it when the model looks it up through tools
+    fn context_settings(&self, mode: WriteMode) -> write_processor::WriteSettings {
+        write_processor::WriteSettings {
+            mode,
+            creativity_level: self.creativity_level,
+            word_count_target: None,
+            tone: Some(self.tone.clone()),
+            include_key_details: Vec::new(),
+            include_story_bible: Some(true),
+            max_story_bible_tokens: Some(1000),
+            story_bible_priority: Some(StoryBiblePriority::Balanced),
+            use_story_bible_tools: self.use_story_bible_tools,
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -253,6 +348,11 @@ pub struct WriteResult {
     pub generated_text: String,
     pub credits_used: u32,
     pub word_count: usize,
+    /// Providers tried and the one that served the request
+    pub route: RouteTaken,
+    /// Story Bible lookups the model made while writing
+    #[serde(default)]
+    pub tool_calls: Vec<ToolCallRecord>,
 }
 
 
@@ -282,7 +382,7 @@ pub async fn auto_write(
     // Validate WriteSettings
     validate_write_settings(&settings)?;
     
-    let processor = WriteProcessor::new(state.inner().clone(), ContextBuilder);
+    let processor = WriteProcessor::new(state.inner().clone(), get_pool()?);
     processor.auto_write(document_id, cursor_position, settings).await
 }
 
@@ -308,7 +408,7 @@ pub async fn guided_write(
     // Validate WriteSettings
     validate_write_settings(&settings)?;
     
-    let processor = WriteProcessor::new(state.inner().clone(), ContextBuilder);
+    let processor = WriteProcessor::new(state.inner().clone(), get_pool()?);
     processor.guided_write(document_id, &user_prompt, settings).await
 }
 
@@ -332,69 +432,20 @@ pub async fn auto_write_stream(
     
     // Validate WriteSettings
     validate_write_settings(&settings)?;
-    let processor = WriteProcessor::new(state.inner().clone(), ContextBuilder);
+    let pool = get_pool()?;
+    let processor = WriteProcessor::new(state.inner().clone(), pool.clone());
+    let prepared = processor.prepare_auto_write(document_id, cursor_position, &settings).await?;
+    let optimizer = get_streaming_optimizer()?;
     let stream_id = format!("auto_write_{}_{}", document_id, chrono::Utc::now().timestamp_millis());
+    optimizer.create_stream(stream_id.clone()).await?;
+    let cancellation = optimizer.cancellation_token(&stream_id).await?;
+    start_write_session(&pool, &stream_id, state.get_model_name(), &prepared).await?;
     let stream_id_clone = stream_id.clone();
     
-    // Start streaming in a background task
+    // Relay provider deltas to the frontend as they arrive
     tokio::spawn(async move {
-        match processor.auto_write_stream(document_id, cursor_position, settings).await {
-            Ok(stream) => {
-                // Real streaming implementation
-                let mut accumulated_content = String::new();
-                let mut _token_count = 0;
-                
-                // Check if the provider supports real streaming
-                if stream.is_complete {
-                    // Provider returned complete text, simulate streaming
-                    let words: Vec<&str> = stream.content.split_whitespace().collect();
-                    
-                    for (i, word) in words.iter().enumerate() {
-                        accumulated_content.push_str(word);
-                        if i < words.len() - 1 {
-                            accumulated_content.push(' ');
-                        }
-                        
-                        _token_count = accumulated_content.len() / 4; // Rough estimate
-                        
-                        let chunk = StreamingEnvelope {
-                            content: accumulated_content.clone(),
-                            is_complete: i == words.len() - 1,
-                            token_count: _token_count as u32,
-                            stream_id: stream_id_clone.clone(),
-                        };
-                        
-                        // Emit the chunk to the frontend
-                        if let Err(e) = window.emit("ai_stream_chunk", &chunk) {
-                            eprintln!("Failed to emit stream chunk: {}", e);
-                            break;
-                        }
-                        
-                        // Add delay to simulate real streaming
-                        sleep(Duration::from_millis(100)).await;
-                    }
-                } else {
-                    // Real streaming - emit content as it arrives
-                    // This would be implemented when providers support true streaming
-                    let chunk = StreamingEnvelope {
-                        content: stream.content.clone(),
-                        is_complete: true,
-                        token_count: stream.token_count as u32,
-                        stream_id: stream_id_clone.clone(),
-                    };
-                    
-                    if let Err(e) = window.emit("ai_stream_chunk", &chunk) {
-                        eprintln!("Failed to emit stream chunk: {}", e);
-                    }
-                }
-            }
-            Err(e) => {
-                // Emit error
-                if let Err(emit_err) = window.emit("ai_stream_error", format!("Auto write stream failed: {}", e)) {
-                    eprintln!("Failed to emit stream error: {}", emit_err);
-                }
-            }
-        }
+        let started = processor.stream_write(&prepared, &settings, &cancellation).await;
+        finish_write_stream(&window, &optimizer, &pool, &stream_id_clone, &cancellation, started, "Auto write stream failed").await;
     });
     
     Ok(StreamStartResponse {
@@ -425,69 +476,20 @@ pub async fn guided_write_stream(
     
     // Validate WriteSettings
     validate_write_settings(&settings)?;
-    let processor = WriteProcessor::new(state.inner().clone(), ContextBuilder);
+    let pool = get_pool()?;
+    let processor = WriteProcessor::new(state.inner().clone(), pool.clone());
+    let prepared = processor.prepare_guided_write(document_id, &user_prompt, &settings).await?;
+    let optimizer = get_streaming_optimizer()?;
     let stream_id = format!("guided_write_{}_{}", document_id, chrono::Utc::now().timestamp_millis());
+    optimizer.create_stream(stream_id.clone()).await?;
+    let cancellation = optimizer.cancellation_token(&stream_id).await?;
+    start_write_session(&pool, &stream_id, state.get_model_name(), &prepared).await?;
     let stream_id_clone = stream_id.clone();
     
-    // Start streaming in a background task
+    // Relay provider deltas to the frontend as they arrive
     tokio::spawn(async move {
-        match processor.guided_write_stream(document_id, &user_prompt, settings).await {
-            Ok(stream) => {
-                // Real streaming implementation
-                let mut accumulated_content = String::new();
-                let mut _token_count = 0;
-                
-                // Check if the provider supports real streaming
-                if stream.is_complete {
-                    // Provider returned complete text, simulate streaming
-                    let words: Vec<&str> = stream.content.split_whitespace().collect();
-                    
-                    for (i, word) in words.iter().enumerate() {
-                        accumulated_content.push_str(word);
-                        if i < words.len() - 1 {
-                            accumulated_content.push(' ');
-                        }
-                        
-                        _token_count = accumulated_content.len() / 4; // Rough estimate
-                        
-                        let chunk = StreamingEnvelope {
-                            content: accumulated_content.clone(),
-                            is_complete: i == words.len() - 1,
-                            token_count: _token_count as u32,
-                            stream_id: stream_id_clone.clone(),
-                        };
-                        
-                        // Emit the chunk to the frontend
-                        if let Err(e) = window.emit("ai_stream_chunk", &chunk) {
-                            eprintln!("Failed to emit stream chunk: {}", e);
-                            break;
-                        }
-                        
-                        // Add delay to simulate real streaming
-                        sleep(Duration::from_millis(100)).await;
-                    }
-                } else {
-                    // Real streaming - emit content as it arrives
-                    // This would be implemented when providers support true streaming
-                    let chunk = StreamingEnvelope {
-                        content: stream.content.clone(),
-                        is_complete: true,
-                        token_count: stream.token_count as u32,
-                        stream_id: stream_id_clone.clone(),
-                    };
-                    
-                    if let Err(e) = window.emit("ai_stream_chunk", &chunk) {
-                        eprintln!("Failed to emit stream chunk: {}", e);
-                    }
-                }
-            }
-            Err(e) => {
-                // Emit error
-                if let Err(emit_err) = window.emit("ai_stream_error", format!("Guided write stream failed: {}", e)) {
-                    eprintln!("Failed to emit stream error: {}", emit_err);
-                }
-            }
-        }
+        let started = processor.stream_write(&prepared, &settings, &cancellation).await;
+        finish_write_stream(&window, &optimizer, &pool, &stream_id_clone, &cancellation, started, "Guided write stream failed").await;
     });
     
     Ok(StreamStartResponse {
@@ -496,6 +498,16 @@ pub async fn guided_write_stream(
     })
 }
 
+/// Stop a running write stream. The text generated so far is kept and a final
+/// chunk marked `stopped` is emitted.
+#[tauri::command]
+pub async fn stop_ai_stream(stream_id: String) -> Result<()> {
+    validate_non_empty_str("stream_id", &stream_id, 200)?;
+    
+    let optimizer = get_streaming_optimizer()?;
+    optimizer.request_stop(&stream_id).await
+}
+
 // Additional AI Writing Tools
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -532,23 +544,18 @@ pub async fn rewrite_text(
     
     // Validate RewriteSettings
     validate_rewrite_settings(&settings)?;
-    match state.get_default_provider() {
-        Some(provider) => {
-            let rewrite_style = match settings.style.as_str() {
-                "rephrase" => crate::ai::RewriteStyle::Rephrase,
-                "shorter" => crate::ai::RewriteStyle::Shorter,
-                "longer" => crate::ai::RewriteStyle::Longer,
-                "more_formal" => crate::ai::RewriteStyle::MoreFormal,
-                "more_casual" => crate::ai::RewriteStyle::MoreCasual,
-                "more_descriptive" => crate::ai::RewriteStyle::MoreDescriptive,
-                "simpler" => crate::ai::RewriteStyle::MoreDirect,
-                _ => crate::ai::RewriteStyle::Rephrase,
-            };
-            
-            provider.rewrite_text(&text, &rewrite_style).await.map_err(|e| StoryWeaverError::ai(e.to_string()))
-        }
-        None => Err(StoryWeaverError::ai("No AI provider available")),
-    }
+    let rewrite_style = match settings.style.as_str() {
+        "rephrase" => crate::ai::RewriteStyle::Rephrase,
+        "shorter" => crate::ai::RewriteStyle::Shorter,
+        "longer" => crate::ai::RewriteStyle::Longer,
+        "more_formal" => crate::ai::RewriteStyle::MoreFormal,
+        "more_casual" => crate::ai::RewriteStyle::MoreCasual,
+        "more_descriptive" => crate::ai::RewriteStyle::MoreDescriptive,
+        "simpler" => crate::ai::RewriteStyle::MoreDirect,
+        _ => crate::ai::RewriteStyle::Rephrase,
+    };
+    
+    state.rewrite_text(&text, &rewrite_style).await
 }
 
 #[tauri::command]
@@ -564,22 +571,17 @@ pub async fn expand_text(
     
     // Validate ExpandSettings
     validate_expand_settings(&settings)?;
-    match state.get_default_provider() {
-        Some(provider) => {
-            let mut context = crate::ai::AIContext::default();
-            context.selected_text = Some(text.clone());
-            context.creativity_level = Some(settings.creativity_level);
-            context.feature_options = Some({
-                let mut options = std::collections::HashMap::new();
-                options.insert("focus".to_string(), settings.focus);
-                options.insert("length_multiplier".to_string(), settings.length_multiplier.to_string());
-                options
-            });
-            
-            provider.expand_text(&text, &context).await.map_err(|e| StoryWeaverError::ai(e.to_string()))
-        }
-        None => Err(StoryWeaverError::ai("No AI provider available")),
-    }
+    let mut context = crate::ai::AIContext::default();
+    context.selected_text = Some(text.clone());
+    context.creativity_level = Some(settings.creativity_level);
+    context.feature_options = Some({
+        let mut options = std::collections::HashMap::new();
+        options.insert("focus".to_string(), settings.focus);
+        options.insert("length_multiplier".to_string(), settings.length_multiplier.to_string());
+        options
+    });
+    
+    state.expand_text(&text, &context).await
 }
 
 #[tauri::command]
@@ -597,22 +599,17 @@ pub async fn describe_scene(
         validate_optional_str("focus", &focus, 100, false)?;
     }
     
-    match state.get_default_provider() {
-        Some(provider) => {
-            let mut context = crate::ai::AIContext::default();
-            context.selected_text = Some(text.clone());
-            if let Some(focus_val) = focus {
-                context.feature_options = Some({
-                    let mut options = std::collections::HashMap::new();
-                    options.insert("focus".to_string(), focus_val);
-                    options
-                });
-            }
-            
-            provider.describe_scene(&text, &context).await.map_err(|e| StoryWeaverError::ai(e.to_string()))
-        }
-        None => Err(StoryWeaverError::ai("No AI provider available")),
+    let mut context = crate::ai::AIContext::default();
+    context.selected_text = Some(text.clone());
+    if let Some(focus_val) = focus {
+        context.feature_options = Some({
+            let mut options = std::collections::HashMap::new();
+            options.insert("focus".to_string(), focus_val);
+            options
+        });
     }
+    
+    state.describe_scene(&text, &context).await
 }
 
 #[tauri::command]
@@ -628,21 +625,16 @@ pub async fn brainstorm_ideas(
     
     // Validate BrainstormSettings
     validate_brainstorm_settings(&settings)?;
-    match state.get_default_provider() {
-        Some(provider) => {
-            let mut context = crate::ai::AIContext::default();
-            context.creativity_level = Some(settings.creativity_level);
-            context.feature_options = Some({
-                let mut options = std::collections::HashMap::new();
-                options.insert("category".to_string(), settings.category);
-                options.insert("count".to_string(), settings.count.to_string());
-                options
-            });
-            
-            provider.brainstorm(&prompt, &context).await.map_err(|e| StoryWeaverError::ai(e.to_string()))
-        }
-        None => Err(StoryWeaverError::ai("No AI provider available")),
-    }
+    let mut context = crate::ai::AIContext::default();
+    context.creativity_level = Some(settings.creativity_level);
+    context.feature_options = Some({
+        let mut options = std::collections::HashMap::new();
+        options.insert("category".to_string(), settings.category);
+        options.insert("count".to_string(), settings.count.to_string());
+        options
+    });
+    
+    state.brainstorm(&prompt, &context).await
 }
 
 #[tauri::command]
@@ -655,12 +647,7 @@ pub async fn visualize_scene(
     // Input validation
     validate_non_empty_str("description", &description, 5_000)?;
     
-    match state.get_default_provider() {
-        Some(provider) => {
-            provider.generate_image(&description).await.map_err(|e| StoryWeaverError::ai(e.to_string()))
-        }
-        None => Err(StoryWeaverError::ai("No AI provider available")),
-    }
+    state.generate_image(&description).await
 }
 
 #[tauri::command]
@@ -675,12 +662,7 @@ pub async fn quick_edit(
     validate_non_empty_str("text", &text, 10_000)?;
     validate_non_empty_str("instruction", &instruction, 1_000)?;
     
-    match state.get_default_provider() {
-        Some(provider) => {
-            provider.quick_edit(&text, &instruction).await.map_err(|e| StoryWeaverError::ai(e.to_string()))
-        }
-        None => Err(StoryWeaverError::ai("No AI provider available")),
-    }
+    state.quick_edit(&text, &instruction).await
 }
 
 #[tauri::command]
@@ -698,17 +680,12 @@ pub async fn quick_chat(
         validate_optional_str("context", &context, 10_000, true)?;
     }
     
-    match state.get_default_provider() {
-        Some(provider) => {
-            let mut ai_context = crate::ai::AIContext::default();
-            if let Some(ctx) = context {
-                ai_context.story_context = Some(ctx);
-            }
-            
-            provider.quick_chat(&message, &ai_context).await.map_err(|e| StoryWeaverError::ai(e.to_string()))
-        }
-        None => Err(StoryWeaverError::ai("No AI provider available")),
+    let mut ai_context = crate::ai::AIContext::default();
+    if let Some(ctx) = context {
+        ai_context.story_context = Some(ctx);
     }
+    
+    state.quick_chat(&message, &ai_context).await
 }
 
 #[tauri::command]
@@ -733,7 +710,7 @@ pub async fn tone_shift_write(
     // Validate WriteSettings
     validate_write_settings(&settings)?;
     
-    let processor = WriteProcessor::new(state.inner().clone(), ContextBuilder);
+    let processor = WriteProcessor::new(state.inner().clone(), get_pool()?);
     
     // Create modified settings with the specified tone
     let mut tone_settings = settings;
@@ -757,15 +734,10 @@ pub async fn get_related_words(
         validate_optional_str("context", &context, 5000, true)?;
     }
     
-    match state.get_default_provider() {
-        Some(provider) => {
-            let mut ai_context = crate::ai::AIContext::default();
-            if let Some(ctx) = context {
-                ai_context.preceding_text = Some(ctx);
-            }
-            
-            provider.related_words(&word, &ai_context).await.map_err(|e| StoryWeaverError::ai(e.to_string()))
-        }
-        None => Err(StoryWeaverError::ai("No AI provider available")),
+    let mut ai_context = crate::ai::AIContext::default();
+    if let Some(ctx) = context {
+        ai_context.preceding_text = Some(ctx);
     }
+    
+    state.related_words(&word, &ai_context).await
 }
@@ -51,6 +51,8 @@ pub async fn create_background_task(
         "export" => TaskType::Export,
         "import" => TaskType::Import,
         "backup" => TaskType::Backup,
+        "chapter_draft" => TaskType::ChapterDraft,
+        "story_bible_extraction" => TaskType::StoryBibleExtraction,
         _ => TaskType::Other(task_type),
     };
     
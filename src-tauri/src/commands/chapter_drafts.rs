@@ -0,0 +1,117 @@
+//! Whole-chapter draft command handlers
+
+use crate::ai::create_chapter_draft;
+use crate::background::{BackgroundTaskManager, Task, TaskPriority, TaskStatus, TaskType};
+use crate::commands::CommandResponse;
+use crate::database::get_pool;
+use crate::database::operations::{ChapterDraft, ChapterDraftOps, ChapterDraftStatus, DraftedScene};
+use crate::error::{Result, StoryWeaverError};
+use crate::security::rate_limit::{rl_create, rl_list, rl_update};
+use crate::security::validators::{validate_id, validate_optional_id};
+use serde::Serialize;
+use std::sync::Arc;
+use tauri::State;
+
+/// A chapter draft with the scenes drafted so far
+#[derive(Debug, Serialize)]
+pub struct ChapterDraftDetail {
+    pub draft: ChapterDraft,
+    pub scenes: Vec<DraftedScene>,
+}
+
+/// Queue the background task that drafts (or goes on drafting) the chapter
+async fn enqueue_draft(task_manager: &BackgroundTaskManager, draft: &ChapterDraft) -> Result<ChapterDraft> {
+    let task = Task::new(
+        TaskType::ChapterDraft,
+        format!("Draft chapter from outline {}", draft.outline_id),
+        TaskPriority::Normal,
+        true,
+        Some(draft.project_id.clone()),
+        draft.document_id.clone(),
+        Some(serde_json::json!({ "draft_id": draft.id })),
+    );
+    let task_id = task_manager.enqueue_task(task).await?;
+
+    let pool = get_pool()?;
+    ChapterDraftOps::assign_task(&pool, &draft.id, &task_id).await?;
+    ChapterDraftOps::get(&pool, &draft.id).await
+}
+
+/// Start drafting an outline's chapter in the background. The draft is saved
+/// as a new version of `document_id`, or as a new chapter document.
+#[tauri::command]
+pub async fn start_chapter_draft(
+    outline_id: String,
+    document_id: Option<String>,
+    task_manager: State<'_, Arc<BackgroundTaskManager>>,
+) -> CommandResponse<ChapterDraft> {
+    async fn start(outline_id: String, document_id: Option<String>, task_manager: Arc<BackgroundTaskManager>) -> Result<ChapterDraft> {
+        rl_create("chapter_draft", Some(&outline_id))?;
+        validate_id("outline_id", &outline_id, 64)?;
+        validate_optional_id("document_id", &document_id, 64)?;
+
+        let pool = get_pool()?;
+        let draft = create_chapter_draft(&pool, &outline_id, document_id).await?;
+        enqueue_draft(&task_manager, &draft).await
+    }
+
+    start(outline_id, document_id, task_manager.inner().clone()).await.into()
+}
+
+/// Resume a draft that failed or was cancelled, at its next undrafted scene
+#[tauri::command]
+pub async fn resume_chapter_draft(
+    draft_id: String,
+    task_manager: State<'_, Arc<BackgroundTaskManager>>,
+) -> CommandResponse<ChapterDraft> {
+    async fn resume(draft_id: String, task_manager: Arc<BackgroundTaskManager>) -> Result<ChapterDraft> {
+        rl_update("chapter_draft", Some(&draft_id))?;
+        validate_id("draft_id", &draft_id, 64)?;
+
+        let pool = get_pool()?;
+        let draft = ChapterDraftOps::get(&pool, &draft_id).await?;
+        if draft.status == ChapterDraftStatus::Completed {
+            return Err(StoryWeaverError::validation("This chapter draft is already complete"));
+        }
+        if let Some(task_id) = &draft.task_id {
+            if let Some(task) = task_manager.get_task(task_id).await {
+                if matches!(task.lock().await.status, TaskStatus::Queued | TaskStatus::Running) {
+                    return Err(StoryWeaverError::validation("This chapter is still being drafted"));
+                }
+            }
+        }
+
+        enqueue_draft(&task_manager, &draft).await
+    }
+
+    resume(draft_id, task_manager.inner().clone()).await.into()
+}
+
+#[tauri::command]
+pub async fn get_chapter_draft(draft_id: String) -> CommandResponse<ChapterDraftDetail> {
+    async fn get(draft_id: String) -> Result<ChapterDraftDetail> {
+        rl_list("chapter_draft", Some(&draft_id))?;
+        validate_id("draft_id", &draft_id, 64)?;
+
+        let pool = get_pool()?;
+        Ok(ChapterDraftDetail {
+            draft: ChapterDraftOps::get(&pool, &draft_id).await?,
+            scenes: ChapterDraftOps::get_scenes(&pool, &draft_id).await?,
+        })
+    }
+
+    get(draft_id).await.into()
+}
+
+#[tauri::command]
+pub async fn list_chapter_drafts(outline_id: String) -> CommandResponse<Vec<ChapterDraft>> {
+    async fn list(outline_id: String) -> Result<Vec<ChapterDraft>> {
+        rl_list("chapter_draft", Some(&outline_id))?;
+        validate_id("outline_id", &outline_id, 64)?;
+
+        let pool = get_pool()?;
+        ChapterDraftOps::get_by_outline(&pool, &outline_id).await
+    }
+
+    list(outline_id).await.into()
+}
@@ -0,0 +1,263 @@
+//! Character relationship graph command handlers
+
+use crate::commands::CommandResponse;
+use crate::database::get_pool;
+use crate::database::operations::{
+    CharacterRelationship, CharacterRelationshipOps, RelationshipGraph, RelationshipPathStep, RelationshipType,
+};
+use crate::error::{Result, StoryWeaverError};
+use crate::security::rate_limit::{rl_create, rl_delete, rl_list, rl_update};
+use crate::security::validators::{validate_id, validate_optional_id, validate_optional_str};
+use serde::Deserialize;
+
+/// Create relationship request
+#[derive(Debug, Deserialize)]
+pub struct CreateRelationshipRequest {
+    pub project_id: String,
+    pub from_character_id: String,
+    pub to_character_id: String,
+    pub relationship_type: RelationshipType,
+    pub label: Option<String>,
+    pub description: Option<String>,
+    /// Defaults to whether the type usually holds both ways
+    pub mutual: Option<bool>,
+    pub starts_in_chapter_id: Option<String>,
+    pub ends_in_chapter_id: Option<String>,
+}
+
+/// Update relationship request; fields left out keep their value
+#[derive(Debug, Deserialize)]
+pub struct UpdateRelationshipRequest {
+    pub id: String,
+    pub relationship_type: Option<RelationshipType>,
+    pub label: Option<String>,
+    pub description: Option<String>,
+    pub mutual: Option<bool>,
+    pub starts_in_chapter_id: Option<String>,
+    pub ends_in_chapter_id: Option<String>,
+}
+
+fn validate_text_fields(label: &Option<String>, description: &Option<String>) -> Result<()> {
+    validate_optional_str("label", label, 200, true)?;
+    validate_optional_str("description", description, 5000, true)?;
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn create_character_relationship(request: CreateRelationshipRequest) -> CommandResponse<CharacterRelationship> {
+    async fn create(request: CreateRelationshipRequest) -> Result<CharacterRelationship> {
+        rl_create("character_relationship", Some(&request.project_id))?;
+        validate_id("project_id", &request.project_id, 64)?;
+        validate_id("from_character_id", &request.from_character_id, 64)?;
+        validate_id("to_character_id", &request.to_character_id, 64)?;
+        validate_optional_id("starts_in_chapter_id", &request.starts_in_chapter_id, 64)?;
+        validate_optional_id("ends_in_chapter_id", &request.ends_in_chapter_id, 64)?;
+        validate_text_fields(&request.label, &request.description)?;
+
+        let mut relationship = CharacterRelationship::new(
+            &request.project_id,
+            &request.from_character_id,
+            &request.to_character_id,
+            request.relationship_type,
+        );
+        relationship.label = request.label;
+        relationship.description = request.description;
+        if let Some(mutual) = request.mutual {
+            relationship.mutual = mutual;
+        }
+        relationship.starts_in_chapter_id = request.starts_in_chapter_id;
+        relationship.ends_in_chapter_id = request.ends_in_chapter_id;
+
+        let pool = get_pool()?;
+        CharacterRelationshipOps::create(&pool, relationship).await
+    }
+
+    create(request).await.into()
+}
+
+#[tauri::command]
+pub async fn update_character_relationship(request: UpdateRelationshipRequest) -> CommandResponse<CharacterRelationship> {
+    async fn update(request: UpdateRelationshipRequest) -> Result<CharacterRelationship> {
+        rl_update("character_relationship", Some(&request.id))?;
+        validate_id("id", &request.id, 64)?;
+        validate_optional_id("starts_in_chapter_id", &request.starts_in_chapter_id, 64)?;
+        validate_optional_id("ends_in_chapter_id", &request.ends_in_chapter_id, 64)?;
+        validate_text_fields(&request.label, &request.description)?;
+
+        let pool = get_pool()?;
+        let mut relationship = CharacterRelationshipOps::get(&pool, &request.id).await?;
+        if let Some(relationship_type) = request.relationship_type {
+            relationship.relationship_type = relationship_type;
+        }
+        if let Some(label) = request.label {
+            relationship.label = Some(label).filter(|label| !label.trim().is_empty());
+        }
+        if let Some(description) = request.description {
+            relationship.description = Some(description);
+        }
+        if let Some(mutual) = request.mutual {
+            relationship.mutual = mutual;
+        }
+        if let Some(chapter_id) = request.starts_in_chapter_id {
+            relationship.starts_in_chapter_id = Some(chapter_id);
+        }
+        if let Some(chapter_id) = request.ends_in_chapter_id {
+            relationship.ends_in_chapter_id = Some(chapter_id);
+        }
+
+        CharacterRelationshipOps::update(&pool, &relationship).await?;
+        CharacterRelationshipOps::get(&pool, &relationship.id).await
+    }
+
+    update(request).await.into()
+}
+
+/// Record that a relationship turns into another in `chapter_id`, e.g. allies
+/// who become rivals. Returns the relationship it becomes.
+#[tauri::command]
+pub async fn change_character_relationship(
+    id: String,
+    chapter_id: String,
+    relationship_type: RelationshipType,
+    label: Option<String>,
+    description: Option<String>,
+) -> CommandResponse<CharacterRelationship> {
+    async fn change(
+        id: String,
+        chapter_id: String,
+        relationship_type: RelationshipType,
+        label: Option<String>,
+        description: Option<String>,
+    ) -> Result<CharacterRelationship> {
+        rl_update("character_relationship", Some(&id))?;
+        validate_id("id", &id, 64)?;
+        validate_id("chapter_id", &chapter_id, 64)?;
+        validate_text_fields(&label, &description)?;
+
+        let pool = get_pool()?;
+        CharacterRelationshipOps::change(&pool, &id, &chapter_id, relationship_type, label, description).await
+    }
+
+    change(id, chapter_id, relationship_type, label, description).await.into()
+}
+
+#[tauri::command]
+pub async fn delete_character_relationship(id: String) -> CommandResponse<()> {
+    async fn delete(id: String) -> Result<()> {
+        rl_delete("character_relationship", Some(&id))?;
+        validate_id("id", &id, 64)?;
+
+        let pool = get_pool()?;
+        CharacterRelationshipOps::delete(&pool, &id).await
+    }
+
+    delete(id).await.into()
+}
+
+/// Relationships in a project holding at `document_id` (a chapter, or a scene
+/// in one), or at the end of the story when it's left out
+#[tauri::command]
+pub async fn get_character_relationships(
+    project_id: String,
+    document_id: Option<String>,
+) -> CommandResponse<Vec<CharacterRelationship>> {
+    async fn get(project_id: String, document_id: Option<String>) -> Result<Vec<CharacterRelationship>> {
+        rl_list("character_relationships", Some(&project_id))?;
+        validate_id("project_id", &project_id, 64)?;
+        validate_optional_id("document_id", &document_id, 64)?;
+
+        let pool = get_pool()?;
+        CharacterRelationshipOps::active_at(&pool, &project_id, document_id.as_deref()).await
+    }
+
+    get(project_id, document_id).await.into()
+}
+
+/// A character's relationships holding at `document_id`
+#[tauri::command]
+pub async fn get_relationships_for_character(
+    project_id: String,
+    character_id: String,
+    document_id: Option<String>,
+) -> CommandResponse<Vec<CharacterRelationship>> {
+    async fn get(project_id: String, character_id: String, document_id: Option<String>) -> Result<Vec<CharacterRelationship>> {
+        rl_list("character_relationships", Some(&character_id))?;
+        validate_id("project_id", &project_id, 64)?;
+        validate_id("character_id", &character_id, 64)?;
+        validate_optional_id("document_id", &document_id, 64)?;
+
+        let pool = get_pool()?;
+        CharacterRelationshipOps::for_character_at(&pool, &project_id, &character_id, document_id.as_deref()).await
+    }
+
+    get(project_id, character_id, document_id).await.into()
+}
+
+/// Shortest chain of relationships between two characters at `document_id`;
+/// `None` when they aren't connected
+#[tauri::command]
+pub async fn find_relationship_path(
+    project_id: String,
+    from_character_id: String,
+    to_character_id: String,
+    document_id: Option<String>,
+) -> CommandResponse<Option<Vec<RelationshipPathStep>>> {
+    async fn find(
+        project_id: String,
+        from_character_id: String,
+        to_character_id: String,
+        document_id: Option<String>,
+    ) -> Result<Option<Vec<RelationshipPathStep>>> {
+        rl_list("relationship_path", Some(&project_id))?;
+        validate_id("project_id", &project_id, 64)?;
+        validate_id("from_character_id", &from_character_id, 64)?;
+        validate_id("to_character_id", &to_character_id, 64)?;
+        validate_optional_id("document_id", &document_id, 64)?;
+
+        let pool = get_pool()?;
+        CharacterRelationshipOps::find_path(&pool, &project_id, &from_character_id, &to_character_id, document_id.as_deref())
+            .await
+    }
+
+    find(project_id, from_character_id, to_character_id, document_id).await.into()
+}
+
+/// The relationship graph at `document_id` for the canvas
+#[tauri::command]
+pub async fn get_relationship_graph(project_id: String, document_id: Option<String>) -> CommandResponse<RelationshipGraph> {
+    async fn get(project_id: String, document_id: Option<String>) -> Result<RelationshipGraph> {
+        rl_list("relationship_graph", Some(&project_id))?;
+        validate_id("project_id", &project_id, 64)?;
+        validate_optional_id("document_id", &document_id, 64)?;
+
+        let pool = get_pool()?;
+        CharacterRelationshipOps::graph(&pool, &project_id, document_id.as_deref()).await
+    }
+
+    get(project_id, document_id).await.into()
+}
+
+/// Export the relationship graph at `document_id` as `"dot"` (GraphViz) or
+/// `"json"`
+#[tauri::command]
+pub async fn export_relationship_graph(
+    project_id: String,
+    format: String,
+    document_id: Option<String>,
+) -> CommandResponse<String> {
+    async fn export(project_id: String, format: String, document_id: Option<String>) -> Result<String> {
+        rl_list("relationship_graph", Some(&project_id))?;
+        validate_id("project_id", &project_id, 64)?;
+        validate_optional_id("document_id", &document_id, 64)?;
+
+        let pool = get_pool()?;
+        let graph = CharacterRelationshipOps::graph(&pool, &project_id, document_id.as_deref()).await?;
+        match format.as_str() {
+            "dot" => Ok(graph.to_dot()),
+            "json" => graph.to_json(),
+            other => Err(StoryWeaverError::validation(format!("Unknown graph format: {}", other))),
+        }
+    }
+
+    export(project_id, format, document_id).await.into()
+}
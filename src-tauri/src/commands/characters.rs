@@ -1,6 +1,7 @@
 //! Character command handlers
 
 use crate::commands::CommandResponse;
+use crate::ai::semantic_index::{schedule_index, schedule_removal, SemanticSource, SemanticSourceType};
 use crate::database::{get_pool, models::*, operations::CharacterOps};
 use crate::error::Result;
 use crate::security::validation::{
@@ -118,7 +119,9 @@ pub async fn create_character(request: CreateCharacterRequest) -> CommandRespons
             character.visibility = visibility;
         }
         
-        CharacterOps::create(&pool, character).await
+        let character = CharacterOps::create(&pool, character).await?;
+        schedule_index(SemanticSource::Character(character.clone()));
+        Ok(character)
     }
     
     create(request).await.into()
@@ -271,7 +274,9 @@ pub async fn update_character(request: UpdateCharacterRequest) -> CommandRespons
             character.metadata = metadata;
         }
         
-        CharacterOps::update(&pool, &character).await
+        CharacterOps::update(&pool, &character).await?;
+        schedule_index(SemanticSource::Character(character));
+        Ok(())
     }
     
     update(request).await.into()
@@ -287,7 +292,9 @@ pub async fn delete_character(id: String) -> CommandResponse<()> {
         validate_security_input(&id)?;
         
         let pool = get_pool()?;
-        CharacterOps::delete(&pool, &id).await
+        CharacterOps::delete(&pool, &id).await?;
+        schedule_removal(SemanticSourceType::Character, id);
+        Ok(())
     }
     
     delete(id).await.into()
@@ -368,74 +375,6 @@ pub async fn get_character_summaries(project_id: String) -> CommandResponse<Vec<
     get_summaries(project_id).await.into()
 }
 
-/// Character relationship mapping
-#[derive(Debug, Serialize)]
-pub struct CharacterRelationship {
-    pub from_character_id: String,
-    pub from_character_name: String,
-    pub to_character_id: String,
-    pub to_character_name: String,
-    pub relationship_type: String,
-    pub description: Option<String>,
-}
-
-/// Get character relationships for a project
-#[tauri::command]
-pub async fn get_character_relationships(project_id: String) -> CommandResponse<Vec<CharacterRelationship>> {
-    async fn get_relationships(project_id: String) -> Result<Vec<CharacterRelationship>> {
-        // Rate limiting
-        rl_list("character_relationships", Some(&project_id))?;
-        // Input validation
-        validate_security_input(&project_id.to_string())?;
-        
-        let pool = get_pool()?;
-        let characters = CharacterOps::get_by_project(&pool, &project_id).await?;
-        
-        let mut relationships = Vec::new();
-        
-        // Simple relationship extraction from relationships field
-        // In a more advanced implementation, this would be a separate table
-        for character in &characters {
-            let relationships_text = &character.relationships;
-            if !relationships_text.is_empty() {
-                // Parse relationships (simplified - assumes format like "friend of John, enemy of Jane")
-                for other_character in &characters {
-                    if other_character.id != character.id {
-                        let other_name = &other_character.name;
-                        if relationships_text.to_lowercase().contains(&other_name.to_lowercase()) {
-                            // Extract relationship type (very basic)
-                            let relationship_type = if relationships_text.to_lowercase().contains("friend") {
-                                "friend".to_string()
-                            } else if relationships_text.to_lowercase().contains("enemy") {
-                                "enemy".to_string()
-                            } else if relationships_text.to_lowercase().contains("family") {
-                                "family".to_string()
-                            } else if relationships_text.to_lowercase().contains("romantic") {
-                                "romantic".to_string()
-                            } else {
-                                "acquaintance".to_string()
-                            };
-                            
-                            relationships.push(CharacterRelationship {
-                                from_character_id: character.id.clone(),
-                                from_character_name: character.name.clone(),
-                                to_character_id: other_character.id.clone(),
-                                to_character_name: other_character.name.clone(),
-                                relationship_type,
-                                description: Some(relationships_text.clone()),
-                            });
-                        }
-                    }
-                }
-            }
-        }
-        
-        Ok(relationships)
-    }
-    
-    get_relationships(project_id).await.into()
-}
-
 /// Character statistics
 #[derive(Debug, Serialize)]
 pub struct CharacterStats {
@@ -0,0 +1,172 @@
+//! Quick Chat thread command handlers
+
+use crate::ai::chat_memory::{self, ChatTurn};
+use crate::ai::AIProviderManager;
+use crate::commands::CommandResponse;
+use crate::database::get_pool;
+use crate::database::models::Document;
+use crate::database::operations::{ChatThread, ChatThreadMessage, ChatThreadOps, PinnedReference};
+use crate::error::Result;
+use crate::security::rate_limit::{rl_create, rl_delete, rl_list, rl_update};
+use crate::security::validators::{validate_body_limits, validate_id, validate_non_empty_str, validate_optional_id, validate_optional_str};
+use std::sync::Arc;
+use tauri::State;
+
+/// Longest chat message accepted
+const MAX_MESSAGE_BYTES: usize = 20_000;
+/// Most story bible elements pinned to one thread
+const MAX_PINS: usize = 50;
+
+/// Start a chat thread in a project, optionally tied to one document
+#[tauri::command]
+pub async fn create_chat_thread(
+    project_id: String,
+    document_id: Option<String>,
+    title: Option<String>,
+) -> CommandResponse<ChatThread> {
+    async fn create(project_id: String, document_id: Option<String>, title: Option<String>) -> Result<ChatThread> {
+        rl_create("chat_thread", Some(&project_id))?;
+        validate_id("project_id", &project_id, 64)?;
+        validate_optional_id("document_id", &document_id, 64)?;
+        validate_optional_str("title", &title, 255, false)?;
+
+        let pool = get_pool()?;
+        let title = title.unwrap_or_else(|| "New chat".to_string());
+        ChatThreadOps::create(&pool, ChatThread::new(project_id, document_id, title)).await
+    }
+
+    create(project_id, document_id, title).await.into()
+}
+
+/// A project's chat threads, most recently active first. With `document_id`
+/// only that document's threads are returned.
+#[tauri::command]
+pub async fn list_chat_threads(project_id: String, document_id: Option<String>) -> CommandResponse<Vec<ChatThread>> {
+    async fn list(project_id: String, document_id: Option<String>) -> Result<Vec<ChatThread>> {
+        rl_list("chat_threads", Some(&project_id))?;
+        validate_id("project_id", &project_id, 64)?;
+        validate_optional_id("document_id", &document_id, 64)?;
+
+        let pool = get_pool()?;
+        ChatThreadOps::get_by_project(&pool, &project_id, document_id.as_deref()).await
+    }
+
+    list(project_id, document_id).await.into()
+}
+
+#[tauri::command]
+pub async fn get_chat_messages(thread_id: String) -> CommandResponse<Vec<ChatThreadMessage>> {
+    async fn get(thread_id: String) -> Result<Vec<ChatThreadMessage>> {
+        rl_list("chat_messages", Some(&thread_id))?;
+        validate_id("thread_id", &thread_id, 64)?;
+
+        let pool = get_pool()?;
+        ChatThreadOps::get_messages(&pool, &thread_id).await
+    }
+
+    get(thread_id).await.into()
+}
+
+/// Send a message to a thread and return the stored turn
+#[tauri::command]
+pub async fn send_chat_message(
+    thread_id: String,
+    message: String,
+    ai_manager: State<'_, Arc<AIProviderManager>>,
+) -> CommandResponse<ChatTurn> {
+    async fn send(thread_id: String, message: String, ai_manager: Arc<AIProviderManager>) -> Result<ChatTurn> {
+        rl_create("chat_message", Some(&thread_id))?;
+        validate_id("thread_id", &thread_id, 64)?;
+        validate_non_empty_str("message", &message, MAX_MESSAGE_BYTES)?;
+        validate_body_limits("message", &message, MAX_MESSAGE_BYTES, MAX_MESSAGE_BYTES)?;
+
+        let pool = get_pool()?;
+        chat_memory::send_message(&pool, ai_manager.as_ref(), &thread_id, &message).await
+    }
+
+    send(thread_id, message, ai_manager.inner().clone()).await.into()
+}
+
+/// Fork a thread at `message_id`; the new thread keeps the messages up to and
+/// including it
+#[tauri::command]
+pub async fn branch_chat_thread(thread_id: String, message_id: String, title: Option<String>) -> CommandResponse<ChatThread> {
+    async fn branch(thread_id: String, message_id: String, title: Option<String>) -> Result<ChatThread> {
+        rl_create("chat_thread", Some(&thread_id))?;
+        validate_id("thread_id", &thread_id, 64)?;
+        validate_id("message_id", &message_id, 64)?;
+        validate_optional_str("title", &title, 255, false)?;
+
+        let pool = get_pool()?;
+        ChatThreadOps::branch(&pool, &thread_id, &message_id, title.as_deref()).await
+    }
+
+    branch(thread_id, message_id, title).await.into()
+}
+
+/// Replace the story bible elements sent with every message in a thread
+#[tauri::command]
+pub async fn set_chat_thread_pins(thread_id: String, pins: Vec<PinnedReference>) -> CommandResponse<ChatThread> {
+    async fn set(thread_id: String, pins: Vec<PinnedReference>) -> Result<ChatThread> {
+        rl_update("chat_thread", Some(&thread_id))?;
+        validate_id("thread_id", &thread_id, 64)?;
+        if pins.len() > MAX_PINS {
+            return Err(crate::error::StoryWeaverError::validation(format!(
+                "A chat thread can pin at most {} story bible elements",
+                MAX_PINS
+            )));
+        }
+        for pin in &pins {
+            validate_id("element_id", &pin.element_id, 64)?;
+        }
+
+        let pool = get_pool()?;
+        ChatThreadOps::set_pinned_references(&pool, &thread_id, &pins).await?;
+        ChatThreadOps::get_by_id(&pool, &thread_id).await
+    }
+
+    set(thread_id, pins).await.into()
+}
+
+#[tauri::command]
+pub async fn rename_chat_thread(thread_id: String, title: String) -> CommandResponse<()> {
+    async fn rename(thread_id: String, title: String) -> Result<()> {
+        rl_update("chat_thread", Some(&thread_id))?;
+        validate_id("thread_id", &thread_id, 64)?;
+        validate_non_empty_str("title", &title, 255)?;
+
+        let pool = get_pool()?;
+        ChatThreadOps::rename(&pool, &thread_id, title.trim()).await
+    }
+
+    rename(thread_id, title).await.into()
+}
+
+/// Delete a thread and its messages; branches made from it are kept
+#[tauri::command]
+pub async fn delete_chat_thread(thread_id: String) -> CommandResponse<()> {
+    async fn delete(thread_id: String) -> Result<()> {
+        rl_delete("chat_thread", Some(&thread_id))?;
+        validate_id("thread_id", &thread_id, 64)?;
+
+        let pool = get_pool()?;
+        ChatThreadOps::delete(&pool, &thread_id).await
+    }
+
+    delete(thread_id).await.into()
+}
+
+/// Save a thread's transcript as a notes document in its project
+#[tauri::command]
+pub async fn export_chat_thread(thread_id: String, title: Option<String>) -> CommandResponse<Document> {
+    async fn export(thread_id: String, title: Option<String>) -> Result<Document> {
+        rl_create("document", Some(&thread_id))?;
+        validate_id("thread_id", &thread_id, 64)?;
+        validate_optional_str("title", &title, 255, false)?;
+
+        let pool = get_pool()?;
+        chat_memory::export_to_document(&pool, &thread_id, title.as_deref()).await
+    }
+
+    export(thread_id, title).await.into()
+}
@@ -0,0 +1,35 @@
+//! Manuscript continuity command handlers
+
+use crate::ai::{AIProvider, AIProviderManager};
+use crate::commands::CommandResponse;
+use crate::continuity::{check_continuity, ContinuityMode, ContinuityReport};
+use crate::database::get_pool;
+use crate::error::Result;
+use crate::security::rate_limit::rl_list;
+use crate::security::validators::validate_id;
+use std::sync::Arc;
+use tauri::State;
+
+/// Check a project's chapters and scenes against its story bible. The rules
+/// mode is the default; `ai_assisted` also has the AI read each document,
+/// which costs a request per chapter.
+#[tauri::command]
+pub async fn check_manuscript_continuity(
+    project_id: String,
+    mode: Option<ContinuityMode>,
+    ai_manager: State<'_, Arc<AIProviderManager>>,
+) -> CommandResponse<ContinuityReport> {
+    async fn check(project_id: String, mode: ContinuityMode, ai_manager: Arc<AIProviderManager>) -> Result<ContinuityReport> {
+        rl_list("manuscript_continuity", Some(&project_id))?;
+        validate_id("project_id", &project_id, 64)?;
+
+        let pool = get_pool()?;
+        let provider: Option<&dyn AIProvider> = match mode {
+            ContinuityMode::Rules => None,
+            ContinuityMode::AiAssisted => Some(ai_manager.as_ref()),
+        };
+        check_continuity(&pool, &project_id, provider).await
+    }
+
+    check(project_id, mode.unwrap_or(ContinuityMode::Rules), ai_manager.inner().clone()).await.into()
+}
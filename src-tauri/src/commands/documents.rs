@@ -1,6 +1,8 @@
 //! Document command handlers
 
 use crate::commands::CommandResponse;
+use crate::ai::semantic_index::{schedule_index, schedule_removal, SemanticSource, SemanticSourceType};
+use crate::ai::story_summary::schedule_summary_refresh;
 use crate::database::{get_pool, models::*, operations::DocumentOps};
 use crate::error::Result;
 use crate::security::validation::{
@@ -80,7 +82,10 @@ pub async fn create_document(request: CreateDocumentRequest) -> CommandResponse<
         }
         document.parent_id = request.parent_id;
         
-        DocumentOps::create(&pool, document).await
+        let document = DocumentOps::create(&pool, document).await?;
+        schedule_index(SemanticSource::Document(document.clone()));
+        schedule_summary_refresh(document.project_id.clone());
+        Ok(document)
     }
     
     create(request).await.into()
@@ -174,7 +179,10 @@ pub async fn update_document(request: UpdateDocumentRequest) -> CommandResponse<
             document.metadata = metadata;
         }
         
-        DocumentOps::update(&pool, &document).await
+        DocumentOps::update(&pool, &document).await?;
+        schedule_summary_refresh(document.project_id.clone());
+        schedule_index(SemanticSource::Document(document));
+        Ok(())
     }
     
     update(request).await.into()
@@ -200,7 +208,10 @@ pub async fn save_document(id: String, content: String) -> CommandResponse<()> {
         // Update content
         document.content = content;
         
-        DocumentOps::update(&pool, &document).await
+        DocumentOps::update(&pool, &document).await?;
+        schedule_summary_refresh(document.project_id.clone());
+        schedule_index(SemanticSource::Document(document));
+        Ok(())
     }
     
     save(id, content).await.into()
@@ -216,7 +227,13 @@ pub async fn delete_document(id: String) -> CommandResponse<()> {
         validate_id("document_id", &id, 64)?;
         
         let pool = get_pool()?;
-        DocumentOps::delete(&pool, &id).await
+        let document = DocumentOps::get_by_id(&pool, &id).await?;
+        DocumentOps::delete(&pool, &id).await?;
+        if let Some(document) = document {
+            schedule_summary_refresh(document.project_id);
+        }
+        schedule_removal(SemanticSourceType::Document, id);
+        Ok(())
     }
     
     delete(id).await.into()
@@ -1,6 +1,7 @@
 //! Location command handlers
 
 use crate::commands::CommandResponse;
+use crate::ai::semantic_index::{schedule_index, schedule_removal, SemanticSource, SemanticSourceType};
 use crate::database::{get_pool, models::*, operations::LocationOps};
 use crate::error::Result;
 use crate::security::validation::*;
@@ -116,7 +117,9 @@ pub async fn create_location(request: CreateLocationRequest) -> CommandResponse<
             location.visibility = visibility;
         }
         
-        LocationOps::create(&pool, location).await
+        let location = LocationOps::create(&pool, location).await?;
+        schedule_index(SemanticSource::Location(location.clone()));
+        Ok(location)
     }
     
     create(request).await.into()
@@ -266,7 +269,9 @@ pub async fn update_location(request: UpdateLocationRequest) -> CommandResponse<
             location.metadata = metadata;
         }
         
-        LocationOps::update(&pool, &location).await
+        LocationOps::update(&pool, &location).await?;
+        schedule_index(SemanticSource::Location(location));
+        Ok(())
     }
     
     update(request).await.into()
@@ -282,7 +287,9 @@ pub async fn delete_location(id: String) -> CommandResponse<()> {
         validate_security_input(&id)?;
         
         let pool = get_pool()?;
-        LocationOps::delete(&pool, &id).await
+        LocationOps::delete(&pool, &id).await?;
+        schedule_removal(SemanticSourceType::Location, id);
+        Ok(())
     }
     
     delete(id).await.into()
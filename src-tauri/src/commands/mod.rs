@@ -9,6 +9,7 @@ use serde::{Deserialize, Serialize};
 pub mod projects;
 pub mod documents;
 pub mod characters;
+pub mod character_relationships;
 pub mod locations;
 pub mod story_bible;
 pub mod story_bible_ai;
@@ -28,11 +29,22 @@ pub mod register_commands;
 pub mod background_commands;
 pub mod performance_commands;
 pub mod security_commands;
+pub mod ai_provider_commands;
 pub mod project_preview_commands;
 pub mod series_consistency_commands;
 pub mod templates;
 pub mod advanced_ai_commands;
 pub mod guided_suggestions;
+pub mod semantic_search;
+pub mod chat_threads;
+pub mod prompt_templates;
+pub mod ai_budgets;
+pub mod model_pricing;
+pub mod chapter_drafts;
+pub mod story_summaries;
+pub mod timeline;
+pub mod continuity;
+pub mod story_bible_extraction;
 
 // Phase 5 Collaboration & Plugins
 pub mod collaboration;
@@ -0,0 +1,132 @@
+//! Model pricing catalog command handlers
+
+use crate::ai::{recompute_history_costs, reload_pricing, HistoryCostRecompute};
+use crate::commands::CommandResponse;
+use crate::database::get_pool;
+use crate::database::operations::{ModelPrice, ModelPricingOps};
+use crate::error::{Result, StoryWeaverError};
+use crate::security::rate_limit::{rl_create, rl_delete, rl_list, rl_update};
+use crate::security::validators::{validate_body_limits, validate_id, validate_optional_str};
+use chrono::NaiveDate;
+use serde::Deserialize;
+
+/// Largest pricing catalog accepted for import
+const MAX_IMPORT_BYTES: usize = 1_000_000;
+
+#[derive(Debug, Deserialize)]
+pub struct SetModelPriceRequest {
+    /// Provider the price applies to; omit for the model under any provider
+    pub provider: Option<String>,
+    /// Model name, or `*` for every model of the provider
+    pub model: String,
+    pub input_cost_per_1k: f64,
+    pub output_cost_per_1k: f64,
+    pub cached_input_cost_per_1k: Option<f64>,
+    pub image_cost: Option<f64>,
+    pub currency: Option<String>,
+    pub effective_from: NaiveDate,
+}
+
+#[tauri::command]
+pub async fn list_model_prices() -> CommandResponse<Vec<ModelPrice>> {
+    async fn list() -> Result<Vec<ModelPrice>> {
+        rl_list("model_pricing", None)?;
+        let pool = get_pool()?;
+        ModelPricingOps::list(&pool).await
+    }
+
+    list().await.into()
+}
+
+/// Add a price, or change the one for the same provider, model and date
+#[tauri::command]
+pub async fn set_model_price(request: SetModelPriceRequest) -> CommandResponse<ModelPrice> {
+    async fn set(request: SetModelPriceRequest) -> Result<ModelPrice> {
+        rl_create("model_price", None)?;
+        validate_optional_str("provider", &request.provider, 100, false)?;
+        validate_optional_str("currency", &request.currency, 8, false)?;
+
+        let mut price = ModelPrice::new(
+            request.model.trim(),
+            request.input_cost_per_1k,
+            request.output_cost_per_1k,
+            request.effective_from,
+        );
+        price.provider = request.provider;
+        price.cached_input_cost_per_1k = request.cached_input_cost_per_1k;
+        price.image_cost = request.image_cost;
+        if let Some(currency) = request.currency {
+            price.currency = currency;
+        }
+
+        let pool = get_pool()?;
+        let price = ModelPricingOps::upsert(&pool, &price).await?;
+        reload_pricing(&pool).await?;
+        Ok(price)
+    }
+
+    set(request).await.into()
+}
+
+#[tauri::command]
+pub async fn delete_model_price(id: String) -> CommandResponse<()> {
+    async fn delete(id: String) -> Result<()> {
+        rl_delete("model_price", None)?;
+        validate_id("id", &id, 64)?;
+        let pool = get_pool()?;
+        ModelPricingOps::delete(&pool, &id).await?;
+        reload_pricing(&pool).await
+    }
+
+    delete(id).await.into()
+}
+
+/// The whole catalog as JSON, for sharing or backup
+#[tauri::command]
+pub async fn export_model_pricing() -> CommandResponse<String> {
+    async fn export() -> Result<String> {
+        rl_list("model_pricing", None)?;
+        let pool = get_pool()?;
+        let prices = ModelPricingOps::list(&pool).await?;
+        serde_json::to_string_pretty(&prices)
+            .map_err(|e| StoryWeaverError::serialization(format!("Failed to export model pricing: {}", e)))
+    }
+
+    export().await.into()
+}
+
+/// Import prices exported by `export_model_pricing`. With `replace`, the
+/// catalog is cleared first; missing built-in prices are seeded again.
+#[tauri::command]
+pub async fn import_model_pricing(json: String, replace: Option<bool>) -> CommandResponse<usize> {
+    async fn import(json: String, replace: bool) -> Result<usize> {
+        rl_update("model_pricing", None)?;
+        validate_body_limits("json", &json, MAX_IMPORT_BYTES, MAX_IMPORT_BYTES)?;
+
+        let mut prices: Vec<ModelPrice> = serde_json::from_str(&json)
+            .map_err(|e| StoryWeaverError::validation(format!("Invalid model pricing JSON: {}", e)))?;
+        for price in &mut prices {
+            price.source = "import".to_string();
+        }
+
+        let pool = get_pool()?;
+        let imported = ModelPricingOps::import(&pool, &prices, replace).await?;
+        reload_pricing(&pool).await?;
+        Ok(imported)
+    }
+
+    import(json, replace.unwrap_or(false)).await.into()
+}
+
+/// Recompute the cost of a project's past generations with the current catalog
+#[tauri::command]
+pub async fn recompute_history_costs_for_project(project_id: String) -> CommandResponse<HistoryCostRecompute> {
+    async fn recompute(project_id: String) -> Result<HistoryCostRecompute> {
+        rl_update("ai_history", Some(&project_id))?;
+        validate_id("project_id", &project_id, 64)?;
+        let pool = get_pool()?;
+        recompute_history_costs(&pool, &project_id, &crate::ai::pricing()).await
+    }
+
+    recompute(project_id).await.into()
+}
@@ -13,7 +13,10 @@ use serde_json::Value;
 use std::str::FromStr;
 use tauri::State;
 use std::sync::Arc;
-use crate::ai::{AIProviderManager, AIContext};
+use crate::ai::{context_budget, tokenizer, AIProviderManager, AIContext, RouteRequest};
+use crate::ai::token_counter::pricing;
+use crate::database::models::{AIGenerationHistory, AIGenerationType};
+use crate::database::operations::{AIHistoryOps, DocumentOps};
 
 /// Create a new plugin
 #[tauri::command]
@@ -292,30 +295,38 @@ pub async fn execute_plugin_command(
     crate::security::validation::validate_content_length(&prompt, 20000)?;
     crate::security::validation::validate_security_input(&prompt)?;
 
+    // Budgets and AI history are per project, so a run against a document counts toward its project
+    let document = match document_id {
+        Some(doc_id) => DocumentOps::get_by_id(&pool, &doc_id.to_string()).await?,
+        None => None,
+    };
+
     // Build AI context
     let mut ai_ctx = AIContext::default();
+    ai_ctx.project_id = document.as_ref().map(|document| document.project_id.clone());
     ai_ctx.document_id = document_id.map(|d| d.to_string());
     ai_ctx.selected_text = selected_text.clone();
 
-    // Execute via default AI provider (model routing can be added later)
-    let provider = state
-        .get_default_provider()
-        .ok_or_else(|| StoryWeaverError::ai("No AI provider available"))?;
-
+    // Execute via the plugin's route (its `ai_model` or routing table entry),
+    // failing over along the fallback chain
     let start = std::time::Instant::now();
-    let generated = provider
-        .generate_text(&prompt, &ai_ctx)
-        .await
-        .map_err(|e| StoryWeaverError::ai(e.to_string()))?;
+    let route_request = RouteRequest::plugin(plugin.id, &plugin.ai_model).with_budget(context_budget(&ai_ctx, &prompt));
+    let (prompt_ref, ctx_ref) = (prompt.as_str(), &ai_ctx);
+    let (generated, route) = state
+        .execute_routed(&route_request, |provider| async move {
+            provider.generate_text(prompt_ref, ctx_ref).await
+        })
+        .await?;
 
     let elapsed_ms = start.elapsed().as_millis() as i64;
-    let token_estimate = (generated.len() as f32 / 4.0) as i32;
+    let served_model = route.served_by().map(|attempt| attempt.model.as_str()).unwrap_or_default();
+    let token_estimate = tokenizer::count_tokens(served_model, &generated) as i32;
 
     let result = PluginExecutionResult {
         success: true,
         result_text: Some(generated.clone()),
         error_message: None,
-        credits_used: token_estimate, // simple estimate; can wire to real credit tracking later
+        credits_used: token_estimate, // the priced spend is recorded against the AI budgets
         execution_time_ms: elapsed_ms,
         stage_results: None,
     };
@@ -330,6 +341,36 @@ pub async fn execute_plugin_command(
     };
     let recorded = plugin_ops::record_plugin_execution(&pool, request, result.clone()).await?;
 
+    if let Some(document) = document {
+        let (provider, model) = route
+            .served_by()
+            .map(|attempt| (attempt.provider.clone(), attempt.model.clone()))
+            .unwrap_or_default();
+        let input_tokens = tokenizer::count_tokens(&model, &prompt) as u32;
+        let cost = pricing().estimate(&provider, &model, input_tokens, token_estimate as u32);
+        let template = route.prompt_template.clone();
+        let history = AIGenerationHistory {
+            id: String::new(),
+            project_id: document.project_id,
+            document_id: Some(document.id),
+            generation_type: AIGenerationType::Plugin,
+            provider,
+            model,
+            prompt,
+            response: generated,
+            token_count: token_estimate,
+            cost_estimate: Some(cost.total_cost),
+            context_used: serde_json::json!({ "plugin_id": plugin_id }).to_string(),
+            route_taken: Some(route.to_json()),
+            prompt_template: template.as_ref().map(|t| t.name.clone()),
+            prompt_template_version: template.map(|t| t.version),
+            created_at: chrono::Utc::now(),
+        };
+        if let Err(e) = AIHistoryOps::create(&pool, history).await {
+            tracing::warn!("Failed to record plugin generation history: {}", e);
+        }
+    }
+
     Ok(recorded)
 }
 
@@ -0,0 +1,202 @@
+//! Prompt template command handlers
+
+use crate::ai::prompt_templates::{builtin_templates, check_template, reload_prompt_registry, PromptRegistry};
+use crate::ai::{PromptScope, RenderedPrompt};
+use crate::commands::CommandResponse;
+use crate::database::get_pool;
+use crate::database::operations::{PromptTemplate, PromptTemplateOps, TemplateVariable, FULL_TRAFFIC_WEIGHT};
+use crate::error::{Result, StoryWeaverError};
+use crate::security::rate_limit::{rl_create, rl_list, rl_update};
+use crate::security::validators::{validate_body_limits, validate_id, validate_optional_id, validate_optional_str};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Longest template body accepted
+const MAX_TEMPLATE_BYTES: usize = 50_000;
+
+#[derive(Debug, Deserialize)]
+pub struct CreatePromptTemplateRequest {
+    pub name: String,
+    pub body: String,
+    /// Defaults to the variables of the template's latest version
+    pub variables: Option<Vec<TemplateVariable>>,
+    pub description: Option<String>,
+    pub project_id: Option<String>,
+    pub prose_mode: Option<String>,
+    /// Start sending traffic to the new version right away (default true)
+    pub activate: Option<bool>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TemplateTrafficWeight {
+    pub id: String,
+    pub weight: i64,
+}
+
+/// Every stored version, newest first; with `name`, only that template's
+#[tauri::command]
+pub async fn list_prompt_templates(name: Option<String>) -> CommandResponse<Vec<PromptTemplate>> {
+    async fn list(name: Option<String>) -> Result<Vec<PromptTemplate>> {
+        rl_list("prompt_templates", None)?;
+        validate_optional_str("name", &name, 100, false)?;
+
+        let pool = get_pool()?;
+        // Make sure the built-ins are listed even before the first render
+        reload_prompt_registry(&pool).await?;
+        PromptTemplateOps::get_versions(&pool, name.as_deref()).await
+    }
+
+    list(name).await.into()
+}
+
+/// Save a new version of a template, checked to parse and to only use
+/// declared variables
+#[tauri::command]
+pub async fn create_prompt_template_version(request: CreatePromptTemplateRequest) -> CommandResponse<PromptTemplate> {
+    async fn create(request: CreatePromptTemplateRequest) -> Result<PromptTemplate> {
+        rl_create("prompt_template", request.project_id.as_deref())?;
+        validate_body_limits("body", &request.body, MAX_TEMPLATE_BYTES, MAX_TEMPLATE_BYTES)?;
+        validate_optional_str("description", &request.description, 1000, true)?;
+        validate_optional_id("project_id", &request.project_id, 64)?;
+        validate_optional_str("prose_mode", &request.prose_mode, 100, false)?;
+
+        if !builtin_templates().iter().any(|t| t.name == request.name) {
+            return Err(StoryWeaverError::not_found("prompt template", request.name.as_str()));
+        }
+
+        let pool = get_pool()?;
+        let variables = match request.variables {
+            Some(variables) => variables,
+            None => PromptTemplateOps::get_versions(&pool, Some(&request.name))
+                .await?
+                .into_iter()
+                .next()
+                .map(|latest| latest.variables)
+                .or_else(|| builtin_templates().into_iter().find(|t| t.name == request.name).map(|t| t.variables))
+                .unwrap_or_default(),
+        };
+        check_template(&request.body, &variables)?;
+
+        let mut template = PromptTemplate::new(&request.name, &request.body, variables);
+        template.description = request.description;
+        template.project_id = request.project_id;
+        template.prose_mode = request.prose_mode;
+        if !request.activate.unwrap_or(true) {
+            template.traffic_weight = 0;
+        }
+
+        // Seed the built-ins first so they keep version 1
+        reload_prompt_registry(&pool).await?;
+        let template = PromptTemplateOps::create_version(&pool, template).await?;
+        reload_prompt_registry(&pool).await?;
+        Ok(template)
+    }
+
+    create(request).await.into()
+}
+
+/// Send all of a scope's traffic to one version, e.g. to roll back a change
+#[tauri::command]
+pub async fn activate_prompt_template_version(id: String) -> CommandResponse<PromptTemplate> {
+    async fn activate(id: String) -> Result<PromptTemplate> {
+        rl_update("prompt_template", None)?;
+        validate_id("id", &id, 64)?;
+
+        let pool = get_pool()?;
+        let template = PromptTemplateOps::activate(&pool, &id).await?;
+        reload_prompt_registry(&pool).await?;
+        Ok(template)
+    }
+
+    activate(id).await.into()
+}
+
+/// Split a scope's traffic between versions of one template for an A/B test
+#[tauri::command]
+pub async fn set_prompt_template_weights(weights: Vec<TemplateTrafficWeight>) -> CommandResponse<()> {
+    async fn set(weights: Vec<TemplateTrafficWeight>) -> Result<()> {
+        rl_update("prompt_template", None)?;
+        if weights.is_empty() || weights.len() > 10 {
+            return Err(StoryWeaverError::validation("Traffic can be split between 1 and 10 versions"));
+        }
+        for weight in &weights {
+            validate_id("id", &weight.id, 64)?;
+            if !(0..=FULL_TRAFFIC_WEIGHT).contains(&weight.weight) {
+                return Err(StoryWeaverError::validation(format!(
+                    "Traffic weights must be between 0 and {}",
+                    FULL_TRAFFIC_WEIGHT
+                )));
+            }
+        }
+
+        let pool = get_pool()?;
+        let weights: Vec<(String, i64)> = weights.into_iter().map(|w| (w.id, w.weight)).collect();
+        PromptTemplateOps::set_traffic_weights(&pool, &weights).await?;
+        reload_prompt_registry(&pool).await
+    }
+
+    set(weights).await.into()
+}
+
+/// Stop a project or prose mode override so the default applies again
+#[tauri::command]
+pub async fn deactivate_prompt_template_version(id: String) -> CommandResponse<()> {
+    async fn deactivate(id: String) -> Result<()> {
+        rl_update("prompt_template", None)?;
+        validate_id("id", &id, 64)?;
+
+        let pool = get_pool()?;
+        PromptTemplateOps::deactivate(&pool, &id).await?;
+        reload_prompt_registry(&pool).await
+    }
+
+    deactivate(id).await.into()
+}
+
+/// Render the version a scope would use, or an unsaved `body`, with sample values
+#[tauri::command]
+pub async fn preview_prompt_template(
+    name: String,
+    values: Value,
+    project_id: Option<String>,
+    prose_mode: Option<String>,
+    body: Option<String>,
+) -> CommandResponse<RenderedPrompt> {
+    async fn preview(
+        name: String,
+        values: Value,
+        project_id: Option<String>,
+        prose_mode: Option<String>,
+        body: Option<String>,
+    ) -> Result<RenderedPrompt> {
+        rl_list("prompt_templates", project_id.as_deref())?;
+        validate_optional_id("project_id", &project_id, 64)?;
+        validate_optional_str("prose_mode", &prose_mode, 100, false)?;
+
+        let pool = get_pool()?;
+        let registry = PromptRegistry::load(&pool).await?;
+        let scope = PromptScope { project_id, prose_mode };
+        let Some(body) = body else {
+            return registry.render(&name, &scope, &values);
+        };
+
+        validate_body_limits("body", &body, MAX_TEMPLATE_BYTES, MAX_TEMPLATE_BYTES)?;
+        let current = registry
+            .resolve(&name, &scope)
+            .cloned()
+            .or_else(|| builtin_templates().into_iter().find(|t| t.name == name))
+            .ok_or_else(|| StoryWeaverError::not_found("prompt template", name.as_str()))?;
+        check_template(&body, &current.variables)?;
+        let draft = PromptTemplate {
+            body,
+            version: 0,
+            project_id: None,
+            prose_mode: None,
+            traffic_weight: FULL_TRAFFIC_WEIGHT,
+            ..current
+        };
+        PromptRegistry::from_templates(vec![draft]).render(&name, &PromptScope::global(), &values)
+    }
+
+    preview(name, values, project_id, prose_mode, body).await.into()
+}
@@ -46,13 +46,25 @@ pub struct PrivacySettingsResponse {
     pub settings: PrivacySettings,
 }
 
+/// Map a provider identifier from the frontend to an `ApiProvider`.
+/// Custom OpenAI-compatible providers are addressed as `custom:<name>`.
+fn parse_api_provider(provider: &str) -> Option<ApiProvider> {
+    match provider {
+        "openai" => Some(ApiProvider::OpenAI),
+        "claude" => Some(ApiProvider::Claude),
+        other => other
+            .strip_prefix("custom:")
+            .filter(|name| !name.is_empty())
+            .map(|name| ApiProvider::Custom(name.to_string())),
+    }
+}
+
 /// Save an API key to secure storage
 #[command]
 pub async fn save_api_key(request: SaveApiKeyRequest) -> Result<ApiKeyResponse, StoryWeaverError> {
-    let provider = match request.provider.as_str() {
-        "openai" => ApiProvider::OpenAI,
-        "claude" => ApiProvider::Claude,
-        _ => return Ok(ApiKeyResponse {
+    let provider = match parse_api_provider(&request.provider) {
+        Some(provider) => provider,
+        None => return Ok(ApiKeyResponse {
             success: false,
             error: Some(format!("Unsupported API provider: {}", request.provider)),
         }),
@@ -63,10 +75,7 @@ pub async fn save_api_key(request: SaveApiKeyRequest) -> Result<ApiKeyResponse,
     match manager.save_api_key(provider.clone(), &request.api_key).await {
         Ok(_) => {
             // Log the API key change event
-            let provider_str = match provider {
-                ApiProvider::OpenAI => "OpenAI",
-                ApiProvider::Claude => "Claude",
-            };
+            let provider_str = provider.display_name();
             
             let _ = log_api_key_event(
                 "api_key_saved",
@@ -89,10 +98,9 @@ pub async fn save_api_key(request: SaveApiKeyRequest) -> Result<ApiKeyResponse,
 /// Check if an API key exists
 #[command]
 pub async fn has_api_key(provider: String) -> Result<ApiKeyExistsResponse, StoryWeaverError> {
-    let provider = match provider.as_str() {
-        "openai" => ApiProvider::OpenAI,
-        "claude" => ApiProvider::Claude,
-        _ => return Ok(ApiKeyExistsResponse { exists: false }),
+    let provider = match parse_api_provider(&provider) {
+        Some(provider) => provider,
+        None => return Ok(ApiKeyExistsResponse { exists: false }),
     };
     
     let manager = get_api_key_manager()?;
@@ -106,10 +114,9 @@ pub async fn has_api_key(provider: String) -> Result<ApiKeyExistsResponse, Story
 /// Delete an API key
 #[command]
 pub async fn delete_api_key(provider: String) -> Result<ApiKeyResponse, StoryWeaverError> {
-    let provider = match provider.as_str() {
-        "openai" => ApiProvider::OpenAI,
-        "claude" => ApiProvider::Claude,
-        _ => return Ok(ApiKeyResponse {
+    let provider = match parse_api_provider(&provider) {
+        Some(provider) => provider,
+        None => return Ok(ApiKeyResponse {
             success: false,
             error: Some(format!("Unsupported API provider: {}", provider)),
         }),
@@ -120,10 +127,7 @@ pub async fn delete_api_key(provider: String) -> Result<ApiKeyResponse, StoryWea
     match manager.delete_api_key(provider.clone()).await {
         Ok(_) => {
             // Log the API key deletion event
-            let provider_str = match provider {
-                ApiProvider::OpenAI => "OpenAI",
-                ApiProvider::Claude => "Claude",
-            };
+            let provider_str = provider.display_name();
             
             let _ = log_api_key_event(
                 "api_key_deleted",
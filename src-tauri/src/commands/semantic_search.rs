@@ -0,0 +1,58 @@
+//! Semantic search command handlers
+
+use crate::ai::semantic_index::{get_semantic_indexer, SearchMode, SemanticSearchHit};
+use crate::commands::CommandResponse;
+use crate::database::get_pool;
+use crate::error::{Result, StoryWeaverError};
+use crate::security::rate_limit::{rl_search, rl_update};
+use crate::security::validators::{validate_body_limits, validate_id};
+
+/// Most results a single search may ask for
+const MAX_RESULTS: usize = 100;
+
+/// Search a project's documents and story bible by meaning. `mode` defaults to
+/// hybrid ranking, which blends embedding similarity with FTS5 BM25.
+#[tauri::command]
+pub async fn semantic_search(
+    project_id: String,
+    query: String,
+    k: Option<usize>,
+    mode: Option<SearchMode>,
+) -> CommandResponse<Vec<SemanticSearchHit>> {
+    async fn search(project_id: String, query: String, k: Option<usize>, mode: Option<SearchMode>) -> Result<Vec<SemanticSearchHit>> {
+        // Rate limiting
+        rl_search("semantic", Some(&project_id))?;
+        // Input validation
+        validate_id("project_id", &project_id, 64)?;
+        validate_body_limits("query", &query, 4_000, 1000)?;
+
+        if query.trim().is_empty() {
+            return Err(StoryWeaverError::ValidationError {
+                message: "Search query cannot be empty".to_string()
+            });
+        }
+
+        let k = k.unwrap_or(10).clamp(1, MAX_RESULTS);
+        let pool = get_pool()?;
+        get_semantic_indexer()?
+            .search(&pool, &project_id, &query, k, mode.unwrap_or_default())
+            .await
+    }
+
+    search(project_id, query, k, mode).await.into()
+}
+
+/// Rebuild a project's semantic index; entries whose text and embedding model
+/// are unchanged are kept. Returns the number of entries indexed.
+#[tauri::command]
+pub async fn reindex_semantic_index(project_id: String) -> CommandResponse<usize> {
+    async fn reindex(project_id: String) -> Result<usize> {
+        rl_update("semantic_index", Some(&project_id))?;
+        validate_id("project_id", &project_id, 64)?;
+
+        let pool = get_pool()?;
+        get_semantic_indexer()?.reindex_project(&pool, &project_id).await
+    }
+
+    reindex(project_id).await.into()
+}
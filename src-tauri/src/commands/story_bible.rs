@@ -1,10 +1,13 @@
 //! Story Bible command handlers
 
 use crate::commands::CommandResponse;
+use crate::ai::semantic_index::{schedule_index, schedule_removal, SemanticSource, SemanticSourceType};
 use crate::database::{get_pool, models::*, operations::*};
+use crate::detection::{detect_in_text, StoryBibleDetection};
 use crate::error::{Result, StoryWeaverError};
 use crate::security::validation::*;
 use crate::security::rate_limit::{validate_request_body_size, rl_create, rl_update, rl_delete, rl_list, rl_search};
+use crate::security::validators::{validate_id, validate_non_empty_str};
 use serde::Deserialize;
 use std::collections::HashMap;
 
@@ -351,7 +354,9 @@ pub async fn create_world_element(request: CreateWorldElementRequest) -> Command
             updated_at: chrono::Utc::now(),
         };
         
-        WorldElementOps::create(&pool, element).await
+        let element = WorldElementOps::create(&pool, element).await?;
+        schedule_index(SemanticSource::WorldElement(element.clone()));
+        Ok(element)
     }
     
     create(request).await.into()
@@ -446,7 +451,8 @@ pub async fn update_world_element(request: UpdateWorldElementRequest) -> Command
             element.is_visible = is_visible;
         }
         
-        WorldElementOps::update(&pool, element).await?;
+        let element = WorldElementOps::update(&pool, element).await?;
+        schedule_index(SemanticSource::WorldElement(element));
         Ok(())
     }
     
@@ -463,7 +469,9 @@ pub async fn delete_world_element(id: String) -> CommandResponse<()> {
         validate_security_input(&id)?;
         
         let pool = get_pool()?;
-        WorldElementOps::delete(&pool, &id).await
+        WorldElementOps::delete(&pool, &id).await?;
+        schedule_removal(SemanticSourceType::WorldElement, id);
+        Ok(())
     }
     
     delete(id).await.into()
@@ -848,7 +856,9 @@ pub async fn create_scene(request: CreateSceneRequest) -> CommandResponse<Scene>
             updated_at: chrono::Utc::now(),
         };
         
-        SceneOps::create(&pool, scene).await
+        let scene = SceneOps::create(&pool, scene).await?;
+        schedule_index(SemanticSource::Scene(scene.clone()));
+        Ok(scene)
     }
     
     create(request).await.into()
@@ -953,7 +963,9 @@ pub async fn update_scene(request: UpdateSceneRequest) -> CommandResponse<Scene>
         scene.word_count_estimate = request.word_count_estimate;
         scene.credit_estimate = request.credit_estimate;
         
-        SceneOps::update(&pool, scene).await
+        let scene = SceneOps::update(&pool, scene).await?;
+        schedule_index(SemanticSource::Scene(scene.clone()));
+        Ok(scene)
     }
     
     update(request).await.into()
@@ -969,7 +981,9 @@ pub async fn delete_scene(id: String) -> CommandResponse<()> {
         validate_security_input(&id)?;
         
         let pool = get_pool()?;
-        SceneOps::delete(&pool, &id).await
+        SceneOps::delete(&pool, &id).await?;
+        schedule_removal(SemanticSourceType::Scene, id);
+        Ok(())
     }
     
     delete(id).await.into()
@@ -1207,17 +1221,9 @@ pub struct DetectStoryBibleRequest {
     pub project_id: String,
     pub text: String,
     pub detection_types: Vec<String>, // ["characters", "locations", "world_elements"]
-}
-
-/// Story Bible detection result
-#[derive(Debug, serde::Serialize)]
-pub struct StoryBibleDetection {
-    pub detection_type: String,
-    pub entity_id: String,
-    pub entity_name: String,
-    pub start_position: usize,
-    pub end_position: usize,
-    pub confidence: f64,
+    /// Also link pronouns to the characters they most likely stand for
+    #[serde(default)]
+    pub link_pronouns: bool,
 }
 
 /// Detect Story Bible elements in text
@@ -1236,106 +1242,106 @@ pub async fn detect_story_bible_in_text(request: DetectStoryBibleRequest) -> Com
             validate_safe_name(detection_type, "Detection type")?;
         }
         
-        let pool = get_pool()?;
-        let mut detections = Vec::new();
-        
-        // Detect characters
-        if request.detection_types.contains(&"characters".to_string()) {
-            let characters = CharacterOps::get_by_project(&pool, &request.project_id).await?;
-            for character in characters {
-                if let Some(positions) = find_text_occurrences(&request.text, &character.name) {
-                    for (start, end) in positions {
-                        detections.push(StoryBibleDetection {
-                            detection_type: "character".to_string(),
-                            entity_id: character.id.clone(),
-                            entity_name: character.name.clone(),
-                            start_position: start,
-                            end_position: end,
-                            confidence: 0.9, // High confidence for exact name matches
-                        });
-                    }
-                }
-            }
-        }
-        
-        // Detect locations
-        if request.detection_types.contains(&"locations".to_string()) {
-            let locations = LocationOps::get_by_project(&pool, &request.project_id).await?;
-            for location in locations {
-                if let Some(positions) = find_text_occurrences(&request.text, &location.name) {
-                    for (start, end) in positions {
-                        detections.push(StoryBibleDetection {
-                            detection_type: "location".to_string(),
-                            entity_id: location.id.clone(),
-                            entity_name: location.name.clone(),
-                            start_position: start,
-                            end_position: end,
-                            confidence: 0.9, // High confidence for exact name matches
-                        });
-                    }
-                }
-            }
-        }
-        
-        // Detect world elements
-        if request.detection_types.contains(&"world_elements".to_string()) {
-            let world_elements = WorldElementOps::get_by_project(&pool, &request.project_id).await?;
-            for element in world_elements {
-                if let Some(positions) = find_text_occurrences(&request.text, &element.name) {
-                    for (start, end) in positions {
-                        detections.push(StoryBibleDetection {
-                            detection_type: "world_element".to_string(),
-                            entity_id: element.id.clone(),
-                            entity_name: element.name.clone(),
-                            start_position: start,
-                            end_position: end,
-                            confidence: 0.9, // High confidence for exact name matches
-                        });
-                    }
-                }
-            }
-        }
+        let entity_types: Vec<StoryBibleEntityType> = request
+            .detection_types
+            .iter()
+            .filter_map(|detection_type| match detection_type.as_str() {
+                "characters" => Some(StoryBibleEntityType::Character),
+                "locations" => Some(StoryBibleEntityType::Location),
+                "world_elements" => Some(StoryBibleEntityType::WorldElement),
+                _ => None,
+            })
+            .collect();
         
-        // Sort by position in text
-        detections.sort_by_key(|d| d.start_position);
-        
-        Ok(detections)
+        let pool = get_pool()?;
+        detect_in_text(&pool, &request.project_id, &request.text, &entity_types, request.link_pronouns).await
     }
     
     detect(request).await.into()
 }
 
-/// Helper function to find text occurrences
-fn find_text_occurrences(text: &str, search_term: &str) -> Option<Vec<(usize, usize)>> {
-    if search_term.is_empty() {
-        return None;
+// ===== STORY BIBLE ALIAS COMMANDS =====
+
+/// Add alias request
+#[derive(Debug, Deserialize)]
+pub struct AddStoryBibleAliasRequest {
+    pub project_id: String,
+    pub entity_type: StoryBibleEntityType,
+    pub entity_id: String,
+    pub alias: String,
+    pub kind: Option<AliasKind>,
+}
+
+/// Add an alias, nickname or title to a character, location or world element
+#[tauri::command]
+pub async fn add_story_bible_alias(request: AddStoryBibleAliasRequest) -> CommandResponse<StoryBibleAlias> {
+    async fn add(request: AddStoryBibleAliasRequest) -> Result<StoryBibleAlias> {
+        // Rate limiting
+        rl_create("story_bible_alias", Some(&request.project_id))?;
+        // Input validation
+        validate_id("project_id", &request.project_id, 64)?;
+        validate_id("entity_id", &request.entity_id, 64)?;
+        validate_non_empty_str("alias", &request.alias, 200)?;
+        validate_security_input(&request.alias)?;
+        
+        let alias = StoryBibleAlias::new(
+            &request.project_id,
+            request.entity_type,
+            &request.entity_id,
+            &request.alias,
+            request.kind.unwrap_or(AliasKind::Alias),
+        );
+        let pool = get_pool()?;
+        StoryBibleAliasOps::create(&pool, alias).await
     }
     
-    let mut positions = Vec::new();
-    let text_lower = text.to_lowercase();
-    let search_lower = search_term.to_lowercase();
-    
-    let mut start = 0;
-    while let Some(pos) = text_lower[start..].find(&search_lower) {
-        let actual_pos = start + pos;
-        let end_pos = actual_pos + search_term.len();
-        
-        // Check if it's a whole word (not part of another word)
-        let is_word_boundary_start = actual_pos == 0 || 
-            !text.chars().nth(actual_pos - 1).unwrap_or(' ').is_alphanumeric();
-        let is_word_boundary_end = end_pos >= text.len() || 
-            !text.chars().nth(end_pos).unwrap_or(' ').is_alphanumeric();
+    add(request).await.into()
+}
+
+/// Get every alias in a project
+#[tauri::command]
+pub async fn get_story_bible_aliases(project_id: String) -> CommandResponse<Vec<StoryBibleAlias>> {
+    async fn get(project_id: String) -> Result<Vec<StoryBibleAlias>> {
+        // Rate limiting
+        rl_list("story_bible_alias", Some(&project_id))?;
+        // Input validation
+        validate_id("project_id", &project_id, 64)?;
         
-        if is_word_boundary_start && is_word_boundary_end {
-            positions.push((actual_pos, end_pos));
-        }
+        let pool = get_pool()?;
+        StoryBibleAliasOps::get_by_project(&pool, &project_id).await
+    }
+    
+    get(project_id).await.into()
+}
+
+/// Get the aliases of one character, location or world element
+#[tauri::command]
+pub async fn get_entity_aliases(entity_type: StoryBibleEntityType, entity_id: String) -> CommandResponse<Vec<StoryBibleAlias>> {
+    async fn get(entity_type: StoryBibleEntityType, entity_id: String) -> Result<Vec<StoryBibleAlias>> {
+        // Rate limiting
+        rl_list("story_bible_alias", Some(&entity_id))?;
+        // Input validation
+        validate_id("entity_id", &entity_id, 64)?;
         
-        start = actual_pos + 1;
+        let pool = get_pool()?;
+        StoryBibleAliasOps::get_by_entity(&pool, entity_type, &entity_id).await
     }
     
-    if positions.is_empty() {
-        None
-    } else {
-        Some(positions)
+    get(entity_type, entity_id).await.into()
+}
+
+/// Delete an alias
+#[tauri::command]
+pub async fn delete_story_bible_alias(id: String) -> CommandResponse<()> {
+    async fn delete(id: String) -> Result<()> {
+        // Rate limiting
+        rl_delete("story_bible_alias", Some(&id))?;
+        // Input validation
+        validate_id("id", &id, 64)?;
+        
+        let pool = get_pool()?;
+        StoryBibleAliasOps::delete(&pool, &id).await
     }
+    
+    delete(id).await.into()
 }
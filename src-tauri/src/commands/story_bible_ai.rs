@@ -5,15 +5,13 @@ use crate::database::get_pool;
 
 use crate::database::operations::StoryBibleOps;
 use crate::database::operations::OutlineOps;
-use crate::ai::{AIProviderManager, AIProvider, AIContext, WritingFeature, TokenCounter};
+use crate::ai::{AIProviderManager, AIProvider, AIContext, WritingFeature, pricing, JsonSchema, StructuredGeneration};
+use crate::ai::{render_prompt, PromptScope, RenderedPrompt, TemplateUsage};
+use serde_json::json;
 use serde::{Deserialize, Serialize};
 use tauri::State;
 use std::collections::HashMap;
 use std::sync::Arc;
-use once_cell::sync::Lazy;
-
-// Global token counter instance
-static TOKEN_COUNTER: Lazy<TokenCounter> = Lazy::new(|| TokenCounter::new());
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct GenerateSynopsisRequest {
@@ -77,6 +75,9 @@ pub struct StyleAnalysisResponse {
     pub cost_estimate: f64,
     pub provider: String,
     pub model: String,
+    /// Prompt template version behind the request, for the AI history
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt_template: Option<TemplateUsage>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -86,6 +87,130 @@ pub struct AIGenerationResponse {
     pub cost_estimate: f64,
     pub provider: String,
     pub model: String,
+    /// Validated JSON behind `generated_content`, for schema-driven generations
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub structured_content: Option<serde_json::Value>,
+    /// Prompt template version behind the request, for the AI history
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt_template: Option<TemplateUsage>,
+}
+
+/// Structured reply for `generate_character_traits`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GeneratedTraits {
+    pub traits: Vec<String>,
+}
+
+pub fn character_traits_schema(trait_count: u32) -> JsonSchema {
+    JsonSchema::new(
+        "character_traits",
+        json!({
+            "type": "object",
+            "properties": {
+                "traits": {
+                    "type": "array",
+                    "description": "Short personality or behavioural traits, one per item",
+                    "items": { "type": "string", "minLength": 1, "maxLength": 200 },
+                    "minItems": 1,
+                    "maxItems": trait_count
+                }
+            },
+            "required": ["traits"],
+            "additionalProperties": false
+        }),
+    )
+}
+
+/// Structured reply for `generate_world_element`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GeneratedWorldElement {
+    pub name: String,
+    pub element_type: String,
+    pub description: String,
+    /// Notable facts, rules or history, one per item
+    pub details: Vec<String>,
+}
+
+impl GeneratedWorldElement {
+    fn to_markdown(&self) -> String {
+        let mut text = format!("# {}\n\n{}\n", self.name, self.description);
+        if !self.details.is_empty() {
+            text.push('\n');
+            for detail in &self.details {
+                text.push_str(&format!("- {}\n", detail));
+            }
+        }
+        text
+    }
+}
+
+pub fn world_element_schema() -> JsonSchema {
+    JsonSchema::new(
+        "world_element",
+        json!({
+            "type": "object",
+            "properties": {
+                "name": { "type": "string", "minLength": 1 },
+                "element_type": { "type": "string", "minLength": 1 },
+                "description": { "type": "string", "minLength": 1 },
+                "details": { "type": "array", "items": { "type": "string" } }
+            },
+            "required": ["name", "element_type", "description", "details"],
+            "additionalProperties": false
+        }),
+    )
+}
+
+/// Structured reply for `generate_outline_from_story_bible`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GeneratedOutline {
+    pub title: String,
+    pub chapters: Vec<GeneratedOutlineChapter>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GeneratedOutlineChapter {
+    pub chapter_number: i32,
+    pub title: String,
+    pub summary: String,
+}
+
+impl GeneratedOutline {
+    fn to_markdown(&self) -> String {
+        let mut text = format!("# {}\n", self.title);
+        for chapter in &self.chapters {
+            text.push_str(&format!("\n## Chapter {}: {}\n{}\n", chapter.chapter_number, chapter.title, chapter.summary));
+        }
+        text
+    }
+}
+
+pub fn outline_schema() -> JsonSchema {
+    JsonSchema::new(
+        "story_outline",
+        json!({
+            "type": "object",
+            "properties": {
+                "title": { "type": "string", "minLength": 1 },
+                "chapters": {
+                    "type": "array",
+                    "minItems": 1,
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "chapter_number": { "type": "integer", "minimum": 1 },
+                            "title": { "type": "string", "minLength": 1 },
+                            "summary": { "type": "string", "minLength": 1 }
+                        },
+                        "required": ["chapter_number", "title", "summary"],
+                        "additionalProperties": false
+                    }
+                }
+            },
+            "required": ["title", "chapters"],
+            "additionalProperties": false
+        }),
+    )
 }
 
 /// Generate synopsis from braindump
@@ -154,20 +279,23 @@ pub async fn generate_synopsis(
             creativity_level: request.creativity.map(|c| (c * 10.0) as u8),
             feature_type: Some(WritingFeature::Write),
             feature_options: None,
+            prose_mode: None,
             word_count_target: None,
             genre: request.genre.clone(),
             key_details: None,
+            cancellation: None,
         };
         
         // Generate synopsis
         let result = ai_manager.generate_text(&request.braindump, &context).await?;
         
         // Count tokens and estimate cost
-        let input_tokens = TOKEN_COUNTER.count_tokens(&request.braindump);
-        let output_tokens = TOKEN_COUNTER.count_tokens(&result);
+        let token_counter = pricing();
+        let input_tokens = token_counter.count_tokens_for_model(ai_manager.get_model_name(), &request.braindump);
+        let output_tokens = token_counter.count_tokens_for_model(ai_manager.get_model_name(), &result);
         let total_tokens = input_tokens + output_tokens;
         
-        let cost_estimate = TOKEN_COUNTER.estimate_cost(
+        let cost_estimate = token_counter.estimate_cost(
             ai_manager.get_provider_name(),
             ai_manager.get_model_name(),
             input_tokens,
@@ -180,6 +308,8 @@ pub async fn generate_synopsis(
             cost_estimate,
             provider: ai_manager.get_provider_name().to_string(),
             model: ai_manager.get_model_name().to_string(),
+            structured_content: None,
+            prompt_template: None,
         })
     }
     
@@ -261,26 +391,32 @@ pub async fn generate_character_traits(
             creativity_level: request.creativity.map(|c| (c * 10.0) as u8),
             feature_type: Some(WritingFeature::Write),
             feature_options: None,
+            prose_mode: None,
             word_count_target: None,
             genre: None,
             key_details: None,
+            cancellation: None,
         };
         
         // Generate traits
-        let result = ai_manager.generate_text(&format!("Generate character traits for {}", request.character_name), &context).await?;
-        
-        // Parse traits from response
-        let traits = result
-            .lines()
-            .filter_map(|line| {
-                let line = line.trim();
-                if line.is_empty() || line.starts_with('-') || line.starts_with('*') {
-                    None
-                } else {
-                    Some(line.to_string())
-                }
-            })
-            .take(request.trait_count.unwrap_or(5) as usize)
+        let trait_count = request.trait_count.unwrap_or(5);
+        let values = json!({
+            "trait_count": trait_count,
+            "character_name": request.character_name,
+            "existing_traits": request.existing_traits,
+            "custom_prompt": request.custom_prompt,
+        });
+        let prompt = render_prompt("character_traits", &PromptScope::from_context(&context), &values)?.text;
+        let generated: GeneratedTraits = ai_manager
+            .generate_structured(&prompt, &character_traits_schema(trait_count), &context)
+            .await?;
+
+        let traits = generated
+            .traits
+            .into_iter()
+            .map(|t| t.trim().to_string())
+            .filter(|t| !t.is_empty())
+            .take(trait_count as usize)
             .collect();
         
         Ok(traits)
@@ -363,21 +499,34 @@ pub async fn generate_world_element(
             creativity_level: request.creativity.map(|c| (c * 10.0) as u8),
             feature_type: Some(WritingFeature::Write),
             feature_options: None,
+            prose_mode: None,
             word_count_target: None,
             genre: None,
             key_details: None,
+            cancellation: None,
         };
         
         // Generate world element
-        let prompt = format!("Generate {}: {}", request.element_type, request.name);
-        let result = ai_manager.generate_text(&prompt, &context).await?;
+        let values = json!({
+            "element_type": request.element_type,
+            "name": request.name,
+            "existing_elements": request.existing_elements,
+            "custom_prompt": request.custom_prompt,
+        });
+        let RenderedPrompt { text: prompt, template } =
+            render_prompt("world_element", &PromptScope::from_context(&context), &values)?;
+        let element: GeneratedWorldElement = ai_manager
+            .generate_structured(&prompt, &world_element_schema(), &context)
+            .await?;
+        let result = element.to_markdown();
         
         // Count tokens and estimate cost
-        let input_tokens = TOKEN_COUNTER.count_tokens(&prompt);
-        let output_tokens = TOKEN_COUNTER.count_tokens(&result);
+        let token_counter = pricing();
+        let input_tokens = token_counter.count_tokens_for_model(ai_manager.get_model_name(), &prompt);
+        let output_tokens = token_counter.count_tokens_for_model(ai_manager.get_model_name(), &result);
         let total_tokens = input_tokens + output_tokens;
         
-        let cost_estimate = TOKEN_COUNTER.estimate_cost(
+        let cost_estimate = token_counter.estimate_cost(
             ai_manager.get_provider_name(),
             ai_manager.get_model_name(),
             input_tokens,
@@ -390,6 +539,8 @@ pub async fn generate_world_element(
             cost_estimate,
             provider: ai_manager.get_provider_name().to_string(),
             model: ai_manager.get_model_name().to_string(),
+            structured_content: serde_json::to_value(&element).ok(),
+            prompt_template: Some(template),
         })
     }
     
@@ -445,21 +596,28 @@ pub async fn generate_outline_from_story_bible(
             creativity_level: creativity.map(|c| (c * 10.0) as u8),
             feature_type: Some(WritingFeature::Write),
             feature_options: None,
+            prose_mode: None,
             word_count_target: None,
             genre: None,
             key_details: None,
+            cancellation: None,
         };
         
         // Generate outline
-        let prompt = custom_prompt.unwrap_or_else(|| "Generate a detailed story outline".to_string());
-        let result = ai_manager.generate_text(&prompt, &context).await?;
+        let RenderedPrompt { text: prompt, template } =
+            render_prompt("outline", &PromptScope::from_context(&context), &json!({ "custom_prompt": custom_prompt }))?;
+        let outline: GeneratedOutline = ai_manager
+            .generate_structured(&prompt, &outline_schema(), &context)
+            .await?;
+        let result = outline.to_markdown();
         
         // Count tokens and estimate cost
-        let input_tokens = TOKEN_COUNTER.count_tokens(&prompt);
-        let output_tokens = TOKEN_COUNTER.count_tokens(&result);
+        let token_counter = pricing();
+        let input_tokens = token_counter.count_tokens_for_model(ai_manager.get_model_name(), &prompt);
+        let output_tokens = token_counter.count_tokens_for_model(ai_manager.get_model_name(), &result);
         let total_tokens = input_tokens + output_tokens;
         
-        let cost_estimate = TOKEN_COUNTER.estimate_cost(
+        let cost_estimate = token_counter.estimate_cost(
             ai_manager.get_provider_name(),
             ai_manager.get_model_name(),
             input_tokens,
@@ -472,6 +630,8 @@ pub async fn generate_outline_from_story_bible(
             cost_estimate,
             provider: ai_manager.get_provider_name().to_string(),
             model: ai_manager.get_model_name().to_string(),
+            structured_content: serde_json::to_value(&outline).ok(),
+            prompt_template: Some(template),
         })
     }
     
@@ -545,21 +705,26 @@ pub async fn generate_scene_content(
             creativity_level: creativity.map(|c| (c * 10.0) as u8),
             feature_type: Some(WritingFeature::Write),
             feature_options: None,
+            prose_mode: None,
             word_count_target: None,
             genre: None,
             key_details: None,
+            cancellation: None,
         };
         
         // Generate scene content
-        let prompt = custom_prompt.unwrap_or_else(|| format!("Generate scene content for: {}", scene_title));
+        let values = json!({ "scene_title": scene_title, "scene_summary": scene_summary, "custom_prompt": custom_prompt });
+        let RenderedPrompt { text: prompt, template } =
+            render_prompt("scene", &PromptScope::from_context(&context), &values)?;
         let result = ai_manager.generate_text(&prompt, &context).await?;
         
         // Count tokens and estimate cost
-        let input_tokens = TOKEN_COUNTER.count_tokens(&prompt);
-        let output_tokens = TOKEN_COUNTER.count_tokens(&result);
+        let token_counter = pricing();
+        let input_tokens = token_counter.count_tokens_for_model(ai_manager.get_model_name(), &prompt);
+        let output_tokens = token_counter.count_tokens_for_model(ai_manager.get_model_name(), &result);
         let total_tokens = input_tokens + output_tokens;
         
-        let cost_estimate = TOKEN_COUNTER.estimate_cost(
+        let cost_estimate = token_counter.estimate_cost(
             ai_manager.get_provider_name(),
             ai_manager.get_model_name(),
             input_tokens,
@@ -572,6 +737,8 @@ pub async fn generate_scene_content(
             cost_estimate,
             provider: ai_manager.get_provider_name().to_string(),
             model: ai_manager.get_model_name().to_string(),
+            structured_content: None,
+            prompt_template: Some(template),
         })
     }
     
@@ -637,21 +804,28 @@ pub async fn analyze_style_example(
             creativity_level: request.creativity.map(|c| (c * 10.0) as u8),
             feature_type: Some(WritingFeature::Write),
             feature_options: None,
+            prose_mode: None,
             word_count_target: None,
             genre: None,
             key_details: None,
+            cancellation: None,
         };
         
         // Analyze style
-        let prompt = request.custom_prompt.unwrap_or_else(|| "Analyze the writing style and generate a style prompt".to_string());
+        let RenderedPrompt { text: prompt, template } = render_prompt(
+            "style_analysis",
+            &PromptScope::from_context(&context),
+            &json!({ "custom_prompt": request.custom_prompt }),
+        )?;
         let result = ai_manager.generate_text(&prompt, &context).await?;
         
         // Count tokens and estimate cost
-        let input_tokens = TOKEN_COUNTER.count_tokens(&prompt);
-        let output_tokens = TOKEN_COUNTER.count_tokens(&result);
+        let token_counter = pricing();
+        let input_tokens = token_counter.count_tokens_for_model(ai_manager.get_model_name(), &prompt);
+        let output_tokens = token_counter.count_tokens_for_model(ai_manager.get_model_name(), &result);
         let total_tokens = input_tokens + output_tokens;
         
-        let cost_estimate = TOKEN_COUNTER.estimate_cost(
+        let cost_estimate = token_counter.estimate_cost(
             ai_manager.get_provider_name(),
             ai_manager.get_model_name(),
             input_tokens,
@@ -665,6 +839,7 @@ pub async fn analyze_style_example(
             cost_estimate,
             provider: ai_manager.get_provider_name().to_string(),
             model: ai_manager.get_model_name().to_string(),
+            prompt_template: Some(template),
         })
     }
     
@@ -740,21 +915,28 @@ pub async fn generate_outline_from_text(
             creativity_level: request.creativity.map(|c| (c * 10.0) as u8),
             feature_type: Some(WritingFeature::Write),
             feature_options: None,
+            prose_mode: None,
             word_count_target: None,
             genre: None,
             key_details: None,
+            cancellation: None,
         };
         
         // Generate outline
-        let prompt = request.custom_prompt.unwrap_or_else(|| "Generate an outline from the provided text".to_string());
+        let RenderedPrompt { text: prompt, template } = render_prompt(
+            "outline_from_text",
+            &PromptScope::from_context(&context),
+            &json!({ "custom_prompt": request.custom_prompt }),
+        )?;
         let result = ai_manager.generate_text(&prompt, &context).await?;
         
         // Count tokens and estimate cost
-        let input_tokens = TOKEN_COUNTER.count_tokens(&prompt);
-        let output_tokens = TOKEN_COUNTER.count_tokens(&result);
+        let token_counter = pricing();
+        let input_tokens = token_counter.count_tokens_for_model(ai_manager.get_model_name(), &prompt);
+        let output_tokens = token_counter.count_tokens_for_model(ai_manager.get_model_name(), &result);
         let total_tokens = input_tokens + output_tokens;
         
-        let cost_estimate = TOKEN_COUNTER.estimate_cost(
+        let cost_estimate = token_counter.estimate_cost(
             ai_manager.get_provider_name(),
             ai_manager.get_model_name(),
             input_tokens,
@@ -767,6 +949,8 @@ pub async fn generate_outline_from_text(
             cost_estimate,
             provider: ai_manager.get_provider_name().to_string(),
             model: ai_manager.get_model_name().to_string(),
+            structured_content: None,
+            prompt_template: Some(template),
         })
     }
     
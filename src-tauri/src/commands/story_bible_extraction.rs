@@ -0,0 +1,149 @@
+//! Story bible extraction command handlers
+
+use crate::ai::create_story_bible_extraction;
+use crate::background::{BackgroundTaskManager, Task, TaskPriority, TaskStatus, TaskType};
+use crate::commands::CommandResponse;
+use crate::database::get_pool;
+use crate::database::operations::{ExtractionStatus, StoryBibleExtraction, StoryBibleExtractionOps, StoryBibleProposal};
+use crate::error::{Result, StoryWeaverError};
+use crate::security::rate_limit::{rl_create, rl_list, rl_update};
+use crate::security::validators::{validate_id, validate_optional_str};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tauri::State;
+
+/// An extraction with everything it has proposed so far
+#[derive(Debug, Serialize)]
+pub struct StoryBibleExtractionDetail {
+    pub extraction: StoryBibleExtraction,
+    pub proposals: Vec<StoryBibleProposal>,
+}
+
+/// The writer's decision on a proposal. `name` and `description` replace the
+/// proposal's own when it is accepted.
+#[derive(Debug, Deserialize)]
+pub struct ReviewStoryBibleProposalRequest {
+    pub proposal_id: String,
+    pub accept: bool,
+    pub name: Option<String>,
+    pub description: Option<String>,
+}
+
+/// Queue the background task that scans (or goes on scanning) the project
+async fn enqueue_extraction(
+    task_manager: &BackgroundTaskManager,
+    extraction: &StoryBibleExtraction,
+) -> Result<StoryBibleExtraction> {
+    let task = Task::new(
+        TaskType::StoryBibleExtraction,
+        format!("Extract story bible from project {}", extraction.project_id),
+        TaskPriority::Normal,
+        true,
+        Some(extraction.project_id.clone()),
+        None,
+        Some(serde_json::json!({ "extraction_id": extraction.id })),
+    );
+    let task_id = task_manager.enqueue_task(task).await?;
+
+    let pool = get_pool()?;
+    StoryBibleExtractionOps::assign_task(&pool, &extraction.id, &task_id).await?;
+    StoryBibleExtractionOps::get(&pool, &extraction.id).await
+}
+
+/// Start scanning a project's documents for story bible entries in the
+/// background
+#[tauri::command]
+pub async fn start_story_bible_extraction(
+    project_id: String,
+    task_manager: State<'_, Arc<BackgroundTaskManager>>,
+) -> CommandResponse<StoryBibleExtraction> {
+    async fn start(project_id: String, task_manager: Arc<BackgroundTaskManager>) -> Result<StoryBibleExtraction> {
+        rl_create("story_bible_extraction", Some(&project_id))?;
+        validate_id("project_id", &project_id, 64)?;
+
+        let pool = get_pool()?;
+        let extraction = create_story_bible_extraction(&pool, &project_id).await?;
+        enqueue_extraction(&task_manager, &extraction).await
+    }
+
+    start(project_id, task_manager.inner().clone()).await.into()
+}
+
+/// Resume an extraction that failed or was cancelled, at its next unscanned
+/// document
+#[tauri::command]
+pub async fn resume_story_bible_extraction(
+    extraction_id: String,
+    task_manager: State<'_, Arc<BackgroundTaskManager>>,
+) -> CommandResponse<StoryBibleExtraction> {
+    async fn resume(extraction_id: String, task_manager: Arc<BackgroundTaskManager>) -> Result<StoryBibleExtraction> {
+        rl_update("story_bible_extraction", Some(&extraction_id))?;
+        validate_id("extraction_id", &extraction_id, 64)?;
+
+        let pool = get_pool()?;
+        let extraction = StoryBibleExtractionOps::get(&pool, &extraction_id).await?;
+        if extraction.status == ExtractionStatus::Completed {
+            return Err(StoryWeaverError::validation("This story bible extraction is already complete"));
+        }
+        if let Some(task_id) = &extraction.task_id {
+            if let Some(task) = task_manager.get_task(task_id).await {
+                if matches!(task.lock().await.status, TaskStatus::Queued | TaskStatus::Running) {
+                    return Err(StoryWeaverError::validation("This project is still being scanned"));
+                }
+            }
+        }
+
+        enqueue_extraction(&task_manager, &extraction).await
+    }
+
+    resume(extraction_id, task_manager.inner().clone()).await.into()
+}
+
+#[tauri::command]
+pub async fn get_story_bible_extraction(extraction_id: String) -> CommandResponse<StoryBibleExtractionDetail> {
+    async fn get(extraction_id: String) -> Result<StoryBibleExtractionDetail> {
+        rl_list("story_bible_extraction", Some(&extraction_id))?;
+        validate_id("extraction_id", &extraction_id, 64)?;
+
+        let pool = get_pool()?;
+        Ok(StoryBibleExtractionDetail {
+            extraction: StoryBibleExtractionOps::get(&pool, &extraction_id).await?,
+            proposals: StoryBibleExtractionOps::get_proposals(&pool, &extraction_id).await?,
+        })
+    }
+
+    get(extraction_id).await.into()
+}
+
+#[tauri::command]
+pub async fn list_story_bible_extractions(project_id: String) -> CommandResponse<Vec<StoryBibleExtraction>> {
+    async fn list(project_id: String) -> Result<Vec<StoryBibleExtraction>> {
+        rl_list("story_bible_extraction", Some(&project_id))?;
+        validate_id("project_id", &project_id, 64)?;
+
+        let pool = get_pool()?;
+        StoryBibleExtractionOps::get_by_project(&pool, &project_id).await
+    }
+
+    list(project_id).await.into()
+}
+
+/// Accept a proposal, writing it to the story bible, or reject it
+#[tauri::command]
+pub async fn review_story_bible_proposal(request: ReviewStoryBibleProposalRequest) -> CommandResponse<StoryBibleProposal> {
+    async fn review(request: ReviewStoryBibleProposalRequest) -> Result<StoryBibleProposal> {
+        rl_update("story_bible_proposal", Some(&request.proposal_id))?;
+        validate_id("proposal_id", &request.proposal_id, 64)?;
+        validate_optional_str("name", &request.name, 200, false)?;
+        validate_optional_str("description", &request.description, 5000, true)?;
+
+        let pool = get_pool()?;
+        if request.accept {
+            StoryBibleExtractionOps::accept_proposal(&pool, &request.proposal_id, request.name, request.description).await
+        } else {
+            StoryBibleExtractionOps::reject_proposal(&pool, &request.proposal_id).await
+        }
+    }
+
+    review(request).await.into()
+}
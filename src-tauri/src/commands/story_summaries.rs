@@ -0,0 +1,39 @@
+//! Rolling story summary command handlers
+
+use crate::ai::story_summary::refresh_project_summaries;
+use crate::ai::SummaryRefresh;
+use crate::commands::CommandResponse;
+use crate::database::get_pool;
+use crate::database::operations::{StorySummary, StorySummaryOps};
+use crate::error::Result;
+use crate::security::rate_limit::{rl_list, rl_update};
+use crate::security::validators::validate_id;
+
+/// A project's book, chapter and scene summaries, book first
+#[tauri::command]
+pub async fn get_story_summaries(project_id: String) -> CommandResponse<Vec<StorySummary>> {
+    async fn get(project_id: String) -> Result<Vec<StorySummary>> {
+        rl_list("story_summaries", Some(&project_id))?;
+        validate_id("project_id", &project_id, 64)?;
+
+        let pool = get_pool()?;
+        StorySummaryOps::get_by_project(&pool, &project_id).await
+    }
+
+    get(project_id).await.into()
+}
+
+/// Summarize whatever changed since the last refresh now, instead of waiting
+/// for the background refresh that follows a save
+#[tauri::command]
+pub async fn refresh_story_summaries(project_id: String) -> CommandResponse<SummaryRefresh> {
+    async fn refresh(project_id: String) -> Result<SummaryRefresh> {
+        rl_update("story_summaries", Some(&project_id))?;
+        validate_id("project_id", &project_id, 64)?;
+
+        let pool = get_pool()?;
+        refresh_project_summaries(&pool, &project_id).await
+    }
+
+    refresh(project_id).await.into()
+}
@@ -1,5 +1,6 @@
+use crate::ai::stylometry::{project_fingerprint, StyleDrift, StyleFingerprint};
 use crate::database::{get_pool};
-use crate::database::operations::StyleExampleOps;
+use crate::database::operations::{DocumentOps, StyleExampleOps};
 use crate::database::models::StyleExample;
 use crate::error::{Result, StoryWeaverError};
 use serde::{Deserialize, Serialize};
@@ -206,3 +207,56 @@ pub async fn delete_style_examples_by_project(
     StyleExampleOps::delete_by_project(&pool, &project_id).await?;
     Ok(())
 }
+
+/// Get the stylometric fingerprint of a project's style examples
+#[command]
+pub async fn get_style_fingerprint(
+    project_id: String,
+) -> Result<Option<StyleFingerprint>> {
+    // Input validation
+    crate::security::validate_security_input(&project_id)?;
+
+    let pool = get_pool()?;
+    project_fingerprint(&pool, &project_id).await
+}
+
+/// Score a passage, such as AI output waiting to be accepted, for drift from
+/// the project's style examples
+#[command]
+pub async fn score_style_drift(
+    project_id: String,
+    text: String,
+) -> Result<StyleDrift> {
+    // Input validation
+    crate::security::validate_security_input(&project_id)?;
+    if text.trim().is_empty() {
+        return Err(StoryWeaverError::validation("Text cannot be empty".to_string()));
+    }
+    if text.len() > 1_000_000 {
+        return Err(StoryWeaverError::validation("Text too long (max 1000000 characters)".to_string()));
+    }
+
+    let pool = get_pool()?;
+    let fingerprint = project_fingerprint(&pool, &project_id)
+        .await?
+        .ok_or_else(|| StoryWeaverError::validation("Add style examples to the project to check style drift".to_string()))?;
+    Ok(fingerprint.score(&text))
+}
+
+/// Score a document for drift from its project's style examples
+#[command]
+pub async fn score_document_style_drift(
+    document_id: String,
+) -> Result<StyleDrift> {
+    // Input validation
+    crate::security::validate_security_input(&document_id)?;
+
+    let pool = get_pool()?;
+    let document = DocumentOps::get_by_id(&pool, &document_id)
+        .await?
+        .ok_or_else(|| StoryWeaverError::DocumentNotFound { id: document_id.clone() })?;
+    let fingerprint = project_fingerprint(&pool, &document.project_id)
+        .await?
+        .ok_or_else(|| StoryWeaverError::validation("Add style examples to the project to check style drift".to_string()))?;
+    Ok(fingerprint.score(&document.content))
+}
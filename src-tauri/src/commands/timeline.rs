@@ -0,0 +1,110 @@
+//! Story calendar and timeline command handlers
+
+use crate::commands::CommandResponse;
+use crate::database::get_pool;
+use crate::database::models::TimelineEvent;
+use crate::database::operations::{StoryCalendarOps, TimelineOps};
+use crate::error::Result;
+use crate::security::rate_limit::{rl_list, rl_update};
+use crate::security::validators::{validate_id, validate_non_empty_str};
+use crate::timeline::{validate_timeline as check_timeline, StoryCalendar, StoryDate, TimelineReport};
+use serde::Serialize;
+
+/// A duration read with a project's calendar
+#[derive(Debug, Serialize)]
+pub struct StoryDuration {
+    pub minutes: i64,
+    pub normalized: String,
+}
+
+/// The project's calendar; Gregorian until one is set
+#[tauri::command]
+pub async fn get_story_calendar(project_id: String) -> CommandResponse<StoryCalendar> {
+    async fn get(project_id: String) -> Result<StoryCalendar> {
+        rl_list("story_calendar", Some(&project_id))?;
+        validate_id("project_id", &project_id, 64)?;
+
+        let pool = get_pool()?;
+        StoryCalendarOps::get(&pool, &project_id).await
+    }
+
+    get(project_id).await.into()
+}
+
+/// Set the project's calendar. Every timeline date is read again with it;
+/// returns how many the new calendar can't read.
+#[tauri::command]
+pub async fn set_story_calendar(project_id: String, calendar: StoryCalendar) -> CommandResponse<usize> {
+    async fn set(project_id: String, calendar: StoryCalendar) -> Result<usize> {
+        rl_update("story_calendar", Some(&project_id))?;
+        validate_id("project_id", &project_id, 64)?;
+        for name in calendar.months.iter().map(|m| &m.name).chain(calendar.eras.iter().map(|e| &e.name)) {
+            validate_non_empty_str("name", name, 100)?;
+        }
+
+        let pool = get_pool()?;
+        StoryCalendarOps::set(&pool, &project_id, &calendar).await
+    }
+
+    set(project_id, calendar).await.into()
+}
+
+/// Read a date the way the project's calendar writes them
+#[tauri::command]
+pub async fn parse_story_date(project_id: String, date: String) -> CommandResponse<StoryDate> {
+    async fn parse(project_id: String, date: String) -> Result<StoryDate> {
+        rl_list("story_calendar", Some(&project_id))?;
+        validate_id("project_id", &project_id, 64)?;
+        validate_non_empty_str("date", &date, 200)?;
+
+        let pool = get_pool()?;
+        StoryCalendarOps::get(&pool, &project_id).await?.parse(&date)
+    }
+
+    parse(project_id, date).await.into()
+}
+
+/// Read a duration such as "3 days" or "2h 30m"
+#[tauri::command]
+pub async fn parse_story_duration(project_id: String, duration: String) -> CommandResponse<StoryDuration> {
+    async fn parse(project_id: String, duration: String) -> Result<StoryDuration> {
+        rl_list("story_calendar", Some(&project_id))?;
+        validate_id("project_id", &project_id, 64)?;
+        validate_non_empty_str("duration", &duration, 200)?;
+
+        let pool = get_pool()?;
+        let calendar = StoryCalendarOps::get(&pool, &project_id).await?;
+        let minutes = calendar.parse_duration(&duration)?;
+        Ok(StoryDuration { minutes, normalized: calendar.format_duration(minutes) })
+    }
+
+    parse(project_id, duration).await.into()
+}
+
+/// A project's timeline events in story order
+#[tauri::command]
+pub async fn get_timeline_events(project_id: String) -> CommandResponse<Vec<TimelineEvent>> {
+    async fn get(project_id: String) -> Result<Vec<TimelineEvent>> {
+        rl_list("timeline_events", Some(&project_id))?;
+        validate_id("project_id", &project_id, 64)?;
+
+        let pool = get_pool()?;
+        TimelineOps::get_by_project(&pool, &project_id).await
+    }
+
+    get(project_id).await.into()
+}
+
+/// Look for timeline events that can't all have happened
+#[tauri::command]
+pub async fn validate_timeline(project_id: String) -> CommandResponse<TimelineReport> {
+    async fn validate(project_id: String) -> Result<TimelineReport> {
+        rl_list("timeline_validation", Some(&project_id))?;
+        validate_id("project_id", &project_id, 64)?;
+
+        let pool = get_pool()?;
+        check_timeline(&pool, &project_id).await
+    }
+
+    validate(project_id).await.into()
+}
@@ -0,0 +1,173 @@
+//! AI-assisted claim extraction
+//! Asks the model for the facts a document states, as structured JSON, and
+//! keeps the ones it can quote: a claim whose quote isn't in the document is
+//! dropped, so every claim points at real text.
+
+use super::claims::{eye_colors, hair_colors, normalize_title, parse_age, place_category, titles_in, Cast, Claim, ClaimKind, ClaimSource};
+use crate::ai::prompt_templates::{render_prompt, PromptScope};
+use crate::ai::{AIContext, AIProvider, JsonSchema, StructuredGeneration};
+use crate::error::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+/// Most characters sent to the model at once; longer documents are read in
+/// paragraph-aligned chunks
+pub const MAX_CHUNK_CHARS: usize = 12_000;
+
+/// Structured reply for `continuity_claims`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GeneratedClaims {
+    pub claims: Vec<GeneratedClaim>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GeneratedClaim {
+    pub subject: String,
+    pub attribute: String,
+    pub value: String,
+    pub quote: String,
+}
+
+pub fn continuity_claims_schema() -> JsonSchema {
+    JsonSchema::new(
+        "continuity_claims",
+        json!({
+            "type": "object",
+            "properties": {
+                "claims": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "subject": { "type": "string", "minLength": 1, "maxLength": 200 },
+                            "attribute": {
+                                "type": "string",
+                                "enum": ["eye_color", "hair_color", "age", "title", "home", "birthplace", "location_kind", "knows", "learns", "unaware"]
+                            },
+                            "value": { "type": "string", "minLength": 1, "maxLength": 500 },
+                            "quote": { "type": "string", "minLength": 1, "maxLength": 1000 }
+                        },
+                        "required": ["subject", "attribute", "value", "quote"],
+                        "additionalProperties": false
+                    }
+                }
+            },
+            "required": ["claims"],
+            "additionalProperties": false
+        }),
+    )
+}
+
+/// `text` split at paragraph breaks into pieces of at most `MAX_CHUNK_CHARS`
+/// (a longer paragraph is a piece of its own), with each piece's character
+/// offset
+pub(crate) fn chunks(text: &str) -> Vec<(usize, &str)> {
+    let mut pieces = Vec::new();
+    let (mut start_byte, mut start_char, mut chars) = (0, 0, 0);
+    let mut end_byte = 0;
+    for paragraph in text.split_inclusive("\n\n") {
+        let length = paragraph.chars().count();
+        if chars > 0 && chars + length > MAX_CHUNK_CHARS {
+            pieces.push((start_char, &text[start_byte..end_byte]));
+            start_byte = end_byte;
+            start_char += chars;
+            chars = 0;
+        }
+        end_byte += paragraph.len();
+        chars += length;
+    }
+    if chars > 0 {
+        pieces.push((start_char, &text[start_byte..end_byte]));
+    }
+    pieces
+}
+
+/// Turn one of the model's claims into a `Claim`, or `None` when it names
+/// someone the project doesn't have, a value that can't be read, or a quote
+/// that isn't in the text
+fn to_claim(generated: &GeneratedClaim, document_id: &str, offset: usize, text: &str, cast: &Cast) -> Option<Claim> {
+    let kind = match generated.attribute.as_str() {
+        "eye_color" => ClaimKind::EyeColor,
+        "hair_color" => ClaimKind::HairColor,
+        "age" => ClaimKind::Age,
+        "title" => ClaimKind::Title,
+        "home" => ClaimKind::Home,
+        "birthplace" => ClaimKind::Birthplace,
+        "location_kind" => ClaimKind::LocationKind,
+        "knows" => ClaimKind::Knows,
+        "learns" => ClaimKind::Learns,
+        "unaware" => ClaimKind::Unaware,
+        _ => return None,
+    };
+    let (subject_id, subject_name) = if kind.about_location() {
+        cast.location(&generated.subject)?.clone()
+    } else {
+        cast.character(&generated.subject)?.clone()
+    };
+
+    let raw = generated.value.trim();
+    let value = match kind {
+        ClaimKind::EyeColor => eye_colors(raw).into_iter().next()?,
+        ClaimKind::HairColor => hair_colors(raw).into_iter().next()?,
+        ClaimKind::Age => parse_age(raw).or_else(|| raw.split_whitespace().next().and_then(parse_age))?.to_string(),
+        ClaimKind::Title => titles_in(raw).into_iter().next().unwrap_or_else(|| normalize_title(raw)),
+        ClaimKind::Home | ClaimKind::Birthplace => match cast.location(raw) {
+            Some((id, _)) => id.clone(),
+            None => cast.locations_in(raw).into_iter().next()?,
+        },
+        ClaimKind::LocationKind => {
+            let kind = raw.to_lowercase();
+            place_category(&kind)?;
+            kind
+        }
+        _ => raw.to_string(),
+    };
+
+    let quote = generated.quote.trim();
+    let at = text.find(quote).filter(|_| !quote.is_empty())?;
+    let start = offset + text[..at].chars().count();
+    Some(Claim {
+        document_id: document_id.to_string(),
+        subject_id,
+        subject_name,
+        kind,
+        value,
+        start,
+        end: start + quote.chars().count(),
+        excerpt: quote.to_string(),
+        source: ClaimSource::Ai,
+    })
+}
+
+/// The claims an AI model finds in a document about the project's
+/// characters and places
+pub async fn ai_claims(
+    provider: &dyn AIProvider,
+    project_id: &str,
+    document_id: &str,
+    text: &str,
+    cast: &Cast,
+) -> Result<Vec<Claim>> {
+    let context = AIContext {
+        project_id: Some(project_id.to_string()),
+        document_id: Some(document_id.to_string()),
+        ..Default::default()
+    };
+    let schema = continuity_claims_schema();
+
+    let mut claims = Vec::new();
+    for (offset, chunk) in chunks(text) {
+        if chunk.trim().is_empty() {
+            continue;
+        }
+        let values = json!({
+            "characters": cast.character_names(),
+            "places": cast.location_names(),
+            "text": chunk,
+        });
+        let prompt = render_prompt("continuity_claims", &PromptScope::for_project(project_id), &values)?.text;
+        let generated: GeneratedClaims = provider.generate_structured(&prompt, &schema, &context).await?;
+        claims.extend(generated.claims.iter().filter_map(|claim| to_claim(claim, document_id, offset, chunk, cast)));
+    }
+    Ok(claims)
+}
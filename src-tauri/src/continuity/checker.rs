@@ -0,0 +1,601 @@
+//! Continuity checks
+//! Compares what the manuscript claims with the story bible: character
+//! traits, `Character` fields and location types. Facts the story bible
+//! doesn't settle are compared between chapters instead, and a character
+//! can't know something before the chapter where they learn it.
+
+use super::assisted::ai_claims;
+use super::claims::{
+    eye_colors, eye_colors_in, hair_colors, hair_colors_in, parse_age, place_category, split_title, titles_in, Cast,
+    Claim, ClaimKind,
+};
+use crate::ai::AIProvider;
+use crate::database::models::{Character, CharacterTrait, Document, DocumentType, Location, LocationType};
+use crate::database::operations::{
+    CharacterOps, CharacterTraitOps, ConflictPassage, ConflictSeverity, ConflictType, ConsistencyConflict, DocumentOps,
+    LocationOps, SeriesConsistencyOps,
+};
+use crate::database::DbPool;
+use crate::error::Result;
+use chrono::{DateTime, Utc};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+static BORN_IN: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?i)\bborn\s+in\s+([^.,;\n]+)").unwrap());
+static LIVES_IN: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?i)\b(?:lives|lived|grew\s+up|resides|dwells)\s+in\s+([^.,;\n]+)").unwrap());
+
+/// Words that don't tell two facts apart
+const FACT_STOPWORDS: &[&str] = &[
+    "that", "the", "and", "was", "were", "had", "has", "have", "her", "his", "their", "with", "from", "for", "not",
+    "but", "who", "what", "this", "she", "him", "they", "them", "been", "would", "could", "all", "its", "are",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ContinuityMode {
+    /// Claims found by the built-in patterns only
+    Rules,
+    /// Pattern claims plus the ones an AI model finds
+    AiAssisted,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContinuityReport {
+    pub project_id: String,
+    pub mode: ContinuityMode,
+    pub documents_checked: usize,
+    pub claims_found: usize,
+    pub conflicts: Vec<ConsistencyConflict>,
+    pub consistency_score: f64, // 0.0 to 1.0
+    pub generated_at: DateTime<Utc>,
+}
+
+/// What the story bible says about a character
+#[derive(Debug, Default)]
+struct CharacterCanon {
+    eye_colors: Vec<String>,
+    hair_colors: Vec<String>,
+    age: Option<u32>,
+    titles: Vec<String>,
+    /// Location IDs
+    homes: Vec<String>,
+    birthplaces: Vec<String>,
+    knows: Vec<String>,
+    unaware: Vec<String>,
+}
+
+/// The story bible facts claims are checked against
+pub struct Canon {
+    characters: HashMap<String, CharacterCanon>,
+    /// Location IDs to the kind of place their type names
+    location_kinds: HashMap<String, &'static str>,
+}
+
+fn push_unique(values: &mut Vec<String>, found: Vec<String>) {
+    for value in found {
+        if !values.contains(&value) {
+            values.push(value);
+        }
+    }
+}
+
+impl Canon {
+    pub fn new(characters: &[Character], traits: &[CharacterTrait], locations: &[Location], cast: &Cast) -> Self {
+        let mut canon: HashMap<String, CharacterCanon> = HashMap::new();
+
+        for character in characters {
+            let entry = canon.entry(character.id.clone()).or_default();
+            if let Some(appearance) = &character.appearance {
+                push_unique(&mut entry.eye_colors, eye_colors_in(appearance));
+                push_unique(&mut entry.hair_colors, hair_colors_in(appearance));
+            }
+            entry.age = character.age.and_then(|age| u32::try_from(age).ok());
+            if let (Some(title), _) = split_title(&character.name) {
+                push_unique(&mut entry.titles, vec![title]);
+            }
+            for text in [&character.background, &character.description].into_iter().flatten() {
+                for captures in BORN_IN.captures_iter(text) {
+                    push_unique(&mut entry.birthplaces, cast.locations_in(&captures[1]));
+                }
+                for captures in LIVES_IN.captures_iter(text) {
+                    push_unique(&mut entry.homes, cast.locations_in(&captures[1]));
+                }
+            }
+        }
+
+        for character_trait in traits {
+            let Some(value) = character_trait.trait_value.as_deref().filter(|v| !v.trim().is_empty()) else {
+                continue;
+            };
+            let entry = canon.entry(character_trait.character_id.clone()).or_default();
+            let name = character_trait.trait_name.to_lowercase();
+            if name.contains("eye") {
+                push_unique(&mut entry.eye_colors, eye_colors(value));
+            } else if name.contains("hair") {
+                push_unique(&mut entry.hair_colors, hair_colors(value));
+            } else if name.trim() == "age" {
+                if let Some(age) = value.split_whitespace().next().and_then(parse_age).or_else(|| parse_age(value)) {
+                    entry.age = Some(age);
+                }
+            } else if name.contains("title") || name.contains("rank") {
+                push_unique(&mut entry.titles, titles_in(value));
+            } else if name.contains("birth") || name.contains("born") {
+                push_unique(&mut entry.birthplaces, cast.locations_in(value));
+            } else if name.contains("home") || name.contains("residence") || name.contains("lives") {
+                push_unique(&mut entry.homes, cast.locations_in(value));
+            } else if name.contains("unaware") || name.contains("doesn't know") || name.contains("does not know") {
+                entry.unaware.push(value.trim().to_string());
+            } else if name.contains("knows") || name.contains("aware of") || name.contains("knowledge") {
+                entry.knows.push(value.trim().to_string());
+            }
+        }
+
+        let location_kinds = locations
+            .iter()
+            .filter_map(|location| {
+                let kind = match location.location_type {
+                    LocationType::City => "city",
+                    LocationType::Building => "building",
+                    LocationType::Room => "room",
+                    LocationType::Landscape => "landscape",
+                    LocationType::Fictional | LocationType::Historical => return None,
+                };
+                Some((location.id.clone(), kind))
+            })
+            .collect();
+
+        Self { characters: canon, location_kinds }
+    }
+}
+
+fn content_words(text: &str) -> HashSet<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .map(str::to_lowercase)
+        .filter(|word| word.chars().count() > 2 && !FACT_STOPWORDS.contains(&word.as_str()))
+        .collect()
+}
+
+/// Whether two known facts are the same fact: most of the shorter one's
+/// words appear in the longer one
+pub fn same_fact(a: &str, b: &str) -> bool {
+    let (a, b) = (content_words(a), content_words(b));
+    let shorter = a.len().min(b.len());
+    if shorter == 0 {
+        return false;
+    }
+    if shorter < 2 {
+        return a == b;
+    }
+    a.intersection(&b).count() as f64 / shorter as f64 >= 0.6
+}
+
+fn passage(claim: &Claim) -> ConflictPassage {
+    ConflictPassage {
+        document_id: claim.document_id.clone(),
+        start: claim.start,
+        end: claim.end,
+        excerpt: claim.excerpt.clone(),
+    }
+}
+
+fn listed(values: &[String]) -> String {
+    values.join(" or ")
+}
+
+/// Compares a project's claims, in manuscript order
+struct Checker<'a> {
+    project_id: &'a str,
+    canon: &'a Canon,
+    cast: &'a Cast,
+    conflicts: Vec<ConsistencyConflict>,
+}
+
+impl<'a> Checker<'a> {
+    fn push(
+        &mut self,
+        conflict_type: ConflictType,
+        severity: ConflictSeverity,
+        description: String,
+        elements: Vec<String>,
+        suggestions: &[&str],
+        claims: &[&Claim],
+    ) {
+        self.conflicts.push(ConsistencyConflict {
+            conflict_type,
+            severity,
+            description,
+            affected_projects: vec![self.project_id.to_string()],
+            affected_elements: elements,
+            suggestions: suggestions.iter().map(|s| s.to_string()).collect(),
+            passages: claims.iter().map(|claim| passage(claim)).collect(),
+        });
+    }
+
+    fn place(&self, id: &str) -> String {
+        self.cast.location_name(id).unwrap_or(id).to_string()
+    }
+
+    fn places(&self, ids: &[String]) -> String {
+        listed(&ids.iter().map(|id| self.place(id)).collect::<Vec<_>>())
+    }
+
+    /// Claims about an attribute the story bible settles
+    fn check_against_canon(&mut self, claims: &[&Claim]) {
+        let first = claims[0];
+        let (name, value) = (first.subject_name.as_str(), first.value.as_str());
+        let elements = vec![name.to_string(), first.kind.label().to_string()];
+
+        if first.kind == ClaimKind::LocationKind {
+            let (Some(&canon), Some(claimed)) = (self.canon.location_kinds.get(&first.subject_id), place_category(value)) else {
+                return;
+            };
+            if canon != claimed {
+                self.push(
+                    ConflictType::WorldElementInconsistency,
+                    ConflictSeverity::Medium,
+                    format!("The manuscript calls {} a {}, but the story bible lists it as a {}", name, value, canon),
+                    elements,
+                    &["Update the location's type or the passage so they agree"],
+                    claims,
+                );
+            }
+            return;
+        }
+
+        let all: &'a Canon = self.canon;
+        let Some(canon) = all.characters.get(&first.subject_id) else {
+            return;
+        };
+        match first.kind {
+            ClaimKind::EyeColor if !canon.eye_colors.is_empty() && !canon.eye_colors.contains(&first.value) => self.push(
+                ConflictType::CharacterInconsistency,
+                ConflictSeverity::High,
+                format!("{} has {} eyes in the manuscript, but {} in the story bible", name, value, listed(&canon.eye_colors)),
+                elements,
+                &["Correct the passage or the character's appearance"],
+                claims,
+            ),
+            ClaimKind::HairColor if !canon.hair_colors.is_empty() && !canon.hair_colors.contains(&first.value) => self.push(
+                ConflictType::CharacterInconsistency,
+                ConflictSeverity::Medium,
+                format!("{} has {} hair in the manuscript, but {} in the story bible", name, value, listed(&canon.hair_colors)),
+                elements,
+                &[
+                    "Correct the passage or the character's appearance",
+                    "If the character changed their hair, mention it in the story",
+                ],
+                claims,
+            ),
+            ClaimKind::Age => {
+                let (Some(canon_age), Ok(age)) = (canon.age, value.parse::<u32>()) else {
+                    return;
+                };
+                if age != canon_age {
+                    let severity = if age.abs_diff(canon_age) <= 1 { ConflictSeverity::Low } else { ConflictSeverity::Medium };
+                    self.push(
+                        ConflictType::CharacterInconsistency,
+                        severity,
+                        format!("{} is {} in the manuscript, but {} in the story bible", name, age, canon_age),
+                        elements,
+                        &[
+                            "Correct the passage or the character's age",
+                            "If years pass in the story, record the age the character starts at",
+                        ],
+                        claims,
+                    );
+                }
+            }
+            ClaimKind::Title if !canon.titles.is_empty() && !canon.titles.contains(&first.value) => self.push(
+                ConflictType::CharacterInconsistency,
+                ConflictSeverity::Medium,
+                format!("The manuscript calls {} {}, but their title in the story bible is {}", name, value, listed(&canon.titles)),
+                elements,
+                &["Correct the passage or the character's title", "If the character is promoted, note when in the story bible"],
+                claims,
+            ),
+            ClaimKind::Home if !canon.homes.is_empty() && !canon.homes.contains(&first.value) => {
+                let description = format!(
+                    "{} lives in {} in the manuscript, but in {} in the story bible",
+                    name,
+                    self.place(value),
+                    self.places(&canon.homes)
+                );
+                self.push(
+                    ConflictType::CharacterInconsistency,
+                    ConflictSeverity::Medium,
+                    description,
+                    elements,
+                    &["Correct the passage or the character's home", "If the character moves, show the move in the story"],
+                    claims,
+                );
+            }
+            ClaimKind::Birthplace if !canon.birthplaces.is_empty() && !canon.birthplaces.contains(&first.value) => {
+                let description = format!(
+                    "{} was born in {} according to the manuscript, but in {} according to the story bible",
+                    name,
+                    self.place(value),
+                    self.places(&canon.birthplaces)
+                );
+                self.push(
+                    ConflictType::CharacterInconsistency,
+                    ConflictSeverity::High,
+                    description,
+                    elements,
+                    &["Correct the passage or the character's background"],
+                    claims,
+                );
+            }
+            ClaimKind::Unaware if canon.knows.iter().any(|fact| same_fact(fact, value)) => self.push(
+                ConflictType::CharacterInconsistency,
+                ConflictSeverity::Medium,
+                format!("{} doesn't know \"{}\" in the manuscript, but the story bible says they do", name, value),
+                elements,
+                &["Correct the passage or the character's knowledge"],
+                claims,
+            ),
+            _ => {}
+        }
+    }
+
+    /// Facts that can't change, stated differently in different places
+    fn check_between_claims(&mut self, kind: ClaimKind, groups: &[Vec<&Claim>]) {
+        let first = groups[0][0];
+        let name = first.subject_name.as_str();
+        let values: Vec<String> = groups
+            .iter()
+            .map(|group| match kind {
+                ClaimKind::Birthplace => self.place(&group[0].value),
+                _ => group[0].value.clone(),
+            })
+            .collect();
+        let description = match kind {
+            ClaimKind::EyeColor => format!("{} has {} eyes in different places in the manuscript", name, listed(&values)),
+            ClaimKind::Birthplace => format!("The manuscript has {} born in {}", name, listed(&values)),
+            _ => return,
+        };
+        let claims: Vec<&Claim> = groups.iter().flatten().copied().collect();
+        self.push(
+            ConflictType::CharacterInconsistency,
+            ConflictSeverity::Medium,
+            description,
+            vec![name.to_string(), kind.label().to_string()],
+            &["Pick one and record it in the story bible so later chapters can be checked against it"],
+            &claims,
+        );
+    }
+
+    /// A character who knows something before they learn it, or knows what
+    /// the story bible says they don't
+    fn check_knowledge(&mut self, claims: &[&Claim]) {
+        for (i, known) in claims.iter().enumerate().filter(|(_, claim)| claim.kind == ClaimKind::Knows) {
+            let learned_before = claims[..i]
+                .iter()
+                .any(|claim| claim.kind == ClaimKind::Learns && same_fact(&claim.value, &known.value));
+            if learned_before {
+                continue;
+            }
+
+            let elements = vec![known.subject_name.clone(), known.kind.label().to_string()];
+            if let Some(learned) = claims[i + 1..]
+                .iter()
+                .find(|claim| claim.kind == ClaimKind::Learns && same_fact(&claim.value, &known.value))
+            {
+                self.push(
+                    ConflictType::TimelineConflict,
+                    ConflictSeverity::Medium,
+                    format!("{} knows \"{}\" before learning it", known.subject_name, known.value),
+                    elements,
+                    &["Move the discovery earlier or the knowing later", "If the character suspected it, say so"],
+                    &[*known, *learned],
+                );
+                continue;
+            }
+
+            let unaware = self
+                .canon
+                .characters
+                .get(&known.subject_id)
+                .is_some_and(|canon| canon.unaware.iter().any(|fact| same_fact(fact, &known.value)));
+            if unaware {
+                self.push(
+                    ConflictType::CharacterInconsistency,
+                    ConflictSeverity::Medium,
+                    format!("{} knows \"{}\", but the story bible says they don't", known.subject_name, known.value),
+                    elements,
+                    &["Correct the passage, or show the character learning it first"],
+                    &[*known],
+                );
+            }
+        }
+    }
+
+    /// A character at two places in one scene without going anywhere
+    fn check_whereabouts(&mut self, claims: &[&Claim]) {
+        let mut last: Option<&Claim> = None;
+        for &claim in claims {
+            match claim.kind {
+                ClaimKind::Moves => last = None,
+                ClaimKind::Whereabouts => {
+                    if let Some(previous) = last.filter(|previous| previous.value != claim.value) {
+                        let description = format!(
+                            "{} is in {} and then in {} in the same scene without leaving",
+                            claim.subject_name,
+                            self.place(&previous.value),
+                            self.place(&claim.value)
+                        );
+                        self.push(
+                            ConflictType::TimelineConflict,
+                            ConflictSeverity::Low,
+                            description,
+                            vec![claim.subject_name.clone(), claim.kind.label().to_string()],
+                            &["Show the character moving between the two places"],
+                            &[previous, claim],
+                        );
+                    }
+                    last = Some(claim);
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Check claims, given in manuscript order, against the story bible and
+/// each other. Whereabouts are only compared within `scene_ids`' documents,
+/// since a chapter can move between scenes.
+pub fn check_claims(
+    project_id: &str,
+    canon: &Canon,
+    cast: &Cast,
+    claims: &[Claim],
+    scene_ids: &HashSet<String>,
+) -> Vec<ConsistencyConflict> {
+    let mut checker = Checker { project_id, canon, cast, conflicts: Vec::new() };
+
+    // Claims of one value about one attribute, in the order they first appear
+    let mut groups: Vec<Vec<&Claim>> = Vec::new();
+    let mut index: HashMap<(&str, ClaimKind, &str), usize> = HashMap::new();
+    for claim in claims.iter().filter(|claim| !matches!(claim.kind, ClaimKind::Moves | ClaimKind::Whereabouts)) {
+        let key = (claim.subject_id.as_str(), claim.kind, claim.value.as_str());
+        match index.get(&key) {
+            Some(&i) => groups[i].push(claim),
+            None => {
+                index.insert(key, groups.len());
+                groups.push(vec![claim]);
+            }
+        }
+    }
+    for group in &groups {
+        checker.check_against_canon(group);
+    }
+
+    // Unchanging facts the story bible doesn't settle
+    for kind in [ClaimKind::EyeColor, ClaimKind::Birthplace] {
+        let mut by_subject: Vec<(&str, Vec<Vec<&Claim>>)> = Vec::new();
+        for group in groups.iter().filter(|group| group[0].kind == kind) {
+            let subject = group[0].subject_id.as_str();
+            let settled = canon.characters.get(subject).is_some_and(|canon| match kind {
+                ClaimKind::EyeColor => !canon.eye_colors.is_empty(),
+                _ => !canon.birthplaces.is_empty(),
+            });
+            if settled {
+                continue;
+            }
+            match by_subject.iter_mut().find(|(id, _)| *id == subject) {
+                Some((_, values)) => values.push(group.clone()),
+                None => by_subject.push((subject, vec![group.clone()])),
+            }
+        }
+        for (_, values) in by_subject.iter().filter(|(_, values)| values.len() > 1) {
+            checker.check_between_claims(kind, values);
+        }
+    }
+
+    let mut by_character: Vec<(&str, Vec<&Claim>)> = Vec::new();
+    for claim in claims.iter().filter(|claim| matches!(claim.kind, ClaimKind::Knows | ClaimKind::Learns)) {
+        match by_character.iter_mut().find(|(id, _)| *id == claim.subject_id) {
+            Some((_, claims)) => claims.push(claim),
+            None => by_character.push((claim.subject_id.as_str(), vec![claim])),
+        }
+    }
+    for (_, claims) in &by_character {
+        checker.check_knowledge(claims);
+    }
+
+    let mut in_scenes: Vec<((&str, &str), Vec<&Claim>)> = Vec::new();
+    for claim in claims
+        .iter()
+        .filter(|claim| matches!(claim.kind, ClaimKind::Moves | ClaimKind::Whereabouts))
+        .filter(|claim| scene_ids.contains(&claim.document_id))
+    {
+        let key = (claim.document_id.as_str(), claim.subject_id.as_str());
+        match in_scenes.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, claims)) => claims.push(claim),
+            None => in_scenes.push((key, vec![claim])),
+        }
+    }
+    for (_, claims) in &in_scenes {
+        checker.check_whereabouts(claims);
+    }
+
+    checker.conflicts
+}
+
+/// A project's chapters in order, each followed by its scenes, then scenes
+/// that aren't in a chapter. Other documents aren't part of the manuscript.
+pub fn manuscript_order(documents: &[Document]) -> Vec<&Document> {
+    let mut chapters: Vec<&Document> =
+        documents.iter().filter(|doc| matches!(doc.document_type, DocumentType::Chapter)).collect();
+    chapters.sort_by_key(|doc| doc.order_index);
+    let mut scenes: Vec<&Document> =
+        documents.iter().filter(|doc| matches!(doc.document_type, DocumentType::Scene)).collect();
+    scenes.sort_by_key(|doc| doc.order_index);
+
+    let mut ordered = Vec::new();
+    for chapter in &chapters {
+        ordered.push(*chapter);
+        ordered.extend(scenes.iter().filter(|scene| scene.parent_id.as_deref() == Some(chapter.id.as_str())));
+    }
+    let placed: HashSet<&str> = ordered.iter().map(|doc| doc.id.as_str()).collect();
+    ordered.extend(scenes.into_iter().filter(|scene| !placed.contains(scene.id.as_str())));
+    ordered
+}
+
+/// Whether an AI claim repeats one the rules already found
+fn is_duplicate(claim: &Claim, found: &[Claim]) -> bool {
+    found.iter().any(|other| {
+        other.subject_id == claim.subject_id
+            && other.kind == claim.kind
+            && other.start < claim.end
+            && claim.start < other.end
+    })
+}
+
+/// Check a project's manuscript for continuity errors. With a provider, an
+/// AI model also reads each document for claims the patterns miss.
+pub async fn check_continuity(pool: &DbPool, project_id: &str, provider: Option<&dyn AIProvider>) -> Result<ContinuityReport> {
+    let characters = CharacterOps::get_by_project(pool, project_id).await?;
+    let traits = CharacterTraitOps::get_by_project(pool, project_id).await?;
+    let locations = LocationOps::get_by_project(pool, project_id).await?;
+    let documents = DocumentOps::get_by_project(pool, project_id).await?;
+
+    let cast = Cast::new(&characters, &locations)?;
+    let canon = Canon::new(&characters, &traits, &locations, &cast);
+    let manuscript: Vec<&Document> = manuscript_order(&documents)
+        .into_iter()
+        .filter(|doc| !doc.content.trim().is_empty())
+        .collect();
+    let scene_ids: HashSet<String> = manuscript
+        .iter()
+        .filter(|doc| matches!(doc.document_type, DocumentType::Scene))
+        .map(|doc| doc.id.clone())
+        .collect();
+
+    let mut claims = Vec::new();
+    for document in &manuscript {
+        let mut found = cast.extract(&document.id, &document.content);
+        if let Some(provider) = provider {
+            let extra = ai_claims(provider, project_id, &document.id, &document.content, &cast).await?;
+            let extra: Vec<Claim> = extra.into_iter().filter(|claim| !is_duplicate(claim, &found)).collect();
+            found.extend(extra);
+            found.sort_by_key(|claim| (claim.start, claim.end));
+        }
+        claims.extend(found);
+    }
+
+    let conflicts = check_claims(project_id, &canon, &cast, &claims, &scene_ids);
+    let consistency_score = SeriesConsistencyOps::calculate_consistency_score(&conflicts, manuscript.len().max(1));
+
+    Ok(ContinuityReport {
+        project_id: project_id.to_string(),
+        mode: if provider.is_some() { ContinuityMode::AiAssisted } else { ContinuityMode::Rules },
+        documents_checked: manuscript.len(),
+        claims_found: claims.iter().filter(|claim| claim.kind != ClaimKind::Moves).count(),
+        conflicts,
+        consistency_score,
+        generated_at: Utc::now(),
+    })
+}
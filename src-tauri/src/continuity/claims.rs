@@ -0,0 +1,533 @@
+//! Claims the prose makes
+//! Rule-based extraction of what a document says about the project's named
+//! characters and places: eye and hair color, age, titles, where someone
+//! lives or was born, what kind of place somewhere is, where someone is
+//! during a scene, and what they know.
+
+use crate::database::models::{Character, Location};
+use crate::error::{Result, StoryWeaverError};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ClaimKind {
+    EyeColor,
+    HairColor,
+    Age,
+    Title,
+    /// Where a character lives or grew up
+    Home,
+    Birthplace,
+    /// Where a character is at that point in a scene
+    Whereabouts,
+    /// A character leaving for or arriving somewhere; ends their whereabouts
+    Moves,
+    /// What kind of place a location is
+    LocationKind,
+    Knows,
+    Learns,
+    Unaware,
+}
+
+impl ClaimKind {
+    /// How the attribute is named in conflict descriptions
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::EyeColor => "eye color",
+            Self::HairColor => "hair color",
+            Self::Age => "age",
+            Self::Title => "title",
+            Self::Home => "home",
+            Self::Birthplace => "birthplace",
+            Self::Whereabouts => "whereabouts",
+            Self::Moves => "movement",
+            Self::LocationKind => "kind of place",
+            Self::Knows => "knowledge",
+            Self::Learns => "discovery",
+            Self::Unaware => "ignorance",
+        }
+    }
+
+    /// Whether the claim is about a location rather than a character
+    pub fn about_location(&self) -> bool {
+        matches!(self, Self::LocationKind)
+    }
+}
+
+/// Where a claim was found
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ClaimSource {
+    Rules,
+    Ai,
+}
+
+/// One thing a document says about a character or location
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Claim {
+    pub document_id: String,
+    /// ID of the character or location the claim is about
+    pub subject_id: String,
+    pub subject_name: String,
+    pub kind: ClaimKind,
+    /// Normalized value: a lowercase color, an age in years, a title, a
+    /// location ID, a kind of place or the known fact
+    pub value: String,
+    /// Character offsets of the claim in the document's content
+    pub start: usize,
+    pub end: usize,
+    pub excerpt: String,
+    pub source: ClaimSource,
+}
+
+const EYE_COLORS: &[&str] = &[
+    "blue", "green", "brown", "grey", "gray", "hazel", "amber", "black", "violet", "golden", "gold", "silver", "red",
+];
+
+const HAIR_COLORS: &[&str] = &[
+    "black", "brown", "blond", "blonde", "red", "auburn", "ginger", "grey", "gray", "white", "silver", "golden",
+    "chestnut", "copper",
+];
+
+const TITLES: &[&str] = &[
+    "King", "Queen", "Prince", "Princess", "Emperor", "Empress", "Lord", "Lady", "Sir", "Dame", "Duke", "Duchess",
+    "Count", "Countess", "Baron", "Baroness", "Captain", "Commander", "Lieutenant", "Sergeant", "Major", "Colonel",
+    "General", "Admiral", "Doctor", "Dr", "Professor", "Master", "Magister", "Inspector", "Detective", "Agent",
+];
+
+/// Words naming a kind of place, with the location type they belong to
+const PLACE_KINDS: &[(&str, &str)] = &[
+    ("city", "city"),
+    ("town", "city"),
+    ("village", "city"),
+    ("hamlet", "city"),
+    ("capital", "city"),
+    ("port", "city"),
+    ("castle", "building"),
+    ("keep", "building"),
+    ("fortress", "building"),
+    ("tower", "building"),
+    ("palace", "building"),
+    ("manor", "building"),
+    ("house", "building"),
+    ("inn", "building"),
+    ("tavern", "building"),
+    ("temple", "building"),
+    ("church", "building"),
+    ("cathedral", "building"),
+    ("abbey", "building"),
+    ("room", "room"),
+    ("chamber", "room"),
+    ("cellar", "room"),
+    ("study", "room"),
+    ("forest", "landscape"),
+    ("wood", "landscape"),
+    ("woods", "landscape"),
+    ("mountain", "landscape"),
+    ("mountains", "landscape"),
+    ("valley", "landscape"),
+    ("river", "landscape"),
+    ("lake", "landscape"),
+    ("desert", "landscape"),
+    ("plain", "landscape"),
+    ("plains", "landscape"),
+    ("island", "landscape"),
+    ("marsh", "landscape"),
+    ("moor", "landscape"),
+    ("swamp", "landscape"),
+    ("hills", "landscape"),
+];
+
+const UNITS: &[&str] = &[
+    "one", "two", "three", "four", "five", "six", "seven", "eight", "nine", "ten", "eleven", "twelve", "thirteen",
+    "fourteen", "fifteen", "sixteen", "seventeen", "eighteen", "nineteen",
+];
+
+const TENS: &[&str] = &["twenty", "thirty", "forty", "fifty", "sixty", "seventy", "eighty", "ninety"];
+
+/// Words that, between a name and a feature, mean the feature is someone
+/// else's: "Mara's sister had green eyes"
+const FILLER_STOPS: &[&str] = &[
+    "had", "has", "have", "with", "and", "of", "the", "a", "an", "his", "her", "their", "its", "was", "were", "is", "are",
+];
+
+/// Normalize a color word: "Gray" and "grey" are the same color
+pub fn normalize_color(color: &str) -> String {
+    match color.to_lowercase().as_str() {
+        "gray" => "grey".to_string(),
+        "golden" => "gold".to_string(),
+        "blonde" => "blond".to_string(),
+        "ginger" => "red".to_string(),
+        other => other.to_string(),
+    }
+}
+
+/// Normalize a title, spelling out abbreviations
+pub fn normalize_title(title: &str) -> String {
+    let title = title.trim().trim_end_matches('.');
+    if title.eq_ignore_ascii_case("dr") {
+        return "Doctor".to_string();
+    }
+    let mut chars = title.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars.flat_map(char::to_lowercase)).collect(),
+        None => String::new(),
+    }
+}
+
+/// The location type a word like "village" or "keep" names
+pub fn place_category(kind: &str) -> Option<&'static str> {
+    let kind = kind.to_lowercase();
+    PLACE_KINDS.iter().find(|(word, _)| *word == kind).map(|(_, category)| *category)
+}
+
+/// Read an age written in digits or words, up to ninety-nine
+pub fn parse_age(text: &str) -> Option<u32> {
+    let text = text.trim().to_lowercase();
+    if let Ok(age) = text.parse::<u32>() {
+        return (age < 200).then_some(age);
+    }
+    let mut words = text.split(['-', ' ']).filter(|word| !word.is_empty());
+    let first = words.next()?;
+    let rest = words.next();
+    if words.next().is_some() {
+        return None;
+    }
+    if let Some(unit) = UNITS.iter().position(|word| *word == first) {
+        return rest.is_none().then_some(unit as u32 + 1);
+    }
+    let tens = TENS.iter().position(|word| *word == first)? as u32 * 10 + 20;
+    match rest {
+        None => Some(tens),
+        Some(unit) => UNITS[..9].iter().position(|word| *word == unit).map(|unit| tens + unit as u32 + 1),
+    }
+}
+
+/// Colors from `colors` mentioned anywhere in `text`
+fn colors_in(text: &str, colors: &[&str]) -> Vec<String> {
+    let mut found: Vec<String> = text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| colors.iter().any(|color| color.eq_ignore_ascii_case(word)))
+        .map(normalize_color)
+        .collect();
+    found.dedup();
+    found
+}
+
+/// Colors a description gives one feature, so "auburn hair and green eyes"
+/// gives green eyes and auburn hair
+fn feature_colors(text: &str, colors: &[&str], feature: &str, adjective: &str) -> Vec<String> {
+    let colors = alternation(colors);
+    let patterns = [
+        format!(r"(?i)\b({colors})(?:[\s-][\w]+)?(?:\s+{feature}s?|-{adjective})\b"),
+        format!(r"(?i)\b{feature}s?\s*(?:were|are|was|is|:)\s*(?:[\w-]+\s+){{0,2}}?({colors})\b"),
+    ];
+    let mut found = Vec::new();
+    for pattern in patterns {
+        let Ok(regex) = Regex::new(&pattern) else {
+            continue;
+        };
+        for captures in regex.captures_iter(text) {
+            if let Some(color) = captures.get(1) {
+                let color = normalize_color(color.as_str());
+                if !found.contains(&color) {
+                    found.push(color);
+                }
+            }
+        }
+    }
+    found
+}
+
+/// Eye colors in a description such as `Character.appearance`
+pub fn eye_colors_in(text: &str) -> Vec<String> {
+    feature_colors(text, EYE_COLORS, "eye", "eyed")
+}
+
+/// Hair colors in a description such as `Character.appearance`
+pub fn hair_colors_in(text: &str) -> Vec<String> {
+    feature_colors(text, HAIR_COLORS, "hair", "haired")
+}
+
+/// Every eye color named in a trait's value, such as an "Eye color" trait
+pub fn eye_colors(text: &str) -> Vec<String> {
+    colors_in(text, EYE_COLORS)
+}
+
+/// Every hair color named in a trait's value
+pub fn hair_colors(text: &str) -> Vec<String> {
+    colors_in(text, HAIR_COLORS)
+}
+
+/// Titles mentioned in a story bible field
+pub fn titles_in(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| TITLES.iter().any(|title| title.eq_ignore_ascii_case(word)))
+        .map(normalize_title)
+        .collect()
+}
+
+/// A name's leading title, if it has one: "Captain Mara Voss"
+pub fn split_title(name: &str) -> (Option<String>, &str) {
+    let name = name.trim();
+    if let Some((first, rest)) = name.split_once(' ') {
+        let word = first.trim_end_matches('.');
+        if TITLES.contains(&word) && !rest.trim().is_empty() {
+            return (Some(normalize_title(word)), rest.trim());
+        }
+    }
+    (None, name)
+}
+
+fn alternation(words: &[&str]) -> String {
+    let mut words: Vec<&str> = words.to_vec();
+    // Longest first, so "seventeen" isn't read as "seven"
+    words.sort_by_key(|word| std::cmp::Reverse(word.len()));
+    words.iter().map(|word| regex::escape(word)).collect::<Vec<_>>().join("|")
+}
+
+/// Regex for the ways a name is written, longest first and case-sensitive
+fn name_alternation<'a>(names: impl Iterator<Item = &'a String>) -> String {
+    let mut names: Vec<&String> = names.collect();
+    names.sort_by_key(|name| std::cmp::Reverse(name.chars().count()));
+    format!("(?-i:{})", names.iter().map(|name| regex::escape(name)).collect::<Vec<_>>().join("|"))
+}
+
+fn number_pattern() -> String {
+    let units = alternation(UNITS);
+    format!(
+        r"(?P<value>\d{{1,3}}|(?:{})(?:[- ](?:{}))?|{})",
+        alternation(TENS),
+        alternation(&UNITS[..9]),
+        units
+    )
+}
+
+/// Byte offset to character offset
+fn char_offset(text: &str, byte: usize) -> usize {
+    text[..byte].chars().count()
+}
+
+/// How the project's characters and places can be named, with the patterns
+/// that find claims about them
+pub struct Cast {
+    /// Ways each character is named, mapped to (ID, full name)
+    characters: HashMap<String, (String, String)>,
+    /// Location names, lowercased, mapped to (ID, name)
+    locations: HashMap<String, (String, String)>,
+    patterns: Vec<(ClaimKind, Regex)>,
+}
+
+impl Cast {
+    /// Characters are found by their full name, and by their first or last
+    /// name when no other character shares it. A leading title is dropped.
+    pub fn new(characters: &[Character], locations: &[Location]) -> Result<Self> {
+        let mut forms: HashMap<String, Vec<(String, String)>> = HashMap::new();
+        for character in characters {
+            let (_, name) = split_title(&character.name);
+            let mut names = vec![name.to_string()];
+            let parts: Vec<&str> = name.split_whitespace().collect();
+            if parts.len() > 1 {
+                names.push(parts[0].to_string());
+                names.push(parts[parts.len() - 1].to_string());
+            }
+            for form in names.into_iter().filter(|form| form.chars().count() > 1) {
+                let entry = forms.entry(form).or_default();
+                if !entry.iter().any(|(id, _)| *id == character.id) {
+                    entry.push((character.id.clone(), character.name.clone()));
+                }
+            }
+        }
+        // A name shared by two characters can't say which one is meant
+        let characters: HashMap<String, (String, String)> = forms
+            .into_iter()
+            .filter_map(|(form, mut owners)| (owners.len() == 1).then(|| (form, owners.remove(0))))
+            .collect();
+
+        let locations: HashMap<String, (String, String)> = locations
+            .iter()
+            .filter(|location| !location.name.trim().is_empty())
+            .map(|location| (location.name.trim().to_lowercase(), (location.id.clone(), location.name.trim().to_string())))
+            .collect();
+
+        let mut cast = Self { characters, locations, patterns: Vec::new() };
+        cast.patterns = cast.build_patterns()?;
+        Ok(cast)
+    }
+
+    fn build_patterns(&self) -> Result<Vec<(ClaimKind, Regex)>> {
+        let name = (!self.characters.is_empty())
+            .then(|| format!(r"\b(?P<name>{})\b", name_alternation(self.characters.keys())));
+        let place_names: Vec<String> = self.locations.values().map(|(_, name)| name.clone()).collect();
+        let place = (!place_names.is_empty())
+            .then(|| format!(r"(?:the\s+)?\b(?P<place>{})\b", name_alternation(place_names.iter())));
+        let eye = format!(r"(?P<value>{})", alternation(EYE_COLORS));
+        let hair = format!(r"(?P<value>{})", alternation(HAIR_COLORS));
+        let kinds: Vec<&str> = PLACE_KINDS.iter().map(|(word, _)| *word).collect();
+        let kind = format!(r"(?P<value>{})", alternation(&kinds));
+        let title = format!(r"\b(?-i:(?P<value>{}))\.?", alternation(TITLES));
+        let number = number_pattern();
+        let fact = r"(?P<value>[^.!?;\n]{3,200})";
+        let filler = r"(?P<filler>(?:[\w'’-]+,?\s+){0,2}?)";
+
+        let mut templates: Vec<(ClaimKind, String)> = Vec::new();
+        if let Some(name) = &name {
+            templates.extend([
+                (ClaimKind::EyeColor, format!(r"{name}['’]s\s+{filler}{eye}(?:\s+eyes|-eyed)\b")),
+                (ClaimKind::EyeColor, format!(r"{name},?\s+(?:with|had|has)\s+{filler}{eye}\s+eyes\b")),
+                (ClaimKind::EyeColor, format!(r"{name}['’]s\s+eyes\s+(?:were|are|was|is|looked|shone|glinted|gleamed|flashed)\s+{filler}{eye}\b")),
+                (ClaimKind::HairColor, format!(r"{name}['’]s\s+{filler}{hair}(?:[\s-][\w-]+)??\s+hair\b")),
+                (ClaimKind::HairColor, format!(r"{name},?\s+(?:with|had|has)\s+{filler}{hair}\s+hair\b")),
+                (ClaimKind::HairColor, format!(r"{name}['’]s\s+hair\s+(?:was|is|were|had\s+gone|had\s+turned)\s+{filler}{hair}\b")),
+                (ClaimKind::Age, format!(r"{name},\s+(?P<value>\d{{1,3}}),")),
+                (ClaimKind::Age, format!(r"{name}\s+(?:was|is)\s+(?:now\s+|only\s+|nearly\s+)?{number}\s+years?\s+old\b")),
+                (ClaimKind::Age, format!(r"\b{number}-year-old\s+{name}")),
+                (ClaimKind::Age, format!(r"{name}\s+(?:had\s+)?turned\s+{number}\b")),
+                (ClaimKind::Title, format!(r"{title}\s+{name}")),
+                (ClaimKind::Knows, format!(r"{name}\s+(?:knew|knows|had\s+known|has\s+known|remembered|remembers)\s+(?:that\s+)?{fact}")),
+                (ClaimKind::Learns, format!(r"{name}\s+(?:had\s+)?(?:learned|learnt|learns|discovered|discovers|found\s+out|finds\s+out|realized|realised|realizes|realises|was\s+told)\s+(?:that\s+)?{fact}")),
+                (ClaimKind::Unaware, format!(r"{name}\s+(?:(?:didn['’]t|did\s+not|doesn['’]t|does\s+not|never)\s+(?:know|knew|suspected|suspect)|had\s+no\s+idea|has\s+no\s+idea)\s+(?:that\s+)?{fact}")),
+                (ClaimKind::Moves, format!(r"{name}\s+(?:arrived|went|left|returned|rode|walked|ran|fled|travell?ed|sailed|flew|journeyed|headed|came|set\s+out|stepped|climbed|crossed)\b")),
+            ]);
+        }
+        if let (Some(name), Some(place)) = (&name, &place) {
+            templates.extend([
+                (ClaimKind::Home, format!(r"{name}\s+(?:lived|lives|had\s+lived|has\s+lived|grew\s+up|dwelt|resided|resides)\s+in\s+{place}")),
+                (ClaimKind::Birthplace, format!(r"{name}\s+(?:was|had\s+been)\s+born\s+in\s+{place}")),
+                (ClaimKind::Whereabouts, format!(r"{name}\s+(?:was|stood|sat|waited|remained|stayed|lay|slept|hid)\s+(?:in|at|inside)\s+{place}")),
+            ]);
+        }
+        if let Some(place) = &place {
+            templates.extend([
+                (ClaimKind::LocationKind, format!(r"\bthe\s+{kind}\s+of\s+{place}")),
+                (ClaimKind::LocationKind, format!(r"{place},\s+(?:a|an|the)\s+{filler}{kind}\b")),
+            ]);
+        }
+
+        templates
+            .into_iter()
+            .map(|(kind, template)| {
+                Regex::new(&format!("(?i){}", template))
+                    .map(|regex| (kind, regex))
+                    .map_err(|e| StoryWeaverError::internal(format!("Invalid continuity pattern: {}", e)))
+            })
+            .collect()
+    }
+
+    /// Every character's full name, sorted
+    pub fn character_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.characters.values().map(|(_, name)| name.clone()).collect();
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    /// Every location's name, sorted
+    pub fn location_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.locations.values().map(|(_, name)| name.clone()).collect();
+        names.sort();
+        names
+    }
+
+    /// A location's name from its ID
+    pub fn location_name(&self, id: &str) -> Option<&str> {
+        self.locations.values().find(|(location_id, _)| location_id == id).map(|(_, name)| name.as_str())
+    }
+
+    /// The character a name refers to, as (ID, full name)
+    pub fn character(&self, name: &str) -> Option<&(String, String)> {
+        self.characters.get(name.trim()).or_else(|| {
+            self.characters
+                .iter()
+                .find(|(form, _)| form.eq_ignore_ascii_case(name.trim()))
+                .map(|(_, character)| character)
+        })
+    }
+
+    /// The location a name refers to, as (ID, name)
+    pub fn location(&self, name: &str) -> Option<&(String, String)> {
+        let name = name.trim().to_lowercase();
+        let name = name.strip_prefix("the ").unwrap_or(&name);
+        self.locations.get(name)
+    }
+
+    /// Locations named in a piece of text, by ID
+    pub fn locations_in(&self, text: &str) -> Vec<String> {
+        let text = text.to_lowercase();
+        self.locations
+            .iter()
+            .filter(|(name, _)| {
+                text.match_indices(name.as_str()).any(|(at, _)| {
+                    let before = text[..at].chars().next_back();
+                    let after = text[at + name.len()..].chars().next();
+                    !before.is_some_and(char::is_alphanumeric) && !after.is_some_and(char::is_alphanumeric)
+                })
+            })
+            .map(|(_, (id, _))| id.clone())
+            .collect()
+    }
+
+    /// Every claim the rules find in a document, in the order they appear
+    pub fn extract(&self, document_id: &str, text: &str) -> Vec<Claim> {
+        let mut claims = Vec::new();
+        for (kind, regex) in &self.patterns {
+            for captures in regex.captures_iter(text) {
+                let Some(whole) = captures.get(0) else {
+                    continue;
+                };
+                if let Some(filler) = captures.name("filler") {
+                    let stopped = filler.as_str().split_whitespace().any(|word| {
+                        let word = word.trim_end_matches(',').to_lowercase();
+                        FILLER_STOPS.contains(&word.as_str()) || word.ends_with("'s") || word.ends_with("’s")
+                    });
+                    if stopped {
+                        continue;
+                    }
+                }
+
+                let subject = if kind.about_location() {
+                    captures.name("place").and_then(|m| self.location(m.as_str()))
+                } else {
+                    captures.name("name").and_then(|m| self.character(m.as_str()))
+                };
+                let Some((subject_id, subject_name)) = subject.cloned() else {
+                    continue;
+                };
+                let raw = captures.name("value").map(|m| m.as_str()).unwrap_or_default();
+                let value = match kind {
+                    ClaimKind::EyeColor | ClaimKind::HairColor => normalize_color(raw),
+                    ClaimKind::Age => match parse_age(raw) {
+                        Some(age) => age.to_string(),
+                        None => continue,
+                    },
+                    ClaimKind::Title => normalize_title(raw),
+                    ClaimKind::LocationKind => raw.to_lowercase(),
+                    ClaimKind::Home | ClaimKind::Birthplace | ClaimKind::Whereabouts => {
+                        match captures.name("place").and_then(|m| self.location(m.as_str())) {
+                            Some((id, _)) => id.clone(),
+                            None => continue,
+                        }
+                    }
+                    ClaimKind::Moves => String::new(),
+                    ClaimKind::Knows | ClaimKind::Learns | ClaimKind::Unaware => raw.trim().to_string(),
+                };
+
+                claims.push(Claim {
+                    document_id: document_id.to_string(),
+                    subject_id,
+                    subject_name,
+                    kind: *kind,
+                    value,
+                    start: char_offset(text, whole.start()),
+                    end: char_offset(text, whole.end()),
+                    excerpt: whole.as_str().trim().to_string(),
+                    source: ClaimSource::Rules,
+                });
+            }
+        }
+        claims.sort_by_key(|claim| (claim.start, claim.end));
+        claims
+    }
+}
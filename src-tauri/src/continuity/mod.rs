@@ -0,0 +1,11 @@
+//! Manuscript continuity
+//! Reads a project's chapters and scenes for what they say about its
+//! characters and places, and reports where the prose disagrees with the
+//! story bible or with itself.
+
+pub mod assisted;
+pub mod checker;
+pub mod claims;
+
+pub use checker::{check_claims, check_continuity, manuscript_order, same_fact, Canon, ContinuityMode, ContinuityReport};
+pub use claims::{Cast, Claim, ClaimKind, ClaimSource};
@@ -12,6 +12,22 @@ mod fix_credit_usage_schema;
 mod phase5_collaboration_plugins;
 mod add_folder_support;
 mod _015_phase6_optimization;
+mod openai_compatible_providers;
+mod ai_routing;
+mod streaming_session_tracking;
+mod semantic_index;
+mod chat_threads;
+mod prompt_templates;
+mod ai_budgets;
+mod model_pricing;
+mod brainstorm_ideas;
+mod chapter_drafts;
+mod story_summaries;
+mod character_relationships;
+mod story_calendars;
+mod story_bible_aliases;
+mod story_bible_extractions;
+mod optional_model_context_window;
 
 /// Run all database migrations
 pub async fn run_migrations(pool: &Pool<Sqlite>) -> Result<()> {
@@ -44,6 +60,22 @@ pub async fn run_migrations(pool: &Pool<Sqlite>) -> Result<()> {
         ("017_phase5_collaboration_plugins", |pool| Box::pin(phase5_collaboration_plugins::up(&*pool))),
         ("018_add_folder_support", |pool| Box::pin(add_folder_support::up(&*pool))),
         ("019_phase6_optimization", |pool| Box::pin(_015_phase6_optimization::up(&*pool))),
+        ("020_openai_compatible_providers", |pool| Box::pin(openai_compatible_providers::up(&*pool))),
+        ("021_ai_routing", |pool| Box::pin(ai_routing::up(&*pool))),
+        ("022_streaming_session_tracking", |pool| Box::pin(streaming_session_tracking::up(&*pool))),
+        ("023_semantic_index", |pool| Box::pin(semantic_index::up(&*pool))),
+        ("024_chat_threads", |pool| Box::pin(chat_threads::up(&*pool))),
+        ("025_prompt_templates", |pool| Box::pin(prompt_templates::up(&*pool))),
+        ("026_ai_budgets", |pool| Box::pin(ai_budgets::up(&*pool))),
+        ("027_model_pricing", |pool| Box::pin(model_pricing::up(&*pool))),
+        ("028_brainstorm_ideas", |pool| Box::pin(brainstorm_ideas::up(&*pool))),
+        ("029_chapter_drafts", |pool| Box::pin(chapter_drafts::up(&*pool))),
+        ("030_story_summaries", |pool| Box::pin(story_summaries::up(&*pool))),
+        ("031_character_relationships", |pool| Box::pin(character_relationships::up(&*pool))),
+        ("032_story_calendars", |pool| Box::pin(story_calendars::up(&*pool))),
+        ("033_story_bible_aliases", |pool| Box::pin(story_bible_aliases::up(&*pool))),
+        ("034_story_bible_extractions", |pool| Box::pin(story_bible_extractions::up(&*pool))),
+        ("035_optional_model_context_window", |pool| Box::pin(optional_model_context_window::up(&*pool))),
     ];
     
     for (name, migration_fn) in migrations {
@@ -0,0 +1,75 @@
+//! Migration 026: AI budgets
+//! Spending limits per project, per provider and globally, and the alerts
+//! already sent for them. `credit_usage` is rebuilt so spend can be recorded
+//! against text project ids (or none) with its provider and estimated cost.
+
+use crate::error::{Result, StoryWeaverError};
+use sqlx::{Pool, Sqlite};
+
+pub async fn up(pool: &Pool<Sqlite>) -> Result<()> {
+    let statements = [
+        r#"
+        CREATE TABLE credit_usage_new (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            project_id TEXT, -- NULL for requests outside a project
+            operation_type TEXT NOT NULL,
+            provider TEXT,
+            model_used TEXT,
+            tokens_used INTEGER,
+            credits_consumed REAL,
+            cost_usd REAL NOT NULL DEFAULT 0,
+            operation_details TEXT,
+            session_id TEXT,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            FOREIGN KEY (project_id) REFERENCES projects(id) ON DELETE CASCADE
+        )
+        "#,
+        r#"
+        INSERT INTO credit_usage_new (id, project_id, operation_type, model_used, tokens_used,
+            credits_consumed, operation_details, session_id, created_at)
+        SELECT id, (SELECT p.id FROM projects p WHERE p.id = CAST(credit_usage.project_id AS TEXT)),
+            operation_type, model_used, tokens_used,
+            credits_consumed, operation_details, session_id, created_at
+        FROM credit_usage
+        "#,
+        "DROP TABLE credit_usage",
+        "ALTER TABLE credit_usage_new RENAME TO credit_usage",
+        "CREATE INDEX IF NOT EXISTS idx_credit_usage_project ON credit_usage(project_id)",
+        "CREATE INDEX IF NOT EXISTS idx_credit_usage_created_at ON credit_usage(created_at)",
+        "CREATE INDEX IF NOT EXISTS idx_credit_usage_provider ON credit_usage(provider, created_at)",
+        r#"
+        CREATE TABLE IF NOT EXISTS ai_budgets (
+            id TEXT PRIMARY KEY,
+            scope TEXT NOT NULL, -- global, project or provider
+            scope_id TEXT NOT NULL DEFAULT '', -- project id or provider key; '' for global
+            period TEXT NOT NULL, -- daily or monthly
+            limit_usd REAL NOT NULL,
+            enforcement TEXT NOT NULL DEFAULT 'block', -- block or confirm
+            alert_thresholds TEXT NOT NULL DEFAULT '[0.5,0.8,1.0]', -- JSON fractions of the limit
+            approved_period TEXT, -- period in which the user approved going over a confirm budget
+            created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            UNIQUE (scope, scope_id, period)
+        )
+        "#,
+        r#"
+        CREATE TABLE IF NOT EXISTS ai_budget_alerts (
+            budget_id TEXT NOT NULL,
+            period_start TEXT NOT NULL,
+            threshold REAL NOT NULL,
+            sent_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            PRIMARY KEY (budget_id, period_start, threshold),
+            FOREIGN KEY (budget_id) REFERENCES ai_budgets(id) ON DELETE CASCADE
+        )
+        "#,
+    ];
+
+    for statement in statements {
+        sqlx::query(statement)
+            .execute(pool)
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to set up AI budgets: {}", e)))?;
+    }
+
+    Ok(())
+}
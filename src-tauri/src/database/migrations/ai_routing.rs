@@ -0,0 +1,15 @@
+//! Migration 021: AI routing
+//! Records the provider route each generation actually took (including
+//! failovers) on ai_generation_history
+
+use crate::error::{Result, StoryWeaverError};
+use sqlx::{Pool, Sqlite};
+
+pub async fn up(pool: &Pool<Sqlite>) -> Result<()> {
+    sqlx::query("ALTER TABLE ai_generation_history ADD COLUMN route_taken TEXT")
+        .execute(pool)
+        .await
+        .map_err(|e| StoryWeaverError::database(format!("Failed to add route_taken to ai_generation_history: {}", e)))?;
+
+    Ok(())
+}
@@ -0,0 +1,64 @@
+//! Migration 028: Persistent brainstorming
+//! `brainstorm_sessions` is rebuilt with text ids matching projects, and each
+//! idea gets its own row so ratings, keepers, merges, themes and story bible
+//! exports survive restarts.
+
+use crate::error::{Result, StoryWeaverError};
+use sqlx::{Pool, Sqlite};
+
+pub async fn up(pool: &Pool<Sqlite>) -> Result<()> {
+    let statements = [
+        r#"
+        CREATE TABLE brainstorm_sessions_new (
+            id TEXT PRIMARY KEY,
+            project_id TEXT NOT NULL,
+            category TEXT NOT NULL,
+            seed_prompt TEXT,
+            session_notes TEXT NOT NULL DEFAULT '',
+            created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            FOREIGN KEY (project_id) REFERENCES projects(id) ON DELETE CASCADE
+        )
+        "#,
+        r#"
+        INSERT INTO brainstorm_sessions_new (id, project_id, category, seed_prompt, created_at, updated_at)
+        SELECT CAST(b.id AS TEXT), p.id, b.category, b.seed_prompt,
+            COALESCE(b.created_at, CURRENT_TIMESTAMP), COALESCE(b.updated_at, CURRENT_TIMESTAMP)
+        FROM brainstorm_sessions b
+        JOIN projects p ON p.id = CAST(b.project_id AS TEXT)
+        "#,
+        "DROP TABLE brainstorm_sessions",
+        "ALTER TABLE brainstorm_sessions_new RENAME TO brainstorm_sessions",
+        "CREATE INDEX IF NOT EXISTS idx_brainstorm_sessions_project ON brainstorm_sessions(project_id, updated_at)",
+        r#"
+        CREATE TABLE IF NOT EXISTS brainstorm_ideas (
+            id TEXT PRIMARY KEY,
+            session_id TEXT NOT NULL,
+            position INTEGER NOT NULL,
+            content TEXT NOT NULL,
+            category TEXT NOT NULL,
+            tags TEXT NOT NULL DEFAULT '[]', -- JSON array
+            rating INTEGER, -- 1-5 stars
+            notes TEXT NOT NULL DEFAULT '',
+            is_keeper INTEGER NOT NULL DEFAULT 0,
+            origin TEXT NOT NULL DEFAULT 'generated', -- generated, template, more_like_this or merged
+            derived_from TEXT NOT NULL DEFAULT '[]', -- JSON ids of the ideas it was seeded by or merged from
+            theme TEXT, -- theme cluster label
+            exported_type TEXT, -- character, world_element or plot_thread
+            exported_id TEXT,
+            created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            FOREIGN KEY (session_id) REFERENCES brainstorm_sessions(id) ON DELETE CASCADE
+        )
+        "#,
+        "CREATE INDEX IF NOT EXISTS idx_brainstorm_ideas_session ON brainstorm_ideas(session_id, position)",
+    ];
+
+    for statement in statements {
+        sqlx::query(statement)
+            .execute(pool)
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to set up brainstorm ideas: {}", e)))?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,53 @@
+//! Migration 029: Whole-chapter drafts
+//! A chapter draft walks an outline's scenes in order. Each drafted scene is
+//! checkpointed with its summary, so a failed draft resumes at the next scene.
+
+use crate::error::{Result, StoryWeaverError};
+use sqlx::{Pool, Sqlite};
+
+pub async fn up(pool: &Pool<Sqlite>) -> Result<()> {
+    let statements = [
+        r#"
+        CREATE TABLE IF NOT EXISTS chapter_drafts (
+            id TEXT PRIMARY KEY,
+            project_id TEXT NOT NULL,
+            outline_id TEXT NOT NULL,
+            document_id TEXT, -- document the draft is saved to; created when the draft completes if empty
+            task_id TEXT, -- background task drafting it, if any
+            status TEXT NOT NULL DEFAULT 'pending', -- pending, running, failed or completed
+            scene_count INTEGER NOT NULL DEFAULT 0,
+            credit_estimate REAL, -- total of the scenes' credit estimates
+            error_message TEXT,
+            version_id TEXT, -- document version holding the finished draft
+            created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            FOREIGN KEY (project_id) REFERENCES projects(id) ON DELETE CASCADE,
+            FOREIGN KEY (outline_id) REFERENCES outlines(id) ON DELETE CASCADE,
+            FOREIGN KEY (document_id) REFERENCES documents(id) ON DELETE SET NULL
+        )
+        "#,
+        "CREATE INDEX IF NOT EXISTS idx_chapter_drafts_outline ON chapter_drafts(outline_id, created_at)",
+        r#"
+        CREATE TABLE IF NOT EXISTS chapter_draft_scenes (
+            draft_id TEXT NOT NULL,
+            scene_id TEXT NOT NULL,
+            position INTEGER NOT NULL,
+            content TEXT NOT NULL,
+            summary TEXT NOT NULL, -- fed forward to the next scene
+            word_count INTEGER NOT NULL DEFAULT 0,
+            created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            PRIMARY KEY (draft_id, scene_id),
+            FOREIGN KEY (draft_id) REFERENCES chapter_drafts(id) ON DELETE CASCADE
+        )
+        "#,
+    ];
+
+    for statement in statements {
+        sqlx::query(statement)
+            .execute(pool)
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to set up chapter drafts: {}", e)))?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,44 @@
+//! Migration 031: Typed character relationships
+//! Directed edges between two characters of a project. An edge can begin
+//! and end at a chapter, so the graph can be read at any point in the story.
+
+use crate::error::{Result, StoryWeaverError};
+use sqlx::{Pool, Sqlite};
+
+pub async fn up(pool: &Pool<Sqlite>) -> Result<()> {
+    let statements = [
+        r#"
+        CREATE TABLE IF NOT EXISTS character_relationships (
+            id TEXT PRIMARY KEY,
+            project_id TEXT NOT NULL,
+            from_character_id TEXT NOT NULL,
+            to_character_id TEXT NOT NULL,
+            relationship_type TEXT NOT NULL, -- family, romantic, friend, ally, rival, enemy, mentor, colleague or other
+            label TEXT, -- e.g. "older sister", shown instead of the type
+            description TEXT,
+            mutual INTEGER NOT NULL DEFAULT 0, -- holds in both directions
+            starts_in_chapter_id TEXT, -- chapter where it begins; the start of the story when empty
+            ends_in_chapter_id TEXT, -- chapter where it ends or changes; still holds when empty
+            created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            FOREIGN KEY (project_id) REFERENCES projects(id) ON DELETE CASCADE,
+            FOREIGN KEY (from_character_id) REFERENCES characters(id) ON DELETE CASCADE,
+            FOREIGN KEY (to_character_id) REFERENCES characters(id) ON DELETE CASCADE,
+            FOREIGN KEY (starts_in_chapter_id) REFERENCES documents(id) ON DELETE SET NULL,
+            FOREIGN KEY (ends_in_chapter_id) REFERENCES documents(id) ON DELETE SET NULL
+        )
+        "#,
+        "CREATE INDEX IF NOT EXISTS idx_character_relationships_project ON character_relationships(project_id)",
+        "CREATE INDEX IF NOT EXISTS idx_character_relationships_from ON character_relationships(from_character_id)",
+        "CREATE INDEX IF NOT EXISTS idx_character_relationships_to ON character_relationships(to_character_id)",
+    ];
+
+    for statement in statements {
+        sqlx::query(statement)
+            .execute(pool)
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to set up character relationships: {}", e)))?;
+    }
+
+    Ok(())
+}
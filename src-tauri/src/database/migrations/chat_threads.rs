@@ -0,0 +1,54 @@
+//! Migration 024: Quick Chat threads
+//! Persistent chat threads scoped to a project or document, with their
+//! messages, a rolling summary of messages that no longer fit the model's
+//! context window, pinned story bible references and branch lineage
+
+use crate::error::{Result, StoryWeaverError};
+use sqlx::{Pool, Sqlite};
+
+pub async fn up(pool: &Pool<Sqlite>) -> Result<()> {
+    let statements = [
+        r#"
+        CREATE TABLE IF NOT EXISTS chat_threads (
+            id TEXT PRIMARY KEY,
+            project_id TEXT NOT NULL,
+            document_id TEXT,
+            title TEXT NOT NULL,
+            summary TEXT NOT NULL DEFAULT '',
+            summarized_count INTEGER NOT NULL DEFAULT 0, -- leading messages folded into summary
+            pinned_references TEXT NOT NULL DEFAULT '[]', -- JSON array of {element_type, element_id}
+            parent_thread_id TEXT,
+            branched_from_message_id TEXT,
+            created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            FOREIGN KEY (project_id) REFERENCES projects(id) ON DELETE CASCADE,
+            FOREIGN KEY (document_id) REFERENCES documents(id) ON DELETE SET NULL,
+            FOREIGN KEY (parent_thread_id) REFERENCES chat_threads(id) ON DELETE SET NULL
+        )
+        "#,
+        r#"
+        CREATE TABLE IF NOT EXISTS chat_messages (
+            id TEXT PRIMARY KEY,
+            thread_id TEXT NOT NULL,
+            position INTEGER NOT NULL,
+            role TEXT NOT NULL, -- user, assistant
+            content TEXT NOT NULL,
+            token_count INTEGER NOT NULL DEFAULT 0,
+            created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            FOREIGN KEY (thread_id) REFERENCES chat_threads(id) ON DELETE CASCADE,
+            UNIQUE (thread_id, position)
+        )
+        "#,
+        "CREATE INDEX IF NOT EXISTS idx_chat_threads_project_id ON chat_threads(project_id, updated_at)",
+        "CREATE INDEX IF NOT EXISTS idx_chat_threads_document_id ON chat_threads(document_id)",
+    ];
+
+    for statement in statements {
+        sqlx::query(statement)
+            .execute(pool)
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to set up chat threads: {}", e)))?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,38 @@
+//! Migration 027: Model pricing
+//! Per-model prices with the date they took effect, so costs can be estimated
+//! from user-editable data and recomputed for past generations
+
+use crate::error::{Result, StoryWeaverError};
+use sqlx::{Pool, Sqlite};
+
+pub async fn up(pool: &Pool<Sqlite>) -> Result<()> {
+    let statements = [
+        r#"
+        CREATE TABLE IF NOT EXISTS model_pricing (
+            id TEXT PRIMARY KEY,
+            provider TEXT NOT NULL DEFAULT '', -- '' for the model under any provider
+            model TEXT NOT NULL, -- '*' for every model of the provider
+            input_cost_per_1k REAL NOT NULL,
+            output_cost_per_1k REAL NOT NULL,
+            cached_input_cost_per_1k REAL, -- NULL bills cached input at the input price
+            image_cost REAL, -- per generated image
+            currency TEXT NOT NULL DEFAULT 'USD',
+            effective_from TEXT NOT NULL, -- YYYY-MM-DD
+            source TEXT NOT NULL DEFAULT 'user', -- builtin, user or import
+            created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            UNIQUE (provider, model, effective_from)
+        )
+        "#,
+        "CREATE INDEX IF NOT EXISTS idx_model_pricing_model ON model_pricing(model, effective_from)",
+    ];
+
+    for statement in statements {
+        sqlx::query(statement)
+            .execute(pool)
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to set up model pricing: {}", e)))?;
+    }
+
+    Ok(())
+}
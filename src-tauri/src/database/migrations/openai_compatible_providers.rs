@@ -0,0 +1,24 @@
+//! Migration 020: OpenAI-compatible providers
+//! Adds a provider type to ai_providers so self-hosted endpoints (Ollama, llama.cpp,
+//! LM Studio) can be told apart from the built-in providers and loaded at startup
+
+use crate::error::{Result, StoryWeaverError};
+use sqlx::{Pool, Sqlite};
+
+pub async fn up(pool: &Pool<Sqlite>) -> Result<()> {
+    sqlx::query(
+        r#"
+        ALTER TABLE ai_providers ADD COLUMN provider_type TEXT NOT NULL DEFAULT 'native'
+        "#,
+    )
+    .execute(pool)
+    .await
+    .map_err(|e| StoryWeaverError::database(format!("Failed to add provider_type to ai_providers: {}", e)))?;
+
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_ai_providers_provider_type ON ai_providers(provider_type)")
+        .execute(pool)
+        .await
+        .map_err(|e| StoryWeaverError::database(format!("Failed to create ai_providers provider_type index: {}", e)))?;
+
+    Ok(())
+}
@@ -0,0 +1,75 @@
+//! Migration 035: Optional model context window
+//! Rebuilds ai_model_configurations so `context_window` can be empty. Self-hosted
+//! models saved without a window then keep reporting their provider's own.
+
+use crate::error::{Result, StoryWeaverError};
+use sqlx::{Pool, Sqlite};
+
+pub async fn up(pool: &Pool<Sqlite>) -> Result<()> {
+    // prose_modes references this table; dropping it with foreign keys on would
+    // fail, so the rebuild runs on one connection with them switched off
+    let mut conn = pool
+        .acquire()
+        .await
+        .map_err(|e| StoryWeaverError::database(format!("Failed to acquire connection: {}", e)))?;
+
+    sqlx::query("PRAGMA foreign_keys = OFF")
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| StoryWeaverError::database(format!("Failed to disable foreign keys: {}", e)))?;
+
+    let statements = [
+        "BEGIN",
+        r#"
+        CREATE TABLE ai_model_configurations_new (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            provider_id INTEGER NOT NULL,
+            model_name TEXT NOT NULL,
+            display_name TEXT NOT NULL,
+            context_window INTEGER, -- empty when the provider's own window applies
+            max_output_tokens INTEGER NOT NULL,
+            supports_streaming BOOLEAN DEFAULT TRUE,
+            supports_images BOOLEAN DEFAULT FALSE,
+            cost_per_input_token REAL,
+            cost_per_output_token REAL,
+            cost_per_image REAL,
+            quality_tier TEXT DEFAULT 'standard',
+            specializations TEXT, -- JSON
+            is_active BOOLEAN DEFAULT TRUE,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            FOREIGN KEY (provider_id) REFERENCES ai_providers(id)
+        )
+        "#,
+        r#"
+        INSERT INTO ai_model_configurations_new (
+            id, provider_id, model_name, display_name, context_window, max_output_tokens,
+            supports_streaming, supports_images, cost_per_input_token, cost_per_output_token,
+            cost_per_image, quality_tier, specializations, is_active, created_at
+        )
+        SELECT id, provider_id, model_name, display_name, context_window, max_output_tokens,
+               supports_streaming, supports_images, cost_per_input_token, cost_per_output_token,
+               cost_per_image, quality_tier, specializations, is_active, created_at
+        FROM ai_model_configurations
+        "#,
+        "DROP TABLE ai_model_configurations",
+        "ALTER TABLE ai_model_configurations_new RENAME TO ai_model_configurations",
+        "COMMIT",
+    ];
+
+    let mut result = Ok(());
+    for statement in statements {
+        if let Err(e) = sqlx::query(statement).execute(&mut *conn).await {
+            let _ = sqlx::query("ROLLBACK").execute(&mut *conn).await;
+            result = Err(StoryWeaverError::database(format!("Failed to rebuild ai_model_configurations: {}", e)));
+            break;
+        }
+    }
+
+    // The connection goes back to the pool, so restore foreign keys either way
+    sqlx::query("PRAGMA foreign_keys = ON")
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| StoryWeaverError::database(format!("Failed to enable foreign keys: {}", e)))?;
+
+    result
+}
@@ -0,0 +1,39 @@
+//! Migration 025: Prompt templates
+//! Named, versioned prompt templates that can be overridden per project or
+//! prose mode, and the template version behind each AI generation
+
+use crate::error::{Result, StoryWeaverError};
+use sqlx::{Pool, Sqlite};
+
+pub async fn up(pool: &Pool<Sqlite>) -> Result<()> {
+    let statements = [
+        r#"
+        CREATE TABLE IF NOT EXISTS prompt_templates (
+            id TEXT PRIMARY KEY,
+            name TEXT NOT NULL,
+            version INTEGER NOT NULL, -- increases per name across every scope
+            project_id TEXT, -- NULL for every project
+            prose_mode TEXT, -- NULL for every prose mode
+            description TEXT,
+            body TEXT NOT NULL,
+            variables TEXT NOT NULL DEFAULT '[]', -- JSON array of {name, kind, required, description}
+            traffic_weight INTEGER NOT NULL DEFAULT 0, -- 0 = inactive; weights split traffic within a scope
+            created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            FOREIGN KEY (project_id) REFERENCES projects(id) ON DELETE CASCADE,
+            UNIQUE (name, version)
+        )
+        "#,
+        "CREATE INDEX IF NOT EXISTS idx_prompt_templates_scope ON prompt_templates(name, project_id, prose_mode)",
+        "ALTER TABLE ai_generation_history ADD COLUMN prompt_template TEXT",
+        "ALTER TABLE ai_generation_history ADD COLUMN prompt_template_version INTEGER",
+    ];
+
+    for statement in statements {
+        sqlx::query(statement)
+            .execute(pool)
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to set up prompt templates: {}", e)))?;
+    }
+
+    Ok(())
+}
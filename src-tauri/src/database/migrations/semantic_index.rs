@@ -0,0 +1,83 @@
+//! Migration 023: Semantic index
+//! Stores embeddings of document chunks and story bible entries for semantic
+//! search, with an FTS5 table over the same text so keyword (BM25) and
+//! embedding scores can be ranked together
+
+use crate::error::{Result, StoryWeaverError};
+use sqlx::{Pool, Sqlite};
+
+pub async fn up(pool: &Pool<Sqlite>) -> Result<()> {
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS semantic_entries (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            project_id TEXT NOT NULL,
+            source_type TEXT NOT NULL, -- document, character, location, world_element, scene
+            source_id TEXT NOT NULL,
+            chunk_index INTEGER NOT NULL DEFAULT 0,
+            start_position INTEGER NOT NULL DEFAULT 0,
+            end_position INTEGER NOT NULL DEFAULT 0,
+            title TEXT NOT NULL DEFAULT '',
+            content TEXT NOT NULL,
+            content_hash TEXT NOT NULL,
+            model TEXT NOT NULL,
+            dimension INTEGER NOT NULL,
+            embedding BLOB NOT NULL, -- little-endian f32
+            updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            UNIQUE (source_type, source_id, chunk_index)
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .map_err(|e| StoryWeaverError::database(format!("Failed to create semantic_entries table: {}", e)))?;
+
+    sqlx::query(
+        r#"
+        CREATE VIRTUAL TABLE IF NOT EXISTS semantic_entries_fts USING fts5(
+            title,
+            content,
+            content='semantic_entries',
+            content_rowid='id'
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .map_err(|e| StoryWeaverError::database(format!("Failed to create semantic_entries_fts table: {}", e)))?;
+
+    let statements = [
+        r#"
+        CREATE TRIGGER IF NOT EXISTS semantic_entries_fts_insert AFTER INSERT ON semantic_entries
+        BEGIN
+            INSERT INTO semantic_entries_fts(rowid, title, content) VALUES (new.id, new.title, new.content);
+        END
+        "#,
+        r#"
+        CREATE TRIGGER IF NOT EXISTS semantic_entries_fts_delete AFTER DELETE ON semantic_entries
+        BEGIN
+            INSERT INTO semantic_entries_fts(semantic_entries_fts, rowid, title, content)
+            VALUES ('delete', old.id, old.title, old.content);
+        END
+        "#,
+        r#"
+        CREATE TRIGGER IF NOT EXISTS semantic_entries_fts_update AFTER UPDATE ON semantic_entries
+        BEGIN
+            INSERT INTO semantic_entries_fts(semantic_entries_fts, rowid, title, content)
+            VALUES ('delete', old.id, old.title, old.content);
+            INSERT INTO semantic_entries_fts(rowid, title, content) VALUES (new.id, new.title, new.content);
+        END
+        "#,
+        "CREATE INDEX IF NOT EXISTS idx_semantic_entries_project_id ON semantic_entries(project_id)",
+        "CREATE INDEX IF NOT EXISTS idx_semantic_entries_source ON semantic_entries(source_type, source_id)",
+    ];
+
+    for statement in statements {
+        sqlx::query(statement)
+            .execute(pool)
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to set up semantic index: {}", e)))?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,51 @@
+//! Migration 033: Story bible aliases
+//! Other names a character, location or world element goes by in the prose:
+//! aliases, nicknames and titles. Entries point at one of three tables, so
+//! triggers remove them along with the entity.
+
+use crate::error::{Result, StoryWeaverError};
+use sqlx::{Pool, Sqlite};
+
+pub async fn up(pool: &Pool<Sqlite>) -> Result<()> {
+    let statements = [
+        r#"
+        CREATE TABLE IF NOT EXISTS story_bible_aliases (
+            id TEXT PRIMARY KEY,
+            project_id TEXT NOT NULL,
+            entity_type TEXT NOT NULL, -- character, location or world_element
+            entity_id TEXT NOT NULL,
+            alias TEXT NOT NULL,
+            alias_kind TEXT NOT NULL DEFAULT 'alias', -- alias, nickname or title
+            created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            FOREIGN KEY (project_id) REFERENCES projects(id) ON DELETE CASCADE,
+            UNIQUE (entity_type, entity_id, alias)
+        )
+        "#,
+        "CREATE INDEX IF NOT EXISTS idx_story_bible_aliases_project ON story_bible_aliases(project_id)",
+        "CREATE INDEX IF NOT EXISTS idx_story_bible_aliases_entity ON story_bible_aliases(entity_type, entity_id)",
+        r#"
+        CREATE TRIGGER IF NOT EXISTS delete_character_aliases AFTER DELETE ON characters BEGIN
+            DELETE FROM story_bible_aliases WHERE entity_type = 'character' AND entity_id = OLD.id;
+        END
+        "#,
+        r#"
+        CREATE TRIGGER IF NOT EXISTS delete_location_aliases AFTER DELETE ON locations BEGIN
+            DELETE FROM story_bible_aliases WHERE entity_type = 'location' AND entity_id = OLD.id;
+        END
+        "#,
+        r#"
+        CREATE TRIGGER IF NOT EXISTS delete_world_element_aliases AFTER DELETE ON worldbuilding BEGIN
+            DELETE FROM story_bible_aliases WHERE entity_type = 'world_element' AND entity_id = OLD.id;
+        END
+        "#,
+    ];
+
+    for statement in statements {
+        sqlx::query(statement)
+            .execute(pool)
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to set up story bible aliases: {}", e)))?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,79 @@
+//! Migration 034: Story bible extraction
+//! An extraction reads every document of a project and proposes story bible
+//! entries, each with citations to the passages it was found in. Scanned
+//! documents are checkpointed, so a failed extraction resumes at the next
+//! one. Proposals only become real entries when the writer accepts them.
+
+use crate::error::{Result, StoryWeaverError};
+use sqlx::{Pool, Sqlite};
+
+pub async fn up(pool: &Pool<Sqlite>) -> Result<()> {
+    let statements = [
+        r#"
+        CREATE TABLE IF NOT EXISTS story_bible_extractions (
+            id TEXT PRIMARY KEY,
+            project_id TEXT NOT NULL,
+            task_id TEXT, -- background task running it, if any
+            status TEXT NOT NULL DEFAULT 'pending', -- pending, running, failed or completed
+            document_count INTEGER NOT NULL DEFAULT 0,
+            error_message TEXT,
+            created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            FOREIGN KEY (project_id) REFERENCES projects(id) ON DELETE CASCADE
+        )
+        "#,
+        "CREATE INDEX IF NOT EXISTS idx_story_bible_extractions_project ON story_bible_extractions(project_id, created_at)",
+        r#"
+        CREATE TABLE IF NOT EXISTS story_bible_extraction_documents (
+            extraction_id TEXT NOT NULL,
+            document_id TEXT NOT NULL,
+            position INTEGER NOT NULL,
+            created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            PRIMARY KEY (extraction_id, document_id),
+            FOREIGN KEY (extraction_id) REFERENCES story_bible_extractions(id) ON DELETE CASCADE
+        )
+        "#,
+        r#"
+        CREATE TABLE IF NOT EXISTS story_bible_proposals (
+            id TEXT PRIMARY KEY,
+            extraction_id TEXT NOT NULL,
+            project_id TEXT NOT NULL,
+            entity_type TEXT NOT NULL, -- character, location, world_element or timeline_event
+            name TEXT NOT NULL,
+            category TEXT, -- role, location type or element type
+            description TEXT,
+            event_date TEXT, -- story date of a timeline event
+            possible_duplicate_id TEXT, -- existing entry with a similar name
+            possible_duplicate_name TEXT,
+            status TEXT NOT NULL DEFAULT 'pending', -- pending, accepted or rejected
+            created_entity_id TEXT, -- entry written when the proposal was accepted
+            created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            FOREIGN KEY (extraction_id) REFERENCES story_bible_extractions(id) ON DELETE CASCADE,
+            FOREIGN KEY (project_id) REFERENCES projects(id) ON DELETE CASCADE
+        )
+        "#,
+        "CREATE INDEX IF NOT EXISTS idx_story_bible_proposals_extraction ON story_bible_proposals(extraction_id)",
+        r#"
+        CREATE TABLE IF NOT EXISTS story_bible_proposal_citations (
+            proposal_id TEXT NOT NULL,
+            document_id TEXT NOT NULL,
+            start_position INTEGER NOT NULL, -- in characters from the start of the document
+            end_position INTEGER NOT NULL,
+            excerpt TEXT NOT NULL,
+            PRIMARY KEY (proposal_id, document_id, start_position),
+            FOREIGN KEY (proposal_id) REFERENCES story_bible_proposals(id) ON DELETE CASCADE,
+            FOREIGN KEY (document_id) REFERENCES documents(id) ON DELETE CASCADE
+        )
+        "#,
+    ];
+
+    for statement in statements {
+        sqlx::query(statement)
+            .execute(pool)
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to set up story bible extraction: {}", e)))?;
+    }
+
+    Ok(())
+}
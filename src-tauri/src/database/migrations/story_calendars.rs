@@ -0,0 +1,47 @@
+//! Migration 032: Story calendars
+//! Each project can say how its timeline dates are written. Timeline events
+//! keep the instant their date was read as, so they sort chronologically,
+//! along with how long they last and who dies in them. Existing events are
+//! read with the default calendar, which projects use until they set their own.
+
+use crate::database::operations::TimelineOps;
+use crate::error::{Result, StoryWeaverError};
+use crate::timeline::StoryCalendar;
+use sqlx::{Pool, Sqlite};
+
+pub async fn up(pool: &Pool<Sqlite>) -> Result<()> {
+    let statements = [
+        r#"
+        CREATE TABLE IF NOT EXISTS story_calendars (
+            project_id TEXT PRIMARY KEY,
+            calendar TEXT NOT NULL, -- JSON StoryCalendar
+            updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            FOREIGN KEY (project_id) REFERENCES projects(id) ON DELETE CASCADE
+        )
+        "#,
+        // Minutes since the calendar's epoch; empty when the date can't be read
+        "ALTER TABLE timeline_events ADD COLUMN event_instant INTEGER",
+        "ALTER TABLE timeline_events ADD COLUMN duration_minutes INTEGER",
+        // JSON array of IDs of characters who die in the event
+        "ALTER TABLE timeline_events ADD COLUMN deaths TEXT NOT NULL DEFAULT '[]'",
+        "CREATE INDEX IF NOT EXISTS idx_timeline_events_instant ON timeline_events(project_id, event_instant)",
+    ];
+
+    for statement in statements {
+        sqlx::query(statement)
+            .execute(pool)
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to set up story calendars: {}", e)))?;
+    }
+
+    let project_ids: Vec<String> = sqlx::query_scalar("SELECT DISTINCT project_id FROM timeline_events")
+        .fetch_all(pool)
+        .await
+        .map_err(|e| StoryWeaverError::database(format!("Failed to list timeline projects: {}", e)))?;
+    let calendar = StoryCalendar::default();
+    for project_id in project_ids {
+        TimelineOps::refresh_instants(pool, &project_id, &calendar).await?;
+    }
+
+    Ok(())
+}
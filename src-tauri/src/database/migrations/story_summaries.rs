@@ -0,0 +1,39 @@
+//! Migration 030: Rolling story summaries
+//! Per-scene, per-chapter and whole-book summaries of a project's manuscript.
+//! Each row keeps the hash of the text it was made from, so only changed
+//! documents are summarized again.
+
+use crate::error::{Result, StoryWeaverError};
+use sqlx::{Pool, Sqlite};
+
+pub async fn up(pool: &Pool<Sqlite>) -> Result<()> {
+    let statements = [
+        r#"
+        CREATE TABLE IF NOT EXISTS story_summaries (
+            id TEXT PRIMARY KEY,
+            project_id TEXT NOT NULL,
+            level TEXT NOT NULL, -- scene, chapter or book
+            source_id TEXT NOT NULL, -- document summarized; the project for the book
+            parent_id TEXT, -- chapter a scene belongs to
+            position INTEGER NOT NULL DEFAULT 0, -- order within the chapter or the book
+            title TEXT NOT NULL DEFAULT '',
+            summary TEXT NOT NULL,
+            content_hash TEXT NOT NULL, -- hash of the text the summary was made from
+            created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            UNIQUE (level, source_id),
+            FOREIGN KEY (project_id) REFERENCES projects(id) ON DELETE CASCADE
+        )
+        "#,
+        "CREATE INDEX IF NOT EXISTS idx_story_summaries_project ON story_summaries(project_id, level, position)",
+    ];
+
+    for statement in statements {
+        sqlx::query(statement)
+            .execute(pool)
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to set up story summaries: {}", e)))?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,52 @@
+//! Migration 022: Streaming session tracking
+//! Rebuilds streaming_sessions with the columns `StreamingSessionOps` reads
+//! and writes. The table created in migration 015 was never written to, so
+//! nothing is carried over.
+
+use crate::error::{Result, StoryWeaverError};
+use sqlx::{Pool, Sqlite};
+
+pub async fn up(pool: &Pool<Sqlite>) -> Result<()> {
+    sqlx::query("DROP TABLE IF EXISTS streaming_sessions")
+        .execute(pool)
+        .await
+        .map_err(|e| StoryWeaverError::database(format!("Failed to drop streaming_sessions table: {}", e)))?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE streaming_sessions (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            session_id TEXT UNIQUE NOT NULL,
+            project_id TEXT NOT NULL,
+            model_used TEXT NOT NULL,
+            prompt TEXT NOT NULL,
+            status TEXT NOT NULL DEFAULT 'active', -- active, completed, error, cancelled
+            generated_content TEXT,
+            tokens_generated INTEGER,
+            credits_consumed REAL,
+            error_message TEXT,
+            metadata TEXT, -- JSON
+            started_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            completed_at DATETIME,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .map_err(|e| StoryWeaverError::database(format!("Failed to create streaming_sessions table: {}", e)))?;
+
+    let indexes = [
+        "CREATE INDEX IF NOT EXISTS idx_streaming_sessions_project ON streaming_sessions(project_id)",
+        "CREATE INDEX IF NOT EXISTS idx_streaming_sessions_status ON streaming_sessions(status)",
+    ];
+    for index in indexes {
+        sqlx::query(index)
+            .execute(pool)
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to create streaming_sessions index: {}", e)))?;
+    }
+
+    Ok(())
+}
@@ -150,9 +150,9 @@ pub struct DatabaseStats {
 }
 
 #[cfg(test)]
-/// Initialize an in-memory SQLite database for tests and set it as the global pool.
+/// Open a fresh in-memory SQLite database for a single test, with foreign keys enabled and migrations applied.
 /// Uses a single connection to ensure the ':memory:' database remains consistent across operations.
-pub async fn init_test_db() -> Result<()> {
+pub async fn connect_test_db() -> Result<DbPool> {
     // Build in-memory connection options with foreign keys enabled
     let connect_options = sqlx::sqlite::SqliteConnectOptions::new()
         .filename(":memory:")
@@ -169,6 +169,14 @@ pub async fn init_test_db() -> Result<()> {
     // Run schema migrations
     migrations::run_migrations(&pool).await?;
 
+    Ok(pool)
+}
+
+#[cfg(test)]
+/// Initialize an in-memory SQLite database for tests and set it as the global pool.
+pub async fn init_test_db() -> Result<()> {
+    let pool = connect_test_db().await?;
+
     // Install pool into the global slot
     DB_POOL.set(Arc::new(pool))
         .map_err(|_| StoryWeaverError::database("Test database pool already initialized"))?;
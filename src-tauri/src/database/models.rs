@@ -234,6 +234,9 @@ pub struct TimelineEvent {
     pub visibility: VisibilityLevel,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    pub event_instant: Option<i64>, // event_date read with the project calendar, in minutes
+    pub duration_minutes: Option<i64>, // How long the event lasts
+    pub deaths: String, // JSON array of IDs of characters who die in the event
 }
 
 /// Event importance enumeration
@@ -320,6 +323,12 @@ pub struct AIGenerationHistory {
     pub token_count: i32,
     pub cost_estimate: Option<f64>,
     pub context_used: String, // JSON string of context elements used
+    #[sqlx(default)]
+    pub route_taken: Option<String>, // JSON RouteTaken: providers tried, and which one served it
+    #[sqlx(default)]
+    pub prompt_template: Option<String>, // Prompt template name that produced the prompt
+    #[sqlx(default)]
+    pub prompt_template_version: Option<i64>,
     pub created_at: DateTime<Utc>,
 }
 
@@ -343,6 +352,12 @@ pub enum AIGenerationType {
     CharacterDevelopment,
     #[sqlx(rename = "world_building")]
     WorldBuilding,
+    #[sqlx(rename = "plugin")]
+    Plugin,
+    #[sqlx(rename = "tool_call")]
+    ToolCall,
+    #[sqlx(rename = "summary")]
+    Summary,
 }
 
 /// User preferences model
@@ -1,6 +1,8 @@
 //! AI Generation History Operations
 
-use crate::database::models::AIGenerationHistory;
+use crate::ai::token_counter::pricing;
+use crate::ai::{tokenizer, AIProvider, RenderedPrompt};
+use crate::database::models::{AIGenerationHistory, AIGenerationType};
 use crate::error::{StoryWeaverError, Result};
 use sqlx::{Pool, Sqlite};
 use uuid::Uuid;
@@ -17,8 +19,9 @@ impl super::AIHistoryOps {
             r#"
             INSERT INTO ai_generation_history (
                 id, project_id, document_id, generation_type, provider, model,
-                prompt, response, token_count, cost_estimate, context_used, created_at
-            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+                prompt, response, token_count, cost_estimate, context_used, route_taken,
+                prompt_template, prompt_template_version, created_at
+            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
             "#,
             record.id,
             record.project_id,
@@ -31,6 +34,9 @@ impl super::AIHistoryOps {
             record.token_count,
             record.cost_estimate,
             record.context_used,
+            record.route_taken,
+            record.prompt_template,
+            record.prompt_template_version,
             record.created_at
         )
         .execute(&*pool)
@@ -40,6 +46,45 @@ impl super::AIHistoryOps {
         Ok(record)
     }
 
+    /// Record a generation made on the backend from a rendered prompt,
+    /// crediting the template version it came from. Failures are logged, so
+    /// the history never stops the work it describes.
+    pub async fn record_rendered(
+        pool: &Pool<Sqlite>,
+        project_id: &str,
+        document_id: Option<&str>,
+        generation_type: AIGenerationType,
+        provider: &dyn AIProvider,
+        prompt: RenderedPrompt,
+        response: &str,
+    ) {
+        let provider_name = provider.get_provider_name().to_string();
+        let model = provider.get_model_name().to_string();
+        let input_tokens = tokenizer::count_tokens(&model, &prompt.text) as u32;
+        let output_tokens = tokenizer::count_tokens(&model, response) as u32;
+        let cost = pricing().estimate(&provider_name, &model, input_tokens, output_tokens);
+        let record = AIGenerationHistory {
+            id: String::new(),
+            project_id: project_id.to_string(),
+            document_id: document_id.map(str::to_string),
+            generation_type,
+            provider: provider_name,
+            model,
+            prompt: prompt.text,
+            response: response.to_string(),
+            token_count: output_tokens as i32,
+            cost_estimate: Some(cost.total_cost),
+            context_used: "{}".to_string(),
+            route_taken: None,
+            prompt_template: Some(prompt.template.name),
+            prompt_template_version: Some(prompt.template.version),
+            created_at: chrono::Utc::now(),
+        };
+        if let Err(e) = Self::create(pool, record).await {
+            tracing::warn!("Failed to record AI generation history: {}", e);
+        }
+    }
+
     /// Get AI generation history by project
     pub async fn get_by_project(pool: &Pool<Sqlite>, project_id: &str, limit: Option<i32>) -> Result<Vec<AIGenerationHistory>> {
         let limit = limit.unwrap_or(100);
@@ -109,7 +154,7 @@ impl super::AIHistoryOps {
             UPDATE ai_generation_history SET
                 project_id = ?, document_id = ?, generation_type = ?, provider = ?,
                 model = ?, prompt = ?, response = ?, token_count = ?, cost_estimate = ?,
-                context_used = ?
+                context_used = ?, route_taken = ?, prompt_template = ?, prompt_template_version = ?
             WHERE id = ?
             "#,
             record.project_id,
@@ -122,6 +167,9 @@ impl super::AIHistoryOps {
             record.token_count,
             record.cost_estimate,
             record.context_used,
+            record.route_taken,
+            record.prompt_template,
+            record.prompt_template_version,
             record.id
         )
         .execute(&*pool)
@@ -130,4 +178,16 @@ impl super::AIHistoryOps {
 
         Ok(())
     }
-}
\ No newline at end of file
+
+    /// Replace the cost estimate of a record, e.g. after prices are corrected
+    pub async fn update_cost_estimate(pool: &Pool<Sqlite>, id: &str, cost_estimate: f64) -> Result<()> {
+        sqlx::query("UPDATE ai_generation_history SET cost_estimate = ? WHERE id = ?")
+            .bind(cost_estimate)
+            .bind(id)
+            .execute(&*pool)
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to update AI history cost: {}", e)))?;
+
+        Ok(())
+    }
+}
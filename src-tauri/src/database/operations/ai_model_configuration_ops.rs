@@ -11,7 +11,8 @@ pub struct AIModelConfiguration {
     pub provider_id: i32,
     pub model_name: String,
     pub display_name: String,
-    pub context_window: i32,
+    /// Empty when the provider's own context window applies
+    pub context_window: Option<i32>,
     pub max_output_tokens: i32,
     pub supports_streaming: bool,
     pub supports_images: bool,
@@ -78,7 +79,7 @@ impl super::AIModelConfigurationOps {
             provider_id: r.provider_id as i32,
             model_name: r.model_name,
             display_name: r.display_name,
-            context_window: r.context_window as i32,
+            context_window: r.context_window.map(|w| w as i32),
             max_output_tokens: r.max_output_tokens as i32,
             supports_streaming: r.supports_streaming.unwrap_or(true),
             supports_images: r.supports_images.unwrap_or(false),
@@ -112,7 +113,7 @@ impl super::AIModelConfigurationOps {
             provider_id: r.provider_id as i32,
             model_name: r.model_name,
             display_name: r.display_name,
-            context_window: r.context_window as i32,
+            context_window: r.context_window.map(|w| w as i32),
             max_output_tokens: r.max_output_tokens as i32,
             supports_streaming: r.supports_streaming.unwrap_or(true),
             supports_images: r.supports_images.unwrap_or(false),
@@ -145,7 +146,7 @@ impl super::AIModelConfigurationOps {
             provider_id: r.provider_id as i32,
             model_name: r.model_name,
             display_name: r.display_name,
-            context_window: r.context_window as i32,
+            context_window: r.context_window.map(|w| w as i32),
             max_output_tokens: r.max_output_tokens as i32,
             supports_streaming: r.supports_streaming.unwrap_or(true),
             supports_images: r.supports_images.unwrap_or(false),
@@ -178,7 +179,7 @@ impl super::AIModelConfigurationOps {
             provider_id: r.provider_id as i32,
             model_name: r.model_name,
             display_name: r.display_name,
-            context_window: r.context_window as i32,
+            context_window: r.context_window.map(|w| w as i32),
             max_output_tokens: r.max_output_tokens as i32,
             supports_streaming: r.supports_streaming.unwrap_or(true),
             supports_images: r.supports_images.unwrap_or(false),
@@ -268,7 +269,7 @@ impl super::AIModelConfigurationOps {
             provider_id: r.provider_id as i32,
             model_name: r.model_name,
             display_name: r.display_name,
-            context_window: r.context_window as i32,
+            context_window: r.context_window.map(|w| w as i32),
             max_output_tokens: r.max_output_tokens as i32,
             supports_streaming: r.supports_streaming.unwrap_or(true),
             supports_images: r.supports_images.unwrap_or(false),
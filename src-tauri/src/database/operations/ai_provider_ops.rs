@@ -1,8 +1,9 @@
 //! AI Provider database operations
 //! Provides functions to interact with the ai_providers table
 
+use crate::ai::openai::OpenAICompatibleConfig;
 use crate::error::{Result, StoryWeaverError};
-use sqlx::{Pool, Sqlite};
+use sqlx::{Pool, Row, Sqlite};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -158,3 +159,161 @@ impl super::AIProviderOps {
         Ok(())
     }
 }
+
+/// `provider_type` tag for self-hosted endpoints speaking the OpenAI protocol
+pub const OPENAI_COMPATIBLE_PROVIDER_TYPE: &str = "openai_compatible";
+
+/// OpenAI-compatible provider persistence
+impl super::AIProviderOps {
+    /// Insert or update an OpenAI-compatible provider together with its chat and
+    /// embedding models. The API key is not stored here; it lives in the OS keychain.
+    /// Fails if a built-in provider already uses the name.
+    pub async fn save_openai_compatible(pool: &Pool<Sqlite>, config: &OpenAICompatibleConfig) -> Result<i64> {
+        let mut tx = pool.begin().await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to begin transaction: {}", e)))?;
+
+        // Only a provider saved from here before may be updated
+        let saved = sqlx::query(
+            r#"
+            INSERT INTO ai_providers (name, display_name, api_endpoint, is_active, provider_type)
+            VALUES (?, ?, ?, TRUE, ?)
+            ON CONFLICT(name) DO UPDATE SET
+                display_name = excluded.display_name,
+                api_endpoint = excluded.api_endpoint
+            WHERE ai_providers.provider_type = excluded.provider_type
+            "#,
+        )
+        .bind(&config.name)
+        .bind(&config.display_name)
+        .bind(&config.base_url)
+        .bind(OPENAI_COMPATIBLE_PROVIDER_TYPE)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| StoryWeaverError::database(format!("Failed to save OpenAI-compatible provider: {}", e)))?;
+
+        if saved.rows_affected() == 0 {
+            return Err(StoryWeaverError::input_validation(
+                "name",
+                format!("'{}' is already used by a built-in provider", config.name).as_str(),
+            ));
+        }
+
+        let provider_id: i64 = sqlx::query_scalar("SELECT id FROM ai_providers WHERE name = ? AND provider_type = ?")
+            .bind(&config.name)
+            .bind(OPENAI_COMPATIBLE_PROVIDER_TYPE)
+            .fetch_one(&mut *tx)
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to look up saved provider: {}", e)))?;
+
+        sqlx::query("DELETE FROM ai_model_configurations WHERE provider_id = ?")
+            .bind(provider_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to clear provider models: {}", e)))?;
+
+        // Left empty, the provider's own context window applies when it is loaded
+        let context_window = config.context_window.map(|c| c as i64);
+        let mut models = vec![(config.model.as_str(), r#"["chat"]"#)];
+        // Without its own embedding model the provider embeds with the chat model,
+        // which is stored once
+        if let Some(embedding_model) = config.embedding_model.as_deref().filter(|m| *m != config.model) {
+            models.push((embedding_model, r#"["embedding"]"#));
+        }
+
+        for (model_name, specializations) in models {
+            sqlx::query(
+                r#"
+                INSERT INTO ai_model_configurations (
+                    provider_id, model_name, display_name, context_window, max_output_tokens,
+                    supports_streaming, supports_images, quality_tier, specializations, is_active
+                )
+                VALUES (?, ?, ?, ?, ?, TRUE, FALSE, 'local', ?, TRUE)
+                "#,
+            )
+            .bind(provider_id)
+            .bind(model_name)
+            .bind(model_name)
+            .bind(context_window)
+            .bind(2048_i64)
+            .bind(specializations)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to save provider model: {}", e)))?;
+        }
+
+        tx.commit().await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to commit provider: {}", e)))?;
+
+        Ok(provider_id)
+    }
+
+    /// List active OpenAI-compatible providers as ready-to-build configurations
+    /// (without API keys)
+    pub async fn list_openai_compatible(pool: &Pool<Sqlite>) -> Result<Vec<OpenAICompatibleConfig>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT p.name, p.display_name, p.api_endpoint,
+                   chat.model_name AS chat_model, chat.context_window AS context_window,
+                   emb.model_name AS embedding_model
+            FROM ai_providers p
+            LEFT JOIN ai_model_configurations chat
+                ON chat.provider_id = p.id AND chat.specializations = '["chat"]'
+            LEFT JOIN ai_model_configurations emb
+                ON emb.provider_id = p.id AND emb.specializations = '["embedding"]'
+            WHERE p.provider_type = ? AND p.is_active = 1
+            ORDER BY p.name
+            "#,
+        )
+        .bind(OPENAI_COMPATIBLE_PROVIDER_TYPE)
+        .fetch_all(&*pool)
+        .await
+        .map_err(|e| StoryWeaverError::database(format!("Failed to list OpenAI-compatible providers: {}", e)))?;
+
+        let mut configs = Vec::new();
+        for row in rows {
+            let base_url: Option<String> = row.get("api_endpoint");
+            let model: Option<String> = row.get("chat_model");
+            // A provider without an endpoint or a chat model can't be used
+            let (Some(base_url), Some(model)) = (base_url, model) else {
+                continue;
+            };
+            let context_window: Option<i64> = row.get("context_window");
+
+            configs.push(OpenAICompatibleConfig {
+                name: row.get("name"),
+                display_name: row.get("display_name"),
+                base_url,
+                api_key: None,
+                model,
+                embedding_model: row.get("embedding_model"),
+                context_window: context_window.map(|c| c as usize),
+            });
+        }
+
+        Ok(configs)
+    }
+
+    /// Remove an OpenAI-compatible provider and its models
+    pub async fn delete_openai_compatible(pool: &Pool<Sqlite>, name: &str) -> Result<()> {
+        sqlx::query(
+            r#"
+            DELETE FROM ai_model_configurations
+            WHERE provider_id IN (SELECT id FROM ai_providers WHERE name = ? AND provider_type = ?)
+            "#,
+        )
+        .bind(name)
+        .bind(OPENAI_COMPATIBLE_PROVIDER_TYPE)
+        .execute(&*pool)
+        .await
+        .map_err(|e| StoryWeaverError::database(format!("Failed to delete provider models: {}", e)))?;
+
+        sqlx::query("DELETE FROM ai_providers WHERE name = ? AND provider_type = ?")
+            .bind(name)
+            .bind(OPENAI_COMPATIBLE_PROVIDER_TYPE)
+            .execute(&*pool)
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to delete OpenAI-compatible provider: {}", e)))?;
+
+        Ok(())
+    }
+}
@@ -27,6 +27,8 @@ impl super::BackgroundTaskOps {
             TaskType::Export => "export",
             TaskType::Import => "import",
             TaskType::Backup => "backup",
+            TaskType::ChapterDraft => "chapter_draft",
+            TaskType::StoryBibleExtraction => "story_bible_extraction",
             TaskType::Other(name) => name,
         };
         
@@ -215,6 +217,8 @@ impl super::BackgroundTaskOps {
             "export" => TaskType::Export,
             "import" => TaskType::Import,
             "backup" => TaskType::Backup,
+            "chapter_draft" => TaskType::ChapterDraft,
+            "story_bible_extraction" => TaskType::StoryBibleExtraction,
             other => TaskType::Other(other.to_string()),
         };
         
@@ -264,6 +268,7 @@ impl super::BackgroundTaskOps {
             project_id: record.get("project_id"),
             document_id: record.get("document_id"),
             metadata,
+            cancellation: Default::default(),
         })
     }
 }
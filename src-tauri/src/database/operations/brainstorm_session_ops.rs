@@ -1,419 +1,375 @@
 //! Brainstorm Session database operations
-//! Provides functions to interact with the brainstorm_sessions table
+//! Provides functions to interact with the brainstorm_sessions and brainstorm_ideas tables
 
+use crate::ai::brainstorm::{BrainstormCategory, BrainstormIdea, BrainstormSession, IdeaOrigin, StoryBibleLink};
 use crate::error::{Result, StoryWeaverError};
-use sqlx::{Pool, Sqlite};
-use serde::{Deserialize, Serialize};
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct BrainstormSession {
-    pub id: Option<i32>,
-    pub project_id: i32,
-    pub session_name: String,
-    pub session_type: String, // "character", "plot", "worldbuilding", "dialogue", "general"
-    pub initial_prompt: String,
-    pub context_data: Option<String>, // JSON
-    pub generated_ideas: Option<String>, // JSON array of ideas
-    pub selected_ideas: Option<String>, // JSON array of selected idea IDs
-    pub session_notes: Option<String>,
-    pub model_used: String,
-    pub total_tokens: Option<i32>,
-    pub cost_credits: Option<f64>,
-    pub status: String, // "active", "completed", "archived"
-    pub created_at: Option<String>,
-    pub updated_at: Option<String>,
+use chrono::Utc;
+use sqlx::{Pool, Row, Sqlite};
+use std::str::FromStr;
+
+fn idea_from_row(row: &sqlx::sqlite::SqliteRow) -> BrainstormIdea {
+    let tags: String = row.get("tags");
+    let derived_from: String = row.get("derived_from");
+    let exported_type: Option<String> = row.get("exported_type");
+    let exported_id: Option<String> = row.get("exported_id");
+    BrainstormIdea {
+        id: row.get("id"),
+        content: row.get("content"),
+        category: row.get("category"),
+        tags: serde_json::from_str(&tags).unwrap_or_default(),
+        rating: row.get("rating"),
+        notes: row.get("notes"),
+        is_keeper: row.get("is_keeper"),
+        origin: IdeaOrigin::parse(row.get("origin")),
+        derived_from: serde_json::from_str(&derived_from).unwrap_or_default(),
+        theme: row.get("theme"),
+        exported_to: exported_type.zip(exported_id).and_then(|(kind, id)| StoryBibleLink::parse(&kind, id)),
+        created_at: row.get("created_at"),
+    }
+}
+
+fn session_from_row(row: &sqlx::sqlite::SqliteRow, ideas: Vec<BrainstormIdea>) -> BrainstormSession {
+    let category: String = row.get("category");
+    BrainstormSession {
+        id: row.get("id"),
+        project_id: row.get("project_id"),
+        category: BrainstormCategory::from_str(&category).unwrap_or_default(),
+        seed_prompt: row.get("seed_prompt"),
+        keepers: ideas.iter().filter(|idea| idea.is_keeper).map(|idea| idea.id.clone()).collect(),
+        ideas,
+        session_notes: row.get("session_notes"),
+        created_at: row.get("created_at"),
+        updated_at: row.get("updated_at"),
+    }
+}
+
+const INSERT_IDEA: &str = r#"
+    INSERT INTO brainstorm_ideas (id, session_id, position, content, category, tags, rating, notes,
+        is_keeper, origin, derived_from, theme, created_at)
+    VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+"#;
+
+fn bind_idea<'q>(
+    query: sqlx::query::Query<'q, Sqlite, sqlx::sqlite::SqliteArguments<'q>>,
+    session_id: &'q str,
+    position: i64,
+    idea: &'q BrainstormIdea,
+) -> sqlx::query::Query<'q, Sqlite, sqlx::sqlite::SqliteArguments<'q>> {
+    query
+        .bind(&idea.id)
+        .bind(session_id)
+        .bind(position)
+        .bind(&idea.content)
+        .bind(&idea.category)
+        .bind(serde_json::to_string(&idea.tags).unwrap_or_else(|_| "[]".to_string()))
+        .bind(idea.rating)
+        .bind(&idea.notes)
+        .bind(idea.is_keeper)
+        .bind(idea.origin.as_str())
+        .bind(serde_json::to_string(&idea.derived_from).unwrap_or_else(|_| "[]".to_string()))
+        .bind(&idea.theme)
+        .bind(idea.created_at)
 }
 
 /// Brainstorm Session database operations
 impl super::BrainstormSessionOps {
-    /// Create a new brainstorm session
-    pub async fn create(pool: &Pool<Sqlite>, session: &BrainstormSession) -> Result<i64> {
-        let result = sqlx::query!(
+    /// Create a brainstorm session and any ideas it already has
+    pub async fn create(pool: &Pool<Sqlite>, session: &BrainstormSession) -> Result<()> {
+        sqlx::query(
             r#"
-            INSERT INTO brainstorm_sessions (
-                project_id, session_name, session_type, initial_prompt, context_data,
-                generated_ideas, selected_ideas, session_notes, model_used, total_tokens,
-                cost_credits, status
-            )
-            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            INSERT INTO brainstorm_sessions (id, project_id, category, seed_prompt, session_notes, created_at, updated_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?)
             "#,
-            session.project_id,
-            session.session_name,
-            session.session_type,
-            session.initial_prompt,
-            session.context_data,
-            session.generated_ideas,
-            session.selected_ideas,
-            session.session_notes,
-            session.model_used,
-            session.total_tokens,
-            session.cost_credits,
-            session.status
         )
-        .execute(&*pool)
+        .bind(&session.id)
+        .bind(&session.project_id)
+        .bind(session.category.to_string())
+        .bind(&session.seed_prompt)
+        .bind(&session.session_notes)
+        .bind(session.created_at)
+        .bind(session.updated_at)
+        .execute(pool)
         .await
         .map_err(|e| StoryWeaverError::database(format!("Failed to create brainstorm session: {}", e)))?;
 
-        Ok(result.last_insert_rowid())
+        if !session.ideas.is_empty() {
+            Self::add_ideas(pool, &session.id, &session.ideas).await?;
+        }
+        Ok(())
     }
 
-    /// Get a brainstorm session by ID
-    pub async fn get_by_id(pool: &Pool<Sqlite>, id: i32) -> Result<Option<BrainstormSession>> {
-        let row = sqlx::query!(
-            r#"
-            SELECT id, project_id, session_name, session_type, initial_prompt, context_data,
-                   generated_ideas, selected_ideas, session_notes, model_used, total_tokens,
-                   cost_credits, status, created_at, updated_at
-            FROM brainstorm_sessions WHERE id = ?
-            "#,
-            id
-        )
-        .fetch_optional(&*pool)
-        .await
-        .map_err(|e| StoryWeaverError::database(format!("Failed to get brainstorm session: {}", e)))?;
-
-        Ok(row.map(|r| BrainstormSession {
-            id: r.id.and_then(|id| id.parse().ok()),
-            project_id: r.project_id.parse().unwrap_or_else(|e| {
-                tracing::warn!("Failed to parse project_id: {}", e);
-                0 // Return 0 as fallback for i32
-            }),
-            session_name: r.session_name,
-            session_type: r.session_type,
-            initial_prompt: r.initial_prompt,
-            context_data: r.context_data,
-            generated_ideas: r.generated_ideas,
-            selected_ideas: r.selected_ideas,
-            session_notes: r.session_notes,
-            model_used: r.model_used,
-            total_tokens: r.total_tokens.map(|t| t as i32),
-            cost_credits: r.cost_credits.map(|c| c as f64),
-            status: r.status,
-            created_at: r.created_at.map(|dt| dt.to_string()),
-            updated_at: r.updated_at.map(|dt| dt.to_string()),
-        }))
+    /// Get a brainstorm session with its ideas in order
+    pub async fn get_by_id(pool: &Pool<Sqlite>, id: &str) -> Result<Option<BrainstormSession>> {
+        let row = sqlx::query("SELECT * FROM brainstorm_sessions WHERE id = ?")
+            .bind(id)
+            .fetch_optional(pool)
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to get brainstorm session: {}", e)))?;
+
+        match row {
+            Some(row) => {
+                let ideas = Self::get_ideas(pool, id).await?;
+                Ok(Some(session_from_row(&row, ideas)))
+            }
+            None => Ok(None),
+        }
     }
 
-    /// Get brainstorm sessions by project ID
-    pub async fn get_by_project(pool: &Pool<Sqlite>, project_id: i32) -> Result<Vec<BrainstormSession>> {
-        let rows = sqlx::query!(
-            r#"
-            SELECT id, project_id, session_name, session_type, initial_prompt, context_data,
-                   generated_ideas, selected_ideas, session_notes, model_used, total_tokens,
-                   cost_credits, status, created_at, updated_at
-            FROM brainstorm_sessions WHERE project_id = ? ORDER BY updated_at DESC
-            "#,
-            project_id
-        )
-        .fetch_all(&*pool)
-        .await
-        .map_err(|e| StoryWeaverError::database(format!("Failed to get brainstorm sessions by project: {}", e)))?;
-
-        Ok(rows.into_iter().filter_map(|r| {
-            let project_id = match r.project_id.parse::<i32>() {
-                Ok(id) => id,
-                Err(e) => {
-                    tracing::warn!("Skipping row with invalid project_id: {}", e);
-                    return None;
-                }
-            };
-            Some(BrainstormSession {
-                id: r.id.and_then(|id| id.parse().ok()),
-                project_id,
-            session_name: r.session_name,
-            session_type: r.session_type,
-            initial_prompt: r.initial_prompt,
-            context_data: r.context_data,
-            generated_ideas: r.generated_ideas,
-            selected_ideas: r.selected_ideas,
-            session_notes: r.session_notes,
-            model_used: r.model_used,
-            total_tokens: r.total_tokens.map(|t| t as i32),
-            cost_credits: r.cost_credits.map(|c| c as f64),
-            status: r.status,
-            created_at: r.created_at.map(|dt| dt.to_string()),
-            updated_at: r.updated_at.map(|dt| dt.to_string()),
-        })
-        }).collect())
+    /// Like `get_by_id`, but a missing session is an error
+    pub async fn get(pool: &Pool<Sqlite>, id: &str) -> Result<BrainstormSession> {
+        Self::get_by_id(pool, id)
+            .await?
+            .ok_or_else(|| StoryWeaverError::not_found("brainstorm session", id))
     }
 
-    /// Get brainstorm sessions by type
-    pub async fn get_by_type(pool: &Pool<Sqlite>, session_type: &str) -> Result<Vec<BrainstormSession>> {
-        let rows = sqlx::query!(
-            r#"
-            SELECT id, project_id, session_name, session_type, initial_prompt, context_data,
-                   generated_ideas, selected_ideas, session_notes, model_used, total_tokens,
-                   cost_credits, status, created_at, updated_at
-            FROM brainstorm_sessions WHERE session_type = ? ORDER BY updated_at DESC
-            "#,
-            session_type
-        )
-        .fetch_all(&*pool)
-        .await
-        .map_err(|e| StoryWeaverError::database(format!("Failed to get brainstorm sessions by type: {}", e)))?;
-
-        Ok(rows.into_iter().filter_map(|r| {
-            let project_id = match r.project_id.parse::<i32>() {
-                Ok(id) => id,
-                Err(e) => {
-                    tracing::warn!("Skipping row with invalid project_id: {}", e);
-                    return None;
-                }
-            };
-            Some(BrainstormSession {
-                id: r.id.and_then(|id| id.parse().ok()),
-                project_id,
-            session_name: r.session_name,
-            session_type: r.session_type,
-            initial_prompt: r.initial_prompt,
-            context_data: r.context_data,
-            generated_ideas: r.generated_ideas,
-            selected_ideas: r.selected_ideas,
-            session_notes: r.session_notes,
-            model_used: r.model_used,
-            total_tokens: r.total_tokens.map(|t| t as i32),
-            cost_credits: r.cost_credits.map(|c| c as f64),
-            status: r.status,
-            created_at: r.created_at.map(|dt| dt.to_string()),
-            updated_at: r.updated_at.map(|dt| dt.to_string()),
-        })
-        }).collect())
+    /// Get brainstorm sessions by project ID, most recently changed first
+    pub async fn get_by_project(pool: &Pool<Sqlite>, project_id: &str) -> Result<Vec<BrainstormSession>> {
+        let rows = sqlx::query("SELECT * FROM brainstorm_sessions WHERE project_id = ? ORDER BY updated_at DESC")
+            .bind(project_id)
+            .fetch_all(pool)
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to get brainstorm sessions by project: {}", e)))?;
+
+        let mut sessions = Vec::with_capacity(rows.len());
+        for row in rows {
+            let id: String = row.get("id");
+            let ideas = Self::get_ideas(pool, &id).await?;
+            sessions.push(session_from_row(&row, ideas));
+        }
+        Ok(sessions)
     }
 
-    /// Get brainstorm sessions by status
-    pub async fn get_by_status(pool: &Pool<Sqlite>, status: &str) -> Result<Vec<BrainstormSession>> {
-        let rows = sqlx::query!(
-            r#"
-            SELECT id, project_id, session_name, session_type, initial_prompt, context_data,
-                   generated_ideas, selected_ideas, session_notes, model_used, total_tokens,
-                   cost_credits, status, created_at, updated_at
-            FROM brainstorm_sessions WHERE status = ? ORDER BY updated_at DESC
-            "#,
-            status
-        )
-        .fetch_all(&*pool)
-        .await
-        .map_err(|e| StoryWeaverError::database(format!("Failed to get brainstorm sessions by status: {}", e)))?;
-
-        Ok(rows.into_iter().filter_map(|r| {
-            let project_id = match r.project_id.parse::<i32>() {
-                Ok(id) => id,
-                Err(e) => {
-                    tracing::warn!("Skipping row with invalid project_id: {}", e);
-                    return None;
-                }
-            };
-            Some(BrainstormSession {
-                id: r.id.and_then(|id| id.parse().ok()),
-                project_id,
-            session_name: r.session_name,
-            session_type: r.session_type,
-            initial_prompt: r.initial_prompt,
-            context_data: r.context_data,
-            generated_ideas: r.generated_ideas,
-            selected_ideas: r.selected_ideas,
-            session_notes: r.session_notes,
-            model_used: r.model_used,
-            total_tokens: r.total_tokens.map(|t| t as i32),
-            cost_credits: r.cost_credits.map(|c| c as f64),
-            status: r.status,
-            created_at: r.created_at.map(|dt| dt.to_string()),
-            updated_at: r.updated_at.map(|dt| dt.to_string()),
-        })
-        }).collect())
+    /// Update session notes
+    pub async fn update_notes(pool: &Pool<Sqlite>, id: &str, notes: &str) -> Result<()> {
+        let result = sqlx::query("UPDATE brainstorm_sessions SET session_notes = ?, updated_at = ? WHERE id = ?")
+            .bind(notes)
+            .bind(Utc::now())
+            .bind(id)
+            .execute(pool)
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to update session notes: {}", e)))?;
+
+        if result.rows_affected() == 0 {
+            return Err(StoryWeaverError::not_found("brainstorm session", id));
+        }
+        Ok(())
     }
 
-    /// List all brainstorm sessions
-    pub async fn list_all(pool: &Pool<Sqlite>) -> Result<Vec<BrainstormSession>> {
-        let rows = sqlx::query!(
-            r#"
-            SELECT id, project_id, session_name, session_type, initial_prompt, context_data,
-                   generated_ideas, selected_ideas, session_notes, model_used, total_tokens,
-                   cost_credits, status, created_at, updated_at
-            FROM brainstorm_sessions ORDER BY updated_at DESC
-            "#
-        )
-        .fetch_all(&*pool)
-        .await
-        .map_err(|e| StoryWeaverError::database(format!("Failed to list brainstorm sessions: {}", e)))?;
-
-        Ok(rows.into_iter().filter_map(|r| {
-            let project_id = match r.project_id.parse::<i32>() {
-                Ok(id) => id,
-                Err(e) => {
-                    tracing::warn!("Skipping row with invalid project_id: {}", e);
-                    return None;
-                }
-            };
-            Some(BrainstormSession {
-                id: r.id.and_then(|id| id.parse().ok()),
-                project_id,
-            session_name: r.session_name,
-            session_type: r.session_type,
-            initial_prompt: r.initial_prompt,
-            context_data: r.context_data,
-            generated_ideas: r.generated_ideas,
-            selected_ideas: r.selected_ideas,
-            session_notes: r.session_notes,
-            model_used: r.model_used,
-            total_tokens: r.total_tokens.map(|t| t as i32),
-            cost_credits: r.cost_credits.map(|c| c as f64),
-            status: r.status,
-            created_at: r.created_at.map(|dt| dt.to_string()),
-            updated_at: r.updated_at.map(|dt| dt.to_string()),
-        })
-        }).collect())
+    /// Delete a brainstorm session and its ideas
+    pub async fn delete(pool: &Pool<Sqlite>, id: &str) -> Result<()> {
+        sqlx::query("DELETE FROM brainstorm_sessions WHERE id = ?")
+            .bind(id)
+            .execute(pool)
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to delete brainstorm session: {}", e)))?;
+
+        Ok(())
     }
 
-    /// Update a brainstorm session
-    pub async fn update(pool: &Pool<Sqlite>, id: i32, session: &BrainstormSession) -> Result<()> {
-        sqlx::query!(
-            r#"
-            UPDATE brainstorm_sessions 
-            SET project_id = ?, session_name = ?, session_type = ?, initial_prompt = ?, context_data = ?,
-                generated_ideas = ?, selected_ideas = ?, session_notes = ?, model_used = ?, total_tokens = ?,
-                cost_credits = ?, status = ?, updated_at = CURRENT_TIMESTAMP
-            WHERE id = ?
-            "#,
-            session.project_id,
-            session.session_name,
-            session.session_type,
-            session.initial_prompt,
-            session.context_data,
-            session.generated_ideas,
-            session.selected_ideas,
-            session.session_notes,
-            session.model_used,
-            session.total_tokens,
-            session.cost_credits,
-            session.status,
-            id
-        )
-        .execute(&*pool)
-        .await
-        .map_err(|e| StoryWeaverError::database(format!("Failed to update brainstorm session: {}", e)))?;
+    /// Ideas of a session in the order they were added
+    pub async fn get_ideas(pool: &Pool<Sqlite>, session_id: &str) -> Result<Vec<BrainstormIdea>> {
+        let rows = sqlx::query("SELECT * FROM brainstorm_ideas WHERE session_id = ? ORDER BY position")
+            .bind(session_id)
+            .fetch_all(pool)
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to get brainstorm ideas: {}", e)))?;
+
+        Ok(rows.iter().map(idea_from_row).collect())
+    }
+
+    pub async fn get_idea(pool: &Pool<Sqlite>, session_id: &str, idea_id: &str) -> Result<BrainstormIdea> {
+        let row = sqlx::query("SELECT * FROM brainstorm_ideas WHERE session_id = ? AND id = ?")
+            .bind(session_id)
+            .bind(idea_id)
+            .fetch_optional(pool)
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to get brainstorm idea: {}", e)))?
+            .ok_or_else(|| StoryWeaverError::not_found("brainstorm idea", idea_id))?;
+
+        Ok(idea_from_row(&row))
+    }
+
+    /// Append ideas to the end of a session
+    pub async fn add_ideas(pool: &Pool<Sqlite>, session_id: &str, ideas: &[BrainstormIdea]) -> Result<()> {
+        let mut tx = pool
+            .begin()
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to begin saving brainstorm ideas: {}", e)))?;
+
+        let next: i64 = sqlx::query_scalar("SELECT COALESCE(MAX(position), -1) + 1 FROM brainstorm_ideas WHERE session_id = ?")
+            .bind(session_id)
+            .fetch_one(&mut *tx)
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to get brainstorm idea position: {}", e)))?;
+
+        for (offset, idea) in ideas.iter().enumerate() {
+            bind_idea(sqlx::query(INSERT_IDEA), session_id, next + offset as i64, idea)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| StoryWeaverError::database(format!("Failed to save brainstorm idea: {}", e)))?;
+        }
+        Self::touch(&mut tx, session_id).await?;
 
+        tx.commit()
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to save brainstorm ideas: {}", e)))?;
         Ok(())
     }
 
-    /// Update session ideas
-    pub async fn update_ideas(pool: &Pool<Sqlite>, id: i32, generated_ideas: &str, selected_ideas: Option<&str>) -> Result<()> {
-        sqlx::query!(
+    /// Save an idea's content, tags, rating, notes, keeper flag and theme
+    pub async fn update_idea(pool: &Pool<Sqlite>, session_id: &str, idea: &BrainstormIdea) -> Result<()> {
+        let mut tx = pool
+            .begin()
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to begin updating brainstorm idea: {}", e)))?;
+
+        let result = sqlx::query(
             r#"
-            UPDATE brainstorm_sessions 
-            SET generated_ideas = ?, selected_ideas = ?, updated_at = CURRENT_TIMESTAMP
-            WHERE id = ?
+            UPDATE brainstorm_ideas
+            SET content = ?, tags = ?, rating = ?, notes = ?, is_keeper = ?, theme = ?
+            WHERE session_id = ? AND id = ?
             "#,
-            generated_ideas,
-            selected_ideas,
-            id
         )
-        .execute(&*pool)
+        .bind(&idea.content)
+        .bind(serde_json::to_string(&idea.tags).unwrap_or_else(|_| "[]".to_string()))
+        .bind(idea.rating)
+        .bind(&idea.notes)
+        .bind(idea.is_keeper)
+        .bind(&idea.theme)
+        .bind(session_id)
+        .bind(&idea.id)
+        .execute(&mut *tx)
         .await
-        .map_err(|e| StoryWeaverError::database(format!("Failed to update session ideas: {}", e)))?;
+        .map_err(|e| StoryWeaverError::database(format!("Failed to update brainstorm idea: {}", e)))?;
+
+        if result.rows_affected() == 0 {
+            return Err(StoryWeaverError::not_found("brainstorm idea", idea.id.as_str()));
+        }
+        Self::touch(&mut tx, session_id).await?;
 
+        tx.commit()
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to update brainstorm idea: {}", e)))?;
         Ok(())
     }
 
-    /// Update session status
-    pub async fn update_status(pool: &Pool<Sqlite>, id: i32, status: &str) -> Result<()> {
-        sqlx::query!(
-            r#"
-            UPDATE brainstorm_sessions 
-            SET status = ?, updated_at = CURRENT_TIMESTAMP
-            WHERE id = ?
-            "#,
-            status,
-            id
-        )
-        .execute(&*pool)
-        .await
-        .map_err(|e| StoryWeaverError::database(format!("Failed to update session status: {}", e)))?;
+    /// Replace two ideas with the idea merged from them, at the first one's place
+    pub async fn merge_ideas(
+        pool: &Pool<Sqlite>,
+        session_id: &str,
+        merged: &BrainstormIdea,
+        first_id: &str,
+        second_id: &str,
+    ) -> Result<()> {
+        let mut tx = pool
+            .begin()
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to begin merging brainstorm ideas: {}", e)))?;
+
+        let position: i64 = sqlx::query_scalar("SELECT position FROM brainstorm_ideas WHERE session_id = ? AND id = ?")
+            .bind(session_id)
+            .bind(first_id)
+            .fetch_optional(&mut *tx)
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to get brainstorm idea: {}", e)))?
+            .ok_or_else(|| StoryWeaverError::not_found("brainstorm idea", first_id))?;
+
+        let removed = sqlx::query("DELETE FROM brainstorm_ideas WHERE session_id = ? AND id IN (?, ?)")
+            .bind(session_id)
+            .bind(first_id)
+            .bind(second_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to remove merged brainstorm ideas: {}", e)))?;
+        if removed.rows_affected() != 2 {
+            return Err(StoryWeaverError::not_found("brainstorm idea", second_id));
+        }
+
+        bind_idea(sqlx::query(INSERT_IDEA), session_id, position, merged)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to save merged brainstorm idea: {}", e)))?;
+        Self::touch(&mut tx, session_id).await?;
 
+        tx.commit()
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to merge brainstorm ideas: {}", e)))?;
         Ok(())
     }
 
-    /// Update session notes
-    pub async fn update_notes(pool: &Pool<Sqlite>, id: i32, notes: &str) -> Result<()> {
-        sqlx::query!(
-            r#"
-            UPDATE brainstorm_sessions 
-            SET session_notes = ?, updated_at = CURRENT_TIMESTAMP
-            WHERE id = ?
-            "#,
-            notes,
-            id
-        )
-        .execute(&*pool)
-        .await
-        .map_err(|e| StoryWeaverError::database(format!("Failed to update session notes: {}", e)))?;
+    /// Remove ideas from a session
+    pub async fn delete_ideas(pool: &Pool<Sqlite>, session_id: &str, idea_ids: &[String]) -> Result<()> {
+        let mut tx = pool
+            .begin()
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to begin removing brainstorm ideas: {}", e)))?;
+
+        for id in idea_ids {
+            sqlx::query("DELETE FROM brainstorm_ideas WHERE session_id = ? AND id = ?")
+                .bind(session_id)
+                .bind(id)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| StoryWeaverError::database(format!("Failed to remove brainstorm idea: {}", e)))?;
+        }
+        Self::touch(&mut tx, session_id).await?;
 
+        tx.commit()
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to remove brainstorm ideas: {}", e)))?;
         Ok(())
     }
 
-    /// Delete a brainstorm session
-    pub async fn delete(pool: &Pool<Sqlite>, id: i32) -> Result<()> {
-        sqlx::query!("DELETE FROM brainstorm_sessions WHERE id = ?", id)
-            .execute(&*pool)
+    /// Set the theme of every idea in a session; ideas not listed lose theirs
+    pub async fn set_themes(pool: &Pool<Sqlite>, session_id: &str, themes: &[(String, String)]) -> Result<()> {
+        let mut tx = pool
+            .begin()
             .await
-            .map_err(|e| StoryWeaverError::database(format!("Failed to delete brainstorm session: {}", e)))?;
+            .map_err(|e| StoryWeaverError::database(format!("Failed to begin saving brainstorm themes: {}", e)))?;
+
+        sqlx::query("UPDATE brainstorm_ideas SET theme = NULL WHERE session_id = ?")
+            .bind(session_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to clear brainstorm themes: {}", e)))?;
+        for (idea_id, theme) in themes {
+            sqlx::query("UPDATE brainstorm_ideas SET theme = ? WHERE session_id = ? AND id = ?")
+                .bind(theme)
+                .bind(session_id)
+                .bind(idea_id)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| StoryWeaverError::database(format!("Failed to save brainstorm theme: {}", e)))?;
+        }
+        Self::touch(&mut tx, session_id).await?;
 
+        tx.commit()
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to save brainstorm themes: {}", e)))?;
         Ok(())
     }
 
-    /// Get recent brainstorm sessions (last N)
-    pub async fn get_recent(pool: &Pool<Sqlite>, limit: i32) -> Result<Vec<BrainstormSession>> {
-        let rows = sqlx::query!(
-            r#"
-            SELECT id, project_id, session_name, session_type, initial_prompt, context_data,
-                   generated_ideas, selected_ideas, session_notes, model_used, total_tokens,
-                   cost_credits, status, created_at, updated_at
-            FROM brainstorm_sessions ORDER BY updated_at DESC LIMIT ?
-            "#,
-            limit
-        )
-        .fetch_all(&*pool)
-        .await
-        .map_err(|e| StoryWeaverError::database(format!("Failed to get recent brainstorm sessions: {}", e)))?;
-
-        Ok(rows.into_iter().filter_map(|r| {
-            let project_id = match r.project_id.parse::<i32>() {
-                Ok(id) => id,
-                Err(e) => {
-                    tracing::warn!("Skipping row with invalid project_id: {}", e);
-                    return None;
-                }
-            };
-            Some(BrainstormSession {
-                id: r.id.and_then(|id| id.parse().ok()),
-                project_id,
-            session_name: r.session_name,
-            session_type: r.session_type,
-            initial_prompt: r.initial_prompt,
-            context_data: r.context_data,
-            generated_ideas: r.generated_ideas,
-            selected_ideas: r.selected_ideas,
-            session_notes: r.session_notes,
-            model_used: r.model_used,
-            total_tokens: r.total_tokens.map(|t| t as i32),
-            cost_credits: r.cost_credits.map(|c| c as f64),
-            status: r.status,
-            created_at: r.created_at.map(|dt| dt.to_string()),
-            updated_at: r.updated_at.map(|dt| dt.to_string()),
-        })
-        }).collect())
+    /// Record the story bible entry an idea was exported as
+    pub async fn mark_exported(pool: &Pool<Sqlite>, idea_id: &str, link: &StoryBibleLink) -> Result<()> {
+        sqlx::query("UPDATE brainstorm_ideas SET exported_type = ?, exported_id = ? WHERE id = ?")
+            .bind(link.kind.as_str())
+            .bind(&link.id)
+            .bind(idea_id)
+            .execute(pool)
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to record brainstorm export: {}", e)))?;
+
+        Ok(())
     }
 
-    /// Get total cost for brainstorm sessions by project
-    pub async fn get_total_cost_by_project(pool: &Pool<Sqlite>, project_id: i32) -> Result<f64> {
-        let row = sqlx::query!(
-            "SELECT COALESCE(SUM(cost_credits), 0.0) as total_cost FROM brainstorm_sessions WHERE project_id = ?",
-            project_id
-        )
-        .fetch_one(&*pool)
-        .await
-        .map_err(|e| StoryWeaverError::database(format!("Failed to get total brainstorm cost for project: {}", e)))?;
+    async fn touch(tx: &mut sqlx::Transaction<'_, Sqlite>, session_id: &str) -> Result<()> {
+        sqlx::query("UPDATE brainstorm_sessions SET updated_at = ? WHERE id = ?")
+            .bind(Utc::now())
+            .bind(session_id)
+            .execute(&mut **tx)
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to update brainstorm session: {}", e)))?;
 
-        Ok(row.total_cost)
+        Ok(())
     }
 }
@@ -0,0 +1,290 @@
+//! AI budget database operations
+//! Provides functions to interact with the ai_budgets and ai_budget_alerts tables
+
+use crate::error::{Result, StoryWeaverError};
+use chrono::{DateTime, Datelike, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Row, Sqlite};
+use uuid::Uuid;
+
+/// What a budget's spend is counted over
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BudgetScope {
+    /// Every request
+    Global,
+    /// Requests made for one project
+    Project,
+    /// Requests served by one registered provider
+    Provider,
+}
+
+impl BudgetScope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Global => "global",
+            Self::Project => "project",
+            Self::Provider => "provider",
+        }
+    }
+
+    fn parse(value: &str) -> Self {
+        match value {
+            "project" => Self::Project,
+            "provider" => Self::Provider,
+            _ => Self::Global,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BudgetPeriod {
+    Daily,
+    Monthly,
+}
+
+impl BudgetPeriod {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Daily => "daily",
+            Self::Monthly => "monthly",
+        }
+    }
+
+    fn parse(value: &str) -> Self {
+        match value {
+            "daily" => Self::Daily,
+            _ => Self::Monthly,
+        }
+    }
+
+    /// First day of the period containing `now`, as `YYYY-MM-DD`
+    pub fn start(&self, now: DateTime<Utc>) -> String {
+        let date = now.date_naive();
+        let start = match self {
+            Self::Daily => date,
+            Self::Monthly => NaiveDate::from_ymd_opt(date.year(), date.month(), 1).unwrap_or(date),
+        };
+        start.format("%Y-%m-%d").to_string()
+    }
+}
+
+/// What happens to a request that would take spend over the limit
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BudgetEnforcement {
+    /// Refuse the request
+    Block,
+    /// Refuse it until the user approves going over for the rest of the period
+    Confirm,
+}
+
+impl BudgetEnforcement {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Block => "block",
+            Self::Confirm => "confirm",
+        }
+    }
+
+    fn parse(value: &str) -> Self {
+        match value {
+            "confirm" => Self::Confirm,
+            _ => Self::Block,
+        }
+    }
+}
+
+/// A spending limit in US dollars of estimated provider cost
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AIBudget {
+    pub id: String,
+    pub scope: BudgetScope,
+    /// Project id or provider key; `None` for global budgets
+    pub scope_id: Option<String>,
+    pub period: BudgetPeriod,
+    pub limit_usd: f64,
+    pub enforcement: BudgetEnforcement,
+    /// Fractions of the limit at which to notify, e.g. 0.8 for 80%
+    pub alert_thresholds: Vec<f64>,
+    /// Start of the period in which the user approved going over this budget
+    pub approved_period: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl AIBudget {
+    pub fn new(scope: BudgetScope, scope_id: Option<String>, period: BudgetPeriod, limit_usd: f64) -> Self {
+        let now = Utc::now();
+        Self {
+            id: Uuid::new_v4().to_string(),
+            scope,
+            scope_id: if scope == BudgetScope::Global { None } else { scope_id },
+            period,
+            limit_usd,
+            enforcement: BudgetEnforcement::Block,
+            alert_thresholds: vec![0.5, 0.8, 1.0],
+            approved_period: None,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    /// What the budget limits, for messages
+    pub fn describe(&self) -> String {
+        match (self.scope, self.scope_id.as_deref()) {
+            (BudgetScope::Project, Some(id)) => format!("AI spending for project {}", id),
+            (BudgetScope::Provider, Some(provider)) => format!("AI spending on {}", provider),
+            _ => "AI spending".to_string(),
+        }
+    }
+
+    /// Whether the user approved going over this budget in the period starting `period_start`
+    pub fn is_approved_for(&self, period_start: &str) -> bool {
+        self.approved_period.as_deref() == Some(period_start)
+    }
+}
+
+fn budget_from_row(row: &sqlx::sqlite::SqliteRow) -> AIBudget {
+    let scope_id: String = row.get("scope_id");
+    let thresholds: String = row.get("alert_thresholds");
+    AIBudget {
+        id: row.get("id"),
+        scope: BudgetScope::parse(row.get("scope")),
+        scope_id: Some(scope_id).filter(|id| !id.is_empty()),
+        period: BudgetPeriod::parse(row.get("period")),
+        limit_usd: row.get("limit_usd"),
+        enforcement: BudgetEnforcement::parse(row.get("enforcement")),
+        alert_thresholds: serde_json::from_str(&thresholds).unwrap_or_default(),
+        approved_period: row.get("approved_period"),
+        created_at: row.get("created_at"),
+        updated_at: row.get("updated_at"),
+    }
+}
+
+impl super::AIBudgetOps {
+    /// Create a budget, or replace the limit and settings of the existing
+    /// budget for the same scope and period
+    pub async fn upsert(pool: &Pool<Sqlite>, budget: AIBudget) -> Result<AIBudget> {
+        sqlx::query(
+            r#"
+            INSERT INTO ai_budgets (id, scope, scope_id, period, limit_usd, enforcement, alert_thresholds,
+                approved_period, created_at, updated_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?, NULL, ?, ?)
+            ON CONFLICT(scope, scope_id, period) DO UPDATE SET
+                limit_usd = excluded.limit_usd,
+                enforcement = excluded.enforcement,
+                alert_thresholds = excluded.alert_thresholds,
+                updated_at = excluded.updated_at
+            "#,
+        )
+        .bind(&budget.id)
+        .bind(budget.scope.as_str())
+        .bind(budget.scope_id.as_deref().unwrap_or_default())
+        .bind(budget.period.as_str())
+        .bind(budget.limit_usd)
+        .bind(budget.enforcement.as_str())
+        .bind(serde_json::to_string(&budget.alert_thresholds).unwrap_or_else(|_| "[]".to_string()))
+        .bind(budget.created_at)
+        .bind(Utc::now())
+        .execute(pool)
+        .await
+        .map_err(|e| StoryWeaverError::database(format!("Failed to save AI budget: {}", e)))?;
+
+        let row = sqlx::query("SELECT * FROM ai_budgets WHERE scope = ? AND scope_id = ? AND period = ?")
+            .bind(budget.scope.as_str())
+            .bind(budget.scope_id.as_deref().unwrap_or_default())
+            .bind(budget.period.as_str())
+            .fetch_one(pool)
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to get AI budget: {}", e)))?;
+
+        Ok(budget_from_row(&row))
+    }
+
+    pub async fn get_by_id(pool: &Pool<Sqlite>, id: &str) -> Result<AIBudget> {
+        let row = sqlx::query("SELECT * FROM ai_budgets WHERE id = ?")
+            .bind(id)
+            .fetch_optional(pool)
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to get AI budget: {}", e)))?
+            .ok_or_else(|| StoryWeaverError::not_found("AI budget", id))?;
+
+        Ok(budget_from_row(&row))
+    }
+
+    pub async fn list(pool: &Pool<Sqlite>) -> Result<Vec<AIBudget>> {
+        let rows = sqlx::query("SELECT * FROM ai_budgets ORDER BY scope, scope_id, period")
+            .fetch_all(pool)
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to list AI budgets: {}", e)))?;
+
+        Ok(rows.iter().map(budget_from_row).collect())
+    }
+
+    /// Budgets a request for `project_id` served by `provider` counts against
+    pub async fn get_applicable(pool: &Pool<Sqlite>, project_id: Option<&str>, provider: &str) -> Result<Vec<AIBudget>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT * FROM ai_budgets
+            WHERE scope = 'global'
+               OR (scope = 'project' AND scope_id = ?)
+               OR (scope = 'provider' AND scope_id = ?)
+            ORDER BY scope, period
+            "#,
+        )
+        .bind(project_id)
+        .bind(provider)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| StoryWeaverError::database(format!("Failed to get AI budgets: {}", e)))?;
+
+        Ok(rows.iter().map(budget_from_row).collect())
+    }
+
+    pub async fn delete(pool: &Pool<Sqlite>, id: &str) -> Result<()> {
+        let result = sqlx::query("DELETE FROM ai_budgets WHERE id = ?")
+            .bind(id)
+            .execute(pool)
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to delete AI budget: {}", e)))?;
+
+        if result.rows_affected() == 0 {
+            return Err(StoryWeaverError::not_found("AI budget", id));
+        }
+        Ok(())
+    }
+
+    /// Let requests go over a confirm budget until its period ends
+    pub async fn approve_overrun(pool: &Pool<Sqlite>, id: &str, period_start: &str) -> Result<AIBudget> {
+        let result = sqlx::query("UPDATE ai_budgets SET approved_period = ?, updated_at = ? WHERE id = ?")
+            .bind(period_start)
+            .bind(Utc::now())
+            .bind(id)
+            .execute(pool)
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to approve AI budget overrun: {}", e)))?;
+
+        if result.rows_affected() == 0 {
+            return Err(StoryWeaverError::not_found("AI budget", id));
+        }
+        Self::get_by_id(pool, id).await
+    }
+
+    /// Note that the alert for `threshold` was sent this period. Returns false
+    /// if it had already been sent.
+    pub async fn record_alert(pool: &Pool<Sqlite>, budget_id: &str, period_start: &str, threshold: f64) -> Result<bool> {
+        let result = sqlx::query(
+            "INSERT OR IGNORE INTO ai_budget_alerts (budget_id, period_start, threshold) VALUES (?, ?, ?)",
+        )
+        .bind(budget_id)
+        .bind(period_start)
+        .bind(threshold)
+        .execute(pool)
+        .await
+        .map_err(|e| StoryWeaverError::database(format!("Failed to record AI budget alert: {}", e)))?;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
@@ -0,0 +1,279 @@
+//! Chapter draft database operations
+//! Provides functions to interact with the chapter_drafts and chapter_draft_scenes tables
+
+use crate::error::{Result, StoryWeaverError};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Row, Sqlite};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChapterDraftStatus {
+    /// Waiting for its background task
+    Pending,
+    Running,
+    /// Stopped at a scene; resuming continues from there
+    Failed,
+    Completed,
+}
+
+impl ChapterDraftStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChapterDraftStatus::Pending => "pending",
+            ChapterDraftStatus::Running => "running",
+            ChapterDraftStatus::Failed => "failed",
+            ChapterDraftStatus::Completed => "completed",
+        }
+    }
+
+    pub fn parse(value: &str) -> Self {
+        match value {
+            "running" => ChapterDraftStatus::Running,
+            "failed" => ChapterDraftStatus::Failed,
+            "completed" => ChapterDraftStatus::Completed,
+            _ => ChapterDraftStatus::Pending,
+        }
+    }
+}
+
+/// A draft of a whole chapter, written scene by scene from its outline
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChapterDraft {
+    pub id: String,
+    pub project_id: String,
+    pub outline_id: String,
+    /// Document the draft is saved to; a new one is created if `None`
+    pub document_id: Option<String>,
+    pub task_id: Option<String>,
+    pub status: ChapterDraftStatus,
+    pub scene_count: i32,
+    /// Total of the scenes' credit estimates when the draft was started
+    pub credit_estimate: Option<f64>,
+    pub error_message: Option<String>,
+    /// Document version holding the finished draft
+    pub version_id: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl ChapterDraft {
+    pub fn new(project_id: &str, outline_id: &str, document_id: Option<String>) -> Self {
+        let now = Utc::now();
+        Self {
+            id: Uuid::new_v4().to_string(),
+            project_id: project_id.to_string(),
+            outline_id: outline_id.to_string(),
+            document_id,
+            task_id: None,
+            status: ChapterDraftStatus::Pending,
+            scene_count: 0,
+            credit_estimate: None,
+            error_message: None,
+            version_id: None,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+}
+
+/// A scene of a chapter draft, saved as soon as it is written
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DraftedScene {
+    pub draft_id: String,
+    pub scene_id: String,
+    pub position: i32,
+    pub content: String,
+    /// Summary fed to the next scene
+    pub summary: String,
+    pub word_count: i32,
+    pub created_at: DateTime<Utc>,
+}
+
+fn draft_from_row(row: &sqlx::sqlite::SqliteRow) -> ChapterDraft {
+    let status: String = row.get("status");
+    ChapterDraft {
+        id: row.get("id"),
+        project_id: row.get("project_id"),
+        outline_id: row.get("outline_id"),
+        document_id: row.get("document_id"),
+        task_id: row.get("task_id"),
+        status: ChapterDraftStatus::parse(&status),
+        scene_count: row.get("scene_count"),
+        credit_estimate: row.get("credit_estimate"),
+        error_message: row.get("error_message"),
+        version_id: row.get("version_id"),
+        created_at: row.get("created_at"),
+        updated_at: row.get("updated_at"),
+    }
+}
+
+fn scene_from_row(row: &sqlx::sqlite::SqliteRow) -> DraftedScene {
+    DraftedScene {
+        draft_id: row.get("draft_id"),
+        scene_id: row.get("scene_id"),
+        position: row.get("position"),
+        content: row.get("content"),
+        summary: row.get("summary"),
+        word_count: row.get("word_count"),
+        created_at: row.get("created_at"),
+    }
+}
+
+impl super::ChapterDraftOps {
+    pub async fn create(pool: &Pool<Sqlite>, draft: &ChapterDraft) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO chapter_drafts (id, project_id, outline_id, document_id, task_id, status, scene_count,
+                credit_estimate, error_message, version_id, created_at, updated_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&draft.id)
+        .bind(&draft.project_id)
+        .bind(&draft.outline_id)
+        .bind(&draft.document_id)
+        .bind(&draft.task_id)
+        .bind(draft.status.as_str())
+        .bind(draft.scene_count)
+        .bind(draft.credit_estimate)
+        .bind(&draft.error_message)
+        .bind(&draft.version_id)
+        .bind(draft.created_at)
+        .bind(draft.updated_at)
+        .execute(pool)
+        .await
+        .map_err(|e| StoryWeaverError::database(format!("Failed to create chapter draft: {}", e)))?;
+
+        Ok(())
+    }
+
+    pub async fn get(pool: &Pool<Sqlite>, id: &str) -> Result<ChapterDraft> {
+        let row = sqlx::query("SELECT * FROM chapter_drafts WHERE id = ?")
+            .bind(id)
+            .fetch_optional(pool)
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to get chapter draft: {}", e)))?
+            .ok_or_else(|| StoryWeaverError::not_found("chapter draft", id))?;
+
+        Ok(draft_from_row(&row))
+    }
+
+    /// Drafts of an outline's chapter, newest first
+    pub async fn get_by_outline(pool: &Pool<Sqlite>, outline_id: &str) -> Result<Vec<ChapterDraft>> {
+        let rows = sqlx::query("SELECT * FROM chapter_drafts WHERE outline_id = ? ORDER BY created_at DESC")
+            .bind(outline_id)
+            .fetch_all(pool)
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to list chapter drafts: {}", e)))?;
+
+        Ok(rows.iter().map(draft_from_row).collect())
+    }
+
+    /// Scenes drafted so far, in chapter order
+    pub async fn get_scenes(pool: &Pool<Sqlite>, draft_id: &str) -> Result<Vec<DraftedScene>> {
+        let rows = sqlx::query("SELECT * FROM chapter_draft_scenes WHERE draft_id = ? ORDER BY position")
+            .bind(draft_id)
+            .fetch_all(pool)
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to get drafted scenes: {}", e)))?;
+
+        Ok(rows.iter().map(scene_from_row).collect())
+    }
+
+    /// Checkpoint a drafted scene
+    pub async fn save_scene(pool: &Pool<Sqlite>, scene: &DraftedScene) -> Result<()> {
+        let mut tx = pool
+            .begin()
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to begin saving drafted scene: {}", e)))?;
+
+        sqlx::query(
+            r#"
+            INSERT OR REPLACE INTO chapter_draft_scenes (draft_id, scene_id, position, content, summary, word_count, created_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&scene.draft_id)
+        .bind(&scene.scene_id)
+        .bind(scene.position)
+        .bind(&scene.content)
+        .bind(&scene.summary)
+        .bind(scene.word_count)
+        .bind(scene.created_at)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| StoryWeaverError::database(format!("Failed to save drafted scene: {}", e)))?;
+
+        sqlx::query("UPDATE chapter_drafts SET updated_at = ? WHERE id = ?")
+            .bind(Utc::now())
+            .bind(&scene.draft_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to update chapter draft: {}", e)))?;
+
+        tx.commit()
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to save drafted scene: {}", e)))?;
+        Ok(())
+    }
+
+    /// Record the task drafting the chapter and reset its status to pending
+    pub async fn assign_task(pool: &Pool<Sqlite>, id: &str, task_id: &str) -> Result<()> {
+        let result = sqlx::query(
+            "UPDATE chapter_drafts SET task_id = ?, status = 'pending', error_message = NULL, updated_at = ? WHERE id = ?",
+        )
+        .bind(task_id)
+        .bind(Utc::now())
+        .bind(id)
+        .execute(pool)
+        .await
+        .map_err(|e| StoryWeaverError::database(format!("Failed to update chapter draft: {}", e)))?;
+
+        if result.rows_affected() == 0 {
+            return Err(StoryWeaverError::not_found("chapter draft", id));
+        }
+        Ok(())
+    }
+
+    /// Mark the draft running over `scene_count` scenes
+    pub async fn mark_running(pool: &Pool<Sqlite>, id: &str, scene_count: i32, credit_estimate: Option<f64>) -> Result<()> {
+        sqlx::query(
+            "UPDATE chapter_drafts SET status = 'running', scene_count = ?, credit_estimate = ?, error_message = NULL, updated_at = ? WHERE id = ?",
+        )
+        .bind(scene_count)
+        .bind(credit_estimate)
+        .bind(Utc::now())
+        .bind(id)
+        .execute(pool)
+        .await
+        .map_err(|e| StoryWeaverError::database(format!("Failed to update chapter draft: {}", e)))?;
+        Ok(())
+    }
+
+    pub async fn mark_failed(pool: &Pool<Sqlite>, id: &str, error_message: &str) -> Result<()> {
+        sqlx::query("UPDATE chapter_drafts SET status = 'failed', error_message = ?, updated_at = ? WHERE id = ?")
+            .bind(error_message)
+            .bind(Utc::now())
+            .bind(id)
+            .execute(pool)
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to update chapter draft: {}", e)))?;
+        Ok(())
+    }
+
+    pub async fn mark_completed(pool: &Pool<Sqlite>, id: &str, document_id: &str, version_id: &str) -> Result<()> {
+        sqlx::query(
+            "UPDATE chapter_drafts SET status = 'completed', document_id = ?, version_id = ?, error_message = NULL, updated_at = ? WHERE id = ?",
+        )
+        .bind(document_id)
+        .bind(version_id)
+        .bind(Utc::now())
+        .bind(id)
+        .execute(pool)
+        .await
+        .map_err(|e| StoryWeaverError::database(format!("Failed to update chapter draft: {}", e)))?;
+        Ok(())
+    }
+}
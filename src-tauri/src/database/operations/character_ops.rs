@@ -7,7 +7,15 @@ use uuid::Uuid;
 /// Character operations
 impl super::CharacterOps {
     /// Create a new character
-    pub async fn create(pool: &Pool<Sqlite>, mut character: Character) -> Result<Character> {
+    pub async fn create(pool: &Pool<Sqlite>, character: Character) -> Result<Character> {
+        Self::insert(pool, character).await
+    }
+
+    /// Create a new character on any executor, such as an open transaction
+    pub(crate) async fn insert<'e, E>(executor: E, mut character: Character) -> Result<Character>
+    where
+        E: sqlx::Executor<'e, Database = Sqlite>,
+    {
         character.id = Uuid::new_v4().to_string();
         character.created_at = Utc::now();
         character.updated_at = Utc::now();
@@ -37,7 +45,7 @@ impl super::CharacterOps {
         .bind(&character.metadata)
         .bind(&character.series_id)
         .bind(&character.original_project_id)
-        .execute(&*pool)
+        .execute(executor)
         .await
         .map_err(|e| StoryWeaverError::database(format!("Failed to create character: {}", e)))?;
         
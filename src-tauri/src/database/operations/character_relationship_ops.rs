@@ -0,0 +1,517 @@
+//! Character relationship database operations
+//! Provides functions to interact with the character_relationships table, and
+//! to read the relationship graph at a point in the story
+
+use crate::database::models::{CharacterRole, DocumentType};
+use crate::database::operations::{CharacterOps, DocumentOps};
+use crate::error::{Result, StoryWeaverError};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Row, Sqlite};
+use std::collections::{HashMap, HashSet, VecDeque};
+use uuid::Uuid;
+
+/// Kind of bond between two characters
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RelationshipType {
+    Family,
+    Romantic,
+    Friend,
+    Ally,
+    Rival,
+    Enemy,
+    /// From the mentor to the student
+    Mentor,
+    Colleague,
+    Other,
+}
+
+impl RelationshipType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RelationshipType::Family => "family",
+            RelationshipType::Romantic => "romantic",
+            RelationshipType::Friend => "friend",
+            RelationshipType::Ally => "ally",
+            RelationshipType::Rival => "rival",
+            RelationshipType::Enemy => "enemy",
+            RelationshipType::Mentor => "mentor",
+            RelationshipType::Colleague => "colleague",
+            RelationshipType::Other => "other",
+        }
+    }
+
+    pub fn parse(value: &str) -> Self {
+        match value {
+            "family" => RelationshipType::Family,
+            "romantic" => RelationshipType::Romantic,
+            "friend" => RelationshipType::Friend,
+            "ally" => RelationshipType::Ally,
+            "rival" => RelationshipType::Rival,
+            "enemy" => RelationshipType::Enemy,
+            "mentor" => RelationshipType::Mentor,
+            "colleague" => RelationshipType::Colleague,
+            _ => RelationshipType::Other,
+        }
+    }
+
+    /// Whether a new relationship of this type holds both ways unless told
+    /// otherwise. Family and mentor edges usually need a direction.
+    pub fn mutual_by_default(&self) -> bool {
+        !matches!(self, RelationshipType::Family | RelationshipType::Mentor | RelationshipType::Other)
+    }
+}
+
+/// A directed relationship from one character to another, optionally bounded
+/// by the chapters where it begins and ends
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CharacterRelationship {
+    pub id: String,
+    pub project_id: String,
+    pub from_character_id: String,
+    pub to_character_id: String,
+    pub relationship_type: RelationshipType,
+    /// Wording shown instead of the type, e.g. "older sister"
+    pub label: Option<String>,
+    pub description: Option<String>,
+    /// Holds in both directions
+    pub mutual: bool,
+    /// Chapter where it begins; the start of the story when `None`
+    pub starts_in_chapter_id: Option<String>,
+    /// Chapter where it ends or changes; still holds when `None`
+    pub ends_in_chapter_id: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl CharacterRelationship {
+    pub fn new(project_id: &str, from_character_id: &str, to_character_id: &str, relationship_type: RelationshipType) -> Self {
+        let now = Utc::now();
+        Self {
+            id: Uuid::new_v4().to_string(),
+            project_id: project_id.to_string(),
+            from_character_id: from_character_id.to_string(),
+            to_character_id: to_character_id.to_string(),
+            relationship_type,
+            label: None,
+            description: None,
+            mutual: relationship_type.mutual_by_default(),
+            starts_in_chapter_id: None,
+            ends_in_chapter_id: None,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    /// The label, or the type when there is none
+    pub fn display_label(&self) -> &str {
+        self.label.as_deref().unwrap_or(self.relationship_type.as_str())
+    }
+
+    /// The character at the other end from `character_id`
+    pub fn other_end(&self, character_id: &str) -> &str {
+        if self.from_character_id == character_id {
+            &self.to_character_id
+        } else {
+            &self.from_character_id
+        }
+    }
+}
+
+fn relationship_from_row(row: &sqlx::sqlite::SqliteRow) -> CharacterRelationship {
+    let relationship_type: String = row.get("relationship_type");
+    CharacterRelationship {
+        id: row.get("id"),
+        project_id: row.get("project_id"),
+        from_character_id: row.get("from_character_id"),
+        to_character_id: row.get("to_character_id"),
+        relationship_type: RelationshipType::parse(&relationship_type),
+        label: row.get("label"),
+        description: row.get("description"),
+        mutual: row.get("mutual"),
+        starts_in_chapter_id: row.get("starts_in_chapter_id"),
+        ends_in_chapter_id: row.get("ends_in_chapter_id"),
+        created_at: row.get("created_at"),
+        updated_at: row.get("updated_at"),
+    }
+}
+
+/// Order of a project's chapters, for reading relationships at a point in
+/// the story. A scene stands for the chapter it belongs to.
+#[derive(Debug, Clone, Default)]
+pub struct StoryTimeline {
+    positions: HashMap<String, usize>,
+}
+
+impl StoryTimeline {
+    pub async fn load(pool: &Pool<Sqlite>, project_id: &str) -> Result<Self> {
+        let documents = DocumentOps::get_by_project(pool, project_id).await?;
+        let mut positions = HashMap::new();
+        let chapters = documents.iter().filter(|doc| matches!(doc.document_type, DocumentType::Chapter));
+        for (position, chapter) in chapters.enumerate() {
+            positions.insert(chapter.id.clone(), position);
+        }
+        for scene in documents.iter().filter(|doc| matches!(doc.document_type, DocumentType::Scene)) {
+            if let Some(position) = scene.parent_id.as_ref().and_then(|id| positions.get(id)).copied() {
+                positions.insert(scene.id.clone(), position);
+            }
+        }
+        Ok(Self { positions })
+    }
+
+    /// Position of the chapter `document_id`, or of the chapter a scene is in
+    pub fn position(&self, document_id: &str) -> Option<usize> {
+        self.positions.get(document_id).copied()
+    }
+
+    /// Whether `relationship` holds at chapter position `at`; with no
+    /// position, whether it still holds at the end of the story. A
+    /// relationship ends at the start of its end chapter.
+    pub fn is_active(&self, relationship: &CharacterRelationship, at: Option<usize>) -> bool {
+        let ends = relationship.ends_in_chapter_id.as_deref().and_then(|id| self.position(id));
+        let Some(at) = at else {
+            return ends.is_none();
+        };
+        let starts = relationship.starts_in_chapter_id.as_deref().and_then(|id| self.position(id)).unwrap_or(0);
+        starts <= at && ends.is_none_or(|ends| at < ends)
+    }
+}
+
+/// One hop along a path between two characters. `from_character_id` and
+/// `to_character_id` follow the path, which may run against the direction
+/// of the relationship itself.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RelationshipPathStep {
+    pub from_character_id: String,
+    pub to_character_id: String,
+    pub relationship: CharacterRelationship,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RelationshipGraphNode {
+    pub id: String,
+    pub name: String,
+    pub role: CharacterRole,
+}
+
+/// A project's characters and the relationships between them at one point
+/// in the story
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RelationshipGraph {
+    pub nodes: Vec<RelationshipGraphNode>,
+    pub edges: Vec<CharacterRelationship>,
+}
+
+fn dot_string(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+impl RelationshipGraph {
+    /// GraphViz DOT; mutual relationships get arrows at both ends
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph relationships {\n");
+        for node in &self.nodes {
+            dot.push_str(&format!("    {} [label={}];\n", dot_string(&node.id), dot_string(&node.name)));
+        }
+        for edge in &self.edges {
+            dot.push_str(&format!(
+                "    {} -> {} [label={}, type={}{}];\n",
+                dot_string(&edge.from_character_id),
+                dot_string(&edge.to_character_id),
+                dot_string(edge.display_label()),
+                edge.relationship_type.as_str(),
+                if edge.mutual { ", dir=both" } else { "" }
+            ));
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        serde_json::to_string_pretty(self).map_err(|e| StoryWeaverError::serialization(e.to_string()))
+    }
+}
+
+impl super::CharacterRelationshipOps {
+    /// Check that both characters, and any bounding chapters, belong to the
+    /// relationship's project
+    async fn check_references(pool: &Pool<Sqlite>, relationship: &CharacterRelationship) -> Result<()> {
+        if relationship.from_character_id == relationship.to_character_id {
+            return Err(StoryWeaverError::validation("A character can't have a relationship with themselves"));
+        }
+
+        let characters = CharacterOps::get_by_project(pool, &relationship.project_id).await?;
+        for id in [&relationship.from_character_id, &relationship.to_character_id] {
+            if !characters.iter().any(|character| &character.id == id) {
+                return Err(StoryWeaverError::not_found("character", id.as_str()));
+            }
+        }
+
+        let documents = DocumentOps::get_by_project(pool, &relationship.project_id).await?;
+        let chapters = [&relationship.starts_in_chapter_id, &relationship.ends_in_chapter_id];
+        for id in chapters.into_iter().flatten() {
+            let is_chapter = documents
+                .iter()
+                .any(|doc| &doc.id == id && matches!(doc.document_type, DocumentType::Chapter));
+            if !is_chapter {
+                return Err(StoryWeaverError::validation(format!("{} is not a chapter of this project", id)));
+            }
+        }
+
+        if let (Some(starts), Some(ends)) = (&relationship.starts_in_chapter_id, &relationship.ends_in_chapter_id) {
+            let timeline = StoryTimeline::load(pool, &relationship.project_id).await?;
+            if timeline.position(ends) <= timeline.position(starts) {
+                return Err(StoryWeaverError::validation("A relationship must end in a later chapter than it begins"));
+            }
+        }
+
+        Ok(())
+    }
+
+    pub async fn create(pool: &Pool<Sqlite>, relationship: CharacterRelationship) -> Result<CharacterRelationship> {
+        Self::check_references(pool, &relationship).await?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO character_relationships (id, project_id, from_character_id, to_character_id,
+                relationship_type, label, description, mutual, starts_in_chapter_id, ends_in_chapter_id,
+                created_at, updated_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&relationship.id)
+        .bind(&relationship.project_id)
+        .bind(&relationship.from_character_id)
+        .bind(&relationship.to_character_id)
+        .bind(relationship.relationship_type.as_str())
+        .bind(&relationship.label)
+        .bind(&relationship.description)
+        .bind(relationship.mutual)
+        .bind(&relationship.starts_in_chapter_id)
+        .bind(&relationship.ends_in_chapter_id)
+        .bind(relationship.created_at)
+        .bind(relationship.updated_at)
+        .execute(pool)
+        .await
+        .map_err(|e| StoryWeaverError::database(format!("Failed to create character relationship: {}", e)))?;
+
+        Ok(relationship)
+    }
+
+    pub async fn get(pool: &Pool<Sqlite>, id: &str) -> Result<CharacterRelationship> {
+        let row = sqlx::query("SELECT * FROM character_relationships WHERE id = ?")
+            .bind(id)
+            .fetch_optional(pool)
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to get character relationship: {}", e)))?
+            .ok_or_else(|| StoryWeaverError::not_found("character relationship", id))?;
+
+        Ok(relationship_from_row(&row))
+    }
+
+    /// Every relationship in a project, oldest first, whether or not it
+    /// still holds
+    pub async fn get_by_project(pool: &Pool<Sqlite>, project_id: &str) -> Result<Vec<CharacterRelationship>> {
+        let rows = sqlx::query("SELECT * FROM character_relationships WHERE project_id = ? ORDER BY created_at, id")
+            .bind(project_id)
+            .fetch_all(pool)
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to get character relationships: {}", e)))?;
+
+        Ok(rows.iter().map(relationship_from_row).collect())
+    }
+
+    /// Relationships from or to a character, oldest first
+    pub async fn get_by_character(pool: &Pool<Sqlite>, character_id: &str) -> Result<Vec<CharacterRelationship>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT * FROM character_relationships
+            WHERE from_character_id = ? OR to_character_id = ?
+            ORDER BY created_at, id
+            "#,
+        )
+        .bind(character_id)
+        .bind(character_id)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| StoryWeaverError::database(format!("Failed to get character relationships: {}", e)))?;
+
+        Ok(rows.iter().map(relationship_from_row).collect())
+    }
+
+    pub async fn update(pool: &Pool<Sqlite>, relationship: &CharacterRelationship) -> Result<()> {
+        Self::check_references(pool, relationship).await?;
+
+        sqlx::query(
+            r#"
+            UPDATE character_relationships SET
+                relationship_type = ?, label = ?, description = ?, mutual = ?,
+                starts_in_chapter_id = ?, ends_in_chapter_id = ?, updated_at = ?
+            WHERE id = ?
+            "#,
+        )
+        .bind(relationship.relationship_type.as_str())
+        .bind(&relationship.label)
+        .bind(&relationship.description)
+        .bind(relationship.mutual)
+        .bind(&relationship.starts_in_chapter_id)
+        .bind(&relationship.ends_in_chapter_id)
+        .bind(Utc::now())
+        .bind(&relationship.id)
+        .execute(pool)
+        .await
+        .map_err(|e| StoryWeaverError::database(format!("Failed to update character relationship: {}", e)))?;
+
+        Ok(())
+    }
+
+    pub async fn delete(pool: &Pool<Sqlite>, id: &str) -> Result<()> {
+        sqlx::query("DELETE FROM character_relationships WHERE id = ?")
+            .bind(id)
+            .execute(pool)
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to delete character relationship: {}", e)))?;
+
+        Ok(())
+    }
+
+    /// Record that a relationship changes in `chapter_id`: it ends there, and
+    /// the relationship it becomes begins there. Returns the new relationship.
+    pub async fn change(
+        pool: &Pool<Sqlite>,
+        id: &str,
+        chapter_id: &str,
+        relationship_type: RelationshipType,
+        label: Option<String>,
+        description: Option<String>,
+    ) -> Result<CharacterRelationship> {
+        let mut previous = Self::get(pool, id).await?;
+        if previous.ends_in_chapter_id.is_some() {
+            return Err(StoryWeaverError::validation("This relationship has already ended"));
+        }
+
+        let mut next = CharacterRelationship::new(
+            &previous.project_id,
+            &previous.from_character_id,
+            &previous.to_character_id,
+            relationship_type,
+        );
+        next.label = label;
+        next.description = description;
+        next.starts_in_chapter_id = Some(chapter_id.to_string());
+
+        previous.ends_in_chapter_id = Some(chapter_id.to_string());
+        Self::update(pool, &previous).await?;
+        Self::create(pool, next).await
+    }
+
+    /// Relationships holding at `document_id` (a chapter, or a scene in
+    /// one); at the end of the story when `None`
+    pub async fn active_at(
+        pool: &Pool<Sqlite>,
+        project_id: &str,
+        document_id: Option<&str>,
+    ) -> Result<Vec<CharacterRelationship>> {
+        let timeline = StoryTimeline::load(pool, project_id).await?;
+        let at = Self::story_position(&timeline, document_id)?;
+        let relationships = Self::get_by_project(pool, project_id).await?;
+
+        Ok(relationships.into_iter().filter(|r| timeline.is_active(r, at)).collect())
+    }
+
+    fn story_position(timeline: &StoryTimeline, document_id: Option<&str>) -> Result<Option<usize>> {
+        match document_id {
+            Some(id) => timeline
+                .position(id)
+                .map(Some)
+                .ok_or_else(|| StoryWeaverError::validation(format!("{} is not a chapter or scene of this project", id))),
+            None => Ok(None),
+        }
+    }
+
+    /// A character's relationships holding at `document_id`
+    pub async fn for_character_at(
+        pool: &Pool<Sqlite>,
+        project_id: &str,
+        character_id: &str,
+        document_id: Option<&str>,
+    ) -> Result<Vec<CharacterRelationship>> {
+        let relationships = Self::active_at(pool, project_id, document_id).await?;
+        Ok(relationships
+            .into_iter()
+            .filter(|r| r.from_character_id == character_id || r.to_character_id == character_id)
+            .collect())
+    }
+
+    /// Shortest chain of relationships linking two characters at
+    /// `document_id`, following relationships in either direction. `None`
+    /// when they aren't connected.
+    pub async fn find_path(
+        pool: &Pool<Sqlite>,
+        project_id: &str,
+        from_character_id: &str,
+        to_character_id: &str,
+        document_id: Option<&str>,
+    ) -> Result<Option<Vec<RelationshipPathStep>>> {
+        let relationships = Self::active_at(pool, project_id, document_id).await?;
+
+        let mut edges: HashMap<&str, Vec<&CharacterRelationship>> = HashMap::new();
+        for relationship in &relationships {
+            edges.entry(relationship.from_character_id.as_str()).or_default().push(relationship);
+            edges.entry(relationship.to_character_id.as_str()).or_default().push(relationship);
+        }
+
+        // Breadth first, remembering the relationship each character was reached by
+        let mut reached_by: HashMap<&str, &CharacterRelationship> = HashMap::new();
+        let mut seen = HashSet::from([from_character_id]);
+        let mut queue = VecDeque::from([from_character_id]);
+        while let Some(current) = queue.pop_front() {
+            if current == to_character_id {
+                break;
+            }
+            for &relationship in edges.get(current).into_iter().flatten() {
+                let next = relationship.other_end(current);
+                if seen.insert(next) {
+                    reached_by.insert(next, relationship);
+                    queue.push_back(next);
+                }
+            }
+        }
+
+        if !seen.contains(to_character_id) {
+            return Ok(None);
+        }
+        let mut steps = Vec::new();
+        let mut current = to_character_id;
+        while let Some(&relationship) = reached_by.get(current) {
+            let previous = relationship.other_end(current);
+            steps.push(RelationshipPathStep {
+                from_character_id: previous.to_string(),
+                to_character_id: current.to_string(),
+                relationship: relationship.clone(),
+            });
+            current = previous;
+        }
+        steps.reverse();
+
+        Ok(Some(steps))
+    }
+
+    /// The project's characters with the relationships holding at
+    /// `document_id`
+    pub async fn graph(pool: &Pool<Sqlite>, project_id: &str, document_id: Option<&str>) -> Result<RelationshipGraph> {
+        let nodes = CharacterOps::get_by_project(pool, project_id)
+            .await?
+            .into_iter()
+            .map(|character| RelationshipGraphNode {
+                id: character.id,
+                name: character.name,
+                role: character.role,
+            })
+            .collect();
+        let edges = Self::active_at(pool, project_id, document_id).await?;
+
+        Ok(RelationshipGraph { nodes, edges })
+    }
+}
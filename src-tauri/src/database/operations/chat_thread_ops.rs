@@ -0,0 +1,368 @@
+//! Quick Chat thread database operations
+//! Provides functions to interact with the chat_threads and chat_messages tables
+
+use crate::error::{Result, StoryWeaverError};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Row, Sqlite};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChatRole {
+    User,
+    Assistant,
+}
+
+impl ChatRole {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChatRole::User => "user",
+            ChatRole::Assistant => "assistant",
+        }
+    }
+
+    fn from_db(value: &str) -> Self {
+        match value {
+            "assistant" => ChatRole::Assistant,
+            _ => ChatRole::User,
+        }
+    }
+}
+
+/// Kind of story bible element a thread can pin
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PinnedElementType {
+    Character,
+    Location,
+    WorldElement,
+    PlotThread,
+}
+
+/// A story bible element included with every turn of a thread
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PinnedReference {
+    pub element_type: PinnedElementType,
+    pub element_id: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatThread {
+    pub id: String,
+    pub project_id: String,
+    pub document_id: Option<String>,
+    pub title: String,
+    /// Rolling summary of the first `summarized_count` messages
+    pub summary: String,
+    pub summarized_count: i64,
+    pub pinned_references: Vec<PinnedReference>,
+    /// Thread and message this one was branched from
+    pub parent_thread_id: Option<String>,
+    pub branched_from_message_id: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl ChatThread {
+    pub fn new(project_id: String, document_id: Option<String>, title: String) -> Self {
+        let now = Utc::now();
+        Self {
+            id: Uuid::new_v4().to_string(),
+            project_id,
+            document_id,
+            title,
+            summary: String::new(),
+            summarized_count: 0,
+            pinned_references: Vec::new(),
+            parent_thread_id: None,
+            branched_from_message_id: None,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatThreadMessage {
+    pub id: String,
+    pub thread_id: String,
+    /// 0-based order within the thread
+    pub position: i64,
+    pub role: ChatRole,
+    pub content: String,
+    pub token_count: i64,
+    pub created_at: DateTime<Utc>,
+}
+
+fn thread_from_row(row: &sqlx::sqlite::SqliteRow) -> ChatThread {
+    let pins: String = row.get("pinned_references");
+    ChatThread {
+        id: row.get("id"),
+        project_id: row.get("project_id"),
+        document_id: row.get("document_id"),
+        title: row.get("title"),
+        summary: row.get("summary"),
+        summarized_count: row.get("summarized_count"),
+        pinned_references: serde_json::from_str(&pins).unwrap_or_default(),
+        parent_thread_id: row.get("parent_thread_id"),
+        branched_from_message_id: row.get("branched_from_message_id"),
+        created_at: row.get("created_at"),
+        updated_at: row.get("updated_at"),
+    }
+}
+
+fn message_from_row(row: &sqlx::sqlite::SqliteRow) -> ChatThreadMessage {
+    let role: String = row.get("role");
+    ChatThreadMessage {
+        id: row.get("id"),
+        thread_id: row.get("thread_id"),
+        position: row.get("position"),
+        role: ChatRole::from_db(&role),
+        content: row.get("content"),
+        token_count: row.get("token_count"),
+        created_at: row.get("created_at"),
+    }
+}
+
+impl super::ChatThreadOps {
+    /// Create a new thread
+    pub async fn create(pool: &Pool<Sqlite>, thread: ChatThread) -> Result<ChatThread> {
+        sqlx::query(
+            r#"
+            INSERT INTO chat_threads (id, project_id, document_id, title, summary, summarized_count,
+                pinned_references, parent_thread_id, branched_from_message_id, created_at, updated_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&thread.id)
+        .bind(&thread.project_id)
+        .bind(&thread.document_id)
+        .bind(&thread.title)
+        .bind(&thread.summary)
+        .bind(thread.summarized_count)
+        .bind(serde_json::to_string(&thread.pinned_references).unwrap_or_else(|_| "[]".to_string()))
+        .bind(&thread.parent_thread_id)
+        .bind(&thread.branched_from_message_id)
+        .bind(thread.created_at)
+        .bind(thread.updated_at)
+        .execute(pool)
+        .await
+        .map_err(|e| StoryWeaverError::database(format!("Failed to create chat thread: {}", e)))?;
+
+        Ok(thread)
+    }
+
+    pub async fn get_by_id(pool: &Pool<Sqlite>, id: &str) -> Result<ChatThread> {
+        let row = sqlx::query("SELECT * FROM chat_threads WHERE id = ?")
+            .bind(id)
+            .fetch_optional(pool)
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to get chat thread: {}", e)))?
+            .ok_or_else(|| StoryWeaverError::not_found("chat thread", id))?;
+
+        Ok(thread_from_row(&row))
+    }
+
+    /// Threads of a project, most recently active first; with `document_id`,
+    /// only the threads scoped to that document
+    pub async fn get_by_project(pool: &Pool<Sqlite>, project_id: &str, document_id: Option<&str>) -> Result<Vec<ChatThread>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT * FROM chat_threads
+            WHERE project_id = ? AND (? IS NULL OR document_id = ?)
+            ORDER BY updated_at DESC
+            "#,
+        )
+        .bind(project_id)
+        .bind(document_id)
+        .bind(document_id)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| StoryWeaverError::database(format!("Failed to get chat threads: {}", e)))?;
+
+        Ok(rows.iter().map(thread_from_row).collect())
+    }
+
+    pub async fn rename(pool: &Pool<Sqlite>, id: &str, title: &str) -> Result<()> {
+        sqlx::query("UPDATE chat_threads SET title = ?, updated_at = ? WHERE id = ?")
+            .bind(title)
+            .bind(Utc::now())
+            .bind(id)
+            .execute(pool)
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to rename chat thread: {}", e)))?;
+
+        Ok(())
+    }
+
+    pub async fn set_pinned_references(pool: &Pool<Sqlite>, id: &str, pins: &[PinnedReference]) -> Result<()> {
+        sqlx::query("UPDATE chat_threads SET pinned_references = ?, updated_at = ? WHERE id = ?")
+            .bind(serde_json::to_string(pins).unwrap_or_else(|_| "[]".to_string()))
+            .bind(Utc::now())
+            .bind(id)
+            .execute(pool)
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to update pinned references: {}", e)))?;
+
+        Ok(())
+    }
+
+    /// Replace the rolling summary, which now covers the first `summarized_count` messages
+    pub async fn set_summary(pool: &Pool<Sqlite>, id: &str, summary: &str, summarized_count: i64) -> Result<()> {
+        sqlx::query("UPDATE chat_threads SET summary = ?, summarized_count = ? WHERE id = ?")
+            .bind(summary)
+            .bind(summarized_count)
+            .bind(id)
+            .execute(pool)
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to update chat summary: {}", e)))?;
+
+        Ok(())
+    }
+
+    pub async fn delete(pool: &Pool<Sqlite>, id: &str) -> Result<()> {
+        sqlx::query("DELETE FROM chat_threads WHERE id = ?")
+            .bind(id)
+            .execute(pool)
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to delete chat thread: {}", e)))?;
+
+        Ok(())
+    }
+
+    /// Append a message to a thread
+    pub async fn add_message(
+        pool: &Pool<Sqlite>,
+        thread_id: &str,
+        role: ChatRole,
+        content: &str,
+        token_count: i64,
+    ) -> Result<ChatThreadMessage> {
+        let position: i64 = sqlx::query("SELECT COALESCE(MAX(position) + 1, 0) AS next FROM chat_messages WHERE thread_id = ?")
+            .bind(thread_id)
+            .fetch_one(pool)
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to get next chat position: {}", e)))?
+            .get("next");
+
+        let message = ChatThreadMessage {
+            id: Uuid::new_v4().to_string(),
+            thread_id: thread_id.to_string(),
+            position,
+            role,
+            content: content.to_string(),
+            token_count,
+            created_at: Utc::now(),
+        };
+
+        sqlx::query(
+            "INSERT INTO chat_messages (id, thread_id, position, role, content, token_count, created_at) VALUES (?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(&message.id)
+        .bind(&message.thread_id)
+        .bind(message.position)
+        .bind(message.role.as_str())
+        .bind(&message.content)
+        .bind(message.token_count)
+        .bind(message.created_at)
+        .execute(pool)
+        .await
+        .map_err(|e| StoryWeaverError::database(format!("Failed to add chat message: {}", e)))?;
+
+        sqlx::query("UPDATE chat_threads SET updated_at = ? WHERE id = ?")
+            .bind(message.created_at)
+            .bind(thread_id)
+            .execute(pool)
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to touch chat thread: {}", e)))?;
+
+        Ok(message)
+    }
+
+    /// A thread's messages in order
+    pub async fn get_messages(pool: &Pool<Sqlite>, thread_id: &str) -> Result<Vec<ChatThreadMessage>> {
+        let rows = sqlx::query("SELECT * FROM chat_messages WHERE thread_id = ? ORDER BY position")
+            .bind(thread_id)
+            .fetch_all(pool)
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to get chat messages: {}", e)))?;
+
+        Ok(rows.iter().map(message_from_row).collect())
+    }
+
+    /// Start a new thread holding a copy of `thread_id`'s messages up to and
+    /// including `message_id`. The parent thread is left untouched.
+    pub async fn branch(pool: &Pool<Sqlite>, thread_id: &str, message_id: &str, title: Option<&str>) -> Result<ChatThread> {
+        let parent = Self::get_by_id(pool, thread_id).await?;
+        let messages = Self::get_messages(pool, thread_id).await?;
+        let cut = messages
+            .iter()
+            .position(|m| m.id == message_id)
+            .ok_or_else(|| StoryWeaverError::not_found("chat message", message_id))?
+            + 1;
+
+        let mut branch = ChatThread::new(
+            parent.project_id.clone(),
+            parent.document_id.clone(),
+            title.map(|t| t.to_string()).unwrap_or_else(|| format!("{} (branch)", parent.title)),
+        );
+        branch.pinned_references = parent.pinned_references.clone();
+        branch.parent_thread_id = Some(parent.id.clone());
+        branch.branched_from_message_id = Some(message_id.to_string());
+        // The summary is only reusable if it covers nothing past the branch point
+        if parent.summarized_count as usize <= cut {
+            branch.summary = parent.summary.clone();
+            branch.summarized_count = parent.summarized_count;
+        }
+
+        let mut tx = pool
+            .begin()
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to begin chat branch transaction: {}", e)))?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO chat_threads (id, project_id, document_id, title, summary, summarized_count,
+                pinned_references, parent_thread_id, branched_from_message_id, created_at, updated_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&branch.id)
+        .bind(&branch.project_id)
+        .bind(&branch.document_id)
+        .bind(&branch.title)
+        .bind(&branch.summary)
+        .bind(branch.summarized_count)
+        .bind(serde_json::to_string(&branch.pinned_references).unwrap_or_else(|_| "[]".to_string()))
+        .bind(&branch.parent_thread_id)
+        .bind(&branch.branched_from_message_id)
+        .bind(branch.created_at)
+        .bind(branch.updated_at)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| StoryWeaverError::database(format!("Failed to create chat branch: {}", e)))?;
+
+        for message in &messages[..cut] {
+            sqlx::query(
+                "INSERT INTO chat_messages (id, thread_id, position, role, content, token_count, created_at) VALUES (?, ?, ?, ?, ?, ?, ?)",
+            )
+            .bind(Uuid::new_v4().to_string())
+            .bind(&branch.id)
+            .bind(message.position)
+            .bind(message.role.as_str())
+            .bind(&message.content)
+            .bind(message.token_count)
+            .bind(message.created_at)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to copy chat message: {}", e)))?;
+        }
+
+        tx.commit()
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to commit chat branch: {}", e)))?;
+
+        Ok(branch)
+    }
+}
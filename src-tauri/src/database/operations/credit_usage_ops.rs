@@ -8,16 +8,36 @@ use serde::{Deserialize, Serialize};
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CreditUsage {
     pub id: Option<i32>,
-    pub project_id: i32,
+    pub project_id: Option<String>,
     pub operation_type: String, // "text_generation", "image_generation", "brainstorming", "style_analysis"
+    #[serde(default)]
+    pub provider: Option<String>,
     pub model_used: String,
     pub tokens_used: Option<i32>,
     pub credits_consumed: f64,
+    #[serde(default)]
+    pub cost_usd: f64, // Estimated cost, counted against AI budgets
     pub operation_details: Option<String>, // JSON
     pub session_id: Option<String>,
     pub created_at: Option<String>,
 }
 
+fn usage_from_row(r: &sqlx::sqlite::SqliteRow) -> CreditUsage {
+    CreditUsage {
+        id: r.get::<Option<i64>, _>("id").map(|id| id as i32),
+        project_id: r.get("project_id"),
+        operation_type: r.get("operation_type"),
+        provider: r.get("provider"),
+        model_used: r.get::<Option<String>, _>("model_used").unwrap_or_default(),
+        tokens_used: r.get::<Option<i64>, _>("tokens_used").map(|t| t as i32),
+        credits_consumed: r.get::<Option<f64>, _>("credits_consumed").unwrap_or_default(),
+        cost_usd: r.get("cost_usd"),
+        operation_details: r.get("operation_details"),
+        session_id: r.get("session_id"),
+        created_at: r.get::<Option<String>, _>("created_at"),
+    }
+}
+
 /// Credit Usage database operations
 impl super::CreditUsageOps {
     /// Create a new credit usage record
@@ -25,17 +45,19 @@ impl super::CreditUsageOps {
         let result = sqlx::query(
             r#"
             INSERT INTO credit_usage (
-                project_id, operation_type, model_used, tokens_used, credits_consumed,
-                operation_details, session_id
+                project_id, operation_type, provider, model_used, tokens_used, credits_consumed,
+                cost_usd, operation_details, session_id
             )
-            VALUES (?, ?, ?, ?, ?, ?, ?)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
             "#
         )
-        .bind(usage.project_id)
+        .bind(&usage.project_id)
         .bind(&usage.operation_type)
+        .bind(&usage.provider)
         .bind(&usage.model_used)
         .bind(usage.tokens_used)
         .bind(usage.credits_consumed)
+        .bind(usage.cost_usd)
         .bind(&usage.operation_details)
         .bind(&usage.session_id)
         .execute(&*pool)
@@ -49,8 +71,8 @@ impl super::CreditUsageOps {
     pub async fn get_by_id(pool: &Pool<Sqlite>, id: i32) -> Result<Option<CreditUsage>> {
         let row = sqlx::query(
             r#"
-            SELECT id, project_id, operation_type, model_used, tokens_used, credits_consumed,
-                   operation_details, session_id, created_at
+            SELECT id, project_id, operation_type, provider, model_used, tokens_used, credits_consumed,
+                   cost_usd, operation_details, session_id, created_at
             FROM credit_usage WHERE id = ?
             "#
         )
@@ -59,25 +81,15 @@ impl super::CreditUsageOps {
         .await
         .map_err(|e| StoryWeaverError::database(format!("Failed to get credit usage record: {}", e)))?;
 
-        Ok(row.map(|r| CreditUsage {
-            id: Some(r.get::<i64, _>("id") as i32),
-            project_id: r.get::<i64, _>("project_id") as i32,
-            operation_type: r.get("operation_type"),
-            model_used: r.get::<Option<String>, _>("model_used").unwrap_or_default(),
-            tokens_used: r.get::<Option<i64>, _>("tokens_used").map(|t| t as i32),
-            credits_consumed: r.get("credits_consumed"),
-            operation_details: r.get("operation_details"),
-            session_id: r.get("session_id"),
-            created_at: r.get::<Option<String>, _>("created_at"),
-        }))
+        Ok(row.as_ref().map(usage_from_row))
     }
 
     /// Get credit usage records by project ID
-    pub async fn get_by_project(pool: &Pool<Sqlite>, project_id: i32) -> Result<Vec<CreditUsage>> {
+    pub async fn get_by_project(pool: &Pool<Sqlite>, project_id: &str) -> Result<Vec<CreditUsage>> {
         let rows = sqlx::query(
             r#"
-            SELECT id, project_id, operation_type, model_used, tokens_used, credits_consumed,
-                   operation_details, session_id, created_at
+            SELECT id, project_id, operation_type, provider, model_used, tokens_used, credits_consumed,
+                   cost_usd, operation_details, session_id, created_at
             FROM credit_usage WHERE project_id = ? ORDER BY created_at DESC
             "#
         )
@@ -86,25 +98,15 @@ impl super::CreditUsageOps {
         .await
         .map_err(|e| StoryWeaverError::database(format!("Failed to get credit usage by project: {}", e)))?;
 
-        Ok(rows.into_iter().map(|r| CreditUsage {
-            id: Some(r.get::<i64, _>("id") as i32),
-            project_id: r.get::<i64, _>("project_id") as i32,
-            operation_type: r.get("operation_type"),
-            model_used: r.get::<Option<String>, _>("model_used").unwrap_or_default(),
-            tokens_used: r.get::<Option<i64>, _>("tokens_used").map(|t| t as i32),
-            credits_consumed: r.get("credits_consumed"),
-            operation_details: r.get("operation_details"),
-            session_id: r.get("session_id"),
-            created_at: r.get::<Option<String>, _>("created_at"),
-        }).collect())
+        Ok(rows.iter().map(usage_from_row).collect())
     }
 
     /// Get credit usage records by operation type
     pub async fn get_by_operation_type(pool: &Pool<Sqlite>, operation_type: &str) -> Result<Vec<CreditUsage>> {
         let rows = sqlx::query(
             r#"
-            SELECT id, project_id, operation_type, model_used, tokens_used, credits_consumed,
-                   operation_details, session_id, created_at
+            SELECT id, project_id, operation_type, provider, model_used, tokens_used, credits_consumed,
+                   cost_usd, operation_details, session_id, created_at
             FROM credit_usage WHERE operation_type = ? ORDER BY created_at DESC
             "#
         )
@@ -113,25 +115,15 @@ impl super::CreditUsageOps {
         .await
         .map_err(|e| StoryWeaverError::database(format!("Failed to get credit usage by operation type: {}", e)))?;
 
-        Ok(rows.into_iter().map(|r| CreditUsage {
-            id: Some(r.get::<i64, _>("id") as i32),
-            project_id: r.get::<i64, _>("project_id") as i32,
-            operation_type: r.get("operation_type"),
-            model_used: r.get::<Option<String>, _>("model_used").unwrap_or_default(),
-            tokens_used: r.get::<Option<i64>, _>("tokens_used").map(|t| t as i32),
-            credits_consumed: r.get("credits_consumed"),
-            operation_details: r.get("operation_details"),
-            session_id: r.get("session_id"),
-            created_at: r.get::<Option<String>, _>("created_at"),
-        }).collect())
+        Ok(rows.iter().map(usage_from_row).collect())
     }
 
     /// Get credit usage records by session ID
     pub async fn get_by_session(pool: &Pool<Sqlite>, session_id: &str) -> Result<Vec<CreditUsage>> {
         let rows = sqlx::query(
             r#"
-            SELECT id, project_id, operation_type, model_used, tokens_used, credits_consumed,
-                   operation_details, session_id, created_at
+            SELECT id, project_id, operation_type, provider, model_used, tokens_used, credits_consumed,
+                   cost_usd, operation_details, session_id, created_at
             FROM credit_usage WHERE session_id = ? ORDER BY created_at DESC
             "#
         )
@@ -140,25 +132,15 @@ impl super::CreditUsageOps {
         .await
         .map_err(|e| StoryWeaverError::database(format!("Failed to get credit usage by session: {}", e)))?;
 
-        Ok(rows.into_iter().map(|r| CreditUsage {
-            id: Some(r.get::<i64, _>("id") as i32),
-            project_id: r.get::<i64, _>("project_id") as i32,
-            operation_type: r.get("operation_type"),
-            model_used: r.get::<Option<String>, _>("model_used").unwrap_or_default(),
-            tokens_used: r.get::<Option<i64>, _>("tokens_used").map(|t| t as i32),
-            credits_consumed: r.get("credits_consumed"),
-            operation_details: r.get("operation_details"),
-            session_id: r.get("session_id"),
-            created_at: r.get::<Option<String>, _>("created_at"),
-        }).collect())
+        Ok(rows.iter().map(usage_from_row).collect())
     }
 
     /// List all credit usage records
     pub async fn list_all(pool: &Pool<Sqlite>) -> Result<Vec<CreditUsage>> {
         let rows = sqlx::query(
             r#"
-            SELECT id, project_id, operation_type, model_used, tokens_used, credits_consumed,
-                   operation_details, session_id, created_at
+            SELECT id, project_id, operation_type, provider, model_used, tokens_used, credits_consumed,
+                   cost_usd, operation_details, session_id, created_at
             FROM credit_usage ORDER BY created_at DESC
             "#
         )
@@ -166,17 +148,7 @@ impl super::CreditUsageOps {
         .await
         .map_err(|e| StoryWeaverError::database(format!("Failed to list credit usage records: {}", e)))?;
 
-        Ok(rows.into_iter().map(|r| CreditUsage {
-            id: Some(r.get::<i64, _>("id") as i32),
-            project_id: r.get::<i64, _>("project_id") as i32,
-            operation_type: r.get("operation_type"),
-            model_used: r.get::<Option<String>, _>("model_used").unwrap_or_default(),
-            tokens_used: r.get::<Option<i64>, _>("tokens_used").map(|t| t as i32),
-            credits_consumed: r.get("credits_consumed"),
-            operation_details: r.get("operation_details"),
-            session_id: r.get("session_id"),
-            created_at: r.get::<Option<String>, _>("created_at"),
-        }).collect())
+        Ok(rows.iter().map(usage_from_row).collect())
     }
 
     /// Delete a credit usage record
@@ -191,7 +163,7 @@ impl super::CreditUsageOps {
     }
 
     /// Get total credits consumed by project
-    pub async fn get_total_by_project(pool: &Pool<Sqlite>, project_id: i32) -> Result<f64> {
+    pub async fn get_total_by_project(pool: &Pool<Sqlite>, project_id: &str) -> Result<f64> {
         let row = sqlx::query(
             "SELECT COALESCE(SUM(credits_consumed), 0.0) as total_credits FROM credit_usage WHERE project_id = ?"
         )
@@ -217,7 +189,7 @@ impl super::CreditUsageOps {
     }
 
     /// Get daily credit usage for a project
-    pub async fn get_daily_usage(pool: &Pool<Sqlite>, project_id: i32, date: &str) -> Result<f64> {
+    pub async fn get_daily_usage(pool: &Pool<Sqlite>, project_id: &str, date: &str) -> Result<f64> {
         let row = sqlx::query(
             r#"
             SELECT COALESCE(SUM(credits_consumed), 0.0) as daily_credits 
@@ -235,11 +207,11 @@ impl super::CreditUsageOps {
     }
 
     /// Get credit usage within date range
-    pub async fn get_usage_in_range(pool: &Pool<Sqlite>, project_id: i32, start_date: &str, end_date: &str) -> Result<Vec<CreditUsage>> {
+    pub async fn get_usage_in_range(pool: &Pool<Sqlite>, project_id: &str, start_date: &str, end_date: &str) -> Result<Vec<CreditUsage>> {
         let rows = sqlx::query(
             r#"
-            SELECT id, project_id, operation_type, model_used, tokens_used, credits_consumed,
-                   operation_details, session_id, created_at
+            SELECT id, project_id, operation_type, provider, model_used, tokens_used, credits_consumed,
+                   cost_usd, operation_details, session_id, created_at
             FROM credit_usage 
             WHERE project_id = ? AND DATE(created_at) BETWEEN ? AND ?
             ORDER BY created_at DESC
@@ -252,25 +224,42 @@ impl super::CreditUsageOps {
         .await
         .map_err(|e| StoryWeaverError::database(format!("Failed to get credit usage in range: {}", e)))?;
 
-        Ok(rows.into_iter().map(|r| CreditUsage {
-            id: r.get::<Option<i64>, _>("id").map(|id| id as i32),
-            project_id: r.get::<i64, _>("project_id") as i32,
-            operation_type: r.get("operation_type"),
-            model_used: r.get::<Option<String>, _>("model_used").unwrap_or_default(),
-            tokens_used: r.get::<Option<i64>, _>("tokens_used").map(|t| t as i32),
-            credits_consumed: r.get("credits_consumed"),
-            operation_details: r.get("operation_details"),
-            session_id: r.get("session_id"),
-            created_at: r.get::<Option<String>, _>("created_at"),
-        }).collect())
+        Ok(rows.iter().map(usage_from_row).collect())
+    }
+
+    /// Estimated cost spent since `since` (a date or datetime), optionally
+    /// only for one project and/or provider
+    pub async fn get_spend_since(
+        pool: &Pool<Sqlite>,
+        project_id: Option<&str>,
+        provider: Option<&str>,
+        since: &str,
+    ) -> Result<f64> {
+        let row = sqlx::query(
+            r#"
+            SELECT COALESCE(SUM(cost_usd), 0.0) as spend
+            FROM credit_usage
+            WHERE created_at >= ? AND (? IS NULL OR project_id = ?) AND (? IS NULL OR provider = ?)
+            "#
+        )
+        .bind(since)
+        .bind(project_id)
+        .bind(project_id)
+        .bind(provider)
+        .bind(provider)
+        .fetch_one(pool)
+        .await
+        .map_err(|e| StoryWeaverError::database(format!("Failed to get AI spend: {}", e)))?;
+
+        Ok(row.get("spend"))
     }
 
     /// Get recent credit usage records (last N)
     pub async fn get_recent(pool: &Pool<Sqlite>, limit: i32) -> Result<Vec<CreditUsage>> {
         let rows = sqlx::query(
             r#"
-            SELECT id, project_id, operation_type, model_used, tokens_used, credits_consumed,
-                   operation_details, session_id, created_at
+            SELECT id, project_id, operation_type, provider, model_used, tokens_used, credits_consumed,
+                   cost_usd, operation_details, session_id, created_at
             FROM credit_usage ORDER BY created_at DESC LIMIT ?
             "#
         )
@@ -279,17 +268,7 @@ impl super::CreditUsageOps {
         .await
         .map_err(|e| StoryWeaverError::database(format!("Failed to get recent credit usage: {}", e)))?;
 
-        Ok(rows.into_iter().map(|r| CreditUsage {
-            id: r.get::<Option<i64>, _>("id").map(|id| id as i32),
-            project_id: r.get::<i64, _>("project_id") as i32,
-            operation_type: r.get("operation_type"),
-            model_used: r.get::<Option<String>, _>("model_used").unwrap_or_default(),
-            tokens_used: r.get::<Option<i64>, _>("tokens_used").map(|t| t as i32),
-            credits_consumed: r.get("credits_consumed"),
-            operation_details: r.get("operation_details"),
-            session_id: r.get("session_id"),
-            created_at: r.get::<Option<String>, _>("created_at"),
-        }).collect())
+        Ok(rows.iter().map(usage_from_row).collect())
     }
 
     /// Get credit usage statistics by model
@@ -7,7 +7,15 @@ use uuid::Uuid;
 /// Location operations
 impl super::LocationOps {
     /// Create a new location
-    pub async fn create(pool: &Pool<Sqlite>, mut location: Location) -> Result<Location> {
+    pub async fn create(pool: &Pool<Sqlite>, location: Location) -> Result<Location> {
+        Self::insert(pool, location).await
+    }
+
+    /// Create a new location on any executor, such as an open transaction
+    pub(crate) async fn insert<'e, E>(executor: E, mut location: Location) -> Result<Location>
+    where
+        E: sqlx::Executor<'e, Database = Sqlite>,
+    {
         location.id = Uuid::new_v4().to_string();
         location.created_at = Utc::now();
         location.updated_at = Utc::now();
@@ -34,7 +42,7 @@ impl super::LocationOps {
         .bind(location.created_at)
         .bind(location.updated_at)
         .bind(&location.metadata)
-        .execute(&*pool)
+        .execute(executor)
         .await
         .map_err(|e| StoryWeaverError::database(format!("Failed to create location: {}", e)))?;
         
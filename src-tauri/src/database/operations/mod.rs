@@ -33,6 +33,17 @@ pub mod generated_image_ops;
 pub mod brainstorm_session_ops;
 pub mod credit_usage_ops;
 pub mod streaming_session_ops;
+pub mod semantic_index_ops;
+pub mod chat_thread_ops;
+pub mod prompt_template_ops;
+pub mod budget_ops;
+pub mod model_pricing_ops;
+pub mod chapter_draft_ops;
+pub mod story_summary_ops;
+pub mod character_relationship_ops;
+pub mod story_calendar_ops;
+pub mod story_bible_alias_ops;
+pub mod story_bible_extraction_ops;
 
 // Phase 5 Collaboration & Plugins
 pub mod collaboration;
@@ -57,6 +68,16 @@ pub use generated_image_ops::*;
 pub use brainstorm_session_ops::*;
 pub use credit_usage_ops::*;
 pub use streaming_session_ops::*;
+pub use semantic_index_ops::*;
+pub use chat_thread_ops::*;
+pub use prompt_template_ops::*;
+pub use budget_ops::*;
+pub use model_pricing_ops::*;
+pub use chapter_draft_ops::*;
+pub use story_summary_ops::*;
+pub use character_relationship_ops::*;
+pub use story_bible_alias_ops::*;
+pub use story_bible_extraction_ops::*;
 
 // Phase 5 Collaboration & Plugins - only actively used
 pub use collaboration::*;
@@ -98,6 +119,17 @@ pub struct GeneratedImageOps;
 pub struct BrainstormSessionOps;
 pub struct CreditUsageOps;
 pub struct StreamingSessionOps;
+pub struct SemanticIndexOps;
+pub struct ChatThreadOps;
+pub struct PromptTemplateOps;
+pub struct AIBudgetOps;
+pub struct ModelPricingOps;
+pub struct ChapterDraftOps;
+pub struct StorySummaryOps;
+pub struct CharacterRelationshipOps;
+pub struct StoryCalendarOps;
+pub struct StoryBibleAliasOps;
+pub struct StoryBibleExtractionOps;
 
 // Phase 5 Collaboration & Plugins
 pub struct CollaborationOps;
@@ -0,0 +1,238 @@
+//! Model pricing database operations
+//! Provides functions to interact with the model_pricing table
+
+use crate::error::{Result, StoryWeaverError};
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Row, Sqlite};
+use uuid::Uuid;
+
+/// Model name that prices every model of a provider, e.g. a local server
+pub const ANY_MODEL: &str = "*";
+
+/// The price of a model from `effective_from` until a later price replaces it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModelPrice {
+    #[serde(default)]
+    pub id: String,
+    /// Provider the price applies to; `None` for the model under any provider
+    #[serde(default)]
+    pub provider: Option<String>,
+    pub model: String,
+    pub input_cost_per_1k: f64,
+    pub output_cost_per_1k: f64,
+    /// Price of input served from the provider's prompt cache, if discounted
+    #[serde(default)]
+    pub cached_input_cost_per_1k: Option<f64>,
+    /// Price per generated image
+    #[serde(default)]
+    pub image_cost: Option<f64>,
+    #[serde(default = "default_currency")]
+    pub currency: String,
+    pub effective_from: NaiveDate,
+    #[serde(default = "default_source")]
+    pub source: String,
+    #[serde(default = "Utc::now")]
+    pub updated_at: DateTime<Utc>,
+}
+
+fn default_currency() -> String {
+    "USD".to_string()
+}
+
+fn default_source() -> String {
+    "user".to_string()
+}
+
+impl ModelPrice {
+    pub fn new(model: &str, input_cost_per_1k: f64, output_cost_per_1k: f64, effective_from: NaiveDate) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            provider: None,
+            model: model.to_string(),
+            input_cost_per_1k,
+            output_cost_per_1k,
+            cached_input_cost_per_1k: None,
+            image_cost: None,
+            currency: default_currency(),
+            effective_from,
+            source: default_source(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    pub fn for_provider(mut self, provider: &str) -> Self {
+        self.provider = Some(provider.to_string());
+        self
+    }
+
+    /// Check the price is usable: a model name, and prices that are finite and not negative
+    pub fn validate(&self) -> Result<()> {
+        if self.model.trim().is_empty() || self.model.len() > 200 {
+            return Err(StoryWeaverError::validation("A model price needs a model name of up to 200 characters"));
+        }
+        let prices = [Some(self.input_cost_per_1k), Some(self.output_cost_per_1k), self.cached_input_cost_per_1k, self.image_cost];
+        if prices.into_iter().flatten().any(|price| !price.is_finite() || price < 0.0) {
+            return Err(StoryWeaverError::validation(format!(
+                "Prices for {} must be zero or more",
+                self.model
+            )));
+        }
+        Ok(())
+    }
+}
+
+fn price_from_row(row: &sqlx::sqlite::SqliteRow) -> ModelPrice {
+    let provider: String = row.get("provider");
+    let effective_from: String = row.get("effective_from");
+    ModelPrice {
+        id: row.get("id"),
+        provider: Some(provider).filter(|p| !p.is_empty()),
+        model: row.get("model"),
+        input_cost_per_1k: row.get("input_cost_per_1k"),
+        output_cost_per_1k: row.get("output_cost_per_1k"),
+        cached_input_cost_per_1k: row.get("cached_input_cost_per_1k"),
+        image_cost: row.get("image_cost"),
+        currency: row.get("currency"),
+        effective_from: NaiveDate::parse_from_str(&effective_from, "%Y-%m-%d").unwrap_or_default(),
+        source: row.get("source"),
+        updated_at: row.get("updated_at"),
+    }
+}
+
+const UPSERT_PRICE: &str = r#"
+    INSERT INTO model_pricing (id, provider, model, input_cost_per_1k, output_cost_per_1k,
+        cached_input_cost_per_1k, image_cost, currency, effective_from, source, updated_at)
+    VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+    ON CONFLICT(provider, model, effective_from) DO UPDATE SET
+        input_cost_per_1k = excluded.input_cost_per_1k,
+        output_cost_per_1k = excluded.output_cost_per_1k,
+        cached_input_cost_per_1k = excluded.cached_input_cost_per_1k,
+        image_cost = excluded.image_cost,
+        currency = excluded.currency,
+        source = excluded.source,
+        updated_at = excluded.updated_at
+"#;
+
+fn bind_price<'q>(
+    query: sqlx::query::Query<'q, Sqlite, sqlx::sqlite::SqliteArguments<'q>>,
+    price: &'q ModelPrice,
+) -> sqlx::query::Query<'q, Sqlite, sqlx::sqlite::SqliteArguments<'q>> {
+    let id = if price.id.is_empty() { Uuid::new_v4().to_string() } else { price.id.clone() };
+    query
+        .bind(id)
+        .bind(price.provider.as_deref().unwrap_or_default())
+        .bind(&price.model)
+        .bind(price.input_cost_per_1k)
+        .bind(price.output_cost_per_1k)
+        .bind(price.cached_input_cost_per_1k)
+        .bind(price.image_cost)
+        .bind(&price.currency)
+        .bind(price.effective_from.format("%Y-%m-%d").to_string())
+        .bind(&price.source)
+        .bind(Utc::now())
+}
+
+impl super::ModelPricingOps {
+    /// Every price, by model and then newest first
+    pub async fn list(pool: &Pool<Sqlite>) -> Result<Vec<ModelPrice>> {
+        let rows = sqlx::query("SELECT * FROM model_pricing ORDER BY model, provider, effective_from DESC")
+            .fetch_all(pool)
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to list model prices: {}", e)))?;
+
+        Ok(rows.iter().map(price_from_row).collect())
+    }
+
+    /// Save a price, replacing the one for the same provider, model and date
+    pub async fn upsert(pool: &Pool<Sqlite>, price: &ModelPrice) -> Result<ModelPrice> {
+        price.validate()?;
+        bind_price(sqlx::query(UPSERT_PRICE), price)
+            .execute(pool)
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to save model price: {}", e)))?;
+
+        let row = sqlx::query("SELECT * FROM model_pricing WHERE provider = ? AND model = ? AND effective_from = ?")
+            .bind(price.provider.as_deref().unwrap_or_default())
+            .bind(&price.model)
+            .bind(price.effective_from.format("%Y-%m-%d").to_string())
+            .fetch_one(pool)
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to get model price: {}", e)))?;
+
+        Ok(price_from_row(&row))
+    }
+
+    /// Save every price or none of them. With `replace`, prices not in the
+    /// import are removed first.
+    pub async fn import(pool: &Pool<Sqlite>, prices: &[ModelPrice], replace: bool) -> Result<usize> {
+        for price in prices {
+            price.validate()?;
+        }
+
+        let mut tx = pool
+            .begin()
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to begin model pricing import: {}", e)))?;
+
+        if replace {
+            sqlx::query("DELETE FROM model_pricing")
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| StoryWeaverError::database(format!("Failed to clear model prices: {}", e)))?;
+        }
+        for price in prices {
+            bind_price(sqlx::query(UPSERT_PRICE), price)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| StoryWeaverError::database(format!("Failed to import price for {}: {}", price.model, e)))?;
+        }
+
+        tx.commit()
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to commit model pricing import: {}", e)))?;
+
+        Ok(prices.len())
+    }
+
+    /// Store built-in prices the catalog doesn't have yet; edited prices are kept
+    pub async fn seed_builtin(pool: &Pool<Sqlite>, prices: &[ModelPrice]) -> Result<usize> {
+        let mut inserted = 0;
+        for price in prices {
+            let result = sqlx::query(
+                r#"
+                INSERT OR IGNORE INTO model_pricing (id, provider, model, input_cost_per_1k, output_cost_per_1k,
+                    cached_input_cost_per_1k, image_cost, currency, effective_from, source)
+                VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, 'builtin')
+                "#,
+            )
+            .bind(Uuid::new_v4().to_string())
+            .bind(price.provider.as_deref().unwrap_or_default())
+            .bind(&price.model)
+            .bind(price.input_cost_per_1k)
+            .bind(price.output_cost_per_1k)
+            .bind(price.cached_input_cost_per_1k)
+            .bind(price.image_cost)
+            .bind(&price.currency)
+            .bind(price.effective_from.format("%Y-%m-%d").to_string())
+            .execute(pool)
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to seed price for {}: {}", price.model, e)))?;
+            inserted += result.rows_affected() as usize;
+        }
+        Ok(inserted)
+    }
+
+    pub async fn delete(pool: &Pool<Sqlite>, id: &str) -> Result<()> {
+        let result = sqlx::query("DELETE FROM model_pricing WHERE id = ?")
+            .bind(id)
+            .execute(pool)
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to delete model price: {}", e)))?;
+
+        if result.rows_affected() == 0 {
+            return Err(StoryWeaverError::not_found("model price", id));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,287 @@
+//! Prompt template database operations
+//! Provides functions to interact with the prompt_templates table
+
+use crate::error::{Result, StoryWeaverError};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Row, Sqlite};
+use uuid::Uuid;
+
+/// Traffic weight given to a version when it is activated on its own
+pub const FULL_TRAFFIC_WEIGHT: i64 = 100;
+
+/// Type a template variable's value must have
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VariableKind {
+    Text,
+    Number,
+    Boolean,
+    List,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TemplateVariable {
+    pub name: String,
+    pub kind: VariableKind,
+    #[serde(default)]
+    pub required: bool,
+    #[serde(default)]
+    pub description: Option<String>,
+}
+
+impl TemplateVariable {
+    pub fn required(name: &str, kind: VariableKind) -> Self {
+        Self { name: name.to_string(), kind, required: true, description: None }
+    }
+
+    pub fn optional(name: &str, kind: VariableKind) -> Self {
+        Self { name: name.to_string(), kind, required: false, description: None }
+    }
+}
+
+/// One version of a named prompt template. Versions without a project or
+/// prose mode apply everywhere; the others override them in their scope.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PromptTemplate {
+    pub id: String,
+    pub name: String,
+    pub version: i64,
+    pub project_id: Option<String>,
+    pub prose_mode: Option<String>,
+    pub description: Option<String>,
+    pub body: String,
+    pub variables: Vec<TemplateVariable>,
+    /// Share of its scope's traffic; 0 when inactive
+    pub traffic_weight: i64,
+    pub created_at: DateTime<Utc>,
+}
+
+impl PromptTemplate {
+    pub fn new(name: &str, body: &str, variables: Vec<TemplateVariable>) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            name: name.to_string(),
+            version: 0,
+            project_id: None,
+            prose_mode: None,
+            description: None,
+            body: body.to_string(),
+            variables,
+            traffic_weight: FULL_TRAFFIC_WEIGHT,
+            created_at: Utc::now(),
+        }
+    }
+
+    pub fn is_global(&self) -> bool {
+        self.project_id.is_none() && self.prose_mode.is_none()
+    }
+}
+
+fn template_from_row(row: &sqlx::sqlite::SqliteRow) -> PromptTemplate {
+    let variables: String = row.get("variables");
+    PromptTemplate {
+        id: row.get("id"),
+        name: row.get("name"),
+        version: row.get("version"),
+        project_id: row.get("project_id"),
+        prose_mode: row.get("prose_mode"),
+        description: row.get("description"),
+        body: row.get("body"),
+        variables: serde_json::from_str(&variables).unwrap_or_default(),
+        traffic_weight: row.get("traffic_weight"),
+        created_at: row.get("created_at"),
+    }
+}
+
+impl super::PromptTemplateOps {
+    /// Store `template` as the next version of its name. A version with a
+    /// traffic weight takes over its scope from the versions active there.
+    pub async fn create_version(pool: &Pool<Sqlite>, mut template: PromptTemplate) -> Result<PromptTemplate> {
+        let mut tx = pool
+            .begin()
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to begin prompt template transaction: {}", e)))?;
+
+        let latest: Option<i64> = sqlx::query_scalar("SELECT MAX(version) FROM prompt_templates WHERE name = ?")
+            .bind(&template.name)
+            .fetch_one(&mut *tx)
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to get latest prompt template version: {}", e)))?;
+        template.version = latest.unwrap_or(0) + 1;
+
+        if template.traffic_weight > 0 {
+            sqlx::query("UPDATE prompt_templates SET traffic_weight = 0 WHERE name = ? AND project_id IS ? AND prose_mode IS ?")
+                .bind(&template.name)
+                .bind(&template.project_id)
+                .bind(&template.prose_mode)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| StoryWeaverError::database(format!("Failed to deactivate prompt templates: {}", e)))?;
+        }
+
+        sqlx::query(
+            r#"
+            INSERT INTO prompt_templates (id, name, version, project_id, prose_mode, description, body,
+                variables, traffic_weight, created_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&template.id)
+        .bind(&template.name)
+        .bind(template.version)
+        .bind(&template.project_id)
+        .bind(&template.prose_mode)
+        .bind(&template.description)
+        .bind(&template.body)
+        .bind(serde_json::to_string(&template.variables).unwrap_or_else(|_| "[]".to_string()))
+        .bind(template.traffic_weight)
+        .bind(template.created_at)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| StoryWeaverError::database(format!("Failed to create prompt template: {}", e)))?;
+
+        tx.commit()
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to commit prompt template: {}", e)))?;
+
+        Ok(template)
+    }
+
+    /// Store a built-in template as version 1 of its name unless the name
+    /// already has versions. Returns whether it was inserted.
+    pub async fn seed_builtin(pool: &Pool<Sqlite>, template: &PromptTemplate) -> Result<bool> {
+        let result = sqlx::query(
+            r#"
+            INSERT INTO prompt_templates (id, name, version, project_id, prose_mode, description, body,
+                variables, traffic_weight, created_at)
+            SELECT ?, ?, 1, NULL, NULL, ?, ?, ?, ?, ?
+            WHERE NOT EXISTS (SELECT 1 FROM prompt_templates WHERE name = ?)
+            "#,
+        )
+        .bind(Uuid::new_v4().to_string())
+        .bind(&template.name)
+        .bind(&template.description)
+        .bind(&template.body)
+        .bind(serde_json::to_string(&template.variables).unwrap_or_else(|_| "[]".to_string()))
+        .bind(FULL_TRAFFIC_WEIGHT)
+        .bind(Utc::now())
+        .bind(&template.name)
+        .execute(pool)
+        .await
+        .map_err(|e| StoryWeaverError::database(format!("Failed to seed prompt template {}: {}", template.name, e)))?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn get_by_id(pool: &Pool<Sqlite>, id: &str) -> Result<PromptTemplate> {
+        let row = sqlx::query("SELECT * FROM prompt_templates WHERE id = ?")
+            .bind(id)
+            .fetch_optional(pool)
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to get prompt template: {}", e)))?
+            .ok_or_else(|| StoryWeaverError::not_found("prompt template", id))?;
+
+        Ok(template_from_row(&row))
+    }
+
+    /// Every version, newest first; with `name`, only that template's
+    pub async fn get_versions(pool: &Pool<Sqlite>, name: Option<&str>) -> Result<Vec<PromptTemplate>> {
+        let rows = sqlx::query("SELECT * FROM prompt_templates WHERE ? IS NULL OR name = ? ORDER BY name, version DESC")
+            .bind(name)
+            .bind(name)
+            .fetch_all(pool)
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to get prompt templates: {}", e)))?;
+
+        Ok(rows.iter().map(template_from_row).collect())
+    }
+
+    /// Versions currently receiving traffic
+    pub async fn get_active(pool: &Pool<Sqlite>) -> Result<Vec<PromptTemplate>> {
+        let rows = sqlx::query("SELECT * FROM prompt_templates WHERE traffic_weight > 0 ORDER BY name, version")
+            .fetch_all(pool)
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to get active prompt templates: {}", e)))?;
+
+        Ok(rows.iter().map(template_from_row).collect())
+    }
+
+    /// Make `id` the only active version in its scope; also how a prompt
+    /// change is rolled back
+    pub async fn activate(pool: &Pool<Sqlite>, id: &str) -> Result<PromptTemplate> {
+        let template = Self::get_by_id(pool, id).await?;
+        Self::set_traffic_weights(pool, &[(id.to_string(), FULL_TRAFFIC_WEIGHT)]).await?;
+        Ok(PromptTemplate { traffic_weight: FULL_TRAFFIC_WEIGHT, ..template })
+    }
+
+    /// Split a scope's traffic between versions for an A/B test. Every id
+    /// must be a version of the same template in the same scope; versions of
+    /// that scope that aren't listed stop receiving traffic.
+    pub async fn set_traffic_weights(pool: &Pool<Sqlite>, weights: &[(String, i64)]) -> Result<()> {
+        if weights.iter().all(|(_, weight)| *weight <= 0) {
+            return Err(StoryWeaverError::validation("At least one template version needs a positive traffic weight"));
+        }
+
+        let mut templates = Vec::with_capacity(weights.len());
+        for (id, _) in weights {
+            templates.push(Self::get_by_id(pool, id).await?);
+        }
+        let first = &templates[0];
+        if templates
+            .iter()
+            .any(|t| t.name != first.name || t.project_id != first.project_id || t.prose_mode != first.prose_mode)
+        {
+            return Err(StoryWeaverError::validation(
+                "Traffic can only be split between versions of one template in the same scope",
+            ));
+        }
+
+        let mut tx = pool
+            .begin()
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to begin prompt template transaction: {}", e)))?;
+
+        sqlx::query("UPDATE prompt_templates SET traffic_weight = 0 WHERE name = ? AND project_id IS ? AND prose_mode IS ?")
+            .bind(&first.name)
+            .bind(&first.project_id)
+            .bind(&first.prose_mode)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to deactivate prompt templates: {}", e)))?;
+
+        for (id, weight) in weights {
+            sqlx::query("UPDATE prompt_templates SET traffic_weight = ? WHERE id = ?")
+                .bind((*weight).max(0))
+                .bind(id)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| StoryWeaverError::database(format!("Failed to set prompt template weight: {}", e)))?;
+        }
+
+        tx.commit()
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to commit prompt template weights: {}", e)))?;
+
+        Ok(())
+    }
+
+    /// Stop a project or prose mode override so the wider scope applies again.
+    /// Versions that apply everywhere can only be replaced, not deactivated.
+    pub async fn deactivate(pool: &Pool<Sqlite>, id: &str) -> Result<()> {
+        let template = Self::get_by_id(pool, id).await?;
+        if template.is_global() {
+            return Err(StoryWeaverError::validation(
+                "The default version of a template can't be deactivated; activate another version instead",
+            ));
+        }
+
+        sqlx::query("UPDATE prompt_templates SET traffic_weight = 0 WHERE id = ?")
+            .bind(id)
+            .execute(pool)
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to deactivate prompt template: {}", e)))?;
+
+        Ok(())
+    }
+}
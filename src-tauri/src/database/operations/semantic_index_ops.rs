@@ -0,0 +1,194 @@
+//! Semantic index database operations
+//! Provides functions to interact with the semantic_entries table and its FTS5 mirror
+
+use crate::error::{Result, StoryWeaverError};
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Row, Sqlite};
+
+/// One embedded unit of text: a document chunk or a whole story bible entry
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SemanticEntry {
+    pub id: Option<i64>,
+    pub project_id: String,
+    pub source_type: String, // "document", "character", "location", "world_element", "scene"
+    pub source_id: String,
+    pub chunk_index: i32,
+    pub start_position: i64,
+    pub end_position: i64,
+    pub title: String,
+    pub content: String,
+    pub content_hash: String,
+    pub model: String,
+    pub embedding: Vec<f32>,
+}
+
+/// Store an embedding as little-endian f32 bytes
+pub fn encode_embedding(embedding: &[f32]) -> Vec<u8> {
+    embedding.iter().flat_map(|v| v.to_le_bytes()).collect()
+}
+
+pub fn decode_embedding(bytes: &[u8]) -> Vec<f32> {
+    bytes
+        .chunks_exact(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect()
+}
+
+fn entry_from_row(row: &sqlx::sqlite::SqliteRow) -> SemanticEntry {
+    let embedding: Vec<u8> = row.get("embedding");
+    SemanticEntry {
+        id: Some(row.get("id")),
+        project_id: row.get("project_id"),
+        source_type: row.get("source_type"),
+        source_id: row.get("source_id"),
+        chunk_index: row.get("chunk_index"),
+        start_position: row.get("start_position"),
+        end_position: row.get("end_position"),
+        title: row.get("title"),
+        content: row.get("content"),
+        content_hash: row.get("content_hash"),
+        model: row.get("model"),
+        embedding: decode_embedding(&embedding),
+    }
+}
+
+const ENTRY_COLUMNS: &str = "id, project_id, source_type, source_id, chunk_index, start_position, end_position, \
+     title, content, content_hash, model, embedding";
+
+impl super::SemanticIndexOps {
+    /// Entries indexed for one document or story bible entry, in chunk order
+    pub async fn get_by_source(pool: &Pool<Sqlite>, source_type: &str, source_id: &str) -> Result<Vec<SemanticEntry>> {
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM semantic_entries WHERE source_type = ? AND source_id = ? ORDER BY chunk_index",
+            ENTRY_COLUMNS
+        ))
+        .bind(source_type)
+        .bind(source_id)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| StoryWeaverError::database(format!("Failed to get semantic entries: {}", e)))?;
+
+        Ok(rows.iter().map(entry_from_row).collect())
+    }
+
+    /// Entries of a project embedded with `model`; vectors from other models
+    /// are not comparable and are left out
+    pub async fn get_by_project_and_model(pool: &Pool<Sqlite>, project_id: &str, model: &str) -> Result<Vec<SemanticEntry>> {
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM semantic_entries WHERE project_id = ? AND model = ?",
+            ENTRY_COLUMNS
+        ))
+        .bind(project_id)
+        .bind(model)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| StoryWeaverError::database(format!("Failed to get semantic entries for project: {}", e)))?;
+
+        Ok(rows.iter().map(entry_from_row).collect())
+    }
+
+    /// Replace everything indexed for a source with `entries`
+    pub async fn replace_source(
+        pool: &Pool<Sqlite>,
+        source_type: &str,
+        source_id: &str,
+        entries: &[SemanticEntry],
+    ) -> Result<()> {
+        let mut tx = pool
+            .begin()
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to begin semantic index transaction: {}", e)))?;
+
+        sqlx::query("DELETE FROM semantic_entries WHERE source_type = ? AND source_id = ?")
+            .bind(source_type)
+            .bind(source_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to clear semantic entries: {}", e)))?;
+
+        for entry in entries {
+            sqlx::query(
+                r#"
+                INSERT INTO semantic_entries (project_id, source_type, source_id, chunk_index, start_position,
+                    end_position, title, content, content_hash, model, dimension, embedding)
+                VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+                "#,
+            )
+            .bind(&entry.project_id)
+            .bind(source_type)
+            .bind(source_id)
+            .bind(entry.chunk_index)
+            .bind(entry.start_position)
+            .bind(entry.end_position)
+            .bind(&entry.title)
+            .bind(&entry.content)
+            .bind(&entry.content_hash)
+            .bind(&entry.model)
+            .bind(entry.embedding.len() as i64)
+            .bind(encode_embedding(&entry.embedding))
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to insert semantic entry: {}", e)))?;
+        }
+
+        tx.commit()
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to commit semantic index transaction: {}", e)))?;
+
+        Ok(())
+    }
+
+    /// Remove a source from the index
+    pub async fn delete_source(pool: &Pool<Sqlite>, source_type: &str, source_id: &str) -> Result<()> {
+        sqlx::query("DELETE FROM semantic_entries WHERE source_type = ? AND source_id = ?")
+            .bind(source_type)
+            .bind(source_id)
+            .execute(pool)
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to delete semantic entries: {}", e)))?;
+
+        Ok(())
+    }
+
+    /// BM25 keyword matches within a project as `(entry id, score)`, best
+    /// first. SQLite's `bm25()` is lower-is-better, so it is negated here.
+    pub async fn keyword_search(
+        pool: &Pool<Sqlite>,
+        project_id: &str,
+        match_query: &str,
+        limit: i64,
+    ) -> Result<Vec<(i64, f64)>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT e.id AS id, bm25(semantic_entries_fts) AS score
+            FROM semantic_entries_fts
+            JOIN semantic_entries e ON e.id = semantic_entries_fts.rowid
+            WHERE semantic_entries_fts MATCH ? AND e.project_id = ?
+            ORDER BY score
+            LIMIT ?
+            "#,
+        )
+        .bind(match_query)
+        .bind(project_id)
+        .bind(limit)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| StoryWeaverError::database(format!("Failed to run keyword search: {}", e)))?;
+
+        Ok(rows
+            .iter()
+            .map(|row| (row.get::<i64, _>("id"), -row.get::<f64, _>("score")))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_embedding_round_trips_through_bytes() {
+        let embedding = vec![0.25, -1.5, 3.0e-7, 0.0];
+        assert_eq!(decode_embedding(&encode_embedding(&embedding)), embedding);
+    }
+}
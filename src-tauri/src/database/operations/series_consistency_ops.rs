@@ -26,6 +26,19 @@ pub struct ConsistencyConflict {
     pub affected_projects: Vec<String>,
     pub affected_elements: Vec<String>,
     pub suggestions: Vec<String>,
+    /// Where the conflict shows up in a project's documents, for conflicts
+    /// found in the manuscript itself
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub passages: Vec<ConflictPassage>,
+}
+
+/// A span of a document, in characters from the start of its content
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConflictPassage {
+    pub document_id: String,
+    pub start: usize,
+    pub end: usize,
+    pub excerpt: String,
 }
 
 /// Conflict severity levels
@@ -238,6 +251,7 @@ impl super::SeriesConsistencyOps {
                         "Consider standardizing the genre across all projects in the series".to_string(),
                         "If different genres are intentional, ensure they complement each other".to_string(),
                     ],
+                    passages: Vec::new(),
                 });
             }
             
@@ -257,6 +271,7 @@ impl super::SeriesConsistencyOps {
                         "Consider maintaining consistent writing style across the series".to_string(),
                         "If style evolution is intentional, ensure smooth transitions".to_string(),
                     ],
+                    passages: Vec::new(),
                 });
             }
         }
@@ -301,6 +316,7 @@ impl super::SeriesConsistencyOps {
                             "Consider if the differences represent character development over time".to_string(),
                             "Update traits to maintain consistency or mark as intentional variations".to_string(),
                         ],
+                        passages: Vec::new(),
                     });
                 }
             }
@@ -333,6 +349,7 @@ impl super::SeriesConsistencyOps {
                     "Ensure world-building consistency across the series".to_string(),
                     "Consider if differences represent world evolution over time".to_string(),
                 ],
+                passages: Vec::new(),
             });
         }
         
@@ -352,6 +369,7 @@ impl super::SeriesConsistencyOps {
                     "Standardize the element type across projects".to_string(),
                     "Ensure consistent categorization of world elements".to_string(),
                 ],
+                passages: Vec::new(),
             });
         }
         
@@ -359,7 +377,7 @@ impl super::SeriesConsistencyOps {
     }
     
     /// Calculate overall consistency score
-    fn calculate_consistency_score(conflicts: &[ConsistencyConflict], project_count: usize) -> f64 {
+    pub(crate) fn calculate_consistency_score(conflicts: &[ConsistencyConflict], project_count: usize) -> f64 {
         if conflicts.is_empty() {
             return 1.0;
         }
@@ -0,0 +1,207 @@
+//! Story bible alias database operations
+//! Provides functions to interact with the story_bible_aliases table
+
+use crate::database::operations::{CharacterOps, LocationOps, WorldElementOps};
+use crate::error::{Result, StoryWeaverError};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Row, Sqlite};
+use uuid::Uuid;
+
+/// Story bible entries that can go by other names
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StoryBibleEntityType {
+    Character,
+    Location,
+    WorldElement,
+}
+
+impl StoryBibleEntityType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StoryBibleEntityType::Character => "character",
+            StoryBibleEntityType::Location => "location",
+            StoryBibleEntityType::WorldElement => "world_element",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "character" => Some(StoryBibleEntityType::Character),
+            "location" => Some(StoryBibleEntityType::Location),
+            "world_element" => Some(StoryBibleEntityType::WorldElement),
+            _ => None,
+        }
+    }
+}
+
+/// How an alias is used in the prose
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AliasKind {
+    /// Another name, e.g. a maiden name or a code name
+    Alias,
+    /// A familiar name, e.g. "Mags" for Margaret
+    Nickname,
+    /// A rank or form of address, e.g. "Captain". It stands alone ("the
+    /// Captain") and in front of the name ("Captain Voss").
+    Title,
+}
+
+impl AliasKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AliasKind::Alias => "alias",
+            AliasKind::Nickname => "nickname",
+            AliasKind::Title => "title",
+        }
+    }
+
+    pub fn parse(value: &str) -> Self {
+        match value {
+            "nickname" => AliasKind::Nickname,
+            "title" => AliasKind::Title,
+            _ => AliasKind::Alias,
+        }
+    }
+}
+
+/// Another name for a character, location or world element
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoryBibleAlias {
+    pub id: String,
+    pub project_id: String,
+    pub entity_type: StoryBibleEntityType,
+    pub entity_id: String,
+    pub alias: String,
+    pub kind: AliasKind,
+    pub created_at: DateTime<Utc>,
+}
+
+impl StoryBibleAlias {
+    pub fn new(project_id: &str, entity_type: StoryBibleEntityType, entity_id: &str, alias: &str, kind: AliasKind) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            project_id: project_id.to_string(),
+            entity_type,
+            entity_id: entity_id.to_string(),
+            alias: alias.trim().to_string(),
+            kind,
+            created_at: Utc::now(),
+        }
+    }
+}
+
+fn alias_from_row(row: &sqlx::sqlite::SqliteRow) -> Option<StoryBibleAlias> {
+    let entity_type: String = row.get("entity_type");
+    let kind: String = row.get("alias_kind");
+    Some(StoryBibleAlias {
+        id: row.get("id"),
+        project_id: row.get("project_id"),
+        entity_type: StoryBibleEntityType::parse(&entity_type)?,
+        entity_id: row.get("entity_id"),
+        alias: row.get("alias"),
+        kind: AliasKind::parse(&kind),
+        created_at: row.get("created_at"),
+    })
+}
+
+impl super::StoryBibleAliasOps {
+    /// Name of the entity an alias points at, if it belongs to the alias's
+    /// project
+    async fn entity_name(pool: &Pool<Sqlite>, alias: &StoryBibleAlias) -> Result<Option<String>> {
+        let name = match alias.entity_type {
+            StoryBibleEntityType::Character => CharacterOps::get_by_project(pool, &alias.project_id)
+                .await?
+                .into_iter()
+                .find(|character| character.id == alias.entity_id)
+                .map(|character| character.name),
+            StoryBibleEntityType::Location => LocationOps::get_by_project(pool, &alias.project_id)
+                .await?
+                .into_iter()
+                .find(|location| location.id == alias.entity_id)
+                .map(|location| location.name),
+            StoryBibleEntityType::WorldElement => WorldElementOps::get_by_project(pool, &alias.project_id)
+                .await?
+                .into_iter()
+                .find(|element| element.id == alias.entity_id)
+                .map(|element| element.name),
+        };
+        Ok(name)
+    }
+
+    pub async fn create(pool: &Pool<Sqlite>, alias: StoryBibleAlias) -> Result<StoryBibleAlias> {
+        if alias.alias.is_empty() {
+            return Err(StoryWeaverError::validation("An alias can't be empty"));
+        }
+        let name = Self::entity_name(pool, &alias)
+            .await?
+            .ok_or_else(|| StoryWeaverError::not_found(alias.entity_type.as_str(), alias.entity_id.as_str()))?;
+        if name.to_lowercase() == alias.alias.to_lowercase() {
+            return Err(StoryWeaverError::validation(format!("\"{}\" is already the name", alias.alias)));
+        }
+        let existing = Self::get_by_entity(pool, alias.entity_type, &alias.entity_id).await?;
+        if existing.iter().any(|other| other.alias.to_lowercase() == alias.alias.to_lowercase()) {
+            return Err(StoryWeaverError::validation(format!("\"{}\" is already an alias of {}", alias.alias, name)));
+        }
+
+        sqlx::query(
+            r#"
+            INSERT INTO story_bible_aliases (id, project_id, entity_type, entity_id, alias, alias_kind, created_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&alias.id)
+        .bind(&alias.project_id)
+        .bind(alias.entity_type.as_str())
+        .bind(&alias.entity_id)
+        .bind(&alias.alias)
+        .bind(alias.kind.as_str())
+        .bind(alias.created_at)
+        .execute(pool)
+        .await
+        .map_err(|e| StoryWeaverError::database(format!("Failed to create alias: {}", e)))?;
+
+        Ok(alias)
+    }
+
+    /// Every alias in a project, oldest first
+    pub async fn get_by_project(pool: &Pool<Sqlite>, project_id: &str) -> Result<Vec<StoryBibleAlias>> {
+        let rows = sqlx::query("SELECT * FROM story_bible_aliases WHERE project_id = ? ORDER BY created_at, id")
+            .bind(project_id)
+            .fetch_all(pool)
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to get aliases: {}", e)))?;
+
+        Ok(rows.iter().filter_map(alias_from_row).collect())
+    }
+
+    /// Aliases of one character, location or world element, oldest first
+    pub async fn get_by_entity(
+        pool: &Pool<Sqlite>,
+        entity_type: StoryBibleEntityType,
+        entity_id: &str,
+    ) -> Result<Vec<StoryBibleAlias>> {
+        let rows = sqlx::query(
+            "SELECT * FROM story_bible_aliases WHERE entity_type = ? AND entity_id = ? ORDER BY created_at, id",
+        )
+        .bind(entity_type.as_str())
+        .bind(entity_id)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| StoryWeaverError::database(format!("Failed to get aliases: {}", e)))?;
+
+        Ok(rows.iter().filter_map(alias_from_row).collect())
+    }
+
+    pub async fn delete(pool: &Pool<Sqlite>, id: &str) -> Result<()> {
+        sqlx::query("DELETE FROM story_bible_aliases WHERE id = ?")
+            .bind(id)
+            .execute(pool)
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to delete alias: {}", e)))?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,638 @@
+//! Story bible extraction database operations
+//! Provides functions to interact with the story_bible_extractions,
+//! story_bible_extraction_documents, story_bible_proposals and
+//! story_bible_proposal_citations tables, and to turn an accepted proposal
+//! into a real story bible entry
+
+use crate::continuity::claims::place_category;
+use crate::database::models::{
+    Character, CharacterRole, EventImportance, Location, LocationType, TimelineEvent, VisibilityLevel, WorldElement,
+};
+use crate::database::operations::{CharacterOps, LocationOps, StoryCalendarOps, TimelineOps, WorldElementOps};
+use crate::error::{Result, StoryWeaverError};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Row, Sqlite};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExtractionStatus {
+    /// Waiting for its background task
+    Pending,
+    Running,
+    /// Stopped at a document; resuming continues from there
+    Failed,
+    Completed,
+}
+
+impl ExtractionStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ExtractionStatus::Pending => "pending",
+            ExtractionStatus::Running => "running",
+            ExtractionStatus::Failed => "failed",
+            ExtractionStatus::Completed => "completed",
+        }
+    }
+
+    pub fn parse(value: &str) -> Self {
+        match value {
+            "running" => ExtractionStatus::Running,
+            "failed" => ExtractionStatus::Failed,
+            "completed" => ExtractionStatus::Completed,
+            _ => ExtractionStatus::Pending,
+        }
+    }
+}
+
+/// A scan of a project's documents for story bible entries
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoryBibleExtraction {
+    pub id: String,
+    pub project_id: String,
+    pub task_id: Option<String>,
+    pub status: ExtractionStatus,
+    pub document_count: i32,
+    pub error_message: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl StoryBibleExtraction {
+    pub fn new(project_id: &str) -> Self {
+        let now = Utc::now();
+        Self {
+            id: Uuid::new_v4().to_string(),
+            project_id: project_id.to_string(),
+            task_id: None,
+            status: ExtractionStatus::Pending,
+            document_count: 0,
+            error_message: None,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+}
+
+/// Kind of story bible entry a proposal would become
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProposalType {
+    Character,
+    Location,
+    WorldElement,
+    TimelineEvent,
+}
+
+impl ProposalType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ProposalType::Character => "character",
+            ProposalType::Location => "location",
+            ProposalType::WorldElement => "world_element",
+            ProposalType::TimelineEvent => "timeline_event",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "character" => Some(ProposalType::Character),
+            "location" => Some(ProposalType::Location),
+            "world_element" => Some(ProposalType::WorldElement),
+            "timeline_event" => Some(ProposalType::TimelineEvent),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProposalStatus {
+    /// Waiting for the writer's review
+    Pending,
+    Accepted,
+    Rejected,
+}
+
+impl ProposalStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ProposalStatus::Pending => "pending",
+            ProposalStatus::Accepted => "accepted",
+            ProposalStatus::Rejected => "rejected",
+        }
+    }
+
+    pub fn parse(value: &str) -> Self {
+        match value {
+            "accepted" => ProposalStatus::Accepted,
+            "rejected" => ProposalStatus::Rejected,
+            _ => ProposalStatus::Pending,
+        }
+    }
+}
+
+/// A passage a proposal was found in, in characters from the start of the
+/// document's content
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProposalCitation {
+    pub document_id: String,
+    pub start: usize,
+    pub end: usize,
+    pub excerpt: String,
+}
+
+/// A story bible entry found in the manuscript, with the evidence for it
+/// merged across documents
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoryBibleProposal {
+    pub id: String,
+    pub extraction_id: String,
+    pub project_id: String,
+    pub entity_type: ProposalType,
+    pub name: String,
+    /// A character's role, a location's type or a world element's type
+    pub category: Option<String>,
+    pub description: Option<String>,
+    /// Story date of a timeline event
+    pub event_date: Option<String>,
+    /// Existing entry whose name is close, for the writer to compare
+    pub possible_duplicate_id: Option<String>,
+    pub possible_duplicate_name: Option<String>,
+    pub status: ProposalStatus,
+    /// Entry written when the proposal was accepted
+    pub created_entity_id: Option<String>,
+    pub citations: Vec<ProposalCitation>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl StoryBibleProposal {
+    pub fn new(extraction_id: &str, project_id: &str, entity_type: ProposalType, name: &str) -> Self {
+        let now = Utc::now();
+        Self {
+            id: Uuid::new_v4().to_string(),
+            extraction_id: extraction_id.to_string(),
+            project_id: project_id.to_string(),
+            entity_type,
+            name: name.to_string(),
+            category: None,
+            description: None,
+            event_date: None,
+            possible_duplicate_id: None,
+            possible_duplicate_name: None,
+            status: ProposalStatus::Pending,
+            created_entity_id: None,
+            citations: Vec::new(),
+            created_at: now,
+            updated_at: now,
+        }
+    }
+}
+
+fn extraction_from_row(row: &sqlx::sqlite::SqliteRow) -> StoryBibleExtraction {
+    let status: String = row.get("status");
+    StoryBibleExtraction {
+        id: row.get("id"),
+        project_id: row.get("project_id"),
+        task_id: row.get("task_id"),
+        status: ExtractionStatus::parse(&status),
+        document_count: row.get("document_count"),
+        error_message: row.get("error_message"),
+        created_at: row.get("created_at"),
+        updated_at: row.get("updated_at"),
+    }
+}
+
+fn proposal_from_row(row: &sqlx::sqlite::SqliteRow) -> Option<StoryBibleProposal> {
+    let entity_type: String = row.get("entity_type");
+    let status: String = row.get("status");
+    Some(StoryBibleProposal {
+        id: row.get("id"),
+        extraction_id: row.get("extraction_id"),
+        project_id: row.get("project_id"),
+        entity_type: ProposalType::parse(&entity_type)?,
+        name: row.get("name"),
+        category: row.get("category"),
+        description: row.get("description"),
+        event_date: row.get("event_date"),
+        possible_duplicate_id: row.get("possible_duplicate_id"),
+        possible_duplicate_name: row.get("possible_duplicate_name"),
+        status: ProposalStatus::parse(&status),
+        created_entity_id: row.get("created_entity_id"),
+        citations: Vec::new(),
+        created_at: row.get("created_at"),
+        updated_at: row.get("updated_at"),
+    })
+}
+
+fn citation_from_row(row: &sqlx::sqlite::SqliteRow) -> (String, ProposalCitation) {
+    let start: i64 = row.get("start_position");
+    let end: i64 = row.get("end_position");
+    let citation = ProposalCitation {
+        document_id: row.get("document_id"),
+        start: start.max(0) as usize,
+        end: end.max(0) as usize,
+        excerpt: row.get("excerpt"),
+    };
+    (row.get("proposal_id"), citation)
+}
+
+fn character_role(category: Option<&str>) -> CharacterRole {
+    match category.map(|c| c.trim().to_lowercase()).as_deref() {
+        Some("protagonist") => CharacterRole::Protagonist,
+        Some("antagonist") => CharacterRole::Antagonist,
+        Some("minor") => CharacterRole::Minor,
+        Some("background") => CharacterRole::Background,
+        _ => CharacterRole::Supporting,
+    }
+}
+
+/// The location type a category names, going by its last word ("small
+/// fishing village" is a city)
+fn location_type(category: Option<&str>) -> LocationType {
+    let category = category.map(|c| c.trim().to_lowercase()).unwrap_or_default();
+    let word = category.split_whitespace().last().unwrap_or("");
+    match place_category(word).unwrap_or(word) {
+        "city" => LocationType::City,
+        "building" => LocationType::Building,
+        "room" => LocationType::Room,
+        "landscape" => LocationType::Landscape,
+        "historical" => LocationType::Historical,
+        _ => LocationType::Fictional,
+    }
+}
+
+impl super::StoryBibleExtractionOps {
+    pub async fn create(pool: &Pool<Sqlite>, extraction: &StoryBibleExtraction) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO story_bible_extractions (id, project_id, task_id, status, document_count, error_message,
+                created_at, updated_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&extraction.id)
+        .bind(&extraction.project_id)
+        .bind(&extraction.task_id)
+        .bind(extraction.status.as_str())
+        .bind(extraction.document_count)
+        .bind(&extraction.error_message)
+        .bind(extraction.created_at)
+        .bind(extraction.updated_at)
+        .execute(pool)
+        .await
+        .map_err(|e| StoryWeaverError::database(format!("Failed to create story bible extraction: {}", e)))?;
+
+        Ok(())
+    }
+
+    pub async fn get(pool: &Pool<Sqlite>, id: &str) -> Result<StoryBibleExtraction> {
+        let row = sqlx::query("SELECT * FROM story_bible_extractions WHERE id = ?")
+            .bind(id)
+            .fetch_optional(pool)
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to get story bible extraction: {}", e)))?
+            .ok_or_else(|| StoryWeaverError::not_found("story bible extraction", id))?;
+
+        Ok(extraction_from_row(&row))
+    }
+
+    /// A project's extractions, newest first
+    pub async fn get_by_project(pool: &Pool<Sqlite>, project_id: &str) -> Result<Vec<StoryBibleExtraction>> {
+        let rows = sqlx::query("SELECT * FROM story_bible_extractions WHERE project_id = ? ORDER BY created_at DESC")
+            .bind(project_id)
+            .fetch_all(pool)
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to list story bible extractions: {}", e)))?;
+
+        Ok(rows.iter().map(extraction_from_row).collect())
+    }
+
+    /// IDs of the documents scanned so far, in the order they were scanned
+    pub async fn get_scanned_documents(pool: &Pool<Sqlite>, extraction_id: &str) -> Result<Vec<String>> {
+        let rows = sqlx::query(
+            "SELECT document_id FROM story_bible_extraction_documents WHERE extraction_id = ? ORDER BY position",
+        )
+        .bind(extraction_id)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| StoryWeaverError::database(format!("Failed to get scanned documents: {}", e)))?;
+
+        Ok(rows.iter().map(|row| row.get("document_id")).collect())
+    }
+
+    /// An extraction's proposals with their citations, oldest first
+    pub async fn get_proposals(pool: &Pool<Sqlite>, extraction_id: &str) -> Result<Vec<StoryBibleProposal>> {
+        let rows = sqlx::query("SELECT * FROM story_bible_proposals WHERE extraction_id = ? ORDER BY created_at, id")
+            .bind(extraction_id)
+            .fetch_all(pool)
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to get story bible proposals: {}", e)))?;
+        let mut proposals: Vec<StoryBibleProposal> = rows.iter().filter_map(proposal_from_row).collect();
+
+        let rows = sqlx::query(
+            r#"
+            SELECT c.* FROM story_bible_proposal_citations c
+            JOIN story_bible_proposals p ON p.id = c.proposal_id
+            WHERE p.extraction_id = ?
+            ORDER BY c.proposal_id, c.rowid
+            "#,
+        )
+        .bind(extraction_id)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| StoryWeaverError::database(format!("Failed to get proposal citations: {}", e)))?;
+        let mut citations: HashMap<String, Vec<ProposalCitation>> = HashMap::new();
+        for (proposal_id, citation) in rows.iter().map(citation_from_row) {
+            citations.entry(proposal_id).or_default().push(citation);
+        }
+        for proposal in &mut proposals {
+            proposal.citations = citations.remove(&proposal.id).unwrap_or_default();
+        }
+
+        Ok(proposals)
+    }
+
+    pub async fn get_proposal(pool: &Pool<Sqlite>, id: &str) -> Result<StoryBibleProposal> {
+        let mut proposal = sqlx::query("SELECT * FROM story_bible_proposals WHERE id = ?")
+            .bind(id)
+            .fetch_optional(pool)
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to get story bible proposal: {}", e)))?
+            .and_then(|row| proposal_from_row(&row))
+            .ok_or_else(|| StoryWeaverError::not_found("story bible proposal", id))?;
+
+        let rows = sqlx::query("SELECT * FROM story_bible_proposal_citations WHERE proposal_id = ? ORDER BY rowid")
+            .bind(id)
+            .fetch_all(pool)
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to get proposal citations: {}", e)))?;
+        proposal.citations = rows.iter().map(|row| citation_from_row(row).1).collect();
+
+        Ok(proposal)
+    }
+
+    /// Checkpoint a scanned document along with the proposals it added to or
+    /// changed. Citations already saved are kept as they are.
+    pub async fn save_document(
+        pool: &Pool<Sqlite>,
+        extraction_id: &str,
+        document_id: &str,
+        position: i32,
+        proposals: &[&StoryBibleProposal],
+    ) -> Result<()> {
+        let mut tx = pool
+            .begin()
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to begin saving scanned document: {}", e)))?;
+
+        for proposal in proposals {
+            sqlx::query(
+                r#"
+                INSERT INTO story_bible_proposals (id, extraction_id, project_id, entity_type, name, category,
+                    description, event_date, possible_duplicate_id, possible_duplicate_name, status,
+                    created_entity_id, created_at, updated_at)
+                VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+                ON CONFLICT(id) DO UPDATE SET
+                    name = excluded.name, category = excluded.category, description = excluded.description,
+                    event_date = excluded.event_date, possible_duplicate_id = excluded.possible_duplicate_id,
+                    possible_duplicate_name = excluded.possible_duplicate_name, updated_at = excluded.updated_at
+                "#,
+            )
+            .bind(&proposal.id)
+            .bind(&proposal.extraction_id)
+            .bind(&proposal.project_id)
+            .bind(proposal.entity_type.as_str())
+            .bind(&proposal.name)
+            .bind(&proposal.category)
+            .bind(&proposal.description)
+            .bind(&proposal.event_date)
+            .bind(&proposal.possible_duplicate_id)
+            .bind(&proposal.possible_duplicate_name)
+            .bind(proposal.status.as_str())
+            .bind(&proposal.created_entity_id)
+            .bind(proposal.created_at)
+            .bind(Utc::now())
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to save story bible proposal: {}", e)))?;
+
+            for citation in &proposal.citations {
+                sqlx::query(
+                    r#"
+                    INSERT OR IGNORE INTO story_bible_proposal_citations (proposal_id, document_id, start_position,
+                        end_position, excerpt)
+                    VALUES (?, ?, ?, ?, ?)
+                    "#,
+                )
+                .bind(&proposal.id)
+                .bind(&citation.document_id)
+                .bind(citation.start as i64)
+                .bind(citation.end as i64)
+                .bind(&citation.excerpt)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| StoryWeaverError::database(format!("Failed to save proposal citation: {}", e)))?;
+            }
+        }
+
+        sqlx::query(
+            "INSERT OR REPLACE INTO story_bible_extraction_documents (extraction_id, document_id, position, created_at) VALUES (?, ?, ?, ?)",
+        )
+        .bind(extraction_id)
+        .bind(document_id)
+        .bind(position)
+        .bind(Utc::now())
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| StoryWeaverError::database(format!("Failed to save scanned document: {}", e)))?;
+
+        sqlx::query("UPDATE story_bible_extractions SET updated_at = ? WHERE id = ?")
+            .bind(Utc::now())
+            .bind(extraction_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to update story bible extraction: {}", e)))?;
+
+        tx.commit()
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to save scanned document: {}", e)))?;
+        Ok(())
+    }
+
+    /// Record the task running the extraction and reset its status to pending
+    pub async fn assign_task(pool: &Pool<Sqlite>, id: &str, task_id: &str) -> Result<()> {
+        let result = sqlx::query(
+            "UPDATE story_bible_extractions SET task_id = ?, status = 'pending', error_message = NULL, updated_at = ? WHERE id = ?",
+        )
+        .bind(task_id)
+        .bind(Utc::now())
+        .bind(id)
+        .execute(pool)
+        .await
+        .map_err(|e| StoryWeaverError::database(format!("Failed to update story bible extraction: {}", e)))?;
+
+        if result.rows_affected() == 0 {
+            return Err(StoryWeaverError::not_found("story bible extraction", id));
+        }
+        Ok(())
+    }
+
+    /// Mark the extraction running over `document_count` documents
+    pub async fn mark_running(pool: &Pool<Sqlite>, id: &str, document_count: i32) -> Result<()> {
+        sqlx::query(
+            "UPDATE story_bible_extractions SET status = 'running', document_count = ?, error_message = NULL, updated_at = ? WHERE id = ?",
+        )
+        .bind(document_count)
+        .bind(Utc::now())
+        .bind(id)
+        .execute(pool)
+        .await
+        .map_err(|e| StoryWeaverError::database(format!("Failed to update story bible extraction: {}", e)))?;
+        Ok(())
+    }
+
+    pub async fn mark_failed(pool: &Pool<Sqlite>, id: &str, error_message: &str) -> Result<()> {
+        sqlx::query("UPDATE story_bible_extractions SET status = 'failed', error_message = ?, updated_at = ? WHERE id = ?")
+            .bind(error_message)
+            .bind(Utc::now())
+            .bind(id)
+            .execute(pool)
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to update story bible extraction: {}", e)))?;
+        Ok(())
+    }
+
+    pub async fn mark_completed(pool: &Pool<Sqlite>, id: &str) -> Result<()> {
+        sqlx::query(
+            "UPDATE story_bible_extractions SET status = 'completed', error_message = NULL, updated_at = ? WHERE id = ?",
+        )
+        .bind(Utc::now())
+        .bind(id)
+        .execute(pool)
+        .await
+        .map_err(|e| StoryWeaverError::database(format!("Failed to update story bible extraction: {}", e)))?;
+        Ok(())
+    }
+
+    /// Mark a proposal reviewed, but only while it is still pending; false
+    /// when someone else reviewed it first
+    async fn set_review<'e, E>(executor: E, id: &str, status: ProposalStatus, entity_id: Option<&str>) -> Result<bool>
+    where
+        E: sqlx::Executor<'e, Database = Sqlite>,
+    {
+        let reviewed = sqlx::query(
+            "UPDATE story_bible_proposals SET status = ?, created_entity_id = ?, updated_at = ? WHERE id = ? AND status = 'pending'",
+        )
+        .bind(status.as_str())
+        .bind(entity_id)
+        .bind(Utc::now())
+        .bind(id)
+        .execute(executor)
+        .await
+        .map_err(|e| StoryWeaverError::database(format!("Failed to review story bible proposal: {}", e)))?;
+        Ok(reviewed.rows_affected() > 0)
+    }
+
+    /// Write a pending proposal to the story bible as a new character,
+    /// location, world element or timeline event. `name` and `description`
+    /// replace the proposal's own when given.
+    pub async fn accept_proposal(
+        pool: &Pool<Sqlite>,
+        id: &str,
+        name: Option<String>,
+        description: Option<String>,
+    ) -> Result<StoryBibleProposal> {
+        let mut proposal = Self::get_proposal(pool, id).await?;
+        if proposal.status != ProposalStatus::Pending {
+            return Err(StoryWeaverError::validation("This proposal has already been reviewed"));
+        }
+        if let Some(name) = name.map(|name| name.trim().to_string()).filter(|name| !name.is_empty()) {
+            proposal.name = name;
+        }
+        if description.is_some() {
+            proposal.description = description;
+        }
+
+        let project_id = proposal.project_id.clone();
+        let category = proposal.category.as_deref();
+        let calendar = match proposal.entity_type {
+            ProposalType::TimelineEvent => Some(StoryCalendarOps::get(pool, &project_id).await?),
+            _ => None,
+        };
+
+        // The entity and the review land together; if another accept got
+        // there first the guard below finds nothing pending and the entity is
+        // rolled back
+        let mut tx = pool.begin().await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to begin transaction: {}", e)))?;
+        let entity_id = match proposal.entity_type {
+            ProposalType::Character => {
+                let mut character = Character::new(project_id, proposal.name.clone(), character_role(category));
+                character.description = proposal.description.clone();
+                CharacterOps::insert(&mut *tx, character).await?.id
+            }
+            ProposalType::Location => {
+                let mut location = Location::new(project_id, proposal.name.clone(), location_type(category));
+                location.description = proposal.description.clone();
+                LocationOps::insert(&mut *tx, location).await?.id
+            }
+            ProposalType::WorldElement => {
+                let element_type = category.map(|c| c.trim().to_lowercase()).filter(|c| !c.is_empty());
+                let mut element = WorldElement::new(
+                    Some(project_id),
+                    proposal.name.clone(),
+                    element_type.unwrap_or_else(|| "other".to_string()),
+                );
+                element.description = proposal.description.clone();
+                WorldElementOps::insert(&mut *tx, element).await?.id
+            }
+            ProposalType::TimelineEvent => {
+                let now = Utc::now();
+                let event = TimelineEvent {
+                    id: String::new(),
+                    project_id,
+                    title: proposal.name.clone(),
+                    description: proposal.description.clone(),
+                    event_date: proposal.event_date.clone(),
+                    real_date: None,
+                    importance: EventImportance::Minor,
+                    characters_involved: "[]".to_string(),
+                    locations_involved: "[]".to_string(),
+                    visibility: VisibilityLevel::Relevant,
+                    created_at: now,
+                    updated_at: now,
+                    event_instant: None,
+                    duration_minutes: None,
+                    deaths: "[]".to_string(),
+                };
+                TimelineOps::insert(&mut *tx, event, &calendar.unwrap_or_default()).await?.id
+            }
+        };
+
+        if !Self::set_review(&mut *tx, id, ProposalStatus::Accepted, Some(&entity_id)).await? {
+            return Err(StoryWeaverError::validation("This proposal has already been reviewed"));
+        }
+        tx.commit().await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to commit proposal: {}", e)))?;
+        proposal.status = ProposalStatus::Accepted;
+        proposal.created_entity_id = Some(entity_id);
+        Ok(proposal)
+    }
+
+    /// Turn a pending proposal down; nothing is written to the story bible
+    pub async fn reject_proposal(pool: &Pool<Sqlite>, id: &str) -> Result<StoryBibleProposal> {
+        let mut proposal = Self::get_proposal(pool, id).await?;
+        if proposal.status != ProposalStatus::Pending {
+            return Err(StoryWeaverError::validation("This proposal has already been reviewed"));
+        }
+        if !Self::set_review(pool, id, ProposalStatus::Rejected, None).await? {
+            return Err(StoryWeaverError::validation("This proposal has already been reviewed"));
+        }
+        proposal.status = ProposalStatus::Rejected;
+        Ok(proposal)
+    }
+}
@@ -0,0 +1,48 @@
+//! Story calendar database operations
+//! Provides functions to interact with the story_calendars table
+
+use crate::error::{Result, StoryWeaverError};
+use crate::timeline::StoryCalendar;
+use chrono::Utc;
+use sqlx::{Pool, Row, Sqlite};
+
+impl super::StoryCalendarOps {
+    /// The project's calendar; Gregorian until one is set
+    pub async fn get(pool: &Pool<Sqlite>, project_id: &str) -> Result<StoryCalendar> {
+        let row = sqlx::query("SELECT calendar FROM story_calendars WHERE project_id = ?")
+            .bind(project_id)
+            .fetch_optional(pool)
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to get story calendar: {}", e)))?;
+
+        match row {
+            Some(row) => {
+                let calendar: String = row.get("calendar");
+                serde_json::from_str(&calendar).map_err(|e| StoryWeaverError::serialization(e.to_string()))
+            }
+            None => Ok(StoryCalendar::default()),
+        }
+    }
+
+    /// Save the project's calendar and read its timeline dates again with it.
+    /// Returns how many dated events the new calendar can't read.
+    pub async fn set(pool: &Pool<Sqlite>, project_id: &str, calendar: &StoryCalendar) -> Result<usize> {
+        calendar.validate()?;
+        let json = serde_json::to_string(calendar).map_err(|e| StoryWeaverError::serialization(e.to_string()))?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO story_calendars (project_id, calendar, updated_at) VALUES (?, ?, ?)
+            ON CONFLICT(project_id) DO UPDATE SET calendar = excluded.calendar, updated_at = excluded.updated_at
+            "#,
+        )
+        .bind(project_id)
+        .bind(&json)
+        .bind(Utc::now())
+        .execute(pool)
+        .await
+        .map_err(|e| StoryWeaverError::database(format!("Failed to save story calendar: {}", e)))?;
+
+        super::TimelineOps::refresh_instants(pool, project_id, calendar).await
+    }
+}
@@ -0,0 +1,177 @@
+//! Story summary database operations
+//! Provides functions to interact with the story_summaries table
+
+use crate::error::{Result, StoryWeaverError};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Row, Sqlite};
+use uuid::Uuid;
+
+/// Rung of the summary hierarchy
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SummaryLevel {
+    /// A scene document within a chapter
+    Scene,
+    /// A chapter document, summarized from its scenes when it has any
+    Chapter,
+    /// The whole manuscript, summarized from its chapters
+    Book,
+}
+
+impl SummaryLevel {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SummaryLevel::Scene => "scene",
+            SummaryLevel::Chapter => "chapter",
+            SummaryLevel::Book => "book",
+        }
+    }
+
+    pub fn parse(value: &str) -> Self {
+        match value {
+            "scene" => SummaryLevel::Scene,
+            "chapter" => SummaryLevel::Chapter,
+            _ => SummaryLevel::Book,
+        }
+    }
+}
+
+/// A stored summary of a scene, a chapter or the whole book
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StorySummary {
+    pub id: String,
+    pub project_id: String,
+    pub level: SummaryLevel,
+    /// Document summarized; the project itself for the book
+    pub source_id: String,
+    /// Chapter a scene belongs to
+    pub parent_id: Option<String>,
+    /// Order within the chapter (scenes) or the book (chapters)
+    pub position: i32,
+    pub title: String,
+    pub summary: String,
+    /// Hash of the text the summary was made from
+    pub content_hash: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl StorySummary {
+    pub fn new(project_id: &str, level: SummaryLevel, source_id: &str) -> Self {
+        let now = Utc::now();
+        Self {
+            id: Uuid::new_v4().to_string(),
+            project_id: project_id.to_string(),
+            level,
+            source_id: source_id.to_string(),
+            parent_id: None,
+            position: 0,
+            title: String::new(),
+            summary: String::new(),
+            content_hash: String::new(),
+            created_at: now,
+            updated_at: now,
+        }
+    }
+}
+
+fn summary_from_row(row: &sqlx::sqlite::SqliteRow) -> StorySummary {
+    let level: String = row.get("level");
+    StorySummary {
+        id: row.get("id"),
+        project_id: row.get("project_id"),
+        level: SummaryLevel::parse(&level),
+        source_id: row.get("source_id"),
+        parent_id: row.get("parent_id"),
+        position: row.get("position"),
+        title: row.get("title"),
+        summary: row.get("summary"),
+        content_hash: row.get("content_hash"),
+        created_at: row.get("created_at"),
+        updated_at: row.get("updated_at"),
+    }
+}
+
+impl super::StorySummaryOps {
+    /// Insert a summary, or replace the one stored for the same source
+    pub async fn upsert(pool: &Pool<Sqlite>, summary: &StorySummary) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO story_summaries (id, project_id, level, source_id, parent_id, position, title, summary,
+                content_hash, created_at, updated_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            ON CONFLICT(level, source_id) DO UPDATE SET
+                parent_id = excluded.parent_id,
+                position = excluded.position,
+                title = excluded.title,
+                summary = excluded.summary,
+                content_hash = excluded.content_hash,
+                updated_at = excluded.updated_at
+            "#,
+        )
+        .bind(&summary.id)
+        .bind(&summary.project_id)
+        .bind(summary.level.as_str())
+        .bind(&summary.source_id)
+        .bind(&summary.parent_id)
+        .bind(summary.position)
+        .bind(&summary.title)
+        .bind(&summary.summary)
+        .bind(&summary.content_hash)
+        .bind(summary.created_at)
+        .bind(summary.updated_at)
+        .execute(pool)
+        .await
+        .map_err(|e| StoryWeaverError::database(format!("Failed to save story summary: {}", e)))?;
+
+        Ok(())
+    }
+
+    /// Every summary of a project: the book first, then chapters and scenes
+    /// in manuscript order
+    pub async fn get_by_project(pool: &Pool<Sqlite>, project_id: &str) -> Result<Vec<StorySummary>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT * FROM story_summaries WHERE project_id = ?
+            ORDER BY CASE level WHEN 'book' THEN 0 WHEN 'chapter' THEN 1 ELSE 2 END, parent_id, position
+            "#,
+        )
+        .bind(project_id)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| StoryWeaverError::database(format!("Failed to get story summaries: {}", e)))?;
+
+        Ok(rows.iter().map(summary_from_row).collect())
+    }
+
+    pub async fn get(pool: &Pool<Sqlite>, level: SummaryLevel, source_id: &str) -> Result<Option<StorySummary>> {
+        let row = sqlx::query("SELECT * FROM story_summaries WHERE level = ? AND source_id = ?")
+            .bind(level.as_str())
+            .bind(source_id)
+            .fetch_optional(pool)
+            .await
+            .map_err(|e| StoryWeaverError::database(format!("Failed to get story summary: {}", e)))?;
+
+        Ok(row.as_ref().map(summary_from_row))
+    }
+
+    /// Drop a project's summaries whose source is no longer part of the
+    /// manuscript; returns how many were removed
+    pub async fn delete_stale(pool: &Pool<Sqlite>, project_id: &str, keep_ids: &[String]) -> Result<u64> {
+        let mut removed = 0;
+        for summary in Self::get_by_project(pool, project_id).await? {
+            if keep_ids.contains(&summary.id) {
+                continue;
+            }
+            removed += sqlx::query("DELETE FROM story_summaries WHERE id = ?")
+                .bind(&summary.id)
+                .execute(pool)
+                .await
+                .map_err(|e| StoryWeaverError::database(format!("Failed to delete story summary: {}", e)))?
+                .rows_affected();
+        }
+
+        Ok(removed)
+    }
+}
@@ -10,7 +10,7 @@ use std::time::Instant;
 pub struct StreamingSession {
     pub id: Option<i32>,
     pub session_id: String,
-    pub project_id: i32,
+    pub project_id: String,
     pub model_used: String,
     pub prompt: String,
     pub status: String, // "active", "completed", "error", "cancelled"
@@ -97,7 +97,7 @@ impl super::StreamingSessionOps {
         Ok(row.map(|r| StreamingSession {
             id: Some(r.id as i32),
             session_id: r.session_id,
-            project_id: r.project_id as i32,
+            project_id: r.project_id,
             model_used: r.model_used,
             prompt: r.prompt,
             status: r.status,
@@ -131,7 +131,7 @@ impl super::StreamingSessionOps {
         Ok(row.map(|r| StreamingSession {
             id: r.id.map(|id| id as i32),
             session_id: r.session_id,
-            project_id: r.project_id as i32,
+            project_id: r.project_id,
             model_used: r.model_used,
             prompt: r.prompt,
             status: r.status,
@@ -148,7 +148,7 @@ impl super::StreamingSessionOps {
     }
 
     /// Get streaming sessions by project ID
-    pub async fn get_by_project(pool: &Pool<Sqlite>, project_id: i32) -> Result<Vec<StreamingSession>> {
+    pub async fn get_by_project(pool: &Pool<Sqlite>, project_id: &str) -> Result<Vec<StreamingSession>> {
         let rows = sqlx::query!(
             r#"
             SELECT id, session_id, project_id, model_used, prompt, status, generated_content,
@@ -165,7 +165,7 @@ impl super::StreamingSessionOps {
         Ok(rows.into_iter().map(|r| StreamingSession {
             id: r.id.map(|id| id as i32),
             session_id: r.session_id,
-            project_id: r.project_id as i32,
+            project_id: r.project_id,
             model_used: r.model_used,
             prompt: r.prompt,
             status: r.status,
@@ -199,7 +199,7 @@ impl super::StreamingSessionOps {
         Ok(rows.into_iter().map(|r| StreamingSession {
              id: r.id.map(|id| id as i32),
             session_id: r.session_id,
-            project_id: r.project_id as i32,
+            project_id: r.project_id,
             model_used: r.model_used,
             prompt: r.prompt,
             status: r.status,
@@ -232,7 +232,7 @@ impl super::StreamingSessionOps {
         Ok(rows.into_iter().map(|r| StreamingSession {
             id: Some(r.id as i32),
             session_id: r.session_id,
-            project_id: r.project_id as i32,
+            project_id: r.project_id,
             model_used: r.model_used,
             prompt: r.prompt,
             status: r.status,
@@ -306,6 +306,26 @@ impl super::StreamingSessionOps {
         Ok(())
     }
 
+    /// Mark a streaming session cancelled, keeping the text generated before the stop
+    pub async fn mark_cancelled(pool: &Pool<Sqlite>, session_id: &str, generated_content: &str, tokens_generated: i32) -> Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE streaming_sessions 
+            SET status = 'cancelled', generated_content = ?, tokens_generated = ?,
+                completed_at = CURRENT_TIMESTAMP, updated_at = CURRENT_TIMESTAMP
+            WHERE session_id = ?
+            "#,
+            generated_content,
+            tokens_generated,
+            session_id
+        )
+        .execute(&*pool)
+        .await
+        .map_err(|e| StoryWeaverError::database(format!("Failed to mark streaming session cancelled: {}", e)))?;
+
+        Ok(())
+    }
+
     /// Update streaming session with error
     pub async fn update_error(pool: &Pool<Sqlite>, session_id: &str, error_message: &str) -> Result<()> {
         sqlx::query!(
@@ -397,7 +417,7 @@ impl super::StreamingSessionOps {
         Ok(rows.into_iter().map(|r| StreamingSession {
             id: r.id.map(|id| id as i32),
             session_id: r.session_id,
-            project_id: r.project_id as i32,
+            project_id: r.project_id,
             model_used: r.model_used,
             prompt: r.prompt,
             status: r.status,
@@ -431,7 +451,7 @@ impl super::StreamingSessionOps {
         Ok(rows.into_iter().map(|r| StreamingSession {
              id: Some(r.id as i32),
             session_id: r.session_id,
-            project_id: r.project_id as i32,
+            project_id: r.project_id,
             model_used: r.model_used,
             prompt: r.prompt,
             status: r.status,
@@ -448,7 +468,7 @@ impl super::StreamingSessionOps {
     }
 
     /// Get total credits consumed by streaming sessions for a project
-    pub async fn get_total_credits_by_project(pool: &Pool<Sqlite>, project_id: i32) -> Result<f64> {
+    pub async fn get_total_credits_by_project(pool: &Pool<Sqlite>, project_id: &str) -> Result<f64> {
         let row = sqlx::query!(
             "SELECT COALESCE(SUM(credits_consumed), 0.0) as total_credits FROM streaming_sessions WHERE project_id = ?",
             project_id
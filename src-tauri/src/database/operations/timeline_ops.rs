@@ -1,25 +1,67 @@
 use crate::database::models::*;
 use crate::error::{Result, StoryWeaverError};
+use crate::timeline::StoryCalendar;
 use chrono::Utc;
 use sqlx::{Pool, Sqlite};
 use sqlx::Row;
 use uuid::Uuid;
 use serde_json;
 
+fn event_from_row(row: &sqlx::sqlite::SqliteRow) -> TimelineEvent {
+    TimelineEvent {
+        id: row.get("id"),
+        project_id: row.get("project_id"),
+        title: row.get("title"),
+        description: row.get("description"),
+        event_date: row.get("event_date"),
+        real_date: row.get("real_date"),
+        importance: row.get("importance"),
+        characters_involved: row.get("characters_involved"),
+        locations_involved: row.get("locations_involved"),
+        visibility: row.get("visibility"),
+        created_at: row.get("created_at"),
+        updated_at: row.get("updated_at"),
+        event_instant: row.get("event_instant"),
+        duration_minutes: row.get("duration_minutes"),
+        deaths: row.get("deaths"),
+    }
+}
+
+/// Where an event's date falls on the project calendar; `None` when it has
+/// no date or the calendar can't read it
+fn instant_of(calendar: &StoryCalendar, event_date: &Option<String>) -> Option<i64> {
+    event_date
+        .as_deref()
+        .filter(|date| !date.trim().is_empty())
+        .and_then(|date| calendar.parse(date).ok())
+        .map(|date| date.instant)
+}
+
 /// Timeline event operations
 impl super::TimelineOps {
     /// Create a new timeline event
-    pub async fn create(pool: &Pool<Sqlite>, mut event: TimelineEvent) -> Result<TimelineEvent> {
+    pub async fn create(pool: &Pool<Sqlite>, event: TimelineEvent) -> Result<TimelineEvent> {
+        let calendar = super::StoryCalendarOps::get(pool, &event.project_id).await?;
+        Self::insert(pool, event, &calendar).await
+    }
+
+    /// Create a new timeline event on any executor, such as an open
+    /// transaction, reading its date with the project's `calendar`
+    pub(crate) async fn insert<'e, E>(executor: E, mut event: TimelineEvent, calendar: &StoryCalendar) -> Result<TimelineEvent>
+    where
+        E: sqlx::Executor<'e, Database = Sqlite>,
+    {
         event.id = Uuid::new_v4().to_string();
         event.created_at = Utc::now();
         event.updated_at = Utc::now();
+        event.event_instant = instant_of(calendar, &event.event_date);
         
         sqlx::query(
             r#"
             INSERT INTO timeline_events (id, project_id, title, description, event_date, real_date,
                                        importance, characters_involved, locations_involved, visibility,
-                                       created_at, updated_at)
-            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+                                       created_at, updated_at, event_instant, duration_minutes, deaths)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
             "#,
         )
         .bind(&event.id)
@@ -34,42 +76,28 @@ impl super::TimelineOps {
         .bind(&event.visibility)
         .bind(event.created_at)
         .bind(event.updated_at)
-        .execute(&*pool)
+        .bind(event.event_instant)
+        .bind(event.duration_minutes)
+        .bind(&event.deaths)
+        .execute(executor)
         .await
         .map_err(|e| StoryWeaverError::database(format!("Failed to create timeline event: {}", e)))?;
         
         Ok(event)
     }
     
-    /// Get timeline events by project ID
+    /// Get timeline events by project ID, in story order; events whose date
+    /// the project calendar can't read come last
     pub async fn get_by_project(pool: &Pool<Sqlite>, project_id: &str) -> Result<Vec<TimelineEvent>> {
         let rows = sqlx::query(
-            "SELECT * FROM timeline_events WHERE project_id = ? ORDER BY event_date, created_at"
+            "SELECT * FROM timeline_events WHERE project_id = ? ORDER BY event_instant IS NULL, event_instant, event_date, created_at"
         )
         .bind(project_id)
         .fetch_all(&*pool)
         .await
         .map_err(|e| StoryWeaverError::database(format!("Failed to get timeline events: {}", e)))?;
         
-        let mut events = Vec::new();
-        for row in rows {
-            events.push(TimelineEvent {
-                id: row.get("id"),
-                project_id: row.get("project_id"),
-                title: row.get("title"),
-                description: row.get("description"),
-                event_date: row.get("event_date"),
-                real_date: row.get("real_date"),
-                importance: row.get("importance"),
-                characters_involved: row.get("characters_involved"),
-                locations_involved: row.get("locations_involved"),
-                visibility: row.get("visibility"),
-                created_at: row.get("created_at"),
-                updated_at: row.get("updated_at"),
-            });
-        }
-        
-        Ok(events)
+        Ok(rows.iter().map(event_from_row).collect())
     }
     
     /// Get timeline event by ID
@@ -82,30 +110,19 @@ impl super::TimelineOps {
         .await
         .map_err(|e| StoryWeaverError::database(format!("Failed to get timeline event: {}", e)))?;
         
-        Ok(TimelineEvent {
-            id: row.get("id"),
-            project_id: row.get("project_id"),
-            title: row.get("title"),
-            description: row.get("description"),
-            event_date: row.get("event_date"),
-            real_date: row.get("real_date"),
-            importance: row.get("importance"),
-            characters_involved: row.get("characters_involved"),
-            locations_involved: row.get("locations_involved"),
-            visibility: row.get("visibility"),
-            created_at: row.get("created_at"),
-            updated_at: row.get("updated_at"),
-        })
+        Ok(event_from_row(&row))
     }
     
     /// Update a timeline event
     pub async fn update(pool: &Pool<Sqlite>, event: &TimelineEvent) -> Result<()> {
+        let calendar = super::StoryCalendarOps::get(pool, &event.project_id).await?;
+        
         sqlx::query(
             r#"
             UPDATE timeline_events SET 
                 title = ?, description = ?, event_date = ?, real_date = ?,
                 importance = ?, characters_involved = ?, locations_involved = ?,
-                visibility = ?, updated_at = ?
+                visibility = ?, updated_at = ?, event_instant = ?, duration_minutes = ?, deaths = ?
             WHERE id = ?
             "#,
         )
@@ -118,6 +135,9 @@ impl super::TimelineOps {
         .bind(serde_json::to_string(&event.locations_involved).unwrap_or_default())
         .bind(&event.visibility)
         .bind(Utc::now())
+        .bind(instant_of(&calendar, &event.event_date))
+        .bind(event.duration_minutes)
+        .bind(&event.deaths)
         .bind(&event.id)
         .execute(&*pool)
         .await
@@ -165,7 +185,7 @@ impl super::TimelineOps {
         };
         
         let rows = sqlx::query(
-            "SELECT * FROM timeline_events WHERE project_id = ? AND importance = ? ORDER BY event_date, created_at"
+            "SELECT * FROM timeline_events WHERE project_id = ? AND importance = ? ORDER BY event_instant IS NULL, event_instant, event_date, created_at"
         )
         .bind(project_id)
         .bind(importance_str)
@@ -173,24 +193,26 @@ impl super::TimelineOps {
         .await
         .map_err(|e| StoryWeaverError::database(format!("Failed to get timeline events by importance: {}", e)))?;
         
-        let mut events = Vec::new();
-        for row in rows {
-            events.push(TimelineEvent {
-                id: row.get("id"),
-                project_id: row.get("project_id"),
-                title: row.get("title"),
-                description: row.get("description"),
-                event_date: row.get("event_date"),
-                real_date: row.get("real_date"),
-                importance: row.get("importance"),
-                characters_involved: row.get("characters_involved"),
-                locations_involved: row.get("locations_involved"),
-                visibility: row.get("visibility"),
-                created_at: row.get("created_at"),
-                updated_at: row.get("updated_at"),
-            });
+        Ok(rows.iter().map(event_from_row).collect())
+    }
+    
+    /// Read every event's date again with `calendar`, after the project's
+    /// calendar changes. Returns how many dated events it couldn't read.
+    pub async fn refresh_instants(pool: &Pool<Sqlite>, project_id: &str, calendar: &StoryCalendar) -> Result<usize> {
+        let mut unreadable = 0;
+        for event in Self::get_by_project(pool, project_id).await? {
+            let instant = instant_of(calendar, &event.event_date);
+            if instant.is_none() && event.event_date.as_deref().is_some_and(|date| !date.trim().is_empty()) {
+                unreadable += 1;
+            }
+            sqlx::query("UPDATE timeline_events SET event_instant = ? WHERE id = ?")
+                .bind(instant)
+                .bind(&event.id)
+                .execute(pool)
+                .await
+                .map_err(|e| StoryWeaverError::database(format!("Failed to update timeline event: {}", e)))?;
         }
         
-        Ok(events)
+        Ok(unreadable)
     }
 }
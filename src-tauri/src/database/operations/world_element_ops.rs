@@ -8,6 +8,14 @@ use uuid::Uuid;
 impl super::WorldElementOps {
     /// Create a new world element
     pub async fn create(pool: &Pool<Sqlite>, world_element: WorldElement) -> Result<WorldElement> {
+        Self::insert(pool, world_element).await
+    }
+
+    /// Create a new world element on any executor, such as an open transaction
+    pub(crate) async fn insert<'e, E>(executor: E, world_element: WorldElement) -> Result<WorldElement>
+    where
+        E: sqlx::Executor<'e, Database = Sqlite>,
+    {
         let mut world_element = world_element;
         world_element.id = Uuid::new_v4().to_string();
         world_element.created_at = Utc::now();
@@ -31,7 +39,7 @@ impl super::WorldElementOps {
         .bind(&world_element.original_project_id)
         .bind(world_element.created_at)
         .bind(world_element.updated_at)
-        .execute(&*pool)
+        .execute(executor)
         .await
         .map_err(|e| StoryWeaverError::database(format!("Failed to create world element: {}", e)))?;
         
@@ -0,0 +1,256 @@
+//! Multi-pattern name matching
+//! Every way an entity is written goes into one Aho-Corasick automaton, which
+//! runs over a lowercased copy of the text. Match offsets are mapped back to
+//! characters of the original text, so accented and other non-ASCII names
+//! land where they are.
+
+use super::{MentionKind, StoryBibleDetection};
+use crate::database::operations::{AliasKind, StoryBibleAlias, StoryBibleEntityType};
+use crate::error::{Result, StoryWeaverError};
+use aho_corasick::AhoCorasick;
+use std::collections::HashMap;
+
+/// Words of a name that don't refer to the person on their own
+const NAME_PARTICLES: &[&str] = &["of", "the", "de", "del", "der", "di", "du", "la", "le", "van", "von"];
+
+/// A character, location or world element and the ways it is written
+#[derive(Debug, Clone)]
+pub struct DetectableEntity {
+    pub entity_type: StoryBibleEntityType,
+    pub id: String,
+    pub name: String,
+    pub forms: Vec<(String, MentionKind)>,
+}
+
+impl DetectableEntity {
+    /// The entity's name and aliases. A character's name is also matched
+    /// word by word ("Mara" and "Voss" for "Mara Voss"), and each title goes
+    /// in front of the name and its words as well as standing alone.
+    pub fn new(entity_type: StoryBibleEntityType, id: &str, name: &str, aliases: &[&StoryBibleAlias]) -> Self {
+        let name = name.trim();
+        let mut forms = vec![(name.to_string(), MentionKind::Name)];
+
+        let parts: Vec<&str> = if entity_type == StoryBibleEntityType::Character {
+            name.split_whitespace()
+                .filter(|word| word.chars().count() > 1 && !NAME_PARTICLES.contains(&word.to_lowercase().as_str()))
+                .collect()
+        } else {
+            Vec::new()
+        };
+        if parts.len() > 1 {
+            forms.extend(parts.iter().map(|part| (part.to_string(), MentionKind::PartialName)));
+        }
+
+        for alias in aliases {
+            match alias.kind {
+                AliasKind::Alias => forms.push((alias.alias.clone(), MentionKind::Alias)),
+                AliasKind::Nickname => forms.push((alias.alias.clone(), MentionKind::Nickname)),
+                AliasKind::Title => {
+                    forms.push((alias.alias.clone(), MentionKind::Title));
+                    let named = std::iter::once(&name).chain(parts.iter().filter(|_| parts.len() > 1));
+                    forms.extend(named.map(|part| (format!("{} {}", alias.alias, part), MentionKind::Alias)));
+                }
+            }
+        }
+
+        Self { entity_type, id: id.to_string(), name: name.to_string(), forms }
+    }
+}
+
+/// An entity a pattern refers to, and how
+#[derive(Debug, Clone)]
+struct Owner {
+    entity: usize,
+    kind: MentionKind,
+    form: String,
+}
+
+/// Finds every entity's forms in a text in one pass
+pub struct EntityMatcher {
+    entities: Vec<DetectableEntity>,
+    automaton: Option<AhoCorasick>,
+    /// Who each pattern of the automaton refers to
+    owners: Vec<Vec<Owner>>,
+}
+
+/// `text` lowercased, and for each of its bytes the index of the character
+/// of `text` it came from. One extra entry holds the character count.
+fn fold(text: &str) -> (String, Vec<usize>) {
+    let mut folded = String::with_capacity(text.len());
+    let mut char_at = Vec::with_capacity(text.len() + 1);
+    let mut count = 0;
+    for (index, c) in text.chars().enumerate() {
+        let before = folded.len();
+        folded.extend(c.to_lowercase());
+        char_at.resize(char_at.len() + folded.len() - before, index);
+        count = index + 1;
+    }
+    char_at.push(count);
+    (folded, char_at)
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric()
+}
+
+fn is_apostrophe(c: char) -> bool {
+    c == '\'' || c == '\u{2019}'
+}
+
+/// How much a kind of mention says the entity is meant
+fn base_confidence(kind: MentionKind) -> f64 {
+    match kind {
+        MentionKind::Name => 0.95,
+        MentionKind::Alias => 0.9,
+        MentionKind::Nickname => 0.85,
+        MentionKind::PartialName => 0.75,
+        MentionKind::Title => 0.6,
+        MentionKind::Pronoun => 0.5,
+    }
+}
+
+/// Explicit names beat titles, which beat single words of a longer name.
+/// A form only refers to the entities in its best tier.
+fn tier(kind: MentionKind) -> u8 {
+    match kind {
+        MentionKind::Name | MentionKind::Alias | MentionKind::Nickname => 0,
+        MentionKind::Title => 1,
+        MentionKind::PartialName | MentionKind::Pronoun => 2,
+    }
+}
+
+pub(crate) fn round_confidence(confidence: f64) -> f64 {
+    (confidence.clamp(0.0, 1.0) * 100.0).round() / 100.0
+}
+
+/// Confidence that `written` (without any possessive ending) refers to the
+/// owner, given how many entities the form could be
+fn confidence(owner: &Owner, written: &str, candidates: usize) -> f64 {
+    let mut confidence = base_confidence(owner.kind);
+    if written != owner.form {
+        let capitalized = owner.form.chars().any(char::is_uppercase);
+        if capitalized && !written.chars().any(char::is_uppercase) {
+            // "hope" for Hope, "the captain" for the Captain
+            confidence -= 0.3;
+        } else {
+            confidence -= 0.1;
+        }
+    }
+    round_confidence(confidence / candidates.max(1) as f64)
+}
+
+impl EntityMatcher {
+    pub fn new(entities: Vec<DetectableEntity>) -> Result<Self> {
+        let mut patterns: Vec<String> = Vec::new();
+        let mut owners: Vec<Vec<Owner>> = Vec::new();
+        let mut index: HashMap<String, usize> = HashMap::new();
+
+        for (entity, detectable) in entities.iter().enumerate() {
+            for (form, kind) in &detectable.forms {
+                let (folded, _) = fold(form.trim());
+                if folded.is_empty() {
+                    continue;
+                }
+                let pattern = *index.entry(folded.clone()).or_insert_with(|| {
+                    patterns.push(folded);
+                    owners.push(Vec::new());
+                    patterns.len() - 1
+                });
+                let owner = Owner { entity, kind: *kind, form: form.trim().to_string() };
+                match owners[pattern].iter_mut().find(|existing| existing.entity == entity) {
+                    Some(existing) if tier(owner.kind) < tier(existing.kind) => *existing = owner,
+                    Some(_) => {}
+                    None => owners[pattern].push(owner),
+                }
+            }
+        }
+        for pattern_owners in &mut owners {
+            if let Some(best) = pattern_owners.iter().map(|owner| tier(owner.kind)).min() {
+                pattern_owners.retain(|owner| tier(owner.kind) == best);
+            }
+        }
+
+        let automaton = if patterns.is_empty() {
+            None
+        } else {
+            let automaton = AhoCorasick::new(&patterns)
+                .map_err(|e| StoryWeaverError::internal(format!("Failed to build name matcher: {}", e)))?;
+            Some(automaton)
+        };
+        Ok(Self { entities, automaton, owners })
+    }
+
+    pub fn entities(&self) -> &[DetectableEntity] {
+        &self.entities
+    }
+
+    /// Mentions of the entities in `text`, with positions in characters. Where
+    /// forms overlap the leftmost, then longest, wins ("Captain Mara Voss"
+    /// over "Mara"). A form several entities share is reported for each of
+    /// them, with its confidence split between them.
+    pub fn find(&self, text: &str) -> Vec<StoryBibleDetection> {
+        let Some(automaton) = &self.automaton else {
+            return Vec::new();
+        };
+        let chars: Vec<char> = text.chars().collect();
+        let (folded, char_at) = fold(text);
+
+        let mut found: Vec<(usize, usize, usize)> = automaton
+            .find_overlapping_iter(&folded)
+            .filter_map(|m| {
+                let start = char_at[m.start()];
+                let end = char_at[m.end() - 1] + 1;
+                // Both ends must fall between characters of the original text
+                let splits_start = m.start() > 0 && char_at[m.start() - 1] == start;
+                let splits_end = char_at[m.end()] == end - 1;
+                let joins_before = start > 0 && is_word_char(chars[start - 1]);
+                let joins_after = end < chars.len() && is_word_char(chars[end]);
+                if splits_start || splits_end || joins_before || joins_after {
+                    return None;
+                }
+                Some((start, end, m.pattern().as_usize()))
+            })
+            .collect();
+        found.sort_by_key(|&(start, end, _)| (start, std::cmp::Reverse(end)));
+
+        let mut detections = Vec::new();
+        let mut covered = 0;
+        for (start, end, pattern) in found {
+            if start < covered {
+                continue;
+            }
+            let written: String = chars[start..end].iter().collect();
+
+            // "Mara's", "Jess'"
+            let mut matched_end = end;
+            if end < chars.len() && is_apostrophe(chars[end]) {
+                let after_s = end + 2;
+                if chars.get(end + 1).is_some_and(|c| *c == 's' || *c == 'S')
+                    && chars.get(after_s).is_none_or(|c| !is_word_char(*c))
+                {
+                    matched_end = after_s;
+                } else if written.ends_with(['s', 'S']) && chars.get(end + 1).is_none_or(|c| !is_word_char(*c)) {
+                    matched_end = end + 1;
+                }
+            }
+            covered = matched_end;
+
+            let owners = &self.owners[pattern];
+            for owner in owners {
+                let entity = &self.entities[owner.entity];
+                detections.push(StoryBibleDetection {
+                    detection_type: entity.entity_type,
+                    entity_id: entity.id.clone(),
+                    entity_name: entity.name.clone(),
+                    start_position: start,
+                    end_position: matched_end,
+                    confidence: confidence(owner, &written, owners.len()),
+                    matched_text: chars[start..matched_end].iter().collect(),
+                    match_kind: owner.kind,
+                    possessive: matched_end > end,
+                });
+            }
+        }
+        detections
+    }
+}
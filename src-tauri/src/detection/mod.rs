@@ -0,0 +1,102 @@
+//! Story bible detection
+//! Finds where a project's characters, locations and world elements are
+//! mentioned in a piece of text: by name, alias, nickname, title or, for
+//! characters, by a single word of their name or a pronoun. Positions are
+//! in characters from the start of the text.
+
+pub mod matcher;
+pub mod pronouns;
+
+pub use matcher::{DetectableEntity, EntityMatcher};
+pub use pronouns::{character_pronouns, link_pronouns, PronounSet};
+
+use crate::database::operations::{
+    CharacterOps, CharacterTraitOps, LocationOps, StoryBibleAlias, StoryBibleAliasOps, StoryBibleEntityType,
+    WorldElementOps,
+};
+use crate::database::DbPool;
+use crate::error::Result;
+use serde::{Deserialize, Serialize};
+
+/// How a mention refers to its entity
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MentionKind {
+    Name,
+    Alias,
+    Nickname,
+    /// A title on its own, e.g. "the Captain"
+    Title,
+    /// One word of a longer name, e.g. "Voss" for Mara Voss
+    PartialName,
+    /// A pronoun linked to the character named before it
+    Pronoun,
+}
+
+/// A mention of a story bible entity
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoryBibleDetection {
+    pub detection_type: StoryBibleEntityType,
+    pub entity_id: String,
+    pub entity_name: String,
+    /// In characters from the start of the text
+    pub start_position: usize,
+    pub end_position: usize,
+    pub confidence: f64,
+    /// The mention as written, with any possessive ending
+    pub matched_text: String,
+    pub match_kind: MentionKind,
+    /// "Mara's", "his"
+    pub possessive: bool,
+}
+
+/// Find mentions of the project's entities of `entity_types` in `text`,
+/// ordered by position. With `with_pronouns`, pronouns are linked to the
+/// characters found too.
+pub async fn detect_in_text(
+    pool: &DbPool,
+    project_id: &str,
+    text: &str,
+    entity_types: &[StoryBibleEntityType],
+    with_pronouns: bool,
+) -> Result<Vec<StoryBibleDetection>> {
+    let aliases = StoryBibleAliasOps::get_by_project(pool, project_id).await?;
+    let aliases_of = |entity_type: StoryBibleEntityType, id: &str| -> Vec<&StoryBibleAlias> {
+        aliases.iter().filter(|a| a.entity_type == entity_type && a.entity_id == id).collect()
+    };
+
+    let mut entities = Vec::new();
+    if entity_types.contains(&StoryBibleEntityType::Character) {
+        let entity_type = StoryBibleEntityType::Character;
+        for character in CharacterOps::get_by_project(pool, project_id).await? {
+            let aliases = aliases_of(entity_type, &character.id);
+            entities.push(DetectableEntity::new(entity_type, &character.id, &character.name, &aliases));
+        }
+    }
+    if entity_types.contains(&StoryBibleEntityType::Location) {
+        let entity_type = StoryBibleEntityType::Location;
+        for location in LocationOps::get_by_project(pool, project_id).await? {
+            let aliases = aliases_of(entity_type, &location.id);
+            entities.push(DetectableEntity::new(entity_type, &location.id, &location.name, &aliases));
+        }
+    }
+    if entity_types.contains(&StoryBibleEntityType::WorldElement) {
+        let entity_type = StoryBibleEntityType::WorldElement;
+        for element in WorldElementOps::get_by_project(pool, project_id).await? {
+            let aliases = aliases_of(entity_type, &element.id);
+            entities.push(DetectableEntity::new(entity_type, &element.id, &element.name, &aliases));
+        }
+    }
+
+    let mut detections = EntityMatcher::new(entities)?.find(text);
+    if with_pronouns && entity_types.contains(&StoryBibleEntityType::Character) {
+        let traits = CharacterTraitOps::get_by_project(pool, project_id).await?;
+        let linked = link_pronouns(text, &detections, &character_pronouns(&traits));
+        detections.extend(linked);
+    }
+
+    detections.sort_by(|a, b| {
+        a.start_position.cmp(&b.start_position).then(b.confidence.total_cmp(&a.confidence))
+    });
+    Ok(detections)
+}
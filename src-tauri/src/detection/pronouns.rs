@@ -0,0 +1,136 @@
+//! Pronoun linking
+//! Links a pronoun to the character most recently named before it whose
+//! pronouns fit. A character's pronouns come from a "Pronouns" or "Gender"
+//! trait; without one they could be anyone's, and links to them are weaker.
+
+use super::{MentionKind, StoryBibleDetection};
+use super::matcher::round_confidence;
+use crate::database::models::CharacterTrait;
+use crate::database::operations::StoryBibleEntityType;
+use std::collections::HashMap;
+
+/// How far back, in characters, a pronoun looks for the character it
+/// stands for
+pub const PRONOUN_WINDOW: usize = 400;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PronounSet {
+    He,
+    She,
+    They,
+}
+
+impl PronounSet {
+    /// Read a pronouns or gender trait value: "she/her", "he", "male",
+    /// "non-binary"
+    pub fn from_trait_value(value: &str) -> Option<Self> {
+        let value = value.trim().to_lowercase();
+        let first = value.split(|c: char| !c.is_alphanumeric() && c != '-').find(|word| !word.is_empty())?;
+        match first {
+            "she" | "her" | "female" | "woman" | "girl" => Some(PronounSet::She),
+            "he" | "him" | "male" | "man" | "boy" => Some(PronounSet::He),
+            "they" | "them" | "non-binary" | "nonbinary" | "enby" => Some(PronounSet::They),
+            _ => None,
+        }
+    }
+
+    /// The set `word` belongs to, and whether it is possessive
+    fn of_word(word: &str) -> Option<(Self, bool)> {
+        match word.to_lowercase().as_str() {
+            "he" | "him" | "himself" => Some((PronounSet::He, false)),
+            "his" => Some((PronounSet::He, true)),
+            "she" | "her" | "herself" => Some((PronounSet::She, false)),
+            "hers" => Some((PronounSet::She, true)),
+            "they" | "them" | "themself" | "themselves" => Some((PronounSet::They, false)),
+            "their" | "theirs" => Some((PronounSet::They, true)),
+            _ => None,
+        }
+    }
+}
+
+/// Pronouns of each character that has a pronouns or gender trait, by
+/// character ID. A pronouns trait wins over a gender trait.
+pub fn character_pronouns(traits: &[CharacterTrait]) -> HashMap<String, PronounSet> {
+    let mut pronouns = HashMap::new();
+    for name in ["gender", "pronouns"] {
+        for character_trait in traits.iter().filter(|t| t.trait_name.trim().eq_ignore_ascii_case(name)) {
+            if let Some(set) = character_trait.trait_value.as_deref().and_then(PronounSet::from_trait_value) {
+                pronouns.insert(character_trait.character_id.clone(), set);
+            }
+        }
+    }
+    pronouns
+}
+
+/// Pronouns in `text` linked to the characters among `mentions` they most
+/// likely stand for. Words already inside a mention are skipped.
+pub fn link_pronouns(
+    text: &str,
+    mentions: &[StoryBibleDetection],
+    pronouns: &HashMap<String, PronounSet>,
+) -> Vec<StoryBibleDetection> {
+    let mut characters: Vec<&StoryBibleDetection> = mentions
+        .iter()
+        .filter(|m| m.detection_type == StoryBibleEntityType::Character && m.match_kind != MentionKind::Pronoun)
+        .collect();
+    characters.sort_by_key(|m| m.start_position);
+
+    let chars: Vec<char> = text.chars().collect();
+    let mut links = Vec::new();
+    let mut position = 0;
+    while position < chars.len() {
+        if !chars[position].is_alphabetic() {
+            position += 1;
+            continue;
+        }
+        let start = position;
+        while position < chars.len() && chars[position].is_alphabetic() {
+            position += 1;
+        }
+        let word: String = chars[start..position].iter().collect();
+        let Some((set, possessive)) = PronounSet::of_word(&word) else {
+            continue;
+        };
+        if mentions.iter().any(|m| m.start_position <= start && start < m.end_position) {
+            continue;
+        }
+
+        // Characters named in the window, most recent first
+        let mut recent: Vec<&StoryBibleDetection> = Vec::new();
+        for mention in characters.iter().rev() {
+            if mention.end_position > start || start - mention.end_position > PRONOUN_WINDOW {
+                continue;
+            }
+            if !recent.iter().any(|m| m.entity_id == mention.entity_id) {
+                recent.push(mention);
+            }
+        }
+
+        let fitting: Vec<&&StoryBibleDetection> =
+            recent.iter().filter(|m| pronouns.get(&m.entity_id) == Some(&set)).collect();
+        let unknown: Vec<&&StoryBibleDetection> =
+            recent.iter().filter(|m| !pronouns.contains_key(&m.entity_id)).collect();
+        let (referent, mut confidence) = match (fitting.first(), unknown.first()) {
+            (Some(referent), _) => (referent, if fitting.len() == 1 { 0.7 } else { 0.4 }),
+            (None, Some(referent)) => (referent, if unknown.len() == 1 { 0.4 } else { 0.25 }),
+            (None, None) => continue,
+        };
+        if set == PronounSet::They {
+            // Just as likely to mean a group
+            confidence *= 0.7;
+        }
+
+        links.push(StoryBibleDetection {
+            detection_type: StoryBibleEntityType::Character,
+            entity_id: referent.entity_id.clone(),
+            entity_name: referent.entity_name.clone(),
+            start_position: start,
+            end_position: position,
+            confidence: round_confidence(confidence),
+            matched_text: word,
+            match_kind: MentionKind::Pronoun,
+            possessive,
+        });
+    }
+    links
+}
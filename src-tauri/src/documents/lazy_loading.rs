@@ -22,6 +22,56 @@ pub struct DocumentChunk {
     pub last_accessed: chrono::DateTime<chrono::Utc>,
 }
 
+/// Split `content` into chunks of at most `chunk_size` bytes, breaking at the
+/// last space where possible and never inside a UTF-8 character
+pub fn split_into_chunks(document_id: &str, content: &str, chunk_size: usize) -> Vec<DocumentChunk> {
+    let mut chunks = Vec::new();
+    let content_len = content.len();
+    let chunk_size = chunk_size.max(1);
+
+    let mut start = 0;
+    let mut chunk_index = 0;
+
+    while start < content_len {
+        let mut end = std::cmp::min(start + chunk_size, content_len);
+        while !content.is_char_boundary(end) {
+            end -= 1;
+        }
+        if end == start {
+            // A single character wider than the chunk size
+            end = start + content[start..].chars().next().map_or(1, char::len_utf8);
+        }
+
+        // Try to break at word boundaries
+        if end < content_len {
+            if let Some(last_space) = content[start..end].rfind(' ') {
+                if last_space > 0 {
+                    end = start + last_space;
+                }
+            }
+        }
+
+        let chunk_content = &content[start..end];
+        let now = chrono::Utc::now();
+        chunks.push(DocumentChunk {
+            chunk_id: format!("{}_{}", document_id, chunk_index),
+            document_id: document_id.to_string(),
+            start_position: start,
+            end_position: end,
+            content: chunk_content.to_string(),
+            word_count: chunk_content.split_whitespace().count(),
+            line_count: chunk_content.lines().count(),
+            loaded_at: now,
+            access_count: 0,
+            last_accessed: now,
+        });
+        start = end;
+        chunk_index += 1;
+    }
+
+    chunks
+}
+
 /// Lazy loading configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LazyLoadingConfig {
@@ -154,45 +204,7 @@ impl LazyDocumentLoader {
 
     /// Create chunks from document content
     async fn create_chunks(&self, document_id: &str, content: &str) -> Result<Vec<DocumentChunk>> {
-        let mut chunks = Vec::new();
-        let chunk_size = self.config.chunk_size;
-        let content_len = content.len();
-        
-        let mut start = 0;
-        let mut chunk_index = 0;
-        
-        while start < content_len {
-            let mut end = std::cmp::min(start + chunk_size, content_len);
-            
-            // Try to break at word boundaries
-            if end < content_len {
-                if let Some(last_space) = content[start..end].rfind(' ') {
-                    end = start + last_space;
-                }
-            }
-            
-            let chunk_content = &content[start..end];
-            let chunk_id = format!("{}_{}", document_id, chunk_index);
-            
-            let chunk = DocumentChunk {
-                chunk_id,
-                document_id: document_id.to_string(),
-                start_position: start,
-                end_position: end,
-                content: chunk_content.to_string(),
-                word_count: chunk_content.split_whitespace().count(),
-                line_count: chunk_content.lines().count(),
-                loaded_at: chrono::Utc::now(),
-                access_count: 0,
-                last_accessed: chrono::Utc::now(),
-            };
-            
-            chunks.push(chunk);
-            start = end;
-            chunk_index += 1;
-        }
-        
-        Ok(chunks)
+        Ok(split_into_chunks(document_id, content, self.config.chunk_size))
     }
 
     /// Load a specific chunk by position
@@ -468,4 +480,15 @@ mod tests {
         assert!(stats.cache_hits > 0);
         assert!(stats.hit_rate > 0.0);
     }
+
+    #[test]
+    fn test_split_into_chunks_respects_char_boundaries() {
+        let content = "naïve café ".repeat(50);
+        let chunks = split_into_chunks("doc", &content, 16);
+
+        assert!(chunks.len() > 1);
+        assert_eq!(chunks.iter().map(|c| c.content.as_str()).collect::<String>(), content);
+        assert!(chunks.iter().all(|c| c.content.len() <= 16));
+        assert_eq!(chunks[1].chunk_id, "doc_1");
+    }
 }
@@ -4,7 +4,7 @@
 pub mod lazy_loading;
 
 pub use lazy_loading::{
-    LazyDocumentLoader, DocumentChunk, DocumentMetadata, LazyLoadingConfig,
+    LazyDocumentLoader, DocumentChunk, DocumentMetadata, LazyLoadingConfig, split_into_chunks,
     CacheStats as DocumentCacheStats, init_lazy_loader, get_lazy_loader,
     start_lazy_loading_cleanup_task
 };
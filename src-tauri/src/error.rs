@@ -103,6 +103,12 @@ pub enum StoryWeaverError {
     #[error("AI content filtered: {reason}")]
     AIContentFiltered { reason: String },
     
+    #[error("AI request cancelled: {operation}")]
+    AIRequestCancelled { operation: String },
+    
+    #[error("AI budget exceeded: {message}")]
+    BudgetExceeded { budget_id: String, message: String, requires_confirmation: bool },
+    
     #[error("Saliency engine error: {message}")]
     SaliencyEngineError { message: String },
     
@@ -431,6 +437,22 @@ impl StoryWeaverError {
         }
     }
     
+    /// Create an AI request cancelled error
+    pub fn ai_cancelled<S: Into<String>>(operation: S) -> Self {
+        Self::AIRequestCancelled {
+            operation: operation.into(),
+        }
+    }
+    
+    /// Create an AI budget exceeded error
+    pub fn budget_exceeded<S: Into<String>>(budget_id: S, message: S, requires_confirmation: bool) -> Self {
+        Self::BudgetExceeded {
+            budget_id: budget_id.into(),
+            message: message.into(),
+            requires_confirmation,
+        }
+    }
+    
     /// Create a vector database error
     pub fn vector_database<S: Into<String>>(message: S) -> Self {
         Self::VectorDatabase {
@@ -628,6 +650,11 @@ impl StoryWeaverError {
             Self::TokenLimitExceeded { .. } => "The request exceeds the token limit. Please reduce the content size.".to_string(),
             Self::Network { .. } => "Network connection failed. Please check your internet connection.".to_string(),
             Self::DatabaseConnection { .. } => "Database connection failed. Please try again.".to_string(),
+            Self::AIRequestCancelled { .. } => "The AI request was cancelled.".to_string(),
+            Self::BudgetExceeded { requires_confirmation: true, .. } => {
+                "This request would go over an AI budget. Approve the overrun to continue.".to_string()
+            }
+            Self::BudgetExceeded { .. } => "This request would go over an AI budget.".to_string(),
             _ => "An unexpected error occurred. Please try again.".to_string(),
         }
     }
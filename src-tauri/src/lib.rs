@@ -17,6 +17,9 @@ mod utils;
 pub mod security;
 pub mod documents;
 pub mod logging;
+pub mod timeline;
+pub mod continuity;
+pub mod detection;
 
 #[cfg(test)]
 mod tests;
@@ -76,6 +79,44 @@ pub fn run() {
             commands::documents::save_document,
             commands::documents::delete_document,
             commands::documents::search_documents,
+            commands::semantic_search::semantic_search,
+            commands::semantic_search::reindex_semantic_index,
+            commands::chat_threads::create_chat_thread,
+            commands::chat_threads::list_chat_threads,
+            commands::chat_threads::get_chat_messages,
+            commands::chat_threads::send_chat_message,
+            commands::chat_threads::branch_chat_thread,
+            commands::chat_threads::set_chat_thread_pins,
+            commands::chat_threads::rename_chat_thread,
+            commands::chat_threads::delete_chat_thread,
+            commands::chat_threads::export_chat_thread,
+            commands::prompt_templates::list_prompt_templates,
+            commands::prompt_templates::create_prompt_template_version,
+            commands::prompt_templates::activate_prompt_template_version,
+            commands::prompt_templates::set_prompt_template_weights,
+            commands::prompt_templates::deactivate_prompt_template_version,
+            commands::prompt_templates::preview_prompt_template,
+            commands::ai_budgets::list_ai_budgets,
+            commands::ai_budgets::set_ai_budget,
+            commands::ai_budgets::delete_ai_budget,
+            commands::ai_budgets::approve_budget_overrun,
+            commands::ai_budgets::preflight_ai_cost,
+            commands::ai_budgets::get_spend_forecast,
+            // Model pricing commands
+            commands::model_pricing::list_model_prices,
+            commands::model_pricing::set_model_price,
+            commands::model_pricing::delete_model_price,
+            commands::model_pricing::export_model_pricing,
+            commands::model_pricing::import_model_pricing,
+            commands::model_pricing::recompute_history_costs_for_project,
+            // Chapter draft commands
+            commands::chapter_drafts::start_chapter_draft,
+            commands::chapter_drafts::resume_chapter_draft,
+            commands::chapter_drafts::get_chapter_draft,
+            commands::chapter_drafts::list_chapter_drafts,
+            // Story summary commands
+            commands::story_summaries::get_story_summaries,
+            commands::story_summaries::refresh_story_summaries,
             commands::documents::get_document_tree,
             commands::documents::get_document_stats,
             
@@ -90,6 +131,28 @@ pub fn run() {
             commands::characters::share_character_to_series,
             commands::characters::unshare_character_from_series,
             commands::characters::get_character_stats,
+
+            // Character relationship commands
+            commands::character_relationships::create_character_relationship,
+            commands::character_relationships::update_character_relationship,
+            commands::character_relationships::change_character_relationship,
+            commands::character_relationships::delete_character_relationship,
+            commands::character_relationships::get_character_relationships,
+            commands::character_relationships::get_relationships_for_character,
+            commands::character_relationships::find_relationship_path,
+            commands::character_relationships::get_relationship_graph,
+            commands::character_relationships::export_relationship_graph,
+
+            // Story calendar and timeline commands
+            commands::timeline::get_story_calendar,
+            commands::timeline::set_story_calendar,
+            commands::timeline::parse_story_date,
+            commands::timeline::parse_story_duration,
+            commands::timeline::get_timeline_events,
+            commands::timeline::validate_timeline,
+
+            // Manuscript continuity commands
+            commands::continuity::check_manuscript_continuity,
             
             // Folder commands
             commands::folder_commands::create_folder,
@@ -184,11 +247,21 @@ pub fn run() {
             commands::security_commands::get_privacy_settings,
             commands::security_commands::update_privacy_settings,
             
+            // AI provider commands
+            commands::ai_provider_commands::save_openai_compatible_provider,
+            commands::ai_provider_commands::get_openai_compatible_providers,
+            commands::ai_provider_commands::delete_openai_compatible_provider,
+            commands::ai_provider_commands::discover_openai_compatible_models,
+            commands::ai_provider_commands::get_ai_routing_table,
+            commands::ai_provider_commands::save_ai_routing_table,
+            commands::ai_provider_commands::get_ai_provider_health,
+            
             // AI Writing commands
             commands::ai_writing::auto_write,
             commands::ai_writing::guided_write,
             commands::ai_writing::auto_write_stream,
             commands::ai_writing::guided_write_stream,
+            commands::ai_writing::stop_ai_stream,
             commands::ai_writing::rewrite_text,
             commands::ai_writing::expand_text,
             commands::ai_writing::describe_scene,
@@ -254,6 +327,17 @@ pub fn run() {
             
             // Story Bible detection commands
             commands::story_bible::detect_story_bible_in_text,
+            commands::story_bible::add_story_bible_alias,
+            commands::story_bible::get_story_bible_aliases,
+            commands::story_bible::get_entity_aliases,
+            commands::story_bible::delete_story_bible_alias,
+            
+            // Story bible extraction commands
+            commands::story_bible_extraction::start_story_bible_extraction,
+            commands::story_bible_extraction::resume_story_bible_extraction,
+            commands::story_bible_extraction::get_story_bible_extraction,
+            commands::story_bible_extraction::list_story_bible_extractions,
+            commands::story_bible_extraction::review_story_bible_proposal,
             
             // Story Bible AI Generation commands
             commands::story_bible_ai::generate_synopsis,
@@ -272,6 +356,9 @@ pub fn run() {
             commands::style_examples::update_style_example,
             commands::style_examples::delete_style_example,
             commands::style_examples::delete_style_examples_by_project,
+            commands::style_examples::get_style_fingerprint,
+            commands::style_examples::score_style_drift,
+            commands::style_examples::score_document_style_drift,
             
             // Template commands
             commands::templates::get_character_templates,
@@ -290,6 +377,12 @@ pub fn run() {
             commands::advanced_ai_commands::get_brainstorm_session,
             commands::advanced_ai_commands::rate_brainstorm_idea,
             commands::advanced_ai_commands::mark_idea_as_keeper,
+            commands::advanced_ai_commands::list_brainstorm_sessions,
+            commands::advanced_ai_commands::brainstorm_more_like_this,
+            commands::advanced_ai_commands::merge_brainstorm_ideas,
+            commands::advanced_ai_commands::deduplicate_brainstorm_ideas,
+            commands::advanced_ai_commands::cluster_brainstorm_ideas,
+            commands::advanced_ai_commands::export_brainstorm_keepers,
             commands::advanced_ai_commands::add_style_example,
             commands::advanced_ai_commands::analyze_text_style,
             commands::advanced_ai_commands::get_available_prose_modes,
@@ -400,13 +493,24 @@ pub fn run() {
             commands::performance_optimization::get_memory_pressure
         ])
         .setup(|app| {
-            // Initialize database on startup
+            // Initialize database on startup. This blocks so that providers persisted
+            // in the database can be registered before the AI manager is managed.
             let app_handle = app.handle().clone();
-            tauri::async_runtime::spawn(async move {
-                if let Err(e) = database::init(&app_handle).await {
+            let database_ready = match tauri::async_runtime::block_on(database::init(&app_handle)) {
+                Ok(()) => true,
+                Err(e) => {
                     eprintln!("Failed to initialize database: {}", e);
+                    false
                 }
-            });
+            };
+
+            // Load offline BPE vocab files; models without one use approximate counts
+            if let Ok(app_data_dir) = app.path().app_data_dir() {
+                let loaded = ai::tokenizer::load_vocab_dir(&app_data_dir.join("tokenizers"));
+                if loaded > 0 {
+                    println!("Loaded {} tokenizer vocab file(s)", loaded);
+                }
+            }
 
             // Initialize AIProviderManager and register OpenAIProvider
             let mut ai_manager = ai::AIProviderManager::new();
@@ -416,10 +520,60 @@ pub fn run() {
             ));
             ai_manager.register_provider("openai".to_string(), openai_provider);
             ai_manager.set_default_provider("openai".to_string());
+
+            // Serve recorded responses instead of calling providers, for offline runs
+            if let Ok(fixture_path) = std::env::var("STORYWEAVER_AI_FIXTURE") {
+                match ai::ReplayProvider::load_fixture(std::path::Path::new(&fixture_path)) {
+                    Ok(replay) => {
+                        ai_manager.register_provider("replay".to_string(), Arc::new(replay));
+                        ai_manager.set_default_provider("replay".to_string());
+                        println!("Replaying AI responses from {}", fixture_path);
+                    }
+                    Err(e) => eprintln!("Failed to load AI fixture {}: {}", fixture_path, e),
+                }
+            }
+
+            // Register self-hosted OpenAI-compatible providers (Ollama, llama.cpp, LM Studio)
+            if database_ready {
+                if let Ok(pool) = database::get_pool() {
+                    match tauri::async_runtime::block_on(ai_manager.load_openai_compatible_providers(&pool)) {
+                        Ok(count) if count > 0 => println!("Registered {} OpenAI-compatible provider(s)", count),
+                        Ok(_) => {}
+                        Err(e) => eprintln!("Failed to load OpenAI-compatible providers: {}", e),
+                    }
+                    if let Err(e) = tauri::async_runtime::block_on(ai_manager.load_routing_table(&pool)) {
+                        eprintln!("Failed to load AI routing table: {}", e);
+                    }
+                    if let Err(e) = tauri::async_runtime::block_on(ai::prompt_templates::reload_prompt_registry(&pool)) {
+                        eprintln!("Failed to load prompt templates: {}", e);
+                    }
+                    if let Err(e) = tauri::async_runtime::block_on(ai::reload_pricing(&pool)) {
+                        eprintln!("Failed to load model pricing: {}", e);
+                    }
+                    ai_manager.enable_budgets((*pool).clone());
+                }
+            }
+            let ai_manager = Arc::new(ai_manager);
+            ai::advanced_ai_manager::set_budget_notifier(app.handle().clone());
+            if let Err(e) = ai::semantic_index::init_semantic_indexer(ai_manager.clone()) {
+                eprintln!("Failed to initialize semantic indexer: {}", e);
+            }
+            if let Err(e) = ai::story_summary::init_story_summarizer(ai_manager.clone()) {
+                eprintln!("Failed to initialize story summarizer: {}", e);
+            }
             app.manage(ai_manager);
+
+            // Streaming buffer between providers and the frontend
+            match ai::streaming_optimizer::init_streaming_optimizer(ai::streaming_optimizer::StreamingConfig::default()) {
+                Ok(()) => {
+                    tauri::async_runtime::spawn(ai::streaming_optimizer::start_streaming_cleanup_task());
+                }
+                Err(e) => eprintln!("Failed to initialize streaming optimizer: {}", e),
+            }
             
             // Initialize Advanced AI Manager (Phase 4)
-            let advanced_ai_manager = ai::AdvancedAIManager::new();
+            let mut advanced_ai_manager = ai::AdvancedAIManager::new();
+            advanced_ai_manager.set_embedder(app.state::<Arc<ai::AIProviderManager>>().inner().clone());
             app.manage(commands::advanced_ai_commands::AdvancedAIState::new(advanced_ai_manager));
             
             // Initialize background task manager
@@ -428,10 +582,17 @@ pub fn run() {
             // Register AI task processor
             let app_handle_clone = app.handle().clone();
             let ai_task_processor = Arc::new(background::ai_processor::AITaskProcessor::new(app_handle_clone));
+            let chapter_draft_processor =
+                Arc::new(background::chapter_draft_processor::ChapterDraftProcessor::new(app.handle().clone()));
+            let story_bible_extraction_processor = Arc::new(
+                background::story_bible_extraction_processor::StoryBibleExtractionProcessor::new(app.handle().clone()),
+            );
             
             let background_task_manager_clone = background_task_manager.clone();
             tauri::async_runtime::spawn(async move {
                 background_task_manager_clone.register_processor(ai_task_processor).await;
+                background_task_manager_clone.register_processor(chapter_draft_processor).await;
+                background_task_manager_clone.register_processor(story_bible_extraction_processor).await;
                 if let Err(e) = background_task_manager_clone.start().await {
                     eprintln!("Failed to start background task manager: {}", e);
                 }
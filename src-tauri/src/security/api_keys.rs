@@ -19,6 +19,28 @@ const CLAUDE_KEY: &str = "claude";
 pub enum ApiProvider {
     OpenAI,
     Claude,
+    /// A user-registered OpenAI-compatible endpoint, keyed by provider name
+    Custom(String),
+}
+
+impl ApiProvider {
+    /// Keychain entry name for this provider
+    fn key_name(&self) -> String {
+        match self {
+            ApiProvider::OpenAI => OPENAI_KEY.to_string(),
+            ApiProvider::Claude => CLAUDE_KEY.to_string(),
+            ApiProvider::Custom(name) => format!("custom:{}", name),
+        }
+    }
+
+    /// Human readable provider name for logs and audit events
+    pub fn display_name(&self) -> String {
+        match self {
+            ApiProvider::OpenAI => "OpenAI".to_string(),
+            ApiProvider::Claude => "Claude".to_string(),
+            ApiProvider::Custom(name) => name.clone(),
+        }
+    }
 }
 
 /// API key manager for secure storage and retrieval
@@ -35,12 +57,9 @@ impl ApiKeyManager {
 
     /// Save an API key to secure storage
     pub async fn save_api_key(&self, provider: ApiProvider, api_key: &str) -> Result<(), StoryWeaverError> {
-        let key_name = match provider {
-            ApiProvider::OpenAI => OPENAI_KEY,
-            ApiProvider::Claude => CLAUDE_KEY,
-        };
+        let key_name = provider.key_name();
         
-        let entry = Entry::new(SERVICE, key_name)
+        let entry = Entry::new(SERVICE, &key_name)
             .map_err(|e| StoryWeaverError::SecurityError {
                 message: format!("Failed to create keyring entry: {}", e),
             })?;
@@ -59,42 +78,14 @@ impl ApiKeyManager {
 
     /// Get an API key from secure storage
     pub async fn get_api_key(&self, provider: ApiProvider) -> Result<Option<String>, StoryWeaverError> {
-        let key_name = match provider {
-            ApiProvider::OpenAI => OPENAI_KEY,
-            ApiProvider::Claude => CLAUDE_KEY,
-        };
-
-        let entry = Entry::new(SERVICE, key_name)
-            .map_err(|e| StoryWeaverError::SecurityError {
-                message: format!("Failed to create keyring entry: {}", e),
-            })?;
-        
-        match entry.get_password() {
-            Ok(password) => {
-                debug!("Successfully retrieved API key for provider: {:?}", provider);
-                Ok(Some(password))
-            },
-            Err(KeyringError::NoEntry) => {
-                debug!("No API key found for provider: {:?}", provider);
-                Ok(None)
-            },
-            Err(e) => {
-                error!("Failed to retrieve API key for {}: {}", key_name, e);
-                Err(StoryWeaverError::SecurityError {
-                    message: format!("Failed to retrieve API key: {}", e),
-                })
-            }
-        }
+        read_stored_api_key(&provider)
     }
 
     /// Delete an API key from secure storage
     pub async fn delete_api_key(&self, provider: ApiProvider) -> Result<(), StoryWeaverError> {
-        let key_name = match provider {
-            ApiProvider::OpenAI => OPENAI_KEY,
-            ApiProvider::Claude => CLAUDE_KEY,
-        };
+        let key_name = provider.key_name();
 
-        let entry = Entry::new(SERVICE, key_name)
+        let entry = Entry::new(SERVICE, &key_name)
             .map_err(|e| StoryWeaverError::SecurityError {
                 message: format!("Failed to create keyring entry: {}", e),
             })?;
@@ -124,6 +115,35 @@ impl ApiKeyManager {
     }
 }
 
+/// Read an API key straight from the OS keychain.
+///
+/// Used during startup, before the global manager has been initialized.
+pub fn read_stored_api_key(provider: &ApiProvider) -> Result<Option<String>, StoryWeaverError> {
+    let key_name = provider.key_name();
+
+    let entry = Entry::new(SERVICE, &key_name)
+        .map_err(|e| StoryWeaverError::SecurityError {
+            message: format!("Failed to create keyring entry: {}", e),
+        })?;
+
+    match entry.get_password() {
+        Ok(password) => {
+            debug!("Successfully retrieved API key for provider: {:?}", provider);
+            Ok(Some(password))
+        },
+        Err(KeyringError::NoEntry) => {
+            debug!("No API key found for provider: {:?}", provider);
+            Ok(None)
+        },
+        Err(e) => {
+            error!("Failed to retrieve API key for {}: {}", key_name, e);
+            Err(StoryWeaverError::SecurityError {
+                message: format!("Failed to retrieve API key: {}", e),
+            })
+        }
+    }
+}
+
 /// Global instance of the API key manager
 static API_KEY_MANAGER: OnceLock<Arc<ApiKeyManager>> = OnceLock::new();
 
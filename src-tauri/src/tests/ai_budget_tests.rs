@@ -0,0 +1,258 @@
+//! Tests for AI budget enforcement, spend alerts and forecasts
+
+use crate::ai::token_counter::builtin_prices;
+use crate::ai::tokenizer::count_tokens;
+use crate::ai::{
+    set_pricing, AIContext, AIProvider, AIProviderManager, BudgetDecision, CreditTracker, MockProvider,
+    OpenAICompatibleConfig, RouteTarget, RoutingTable, ScriptedResponse, SpendEstimate, WritingFeature,
+};
+use crate::database::operations::{
+    AIBudget, AIBudgetOps, BudgetEnforcement, BudgetPeriod, BudgetScope, CreditUsageOps, ModelPrice,
+};
+use crate::error::StoryWeaverError;
+use crate::tests::mock_http_server::{MockHttpServer, MockResponse};
+use crate::tests::test_project;
+use chrono::{NaiveDate, Utc};
+use futures_util::StreamExt;
+use serde_json::json;
+use sqlx::{Pool, Sqlite};
+use std::sync::Arc;
+use std::time::Duration;
+
+async fn test_pool() -> Pool<Sqlite> {
+    // Unpriced models cost nothing, so give the mock model a price
+    let mut prices = builtin_prices();
+    prices.push(ModelPrice::new("mock-model", 0.01, 0.03, NaiveDate::from_ymd_opt(2024, 1, 1).unwrap()));
+    set_pricing(prices);
+
+    crate::tests::test_pool().await
+}
+
+fn project_context(project_id: &str) -> AIContext {
+    AIContext { project_id: Some(project_id.to_string()), ..Default::default() }
+}
+
+/// Manager with a "primary" default provider and a "backup" after it in the fallback chain
+fn manager(pool: &Pool<Sqlite>) -> (AIProviderManager, Arc<MockProvider>, Arc<MockProvider>) {
+    let primary = Arc::new(MockProvider::new().with_name("primary"));
+    let backup = Arc::new(MockProvider::new().with_name("backup"));
+    let mut manager = AIProviderManager::new();
+    manager.register_provider("primary".to_string(), primary.clone());
+    manager.register_provider("backup".to_string(), backup.clone());
+    manager.set_default_provider("primary".to_string());
+    manager.set_routing_table(RoutingTable {
+        fallback_chain: vec![RouteTarget::new("primary"), RouteTarget::new("backup")],
+        ..RoutingTable::default()
+    });
+    manager.enable_budgets(pool.clone());
+    (manager, primary, backup)
+}
+
+async fn budget(pool: &Pool<Sqlite>, scope: BudgetScope, scope_id: Option<&str>, limit_usd: f64, enforcement: BudgetEnforcement) -> AIBudget {
+    let mut budget = AIBudget::new(scope, scope_id.map(str::to_string), BudgetPeriod::Daily, limit_usd);
+    budget.enforcement = enforcement;
+    AIBudgetOps::upsert(pool, budget).await.unwrap()
+}
+
+#[tokio::test]
+async fn test_requests_over_a_block_budget_are_not_sent() {
+    let pool = test_pool().await;
+    let project_id = test_project(&pool, "Saga").await;
+    let (manager, primary, backup) = manager(&pool);
+    budget(&pool, BudgetScope::Project, Some(&project_id), 0.000_001, BudgetEnforcement::Block).await;
+
+    let error = manager.generate_text("Continue the chapter", &project_context(&project_id)).await.unwrap_err();
+    assert!(matches!(error, StoryWeaverError::BudgetExceeded { requires_confirmation: false, .. }));
+    assert!(primary.calls().is_empty());
+    assert!(backup.calls().is_empty());
+
+    // Other projects aren't limited by it, and their spend is recorded
+    let other = test_project(&pool, "Saga").await;
+    manager.generate_text("Continue the chapter", &project_context(&other)).await.unwrap();
+    let usage = CreditUsageOps::get_by_project(&pool, &other).await.unwrap();
+    assert_eq!(usage.len(), 1);
+    assert_eq!(usage[0].provider.as_deref(), Some("primary"));
+    assert!(usage[0].cost_usd > 0.0);
+}
+
+#[tokio::test]
+async fn test_provider_budget_fails_over_to_next_provider() {
+    let pool = test_pool().await;
+    let project_id = test_project(&pool, "Saga").await;
+    let (manager, primary, backup) = manager(&pool);
+    budget(&pool, BudgetScope::Provider, Some("primary"), 0.000_001, BudgetEnforcement::Block).await;
+
+    manager.generate_text("Continue the chapter", &project_context(&project_id)).await.unwrap();
+    assert!(primary.calls().is_empty());
+    assert_eq!(backup.calls().len(), 1);
+
+    // Once every provider is over its budget the request is refused
+    budget(&pool, BudgetScope::Provider, Some("backup"), 0.000_001, BudgetEnforcement::Block).await;
+    assert!(manager.generate_text("Continue the chapter", &project_context(&project_id)).await.is_err());
+    assert_eq!(backup.calls().len(), 1);
+}
+
+#[tokio::test]
+async fn test_spend_is_recorded_from_the_generated_text() {
+    let pool = test_pool().await;
+    let project_id = test_project(&pool, "Saga").await;
+    let reply = "The tide turned, and the harbour emptied before dawn.";
+    let primary = Arc::new(MockProvider::new().on(&WritingFeature::Write, ScriptedResponse::text(reply)));
+    let mut manager = AIProviderManager::new();
+    manager.register_provider("primary".to_string(), primary);
+    manager.set_default_provider("primary".to_string());
+    manager.enable_budgets(pool.clone());
+
+    manager.generate_text("Continue the chapter", &project_context(&project_id)).await.unwrap();
+
+    // No usage reported, so the reply is counted instead of the output allowance
+    let usage = CreditUsageOps::get_by_project(&pool, &project_id).await.unwrap();
+    assert_eq!(usage.len(), 1);
+    let expected = count_tokens("", "Continue the chapter") + count_tokens("mock-model", reply);
+    assert_eq!(usage[0].tokens_used, Some(expected as i32));
+}
+
+#[tokio::test]
+async fn test_spend_uses_the_usage_the_provider_reports() {
+    let pool = test_pool().await;
+    let project_id = test_project(&pool, "Saga").await;
+    let server = MockHttpServer::start(vec![(
+        "POST",
+        "/v1/chat/completions",
+        MockResponse::json(200, json!({
+            "choices": [{ "message": { "role": "assistant", "content": "Dawn came." }, "finish_reason": "stop" }],
+            "usage": { "prompt_tokens": 120, "completion_tokens": 45, "total_tokens": 165 }
+        })),
+    )])
+    .await;
+    let mut manager = AIProviderManager::new();
+    manager.register_openai_compatible(OpenAICompatibleConfig {
+        name: "local".to_string(),
+        display_name: "Local".to_string(),
+        base_url: server.base_url(),
+        api_key: None,
+        model: "llama3".to_string(),
+        embedding_model: None,
+        context_window: Some(8192),
+    });
+    manager.set_default_provider("local".to_string());
+    manager.enable_budgets(pool.clone());
+
+    manager.generate_text("Continue the chapter", &project_context(&project_id)).await.unwrap();
+
+    let usage = CreditUsageOps::get_by_project(&pool, &project_id).await.unwrap();
+    assert_eq!(usage.len(), 1);
+    assert_eq!(usage[0].tokens_used, Some(165));
+}
+
+#[tokio::test]
+async fn test_stopped_stream_records_what_it_generated() {
+    let pool = test_pool().await;
+    let project_id = test_project(&pool, "Saga").await;
+    let chunks = ScriptedResponse::chunks(&["The tide ", "turned ", "at last."]).with_chunk_delay(Duration::from_millis(5));
+    let primary = Arc::new(MockProvider::new().on(&WritingFeature::Write, chunks));
+    let mut manager = AIProviderManager::new();
+    manager.register_provider("primary".to_string(), primary);
+    manager.set_default_provider("primary".to_string());
+    manager.enable_budgets(pool.clone());
+
+    let mut stream = manager.generate_text_stream("Continue the chapter", &project_context(&project_id)).await.unwrap();
+    // Nothing is recorded until the stream ends
+    assert!(CreditUsageOps::get_by_project(&pool, &project_id).await.unwrap().is_empty());
+    assert_eq!(stream.next().await.unwrap().unwrap(), "The tide ");
+    // The writer stops it: the stream is dropped
+    drop(stream);
+
+    let mut usage = Vec::new();
+    for _ in 0..100 {
+        usage = CreditUsageOps::get_by_project(&pool, &project_id).await.unwrap();
+        if !usage.is_empty() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert_eq!(usage.len(), 1);
+    let expected = count_tokens("", "Continue the chapter") + count_tokens("mock-model", "The tide ");
+    assert_eq!(usage[0].tokens_used, Some(expected as i32));
+}
+
+#[tokio::test]
+async fn test_confirm_budget_allows_requests_once_approved() {
+    let pool = test_pool().await;
+    let project_id = test_project(&pool, "Saga").await;
+    let (manager, primary, _) = manager(&pool);
+    let confirm = budget(&pool, BudgetScope::Global, None, 0.000_001, BudgetEnforcement::Confirm).await;
+
+    let error = manager.generate_text("Continue the chapter", &project_context(&project_id)).await.unwrap_err();
+    assert!(matches!(error, StoryWeaverError::BudgetExceeded { requires_confirmation: true, .. }));
+
+    AIBudgetOps::approve_overrun(&pool, &confirm.id, &BudgetPeriod::Daily.start(Utc::now())).await.unwrap();
+    manager.generate_text("Continue the chapter", &project_context(&project_id)).await.unwrap();
+    assert_eq!(primary.calls().len(), 1);
+
+    // A block budget still refuses the request
+    budget(&pool, BudgetScope::Project, Some(&project_id), 0.000_001, BudgetEnforcement::Block).await;
+    let estimate = SpendEstimate::new(Some(project_id.clone()), "primary", "mock-model", 100, 100);
+    let check = CreditTracker::preflight(&pool, estimate).await.unwrap();
+    assert!(matches!(check.decision, BudgetDecision::Blocked { .. }));
+    assert!(!check.provider_limited());
+}
+
+#[tokio::test]
+async fn test_thresholds_alert_once_per_period() {
+    let pool = test_pool().await;
+    let project_id = test_project(&pool, "Saga").await;
+    let estimate = SpendEstimate::new(Some(project_id.clone()), "primary", "mock-model", 1_000, 1_000);
+    let mut watched = AIBudget::new(BudgetScope::Project, Some(project_id.clone()), BudgetPeriod::Monthly, estimate.cost_usd * 2.5);
+    watched.alert_thresholds = vec![0.5, 1.0];
+    AIBudgetOps::upsert(&pool, watched).await.unwrap();
+
+    let alerts = CreditTracker::record_spend(&pool, &estimate, "write").await.unwrap();
+    assert!(alerts.is_empty());
+
+    let alerts = CreditTracker::record_spend(&pool, &estimate, "write").await.unwrap();
+    assert_eq!(alerts.len(), 1);
+    assert_eq!(alerts[0].threshold, 0.5);
+    assert!(alerts[0].message().contains("50%"));
+
+    // Still above 50%, but that alert was already sent
+    let alerts = CreditTracker::record_spend(&pool, &estimate, "write").await.unwrap();
+    assert_eq!(alerts.iter().map(|a| a.threshold).collect::<Vec<_>>(), vec![1.0]);
+}
+
+#[tokio::test]
+async fn test_forecast_projects_month_from_daily_average() {
+    let pool = test_pool().await;
+    let project_id = test_project(&pool, "Saga").await;
+    let estimate = SpendEstimate::new(Some(project_id.clone()), "primary", "mock-model", 10_000, 10_000);
+    CreditTracker::record_spend(&pool, &estimate, "write").await.unwrap();
+    CreditTracker::record_spend(&pool, &estimate, "write").await.unwrap();
+
+    let forecast = CreditTracker::forecast(&pool, &project_id).await.unwrap();
+    assert!((forecast.spent_this_month_usd - estimate.cost_usd * 2.0).abs() < 1e-9);
+    assert!((forecast.daily_average_usd - estimate.cost_usd * 2.0).abs() < 1e-9);
+    assert!(forecast.projected_month_usd >= forecast.spent_this_month_usd);
+    assert!(forecast.monthly_limit_usd.is_none());
+    assert!(forecast.limit_reached_on.is_none());
+
+    let limit = AIBudget::new(BudgetScope::Project, Some(project_id.clone()), BudgetPeriod::Monthly, estimate.cost_usd);
+    AIBudgetOps::upsert(&pool, limit).await.unwrap();
+    let forecast = CreditTracker::forecast(&pool, &project_id).await.unwrap();
+    assert_eq!(forecast.limit_reached_on, Some(Utc::now().date_naive().format("%Y-%m-%d").to_string()));
+}
+
+#[tokio::test]
+async fn test_budgets_are_replaced_per_scope_and_period() {
+    let pool = test_pool().await;
+    let first = budget(&pool, BudgetScope::Global, None, 5.0, BudgetEnforcement::Block).await;
+    let second = budget(&pool, BudgetScope::Global, None, 8.0, BudgetEnforcement::Confirm).await;
+
+    assert_eq!(first.id, second.id);
+    let budgets = AIBudgetOps::list(&pool).await.unwrap();
+    assert_eq!(budgets.len(), 1);
+    assert_eq!(budgets[0].limit_usd, 8.0);
+    assert_eq!(budgets[0].enforcement, BudgetEnforcement::Confirm);
+
+    AIBudgetOps::delete(&pool, &first.id).await.unwrap();
+    assert!(AIBudgetOps::list(&pool).await.unwrap().is_empty());
+}
@@ -0,0 +1,150 @@
+//! Tests for per-feature routing and provider failover in AIProviderManager
+
+use crate::ai::{
+    AIContext, AIProvider, AIProviderManager, OpenAICompatibleConfig, RewriteStyle, RouteRequest, RouteTaken, RouteTarget,
+    RoutingTable, WritingFeature,
+};
+use crate::tests::mock_http_server::{MockHttpServer, MockResponse};
+use serde_json::json;
+
+fn chat_completion(content: &str) -> MockResponse {
+    MockResponse::json(200, json!({
+        "choices": [{ "message": { "role": "assistant", "content": content }, "finish_reason": "stop" }]
+    }))
+}
+
+async fn provider_server(response: MockResponse) -> MockHttpServer {
+    MockHttpServer::start(vec![("POST", "/v1/chat/completions", response)]).await
+}
+
+fn register(manager: &mut AIProviderManager, name: &str, model: &str, server: &MockHttpServer) {
+    manager.register_openai_compatible(OpenAICompatibleConfig {
+        name: name.to_string(),
+        display_name: name.to_string(),
+        base_url: server.base_url(),
+        api_key: None,
+        model: model.to_string(),
+        embedding_model: None,
+        context_window: None,
+    });
+}
+
+#[tokio::test]
+async fn test_rate_limited_provider_fails_over_to_next_in_chain() {
+    // Asking for a longer wait than the retry policy allows fails over at once
+    let primary = provider_server(
+        MockResponse::json(429, json!({ "error": "rate limited" })).with_header("Retry-After", "120"),
+    ).await;
+    let backup = provider_server(chat_completion("from backup")).await;
+
+    let mut manager = AIProviderManager::new();
+    register(&mut manager, "primary", "model-a", &primary);
+    register(&mut manager, "backup", "model-b", &backup);
+    manager.set_default_provider("primary".to_string());
+    manager.set_routing_table(RoutingTable {
+        fallback_chain: vec![RouteTarget::new("primary"), RouteTarget::new("backup")],
+        ..RoutingTable::default()
+    });
+
+    let (text, route) = manager
+        .execute_routed(&RouteRequest::feature(WritingFeature::Write), |provider| async move {
+            provider.generate_text("Begin", &AIContext::default()).await
+        })
+        .await
+        .unwrap();
+
+    assert_eq!(text, "from backup");
+    assert!(route.failed_over());
+    assert_eq!(route.feature.as_deref(), Some("write"));
+    assert_eq!(route.attempts[0].provider, "primary");
+    assert!(route.attempts[0].error.as_deref().unwrap_or_default().contains("rate limit"));
+    let served_by = route.served_by().unwrap();
+    assert_eq!(served_by.provider, "backup");
+    assert_eq!(served_by.model, "model-b");
+}
+
+#[tokio::test]
+async fn test_client_errors_do_not_fail_over() {
+    let primary = provider_server(MockResponse::json(400, json!({ "error": "bad request" }))).await;
+    let backup = provider_server(chat_completion("from backup")).await;
+
+    let mut manager = AIProviderManager::new();
+    register(&mut manager, "primary", "model-a", &primary);
+    register(&mut manager, "backup", "model-b", &backup);
+    manager.set_default_provider("backup".to_string());
+    manager.set_routing_table(RoutingTable {
+        fallback_chain: vec![RouteTarget::new("primary")],
+        ..RoutingTable::default()
+    });
+
+    let error = manager.generate_text("Begin", &AIContext::default()).await.unwrap_err();
+
+    assert!(error.to_string().contains("400"));
+    assert!(backup.requests().is_empty());
+}
+
+#[tokio::test]
+async fn test_feature_route_takes_precedence_over_default() {
+    let default_server = provider_server(chat_completion("from default")).await;
+    let brainstorm_server = provider_server(chat_completion("1. An idea")).await;
+
+    let mut manager = AIProviderManager::new();
+    register(&mut manager, "default", "model-a", &default_server);
+    register(&mut manager, "ideas", "model-b", &brainstorm_server);
+    manager.set_default_provider("default".to_string());
+
+    let mut table = RoutingTable::default();
+    table.features.insert("quick_chat".to_string(), vec![RouteTarget::new("ideas")]);
+    manager.set_routing_table(table);
+
+    let chat = manager.quick_chat("Any ideas?", &AIContext::default()).await.unwrap();
+    let edit = manager.quick_edit("text", "tighten").await.unwrap();
+
+    assert_eq!(chat, "1. An idea");
+    assert_eq!(edit, "from default");
+}
+
+#[tokio::test]
+async fn test_plugin_model_routes_to_provider_serving_it() {
+    let default_server = provider_server(chat_completion("from default")).await;
+    let local_server = provider_server(chat_completion("from local")).await;
+
+    let mut manager = AIProviderManager::new();
+    register(&mut manager, "default", "gpt-4", &default_server);
+    register(&mut manager, "local", "llama3", &local_server);
+    manager.set_default_provider("default".to_string());
+
+    let chain = manager.resolve_route(&RouteRequest::plugin(3, "llama3"));
+    let keys: Vec<&str> = chain.iter().map(|(key, _)| key.as_str()).collect();
+    assert_eq!(keys, vec!["local", "default"]);
+
+    let mut table = RoutingTable::default();
+    table.plugins.insert(3, vec![RouteTarget::new("default")]);
+    manager.set_routing_table(table);
+
+    let chain = manager.resolve_route(&RouteRequest::plugin(3, "llama3"));
+    assert_eq!(chain.len(), 1);
+    assert_eq!(chain[0].0, "default");
+}
+
+#[tokio::test]
+async fn test_route_records_the_template_the_provider_rendered() {
+    let server = provider_server(chat_completion("Shorter.")).await;
+    let mut manager = AIProviderManager::new();
+    register(&mut manager, "local", "model-a", &server);
+    manager.set_default_provider("local".to_string());
+
+    let request = RouteRequest::feature(WritingFeature::Rewrite(RewriteStyle::Shorter));
+    let (_, route) = manager
+        .execute_routed(&request, |provider| async move {
+            provider.rewrite_text("A much longer sentence.", &RewriteStyle::Shorter).await
+        })
+        .await
+        .unwrap();
+
+    // The rewrite template is credited over the system prompt sent with it
+    let template = route.prompt_template.clone().unwrap();
+    assert_eq!(template.name, "rewrite");
+    let stored: RouteTaken = serde_json::from_str(&route.to_json()).unwrap();
+    assert_eq!(stored.prompt_template, Some(template));
+}
@@ -0,0 +1,308 @@
+//! Tests for persisted brainstorm sessions and their idea operations
+
+use crate::ai::{
+    BrainstormCategory, BrainstormEngine, BrainstormRequest, IdeaOrigin, MockProvider, ScriptedResponse, StoryBibleKind,
+    WritingFeature,
+};
+use crate::database::models::AIGenerationType;
+use crate::database::operations::{AIHistoryOps, BrainstormSessionOps, CharacterOps, PlotThreadOps};
+use crate::tests::test_project;
+use crate::error::StoryWeaverError;
+use sqlx::{Pool, Sqlite};
+
+async fn test_pool() -> (Pool<Sqlite>, String) {
+    let pool = crate::tests::test_pool().await;
+    let project_id = test_project(&pool, "Saga").await;
+    (pool, project_id)
+}
+
+fn request(project_id: &str, category: BrainstormCategory, num_ideas: usize) -> BrainstormRequest {
+    BrainstormRequest {
+        project_id: project_id.to_string(),
+        category,
+        seed_prompt: Some("coastal mystery".to_string()),
+        context: None,
+        num_ideas,
+        creativity_level: 5,
+        focus_areas: Vec::new(),
+    }
+}
+
+/// Structured reply listing `ideas`, each tagged with its `tags`
+fn ideas_reply(ideas: &[(&str, &[&str])]) -> ScriptedResponse {
+    let ideas: Vec<_> = ideas
+        .iter()
+        .map(|(content, tags)| serde_json::json!({ "content": content, "tags": tags }))
+        .collect();
+    ScriptedResponse::text(&serde_json::json!({ "ideas": ideas }).to_string())
+}
+
+/// A session holding `ideas`, in order
+async fn session_with(
+    pool: &Pool<Sqlite>,
+    project_id: &str,
+    category: BrainstormCategory,
+    ideas: &[(&str, &[&str])],
+) -> (BrainstormEngine, String, Vec<String>) {
+    let engine = BrainstormEngine::default();
+    let provider = MockProvider::new().on(&WritingFeature::Write, ideas_reply(ideas));
+    let request = request(project_id, category, ideas.len());
+    let session_id = engine.create_session(pool, request.clone()).await.unwrap();
+    let ideas = engine.generate_ideas(pool, &session_id, &request, Some(&provider)).await.unwrap();
+    (engine, session_id, ideas.into_iter().map(|idea| idea.id).collect())
+}
+
+#[tokio::test]
+async fn test_sessions_survive_a_new_engine() {
+    let (pool, project_id) = test_pool().await;
+    let (engine, session_id, ids) = session_with(
+        &pool,
+        &project_id,
+        BrainstormCategory::Plot,
+        &[("A lighthouse with no keeper", &["lighthouse"]), ("A map drawn in salt", &[]), ("A bell at low tide", &[])],
+    )
+    .await;
+    engine.mark_as_keeper(&pool, &session_id, &ids[1]).await.unwrap();
+    engine.rate_idea(&pool, &session_id, &ids[1], 9).await.unwrap();
+    engine.add_idea_notes(&pool, &session_id, &ids[1], "Use in act two".to_string()).await.unwrap();
+    drop(engine);
+
+    // As after a restart
+    let engine = BrainstormEngine::default();
+    let session = engine.get_session(&pool, &session_id).await.unwrap().unwrap();
+    let contents: Vec<&str> = session.ideas.iter().map(|idea| idea.content.as_str()).collect();
+    assert_eq!(contents, vec!["A lighthouse with no keeper", "A map drawn in salt", "A bell at low tide"]);
+    assert_eq!(session.keepers, vec![ids[1].clone()]);
+    assert_eq!(session.ideas[1].rating, Some(5));
+    assert_eq!(session.ideas[1].notes, "Use in act two");
+    assert!(session.ideas[0].tags.contains(&"lighthouse".to_string()));
+    assert!(matches!(session.category, BrainstormCategory::Plot));
+
+    engine.remove_keeper(&pool, &session_id, &ids[1]).await.unwrap();
+    assert!(engine.get_keepers(&pool, &session_id).await.unwrap().is_empty());
+    assert_eq!(engine.list_project_sessions(&pool, &project_id).await.unwrap().len(), 1);
+
+    // Ideas of another session are out of reach
+    let err = engine.rate_idea(&pool, "missing", &ids[0], 3).await.unwrap_err();
+    assert!(matches!(err, StoryWeaverError::NotFound { .. }));
+}
+
+#[tokio::test]
+async fn test_template_ideas_are_persisted_without_a_provider() {
+    let (pool, project_id) = test_pool().await;
+    let engine = BrainstormEngine::default();
+    let request = request(&project_id, BrainstormCategory::Characters, 3);
+    let session_id = engine.create_session(&pool, request.clone()).await.unwrap();
+
+    let ideas = engine.generate_ideas(&pool, &session_id, &request, None).await.unwrap();
+    assert_eq!(ideas.len(), 3);
+    assert!(ideas.iter().all(|idea| idea.origin == IdeaOrigin::Template));
+    assert_eq!(BrainstormSessionOps::get_ideas(&pool, &session_id).await.unwrap().len(), 3);
+}
+
+#[tokio::test]
+async fn test_more_like_this_follows_the_keepers() {
+    let (pool, project_id) = test_pool().await;
+    let (engine, session_id, ids) = session_with(
+        &pool,
+        &project_id,
+        BrainstormCategory::Plot,
+        &[("A lighthouse with no keeper", &[]), ("A map drawn in salt", &[])],
+    )
+    .await;
+
+    let provider = MockProvider::new().on(
+        &WritingFeature::Write,
+        ideas_reply(&[
+            ("A lighthouse with no keeper.", &[]),
+            ("A lighthouse whose lamp lights itself", &["lighthouse"]),
+            ("A keeper who never leaves the lamp room", &[]),
+        ]),
+    );
+    let err = engine.more_like_this(&pool, &session_id, 2, &provider).await.unwrap_err();
+    assert!(matches!(err, StoryWeaverError::InputValidation { .. }));
+    assert!(provider.calls().is_empty());
+
+    engine.mark_as_keeper(&pool, &session_id, &ids[0]).await.unwrap();
+    let ideas = engine.more_like_this(&pool, &session_id, 2, &provider).await.unwrap();
+
+    // The repeat of an existing idea is dropped
+    let contents: Vec<&str> = ideas.iter().map(|idea| idea.content.as_str()).collect();
+    assert_eq!(contents, vec!["A lighthouse whose lamp lights itself", "A keeper who never leaves the lamp room"]);
+    assert!(ideas.iter().all(|idea| idea.origin == IdeaOrigin::MoreLikeThis && idea.derived_from == vec![ids[0].clone()]));
+
+    let prompt = &provider.calls()[0].input;
+    assert!(prompt.contains("A lighthouse with no keeper"));
+    assert!(prompt.contains("A map drawn in salt"));
+
+    let session = engine.get_session(&pool, &session_id).await.unwrap().unwrap();
+    assert_eq!(session.ideas.len(), 4);
+    assert_eq!(session.ideas[3].origin, IdeaOrigin::MoreLikeThis);
+}
+
+#[tokio::test]
+async fn test_merged_idea_replaces_both_in_place() {
+    let (pool, project_id) = test_pool().await;
+    let (engine, session_id, ids) = session_with(
+        &pool,
+        &project_id,
+        BrainstormCategory::Plot,
+        &[("A map drawn in salt", &["map"]), ("A bell at low tide", &[]), ("A lighthouse with no keeper", &["lighthouse"])],
+    )
+    .await;
+    engine.mark_as_keeper(&pool, &session_id, &ids[2]).await.unwrap();
+    engine.add_idea_notes(&pool, &session_id, &ids[0], "Salt dissolves".to_string()).await.unwrap();
+
+    let err = engine.merge_ideas(&pool, &session_id, &ids[0], &ids[0], None).await.unwrap_err();
+    assert!(matches!(err, StoryWeaverError::InputValidation { .. }));
+
+    let merged = engine.merge_ideas(&pool, &session_id, &ids[0], &ids[2], None).await.unwrap();
+    assert_eq!(merged.content, "A map drawn in salt A lighthouse with no keeper");
+    assert_eq!(merged.origin, IdeaOrigin::Merged);
+    assert_eq!(merged.derived_from, vec![ids[0].clone(), ids[2].clone()]);
+    assert!(merged.is_keeper);
+    assert!(merged.tags.contains(&"map".to_string()) && merged.tags.contains(&"lighthouse".to_string()));
+    assert_eq!(merged.notes, "Salt dissolves");
+
+    let session = engine.get_session(&pool, &session_id).await.unwrap().unwrap();
+    let order: Vec<&str> = session.ideas.iter().map(|idea| idea.id.as_str()).collect();
+    assert_eq!(order, vec![merged.id.as_str(), ids[1].as_str()]);
+    assert_eq!(session.keepers, vec![merged.id.clone()]);
+
+    // With a provider the AI writes the combined idea
+    let provider = MockProvider::new().on(&WritingFeature::Write, ScriptedResponse::text("  A salt map that rings  "));
+    let merged = engine.merge_ideas(&pool, &session_id, &merged.id, &ids[1], Some(&provider)).await.unwrap();
+    assert_eq!(merged.content, "A salt map that rings");
+    assert!(provider.calls()[0].input.contains("A bell at low tide"));
+}
+
+#[tokio::test]
+async fn test_ai_ideas_are_logged_with_their_template() {
+    let (pool, project_id) = test_pool().await;
+    let (engine, session_id, ids) = session_with(
+        &pool,
+        &project_id,
+        BrainstormCategory::Plot,
+        &[("A map drawn in salt", &[]), ("A bell at low tide", &[])],
+    )
+    .await;
+    let provider = MockProvider::new().on(&WritingFeature::Write, ScriptedResponse::text("A salt map that rings"));
+    engine.merge_ideas(&pool, &session_id, &ids[0], &ids[1], Some(&provider)).await.unwrap();
+
+    let history = AIHistoryOps::get_by_project(&pool, &project_id, None).await.unwrap();
+    assert_eq!(history.len(), 2);
+    assert!(history.iter().all(|h| matches!(h.generation_type, AIGenerationType::Brainstorm)));
+    let mut templates: Vec<(&str, i64)> = history
+        .iter()
+        .map(|h| (h.prompt_template.as_deref().unwrap(), h.prompt_template_version.unwrap()))
+        .collect();
+    templates.sort();
+    assert_eq!(templates, vec![("brainstorm", 1), ("brainstorm_merge", 1)]);
+    let merge = history.iter().find(|h| h.prompt_template.as_deref() == Some("brainstorm_merge")).unwrap();
+    assert_eq!(merge.response, "A salt map that rings");
+}
+
+#[tokio::test]
+async fn test_deduplicate_keeps_the_keeper_and_its_tags() {
+    let (pool, project_id) = test_pool().await;
+    let (engine, session_id, ids) = session_with(
+        &pool,
+        &project_id,
+        BrainstormCategory::Plot,
+        &[
+            ("A lighthouse with no keeper", &["coast"]),
+            ("Twin sisters trade places at a royal wedding", &[]),
+            ("a lighthouse with NO keeper!", &["ghost"]),
+        ],
+    )
+    .await;
+    engine.mark_as_keeper(&pool, &session_id, &ids[2]).await.unwrap();
+
+    let duplicates = engine.deduplicate(&pool, &session_id).await.unwrap();
+    assert_eq!(duplicates.len(), 1);
+    assert_eq!(duplicates[0].id, ids[0]);
+    assert_eq!(duplicates[0].duplicate_of, ids[2]);
+    assert!(duplicates[0].similarity > 0.99);
+
+    let session = engine.get_session(&pool, &session_id).await.unwrap().unwrap();
+    let remaining: Vec<&str> = session.ideas.iter().map(|idea| idea.id.as_str()).collect();
+    assert_eq!(remaining, vec![ids[1].as_str(), ids[2].as_str()]);
+    assert!(session.ideas[1].tags.contains(&"coast".to_string()));
+
+    assert!(engine.deduplicate(&pool, &session_id).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_ideas_are_clustered_into_named_themes() {
+    let (pool, project_id) = test_pool().await;
+    let (engine, session_id, ids) = session_with(
+        &pool,
+        &project_id,
+        BrainstormCategory::Plot,
+        &[
+            ("The drowned lighthouse keeper guards a lantern", &["sea"]),
+            ("Dragons sing forgotten songs in the mountain choir", &[]),
+            ("A lighthouse keeper lights the lantern for drowned sailors", &["sea"]),
+            ("The mountain dragons forget their choir songs", &[]),
+            ("Accountants audit the quarterly ledger", &[]),
+        ],
+    )
+    .await;
+
+    let themes = engine.cluster_themes(&pool, &session_id).await.unwrap();
+    assert_eq!(themes.len(), 2);
+    let sea = themes.iter().find(|theme| theme.name == "Sea").unwrap();
+    assert_eq!(sea.idea_ids, vec![ids[0].clone(), ids[2].clone()]);
+    let dragons = themes.iter().find(|theme| theme.name != "Sea").unwrap();
+    assert_eq!(dragons.idea_ids, vec![ids[1].clone(), ids[3].clone()]);
+
+    let session = engine.get_session(&pool, &session_id).await.unwrap().unwrap();
+    assert_eq!(session.ideas[0].theme.as_deref(), Some("Sea"));
+    assert_eq!(session.ideas[4].theme, None);
+    assert_eq!(session.themes(), themes);
+}
+
+#[tokio::test]
+async fn test_keepers_are_exported_to_the_story_bible_once() {
+    let (pool, project_id) = test_pool().await;
+    let (engine, session_id, ids) = session_with(
+        &pool,
+        &project_id,
+        BrainstormCategory::Characters,
+        &[
+            ("A cartographer who maps only places that no longer exist. She sells them to ghosts", &["map"]),
+            ("A ferryman who cannot swim", &[]),
+        ],
+    )
+    .await;
+    engine.mark_as_keeper(&pool, &session_id, &ids[0]).await.unwrap();
+
+    let export = engine.export_keepers_to_story_bible(&pool, &session_id).await.unwrap();
+    assert_eq!(export.characters.len(), 1);
+    assert!(export.world_elements.is_empty() && export.plot_threads.is_empty());
+    let character = &export.characters[0];
+    assert_eq!(character.name, "A cartographer who maps only places that no longer exist");
+    assert!(character.description.as_deref().unwrap().contains("She sells them to ghosts"));
+    let metadata: serde_json::Value = serde_json::from_str(&character.metadata).unwrap();
+    assert_eq!(metadata["brainstorm"]["idea_id"], ids[0].as_str());
+
+    let stored = CharacterOps::get_by_project(&pool, &project_id).await.unwrap();
+    assert_eq!(stored.len(), 1);
+    let idea = BrainstormSessionOps::get_idea(&pool, &session_id, &ids[0]).await.unwrap();
+    let link = idea.exported_to.unwrap();
+    assert_eq!((link.kind, link.id.as_str()), (StoryBibleKind::Character, stored[0].id.as_str()));
+
+    // Exporting again doesn't duplicate the character
+    let again = engine.export_keepers_to_story_bible(&pool, &session_id).await.unwrap();
+    assert!(again.characters.is_empty());
+    assert_eq!(again.already_exported.len(), 1);
+    assert_eq!(CharacterOps::get_by_project(&pool, &project_id).await.unwrap().len(), 1);
+
+    // Plot keepers become plot threads
+    let (engine, session_id, ids) =
+        session_with(&pool, &project_id, BrainstormCategory::Conflicts, &[("Two heirs claim one crown", &[])]).await;
+    engine.mark_as_keeper(&pool, &session_id, &ids[0]).await.unwrap();
+    let export = engine.export_keepers_to_story_bible(&pool, &session_id).await.unwrap();
+    assert_eq!(export.plot_threads.len(), 1);
+    assert_eq!(PlotThreadOps::get_by_project(&pool, &project_id).await.unwrap()[0].name, "Two heirs claim one crown");
+}
@@ -0,0 +1,132 @@
+//! Tests for aborting in-flight AI requests and background tasks
+
+use crate::ai::{
+    AIContext, AIProvider, AIProviderManager, CancellationToken, MockProvider, RouteTarget, RoutingTable,
+    ScriptedResponse, WritingFeature,
+};
+use crate::background::{BackgroundTaskManager, Task, TaskPriority, TaskProcessor, TaskStatus, TaskType};
+use crate::error::{Result, StoryWeaverError};
+use futures_util::StreamExt;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+fn cancel_after(token: &CancellationToken, delay: Duration) {
+    let token = token.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(delay).await;
+        token.cancel();
+    });
+}
+
+#[tokio::test]
+async fn test_cancelled_request_returns_promptly_without_failover() {
+    let slow = MockProvider::new()
+        .with_name("primary")
+        .on(&WritingFeature::Write, ScriptedResponse::text("too late").with_latency(Duration::from_secs(30)));
+    let backup = Arc::new(MockProvider::new().with_name("backup"));
+
+    let mut manager = AIProviderManager::new();
+    manager.register_provider("primary".to_string(), Arc::new(slow));
+    manager.register_provider("backup".to_string(), backup.clone());
+    manager.set_default_provider("primary".to_string());
+    manager.set_routing_table(RoutingTable {
+        fallback_chain: vec![RouteTarget::new("primary"), RouteTarget::new("backup")],
+        ..RoutingTable::default()
+    });
+
+    let token = CancellationToken::new();
+    let context = AIContext {
+        cancellation: Some(token.clone()),
+        ..AIContext::default()
+    };
+    cancel_after(&token, Duration::from_millis(20));
+
+    let started = Instant::now();
+    let error = manager.generate_text("Begin", &context).await.unwrap_err();
+
+    assert!(matches!(error, StoryWeaverError::AIRequestCancelled { .. }));
+    assert!(started.elapsed() < Duration::from_secs(5));
+    assert!(backup.calls().is_empty());
+}
+
+#[tokio::test]
+async fn test_cancelled_stream_stops_yielding_deltas() {
+    let provider = MockProvider::new().on(
+        &WritingFeature::Write,
+        ScriptedResponse::chunks(&["The ", "lamp ", "guttered ", "out."]).with_chunk_delay(Duration::from_millis(50)),
+    );
+    let mut manager = AIProviderManager::new();
+    manager.register_provider("mock".to_string(), Arc::new(provider));
+    manager.set_default_provider("mock".to_string());
+
+    let token = CancellationToken::new();
+    let context = AIContext {
+        cancellation: Some(token.clone()),
+        ..AIContext::default()
+    };
+
+    let mut chunks = manager.generate_text_stream("Begin", &context).await.unwrap();
+    assert_eq!(chunks.next().await.unwrap().unwrap(), "The ");
+    token.cancel();
+    assert!(chunks.next().await.is_none());
+}
+
+/// Holds the task lock for the whole run, like the AI task processor
+struct StallingProcessor;
+
+#[async_trait::async_trait]
+impl TaskProcessor for StallingProcessor {
+    async fn process_task(&self, task: Arc<Mutex<Task>>) -> Result<()> {
+        let _task = task.lock().await;
+        tokio::time::sleep(Duration::from_secs(30)).await;
+        Ok(())
+    }
+
+    fn can_process(&self, task_type: &TaskType) -> bool {
+        matches!(task_type, TaskType::AIGeneration)
+    }
+}
+
+#[tokio::test]
+async fn test_running_background_task_can_be_cancelled() {
+    let manager = BackgroundTaskManager::new(2, 10);
+    manager.register_processor(Arc::new(StallingProcessor)).await;
+    manager.start().await.unwrap();
+
+    let task = Task::new(
+        TaskType::AIGeneration,
+        "Summarize chapter".to_string(),
+        TaskPriority::Normal,
+        true,
+        None,
+        None,
+        None,
+    );
+    let task_id = manager.enqueue_task(task).await.unwrap();
+
+    // Wait for the processor to pick the task up
+    let deadline = Instant::now() + Duration::from_secs(5);
+    loop {
+        let task = manager.get_task(&task_id).await.unwrap();
+        if task.try_lock().is_err() {
+            break;
+        }
+        assert!(Instant::now() < deadline, "task never started");
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+
+    manager.cancel_task(&task_id).await.unwrap();
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    loop {
+        let cancelled = manager.get_tasks_by_status(TaskStatus::Cancelled).await;
+        if cancelled.iter().any(|task| task.id == task_id) {
+            break;
+        }
+        assert!(Instant::now() < deadline, "task was not cancelled");
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+
+    manager.stop().await.unwrap();
+}
@@ -0,0 +1,150 @@
+//! Tests for whole-chapter drafting from outline scenes
+
+use crate::ai::{create_chapter_draft, run_chapter_draft, MockProvider, ScriptedResponse, WritingFeature, SCENE_BREAK};
+use crate::database::models::{Character, CharacterRole, Document, DocumentType, Outline, Scene};
+use crate::database::operations::{
+    ChapterDraftOps, ChapterDraftStatus, CharacterOps, DocumentOps, DocumentVersionOps, OutlineOps, SceneOps,
+};
+use crate::error::StoryWeaverError;
+use crate::tests::test_project;
+use sqlx::{Pool, Sqlite};
+
+async fn test_pool() -> (Pool<Sqlite>, String) {
+    let pool = crate::tests::test_pool().await;
+    let project_id = test_project(&pool, "Saga").await;
+    (pool, project_id)
+}
+
+/// An outline for chapter 3 with `scenes` scenes, each estimated at 1.5 credits
+async fn outline_with_scenes(pool: &Pool<Sqlite>, project_id: &str, scenes: i32) -> (Outline, Vec<Scene>) {
+    let mut outline = Outline::new(project_id.to_string(), Some(3));
+    outline.title = Some("The Flooded Archive".to_string());
+    outline.tense = Some("past".to_string());
+    let outline = OutlineOps::create(pool, outline).await.unwrap();
+
+    let mut created = Vec::new();
+    for number in 1..=scenes {
+        let mut scene = Scene::new(outline.id.clone(), number);
+        scene.title = Some(format!("Scene {}", number));
+        scene.summary = Some(format!("Things happen in scene {}", number));
+        scene.credit_estimate = Some(1.5);
+        created.push(SceneOps::create(pool, scene).await.unwrap());
+    }
+    (outline, created)
+}
+
+/// Scripted prose and recap replies for the given scene numbers, in order
+fn scene_replies(provider: MockProvider, scenes: &[i32]) -> MockProvider {
+    scenes.iter().fold(provider, |provider, number| {
+        provider
+            .on(&WritingFeature::Write, ScriptedResponse::text(&format!("Prose of scene {}.", number)))
+            .on(&WritingFeature::Write, ScriptedResponse::text(&format!("Recap of scene {}.", number)))
+    })
+}
+
+#[tokio::test]
+async fn test_failed_draft_resumes_at_the_next_scene() {
+    let (pool, project_id) = test_pool().await;
+    let (outline, scenes) = outline_with_scenes(&pool, &project_id, 3).await;
+    let draft = create_chapter_draft(&pool, &outline.id, None).await.unwrap();
+    assert_eq!(draft.scene_count, 3);
+    assert_eq!(draft.credit_estimate, Some(4.5));
+
+    // Scene 2 fails after scene 1 is checkpointed
+    let failing = scene_replies(MockProvider::new(), &[1]).on(&WritingFeature::Write, ScriptedResponse::error(500, "overloaded"));
+    let mut progress = Vec::new();
+    let result = run_chapter_draft(&pool, &failing, &draft.id, None, &mut |done, total| progress.push((done, total))).await;
+    assert!(result.is_err());
+    assert_eq!(progress, vec![(1, 3)]);
+
+    let failed = ChapterDraftOps::get(&pool, &draft.id).await.unwrap();
+    assert_eq!(failed.status, ChapterDraftStatus::Failed);
+    assert!(failed.error_message.is_some());
+    let checkpoints = ChapterDraftOps::get_scenes(&pool, &draft.id).await.unwrap();
+    assert_eq!(checkpoints.len(), 1);
+    assert_eq!(checkpoints[0].scene_id, scenes[0].id);
+    assert_eq!(checkpoints[0].summary, "Recap of scene 1.");
+
+    // Resuming drafts only scenes 2 and 3, starting from scene 1's recap
+    let provider = scene_replies(MockProvider::new(), &[2, 3]);
+    let mut progress = Vec::new();
+    let done = run_chapter_draft(&pool, &provider, &draft.id, None, &mut |done, total| progress.push((done, total)))
+        .await
+        .unwrap();
+    assert_eq!(progress, vec![(2, 3), (3, 3)]);
+    let calls = provider.calls();
+    assert_eq!(calls.len(), 4);
+    assert!(calls[0].input.contains("Write scene 2 of 3"));
+    assert!(calls[0].input.contains("The previous scene: Recap of scene 1."));
+    assert!(calls[1].input.contains("Prose of scene 2."));
+    assert!(calls[2].input.contains("The previous scene: Recap of scene 2."));
+
+    // The chapter becomes a new document with the draft as its first version
+    assert_eq!(done.status, ChapterDraftStatus::Completed);
+    let document = DocumentOps::get_by_id(&pool, done.document_id.as_deref().unwrap()).await.unwrap().unwrap();
+    assert_eq!(document.title, "The Flooded Archive");
+    let expected = ["Prose of scene 1.", "Prose of scene 2.", "Prose of scene 3."].join(SCENE_BREAK);
+    assert_eq!(document.content, expected);
+    let version = DocumentVersionOps::get_by_id(&pool, done.version_id.as_deref().unwrap()).await.unwrap().unwrap();
+    assert_eq!(version.content, expected);
+    assert_eq!(version.version_number, 1);
+
+    // Running a completed draft again changes nothing
+    let again = run_chapter_draft(&pool, &provider, &draft.id, None, &mut |_, _| {}).await.unwrap();
+    assert_eq!(again.version_id, done.version_id);
+    assert_eq!(provider.calls().len(), 4);
+}
+
+#[tokio::test]
+async fn test_scene_settings_shape_each_prompt_and_existing_document_gets_a_version() {
+    let (pool, project_id) = test_pool().await;
+    let (outline, mut scenes) = outline_with_scenes(&pool, &project_id, 2).await;
+    let mara = CharacterOps::create(&pool, Character::new(project_id.clone(), "Mara".to_string(), CharacterRole::Protagonist))
+        .await
+        .unwrap();
+
+    scenes[0].pov = Some("first person".to_string());
+    scenes[0].character_pov_ids = serde_json::json!([mara.id]).to_string();
+    scenes[0].extra_instructions = Some("End on the sound of water".to_string());
+    scenes[0].word_count_estimate = Some(800);
+    SceneOps::update(&pool, scenes[0].clone()).await.unwrap();
+
+    let mut document = Document::new(project_id.clone(), "Chapter Three".to_string(), DocumentType::Chapter);
+    document.content = "My own opening.".to_string();
+    let document = DocumentOps::create(&pool, document).await.unwrap();
+
+    let draft = create_chapter_draft(&pool, &outline.id, Some(document.id.clone())).await.unwrap();
+    let provider = scene_replies(MockProvider::new(), &[1, 2]);
+    let done = run_chapter_draft(&pool, &provider, &draft.id, None, &mut |_, _| {}).await.unwrap();
+
+    let first = &provider.calls()[0].input;
+    assert!(first.contains("Write scene 1 of 2 of the chapter \"The Flooded Archive\""));
+    assert!(first.contains("This is the chapter's opening scene."));
+    assert!(first.contains("Point of view: first person"));
+    assert!(first.contains("Mara"));
+    assert!(first.contains("Tense: past"));
+    assert!(first.contains("about 800 words"));
+    assert!(first.contains("End on the sound of water"));
+    let second = &provider.calls()[2].input;
+    assert!(!second.contains("first person"));
+    assert!(!second.contains("End on the sound of water"));
+
+    // The writer's text is left alone; the draft is a version to restore
+    assert_eq!(done.document_id.as_deref(), Some(document.id.as_str()));
+    let stored = DocumentOps::get_by_id(&pool, &document.id).await.unwrap().unwrap();
+    assert_eq!(stored.content, "My own opening.");
+    let versions = DocumentVersionOps::get_versions(&pool, &document.id).await.unwrap();
+    assert_eq!(versions.len(), 1);
+    assert_eq!(versions[0].content, ["Prose of scene 1.", "Prose of scene 2."].join(SCENE_BREAK));
+    assert_eq!(versions[0].created_by.as_deref(), Some("chapter_draft"));
+}
+
+#[tokio::test]
+async fn test_outline_without_scenes_cannot_be_drafted() {
+    let (pool, project_id) = test_pool().await;
+    let (outline, _) = outline_with_scenes(&pool, &project_id, 0).await;
+
+    let err = create_chapter_draft(&pool, &outline.id, None).await.unwrap_err();
+    assert!(matches!(err, StoryWeaverError::InputValidation { .. }));
+    assert!(ChapterDraftOps::get_by_outline(&pool, &outline.id).await.unwrap().is_empty());
+}
@@ -0,0 +1,172 @@
+//! Tests for the typed character relationship graph
+
+use crate::database::models::{Character, CharacterRole, Document, DocumentType};
+use crate::database::operations::{
+    CharacterOps, CharacterRelationship, CharacterRelationshipOps, DocumentOps, RelationshipType,
+};
+use crate::tests::test_project;
+use sqlx::{Pool, Sqlite};
+
+async fn test_pool() -> (Pool<Sqlite>, String) {
+    let pool = crate::tests::test_pool().await;
+    let project_id = test_project(&pool, "Saga").await;
+    (pool, project_id)
+}
+
+async fn character(pool: &Pool<Sqlite>, project_id: &str, name: &str) -> String {
+    let character = Character::new(project_id.to_string(), name.to_string(), CharacterRole::Supporting);
+    CharacterOps::create(pool, character).await.unwrap().id
+}
+
+async fn document(pool: &Pool<Sqlite>, project_id: &str, document_type: DocumentType, parent_id: Option<&str>, order_index: i32) -> String {
+    let mut document = Document::new(project_id.to_string(), format!("Part {}", order_index), document_type);
+    document.parent_id = parent_id.map(str::to_string);
+    document.order_index = order_index;
+    DocumentOps::create(pool, document).await.unwrap().id
+}
+
+struct Cast {
+    mara: String,
+    ivo: String,
+    pell: String,
+    tam: String,
+    chapters: Vec<String>,
+    scene: String,
+}
+
+/// Mara and Ivo are friends until chapter 2, where they become rivals. Ivo
+/// mentors Pell throughout; Tam knows no one.
+async fn cast(pool: &Pool<Sqlite>, project_id: &str) -> Cast {
+    let mut chapters = Vec::new();
+    for i in 0..3 {
+        chapters.push(document(pool, project_id, DocumentType::Chapter, None, i).await);
+    }
+    let scene = document(pool, project_id, DocumentType::Scene, Some(&chapters[1]), 10).await;
+    let cast = Cast {
+        mara: character(pool, project_id, "Mara").await,
+        ivo: character(pool, project_id, "Ivo").await,
+        pell: character(pool, project_id, "Pell \"Quill\"").await,
+        tam: character(pool, project_id, "Tam").await,
+        chapters,
+        scene,
+    };
+
+    let friends = CharacterRelationship::new(project_id, &cast.mara, &cast.ivo, RelationshipType::Friend);
+    let friends = CharacterRelationshipOps::create(pool, friends).await.unwrap();
+    CharacterRelationshipOps::change(pool, &friends.id, &cast.chapters[1], RelationshipType::Rival, None, None)
+        .await
+        .unwrap();
+    let mut mentor = CharacterRelationship::new(project_id, &cast.ivo, &cast.pell, RelationshipType::Mentor);
+    mentor.label = Some("teaches swordcraft".to_string());
+    CharacterRelationshipOps::create(pool, mentor).await.unwrap();
+    cast
+}
+
+#[tokio::test]
+async fn test_relationships_are_read_at_a_point_in_the_story() {
+    let (pool, project_id) = test_pool().await;
+    let cast = cast(&pool, &project_id).await;
+
+    let types_at = |document_id: Option<String>| {
+        let pool = pool.clone();
+        let project_id = project_id.clone();
+        let mara = cast.mara.clone();
+        async move {
+            CharacterRelationshipOps::for_character_at(&pool, &project_id, &mara, document_id.as_deref())
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|r| r.relationship_type)
+                .collect::<Vec<_>>()
+        }
+    };
+    assert_eq!(types_at(Some(cast.chapters[0].clone())).await, vec![RelationshipType::Friend]);
+    // A scene counts as the chapter it's in
+    assert_eq!(types_at(Some(cast.scene.clone())).await, vec![RelationshipType::Rival]);
+    assert_eq!(types_at(None).await, vec![RelationshipType::Rival]);
+
+    let all = CharacterRelationshipOps::get_by_project(&pool, &project_id).await.unwrap();
+    assert_eq!(all.len(), 3);
+    assert_eq!(all[0].ends_in_chapter_id.as_deref(), Some(cast.chapters[1].as_str()));
+    assert_eq!(all[1].starts_in_chapter_id.as_deref(), Some(cast.chapters[1].as_str()));
+    assert!(all[1].mutual);
+    assert!(!all[2].mutual);
+
+    // An ended relationship can't change again
+    assert!(CharacterRelationshipOps::change(&pool, &all[0].id, &cast.chapters[2], RelationshipType::Enemy, None, None)
+        .await
+        .is_err());
+
+    // Deleting a character takes its relationships with it
+    CharacterOps::delete(&pool, &cast.pell).await.unwrap();
+    assert_eq!(CharacterRelationshipOps::get_by_project(&pool, &project_id).await.unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn test_path_between_characters_follows_relationships_either_way() {
+    let (pool, project_id) = test_pool().await;
+    let cast = cast(&pool, &project_id).await;
+
+    let path = CharacterRelationshipOps::find_path(&pool, &project_id, &cast.pell, &cast.mara, None)
+        .await
+        .unwrap()
+        .unwrap();
+    let hops: Vec<(&str, &str)> = path.iter().map(|s| (s.from_character_id.as_str(), s.to_character_id.as_str())).collect();
+    assert_eq!(hops, vec![(cast.pell.as_str(), cast.ivo.as_str()), (cast.ivo.as_str(), cast.mara.as_str())]);
+    assert_eq!(path[0].relationship.relationship_type, RelationshipType::Mentor);
+    assert_eq!(path[1].relationship.relationship_type, RelationshipType::Rival);
+
+    let early = CharacterRelationshipOps::find_path(&pool, &project_id, &cast.pell, &cast.mara, Some(&cast.chapters[0]))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(early[1].relationship.relationship_type, RelationshipType::Friend);
+
+    assert!(CharacterRelationshipOps::find_path(&pool, &project_id, &cast.mara, &cast.tam, None)
+        .await
+        .unwrap()
+        .is_none());
+}
+
+#[tokio::test]
+async fn test_invalid_relationships_are_rejected() {
+    let (pool, project_id) = test_pool().await;
+    let cast = cast(&pool, &project_id).await;
+
+    let own = CharacterRelationship::new(&project_id, &cast.tam, &cast.tam, RelationshipType::Friend);
+    assert!(CharacterRelationshipOps::create(&pool, own).await.is_err());
+
+    let mut backwards = CharacterRelationship::new(&project_id, &cast.tam, &cast.mara, RelationshipType::Ally);
+    backwards.starts_in_chapter_id = Some(cast.chapters[2].clone());
+    backwards.ends_in_chapter_id = Some(cast.chapters[0].clone());
+    assert!(CharacterRelationshipOps::create(&pool, backwards).await.is_err());
+
+    // Relationships are bounded by chapters, not scenes
+    let mut in_scene = CharacterRelationship::new(&project_id, &cast.tam, &cast.mara, RelationshipType::Ally);
+    in_scene.starts_in_chapter_id = Some(cast.scene.clone());
+    assert!(CharacterRelationshipOps::create(&pool, in_scene).await.is_err());
+
+    let (_, other_project) = test_pool().await;
+    let stranger = CharacterRelationship::new(&other_project, &cast.tam, &cast.mara, RelationshipType::Ally);
+    assert!(CharacterRelationshipOps::create(&pool, stranger).await.is_err());
+}
+
+#[tokio::test]
+async fn test_graph_exports_as_dot_and_json() {
+    let (pool, project_id) = test_pool().await;
+    let cast = cast(&pool, &project_id).await;
+
+    let graph = CharacterRelationshipOps::graph(&pool, &project_id, None).await.unwrap();
+    assert_eq!(graph.nodes.len(), 4);
+    assert_eq!(graph.edges.len(), 2);
+
+    let dot = graph.to_dot();
+    assert!(dot.starts_with("digraph relationships {\n"));
+    assert!(dot.contains(&format!("\"{}\" [label=\"Pell \\\"Quill\\\"\"];", cast.pell)));
+    assert!(dot.contains(&format!("\"{}\" -> \"{}\" [label=\"rival\", type=rival, dir=both];", cast.mara, cast.ivo)));
+    assert!(dot.contains(&format!("\"{}\" -> \"{}\" [label=\"teaches swordcraft\", type=mentor];", cast.ivo, cast.pell)));
+
+    let json: serde_json::Value = serde_json::from_str(&graph.to_json().unwrap()).unwrap();
+    assert_eq!(json["nodes"].as_array().unwrap().len(), 4);
+    assert_eq!(json["edges"][1]["relationship_type"], "mentor");
+}
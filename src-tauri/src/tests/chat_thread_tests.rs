@@ -0,0 +1,135 @@
+//! Tests for persistent Quick Chat threads
+
+use crate::ai::chat_memory::{export_to_document, send_message};
+use crate::ai::{MockProvider, ScriptedResponse, WritingFeature};
+use crate::database::models::{Character, CharacterRole, DocumentType};
+use crate::database::operations::{CharacterOps, ChatRole, ChatThread, ChatThreadOps, PinnedElementType, PinnedReference};
+use crate::tests::{test_pool, test_project};
+use sqlx::{Pool, Sqlite};
+
+async fn new_thread(pool: &Pool<Sqlite>) -> ChatThread {
+    let project_id = test_project(pool, "Saga").await;
+    ChatThreadOps::create(pool, ChatThread::new(project_id, None, "Ship names".to_string()))
+        .await
+        .unwrap()
+}
+
+fn quick_chat_inputs(provider: &MockProvider) -> Vec<String> {
+    let feature = WritingFeature::QuickChat.route_key();
+    provider
+        .calls()
+        .into_iter()
+        .filter(|call| call.feature == feature)
+        .map(|call| call.input)
+        .collect()
+}
+
+#[tokio::test]
+async fn test_messages_persist_and_later_turns_see_history() {
+    let pool = test_pool().await;
+    let thread = new_thread(&pool).await;
+    let provider = MockProvider::new()
+        .on(&WritingFeature::QuickChat, ScriptedResponse::text("Call it the Gull."))
+        .on(&WritingFeature::QuickChat, ScriptedResponse::text("Because it never lands."));
+
+    let first = send_message(&pool, &provider, &thread.id, "Name the ship").await.unwrap();
+    assert_eq!(first.reply.content, "Call it the Gull.");
+    assert!(!first.summarized);
+    send_message(&pool, &provider, &thread.id, "Why that name?").await.unwrap();
+
+    let messages = ChatThreadOps::get_messages(&pool, &thread.id).await.unwrap();
+    let roles: Vec<ChatRole> = messages.iter().map(|m| m.role).collect();
+    assert_eq!(roles, vec![ChatRole::User, ChatRole::Assistant, ChatRole::User, ChatRole::Assistant]);
+    assert_eq!(messages[3].content, "Because it never lands.");
+
+    let inputs = quick_chat_inputs(&provider);
+    assert_eq!(inputs[0], "Name the ship");
+    assert!(inputs[1].contains("Assistant: Call it the Gull."));
+    assert!(inputs[1].ends_with("User: Why that name?"));
+}
+
+#[tokio::test]
+async fn test_long_threads_fold_into_a_summary() {
+    let pool = test_pool().await;
+    let thread = new_thread(&pool).await;
+    let provider = MockProvider::new()
+        .with_context_window(80)
+        .on(&WritingFeature::Write, ScriptedResponse::text("They named the ship the Gull."))
+        .on(
+            &WritingFeature::QuickChat,
+            ScriptedResponse::text("A longer reply that keeps the conversation going for a while longer."),
+        );
+
+    let mut summarized = false;
+    for turn in 0..6 {
+        let message = format!("Question number {} about the ship, its crew and the harbour they sail from", turn);
+        summarized |= send_message(&pool, &provider, &thread.id, &message).await.unwrap().summarized;
+    }
+    assert!(summarized);
+
+    let stored = ChatThreadOps::get_by_id(&pool, &thread.id).await.unwrap();
+    assert_eq!(stored.summary, "They named the ship the Gull.");
+    assert!(stored.summarized_count > 0);
+    // The full history is kept even though the prompt no longer carries it
+    assert_eq!(ChatThreadOps::get_messages(&pool, &thread.id).await.unwrap().len(), 12);
+
+    let last = quick_chat_inputs(&provider).pop().unwrap();
+    assert!(last.contains("Summary of earlier conversation:\nThey named the ship the Gull."));
+    assert!(!last.contains("Question number 0"));
+    assert!(last.ends_with("User: Question number 5 about the ship, its crew and the harbour they sail from"));
+}
+
+#[tokio::test]
+async fn test_pinned_references_are_sent_with_each_message() {
+    let pool = test_pool().await;
+    let thread = new_thread(&pool).await;
+    let mut captain = Character::new(thread.project_id.clone(), "Mara".to_string(), CharacterRole::Protagonist);
+    captain.description = Some("Captain of the Gull".to_string());
+    let captain = CharacterOps::create(&pool, captain).await.unwrap();
+    let pins = vec![PinnedReference { element_type: PinnedElementType::Character, element_id: captain.id }];
+    ChatThreadOps::set_pinned_references(&pool, &thread.id, &pins).await.unwrap();
+
+    let provider = MockProvider::new().on(&WritingFeature::QuickChat, ScriptedResponse::text("She would stay."));
+    send_message(&pool, &provider, &thread.id, "Would Mara abandon ship?").await.unwrap();
+
+    let input = quick_chat_inputs(&provider).pop().unwrap();
+    assert!(input.contains("Pinned story bible references:\n- Mara (character): Captain of the Gull"));
+    assert!(input.ends_with("User: Would Mara abandon ship?"));
+}
+
+#[tokio::test]
+async fn test_branch_copies_messages_up_to_the_chosen_one() {
+    let pool = test_pool().await;
+    let thread = new_thread(&pool).await;
+    let provider = MockProvider::new().on(&WritingFeature::QuickChat, ScriptedResponse::text("Noted."));
+    let first = send_message(&pool, &provider, &thread.id, "Name the ship").await.unwrap();
+    send_message(&pool, &provider, &thread.id, "Now name the captain").await.unwrap();
+
+    let branch = ChatThreadOps::branch(&pool, &thread.id, &first.reply.id, None).await.unwrap();
+    assert_eq!(branch.parent_thread_id.as_deref(), Some(thread.id.as_str()));
+    assert_eq!(branch.title, "Ship names (branch)");
+
+    let copied = ChatThreadOps::get_messages(&pool, &branch.id).await.unwrap();
+    assert_eq!(copied.len(), 2);
+    assert_eq!(copied[0].content, "Name the ship");
+    assert_ne!(copied[0].id, first.user_message.id);
+    assert_eq!(ChatThreadOps::get_messages(&pool, &thread.id).await.unwrap().len(), 4);
+
+    let listed = ChatThreadOps::get_by_project(&pool, &thread.project_id, None).await.unwrap();
+    assert_eq!(listed.len(), 2);
+}
+
+#[tokio::test]
+async fn test_export_creates_a_notes_document() {
+    let pool = test_pool().await;
+    let thread = new_thread(&pool).await;
+    let provider = MockProvider::new().on(&WritingFeature::QuickChat, ScriptedResponse::text("Call it the Gull."));
+    send_message(&pool, &provider, &thread.id, "Name the ship").await.unwrap();
+
+    let document = export_to_document(&pool, &thread.id, None).await.unwrap();
+    assert_eq!(document.project_id, thread.project_id);
+    assert_eq!(document.title, "Ship names");
+    assert!(matches!(document.document_type, DocumentType::Notes));
+    assert!(document.content.contains("**You:** Name the ship"));
+    assert!(document.content.contains("**Assistant:** Call it the Gull."));
+}
@@ -0,0 +1,206 @@
+//! Tests for the manuscript continuity checker
+
+use crate::ai::{MockProvider, ScriptedResponse, WritingFeature};
+use crate::continuity::{check_continuity, Cast, ClaimKind, ContinuityMode};
+use crate::database::models::{Character, CharacterRole, CharacterTrait, Document, DocumentType, Location, LocationType};
+use crate::database::operations::{
+    CharacterOps, CharacterTraitOps, ConflictSeverity, ConflictType, ConsistencyConflict, DocumentOps, LocationOps,
+};
+use crate::tests::test_project;
+use sqlx::{Pool, Sqlite};
+
+async fn test_pool() -> (Pool<Sqlite>, String) {
+    let pool = crate::tests::test_pool().await;
+    let project_id = test_project(&pool, "Saltmarch").await;
+    (pool, project_id)
+}
+
+/// Mara Voss, a green-eyed captain born in the Harbor, and Ivo, who doesn't
+/// know her true name
+async fn create_cast(pool: &Pool<Sqlite>, project_id: &str) -> (Character, Character) {
+    let mara = Character {
+        appearance: Some("Auburn hair and green eyes".to_string()),
+        age: Some(34),
+        ..Character::new(project_id.to_string(), "Mara Voss".to_string(), CharacterRole::Protagonist)
+    };
+    let mara = CharacterOps::create(pool, mara).await.unwrap();
+    let ivo = CharacterOps::create(pool, Character::new(project_id.to_string(), "Ivo".to_string(), CharacterRole::Supporting))
+        .await
+        .unwrap();
+    for (character, name, value) in [
+        (&mara, "Rank", "Captain of the Watch"),
+        (&mara, "Birthplace", "the Harbor"),
+        (&ivo, "Unaware of", "Mara's true name"),
+    ] {
+        let character_trait = CharacterTrait::new(character.id.clone(), name.to_string(), Some(value.to_string()));
+        CharacterTraitOps::create(pool, character_trait).await.unwrap();
+    }
+    for (name, location_type) in [("Harbor", LocationType::City), ("Keep", LocationType::Building)] {
+        LocationOps::create(pool, Location::new(project_id.to_string(), name.to_string(), location_type)).await.unwrap();
+    }
+    (mara, ivo)
+}
+
+async fn create_document(
+    pool: &Pool<Sqlite>,
+    project_id: &str,
+    document_type: DocumentType,
+    order_index: i32,
+    parent_id: Option<&str>,
+    content: &str,
+) -> Document {
+    let document = Document {
+        content: content.to_string(),
+        order_index,
+        parent_id: parent_id.map(str::to_string),
+        ..Document::new(project_id.to_string(), format!("Part {}", order_index + 1), document_type)
+    };
+    DocumentOps::create(pool, document).await.unwrap()
+}
+
+fn find<'a>(conflicts: &'a [ConsistencyConflict], text: &str) -> &'a ConsistencyConflict {
+    conflicts
+        .iter()
+        .find(|conflict| conflict.description.contains(text))
+        .unwrap_or_else(|| panic!("no conflict mentioning {:?} in {:#?}", text, conflicts))
+}
+
+#[test]
+fn test_claims_use_character_offsets_and_skip_other_peoples_features() {
+    let mara = Character::new("p".to_string(), "Captain Mara Voss".to_string(), CharacterRole::Protagonist);
+    let mara_lind = Character::new("p".to_string(), "Mara Lind".to_string(), CharacterRole::Minor);
+    let cast = Cast::new(&[mara.clone(), mara_lind], &[]).unwrap();
+
+    let text = "Zoë looked up. Voss's cold blue eyes met hers. Voss's sister had green eyes. Mara had grey eyes.";
+    let claims = cast.extract("doc", text);
+
+    // "Mara" could be either of them, and the sister's eyes aren't Voss's
+    assert_eq!(claims.len(), 1, "{:?}", claims);
+    let claim = &claims[0];
+    assert_eq!(claim.kind, ClaimKind::EyeColor);
+    assert_eq!(claim.subject_id, mara.id);
+    assert_eq!(claim.value, "blue");
+    assert_eq!(claim.start, 15);
+    let quoted: String = text.chars().skip(claim.start).take(claim.end - claim.start).collect();
+    assert_eq!(quoted, "Voss's cold blue eyes");
+
+    let ages = cast.extract("doc", "The thirty-four-year-old Voss laughed. Voss, 35, did not.");
+    let ages: Vec<&str> = ages.iter().map(|claim| claim.value.as_str()).collect();
+    assert_eq!(ages, vec!["34", "35"]);
+}
+
+#[tokio::test]
+async fn test_rules_flag_prose_that_contradicts_the_story_bible() {
+    let (pool, project_id) = test_pool().await;
+    create_cast(&pool, &project_id).await;
+
+    let first = create_document(
+        &pool,
+        &project_id,
+        DocumentType::Chapter,
+        0,
+        None,
+        "Captain Mara Voss walked the walls of the Keep. Mara's blue eyes swept the harbor. \
+         Lady Mara, they called her in the taverns. Ivo knew that the queen had fled north. \
+         Ivo knew Mara's true name. Ivo's grey eyes gave nothing away. The village of Keep slept below.",
+    )
+    .await;
+    let scene = create_document(
+        &pool,
+        &project_id,
+        DocumentType::Scene,
+        1,
+        Some(&first.id),
+        "Ivo was in the Harbor at dawn. By noon Ivo stood in the Keep. \
+         Mara was at the Harbor. Mara rode to the Keep. Mara was in the Keep.",
+    )
+    .await;
+    let second = create_document(
+        &pool,
+        &project_id,
+        DocumentType::Chapter,
+        2,
+        None,
+        "Mara, 36, had never lost a ship. Mara was born in the Keep. Ivo had brown eyes. \
+         Ivo learned that the queen had fled north.",
+    )
+    .await;
+    // Notes aren't part of the manuscript
+    create_document(&pool, &project_id, DocumentType::Notes, 3, None, "Mara's violet eyes?").await;
+
+    let report = check_continuity(&pool, &project_id, None).await.unwrap();
+    assert_eq!(report.mode, ContinuityMode::Rules);
+    assert_eq!(report.documents_checked, 3);
+    assert_eq!(report.conflicts.len(), 9, "{:#?}", report.conflicts);
+    assert!(report.consistency_score < 1.0);
+
+    let eyes = find(&report.conflicts, "has blue eyes");
+    assert!(matches!(eyes.severity, ConflictSeverity::High));
+    assert_eq!(eyes.affected_projects, vec![project_id.clone()]);
+    assert_eq!(eyes.passages.len(), 1);
+    let passage = &eyes.passages[0];
+    assert_eq!(passage.document_id, first.id);
+    let quoted: String = first.content.chars().skip(passage.start).take(passage.end - passage.start).collect();
+    assert_eq!(quoted, "Mara's blue eyes");
+
+    assert!(matches!(find(&report.conflicts, "calls Mara Voss Lady").severity, ConflictSeverity::Medium));
+    assert!(matches!(find(&report.conflicts, "is 36 in the manuscript").severity, ConflictSeverity::Medium));
+    assert!(matches!(find(&report.conflicts, "born in Keep").severity, ConflictSeverity::High));
+    assert!(matches!(
+        find(&report.conflicts, "calls Keep a village").conflict_type,
+        ConflictType::WorldElementInconsistency
+    ));
+    assert_eq!(find(&report.conflicts, "Ivo has grey or brown eyes").passages.len(), 2);
+    assert!(find(&report.conflicts, "story bible says they don't").description.contains("Mara's true name"));
+
+    let early = find(&report.conflicts, "before learning it");
+    assert!(matches!(early.conflict_type, ConflictType::TimelineConflict));
+    let documents: Vec<&str> = early.passages.iter().map(|p| p.document_id.as_str()).collect();
+    assert_eq!(documents, vec![first.id.as_str(), second.id.as_str()]);
+
+    // Mara rides between her two places; Ivo doesn't
+    let moved = find(&report.conflicts, "without leaving");
+    assert!(moved.description.starts_with("Ivo"));
+    assert!(moved.passages.iter().all(|p| p.document_id == scene.id));
+}
+
+#[tokio::test]
+async fn test_ai_assisted_mode_adds_quoted_claims() {
+    let (pool, project_id) = test_pool().await;
+    create_cast(&pool, &project_id).await;
+    let chapter = create_document(
+        &pool,
+        &project_id,
+        DocumentType::Chapter,
+        0,
+        None,
+        "Mara's blue eyes narrowed.\n\nThe wind tugged at her black hair.",
+    )
+    .await;
+
+    let reply = r#"{"claims": [
+        {"subject": "Mara Voss", "attribute": "hair_color", "value": "black", "quote": "her black hair"},
+        {"subject": "Mara", "attribute": "eye_color", "value": "blue", "quote": "Mara's blue eyes"},
+        {"subject": "Ivo", "attribute": "age", "value": "40", "quote": "Ivo is forty"},
+        {"subject": "Nobody", "attribute": "title", "value": "King", "quote": "Mara's"}
+    ]}"#;
+    let provider = MockProvider::new().on(&WritingFeature::Write, ScriptedResponse::text(reply));
+
+    let report = check_continuity(&pool, &project_id, Some(&provider)).await.unwrap();
+    assert_eq!(report.mode, ContinuityMode::AiAssisted);
+    // The eye color repeats the rules' claim; the age quote isn't in the text
+    assert_eq!(report.claims_found, 2);
+    assert_eq!(report.conflicts.len(), 2, "{:#?}", report.conflicts);
+
+    let hair = find(&report.conflicts, "has black hair");
+    assert!(matches!(hair.severity, ConflictSeverity::Medium));
+    let passage = &hair.passages[0];
+    assert_eq!(passage.document_id, chapter.id);
+    assert_eq!(passage.excerpt, "her black hair");
+    let quoted: String = chapter.content.chars().skip(passage.start).take(passage.end - passage.start).collect();
+    assert_eq!(quoted, "her black hair");
+
+    let calls = provider.calls();
+    assert_eq!(calls.len(), 1);
+    assert!(calls[0].input.contains("Mara Voss"));
+}
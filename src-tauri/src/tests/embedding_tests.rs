@@ -0,0 +1,65 @@
+//! Tests for embedding routing and the offline embedding fallback
+
+use crate::ai::{
+    AIProviderManager, LocalEmbeddingProvider, MockProvider, RouteTarget, RoutingTable, EMBEDDING_ROUTE_KEY,
+    LOCAL_EMBEDDING_MODEL,
+};
+use std::sync::Arc;
+
+fn route_embeddings_to(manager: &AIProviderManager, provider: &str) {
+    let mut table = RoutingTable::default();
+    table.features.insert(EMBEDDING_ROUTE_KEY.to_string(), vec![RouteTarget::new(provider)]);
+    manager.set_routing_table(table);
+}
+
+#[tokio::test]
+async fn test_embeddings_stay_local_without_an_embedding_route() {
+    let cloud = Arc::new(MockProvider::new().with_name("cloud"));
+    let mut manager = AIProviderManager::new();
+    manager.register_provider("cloud".to_string(), cloud.clone());
+    manager.set_default_provider("cloud".to_string());
+
+    let embedding = manager.embed("Fog over the harbor").await.unwrap();
+
+    assert_eq!(embedding.model, LOCAL_EMBEDDING_MODEL);
+    assert_eq!(embedding.vector, LocalEmbeddingProvider::new().embed("Fog over the harbor"));
+    assert!(cloud.calls().is_empty());
+}
+
+#[tokio::test]
+async fn test_routed_provider_serves_embeddings() {
+    let cloud = MockProvider::new()
+        .with_name("cloud")
+        .with_model("embed-large")
+        .on_embedding(vec![0.6, 0.8]);
+    let mut manager = AIProviderManager::new();
+    manager.register_provider("cloud".to_string(), Arc::new(cloud));
+    route_embeddings_to(&manager, "cloud");
+
+    let embedding = manager.embed("Fog over the harbor").await.unwrap();
+
+    assert_eq!(embedding.model, "embed-large");
+    assert_eq!(embedding.vector, vec![0.6, 0.8]);
+}
+
+#[tokio::test]
+async fn test_failing_embedding_routes_fall_back_to_local() {
+    // A strict mock fails unscripted requests; a zero vector is a placeholder
+    let failing = MockProvider::new().with_name("failing").strict();
+    let placeholder = MockProvider::new().with_name("placeholder").on_embedding(vec![0.0; 8]);
+    let mut manager = AIProviderManager::new();
+    manager.register_provider("failing".to_string(), Arc::new(failing));
+    manager.register_provider("placeholder".to_string(), Arc::new(placeholder));
+
+    let mut table = RoutingTable::default();
+    table.features.insert(
+        EMBEDDING_ROUTE_KEY.to_string(),
+        vec![RouteTarget::new("failing"), RouteTarget::new("placeholder")],
+    );
+    manager.set_routing_table(table);
+
+    let embedding = manager.embed("Fog over the harbor").await.unwrap();
+
+    assert_eq!(embedding.model, LOCAL_EMBEDDING_MODEL);
+    assert!(embedding.vector.iter().any(|v| *v != 0.0));
+}
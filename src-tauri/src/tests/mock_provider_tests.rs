@@ -0,0 +1,187 @@
+//! Tests for the scripted mock and record/replay providers
+
+use crate::ai::mock_provider::{input_hash, MockError};
+use crate::ai::{
+    AIContext, AIProvider, AIProviderManager, BrainstormCategory, BrainstormEngine, BrainstormRequest, MockProvider,
+    RecordingProvider, ReplayProvider, RouteTarget, RoutingTable, ScriptedResponse, TextStream, WritingFeature,
+};
+use crate::error::StoryWeaverError;
+use crate::tests::{test_pool, test_project};
+use futures_util::StreamExt;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+#[tokio::test]
+async fn test_input_script_takes_precedence_over_feature_script() {
+    let provider = MockProvider::new()
+        .on(&WritingFeature::Write, ScriptedResponse::text("any prompt"))
+        .on_input(&WritingFeature::Write, "The storm broke", ScriptedResponse::text("that prompt"));
+
+    let context = AIContext::default();
+    assert_eq!(provider.generate_text("The storm broke", &context).await.unwrap(), "that prompt");
+    assert_eq!(provider.generate_text("Something else", &context).await.unwrap(), "any prompt");
+
+    let calls = provider.calls();
+    assert_eq!(calls.len(), 2);
+    assert_eq!(calls[0].feature, "write");
+    assert_eq!(calls[0].input_hash, input_hash("The storm broke"));
+}
+
+#[tokio::test]
+async fn test_scripted_responses_are_served_in_order_and_last_repeats() {
+    let provider = MockProvider::new()
+        .on(&WritingFeature::QuickChat, ScriptedResponse::text("first"))
+        .on(&WritingFeature::QuickChat, ScriptedResponse::text("second"));
+
+    let context = AIContext::default();
+    let mut replies = Vec::new();
+    for _ in 0..3 {
+        replies.push(provider.quick_chat("hi", &context).await.unwrap());
+    }
+
+    assert_eq!(replies, vec!["first", "second", "second"]);
+}
+
+#[tokio::test]
+async fn test_unscripted_requests_echo_deterministically() {
+    let provider = MockProvider::new();
+    let context = AIContext::default();
+
+    let first = provider.expand_text("A door creaked.", &context).await.unwrap();
+    let second = provider.expand_text("A door creaked.", &context).await.unwrap();
+
+    assert_eq!(first, second);
+    assert!(first.contains("expand"));
+    assert_eq!(
+        provider.generate_embedding("harbor lights").await.unwrap(),
+        provider.generate_embedding("harbor lights").await.unwrap()
+    );
+}
+
+#[tokio::test]
+async fn test_streaming_yields_scripted_chunks_then_injected_error() {
+    let mut response = ScriptedResponse::chunks(&["The tide ", "turned"]);
+    response.error = Some(MockError {
+        status_code: 500,
+        message: "connection reset".to_string(),
+        retry_after: None,
+    });
+    let provider = MockProvider::new().on(&WritingFeature::Write, response);
+
+    let mut chunks = provider.generate_text_stream("Begin", &AIContext::default()).await.unwrap();
+
+    assert_eq!(chunks.next().await.unwrap().unwrap(), "The tide ");
+    assert_eq!(chunks.next().await.unwrap().unwrap(), "turned");
+    assert!(chunks.next().await.unwrap().is_err());
+    assert!(chunks.next().await.is_none());
+}
+
+#[tokio::test]
+async fn test_streaming_splits_plain_text_and_applies_latency() {
+    let response = ScriptedResponse::text("Once upon a time")
+        .with_latency(Duration::from_millis(30))
+        .with_chunk_delay(Duration::from_millis(5));
+    let provider = MockProvider::new().on(&WritingFeature::QuickChat, response);
+
+    let started = Instant::now();
+    let chunks = provider.quick_chat_stream("Go on", &AIContext::default()).await.unwrap();
+    let text = TextStream::from_chunks(chunks).await.unwrap();
+
+    assert_eq!(text.content, "Once upon a time");
+    assert!(started.elapsed() >= Duration::from_millis(30));
+    assert!(provider.calls()[0].streamed);
+}
+
+#[tokio::test]
+async fn test_injected_rate_limit_fails_over_in_manager() {
+    let primary = MockProvider::new()
+        .with_name("primary")
+        .on(&WritingFeature::Write, ScriptedResponse::rate_limited(30));
+    let backup = MockProvider::new()
+        .with_name("backup")
+        .on(&WritingFeature::Write, ScriptedResponse::text("from backup"));
+
+    let mut manager = AIProviderManager::new();
+    manager.register_provider("primary".to_string(), Arc::new(primary));
+    manager.register_provider("backup".to_string(), Arc::new(backup));
+    manager.set_default_provider("primary".to_string());
+    manager.set_routing_table(RoutingTable {
+        fallback_chain: vec![RouteTarget::new("primary"), RouteTarget::new("backup")],
+        ..RoutingTable::default()
+    });
+
+    let text = manager.generate_text("Begin", &AIContext::default()).await.unwrap();
+    assert_eq!(text, "from backup");
+}
+
+#[tokio::test]
+async fn test_brainstorm_engine_runs_against_mock() {
+    let provider = MockProvider::new().on(
+        &WritingFeature::Write,
+        ScriptedResponse::text(
+            r#"{"ideas": [
+                {"content": "A lighthouse with no keeper", "tags": ["Lighthouse"]},
+                {"content": "A map drawn in salt"},
+                {"content": "A bell that rings at low tide"}
+            ]}"#,
+        ),
+    );
+
+    let pool = test_pool().await;
+    let project_id = test_project(&pool, "Saga").await;
+
+    let engine = BrainstormEngine::default();
+    let request = BrainstormRequest {
+        project_id,
+        category: BrainstormCategory::Plot,
+        seed_prompt: Some("coastal mystery".to_string()),
+        context: None,
+        num_ideas: 2,
+        creativity_level: 7,
+        focus_areas: Vec::new(),
+    };
+    let session_id = engine.create_session(&pool, request.clone()).await.unwrap();
+    let ideas = engine.generate_ideas(&pool, &session_id, &request, Some(&provider)).await.unwrap();
+
+    let contents: Vec<&str> = ideas.iter().map(|idea| idea.content.as_str()).collect();
+    assert_eq!(contents, vec!["A lighthouse with no keeper", "A map drawn in salt"]);
+    assert!(ideas[0].tags.contains(&"lighthouse".to_string()));
+}
+
+#[tokio::test]
+async fn test_recorded_session_replays_offline() {
+    let live = Arc::new(
+        MockProvider::new()
+            .with_name("openai")
+            .with_model("gpt-4")
+            .on(&WritingFeature::Write, ScriptedResponse::chunks(&["Fog ", "rolled in."]))
+            .on(&WritingFeature::QuickEdit, ScriptedResponse::error(400, "bad request")),
+    );
+    let recorder = RecordingProvider::new(live);
+    let context = AIContext::default();
+
+    let chunks = recorder.generate_text_stream("Begin", &context).await.unwrap();
+    let streamed = TextStream::from_chunks(chunks).await.unwrap();
+    let words = recorder.related_words("fog", &context).await.unwrap();
+    assert!(recorder.quick_edit("text", "tighten").await.is_err());
+
+    let path = std::env::temp_dir().join(format!("storyweaver-fixture-{}.json", uuid::Uuid::new_v4()));
+    recorder.save(&path).unwrap();
+    let replay = ReplayProvider::load_fixture(&path).unwrap();
+    std::fs::remove_file(&path).ok();
+
+    assert_eq!(replay.get_provider_name(), "openai");
+    assert_eq!(replay.get_model_name(), "gpt-4");
+
+    let mut chunks = replay.generate_text_stream("Begin", &context).await.unwrap();
+    assert_eq!(chunks.next().await.unwrap().unwrap(), "Fog ");
+    assert_eq!(chunks.next().await.unwrap().unwrap(), "rolled in.");
+    assert_eq!(replay.generate_text("Begin", &context).await.unwrap(), streamed.content);
+    assert_eq!(replay.related_words("fog", &context).await.unwrap(), words);
+
+    let error = replay.quick_edit("text", "tighten").await.unwrap_err();
+    assert!(matches!(error, StoryWeaverError::AIRequest { status_code: 400, .. }));
+
+    // Replays are strict: requests that were never recorded fail
+    assert!(replay.generate_text("A different prompt", &context).await.is_err());
+}
@@ -3,6 +3,20 @@
 //! This module contains comprehensive test suites for validating the functionality,
 //! security, and performance of all Tauri command handlers and core systems.
 
+/// A fresh in-memory database for one test, with foreign keys enforced so
+/// cascades and dangling references behave as they do in the app
+#[cfg(test)]
+pub async fn test_pool() -> sqlx::Pool<sqlx::Sqlite> {
+    crate::database::connect_test_db().await.expect("test database")
+}
+
+/// Creates a project for test data to belong to and returns its id
+#[cfg(test)]
+pub async fn test_project(pool: &sqlx::Pool<sqlx::Sqlite>, name: &str) -> String {
+    let project = crate::database::models::Project::new(name.to_string(), None);
+    crate::database::operations::ProjectOps::create(pool, project).await.expect("test project").id
+}
+
 #[cfg(test)]
 pub mod integration_commands_tests;
 
@@ -20,3 +34,75 @@ pub mod ai_card_filtering_tests;
 
 #[cfg(test)]
 pub mod critical_workflows_tests;
+
+#[cfg(test)]
+pub mod mock_http_server;
+
+#[cfg(test)]
+pub mod openai_compatible_provider_tests;
+
+#[cfg(test)]
+pub mod ai_routing_tests;
+
+#[cfg(test)]
+pub mod provider_resilience_tests;
+
+#[cfg(test)]
+pub mod mock_provider_tests;
+
+#[cfg(test)]
+pub mod cancellation_tests;
+
+#[cfg(test)]
+pub mod embedding_tests;
+
+#[cfg(test)]
+pub mod semantic_index_tests;
+
+#[cfg(test)]
+pub mod saliency_engine_tests;
+
+#[cfg(test)]
+pub mod structured_output_tests;
+
+#[cfg(test)]
+pub mod tool_calling_tests;
+
+#[cfg(test)]
+pub mod chat_thread_tests;
+
+#[cfg(test)]
+pub mod prompt_template_tests;
+
+#[cfg(test)]
+pub mod ai_budget_tests;
+
+#[cfg(test)]
+pub mod model_pricing_tests;
+
+#[cfg(test)]
+pub mod brainstorm_tests;
+
+#[cfg(test)]
+pub mod chapter_draft_tests;
+
+#[cfg(test)]
+pub mod story_summary_tests;
+
+#[cfg(test)]
+pub mod stylometry_tests;
+
+#[cfg(test)]
+pub mod character_relationship_tests;
+
+#[cfg(test)]
+pub mod timeline_calendar_tests;
+
+#[cfg(test)]
+pub mod continuity_tests;
+
+#[cfg(test)]
+pub mod story_bible_detection_tests;
+
+#[cfg(test)]
+pub mod story_bible_extraction_tests;
@@ -0,0 +1,196 @@
+//! Tests for the model pricing catalog and history cost recomputation
+
+use crate::ai::token_counter::builtin_prices;
+use crate::ai::{recompute_history_costs, BillableUsage, TokenCounter};
+use crate::database::models::{AIGenerationHistory, AIGenerationType};
+use crate::database::operations::{AIHistoryOps, ModelPrice, ModelPricingOps, ANY_MODEL};
+use crate::tests::{test_pool, test_project};
+use chrono::{NaiveDate, TimeZone, Utc};
+use uuid::Uuid;
+
+fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+    NaiveDate::from_ymd_opt(year, month, day).unwrap()
+}
+
+fn close(a: f64, b: f64) -> bool {
+    (a - b).abs() < 1e-9
+}
+
+#[test]
+fn test_prices_apply_from_their_effective_date() {
+    let counter = TokenCounter::from_prices(vec![
+        ModelPrice::new("writer-1", 0.01, 0.02, date(2024, 1, 1)),
+        ModelPrice::new("writer-1", 0.005, 0.01, date(2025, 6, 1)),
+    ]);
+    let usage = BillableUsage::tokens(1_000, 1_000);
+
+    let old = counter.estimate_usage("acme", "writer-1", usage, date(2025, 5, 31));
+    assert!(close(old.total_cost, 0.03));
+    assert_eq!(old.priced_from, Some(date(2024, 1, 1)));
+
+    let new = counter.estimate_usage("acme", "writer-1", usage, date(2025, 6, 1));
+    assert!(close(new.total_cost, 0.015));
+
+    // Nothing was priced yet
+    let early = counter.estimate_usage("acme", "writer-1", usage, date(2023, 12, 31));
+    assert_eq!(early.total_cost, 0.0);
+    assert!(early.warning.is_some());
+}
+
+#[test]
+fn test_closest_model_and_provider_price_wins() {
+    let counter = TokenCounter::new();
+    let usage = BillableUsage::tokens(1_000, 0);
+    let today = Utc::now().date_naive();
+
+    // Versioned names use the base model's price, the longest match first
+    let versioned = counter.estimate_usage("openai", "gpt-4-0613", usage, today);
+    assert!(close(versioned.total_cost, 0.03));
+    let turbo = counter.estimate_usage("openai", "gpt-4-turbo-preview", usage, today);
+    assert!(close(turbo.total_cost, 0.01));
+
+    // Local providers are free for any model, without a warning
+    let local = counter.estimate_usage("Ollama", "llama3:8b", usage, today);
+    assert_eq!(local.total_cost, 0.0);
+    assert!(local.warning.is_none());
+
+    // A provider's own price beats the price for any provider
+    let mut prices = builtin_prices();
+    prices.push(ModelPrice::new("gpt-4", 0.02, 0.04, date(2024, 1, 1)).for_provider("azure"));
+    let counter = TokenCounter::from_prices(prices);
+    assert!(close(counter.estimate_usage("azure", "gpt-4", usage, today).total_cost, 0.02));
+    assert!(close(counter.estimate_usage("openai", "gpt-4", usage, today).total_cost, 0.03));
+}
+
+#[test]
+fn test_unknown_models_warn_instead_of_guessing() {
+    let cost = TokenCounter::new().estimate("acme", "mystery-model", 5_000, 5_000);
+    assert_eq!(cost.total_cost, 0.0);
+    assert!(cost.priced_from.is_none());
+    assert!(cost.warning.unwrap().contains("mystery-model"));
+}
+
+#[test]
+fn test_cached_input_and_images_are_billed_at_their_prices() {
+    let mut price = ModelPrice::new("writer-1", 0.01, 0.02, date(2024, 1, 1));
+    price.cached_input_cost_per_1k = Some(0.001);
+    price.image_cost = Some(0.04);
+    let counter = TokenCounter::from_prices(vec![price]);
+
+    let usage = BillableUsage { input_tokens: 2_000, cached_input_tokens: 1_000, output_tokens: 0, images: 2 };
+    let cost = counter.estimate_usage("acme", "writer-1", usage, date(2024, 2, 1));
+    assert!(close(cost.input_cost, 0.011));
+    assert!(close(cost.output_cost, 0.08));
+
+    // Without a cached price, cached input costs the same as other input
+    let counter = TokenCounter::from_prices(vec![ModelPrice::new("writer-1", 0.01, 0.02, date(2024, 1, 1))]);
+    let cost = counter.estimate_usage("acme", "writer-1", usage, date(2024, 2, 1));
+    assert!(close(cost.input_cost, 0.02));
+    assert_eq!(cost.output_cost, 0.0);
+}
+
+#[tokio::test]
+async fn test_builtin_seeding_keeps_edited_prices() {
+    let pool = test_pool().await;
+    let builtins = builtin_prices();
+    assert_eq!(ModelPricingOps::seed_builtin(&pool, &builtins).await.unwrap(), builtins.len());
+
+    let mut edited = builtins.iter().find(|p| p.model == "gpt-4").unwrap().clone();
+    edited.input_cost_per_1k = 0.025;
+    edited.source = "user".to_string();
+    let saved = ModelPricingOps::upsert(&pool, &edited).await.unwrap();
+    assert_eq!(saved.source, "user");
+
+    assert_eq!(ModelPricingOps::seed_builtin(&pool, &builtins).await.unwrap(), 0);
+    let prices = ModelPricingOps::list(&pool).await.unwrap();
+    assert_eq!(prices.len(), builtins.len());
+    let gpt4 = prices.iter().find(|p| p.model == "gpt-4").unwrap();
+    assert_eq!(gpt4.input_cost_per_1k, 0.025);
+    assert!(prices.iter().any(|p| p.model == ANY_MODEL && p.provider.as_deref() == Some("ollama")));
+
+    ModelPricingOps::delete(&pool, &gpt4.id).await.unwrap();
+    assert!(ModelPricingOps::delete(&pool, &gpt4.id).await.is_err());
+}
+
+#[tokio::test]
+async fn test_import_round_trips_export_and_is_all_or_nothing() {
+    let pool = test_pool().await;
+    let mut price = ModelPrice::new("writer-1", 0.01, 0.02, date(2025, 1, 1)).for_provider("acme");
+    price.cached_input_cost_per_1k = Some(0.002);
+    ModelPricingOps::upsert(&pool, &price).await.unwrap();
+
+    let exported = serde_json::to_string(&ModelPricingOps::list(&pool).await.unwrap()).unwrap();
+    let other = test_pool().await;
+    let imported: Vec<ModelPrice> = serde_json::from_str(&exported).unwrap();
+    assert_eq!(ModelPricingOps::import(&other, &imported, true).await.unwrap(), 1);
+    let copied = ModelPricingOps::list(&other).await.unwrap();
+    assert_eq!(copied.len(), 1);
+    assert_eq!(copied[0].provider.as_deref(), Some("acme"));
+    assert_eq!(copied[0].cached_input_cost_per_1k, Some(0.002));
+    assert_eq!(copied[0].effective_from, date(2025, 1, 1));
+
+    // Hand-written JSON only needs the model, prices and date
+    let minimal: Vec<ModelPrice> =
+        serde_json::from_str(r#"[{"model": "writer-2", "input_cost_per_1k": 0.1, "output_cost_per_1k": 0.2, "effective_from": "2025-03-01"}]"#)
+            .unwrap();
+    assert_eq!(minimal[0].currency, "USD");
+
+    // One bad price rejects the whole import
+    let bad = ModelPrice::new("writer-3", -1.0, 0.0, date(2025, 1, 1));
+    assert!(ModelPricingOps::import(&other, &[minimal[0].clone(), bad], false).await.is_err());
+    assert_eq!(ModelPricingOps::list(&other).await.unwrap().len(), 1);
+
+    ModelPricingOps::import(&other, &minimal, false).await.unwrap();
+    assert_eq!(ModelPricingOps::list(&other).await.unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn test_history_costs_are_recomputed_at_historic_prices() {
+    let pool = test_pool().await;
+    let project_id = test_project(&pool, "Saga").await;
+    let counter = TokenCounter::from_prices(vec![
+        ModelPrice::new("writer-1", 0.01, 0.02, date(2024, 1, 1)),
+        ModelPrice::new("writer-1", 0.001, 0.002, date(2025, 1, 1)),
+    ]);
+
+    let mut ids = Vec::new();
+    for (model, created_at) in [
+        ("writer-1", Utc.with_ymd_and_hms(2024, 6, 1, 12, 0, 0).unwrap()),
+        ("writer-1", Utc.with_ymd_and_hms(2025, 6, 1, 12, 0, 0).unwrap()),
+        ("mystery-model", Utc.with_ymd_and_hms(2025, 6, 1, 12, 0, 0).unwrap()),
+    ] {
+        let record = AIGenerationHistory {
+            id: Uuid::new_v4().to_string(),
+            project_id: project_id.clone(),
+            document_id: None,
+            generation_type: AIGenerationType::Outline,
+            provider: "acme".to_string(),
+            model: model.to_string(),
+            prompt: "Outline the story of the lighthouse keeper".to_string(),
+            response: "1. The lamp fails. 2. A ship is lost.".to_string(),
+            token_count: 20,
+            cost_estimate: Some(9.99),
+            context_used: "{}".to_string(),
+            route_taken: None,
+            prompt_template: None,
+            prompt_template_version: None,
+            created_at,
+        };
+        ids.push(AIHistoryOps::create(&pool, record).await.unwrap().id);
+    }
+
+    let outcome = recompute_history_costs(&pool, &project_id, &counter).await.unwrap();
+    assert_eq!(outcome.updated, 2);
+    assert_eq!(outcome.unpriced, 1);
+    assert_eq!(outcome.unpriced_models, vec!["mystery-model".to_string()]);
+
+    let cost = |id: &str| {
+        let pool = pool.clone();
+        let id = id.to_string();
+        async move { AIHistoryOps::get_by_id(&pool, &id).await.unwrap().unwrap().cost_estimate.unwrap() }
+    };
+    let (older, newer) = (cost(&ids[0]).await, cost(&ids[1]).await);
+    assert!(older > 0.0);
+    assert!(close(older, newer * 10.0));
+    assert_eq!(cost(&ids[2]).await, 9.99);
+}
@@ -0,0 +1,264 @@
+//! Tests for the OpenAI-compatible provider against a local mock server
+
+use crate::ai::{AIContext, AIProvider, AIProviderManager, OpenAICompatibleConfig, TextStream};
+use crate::database::operations::AIProviderOps;
+use futures_util::StreamExt;
+use crate::tests::mock_http_server::{MockHttpServer, MockResponse};
+use crate::tests::test_pool;
+use serde_json::json;
+
+fn local_config(base_url: String, api_key: Option<&str>) -> OpenAICompatibleConfig {
+    OpenAICompatibleConfig {
+        name: "ollama".to_string(),
+        display_name: "Ollama".to_string(),
+        base_url,
+        api_key: api_key.map(|k| k.to_string()),
+        model: "llama3".to_string(),
+        embedding_model: Some("nomic-embed-text".to_string()),
+        context_window: Some(8192),
+    }
+}
+
+fn chat_completion(content: &str) -> MockResponse {
+    MockResponse::json(200, json!({
+        "id": "chatcmpl-local",
+        "choices": [{ "message": { "role": "assistant", "content": content }, "finish_reason": "stop" }],
+        "usage": { "prompt_tokens": 12, "completion_tokens": 5, "total_tokens": 17 }
+    }))
+}
+
+#[tokio::test]
+async fn test_generate_text_uses_configured_base_url_without_key() {
+    let server = MockHttpServer::start(vec![
+        ("POST", "/v1/chat/completions", chat_completion("The lighthouse keeper woke.")),
+    ]).await;
+
+    let provider = local_config(server.base_url(), None).into_provider();
+    let text = provider.generate_text("Begin the story", &AIContext::default()).await.unwrap();
+
+    assert_eq!(text, "The lighthouse keeper woke.");
+    let requests = server.requests();
+    assert_eq!(requests.len(), 1);
+    assert!(!requests[0].headers.contains_key("authorization"));
+
+    let body: serde_json::Value = serde_json::from_str(&requests[0].body).unwrap();
+    assert_eq!(body["model"], "llama3");
+}
+
+#[tokio::test]
+async fn test_optional_api_key_is_sent_as_bearer_token() {
+    let server = MockHttpServer::start(vec![
+        ("POST", "/v1/chat/completions", chat_completion("ok")),
+    ]).await;
+
+    let provider = local_config(server.base_url(), Some("lm-studio-key")).into_provider();
+    provider.quick_edit("text", "fix typos").await.unwrap();
+
+    let requests = server.requests();
+    assert_eq!(requests[0].headers.get("authorization").map(String::as_str), Some("Bearer lm-studio-key"));
+}
+
+#[tokio::test]
+async fn test_list_models_discovers_served_models() {
+    let server = MockHttpServer::start(vec![
+        ("GET", "/v1/models", MockResponse::json(200, json!({
+            "object": "list",
+            "data": [{ "id": "mistral", "object": "model" }, { "id": "llama3", "object": "model" }]
+        }))),
+    ]).await;
+
+    let provider = local_config(server.base_url(), None).into_provider();
+    let models = provider.list_models().await.unwrap();
+
+    assert_eq!(models, vec!["llama3".to_string(), "mistral".to_string()]);
+}
+
+#[tokio::test]
+async fn test_streaming_yields_sse_deltas_incrementally() {
+    let server = MockHttpServer::start(vec![
+        ("POST", "/v1/chat/completions", MockResponse::sse(&[
+            r#"{"choices":[{"delta":{"content":"Once "}}]}"#,
+            r#"{"choices":[{"delta":{"content":"upon a time"}}]}"#,
+            "[DONE]",
+        ])),
+    ]).await;
+
+    let provider = local_config(server.base_url(), None).into_provider();
+    let chunks = provider.generate_text_stream("Begin", &AIContext::default()).await.unwrap();
+    let deltas: Vec<String> = chunks.map(|delta| delta.unwrap()).collect().await;
+
+    assert_eq!(deltas, vec!["Once ".to_string(), "upon a time".to_string()]);
+}
+
+#[tokio::test]
+async fn test_streaming_collects_into_text_stream() {
+    let server = MockHttpServer::start(vec![
+        ("POST", "/v1/chat/completions", MockResponse::sse(&[
+            r#"{"choices":[{"delta":{"role":"assistant"}}]}"#,
+            r#"{"choices":[{"delta":{"content":"The tide "}}]}"#,
+            r#"{"choices":[{"delta":{"content":"turned."}}]}"#,
+            "[DONE]",
+        ])),
+    ]).await;
+
+    let provider = local_config(server.base_url(), None).into_provider();
+    let chunks = provider.quick_chat_stream("What happens next?", &AIContext::default()).await.unwrap();
+    let text = TextStream::from_chunks(chunks).await.unwrap();
+
+    assert!(text.is_complete);
+    assert_eq!(text.content, "The tide turned.");
+}
+
+#[tokio::test]
+async fn test_streaming_surfaces_error_events() {
+    let server = MockHttpServer::start(vec![
+        ("POST", "/v1/chat/completions", MockResponse::sse(&[
+            r#"{"choices":[{"delta":{"content":"Partial"}}]}"#,
+            r#"{"error":{"message":"context length exceeded"}}"#,
+        ])),
+    ]).await;
+
+    let provider = local_config(server.base_url(), None).into_provider();
+    let mut chunks = provider.generate_text_stream("Begin", &AIContext::default()).await.unwrap();
+
+    assert_eq!(chunks.next().await.unwrap().unwrap(), "Partial");
+    let error = chunks.next().await.unwrap().unwrap_err();
+    assert!(error.to_string().contains("context length exceeded"));
+    assert!(chunks.next().await.is_none());
+}
+
+#[tokio::test]
+async fn test_embeddings_use_embedding_model() {
+    let server = MockHttpServer::start(vec![
+        ("POST", "/v1/embeddings", MockResponse::json(200, json!({
+            "data": [{ "embedding": [0.25, -0.5, 1.0] }]
+        }))),
+    ]).await;
+
+    let provider = local_config(server.base_url(), None).into_provider();
+    let embedding = provider.generate_embedding("a quiet harbor").await.unwrap();
+
+    assert_eq!(embedding, vec![0.25, -0.5, 1.0]);
+    let body: serde_json::Value = serde_json::from_str(&server.requests()[0].body).unwrap();
+    assert_eq!(body["model"], "nomic-embed-text");
+}
+
+#[tokio::test]
+async fn test_server_errors_carry_provider_name() {
+    let server = MockHttpServer::start(vec![
+        ("POST", "/v1/chat/completions", MockResponse::json(500, json!({ "error": "model not loaded" }))),
+    ]).await;
+
+    let provider = local_config(server.base_url(), None).into_provider();
+    let error = provider.generate_text("Begin", &AIContext::default()).await.unwrap_err();
+
+    assert!(error.to_string().contains("ollama"));
+    assert!(error.to_string().contains("500"));
+}
+
+#[tokio::test]
+async fn test_manager_registers_compatible_provider() {
+    let server = MockHttpServer::start(vec![
+        ("POST", "/v1/chat/completions", chat_completion("routed locally")),
+    ]).await;
+
+    let mut manager = AIProviderManager::new();
+    manager.register_openai_compatible(local_config(server.base_url(), None));
+    manager.set_default_provider("ollama".to_string());
+
+    let provider = manager.get_provider("ollama").unwrap();
+    assert_eq!(provider.get_provider_name(), "ollama");
+    assert_eq!(provider.get_context_window(), 8192);
+    assert!(!provider.supports_image_generation());
+
+    let text = manager.generate_text("Begin", &AIContext::default()).await.unwrap();
+    assert_eq!(text, "routed locally");
+}
+
+#[tokio::test]
+async fn test_shared_manager_replaces_and_unregisters_saved_provider() {
+    let first = MockHttpServer::start(vec![
+        ("POST", "/v1/chat/completions", chat_completion("from the first server")),
+    ]).await;
+    let second = MockHttpServer::start(vec![
+        ("POST", "/v1/chat/completions", chat_completion("from the second server")),
+    ]).await;
+
+    let manager = std::sync::Arc::new(AIProviderManager::new());
+    manager.replace_openai_compatible(local_config(first.base_url(), None));
+    let provider = manager.get_provider("ollama").unwrap();
+    assert_eq!(provider.generate_text("Begin", &AIContext::default()).await.unwrap(), "from the first server");
+
+    // Saving again under the same name points the next request at the new settings
+    manager.replace_openai_compatible(local_config(second.base_url(), Some("sk-local")));
+    assert_eq!(manager.list_providers(), vec!["ollama".to_string()]);
+    let provider = manager.get_provider("ollama").unwrap();
+    assert_eq!(provider.generate_text("Begin", &AIContext::default()).await.unwrap(), "from the second server");
+    assert_eq!(second.requests()[0].headers.get("authorization").map(String::as_str), Some("Bearer sk-local"));
+
+    assert!(manager.unregister_provider("ollama"));
+    assert!(manager.get_provider("ollama").is_none());
+    assert!(!manager.unregister_provider("ollama"));
+}
+
+#[tokio::test]
+async fn test_saving_provider_stores_shared_chat_and_embedding_model_once() {
+    let pool = test_pool().await;
+    let config = OpenAICompatibleConfig {
+        embedding_model: Some("llama3".to_string()),
+        ..local_config("http://localhost:11434/v1".to_string(), None)
+    };
+    let provider_id = AIProviderOps::save_openai_compatible(&pool, &config).await.unwrap();
+    // Saving twice replaces the models rather than adding to them
+    AIProviderOps::save_openai_compatible(&pool, &config).await.unwrap();
+
+    let models: Vec<String> =
+        sqlx::query_scalar("SELECT model_name FROM ai_model_configurations WHERE provider_id = ?")
+            .bind(provider_id)
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+    assert_eq!(models, vec!["llama3".to_string()]);
+
+    let saved = AIProviderOps::list_openai_compatible(&pool).await.unwrap();
+    assert_eq!(saved.len(), 1);
+    assert_eq!(saved[0].model, "llama3");
+    assert_eq!(saved[0].embedding_model, None);
+
+    AIProviderOps::delete_openai_compatible(&pool, "ollama").await.unwrap();
+    assert!(AIProviderOps::list_openai_compatible(&pool).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_saving_provider_cannot_take_over_a_seeded_provider() {
+    let pool = test_pool().await;
+    let config = OpenAICompatibleConfig {
+        name: "anthropic".to_string(),
+        ..local_config("http://localhost:11434/v1".to_string(), None)
+    };
+
+    assert!(AIProviderOps::save_openai_compatible(&pool, &config).await.is_err());
+
+    let seeded = AIProviderOps::get_by_name(&pool, "anthropic").await.unwrap().unwrap();
+    assert_eq!(seeded.api_endpoint.as_deref(), Some("https://api.anthropic.com"));
+    let models: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM ai_model_configurations WHERE provider_id = ?")
+        .bind(seeded.id)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert!(models > 0);
+    assert!(AIProviderOps::list_openai_compatible(&pool).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_unset_context_window_reloads_unset() {
+    let pool = test_pool().await;
+    let config = OpenAICompatibleConfig {
+        context_window: None,
+        ..local_config("http://localhost:11434/v1".to_string(), None)
+    };
+    AIProviderOps::save_openai_compatible(&pool, &config).await.unwrap();
+
+    let saved = AIProviderOps::list_openai_compatible(&pool).await.unwrap();
+    assert_eq!(saved[0].context_window, None);
+}
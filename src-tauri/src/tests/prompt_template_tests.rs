@@ -0,0 +1,173 @@
+//! Tests for the versioned prompt template registry
+
+use crate::ai::prompt_templates::{builtin_templates, check_values, PromptRegistry};
+use crate::ai::PromptScope;
+use crate::database::models::{AIGenerationHistory, AIGenerationType};
+use crate::database::operations::{AIHistoryOps, PromptTemplate, PromptTemplateOps, TemplateVariable, VariableKind};
+use crate::tests::{test_pool, test_project};
+use chrono::Utc;
+use serde_json::json;
+use uuid::Uuid;
+
+fn system_variables() -> Vec<TemplateVariable> {
+    builtin_templates().into_iter().find(|t| t.name == "system").unwrap().variables
+}
+
+#[tokio::test]
+async fn test_builtins_are_seeded_as_version_one() {
+    let pool = test_pool().await;
+    let registry = PromptRegistry::load(&pool).await.unwrap();
+
+    let stored = PromptTemplateOps::get_versions(&pool, None).await.unwrap();
+    assert_eq!(stored.len(), builtin_templates().len());
+    assert!(stored.iter().all(|t| t.version == 1 && t.is_global()));
+
+    let rendered = registry
+        .render("system", &PromptScope::global(), &json!({ "genre": "noir" }))
+        .unwrap();
+    assert!(rendered.text.contains("noir genre"));
+    assert_eq!(rendered.template.name, "system");
+    assert_eq!(rendered.template.version, 1);
+
+    // Seeding again leaves the existing versions alone
+    PromptRegistry::load(&pool).await.unwrap();
+    assert_eq!(PromptTemplateOps::get_versions(&pool, None).await.unwrap().len(), stored.len());
+}
+
+#[tokio::test]
+async fn test_project_override_and_rollback() {
+    let pool = test_pool().await;
+    let saga = test_project(&pool, "Saga").await;
+    let other = test_project(&pool, "Other").await;
+    PromptRegistry::load(&pool).await.unwrap();
+
+    let mut custom = PromptTemplate::new("system", "Write like a pirate.", system_variables());
+    custom.project_id = Some(saga.clone());
+    let custom = PromptTemplateOps::create_version(&pool, custom).await.unwrap();
+    assert_eq!(custom.version, 2);
+
+    let registry = PromptRegistry::load(&pool).await.unwrap();
+    let rendered = registry.render("system", &PromptScope::for_project(&saga), &json!({})).unwrap();
+    assert_eq!(rendered.text, "Write like a pirate.");
+    assert_eq!(rendered.template.version, 2);
+    let rendered = registry.render("system", &PromptScope::for_project(&other), &json!({})).unwrap();
+    assert_eq!(rendered.template.version, 1);
+
+    // A newer project version replaces the override, and activating the old one rolls back
+    let mut newer = PromptTemplate::new("system", "Write like a poet.", system_variables());
+    newer.project_id = Some(saga.clone());
+    PromptTemplateOps::create_version(&pool, newer).await.unwrap();
+    let registry = PromptRegistry::load(&pool).await.unwrap();
+    assert_eq!(registry.resolve("system", &PromptScope::for_project(&saga)).unwrap().version, 3);
+
+    PromptTemplateOps::activate(&pool, &custom.id).await.unwrap();
+    let registry = PromptRegistry::load(&pool).await.unwrap();
+    assert_eq!(registry.resolve("system", &PromptScope::for_project(&saga)).unwrap().version, 2);
+
+    // Deactivating the override falls back to the default; the default itself can't be deactivated
+    PromptTemplateOps::deactivate(&pool, &custom.id).await.unwrap();
+    let registry = PromptRegistry::load(&pool).await.unwrap();
+    assert_eq!(registry.resolve("system", &PromptScope::for_project(&saga)).unwrap().version, 1);
+
+    let global = registry.resolve("system", &PromptScope::global()).unwrap().id.clone();
+    assert!(PromptTemplateOps::deactivate(&pool, &global).await.is_err());
+}
+
+#[tokio::test]
+async fn test_prose_mode_scope_sits_between_project_and_default() {
+    let pool = test_pool().await;
+    let saga = test_project(&pool, "Saga").await;
+    PromptRegistry::load(&pool).await.unwrap();
+
+    let mut by_mode = PromptTemplate::new("system", "Lyrical mode.", system_variables());
+    by_mode.prose_mode = Some("lyrical".to_string());
+    PromptTemplateOps::create_version(&pool, by_mode).await.unwrap();
+
+    let registry = PromptRegistry::load(&pool).await.unwrap();
+    let lyrical = PromptScope { project_id: Some(saga.clone()), prose_mode: Some("lyrical".to_string()) };
+    assert_eq!(registry.render("system", &lyrical, &json!({})).unwrap().text, "Lyrical mode.");
+    let plain = PromptScope { project_id: Some(saga.clone()), prose_mode: Some("plain".to_string()) };
+    assert_eq!(registry.resolve("system", &plain).unwrap().version, 1);
+
+    let mut by_project = PromptTemplate::new("system", "Saga voice.", system_variables());
+    by_project.project_id = Some(saga.clone());
+    PromptTemplateOps::create_version(&pool, by_project).await.unwrap();
+    let registry = PromptRegistry::load(&pool).await.unwrap();
+    assert_eq!(registry.render("system", &lyrical, &json!({})).unwrap().text, "Saga voice.");
+}
+
+#[tokio::test]
+async fn test_traffic_split_is_deterministic_per_project() {
+    let pool = test_pool().await;
+    let registry = PromptRegistry::load(&pool).await.unwrap();
+    let original = registry.resolve("system", &PromptScope::global()).unwrap().clone();
+
+    let candidate = PromptTemplate::new("system", "Candidate prompt.", system_variables());
+    let candidate = PromptTemplateOps::create_version(&pool, candidate).await.unwrap();
+    PromptTemplateOps::set_traffic_weights(&pool, &[(original.id.clone(), 50), (candidate.id.clone(), 50)])
+        .await
+        .unwrap();
+
+    let registry = PromptRegistry::load(&pool).await.unwrap();
+    let mut versions = Vec::new();
+    for i in 0..40 {
+        let scope = PromptScope::for_project(&format!("project-{}", i));
+        let version = registry.resolve("system", &scope).unwrap().version;
+        assert_eq!(PromptRegistry::load(&pool).await.unwrap().resolve("system", &scope).unwrap().version, version);
+        versions.push(version);
+    }
+    assert!(versions.contains(&1));
+    assert!(versions.contains(&2));
+
+    // Weights only split traffic between versions of the same template and scope
+    let rewrite = registry.resolve("rewrite", &PromptScope::global()).unwrap().id.clone();
+    assert!(PromptTemplateOps::set_traffic_weights(&pool, &[(candidate.id.clone(), 50), (rewrite, 50)])
+        .await
+        .is_err());
+    assert!(PromptTemplateOps::set_traffic_weights(&pool, &[(candidate.id, 0)]).await.is_err());
+}
+
+#[test]
+fn test_values_are_checked_against_declared_variables() {
+    let variables = vec![
+        TemplateVariable::required("topic", VariableKind::Text),
+        TemplateVariable::optional("count", VariableKind::Number),
+    ];
+    assert!(check_values("t", &variables, &json!({ "topic": "dragons", "count": 3 })).is_ok());
+    assert!(check_values("t", &variables, &json!({ "topic": "dragons" })).is_ok());
+    assert!(check_values("t", &variables, &json!({ "count": 3 })).is_err());
+    assert!(check_values("t", &variables, &json!({ "topic": "dragons", "count": "three" })).is_err());
+
+    let registry = PromptRegistry::from_templates(builtin_templates());
+    assert!(registry.render("brainstorm", &PromptScope::global(), &json!({})).is_err());
+    assert!(registry.render("missing", &PromptScope::global(), &json!({})).is_err());
+}
+
+#[tokio::test]
+async fn test_history_records_template_version() {
+    let pool = test_pool().await;
+    let project_id = test_project(&pool, "Saga").await;
+
+    let record = AIGenerationHistory {
+        id: Uuid::new_v4().to_string(),
+        project_id: project_id.clone(),
+        document_id: None,
+        generation_type: AIGenerationType::Outline,
+        provider: "mock".to_string(),
+        model: "mock-model".to_string(),
+        prompt: "Outline the story".to_string(),
+        response: "1. Beginning".to_string(),
+        token_count: 10,
+        cost_estimate: None,
+        context_used: "{}".to_string(),
+        route_taken: None,
+        prompt_template: Some("outline".to_string()),
+        prompt_template_version: Some(2),
+        created_at: Utc::now(),
+    };
+    let created = AIHistoryOps::create(&pool, record).await.unwrap();
+
+    let stored = AIHistoryOps::get_by_id(&pool, &created.id).await.unwrap().unwrap();
+    assert_eq!(stored.prompt_template.as_deref(), Some("outline"));
+    assert_eq!(stored.prompt_template_version, Some(2));
+}
@@ -0,0 +1,157 @@
+//! Tests for provider retries, Retry-After handling and the circuit breaker
+
+use crate::ai::{AIContext, AIProvider, AIProviderManager, CircuitState, OpenAICompatibleConfig, OpenAIProvider, ResiliencePolicy, RouteRequest, RouteTarget, RoutingTable};
+use crate::error::StoryWeaverError;
+use crate::tests::mock_http_server::{MockHttpServer, MockResponse};
+use serde_json::json;
+use std::sync::Arc;
+use std::time::Duration;
+
+fn chat_completion(content: &str) -> MockResponse {
+    MockResponse::json(200, json!({
+        "choices": [{ "message": { "role": "assistant", "content": content }, "finish_reason": "stop" }]
+    }))
+}
+
+fn fast_policy() -> ResiliencePolicy {
+    ResiliencePolicy {
+        base_delay: Duration::from_millis(5),
+        max_delay: Duration::from_millis(20),
+        ..ResiliencePolicy::default()
+    }
+}
+
+fn provider(name: &str, server: &MockHttpServer, policy: ResiliencePolicy) -> OpenAIProvider {
+    OpenAICompatibleConfig {
+        name: name.to_string(),
+        display_name: name.to_string(),
+        base_url: server.base_url(),
+        api_key: None,
+        model: "llama3".to_string(),
+        embedding_model: None,
+        context_window: None,
+    }
+    .into_provider()
+    .with_resilience_policy(policy)
+}
+
+async fn chat_server(responses: Vec<MockResponse>) -> MockHttpServer {
+    let routes = responses.into_iter().map(|response| ("POST", "/v1/chat/completions", response)).collect();
+    MockHttpServer::start(routes).await
+}
+
+#[tokio::test]
+async fn test_server_errors_are_retried() {
+    let server = chat_server(vec![
+        MockResponse::json(503, json!({ "error": "overloaded" })),
+        MockResponse::json(502, json!({ "error": "bad gateway" })),
+        chat_completion("third time lucky"),
+    ]).await;
+
+    let text = provider("local", &server, fast_policy())
+        .generate_text("Begin", &AIContext::default())
+        .await
+        .unwrap();
+
+    assert_eq!(text, "third time lucky");
+    assert_eq!(server.requests().len(), 3);
+}
+
+#[tokio::test]
+async fn test_rate_limit_waits_for_retry_after() {
+    let server = chat_server(vec![
+        MockResponse::json(429, json!({ "error": "slow down" })).with_header("retry-after-ms", "50"),
+        chat_completion("after the wait"),
+    ]).await;
+
+    let started = std::time::Instant::now();
+    let text = provider("local", &server, fast_policy())
+        .generate_text("Begin", &AIContext::default())
+        .await
+        .unwrap();
+
+    assert_eq!(text, "after the wait");
+    assert!(started.elapsed() >= Duration::from_millis(50));
+}
+
+#[tokio::test]
+async fn test_fatal_errors_are_not_retried() {
+    let server = chat_server(vec![MockResponse::json(400, json!({ "error": "bad request" }))]).await;
+
+    let error = provider("local", &server, fast_policy())
+        .generate_text("Begin", &AIContext::default())
+        .await
+        .unwrap_err();
+
+    assert!(matches!(error, StoryWeaverError::AIRequest { status_code: 400, .. }));
+    assert_eq!(server.requests().len(), 1);
+}
+
+#[tokio::test]
+async fn test_long_retry_after_fails_immediately() {
+    let server = chat_server(vec![
+        MockResponse::json(429, json!({ "error": "quota" })).with_header("Retry-After", "3600"),
+    ]).await;
+
+    let error = provider("local", &server, fast_policy())
+        .generate_text("Begin", &AIContext::default())
+        .await
+        .unwrap_err();
+
+    assert!(matches!(error, StoryWeaverError::AIRateLimit { retry_after: 3600, .. }));
+    assert_eq!(server.requests().len(), 1);
+}
+
+#[tokio::test]
+async fn test_retries_are_bounded() {
+    let server = chat_server(vec![MockResponse::json(503, json!({ "error": "down" }))]).await;
+    let policy = ResiliencePolicy { max_retries: 2, ..fast_policy() };
+
+    let error = provider("local", &server, policy)
+        .generate_text("Begin", &AIContext::default())
+        .await
+        .unwrap_err();
+
+    assert!(matches!(error, StoryWeaverError::AIRequest { status_code: 503, .. }));
+    assert_eq!(server.requests().len(), 3);
+}
+
+#[tokio::test]
+async fn test_open_circuit_marks_provider_unhealthy_and_routes_around_it() {
+    let flaky_server = chat_server(vec![MockResponse::json(503, json!({ "error": "down" }))]).await;
+    let steady_server = chat_server(vec![chat_completion("steady")]).await;
+    let policy = ResiliencePolicy {
+        max_retries: 0,
+        failure_threshold: 2,
+        open_duration: Duration::from_secs(60),
+        ..fast_policy()
+    };
+
+    let flaky = Arc::new(provider("flaky", &flaky_server, policy.clone()));
+    let mut manager = AIProviderManager::new();
+    manager.register_provider("flaky".to_string(), flaky.clone());
+    manager.register_provider("steady".to_string(), Arc::new(provider("steady", &steady_server, policy)));
+    manager.set_default_provider("flaky".to_string());
+
+    for _ in 0..2 {
+        assert!(flaky.generate_text("Begin", &AIContext::default()).await.is_err());
+    }
+    let health = manager.provider_health();
+    assert_eq!(health["flaky"].state, CircuitState::Open);
+    assert!(health["steady"].is_healthy());
+
+    // An open circuit fails fast without contacting the provider
+    assert!(flaky.generate_text("Begin", &AIContext::default()).await.is_err());
+    assert_eq!(flaky_server.requests().len(), 2);
+
+    manager.set_routing_table(RoutingTable {
+        fallback_chain: vec![RouteTarget::new("flaky"), RouteTarget::new("steady")],
+        ..RoutingTable::default()
+    });
+    let chain = manager.resolve_route(&RouteRequest::default());
+    assert_eq!(chain[0].0, "steady");
+
+    let text = manager.generate_text("Begin", &AIContext::default()).await.unwrap();
+    assert_eq!(text, "steady");
+    assert_eq!(flaky_server.requests().len(), 2);
+}
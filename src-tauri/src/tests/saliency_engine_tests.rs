@@ -0,0 +1,140 @@
+//! Tests for embedding-driven saliency selection
+
+use crate::ai::saliency_engine::{
+    annotate_from_database, SaliencyConfig, SaliencyEngine, StoryBibleCharacter, StoryBibleElements,
+    StoryBibleLocation,
+};
+use crate::database::models::{Character, CharacterRole, Document, DocumentType, VisibilityLevel};
+use crate::database::operations::{CharacterOps, DocumentOps};
+use crate::tests::{test_pool, test_project};
+
+fn character(id: &str, name: &str, description: &str) -> StoryBibleCharacter {
+    StoryBibleCharacter {
+        id: id.to_string(),
+        name: name.to_string(),
+        description: description.to_string(),
+        visibility: None,
+        last_seen_chapter: None,
+    }
+}
+
+fn story_bible(characters: Vec<StoryBibleCharacter>) -> StoryBibleElements {
+    StoryBibleElements {
+        characters,
+        locations: vec![StoryBibleLocation {
+            id: "harbor".to_string(),
+            name: "Greyhaven Harbor".to_string(),
+            description: "A storm-battered harbor where ships shelter from the waves".to_string(),
+            visibility: None,
+            last_seen_chapter: None,
+        }],
+        plot_threads: Vec::new(),
+        worldbuilding: Vec::new(),
+        current_chapter: None,
+        story_summary: None,
+    }
+}
+
+const TEXT: &str = "The storm drove the ship toward the harbor while the captain fought the helm.";
+
+#[tokio::test]
+async fn test_embedding_mode_selects_related_elements_with_explanations() {
+    let mut engine = SaliencyEngine::new(SaliencyConfig { similarity_threshold: 0.15, ..SaliencyConfig::default() });
+    let bible = story_bible(vec![
+        character("mara", "Mara", "Captain of a ship, steady at the helm through any storm"),
+        character("pell", "Pell", "A baker who sells honey bread in the market square"),
+    ]);
+
+    let context = engine.build_context("p1", TEXT, &bible).await.unwrap();
+
+    let ids: Vec<&str> = context.selected_elements.characters.iter().map(|c| c.id.as_str()).collect();
+    assert_eq!(ids, vec!["mara"]);
+    assert_eq!(context.selected_elements.locations.len(), 1);
+    assert!(context.selected_elements.characters[0].explanation.starts_with("Embedding similarity"));
+}
+
+#[tokio::test]
+async fn test_visibility_rules_override_similarity() {
+    let mut engine = SaliencyEngine::default();
+    let mut captain = character("mara", "Mara", "Captain of a ship, steady at the helm through any storm");
+    captain.visibility = Some(VisibilityLevel::Hidden);
+    let mut baker = character("pell", "Pell", "A baker who sells honey bread in the market square");
+    baker.visibility = Some(VisibilityLevel::Always);
+
+    let context = engine.build_context("p1", TEXT, &story_bible(vec![captain, baker])).await.unwrap();
+
+    let characters = &context.selected_elements.characters;
+    assert_eq!(characters.len(), 1);
+    assert_eq!(characters[0].id, "pell");
+    assert!(characters[0].explanation.contains("visibility: always"));
+}
+
+#[tokio::test]
+async fn test_recent_appearances_outrank_older_ones() {
+    let mut engine = SaliencyEngine::new(SaliencyConfig {
+        similarity_threshold: 0.0,
+        recency_weight: 0.5,
+        ..SaliencyConfig::default()
+    });
+    let description = "A sailor on the ship";
+    let mut old = character("old", "Ivo", description);
+    old.last_seen_chapter = Some(1);
+    let mut recent = character("recent", "Tam", description);
+    recent.last_seen_chapter = Some(5);
+    let mut bible = story_bible(vec![old, recent]);
+    bible.current_chapter = Some(5);
+
+    let context = engine.build_context("p1", TEXT, &bible).await.unwrap();
+
+    let characters = &context.selected_elements.characters;
+    assert_eq!(characters[0].id, "recent");
+    assert!(characters[0].relevance_score > characters[1].relevance_score);
+    assert!(characters[0].explanation.contains("appears in the current chapter"));
+    assert!(characters[1].explanation.contains("last appeared in chapter 1"));
+}
+
+#[tokio::test]
+async fn test_selection_stays_within_token_budget() {
+    let mut engine = SaliencyEngine::new(SaliencyConfig {
+        max_total_tokens: 60,
+        similarity_threshold: 0.0,
+        ..SaliencyConfig::default()
+    });
+    let characters = (0..10)
+        .map(|i| character(&format!("c{}", i), &format!("Sailor {}", i), "A sailor who rides out the storm on the ship"))
+        .collect();
+
+    let mut bible = story_bible(characters);
+    bible.locations.clear();
+
+    let context = engine.build_context("p1", TEXT, &bible).await.unwrap();
+
+    assert!(context.total_tokens <= 60);
+    assert!(!context.selected_elements.characters.is_empty());
+    assert!(context.selected_elements.characters.len() < 10);
+}
+
+#[tokio::test]
+async fn test_annotation_reads_visibility_and_last_chapter_from_database() {
+    let pool = test_pool().await;
+    let project_id = test_project(&pool, "Saga").await;
+
+    let mut chapters = Vec::new();
+    for (i, content) in ["Mara set sail.", "Mara reached Greyhaven Harbor.", "The crew slept.", "Dawn came."].iter().enumerate() {
+        let mut chapter = Document::new(project_id.clone(), format!("Chapter {}", i + 1), DocumentType::Chapter);
+        chapter.content = content.to_string();
+        chapter.order_index = i as i32;
+        chapters.push(DocumentOps::create(&pool, chapter).await.unwrap());
+    }
+    let mut hidden = Character::new(project_id.clone(), "Mara".to_string(), CharacterRole::Protagonist);
+    hidden.visibility = VisibilityLevel::Hidden;
+    let hidden = CharacterOps::create(&pool, hidden).await.unwrap();
+
+    let mut bible = story_bible(vec![character(&hidden.id, "Mara", "Captain")]);
+    annotate_from_database(&pool, &project_id, Some(&chapters[2].id), &mut bible).await.unwrap();
+
+    assert_eq!(bible.current_chapter, Some(3));
+    assert_eq!(bible.characters[0].last_seen_chapter, Some(2));
+    assert!(matches!(bible.characters[0].visibility, Some(VisibilityLevel::Hidden)));
+    assert_eq!(bible.locations[0].last_seen_chapter, Some(2));
+}
@@ -0,0 +1,106 @@
+//! Tests for the semantic index and hybrid search
+
+use crate::ai::semantic_index::{SearchMode, SemanticIndexer, SemanticSource, SemanticSourceType};
+use crate::ai::{AIProviderManager, MockProvider, RouteTarget, RoutingTable, EMBEDDING_ROUTE_KEY};
+use crate::database::models::{Character, CharacterRole, Document, DocumentType};
+use crate::database::operations::SemanticIndexOps;
+use crate::tests::test_pool;
+use std::sync::Arc;
+
+fn document(project_id: &str, title: &str, content: &str) -> Document {
+    let mut document = Document::new(project_id.to_string(), title.to_string(), DocumentType::Chapter);
+    document.content = content.to_string();
+    document
+}
+
+/// An indexer whose embeddings come from a mock, so calls can be counted
+fn mock_indexer() -> (SemanticIndexer, Arc<MockProvider>) {
+    let embedder = Arc::new(MockProvider::new().with_name("embedder").with_model("mock-embed"));
+    let mut manager = AIProviderManager::new();
+    manager.register_provider("embedder".to_string(), embedder.clone());
+    let mut table = RoutingTable::default();
+    table.features.insert(EMBEDDING_ROUTE_KEY.to_string(), vec![RouteTarget::new("embedder")]);
+    manager.set_routing_table(table);
+    (SemanticIndexer::new(Arc::new(manager)), embedder)
+}
+
+#[tokio::test]
+async fn test_semantic_search_ranks_documents_and_story_bible_by_meaning() {
+    let pool = test_pool().await;
+    let indexer = SemanticIndexer::new(Arc::new(AIProviderManager::new()));
+
+    let storm = document("p1", "Chapter 3", "The storm tore the sails and the ship rolled in the black waves.");
+    let dinner = document("p1", "Chapter 4", "Candles flickered over the dinner table as the guests laughed.");
+    let mut captain = Character::new("p1".to_string(), "Mara".to_string(), CharacterRole::Protagonist);
+    captain.background = Some("Captain of a ship that survived the great storm".to_string());
+    let other_project = document("p2", "Elsewhere", "A storm and a ship and waves.");
+
+    for source in [
+        SemanticSource::Document(storm.clone()),
+        SemanticSource::Document(dinner.clone()),
+        SemanticSource::Character(captain.clone()),
+        SemanticSource::Document(other_project.clone()),
+    ] {
+        indexer.index(&pool, &source).await.unwrap();
+    }
+
+    let hits = indexer.search(&pool, "p1", "ship caught in a storm", 10, SearchMode::Semantic).await.unwrap();
+
+    // The storm chapter and the captain both outrank the dinner party
+    let top: Vec<&str> = hits.iter().take(2).map(|hit| hit.source_id.as_str()).collect();
+    assert!(top.contains(&storm.id.as_str()));
+    assert!(top.contains(&captain.id.as_str()));
+    assert!(hits.iter().all(|hit| hit.source_id != other_project.id));
+    assert!(hits.iter().position(|hit| hit.source_id == dinner.id) != Some(0));
+}
+
+#[tokio::test]
+async fn test_hybrid_search_reports_keyword_scores() {
+    let pool = test_pool().await;
+    let indexer = SemanticIndexer::new(Arc::new(AIProviderManager::new()));
+
+    let named = document("p1", "Chapter 1", "Quillon hid the letter beneath the floorboards.");
+    let unnamed = document("p1", "Chapter 2", "She hid the letter beneath the floorboards.");
+    indexer.index(&pool, &SemanticSource::Document(named.clone())).await.unwrap();
+    indexer.index(&pool, &SemanticSource::Document(unnamed.clone())).await.unwrap();
+
+    let hits = indexer.search(&pool, "p1", "Quillon", 5, SearchMode::Hybrid).await.unwrap();
+
+    assert_eq!(hits[0].source_id, named.id);
+    assert_eq!(hits[0].keyword_score, Some(1.0));
+    assert!(hits.iter().all(|hit| hit.keyword_score.is_some()));
+}
+
+#[tokio::test]
+async fn test_reindexing_only_embeds_changed_chunks() {
+    let pool = test_pool().await;
+    let (indexer, embedder) = mock_indexer();
+
+    let mut chapter = document("p1", "Chapter 1", &"harbor ".repeat(400));
+    let stored = indexer.index(&pool, &SemanticSource::Document(chapter.clone())).await.unwrap();
+    assert!(stored > 1);
+    assert_eq!(embedder.calls().len(), stored);
+
+    // Appending only changes the last chunk
+    chapter.content.push_str("lighthouse");
+    indexer.index(&pool, &SemanticSource::Document(chapter.clone())).await.unwrap();
+    assert_eq!(embedder.calls().len(), stored + 1);
+
+    let entries = SemanticIndexOps::get_by_source(&pool, "document", &chapter.id).await.unwrap();
+    assert_eq!(entries.len(), stored);
+    assert!(entries.iter().all(|entry| entry.model == "mock-embed"));
+    assert!(entries.last().unwrap().content.ends_with("lighthouse"));
+}
+
+#[tokio::test]
+async fn test_removed_sources_leave_the_index() {
+    let pool = test_pool().await;
+    let indexer = SemanticIndexer::new(Arc::new(AIProviderManager::new()));
+
+    let chapter = document("p1", "Chapter 1", "The lighthouse keeper lit the lamp.");
+    indexer.index(&pool, &SemanticSource::Document(chapter.clone())).await.unwrap();
+    indexer.remove(&pool, SemanticSourceType::Document, &chapter.id).await.unwrap();
+
+    let hits = indexer.search(&pool, "p1", "lighthouse", 5, SearchMode::Hybrid).await.unwrap();
+    assert!(hits.is_empty());
+}
@@ -0,0 +1,148 @@
+//! Tests for story bible aliases and detection
+
+use crate::database::models::{Character, CharacterRole, CharacterTrait, Location, LocationType, WorldElement};
+use crate::database::operations::{
+    AliasKind, CharacterOps, CharacterTraitOps, LocationOps, StoryBibleAlias, StoryBibleAliasOps, StoryBibleEntityType,
+    WorldElementOps,
+};
+use crate::detection::{detect_in_text, MentionKind, StoryBibleDetection};
+use crate::tests::test_project;
+use sqlx::{Pool, Sqlite};
+
+const ALL_TYPES: &[StoryBibleEntityType] =
+    &[StoryBibleEntityType::Character, StoryBibleEntityType::Location, StoryBibleEntityType::WorldElement];
+
+async fn test_pool() -> (Pool<Sqlite>, String) {
+    let pool = crate::tests::test_pool().await;
+    let project_id = test_project(&pool, "Saltmarch").await;
+    (pool, project_id)
+}
+
+async fn create_character(pool: &Pool<Sqlite>, project_id: &str, name: &str) -> Character {
+    let character = Character::new(project_id.to_string(), name.to_string(), CharacterRole::Supporting);
+    CharacterOps::create(pool, character).await.unwrap()
+}
+
+async fn add_alias(pool: &Pool<Sqlite>, character: &Character, alias: &str, kind: AliasKind) -> StoryBibleAlias {
+    let alias = StoryBibleAlias::new(&character.project_id, StoryBibleEntityType::Character, &character.id, alias, kind);
+    StoryBibleAliasOps::create(pool, alias).await.unwrap()
+}
+
+fn quoted(text: &str, detection: &StoryBibleDetection) -> String {
+    text.chars()
+        .skip(detection.start_position)
+        .take(detection.end_position - detection.start_position)
+        .collect()
+}
+
+#[tokio::test]
+async fn test_aliases_are_checked_and_go_with_their_entity() {
+    let (pool, project_id) = test_pool().await;
+    let mara = create_character(&pool, &project_id, "Mara Voss").await;
+    add_alias(&pool, &mara, "Mags", AliasKind::Nickname).await;
+
+    let duplicate = StoryBibleAlias::new(&project_id, StoryBibleEntityType::Character, &mara.id, "mags", AliasKind::Alias);
+    assert!(StoryBibleAliasOps::create(&pool, duplicate).await.is_err());
+    let own_name = StoryBibleAlias::new(&project_id, StoryBibleEntityType::Character, &mara.id, "Mara Voss", AliasKind::Alias);
+    assert!(StoryBibleAliasOps::create(&pool, own_name).await.is_err());
+    // A location with Mara's ID doesn't exist
+    let wrong_type = StoryBibleAlias::new(&project_id, StoryBibleEntityType::Location, &mara.id, "Mags", AliasKind::Alias);
+    assert!(StoryBibleAliasOps::create(&pool, wrong_type).await.is_err());
+
+    let aliases = StoryBibleAliasOps::get_by_entity(&pool, StoryBibleEntityType::Character, &mara.id).await.unwrap();
+    assert_eq!(aliases.len(), 1);
+    assert_eq!(aliases[0].kind, AliasKind::Nickname);
+
+    CharacterOps::delete(&pool, &mara.id).await.unwrap();
+    assert!(StoryBibleAliasOps::get_by_project(&pool, &project_id).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_detection_finds_aliases_titles_and_possessives_at_character_offsets() {
+    let (pool, project_id) = test_pool().await;
+    let mara = create_character(&pool, &project_id, "Mara Voss").await;
+    add_alias(&pool, &mara, "Captain", AliasKind::Title).await;
+    add_alias(&pool, &mara, "Mags", AliasKind::Nickname).await;
+    let zoe = create_character(&pool, &project_id, "Zoë").await;
+    let keep = LocationOps::create(&pool, Location::new(project_id.clone(), "Keep".to_string(), LocationType::Building))
+        .await
+        .unwrap();
+    let tide = WorldElementOps::create(
+        &pool,
+        WorldElement::new(Some(project_id.clone()), "Ştorm Tide".to_string(), "event".to_string()),
+    )
+    .await
+    .unwrap();
+
+    let text = "🌊 Zoë's lantern lit the Keep. Captain Voss frowned; the captain always did. \
+                Mags' orders came before the Ştorm Tide. The keeper slept.";
+    let detections = detect_in_text(&pool, &project_id, text, ALL_TYPES, false).await.unwrap();
+    for detection in &detections {
+        assert_eq!(quoted(text, detection), detection.matched_text);
+    }
+    let summary: Vec<(&str, &str, MentionKind)> = detections
+        .iter()
+        .map(|d| (d.entity_id.as_str(), d.matched_text.as_str(), d.match_kind))
+        .collect();
+    assert_eq!(
+        summary,
+        vec![
+            (zoe.id.as_str(), "Zoë's", MentionKind::Name),
+            (keep.id.as_str(), "Keep", MentionKind::Name),
+            (mara.id.as_str(), "Captain Voss", MentionKind::Alias),
+            (mara.id.as_str(), "captain", MentionKind::Title),
+            (mara.id.as_str(), "Mags'", MentionKind::Nickname),
+            (tide.id.as_str(), "Ştorm Tide", MentionKind::Name),
+        ]
+    );
+
+    let zoe_mention = &detections[0];
+    assert_eq!((zoe_mention.start_position, zoe_mention.end_position), (2, 7));
+    assert!(zoe_mention.possessive);
+    assert_eq!(zoe_mention.detection_type, StoryBibleEntityType::Character);
+    // The lowercase title is much less likely to be her than her name
+    assert!(detections[3].confidence < 0.5);
+    assert!(detections[2].confidence > 0.8);
+
+    let locations_only = detect_in_text(&pool, &project_id, text, &[StoryBibleEntityType::Location], false)
+        .await
+        .unwrap();
+    assert_eq!(locations_only.len(), 1);
+}
+
+#[tokio::test]
+async fn test_shared_names_split_confidence_and_pronouns_follow_the_last_fitting_character() {
+    let (pool, project_id) = test_pool().await;
+    let mara = create_character(&pool, &project_id, "Mara Voss").await;
+    let lind = create_character(&pool, &project_id, "Mara Lind").await;
+    let ivo = create_character(&pool, &project_id, "Ivo").await;
+    for (character, name, value) in [(&mara, "Pronouns", "she/her"), (&ivo, "Gender", "male")] {
+        let character_trait = CharacterTrait::new(character.id.clone(), name.to_string(), Some(value.to_string()));
+        CharacterTraitOps::create(&pool, character_trait).await.unwrap();
+    }
+
+    let text = "Mara laughed. Voss handed Ivo her knife, and he turned it in his hands. They waited.";
+    let without = detect_in_text(&pool, &project_id, text, ALL_TYPES, false).await.unwrap();
+    assert!(without.iter().all(|d| d.match_kind != MentionKind::Pronoun));
+
+    let detections = detect_in_text(&pool, &project_id, text, ALL_TYPES, true).await.unwrap();
+    let shared: Vec<&StoryBibleDetection> = detections.iter().filter(|d| d.matched_text == "Mara").collect();
+    assert_eq!(shared.len(), 2);
+    assert!(shared.iter().any(|d| d.entity_id == lind.id));
+    assert!(shared.iter().all(|d| d.confidence < 0.5));
+
+    let pronouns: Vec<(&str, &str)> = detections
+        .iter()
+        .filter(|d| d.match_kind == MentionKind::Pronoun)
+        .map(|d| (d.matched_text.as_str(), d.entity_id.as_str()))
+        .collect();
+    // "They" could be Mara Lind, whose pronouns aren't known, or the group
+    assert_eq!(
+        pronouns,
+        vec![("her", mara.id.as_str()), ("he", ivo.id.as_str()), ("his", ivo.id.as_str()), ("They", lind.id.as_str())]
+    );
+    let his = detections.iter().find(|d| d.matched_text == "his").unwrap();
+    assert!(his.possessive);
+    let they = detections.iter().find(|d| d.matched_text == "They").unwrap();
+    assert!(they.confidence < his.confidence);
+}
@@ -0,0 +1,226 @@
+//! Tests for story bible extraction from an existing manuscript
+
+use crate::ai::{
+    create_story_bible_extraction, name_similarity, run_story_bible_extraction, MockProvider, ScriptedResponse,
+    WritingFeature,
+};
+use crate::database::models::{Character, CharacterRole, Document, DocumentType, Location, LocationType};
+use crate::database::operations::{
+    CharacterOps, DocumentOps, ExtractionStatus, LocationOps, ProposalStatus, ProposalType, StoryBibleExtractionOps,
+    StoryBibleProposal, WorldElementOps,
+};
+use crate::tests::test_project;
+use sqlx::{Pool, Sqlite};
+
+const CHAPTER_ONE: &str = "Mara paced the Harbour. Ivo Brand watched her go.";
+const CHAPTER_TWO: &str = "🌊 Captain Mara Voss sailed at dawn, sworn to the Tide Compact.";
+
+const CHAPTER_ONE_REPLY: &str = r#"{"entries": [
+    {"kind": "character", "name": "Mara", "description": "Restless.", "quote": "Mara paced"},
+    {"kind": "location", "name": "The Harbour", "category": "port", "description": "Where Mara paces.", "quote": "the Harbour"},
+    {"kind": "character", "name": "Ivo Brand", "description": "Watches Mara.", "quote": "Ivo Brand watched"},
+    {"kind": "character", "name": "Ghost", "description": "Never appears.", "quote": "a ghost drifted by"}
+]}"#;
+
+const CHAPTER_TWO_REPLY: &str = r#"{"entries": [
+    {"kind": "character", "name": "Mara Voss", "category": "Protagonist", "description": "A ship's captain.", "quote": "Captain Mara Voss"},
+    {"kind": "world_element", "name": "Tide Compact", "category": "Organization", "description": "An oath of sailors.", "quote": "the Tide Compact"}
+]}"#;
+
+async fn test_pool() -> (Pool<Sqlite>, String) {
+    let pool = crate::tests::test_pool().await;
+    let project_id = test_project(&pool, "Saltmarch").await;
+    (pool, project_id)
+}
+
+/// Ivo Brand and the Harbor are already in the story bible; the chapters
+/// are created out of order
+async fn create_manuscript(pool: &Pool<Sqlite>, project_id: &str) -> (Document, Document) {
+    let ivo = Character::new(project_id.to_string(), "Ivo Brand".to_string(), CharacterRole::Supporting);
+    CharacterOps::create(pool, ivo).await.unwrap();
+    let harbor = Location::new(project_id.to_string(), "Harbor".to_string(), LocationType::City);
+    LocationOps::create(pool, harbor).await.unwrap();
+
+    let mut chapters = Vec::new();
+    for (order_index, content) in [(1, CHAPTER_TWO), (0, CHAPTER_ONE)] {
+        let document = Document {
+            content: content.to_string(),
+            order_index,
+            ..Document::new(project_id.to_string(), format!("Chapter {}", order_index + 1), DocumentType::Chapter)
+        };
+        chapters.push(DocumentOps::create(pool, document).await.unwrap());
+    }
+    let empty = Document::new(project_id.to_string(), "Notes".to_string(), DocumentType::Notes);
+    DocumentOps::create(pool, empty).await.unwrap();
+
+    let two = chapters.remove(0);
+    (chapters.remove(0), two)
+}
+
+fn find<'a>(proposals: &'a [StoryBibleProposal], name: &str) -> &'a StoryBibleProposal {
+    proposals
+        .iter()
+        .find(|proposal| proposal.name == name)
+        .unwrap_or_else(|| panic!("no proposal named {:?} in {:#?}", name, proposals))
+}
+
+fn quoted(document: &Document, start: usize, end: usize) -> String {
+    document.content.chars().skip(start).take(end - start).collect()
+}
+
+#[test]
+fn test_name_similarity_ignores_case_articles_and_partial_names() {
+    assert_eq!(name_similarity("The Harbor", "harbor"), 1.0);
+    assert_eq!(name_similarity("Mara", "Mara Voss"), 0.8);
+    assert!(name_similarity("Harbor", "Harbour") > 0.75);
+    assert!(name_similarity("Mara Voss", "Mara Lind") < 0.75);
+    assert_eq!(name_similarity("", "Mara"), 0.0);
+}
+
+#[tokio::test]
+async fn test_extraction_merges_evidence_across_chapters_and_skips_known_entries() {
+    let (pool, project_id) = test_pool().await;
+    let (one, two) = create_manuscript(&pool, &project_id).await;
+    let extraction = create_story_bible_extraction(&pool, &project_id).await.unwrap();
+    assert_eq!(extraction.document_count, 2);
+
+    let provider = MockProvider::new()
+        .on(&WritingFeature::Write, ScriptedResponse::text(CHAPTER_ONE_REPLY))
+        .on(&WritingFeature::Write, ScriptedResponse::text(CHAPTER_TWO_REPLY));
+    let mut progress = Vec::new();
+    let done = run_story_bible_extraction(&pool, &provider, &extraction.id, None, &mut |done, total| {
+        progress.push((done, total))
+    })
+    .await
+    .unwrap();
+    assert_eq!(done.status, ExtractionStatus::Completed);
+    assert_eq!(progress, vec![(1, 2), (2, 2)]);
+
+    // Chapter one is read first, and the story bible's names are left out
+    let calls = provider.calls();
+    assert_eq!(calls.len(), 2);
+    assert!(calls[0].input.contains("Mara paced"));
+    assert!(calls[0].input.contains("Ivo Brand"));
+
+    // Ivo Brand is already known and the ghost's quote isn't in the text
+    let proposals = StoryBibleExtractionOps::get_proposals(&pool, &extraction.id).await.unwrap();
+    assert_eq!(proposals.len(), 3, "{:#?}", proposals);
+
+    let mara = find(&proposals, "Mara Voss");
+    assert_eq!(mara.entity_type, ProposalType::Character);
+    assert_eq!(mara.category.as_deref(), Some("protagonist"));
+    assert_eq!(mara.description.as_deref(), Some("Restless. A ship's captain."));
+    assert_eq!(mara.status, ProposalStatus::Pending);
+    let cited: Vec<(&str, &str)> =
+        mara.citations.iter().map(|c| (c.document_id.as_str(), c.excerpt.as_str())).collect();
+    assert_eq!(cited, vec![(one.id.as_str(), "Mara paced"), (two.id.as_str(), "Captain Mara Voss")]);
+    let citation = &mara.citations[1];
+    assert_eq!((citation.start, citation.end), (2, 19));
+    assert_eq!(quoted(&two, citation.start, citation.end), "Captain Mara Voss");
+
+    // Close to the Harbor, so flagged for the writer rather than dropped
+    let harbour = find(&proposals, "The Harbour");
+    assert_eq!(harbour.possible_duplicate_name.as_deref(), Some("Harbor"));
+    assert!(mara.possible_duplicate_id.is_none());
+
+    // A completed extraction isn't read again
+    let idle = MockProvider::new().strict();
+    run_story_bible_extraction(&pool, &idle, &extraction.id, None, &mut |_, _| {}).await.unwrap();
+    assert!(idle.calls().is_empty());
+}
+
+#[tokio::test]
+async fn test_failed_extraction_resumes_at_the_next_document() {
+    let (pool, project_id) = test_pool().await;
+    let (one, two) = create_manuscript(&pool, &project_id).await;
+    let extraction = create_story_bible_extraction(&pool, &project_id).await.unwrap();
+
+    let failing = MockProvider::new()
+        .on(&WritingFeature::Write, ScriptedResponse::text(CHAPTER_ONE_REPLY))
+        .on(&WritingFeature::Write, ScriptedResponse::error(500, "overloaded"));
+    let result = run_story_bible_extraction(&pool, &failing, &extraction.id, None, &mut |_, _| {}).await;
+    assert!(result.is_err());
+
+    let failed = StoryBibleExtractionOps::get(&pool, &extraction.id).await.unwrap();
+    assert_eq!(failed.status, ExtractionStatus::Failed);
+    assert!(failed.error_message.is_some());
+    let scanned = StoryBibleExtractionOps::get_scanned_documents(&pool, &extraction.id).await.unwrap();
+    assert_eq!(scanned, vec![one.id.clone()]);
+    assert_eq!(StoryBibleExtractionOps::get_proposals(&pool, &extraction.id).await.unwrap().len(), 2);
+
+    // Only chapter two is read, and its evidence joins chapter one's
+    let provider = MockProvider::new().on(&WritingFeature::Write, ScriptedResponse::text(CHAPTER_TWO_REPLY));
+    let mut progress = Vec::new();
+    run_story_bible_extraction(&pool, &provider, &extraction.id, None, &mut |done, total| progress.push((done, total)))
+        .await
+        .unwrap();
+    assert_eq!(progress, vec![(2, 2)]);
+    assert_eq!(provider.calls().len(), 1);
+    assert!(provider.calls()[0].input.contains("Tide Compact"));
+
+    let proposals = StoryBibleExtractionOps::get_proposals(&pool, &extraction.id).await.unwrap();
+    assert_eq!(proposals.len(), 3);
+    let mara = find(&proposals, "Mara Voss");
+    assert_eq!(mara.citations.len(), 2);
+    assert_eq!(mara.citations[1].document_id, two.id);
+}
+
+#[tokio::test]
+async fn test_reviewed_proposals_are_written_only_when_accepted() {
+    let (pool, project_id) = test_pool().await;
+    create_manuscript(&pool, &project_id).await;
+    let extraction = create_story_bible_extraction(&pool, &project_id).await.unwrap();
+    let provider = MockProvider::new()
+        .on(&WritingFeature::Write, ScriptedResponse::text(CHAPTER_ONE_REPLY))
+        .on(&WritingFeature::Write, ScriptedResponse::text(CHAPTER_TWO_REPLY));
+    run_story_bible_extraction(&pool, &provider, &extraction.id, None, &mut |_, _| {}).await.unwrap();
+    let proposals = StoryBibleExtractionOps::get_proposals(&pool, &extraction.id).await.unwrap();
+
+    let mara = find(&proposals, "Mara Voss");
+    let accepted = StoryBibleExtractionOps::accept_proposal(&pool, &mara.id, None, Some("Captain of the Gull.".to_string()))
+        .await
+        .unwrap();
+    assert_eq!(accepted.status, ProposalStatus::Accepted);
+    let characters = CharacterOps::get_by_project(&pool, &project_id).await.unwrap();
+    let character = characters.iter().find(|c| Some(&c.id) == accepted.created_entity_id.as_ref()).unwrap();
+    assert_eq!(character.name, "Mara Voss");
+    assert!(matches!(character.role, CharacterRole::Protagonist));
+    assert_eq!(character.description.as_deref(), Some("Captain of the Gull."));
+    assert!(StoryBibleExtractionOps::accept_proposal(&pool, &mara.id, None, None).await.is_err());
+
+    let harbour = find(&proposals, "The Harbour");
+    StoryBibleExtractionOps::accept_proposal(&pool, &harbour.id, Some("Saltmarch Harbour".to_string()), None)
+        .await
+        .unwrap();
+    let locations = LocationOps::get_by_project(&pool, &project_id).await.unwrap();
+    let created = locations.iter().find(|location| location.name == "Saltmarch Harbour").unwrap();
+    assert!(matches!(created.location_type, LocationType::City));
+
+    let compact = find(&proposals, "Tide Compact");
+    let rejected = StoryBibleExtractionOps::reject_proposal(&pool, &compact.id).await.unwrap();
+    assert_eq!(rejected.status, ProposalStatus::Rejected);
+    assert!(rejected.created_entity_id.is_none());
+    assert!(WorldElementOps::get_by_project(&pool, &project_id).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_accepting_a_proposal_twice_at_once_writes_one_entity() {
+    let (pool, project_id) = test_pool().await;
+    create_manuscript(&pool, &project_id).await;
+    let extraction = create_story_bible_extraction(&pool, &project_id).await.unwrap();
+    let provider = MockProvider::new()
+        .on(&WritingFeature::Write, ScriptedResponse::text(CHAPTER_ONE_REPLY))
+        .on(&WritingFeature::Write, ScriptedResponse::text(CHAPTER_TWO_REPLY));
+    run_story_bible_extraction(&pool, &provider, &extraction.id, None, &mut |_, _| {}).await.unwrap();
+    let proposals = StoryBibleExtractionOps::get_proposals(&pool, &extraction.id).await.unwrap();
+    let mara = find(&proposals, "Mara Voss");
+
+    let (first, second) = tokio::join!(
+        StoryBibleExtractionOps::accept_proposal(&pool, &mara.id, None, None),
+        StoryBibleExtractionOps::accept_proposal(&pool, &mara.id, None, None),
+    );
+
+    assert!(first.is_ok() != second.is_ok());
+    let characters = CharacterOps::get_by_project(&pool, &project_id).await.unwrap();
+    assert_eq!(characters.iter().filter(|c| c.name == "Mara Voss").count(), 1);
+}
@@ -0,0 +1,146 @@
+//! Tests for rolling scene, chapter and book summaries
+
+use crate::ai::saliency_engine::{annotate_from_database, SaliencyConfig, SaliencyEngine, StoryBibleElements};
+use crate::ai::{refresh_story_summaries, summary_context, MockProvider, ScriptedResponse, SummaryRefresh, WritingFeature};
+use crate::database::models::{Document, DocumentType};
+use crate::database::operations::{DocumentOps, StorySummaryOps, SummaryLevel};
+use crate::tests::test_project;
+use sqlx::{Pool, Sqlite};
+
+async fn test_pool() -> (Pool<Sqlite>, String) {
+    let pool = crate::tests::test_pool().await;
+    let project_id = test_project(&pool, "Saga").await;
+    (pool, project_id)
+}
+
+async fn document(
+    pool: &Pool<Sqlite>,
+    project_id: &str,
+    title: &str,
+    document_type: DocumentType,
+    parent_id: Option<&str>,
+    order_index: i32,
+    content: &str,
+) -> Document {
+    let mut document = Document::new(project_id.to_string(), title.to_string(), document_type);
+    document.parent_id = parent_id.map(str::to_string);
+    document.order_index = order_index;
+    document.content = content.to_string();
+    DocumentOps::create(pool, document).await.unwrap()
+}
+
+/// Chapter 1 told in two scenes, chapter 2 written straight into the chapter
+async fn manuscript(pool: &Pool<Sqlite>, project_id: &str) -> (Document, Document, Document, Document) {
+    let harbor = document(pool, project_id, "The Harbor", DocumentType::Chapter, None, 0, "").await;
+    let arrival = document(pool, project_id, "Arrival", DocumentType::Scene, Some(&harbor.id), 1, "Mara sails into Greyhaven.").await;
+    let tavern = document(pool, project_id, "Tavern", DocumentType::Scene, Some(&harbor.id), 2, "Mara meets the smuggler.").await;
+    let storm = document(pool, project_id, "The Storm", DocumentType::Chapter, None, 3, "The storm sinks the fleet.").await;
+    (harbor, arrival, tavern, storm)
+}
+
+fn replies(texts: &[&str]) -> MockProvider {
+    texts
+        .iter()
+        .fold(MockProvider::new(), |provider, text| provider.on(&WritingFeature::Write, ScriptedResponse::text(text)))
+}
+
+#[tokio::test]
+async fn test_only_changed_documents_and_their_parents_are_summarized_again() {
+    let (pool, project_id) = test_pool().await;
+    let (harbor, _, mut tavern, storm) = manuscript(&pool, &project_id).await;
+
+    // Scenes, then their chapter, the next chapter, and the book
+    let provider = replies(&["Arrival recap.", "Tavern recap.", "Harbor recap.", "Storm recap.", "Book recap."]);
+    let refresh = refresh_story_summaries(&pool, &provider, &project_id).await.unwrap();
+    assert_eq!(refresh, SummaryRefresh { summarized: 5, unchanged: 0, removed: 0 });
+    let calls = provider.calls();
+    assert!(calls[0].input.contains("Mara sails into Greyhaven."));
+    assert!(calls[2].input.contains("Arrival recap.\n\nTavern recap."));
+    assert!(calls[3].input.contains("The storm sinks the fleet."));
+    assert!(calls[4].input.contains("The Harbor: Harbor recap.\n\nThe Storm: Storm recap."));
+
+    let stored = StorySummaryOps::get_by_project(&pool, &project_id).await.unwrap();
+    let levels: Vec<SummaryLevel> = stored.iter().map(|summary| summary.level).collect();
+    assert_eq!(
+        levels,
+        vec![SummaryLevel::Book, SummaryLevel::Chapter, SummaryLevel::Chapter, SummaryLevel::Scene, SummaryLevel::Scene]
+    );
+    assert_eq!(stored[1].source_id, harbor.id);
+    assert_eq!(stored[4].parent_id.as_deref(), Some(harbor.id.as_str()));
+    assert_eq!(stored[4].position, 1);
+
+    // Nothing changed, so nothing is sent
+    let provider = replies(&["Unused."]);
+    let refresh = refresh_story_summaries(&pool, &provider, &project_id).await.unwrap();
+    assert_eq!(refresh, SummaryRefresh { summarized: 0, unchanged: 5, removed: 0 });
+    assert!(provider.calls().is_empty());
+
+    // An edited scene is summarized again along with its chapter and the book
+    tavern.content = "Mara fights the smuggler.".to_string();
+    DocumentOps::update(&pool, &tavern).await.unwrap();
+    let provider = replies(&["Fight recap.", "New harbor recap.", "New book recap."]);
+    let refresh = refresh_story_summaries(&pool, &provider, &project_id).await.unwrap();
+    assert_eq!(refresh, SummaryRefresh { summarized: 3, unchanged: 2, removed: 0 });
+    assert!(provider.calls()[0].input.contains("Mara fights the smuggler."));
+    let chapter = StorySummaryOps::get(&pool, SummaryLevel::Chapter, &harbor.id).await.unwrap().unwrap();
+    assert_eq!(chapter.summary, "New harbor recap.");
+
+    // A deleted chapter's summary goes, and the book is rewritten without it
+    DocumentOps::delete(&pool, &storm.id).await.unwrap();
+    let provider = replies(&["Shorter book recap."]);
+    let refresh = refresh_story_summaries(&pool, &provider, &project_id).await.unwrap();
+    assert_eq!(refresh, SummaryRefresh { summarized: 1, unchanged: 3, removed: 1 });
+    assert!(!provider.calls()[0].input.contains("Storm recap."));
+    assert!(StorySummaryOps::get(&pool, SummaryLevel::Chapter, &storm.id).await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn test_summary_context_covers_the_book_and_nearby_chapters() {
+    let (pool, project_id) = test_pool().await;
+    let (_, arrival, _, storm) = manuscript(&pool, &project_id).await;
+    assert_eq!(summary_context(&pool, &project_id, Some(&storm.id)).await.unwrap(), None);
+
+    let provider = replies(&["Arrival recap.", "Tavern recap.", "Harbor recap.", "Storm recap.", "Book recap."]);
+    refresh_story_summaries(&pool, &provider, &project_id).await.unwrap();
+
+    let context = summary_context(&pool, &project_id, Some(&storm.id)).await.unwrap().unwrap();
+    assert_eq!(
+        context,
+        "Story summary: Book recap.\n\nPrevious chapter (The Harbor): Harbor recap.\n\nThis chapter (The Storm): Storm recap."
+    );
+
+    // A scene is written within its chapter
+    let context = summary_context(&pool, &project_id, Some(&arrival.id)).await.unwrap().unwrap();
+    assert_eq!(context, "Story summary: Book recap.\n\nThis chapter (The Harbor): Harbor recap.");
+}
+
+#[tokio::test]
+async fn test_saliency_context_leads_with_the_story_summary() {
+    let (pool, project_id) = test_pool().await;
+    let (_, _, _, storm) = manuscript(&pool, &project_id).await;
+    let provider = replies(&["Arrival recap.", "Tavern recap.", "Harbor recap.", "Storm recap.", "Book recap."]);
+    refresh_story_summaries(&pool, &provider, &project_id).await.unwrap();
+
+    let mut bible = StoryBibleElements {
+        characters: Vec::new(),
+        locations: Vec::new(),
+        plot_threads: Vec::new(),
+        worldbuilding: Vec::new(),
+        current_chapter: None,
+        story_summary: None,
+    };
+    annotate_from_database(&pool, &project_id, Some(&storm.id), &mut bible).await.unwrap();
+    assert!(bible.story_summary.as_deref().unwrap().contains("Storm recap."));
+
+    let mut engine = SaliencyEngine::default();
+    let context = engine.build_context(&project_id, "The fleet went down.", &bible).await.unwrap();
+    assert!(context.total_tokens > 0);
+    let formatted = engine.format_context_for_ai(&context);
+    assert!(formatted.starts_with("\n## Story So Far:\nStory summary: Book recap."));
+
+    // A summary larger than the whole budget is left out
+    let mut engine = SaliencyEngine::new(SaliencyConfig { max_total_tokens: 5, ..SaliencyConfig::default() });
+    let context = engine.build_context(&project_id, "The fleet went down.", &bible).await.unwrap();
+    assert_eq!(context.story_summary, None);
+    assert!(!engine.format_context_for_ai(&context).contains("Story So Far"));
+}
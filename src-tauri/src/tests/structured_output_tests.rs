@@ -0,0 +1,80 @@
+//! Tests for schema-validated structured generation
+
+use crate::ai::structured::MAX_STRUCTURED_ATTEMPTS;
+use crate::ai::{AIContext, AIProviderManager, MockProvider, ScriptedResponse, StructuredGeneration, WritingFeature};
+use crate::commands::story_bible_ai::{character_traits_schema, outline_schema, GeneratedOutline, GeneratedTraits};
+use crate::error::StoryWeaverError;
+use std::sync::Arc;
+
+#[tokio::test]
+async fn test_invalid_reply_is_reprompted_with_validation_errors() {
+    let provider = MockProvider::new()
+        .on(&WritingFeature::Write, ScriptedResponse::text(r#"{"traits": "stubborn"}"#))
+        .on(&WritingFeature::Write, ScriptedResponse::text(r#"{"traits": ["stubborn", "kind"]}"#));
+
+    let generated: GeneratedTraits = provider
+        .generate_structured("Traits for Mara", &character_traits_schema(3), &AIContext::default())
+        .await
+        .unwrap();
+
+    assert_eq!(generated.traits, vec!["stubborn", "kind"]);
+    let calls = provider.calls();
+    assert_eq!(calls.len(), 2);
+    assert!(calls[1].input.contains("did not match"));
+    assert!(calls[1].input.contains("$.traits: expected array, got string"));
+}
+
+#[tokio::test]
+async fn test_gives_up_after_max_attempts() {
+    let provider = MockProvider::new().on(&WritingFeature::Write, ScriptedResponse::text("Mara is stubborn and kind."));
+
+    let result: crate::error::Result<GeneratedTraits> = provider
+        .generate_structured("Traits for Mara", &character_traits_schema(3), &AIContext::default())
+        .await;
+
+    assert!(matches!(result, Err(StoryWeaverError::AIGenerationError { .. })));
+    assert_eq!(provider.calls().len(), MAX_STRUCTURED_ATTEMPTS);
+}
+
+#[tokio::test]
+async fn test_fenced_reply_is_repaired_without_reprompting() {
+    let provider = MockProvider::new().on(
+        &WritingFeature::Write,
+        ScriptedResponse::text(
+            "Here is the outline:\n```json\n{\"title\": \"Salt\", \"chapters\": [\
+             {\"chapter_number\": 1, \"title\": \"Landfall\", \"summary\": \"Mara reaches the harbor.\"},]}\n```",
+        ),
+    );
+
+    let outline: GeneratedOutline =
+        provider.generate_structured("Outline", &outline_schema(), &AIContext::default()).await.unwrap();
+
+    assert_eq!(outline.title, "Salt");
+    assert_eq!(outline.chapters[0].chapter_number, 1);
+    assert_eq!(provider.calls().len(), 1);
+}
+
+#[tokio::test]
+async fn test_manager_routes_structured_requests() {
+    let provider = Arc::new(
+        MockProvider::new().on(&WritingFeature::Write, ScriptedResponse::text(r#"{"traits": ["wry"]}"#)),
+    );
+    let mut manager = AIProviderManager::new();
+    manager.register_provider("mock".to_string(), provider.clone());
+    manager.set_default_provider("mock".to_string());
+
+    let generated: GeneratedTraits = manager
+        .generate_structured("Traits for Pell", &character_traits_schema(2), &AIContext::default())
+        .await
+        .unwrap();
+
+    assert_eq!(generated.traits, vec!["wry"]);
+    assert!(provider.calls()[0].input.contains("JSON Schema"));
+}
+
+#[test]
+fn test_trait_schema_caps_the_trait_count() {
+    let schema = character_traits_schema(2);
+    let errors = schema.validate(&serde_json::json!({ "traits": ["a", "b", "c"] })).unwrap_err();
+    assert_eq!(errors, vec!["$.traits: expected at most 2 item(s), got 3".to_string()]);
+}
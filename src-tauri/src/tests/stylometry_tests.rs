@@ -0,0 +1,104 @@
+//! Tests for local stylometry and style drift scoring
+
+use crate::ai::stylometry::{project_fingerprint, StyleDimension, StyleFingerprint, StyleProfile};
+use crate::database::models::StyleExample;
+use crate::database::operations::StyleExampleOps;
+use crate::tests::{test_pool, test_project};
+
+/// First person, short sentences, plenty of dialogue
+const EXAMPLE_DOCKS: &str = "\"Where are you going?\" I asked. She didn't answer. I followed her down to the docks, \
+    and the rain kept falling. \"Home,\" she said at last. I laughed. We walked on in the dark, and I held my coat \
+    shut against the wind.";
+const EXAMPLE_LETTER: &str = "I found the letter on Tuesday. It was short. \"Burn this,\" it said, and I almost did. \
+    My hands shook. \"You should tell her,\" my brother said. I didn't. We ate in silence, and the clock ticked on \
+    the wall.";
+
+/// The same voice on a different night
+const SAME_VOICE: &str = "\"Are you sure?\" I asked. He nodded. I took the key from the table and went out into the \
+    cold. \"Don't wait up,\" I said. We both knew I would. The street was empty, and my boots rang on the stones.";
+
+/// Long, ornate third-person narration with no dialogue
+const OTHER_VOICE: &str = "The ancient city, with its labyrinthine streets and towering spires, stood as a testament \
+    to the enduring ambitions of those who had built it; its merchants, scholars and priests moved through the \
+    crowded markets with a quiet determination that spoke of centuries of tradition, while the distant mountains, \
+    shrouded in mist and mystery, watched over everything with an indifferent patience that seemed almost divine.";
+
+fn close(actual: f32, expected: f32) -> bool {
+    (actual - expected).abs() < 1e-4
+}
+
+#[test]
+fn test_profile_measures_sentences_dialogue_pronouns_and_punctuation() {
+    let profile = StyleProfile::measure("\"Come here,\" she said. He ran; she followed. I waited (quietly) -- and then?");
+
+    assert_eq!(profile.word_count, 13);
+    assert_eq!(profile.sentence_count, 3);
+    assert!(close(profile.sentence_length_mean, 13.0 / 3.0));
+    assert!(close(profile.sentence_length_distribution[0], 1.0));
+    assert!(close(profile.dialogue_ratio, 2.0 / 13.0));
+    assert!(close(profile.lexical_density, 6.0 / 13.0));
+    assert!(close(profile.pov_pronouns.first_person, 0.25));
+    assert!(close(profile.pov_pronouns.third_person, 0.75));
+
+    let per_use = 1000.0 / 13.0;
+    for mark in ["comma", "semicolon", "dash", "parenthesis", "question"] {
+        assert!(close(profile.punctuation[mark], per_use), "{}", mark);
+    }
+    assert!(close(profile.punctuation["exclamation"], 0.0));
+    assert!(close(profile.function_words["and"], per_use));
+    assert!(close(profile.function_words["the"], 0.0));
+}
+
+#[test]
+fn test_abbreviations_do_not_end_sentences() {
+    let profile = StyleProfile::measure("Dr. Vale opened the door. It creaked.");
+    assert_eq!(profile.sentence_count, 2);
+    assert!(close(profile.sentence_length_mean, 3.5));
+}
+
+#[test]
+fn test_drift_flags_passages_that_do_not_sound_like_the_examples() {
+    let fingerprint = StyleFingerprint::from_examples("p1", &[EXAMPLE_DOCKS, EXAMPLE_LETTER]);
+    assert_eq!(fingerprint.example_count, 2);
+
+    let same = fingerprint.score(&[SAME_VOICE; 4].join(" "));
+    assert!(same.reliable);
+    assert!(!same.flagged, "{:?}", same);
+
+    let other = fingerprint.score(&[OTHER_VOICE; 3].join(" "));
+    assert!(other.reliable);
+    assert!(other.flagged, "{:?}", other);
+    assert!(other.score > same.score);
+    let sentences = other.dimensions.iter().find(|d| d.dimension == StyleDimension::SentenceLength).unwrap();
+    assert!(sentences.score > 0.9);
+    assert!(sentences.note.contains("the examples average"));
+    assert!(other.dimensions.windows(2).all(|pair| pair[0].score >= pair[1].score));
+
+    // Scoring is deterministic
+    assert_eq!(fingerprint.score(OTHER_VOICE).score, fingerprint.score(OTHER_VOICE).score);
+}
+
+#[test]
+fn test_short_passages_are_never_flagged() {
+    let fingerprint = StyleFingerprint::from_examples("p1", &[EXAMPLE_DOCKS, EXAMPLE_LETTER]);
+    let drift = fingerprint.score(OTHER_VOICE);
+    assert!(!drift.reliable);
+    assert!(!drift.flagged);
+    assert!(drift.score > 0.0);
+}
+
+#[tokio::test]
+async fn test_project_fingerprint_comes_from_its_style_examples() {
+    let pool = test_pool().await;
+    let project_id = test_project(&pool, "Saga").await;
+
+    assert!(project_fingerprint(&pool, &project_id).await.unwrap().is_none());
+
+    for text in [EXAMPLE_DOCKS, EXAMPLE_LETTER] {
+        StyleExampleOps::create(&pool, StyleExample::new(project_id.clone(), None, text.to_string())).await.unwrap();
+    }
+    let fingerprint = project_fingerprint(&pool, &project_id).await.unwrap().unwrap();
+    assert_eq!(fingerprint.example_count, 2);
+    assert!(fingerprint.profile.pov_pronouns.first_person > 0.5);
+    assert!(fingerprint.profile.dialogue_ratio > 0.0);
+}
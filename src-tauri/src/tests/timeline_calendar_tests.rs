@@ -0,0 +1,222 @@
+//! Tests for story calendars and timeline validation
+
+use crate::database::models::{
+    Character, CharacterRole, EventImportance, Location, LocationType, TimelineEvent, VisibilityLevel,
+};
+use crate::database::operations::{CharacterOps, LocationOps, StoryCalendarOps, TimelineOps};
+use crate::tests::test_project;
+use crate::timeline::{
+    validate_timeline, CalendarEra, CalendarMonth, DatePrecision, StoryCalendar, TimelineIssueKind,
+};
+use serde_json::json;
+use sqlx::{Pool, Sqlite};
+
+async fn test_pool() -> (Pool<Sqlite>, String) {
+    let pool = crate::tests::test_pool().await;
+    let project_id = test_project(&pool, "Saga").await;
+    (pool, project_id)
+}
+
+struct Event<'a> {
+    title: &'a str,
+    date: Option<&'a str>,
+    characters: &'a [&'a str],
+    locations: &'a [&'a str],
+    deaths: &'a [&'a str],
+    duration_minutes: Option<i64>,
+}
+
+impl<'a> Event<'a> {
+    fn on(title: &'a str, date: &'a str) -> Self {
+        Self { title, date: Some(date), characters: &[], locations: &[], deaths: &[], duration_minutes: None }
+    }
+}
+
+async fn create_event(pool: &Pool<Sqlite>, project_id: &str, event: Event<'_>) -> TimelineEvent {
+    TimelineOps::create(
+        pool,
+        TimelineEvent {
+            id: String::new(),
+            project_id: project_id.to_string(),
+            title: event.title.to_string(),
+            description: None,
+            event_date: event.date.map(str::to_string),
+            real_date: None,
+            importance: EventImportance::Major,
+            characters_involved: json!(event.characters).to_string(),
+            locations_involved: json!(event.locations).to_string(),
+            visibility: VisibilityLevel::Relevant,
+            created_at: chrono::Utc::now(),
+            updated_at: chrono::Utc::now(),
+            event_instant: None,
+            duration_minutes: event.duration_minutes,
+            deaths: json!(event.deaths).to_string(),
+        },
+    )
+    .await
+    .unwrap()
+}
+
+fn fantasy_calendar() -> StoryCalendar {
+    StoryCalendar::custom(
+        vec![
+            CalendarMonth { name: "Frostfall".to_string(), days: 30 },
+            CalendarMonth { name: "Deep Thaw".to_string(), days: 40 },
+        ],
+        vec![
+            CalendarEra { name: "First Age".to_string(), abbreviation: Some("FA".to_string()), start_year: 1 },
+            CalendarEra { name: "Third Age".to_string(), abbreviation: Some("TA".to_string()), start_year: 1001 },
+        ],
+    )
+}
+
+#[test]
+fn test_gregorian_dates_normalize_and_sort() {
+    let calendar = StoryCalendar::gregorian();
+
+    let written = ["March 12, 1204", "12th March 1204", "1204-03-12", "12 Mar 1204 AD"];
+    let dates: Vec<_> = written.iter().map(|date| calendar.parse(date).unwrap()).collect();
+    assert!(dates.iter().all(|date| date.instant == dates[0].instant && date.normalized == "12 March 1204"));
+    assert_eq!(dates[0].precision, DatePrecision::Day);
+
+    let with_time = calendar.parse("1204-03-12T14:05").unwrap();
+    assert_eq!(with_time.instant - dates[0].instant, 14 * 60 + 5);
+    assert_eq!(with_time.normalized, "12 March 1204 14:05");
+
+    assert_eq!(calendar.parse("March 1204").unwrap().precision, DatePrecision::Month);
+    assert_eq!(calendar.parse("1 January 1").unwrap().instant, 0);
+    assert_eq!(calendar.parse("31 December 1 BC").unwrap().instant, -24 * 60);
+    assert_eq!(calendar.parse("44 BC").unwrap().normalized, "44 BC");
+    assert_eq!(calendar.parse("1 March 2000").unwrap().instant - calendar.parse("28 February 2000").unwrap().instant, 2 * 24 * 60);
+
+    assert!(calendar.parse("29 February 1900").is_err());
+    assert!(calendar.parse("Smarch 3 1204").is_err());
+    assert!(calendar.parse("1204-03-12 25:00").is_err());
+}
+
+#[test]
+fn test_custom_and_relative_calendars() {
+    let calendar = fantasy_calendar();
+    calendar.validate().unwrap();
+
+    let in_era = calendar.parse("3 Frostfall 312 TA").unwrap();
+    assert_eq!(in_era.instant, calendar.parse("3 frostfall 1312").unwrap().instant);
+    assert_eq!(in_era.normalized, "3 Frostfall 312 TA");
+    // Each year is 70 days long
+    assert_eq!(in_era.instant, (1311 * 70 + 2) * 24 * 60);
+
+    let vague = calendar.parse("Year 312 of the Third Age").unwrap();
+    assert_eq!(vague.precision, DatePrecision::Year);
+    assert_eq!(vague.normalized, "312 TA");
+    assert_eq!(calendar.parse("Deep Thaw 5 FA").unwrap().normalized, "Deep Thaw 5 FA");
+    assert!(calendar.parse("41 Deep Thaw 3").is_err());
+    assert!(calendar.parse("3 March 1204").is_err());
+
+    let mut duplicate = fantasy_calendar();
+    duplicate.months.push(CalendarMonth { name: "frostfall".to_string(), days: 10 });
+    assert!(duplicate.validate().is_err());
+    assert!(StoryCalendar::custom(Vec::new(), Vec::new()).validate().is_err());
+
+    let relative = StoryCalendar::relative();
+    let day = relative.parse("Day 3 14:00").unwrap();
+    assert_eq!(day.instant, 2 * 24 * 60 + 14 * 60);
+    assert_eq!(day.normalized, "Day 3 14:00");
+    assert!(relative.parse("12 March 1204").is_err());
+}
+
+#[test]
+fn test_durations() {
+    let calendar = StoryCalendar::gregorian();
+    assert_eq!(calendar.parse_duration("3 days").unwrap(), 3 * 24 * 60);
+    assert_eq!(calendar.parse_duration("2h30m").unwrap(), 150);
+    assert_eq!(calendar.parse_duration("1 week, 2 days and 3 hours").unwrap(), 13_140);
+    assert_eq!(calendar.format_duration(13_140), "9 days 3 hours");
+    assert_eq!(fantasy_calendar().parse_duration("1 year").unwrap(), 70 * 24 * 60);
+    assert!(calendar.parse_duration("soon").is_err());
+    assert!(calendar.parse_duration("3").is_err());
+}
+
+#[tokio::test]
+async fn test_events_sort_by_calendar_and_follow_calendar_changes() {
+    let (pool, project_id) = test_pool().await;
+    for (title, date) in [("Coronation", "1 May 1205"), ("Flood", "44 BC"), ("Siege", "March 1204"), ("Omen", "soonish")] {
+        create_event(&pool, &project_id, Event::on(title, date)).await;
+    }
+
+    let events = TimelineOps::get_by_project(&pool, &project_id).await.unwrap();
+    let titles: Vec<&str> = events.iter().map(|e| e.title.as_str()).collect();
+    assert_eq!(titles, vec!["Flood", "Siege", "Coronation", "Omen"]);
+    assert_eq!(events[3].event_instant, None);
+
+    // A relative calendar can't read any of them
+    let unreadable = StoryCalendarOps::set(&pool, &project_id, &StoryCalendar::relative()).await.unwrap();
+    assert_eq!(unreadable, 4);
+    assert_eq!(StoryCalendarOps::get(&pool, &project_id).await.unwrap(), StoryCalendar::relative());
+    create_event(&pool, &project_id, Event::on("Arrival", "Day 2")).await;
+    let events = TimelineOps::get_by_project(&pool, &project_id).await.unwrap();
+    assert_eq!(events[0].title, "Arrival");
+    assert_eq!(events[0].event_instant, Some(24 * 60));
+
+    let mut invalid = fantasy_calendar();
+    invalid.hours_per_day = 0;
+    assert!(StoryCalendarOps::set(&pool, &project_id, &invalid).await.is_err());
+}
+
+#[tokio::test]
+async fn test_validation_flags_impossible_events() {
+    let (pool, project_id) = test_pool().await;
+    let mara = CharacterOps::create(&pool, Character::new(project_id.clone(), "Mara".to_string(), CharacterRole::Protagonist))
+        .await
+        .unwrap();
+    let ivo = CharacterOps::create(&pool, Character::new(project_id.clone(), "Ivo".to_string(), CharacterRole::Supporting))
+        .await
+        .unwrap();
+    let harbor = LocationOps::create(&pool, Location::new(project_id.clone(), "Harbor".to_string(), LocationType::City))
+        .await
+        .unwrap();
+    let keep = LocationOps::create(&pool, Location::new(project_id.clone(), "Keep".to_string(), LocationType::Building))
+        .await
+        .unwrap();
+    let (mara, ivo, harbor, keep) = (mara.id.as_str(), ivo.id.as_str(), harbor.id.as_str(), keep.id.as_str());
+
+    // A three-hour watch at the harbor overlaps a council at the keep
+    let watch = Event {
+        characters: &[mara],
+        locations: &[harbor],
+        duration_minutes: Some(180),
+        ..Event::on("Harbor watch", "12 March 1204 09:00")
+    };
+    let watch = create_event(&pool, &project_id, watch).await;
+    let council = Event { characters: &[mara], locations: &[keep], ..Event::on("Council", "12 March 1204 10:30") };
+    let council = create_event(&pool, &project_id, council).await;
+    // Two places on the same day, at unknown times, could both happen
+    create_event(&pool, &project_id, Event { characters: &[ivo], locations: &[harbor], ..Event::on("Feast", "13 March 1204") }).await;
+    create_event(&pool, &project_id, Event { characters: &[ivo], locations: &[keep], ..Event::on("Hunt", "13 March 1204") }).await;
+
+    let duel = Event { characters: &[mara, ivo], deaths: &[ivo], ..Event::on("Duel", "20 March 1204") };
+    let duel = create_event(&pool, &project_id, duel).await;
+    // Later the same day is still the day he died
+    create_event(&pool, &project_id, Event { characters: &[ivo], ..Event::on("Last words", "20 March 1204 18:00") }).await;
+    let sighting = create_event(&pool, &project_id, Event { characters: &[ivo], ..Event::on("Sighting", "April 1204") }).await;
+    create_event(&pool, &project_id, Event { deaths: &[ivo], ..Event::on("Ghost", "1 May 1204") }).await;
+    create_event(&pool, &project_id, Event::on("Prophecy", "soonish")).await;
+    create_event(&pool, &project_id, Event { date: None, ..Event::on("Someday", "") }).await;
+
+    let report = validate_timeline(&pool, &project_id).await.unwrap();
+    assert_eq!(report.events_checked, 10);
+    assert_eq!(report.undated, 1);
+    assert_eq!(report.issues.len(), 4, "{:?}", report.issues);
+
+    let issue = |kind| report.issues.iter().find(|issue| issue.kind == kind).unwrap();
+    let together = issue(TimelineIssueKind::SimultaneousLocations);
+    assert_eq!(together.event_ids, vec![watch.id.clone(), council.id.clone()]);
+    assert_eq!(together.description, "Mara is at Harbor for \"Harbor watch\" and at Keep for \"Council\" at the same time");
+
+    let after = issue(TimelineIssueKind::AfterDeath);
+    assert_eq!(after.event_ids, vec![duel.id.clone(), sighting.id.clone()]);
+    assert_eq!(after.character_id.as_deref(), Some(ivo));
+    assert_eq!(after.description, "Ivo takes part in \"Sighting\" (April 1204) after dying in \"Duel\" (20 March 1204)");
+
+    assert_eq!(issue(TimelineIssueKind::RepeatedDeath).event_ids.len(), 2);
+    assert!(issue(TimelineIssueKind::UnreadableDate).description.starts_with("\"Prophecy\""));
+}
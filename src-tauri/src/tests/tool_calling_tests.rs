@@ -0,0 +1,198 @@
+//! Tests for the tool-calling loop and the story bible tools
+
+use crate::ai::tools::run_tool_loop;
+use crate::ai::{AIContext, AIProviderManager, MockProvider, ScriptedResponse, StoryBibleTools, ToolCall, ToolExecutor, WritingFeature};
+use crate::commands::ai_writing::{WriteProcessor, WriteSettings};
+use crate::database::models::{
+    AIGenerationType, Character, CharacterRole, Document, DocumentType, EventImportance, Project, TimelineEvent,
+    VisibilityLevel,
+};
+use crate::database::operations::{AIHistoryOps, CharacterOps, DocumentOps, ProjectOps, TimelineOps};
+use crate::tests::test_pool;
+use serde_json::json;
+use sqlx::{Pool, Sqlite};
+use std::sync::Arc;
+
+/// A project with a visible captain and a hidden smuggler
+async fn seeded_project(pool: &Pool<Sqlite>) -> (Project, Character) {
+    let project = ProjectOps::create(pool, Project::new("Saga".to_string(), None)).await.unwrap();
+
+    let mut captain = Character::new(project.id.clone(), "Mara".to_string(), CharacterRole::Protagonist);
+    captain.personality = Some("Stubborn, dry-witted, never abandons her crew".to_string());
+    let captain = CharacterOps::create(pool, captain).await.unwrap();
+
+    let mut smuggler = Character::new(project.id.clone(), "Quill".to_string(), CharacterRole::Antagonist);
+    smuggler.visibility = VisibilityLevel::Hidden;
+    CharacterOps::create(pool, smuggler).await.unwrap();
+
+    (project, captain)
+}
+
+fn call(name: &str, arguments: serde_json::Value) -> ToolCall {
+    ToolCall { id: "call-1".to_string(), name: name.to_string(), arguments }
+}
+
+#[tokio::test]
+async fn test_loop_runs_requested_tools_and_logs_them() {
+    let pool = test_pool().await;
+    let (project, _) = seeded_project(&pool).await;
+    let provider = MockProvider::new()
+        .on(
+            &WritingFeature::Write,
+            ScriptedResponse::text(r#"{"tool_calls": [{"name": "lookup_character", "arguments": {"name": "Mara"}}]}"#),
+        )
+        .on(&WritingFeature::Write, ScriptedResponse::text("Mara refused to leave the wheel."));
+    let tools = StoryBibleTools::new(pool.clone(), &project.id).logged_as("mock", "mock-model");
+
+    let result = run_tool_loop(&provider, "Continue the storm scene", &AIContext::default(), &tools, 5)
+        .await
+        .unwrap();
+
+    assert_eq!(result.text, "Mara refused to leave the wheel.");
+    assert_eq!(result.rounds, 2);
+    assert_eq!(result.tool_calls.len(), 1);
+    assert!(!result.tool_calls[0].is_error);
+    assert!(result.tool_calls[0].output.contains("never abandons her crew"));
+
+    // The model saw the tool result on its second turn
+    let calls = provider.calls();
+    assert!(calls[1].input.contains("Result of lookup_character"));
+    assert!(calls[1].input.contains("never abandons her crew"));
+
+    let history = AIHistoryOps::get_by_project(&pool, &project.id, None).await.unwrap();
+    assert_eq!(history.len(), 1);
+    assert!(matches!(history[0].generation_type, AIGenerationType::ToolCall));
+    assert_eq!(history[0].model, "mock-model");
+    assert!(history[0].prompt.starts_with("lookup_character("));
+}
+
+#[tokio::test]
+async fn test_write_command_with_story_bible_tools_logs_lookups() {
+    let pool = test_pool().await;
+    let (project, _) = seeded_project(&pool).await;
+    let mut chapter = Document::new(project.id.clone(), "Chapter 1".to_string(), DocumentType::Chapter);
+    chapter.content = "The storm broke over the Gull.".to_string();
+    let chapter = DocumentOps::create(&pool, chapter).await.unwrap();
+    // The write commands address documents by number
+    sqlx::query("UPDATE documents SET id = '7' WHERE id = ?").bind(&chapter.id).execute(&pool).await.unwrap();
+
+    let provider = MockProvider::new()
+        .on(
+            &WritingFeature::Write,
+            ScriptedResponse::text(r#"{"tool_calls": [{"name": "lookup_character", "arguments": {"name": "Mara"}}]}"#),
+        )
+        .on(&WritingFeature::Write, ScriptedResponse::text("Mara refused to leave the wheel."));
+    let mut manager = AIProviderManager::new();
+    manager.register_provider("mock".to_string(), Arc::new(provider));
+    manager.set_default_provider("mock".to_string());
+    let processor = WriteProcessor::new(Arc::new(manager), Arc::new(pool.clone()));
+    let settings = WriteSettings {
+        creativity_level: 5,
+        tone: "tense".to_string(),
+        key_details: String::new(),
+        use_story_bible_tools: Some(true),
+    };
+
+    let result = processor.auto_write(7, 30, settings).await.unwrap();
+
+    assert_eq!(result.generated_text, "Mara refused to leave the wheel.");
+    assert_eq!(result.tool_calls.len(), 1);
+    assert_eq!(result.route.served_by().unwrap().provider, "mock");
+    let history = AIHistoryOps::get_by_project(&pool, &project.id, None).await.unwrap();
+    let lookup = history.iter().find(|h| matches!(h.generation_type, AIGenerationType::ToolCall)).unwrap();
+    assert_eq!(lookup.document_id.as_deref(), Some("7"));
+    assert_eq!(lookup.model, "mock-model");
+    assert!(lookup.prompt.starts_with("lookup_character("));
+}
+
+#[tokio::test]
+async fn test_tool_errors_are_reported_to_the_model() {
+    let pool = test_pool().await;
+    let (project, _) = seeded_project(&pool).await;
+    let provider = MockProvider::new()
+        .on(
+            &WritingFeature::Write,
+            ScriptedResponse::text(r#"{"tool_calls": [{"name": "summon_dragon", "arguments": {}}]}"#),
+        )
+        .on(&WritingFeature::Write, ScriptedResponse::text("The sea stayed quiet."));
+    let tools = StoryBibleTools::new(pool.clone(), &project.id);
+
+    let result = run_tool_loop(&provider, "Continue", &AIContext::default(), &tools, 5).await.unwrap();
+
+    assert_eq!(result.text, "The sea stayed quiet.");
+    assert!(result.tool_calls[0].is_error);
+    assert!(provider.calls()[1].input.contains("Error:"));
+}
+
+#[tokio::test]
+async fn test_tools_are_withdrawn_after_the_round_limit() {
+    let pool = test_pool().await;
+    let (project, _) = seeded_project(&pool).await;
+    let request = r#"{"tool_calls": [{"name": "lookup_location", "arguments": {"name": "Greyhaven"}}]}"#;
+    let provider = MockProvider::new().on(&WritingFeature::Write, ScriptedResponse::text(request));
+    let tools = StoryBibleTools::new(pool.clone(), &project.id);
+
+    let result = run_tool_loop(&provider, "Continue", &AIContext::default(), &tools, 2).await.unwrap();
+
+    assert_eq!(result.rounds, 3);
+    assert_eq!(result.tool_calls.len(), 2);
+    let last_input = provider.calls().last().unwrap().input.clone();
+    assert!(last_input.contains("No more tool calls are available"));
+    assert!(!last_input.contains("You can call these tools"));
+}
+
+#[tokio::test]
+async fn test_hidden_characters_are_never_returned() {
+    let pool = test_pool().await;
+    let (project, _) = seeded_project(&pool).await;
+    let tools = StoryBibleTools::new(pool.clone(), &project.id);
+
+    let output = tools.execute(&call("lookup_character", json!({ "name": "Quill" }))).await.unwrap();
+
+    assert!(output.contains("No character named"));
+    assert!(tools.execute(&call("lookup_character", json!({}))).await.is_err());
+}
+
+#[tokio::test]
+async fn test_manuscript_search_and_timeline_filters() {
+    let pool = test_pool().await;
+    let (project, captain) = seeded_project(&pool).await;
+
+    let mut chapter = Document::new(project.id.clone(), "Chapter 2".to_string(), DocumentType::Chapter);
+    chapter.content = "At dawn Mara burned the letter from the lighthouse keeper.".to_string();
+    DocumentOps::create(&pool, chapter).await.unwrap();
+
+    for (title, characters) in [("The wreck", json!([captain.id])), ("A quiet market day", json!([]))] {
+        TimelineOps::create(
+            &pool,
+            TimelineEvent {
+                id: String::new(),
+                project_id: project.id.clone(),
+                title: title.to_string(),
+                description: None,
+                event_date: Some("Year 3".to_string()),
+                real_date: None,
+                importance: EventImportance::Major,
+                characters_involved: characters.to_string(),
+                locations_involved: "[]".to_string(),
+                visibility: VisibilityLevel::Relevant,
+                created_at: chrono::Utc::now(),
+                updated_at: chrono::Utc::now(),
+                event_instant: None,
+                duration_minutes: None,
+                deaths: "[]".to_string(),
+            },
+        )
+        .await
+        .unwrap();
+    }
+    let tools = StoryBibleTools::new(pool.clone(), &project.id);
+
+    let found = tools.execute(&call("search_manuscript", json!({ "query": "lighthouse" }))).await.unwrap();
+    assert!(found.contains("Chapter 2"));
+    assert!(found.contains("lighthouse keeper"));
+
+    let events = tools.execute(&call("get_timeline_events", json!({ "character": "Mara" }))).await.unwrap();
+    assert!(events.contains("The wreck"));
+    assert!(!events.contains("A quiet market day"));
+}
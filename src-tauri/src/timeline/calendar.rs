@@ -0,0 +1,592 @@
+//! Project calendars
+//! Parses the free-form dates writers give timeline events into sortable
+//! instants: minutes since the start of year 1 (or of Day 1, for relative
+//! calendars). Gregorian dates use the proleptic calendar with leap years;
+//! custom calendars have named months of fixed length and optional eras.
+
+use crate::error::{Result, StoryWeaverError};
+use serde::{Deserialize, Serialize};
+
+const MINUTES_PER_HOUR: i64 = 60;
+
+const GREGORIAN_MONTHS: [&str; 12] = [
+    "January", "February", "March", "April", "May", "June", "July", "August", "September", "October", "November",
+    "December",
+];
+
+/// Words a date may contain that carry no meaning, as in "the 3rd day of March"
+const FILLER_WORDS: &[&str] = &["the", "of", "day", "year", "on", "in"];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CalendarKind {
+    /// "12 March 1204", "1204-03-12 14:00", "44 BC"
+    #[default]
+    Gregorian,
+    /// Named months and eras, e.g. "3 Frostfall 312 TA"
+    Custom,
+    /// Days counted from the start of the story, e.g. "Day 3 14:00"
+    Relative,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CalendarMonth {
+    pub name: String,
+    pub days: u32,
+}
+
+/// A named span of years. Year 1 of the era is `start_year` on the
+/// calendar's own count.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CalendarEra {
+    pub name: String,
+    #[serde(default)]
+    pub abbreviation: Option<String>,
+    pub start_year: i64,
+}
+
+/// How much of a date was given; "March 1204" is month precision
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DatePrecision {
+    Year,
+    Month,
+    Day,
+    Minute,
+}
+
+/// A date read with a project's calendar
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StoryDate {
+    /// Minutes since the calendar's epoch; missing parts count as their first
+    pub instant: i64,
+    pub precision: DatePrecision,
+    /// The date written back out the calendar's way
+    pub normalized: String,
+}
+
+fn default_hours_per_day() -> u32 {
+    24
+}
+
+/// How a project writes dates
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StoryCalendar {
+    pub kind: CalendarKind,
+    /// Months of a custom calendar, in order
+    #[serde(default)]
+    pub months: Vec<CalendarMonth>,
+    /// Eras of a custom calendar
+    #[serde(default)]
+    pub eras: Vec<CalendarEra>,
+    #[serde(default = "default_hours_per_day")]
+    pub hours_per_day: u32,
+}
+
+impl Default for StoryCalendar {
+    fn default() -> Self {
+        Self::gregorian()
+    }
+}
+
+/// How the years of a written date map onto the calendar's own count
+enum YearCount {
+    /// Years as the calendar counts them
+    Calendar,
+    /// Years of an era whose year 1 is the given calendar year
+    Era(i64),
+    /// Gregorian years before 1 AD, where 1 BC is year 0
+    BeforeChrist,
+}
+
+impl YearCount {
+    fn apply(&self, year: i64) -> i64 {
+        match self {
+            YearCount::Calendar => year,
+            YearCount::Era(start_year) => start_year + year - 1,
+            YearCount::BeforeChrist => 1 - year,
+        }
+    }
+}
+
+fn is_leap_year(year: i64) -> bool {
+    year.rem_euclid(4) == 0 && (year.rem_euclid(100) != 0 || year.rem_euclid(400) == 0)
+}
+
+/// Days from 1970-01-01 to a proleptic Gregorian date
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = month as i64;
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u32;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 } as u32;
+    let year = year_of_era + era * 400;
+    (if month <= 2 { year + 1 } else { year }, month, day)
+}
+
+/// Days from 1970-01-01 to 0001-01-01, so Gregorian instants count from year 1
+fn gregorian_epoch() -> i64 {
+    days_from_civil(1, 1, 1)
+}
+
+/// "12th" → 12
+fn parse_number(token: &str) -> Option<i64> {
+    let digits = ["st", "nd", "rd", "th"]
+        .iter()
+        .find_map(|suffix| token.strip_suffix(suffix))
+        .unwrap_or(token);
+    digits.parse().ok()
+}
+
+/// Remove `word` from `text` where it stands as a whole word; true if it did
+fn take_word(text: &mut String, word: &str) -> bool {
+    if word.is_empty() {
+        return false;
+    }
+    let mut start = 0;
+    while let Some(found) = text[start..].find(word) {
+        let at = start + found;
+        let end = at + word.len();
+        let before = text[..at].chars().next_back().is_none_or(|c| !c.is_alphanumeric());
+        let after = text[end..].chars().next().is_none_or(|c| !c.is_alphanumeric());
+        if before && after {
+            text.replace_range(at..end, " ");
+            return true;
+        }
+        start = end;
+    }
+    false
+}
+
+fn invalid_date(date: &str, reason: &str) -> StoryWeaverError {
+    StoryWeaverError::validation(format!("Can't read the date \"{}\": {}", date, reason))
+}
+
+impl StoryCalendar {
+    pub fn gregorian() -> Self {
+        Self {
+            kind: CalendarKind::Gregorian,
+            months: Vec::new(),
+            eras: Vec::new(),
+            hours_per_day: default_hours_per_day(),
+        }
+    }
+
+    pub fn relative() -> Self {
+        Self { kind: CalendarKind::Relative, ..Self::gregorian() }
+    }
+
+    pub fn custom(months: Vec<CalendarMonth>, eras: Vec<CalendarEra>) -> Self {
+        Self { kind: CalendarKind::Custom, months, eras, ..Self::gregorian() }
+    }
+
+    /// Check a calendar before it's saved
+    pub fn validate(&self) -> Result<()> {
+        if !(1..=100).contains(&self.hours_per_day) {
+            return Err(StoryWeaverError::validation("A day must have between 1 and 100 hours"));
+        }
+        if self.kind != CalendarKind::Custom {
+            return Ok(());
+        }
+        if self.months.is_empty() {
+            return Err(StoryWeaverError::validation("A custom calendar needs at least one month"));
+        }
+
+        let mut names: Vec<String> = Vec::new();
+        let month_names = self.months.iter().map(|m| (m.name.as_str(), m.days));
+        let era_names = self
+            .eras
+            .iter()
+            .flat_map(|era| std::iter::once(era.name.as_str()).chain(era.abbreviation.as_deref()))
+            .map(|name| (name, 1));
+        for (name, days) in month_names.chain(era_names) {
+            let name = name.trim().to_lowercase();
+            if name.is_empty() || name.chars().any(|c| c.is_ascii_digit()) {
+                return Err(StoryWeaverError::validation("Month and era names can't be empty or contain digits"));
+            }
+            if days == 0 {
+                return Err(StoryWeaverError::validation(format!("The month {} needs at least one day", name)));
+            }
+            if names.contains(&name) {
+                return Err(StoryWeaverError::validation(format!("{} is used more than once", name)));
+            }
+            names.push(name);
+        }
+        Ok(())
+    }
+
+    fn minutes_per_day(&self) -> i64 {
+        self.hours_per_day as i64 * MINUTES_PER_HOUR
+    }
+
+    fn days_in_year(&self, year: i64) -> i64 {
+        match self.kind {
+            CalendarKind::Custom => self.months.iter().map(|m| m.days as i64).sum(),
+            _ if is_leap_year(year) => 366,
+            _ => 365,
+        }
+    }
+
+    fn month_count(&self) -> usize {
+        match self.kind {
+            CalendarKind::Custom => self.months.len(),
+            _ => GREGORIAN_MONTHS.len(),
+        }
+    }
+
+    fn month_name(&self, month: usize) -> &str {
+        match self.kind {
+            CalendarKind::Custom => &self.months[month].name,
+            _ => GREGORIAN_MONTHS[month],
+        }
+    }
+
+    fn days_in_month(&self, year: i64, month: usize) -> u32 {
+        match self.kind {
+            CalendarKind::Custom => self.months[month].days,
+            _ => match month + 1 {
+                2 if is_leap_year(year) => 29,
+                2 => 28,
+                4 | 6 | 9 | 11 => 30,
+                _ => 31,
+            },
+        }
+    }
+
+    /// Days from the epoch to the first day of `month` (0-based) of `year`
+    fn days_before(&self, year: i64, month: usize) -> i64 {
+        match self.kind {
+            CalendarKind::Custom => {
+                let before_month: i64 = self.months[..month].iter().map(|m| m.days as i64).sum();
+                (year - 1) * self.days_in_year(year) + before_month
+            }
+            _ => days_from_civil(year, month as u32 + 1, 1) - gregorian_epoch(),
+        }
+    }
+
+    /// Year, 0-based month and day of the month for a day count
+    fn civil(&self, days: i64) -> (i64, usize, u32) {
+        match self.kind {
+            CalendarKind::Custom => {
+                let year_days = self.days_in_year(1);
+                let year = days.div_euclid(year_days) + 1;
+                let mut day_of_year = days.rem_euclid(year_days);
+                let mut month = 0;
+                while day_of_year >= self.months[month].days as i64 {
+                    day_of_year -= self.months[month].days as i64;
+                    month += 1;
+                }
+                (year, month, day_of_year as u32 + 1)
+            }
+            _ => {
+                let (year, month, day) = civil_from_days(days + gregorian_epoch());
+                (year, month as usize - 1, day)
+            }
+        }
+    }
+
+    /// Read a date written the calendar's way
+    pub fn parse(&self, date: &str) -> Result<StoryDate> {
+        let mut text = date.trim().to_lowercase().replace(',', " ");
+        if text.is_empty() {
+            return Err(invalid_date(date, "it's empty"));
+        }
+
+        let minute_of_day = self.take_time(date, &mut text)?;
+        let (days, precision) = match self.kind {
+            CalendarKind::Relative => (self.relative_day(date, &text)?, DatePrecision::Day),
+            _ => self.take_date(date, &mut text)?,
+        };
+        let precision = if minute_of_day.is_some() { DatePrecision::Minute } else { precision };
+        let instant = days * self.minutes_per_day() + minute_of_day.unwrap_or(0);
+
+        Ok(StoryDate { instant, precision, normalized: self.format_at(instant, precision) })
+    }
+
+    /// Pull an "HH:MM" time out of the text
+    fn take_time(&self, date: &str, text: &mut String) -> Result<Option<i64>> {
+        // ISO dates may join the time on with a T
+        if let Some((day, time)) = text.split_once('t').filter(|_| !text.contains(' ')) {
+            if day.contains('-') && time.contains(':') {
+                *text = format!("{} {}", day, time);
+            }
+        }
+
+        let Some(token) = text.split_whitespace().find(|token| token.contains(':')).map(str::to_string) else {
+            return Ok(None);
+        };
+        let (hours, minutes) = token.split_once(':').unwrap_or_default();
+        let (Ok(hours), Ok(minutes)) = (hours.parse::<i64>(), minutes.parse::<i64>()) else {
+            return Err(invalid_date(date, "times are written as HH:MM"));
+        };
+        if hours >= self.hours_per_day as i64 || minutes >= MINUTES_PER_HOUR {
+            return Err(invalid_date(date, &format!("days here have {} hours", self.hours_per_day)));
+        }
+        take_word(text, &token);
+        Ok(Some(hours * MINUTES_PER_HOUR + minutes))
+    }
+
+    /// "Day 3" → 2 days after the start of the story
+    fn relative_day(&self, date: &str, text: &str) -> Result<i64> {
+        let tokens: Vec<&str> = text.split_whitespace().collect();
+        match tokens.as_slice() {
+            ["day", number] => parse_number(number)
+                .map(|day| day - 1)
+                .ok_or_else(|| invalid_date(date, "write relative dates as \"Day 3\"")),
+            _ => Err(invalid_date(date, "write relative dates as \"Day 3\"")),
+        }
+    }
+
+    /// Days from the epoch to the date in the text, and how precise it is
+    fn take_date(&self, date: &str, text: &mut String) -> Result<(i64, DatePrecision)> {
+        // 1204-03-12, with a leading minus for years before 1
+        let iso: Vec<&str> = text.trim().trim_start_matches('-').split('-').collect();
+        if iso.len() == 3 && iso.iter().all(|part| !part.is_empty() && part.chars().all(|c| c.is_ascii_digit())) {
+            let numbers: Vec<i64> = iso.iter().filter_map(|part| part.parse().ok()).collect();
+            let year = if text.trim().starts_with('-') { -numbers[0] } else { numbers[0] };
+            if numbers[1] < 1 || numbers[1] as usize > self.month_count() {
+                return Err(invalid_date(date, &format!("there are {} months", self.month_count())));
+            }
+            return self.day_count(date, year, Some(numbers[1] as usize - 1), Some(numbers[2]));
+        }
+
+        let year_count = self.take_era(text);
+        let month = self.take_month(text);
+        let mut numbers = Vec::new();
+        for token in text.split_whitespace() {
+            if let Some(number) = parse_number(token) {
+                numbers.push(number);
+            } else if !FILLER_WORDS.contains(&token) {
+                return Err(invalid_date(date, &format!("\"{}\" isn't a month or era of this calendar", token)));
+            }
+        }
+
+        let (day, year) = match (month, numbers.as_slice()) {
+            (_, [year]) => (None, *year),
+            (Some(_), [day, year]) => (Some(*day), *year),
+            (_, []) => return Err(invalid_date(date, "it has no year")),
+            _ => return Err(invalid_date(date, "it has too many numbers")),
+        };
+        if year < 1 {
+            return Err(invalid_date(date, "years start at 1"));
+        }
+        self.day_count(date, year_count.apply(year), month, day)
+    }
+
+    /// Days from the epoch to a date; a missing month or day counts as the
+    /// first
+    fn day_count(&self, date: &str, year: i64, month: Option<usize>, day: Option<i64>) -> Result<(i64, DatePrecision)> {
+        let precision = match (month, day) {
+            (None, _) => DatePrecision::Year,
+            (Some(_), None) => DatePrecision::Month,
+            (Some(_), Some(_)) => DatePrecision::Day,
+        };
+        let month = month.unwrap_or(0);
+        let day = day.unwrap_or(1);
+        let month_days = self.days_in_month(year, month);
+        if day < 1 || day > month_days as i64 {
+            return Err(invalid_date(date, &format!("{} has {} days", self.month_name(month), month_days)));
+        }
+        Ok((self.days_before(year, month) + day - 1, precision))
+    }
+
+    /// Remove an era from the text
+    fn take_era(&self, text: &mut String) -> YearCount {
+        match self.kind {
+            CalendarKind::Custom => {
+                let mut eras: Vec<(String, i64)> = self
+                    .eras
+                    .iter()
+                    .flat_map(|era| {
+                        std::iter::once(&era.name)
+                            .chain(era.abbreviation.as_ref())
+                            .map(|name| (name.to_lowercase(), era.start_year))
+                    })
+                    .collect();
+                // Longest first, so "Second Age" wins over "Age"
+                eras.sort_by_key(|(name, _)| std::cmp::Reverse(name.len()));
+                eras.into_iter()
+                    .find(|(name, _)| take_word(text, name))
+                    .map_or(YearCount::Calendar, |(_, start_year)| YearCount::Era(start_year))
+            }
+            _ => {
+                if ["bc", "bce"].iter().any(|era| take_word(text, era)) {
+                    return YearCount::BeforeChrist;
+                }
+                for era in ["ad", "ce"] {
+                    take_word(text, era);
+                }
+                YearCount::Calendar
+            }
+        }
+    }
+
+    /// Remove a month name (or, for Gregorian months, its first three
+    /// letters) from the text
+    fn take_month(&self, text: &mut String) -> Option<usize> {
+        let mut names: Vec<(String, usize)> =
+            (0..self.month_count()).map(|month| (self.month_name(month).to_lowercase(), month)).collect();
+        if self.kind == CalendarKind::Gregorian {
+            names.extend(GREGORIAN_MONTHS.iter().enumerate().map(|(month, name)| (name[..3].to_lowercase(), month)));
+            names.push(("sept".to_string(), 8));
+        }
+        names.sort_by_key(|(name, _)| std::cmp::Reverse(name.len()));
+        names.into_iter().find(|(name, _)| take_word(text, name)).map(|(_, month)| month)
+    }
+
+    /// The first instant after the span a date covers at its precision, so
+    /// "March 1204" ends where April begins
+    pub fn end_of(&self, date: &StoryDate) -> i64 {
+        let minutes_per_day = self.minutes_per_day();
+        let days = date.instant.div_euclid(minutes_per_day);
+        match date.precision {
+            DatePrecision::Minute => date.instant + 1,
+            DatePrecision::Day => (days + 1) * minutes_per_day,
+            DatePrecision::Month => {
+                let (year, month, _) = self.civil(days);
+                (self.days_before(year, month) + self.days_in_month(year, month) as i64) * minutes_per_day
+            }
+            DatePrecision::Year => {
+                let (year, _, _) = self.civil(days);
+                self.days_before(year + 1, 0) * minutes_per_day
+            }
+        }
+    }
+
+    /// Write an instant out at minute precision, or day precision when it
+    /// falls on midnight
+    pub fn format(&self, instant: i64) -> String {
+        let precision = if instant.rem_euclid(self.minutes_per_day()) == 0 { DatePrecision::Day } else { DatePrecision::Minute };
+        self.format_at(instant, precision)
+    }
+
+    pub fn format_at(&self, instant: i64, precision: DatePrecision) -> String {
+        let days = instant.div_euclid(self.minutes_per_day());
+        let minute_of_day = instant.rem_euclid(self.minutes_per_day());
+        let time = match precision {
+            DatePrecision::Minute => {
+                format!(" {:02}:{:02}", minute_of_day / MINUTES_PER_HOUR, minute_of_day % MINUTES_PER_HOUR)
+            }
+            _ => String::new(),
+        };
+        if self.kind == CalendarKind::Relative {
+            return format!("Day {}{}", days + 1, time);
+        }
+
+        let (year, month, day) = self.civil(days);
+        let year = self.format_year(year);
+        match precision {
+            DatePrecision::Year => year,
+            DatePrecision::Month => format!("{} {}", self.month_name(month), year),
+            _ => format!("{} {} {}{}", day, self.month_name(month), year, time),
+        }
+    }
+
+    fn format_year(&self, year: i64) -> String {
+        match self.kind {
+            CalendarKind::Custom => self
+                .eras
+                .iter()
+                .filter(|era| era.start_year <= year)
+                .max_by_key(|era| era.start_year)
+                .map(|era| {
+                    let name = era.abbreviation.as_deref().unwrap_or(&era.name);
+                    format!("{} {}", year - era.start_year + 1, name)
+                })
+                .unwrap_or_else(|| year.to_string()),
+            _ if year < 1 => format!("{} BC", 1 - year),
+            _ => year.to_string(),
+        }
+    }
+
+    /// Read a duration such as "3 days", "2h 30m" or "1 year" into minutes.
+    /// Months and years of a custom calendar are its average month and its
+    /// year; Gregorian months count as 30 days and years as 365.
+    pub fn parse_duration(&self, duration: &str) -> Result<i64> {
+        let invalid = || {
+            StoryWeaverError::validation(format!(
+                "Can't read the duration \"{}\": write it like \"3 days\" or \"2h 30m\"",
+                duration
+            ))
+        };
+        let text = duration.trim().to_lowercase().replace(',', " ");
+        let text = text.replace(" and ", " ");
+
+        // Split "2h30m" and "2 h 30 m" alike into numbers and units
+        let mut tokens: Vec<String> = Vec::new();
+        for c in text.chars() {
+            let same_kind = tokens
+                .last()
+                .and_then(|token| token.chars().last())
+                .is_some_and(|last| last.is_ascii_digit() == c.is_ascii_digit() && !last.is_whitespace());
+            if c.is_whitespace() {
+                tokens.push(String::new());
+            } else if same_kind {
+                if let Some(token) = tokens.last_mut() {
+                    token.push(c);
+                }
+            } else {
+                tokens.push(c.to_string());
+            }
+        }
+        tokens.retain(|token| !token.is_empty());
+        if tokens.is_empty() || !tokens.len().is_multiple_of(2) {
+            return Err(invalid());
+        }
+
+        let year_days = match self.kind {
+            CalendarKind::Custom => self.days_in_year(1),
+            _ => 365,
+        };
+        let month_days = match self.kind {
+            CalendarKind::Custom => year_days / self.months.len().max(1) as i64,
+            _ => 30,
+        };
+        let day = self.minutes_per_day();
+
+        let mut total: i64 = 0;
+        for pair in tokens.chunks(2) {
+            let amount: i64 = pair[0].parse().map_err(|_| invalid())?;
+            let unit = match pair[1].as_str() {
+                "m" | "min" | "mins" | "minute" | "minutes" => 1,
+                "h" | "hr" | "hrs" | "hour" | "hours" => MINUTES_PER_HOUR,
+                "d" | "day" | "days" => day,
+                "w" | "wk" | "wks" | "week" | "weeks" => 7 * day,
+                "mo" | "month" | "months" => month_days * day,
+                "y" | "yr" | "yrs" | "year" | "years" => year_days * day,
+                _ => return Err(invalid()),
+            };
+            total = amount.checked_mul(unit).and_then(|minutes| total.checked_add(minutes)).ok_or_else(invalid)?;
+        }
+        Ok(total)
+    }
+
+    /// Write minutes out as days, hours and minutes, e.g. "2 days 3 hours"
+    pub fn format_duration(&self, minutes: i64) -> String {
+        let day = self.minutes_per_day();
+        let parts = [(minutes / day, "day"), (minutes % day / MINUTES_PER_HOUR, "hour"), (minutes % MINUTES_PER_HOUR, "minute")];
+        let written: Vec<String> = parts
+            .iter()
+            .filter(|(amount, _)| *amount != 0)
+            .map(|(amount, unit)| format!("{} {}{}", amount, unit, if amount.abs() == 1 { "" } else { "s" }))
+            .collect();
+        if written.is_empty() {
+            "0 minutes".to_string()
+        } else {
+            written.join(" ")
+        }
+    }
+}
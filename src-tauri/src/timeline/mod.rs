@@ -0,0 +1,9 @@
+//! Story-internal time
+//! Project calendars that turn timeline dates into sortable instants, and
+//! checks that a project's timeline events could all have happened.
+
+pub mod calendar;
+pub mod validation;
+
+pub use calendar::{CalendarEra, CalendarKind, CalendarMonth, DatePrecision, StoryCalendar, StoryDate};
+pub use validation::{check_events, validate_timeline, TimelineIssue, TimelineIssueKind, TimelineReport};
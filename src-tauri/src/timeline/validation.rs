@@ -0,0 +1,213 @@
+//! Timeline checks
+//! Reads a project's timeline events with its calendar and flags what can't
+//! have happened: a character in two places at once, or taking part in
+//! events after their death.
+
+use super::calendar::{DatePrecision, StoryCalendar, StoryDate};
+use crate::database::models::TimelineEvent;
+use crate::database::operations::{CharacterOps, ConflictSeverity, LocationOps, StoryCalendarOps, TimelineOps};
+use crate::database::DbPool;
+use crate::error::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TimelineIssueKind {
+    /// The event's date isn't written the calendar's way
+    UnreadableDate,
+    /// A character is at two different locations at once
+    SimultaneousLocations,
+    /// A character takes part in an event after they die
+    AfterDeath,
+    /// A character dies in more than one event
+    RepeatedDeath,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TimelineIssue {
+    pub kind: TimelineIssueKind,
+    pub severity: ConflictSeverity,
+    pub description: String,
+    pub event_ids: Vec<String>,
+    pub character_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TimelineReport {
+    pub events_checked: usize,
+    /// Events with no date, which can't be checked
+    pub undated: usize,
+    pub issues: Vec<TimelineIssue>,
+}
+
+/// IDs from one of the events' JSON array columns. Some rows hold the array
+/// encoded a second time as a JSON string, so both forms are read.
+fn id_list(json: &str) -> Vec<String> {
+    serde_json::from_str::<Vec<String>>(json)
+        .or_else(|_| serde_json::from_str::<String>(json).and_then(|inner| serde_json::from_str(&inner)))
+        .unwrap_or_default()
+}
+
+/// An event with its date read
+struct DatedEvent<'a> {
+    event: &'a TimelineEvent,
+    date: StoryDate,
+    characters: Vec<String>,
+    locations: Vec<String>,
+    deaths: Vec<String>,
+}
+
+impl DatedEvent<'_> {
+    /// When the event happens: from its start for its duration, or the
+    /// minute it's dated to. With neither, only the day is known, and the
+    /// span comes back marked inexact.
+    fn span(&self, calendar: &StoryCalendar) -> (i64, i64, bool) {
+        let start = self.date.instant;
+        match self.event.duration_minutes.filter(|minutes| *minutes > 0) {
+            Some(minutes) => (start, start + minutes, true),
+            None if self.date.precision == DatePrecision::Minute => (start, start + 1, true),
+            None => (start, calendar.end_of(&self.date), false),
+        }
+    }
+
+    /// Whether two events certainly happen at the same time. Dates vaguer
+    /// than a day never do.
+    fn overlaps(&self, other: &DatedEvent, calendar: &StoryCalendar) -> bool {
+        if self.date.precision < DatePrecision::Day || other.date.precision < DatePrecision::Day {
+            return false;
+        }
+        let (start, end, exact) = self.span(calendar);
+        let (other_start, other_end, other_exact) = other.span(calendar);
+        match (exact, other_exact) {
+            (true, true) => start < other_end && other_start < end,
+            // Something during a day only certainly meets a span covering all of it
+            (true, false) => start <= other_start && other_end <= end,
+            (false, true) => other_start <= start && end <= other_end,
+            (false, false) => false,
+        }
+    }
+}
+
+/// Check `events` against each other. `names` maps character and location
+/// IDs to the names used in descriptions.
+pub fn check_events(calendar: &StoryCalendar, events: &[TimelineEvent], names: &HashMap<String, String>) -> TimelineReport {
+    let name = |id: &str| names.get(id).cloned().unwrap_or_else(|| id.to_string());
+    let mut issues = Vec::new();
+    let mut undated = 0;
+
+    let mut dated: Vec<DatedEvent> = Vec::new();
+    for event in events {
+        let Some(event_date) = event.event_date.as_deref().filter(|date| !date.trim().is_empty()) else {
+            undated += 1;
+            continue;
+        };
+        match calendar.parse(event_date) {
+            Ok(date) => dated.push(DatedEvent {
+                event,
+                date,
+                characters: id_list(&event.characters_involved),
+                locations: id_list(&event.locations_involved),
+                deaths: id_list(&event.deaths),
+            }),
+            Err(e) => issues.push(TimelineIssue {
+                kind: TimelineIssueKind::UnreadableDate,
+                severity: ConflictSeverity::Low,
+                description: format!("\"{}\": {}", event.title, e),
+                event_ids: vec![event.id.clone()],
+                character_id: None,
+            }),
+        }
+    }
+    dated.sort_by_key(|dated| dated.date.instant);
+
+    // Each character's events, in story order
+    let mut by_character: BTreeMap<&str, Vec<&DatedEvent>> = BTreeMap::new();
+    for event in &dated {
+        for id in event.characters.iter().chain(&event.deaths) {
+            let events = by_character.entry(id.as_str()).or_default();
+            if !events.iter().any(|e| e.event.id == event.event.id) {
+                events.push(event);
+            }
+        }
+    }
+
+    for (character_id, events) in by_character {
+        let character = name(character_id);
+
+        for (i, first) in events.iter().enumerate() {
+            for second in &events[i + 1..] {
+                let apart = !first.locations.is_empty()
+                    && !second.locations.is_empty()
+                    && !first.locations.iter().any(|id| second.locations.contains(id));
+                if apart && first.overlaps(second, calendar) {
+                    issues.push(TimelineIssue {
+                        kind: TimelineIssueKind::SimultaneousLocations,
+                        severity: ConflictSeverity::High,
+                        description: format!(
+                            "{} is at {} for \"{}\" and at {} for \"{}\" at the same time",
+                            character,
+                            first.locations.iter().map(|id| name(id)).collect::<Vec<_>>().join(", "),
+                            first.event.title,
+                            second.locations.iter().map(|id| name(id)).collect::<Vec<_>>().join(", "),
+                            second.event.title,
+                        ),
+                        event_ids: vec![first.event.id.clone(), second.event.id.clone()],
+                        character_id: Some(character_id.to_string()),
+                    });
+                }
+            }
+        }
+
+        let deaths: Vec<&&DatedEvent> = events.iter().filter(|e| e.deaths.iter().any(|id| id == character_id)).collect();
+        let Some(death) = deaths.first() else {
+            continue;
+        };
+        if deaths.len() > 1 {
+            issues.push(TimelineIssue {
+                kind: TimelineIssueKind::RepeatedDeath,
+                severity: ConflictSeverity::Medium,
+                description: format!(
+                    "{} dies more than once: {}",
+                    character,
+                    deaths.iter().map(|e| format!("\"{}\"", e.event.title)).collect::<Vec<_>>().join(", ")
+                ),
+                event_ids: deaths.iter().map(|e| e.event.id.clone()).collect(),
+                character_id: Some(character_id.to_string()),
+            });
+        }
+
+        // Only flag events that start after the death's date is certainly over
+        let dead_from = calendar.end_of(&death.date);
+        for event in events.iter().filter(|e| e.date.instant >= dead_from && !e.deaths.iter().any(|id| id == character_id)) {
+            issues.push(TimelineIssue {
+                kind: TimelineIssueKind::AfterDeath,
+                severity: ConflictSeverity::Critical,
+                description: format!(
+                    "{} takes part in \"{}\" ({}) after dying in \"{}\" ({})",
+                    character, event.event.title, event.date.normalized, death.event.title, death.date.normalized
+                ),
+                event_ids: vec![death.event.id.clone(), event.event.id.clone()],
+                character_id: Some(character_id.to_string()),
+            });
+        }
+    }
+
+    TimelineReport { events_checked: events.len(), undated, issues }
+}
+
+/// Check a project's timeline with its calendar
+pub async fn validate_timeline(pool: &DbPool, project_id: &str) -> Result<TimelineReport> {
+    let calendar = StoryCalendarOps::get(pool, project_id).await?;
+    let events = TimelineOps::get_by_project(pool, project_id).await?;
+
+    let mut names = HashMap::new();
+    for character in CharacterOps::get_by_project(pool, project_id).await? {
+        names.insert(character.id, character.name);
+    }
+    for location in LocationOps::get_by_project(pool, project_id).await? {
+        names.insert(location.id, location.name);
+    }
+
+    Ok(check_events(&calendar, &events, &names))
+}
@@ -73,7 +73,7 @@ interface AdvancedAIActions {
   updateCreditUsage: (projectId: string) => Promise<void>;
   
   // Saliency Engine
-  buildSaliencyContext: (projectId: string, textContext: string, storyBible: StoryBibleElements) => Promise<SaliencyContext>;
+  buildSaliencyContext: (projectId: string, textContext: string, storyBible: StoryBibleElements, documentId?: string) => Promise<SaliencyContext>;
   toggleSaliencyEngine: (enabled: boolean) => void;
   
   // Smart Import
@@ -326,7 +326,7 @@ export const useAdvancedAIStore = create<AdvancedAIState & AdvancedAIActions>()(
             }
           });
           
-          if (status.status === 'completed' || status.status === 'error') {
+          if (status.status === 'completed' || status.status === 'error' || status.status === 'cancelled') {
             clearInterval(pollInterval);
             set({ 
               isGenerating: false,
@@ -610,13 +610,15 @@ export const useAdvancedAIStore = create<AdvancedAIState & AdvancedAIActions>()(
     async buildSaliencyContext(
       projectId: string, 
       textContext: string, 
-      storyBible: StoryBibleElements
+      storyBible: StoryBibleElements,
+      documentId?: string
     ): Promise<SaliencyContext> {
       try {
         const response = await invoke<SaliencyContext>('build_saliency_context', {
           projectId,
           textContext,
-          storyBible
+          storyBible,
+          documentId
         });
         set({ lastSaliencyContext: response });
         return response;
@@ -87,6 +87,10 @@ export interface Character {
   description: string;
   traits: string[];
   relationships: Record<string, string>;
+  visibility?: 'Always' | 'Relevant' | 'Manual' | 'Hidden';
+  last_seen_chapter?: number;
+  /** Why the saliency engine included this element */
+  explanation?: string;
 }
 
 export interface Location {
@@ -95,6 +99,10 @@ export interface Location {
   description: string;
   atmosphere: string;
   significance: string;
+  visibility?: 'Always' | 'Relevant' | 'Manual' | 'Hidden';
+  last_seen_chapter?: number;
+  /** Why the saliency engine included this element */
+  explanation?: string;
 }
 
 export interface PlotThread {
@@ -103,6 +111,10 @@ export interface PlotThread {
   description: string;
   status: string;
   importance: number;
+  visibility?: 'Always' | 'Relevant' | 'Manual' | 'Hidden';
+  last_seen_chapter?: number;
+  /** Why the saliency engine included this element */
+  explanation?: string;
 }
 
 export interface WorldbuildingElement {
@@ -111,6 +123,10 @@ export interface WorldbuildingElement {
   type: string;
   description: string;
   rules: string[];
+  visibility?: 'Always' | 'Relevant' | 'Manual' | 'Hidden';
+  last_seen_chapter?: number;
+  /** Why the saliency engine included this element */
+  explanation?: string;
 }
 
 export interface StoryBibleElements {
@@ -118,6 +134,7 @@ export interface StoryBibleElements {
   locations: Location[];
   plot_threads: PlotThread[];
   worldbuilding: WorldbuildingElement[];
+  current_chapter?: number;
 }
 
 // Image Generation Types
@@ -236,7 +253,7 @@ export interface StreamingGenerationRequest {
 }
 
 export interface StreamingStatus {
-  status: 'pending' | 'generating' | 'completed' | 'error';
+  status: 'pending' | 'generating' | 'completed' | 'error' | 'cancelled';
   progress: number;
   current_text?: string;
   error_message?: string;
@@ -423,7 +440,7 @@ export interface AdvancedAIActions {
   getCreditUsage: (projectId: string) => Promise<CreditUsageResponse>;
   
   // Saliency Engine
-  buildSaliencyContext: (projectId: string, textContext: string, storyBible: StoryBibleElements) => Promise<SaliencyContext>;
+  buildSaliencyContext: (projectId: string, textContext: string, storyBible: StoryBibleElements, documentId?: string) => Promise<SaliencyContext>;
   
   // Smart Import
   smartImportContent: (request: SmartImportRequest) => Promise<SmartImportResult>;
@@ -6,6 +6,15 @@ export interface ConsistencyConflict {
   description: string;
   affected_projects: string[];
   details: Record<string, any>;
+  passages?: ConflictPassage[];
+}
+
+// A span of a document, in characters from the start of its content
+export interface ConflictPassage {
+  document_id: string;
+  start: number;
+  end: number;
+  excerpt: string;
 }
 
 export interface SeriesConsistencyReport {
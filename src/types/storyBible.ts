@@ -102,6 +102,73 @@ export interface Scene {
   updated_at: string;
 }
 
+export type StoryBibleEntityType = 'character' | 'location' | 'world_element';
+
+export interface StoryBibleAlias {
+  id: string;
+  project_id: string;
+  entity_type: StoryBibleEntityType;
+  entity_id: string;
+  alias: string;
+  kind: 'alias' | 'nickname' | 'title';
+  created_at: string;
+}
+
+export interface StoryBibleDetection {
+  detection_type: StoryBibleEntityType;
+  entity_id: string;
+  entity_name: string;
+  // Positions are in characters (code points), not UTF-16 units
+  start_position: number;
+  end_position: number;
+  confidence: number;
+  matched_text: string;
+  match_kind: 'name' | 'alias' | 'nickname' | 'title' | 'partial_name' | 'pronoun';
+  possessive: boolean;
+}
+
+export interface StoryBibleExtraction {
+  id: string;
+  project_id: string;
+  task_id?: string;
+  status: 'pending' | 'running' | 'failed' | 'completed';
+  document_count: number;
+  error_message?: string;
+  created_at: string;
+  updated_at: string;
+}
+
+export interface ProposalCitation {
+  document_id: string;
+  // Positions are in characters (code points), not UTF-16 units
+  start: number;
+  end: number;
+  excerpt: string;
+}
+
+export interface StoryBibleProposal {
+  id: string;
+  extraction_id: string;
+  project_id: string;
+  entity_type: StoryBibleEntityType | 'timeline_event';
+  name: string;
+  category?: string;
+  description?: string;
+  event_date?: string;
+  possible_duplicate_id?: string;
+  possible_duplicate_name?: string;
+  status: 'pending' | 'accepted' | 'rejected';
+  created_entity_id?: string;
+  citations: ProposalCitation[];
+  created_at: string;
+  updated_at: string;
+}
+
+export interface StoryBibleExtractionDetail {
+  extraction: StoryBibleExtraction;
+  proposals: StoryBibleProposal[];
+}
+
 // Request/Response Types for Tauri Commands
 export interface CreateStoryBibleRequest {
   project_id: string;
@@ -534,4 +601,6 @@ export interface AIGenerationResponse {
   cost_estimate: number;
   provider: string;
   model: string;
+  structured_content?: Record<string, unknown>;
+  prompt_template?: { name: string; version: number };
 }